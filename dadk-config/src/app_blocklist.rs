@@ -0,0 +1,139 @@
+//! 用户程序黑名单配置文件
+//!
+//! 用于跳过某些用户程序的构建。在`allowlist`模式下，只构建列出的用户程序。
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::common::target_arch::TargetArch;
+
+/// 用户程序黑名单配置文件
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct AppBlocklistConfigFile {
+    /// 名单的模式，默认为黑名单
+    #[serde(default)]
+    pub mode: AppListMode,
+    /// 黑名单（或者白名单）中的用户程序
+    #[serde(default, rename = "blocked_apps")]
+    pub blocked_apps: Vec<BlockedApp>,
+}
+
+/// 名单的模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppListMode {
+    /// 不构建列出的用户程序
+    #[default]
+    Blocklist,
+    /// 只构建列出的用户程序
+    Allowlist,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BlockedApp {
+    /// 用户程序名称
+    pub name: String,
+    /// 只在这些架构上生效，为空时对所有架构生效
+    #[serde(default, rename = "target-arch", alias = "target_arch")]
+    pub target_arch: Vec<TargetArch>,
+    /// （可选）原因，跳过用户程序时会输出到日志中
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl BlockedApp {
+    fn matches(&self, name: &str, arch: TargetArch) -> bool {
+        self.name == name && (self.target_arch.is_empty() || self.target_arch.contains(&arch))
+    }
+}
+
+impl AppBlocklistConfigFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::load_from_str(&content)
+    }
+
+    pub fn load_from_str(content: &str) -> Result<Self> {
+        let config: AppBlocklistConfigFile = toml::from_str(content)?;
+        Ok(config)
+    }
+
+    /// 名单中与给定用户程序、架构匹配的条目
+    pub fn entry(&self, name: &str, arch: TargetArch) -> Option<&BlockedApp> {
+        self.blocked_apps.iter().find(|app| app.matches(name, arch))
+    }
+
+    /// 用户程序在给定架构上是否应该被跳过
+    pub fn is_blocked(&self, name: &str, arch: TargetArch) -> bool {
+        let listed = self.entry(name, arch).is_some();
+        match self.mode {
+            AppListMode::Blocklist => listed,
+            AppListMode::Allowlist => !listed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocklist() {
+        let config = AppBlocklistConfigFile::load_from_str(
+            r#"
+            [[blocked_apps]]
+            name = "app1"
+            reason = "broken"
+
+            [[blocked_apps]]
+            name = "app2"
+            target-arch = ["riscv64"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, AppListMode::Blocklist);
+        assert!(config.is_blocked("app1", TargetArch::X86_64));
+        assert!(config.is_blocked("app1", TargetArch::RiscV64));
+        assert!(!config.is_blocked("app2", TargetArch::X86_64));
+        assert!(config.is_blocked("app2", TargetArch::RiscV64));
+        assert!(!config.is_blocked("app3", TargetArch::RiscV64));
+        assert_eq!(
+            config
+                .entry("app1", TargetArch::X86_64)
+                .unwrap()
+                .reason
+                .as_deref(),
+            Some("broken")
+        );
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let config = AppBlocklistConfigFile::load_from_str(
+            r#"
+            mode = "allowlist"
+
+            [[blocked_apps]]
+            name = "app1"
+
+            [[blocked_apps]]
+            name = "app2"
+            target-arch = ["x86_64"]
+            "#,
+        )
+        .unwrap();
+        assert!(!config.is_blocked("app1", TargetArch::RiscV64));
+        assert!(!config.is_blocked("app2", TargetArch::X86_64));
+        assert!(config.is_blocked("app2", TargetArch::RiscV64));
+        assert!(config.is_blocked("app3", TargetArch::X86_64));
+    }
+
+    #[test]
+    fn test_empty_blocklist() {
+        let config = AppBlocklistConfigFile::load_from_str("").unwrap();
+        assert!(!config.is_blocked("app1", TargetArch::X86_64));
+        assert!(AppBlocklistConfigFile::load_from_str(r#"mode = "denylist""#).is_err());
+    }
+}
@@ -4,6 +4,8 @@
 //! (GPL-2.0 is compatible with MPL-2.0)
 //! https://www.gnu.org/licenses/license-list.zh-cn.html#MPL-2.0
 
+use std::{collections::BTreeSet, path::PathBuf};
+
 use anyhow::{anyhow, Result};
 use serde::Deserialize;
 
@@ -32,6 +34,25 @@ pub struct QemuConfig {
 
     /// Hardware acceleration
     accelerate: Option<QemuAccel>,
+
+    /// QMP socket address of the running qemu instance.
+    ///
+    /// Used by the qmp backend of `dadk profile sample`.
+    ///
+    /// Example: `unix:/tmp/dragonos-qmp.sock` or `tcp:localhost:4444`
+    #[serde(rename = "qmp-socket")]
+    qmp_socket: Option<String>,
+
+    /// Ports forwarded from the host to the guest, using qemu user-mode networking.
+    ///
+    /// Used by `dadk boot run`, e.g. to ssh into the guest.
+    #[serde(rename = "port-forwards", default)]
+    port_forwards: Vec<PortForward>,
+
+    /// Directory on the host shared with the guest.
+    ///
+    /// Used by `dadk boot run`.
+    share: Option<SharedDir>,
 }
 
 impl QemuConfig {
@@ -71,6 +92,122 @@ impl QemuConfig {
     pub fn accelerate(&self) -> QemuAccel {
         self.accelerate.clone().unwrap_or(QemuAccel::None)
     }
+
+    /// Get the QMP socket address
+    pub fn qmp_socket(&self) -> Option<&str> {
+        self.qmp_socket.as_deref()
+    }
+
+    /// Get the port forwards
+    pub fn port_forwards(&self) -> &[PortForward] {
+        &self.port_forwards
+    }
+
+    /// Get the shared directory configuration
+    pub fn share(&self) -> Option<&SharedDir> {
+        self.share.as_ref()
+    }
+
+    /// Check the port forwards and the shared directory
+    pub fn validate(&self) -> Result<()> {
+        let mut host_ports = BTreeSet::new();
+        for forward in &self.port_forwards {
+            if forward.host == 0 || forward.guest == 0 {
+                return Err(anyhow!("qemu.port-forwards: port 0 is not allowed"));
+            }
+            if !host_ports.insert((forward.protocol, forward.host)) {
+                return Err(anyhow!(
+                    "qemu.port-forwards: host port {}/{} is forwarded more than once",
+                    forward.host,
+                    forward.protocol.name()
+                ));
+            }
+        }
+        if let Some(share) = &self.share {
+            share.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// A port forwarded from the host to the guest
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PortForward {
+    /// Port on the host
+    pub host: u16,
+    /// Port in the guest
+    pub guest: u16,
+    /// Defaults to tcp
+    #[serde(default)]
+    pub protocol: ForwardProtocol,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ForwardProtocol {
+    #[default]
+    #[serde(rename = "tcp")]
+    Tcp,
+    #[serde(rename = "udp")]
+    Udp,
+}
+
+impl ForwardProtocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForwardProtocol::Tcp => "tcp",
+            ForwardProtocol::Udp => "udp",
+        }
+    }
+}
+
+/// A directory on the host shared with the guest
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SharedDir {
+    /// Directory to share (relative to the DADK working directory).
+    ///
+    /// Defaults to the sysroot directory, so that freshly installed apps
+    /// are visible in the guest without rebuilding the disk image.
+    pub path: Option<PathBuf>,
+    /// Mount tag used by the guest to mount the directory
+    #[serde(default = "default_share_tag")]
+    pub tag: String,
+    /// How the directory is shared, defaults to 9p
+    #[serde(rename = "type", default)]
+    pub share_type: ShareType,
+    /// Share the directory read-only (9p only)
+    #[serde(default)]
+    pub readonly: bool,
+    /// Path of the virtiofsd binary (virtiofs only), defaults to `virtiofsd`
+    pub virtiofsd: Option<String>,
+}
+
+fn default_share_tag() -> String {
+    "dadk".to_string()
+}
+
+impl SharedDir {
+    fn validate(&self) -> Result<()> {
+        if self.tag.is_empty() || self.tag.contains([',', ' ']) {
+            return Err(anyhow!("qemu.share: invalid tag `{}`", self.tag));
+        }
+        if self.readonly && self.share_type == ShareType::Virtiofs {
+            return Err(anyhow!("qemu.share: readonly is only supported by 9p"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum ShareType {
+    /// virtio-9p, supported by qemu out of the box
+    #[default]
+    #[serde(rename = "9p")]
+    NineP,
+    /// virtio-fs, requires virtiofsd on the host
+    #[serde(rename = "virtiofs")]
+    Virtiofs,
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
@@ -41,6 +41,18 @@ pub enum BootProtocol {
     DragonStub,
 }
 
+impl BootProtocol {
+    /// The name used in the boot config
+    pub fn name(&self) -> &'static str {
+        match self {
+            BootProtocol::GrubLegacy => "grub-legacy",
+            BootProtocol::GrubEFI => "grub-efi",
+            BootProtocol::Direct => "direct",
+            BootProtocol::DragonStub => "dragon-stub",
+        }
+    }
+}
+
 /// The mode of booting
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub enum BootMode {
@@ -2,12 +2,14 @@ use std::{fs, path::PathBuf};
 
 use anyhow::Result;
 use dragonstub::DragonStubConfig;
-use grub::GrubConfig;
-use hypervisor::qemu::QemuConfig;
-use metadata::BootMetadata;
+use grub::{ArchConfig, GrubConfig};
+use hypervisor::{hyp_type::HypervisorType, qemu::QemuConfig};
+use metadata::{BootMetadata, BootProtocol};
 use serde::Deserialize;
 use uboot::UbootConfig;
 
+use crate::common::target_arch::TargetArch;
+
 pub mod dragonstub;
 pub mod grub;
 pub mod hypervisor;
@@ -41,7 +43,94 @@ impl BootConfigFile {
 
     pub fn load_from_str(content: &str) -> Result<Self> {
         let config: BootConfigFile = toml::from_str(content)?;
+        if let Some(qemu) = &config.qemu {
+            qemu.validate()?;
+        }
 
         Ok(config)
     }
+
+    /// Check that the sections required by the boot protocol and the hypervisor are present
+    /// and do not conflict. Returns all problems found
+    pub fn check(&self, arch: TargetArch) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.grub.is_some() && self.dragonstub.is_some() {
+            problems.push(
+                "[grub] and [dragonstub] are mutually exclusive, keep the one used by boot-protocol"
+                    .to_string(),
+            );
+        }
+        match self.metadata.boot_protocol {
+            BootProtocol::GrubLegacy | BootProtocol::GrubEFI => {
+                if arch != TargetArch::X86_64 {
+                    problems.push(format!(
+                        "boot-protocol {} is not supported on {}",
+                        self.metadata.boot_protocol.name(),
+                        <&str>::from(arch)
+                    ));
+                } else if self.grub_arch_config().is_none() {
+                    problems.push(format!(
+                        "boot-protocol {} requires [grub.{}]",
+                        self.metadata.boot_protocol.name(),
+                        self.grub_arch_name()
+                    ));
+                }
+            }
+            BootProtocol::DragonStub => {
+                if arch != TargetArch::RiscV64 {
+                    problems.push(format!(
+                        "boot-protocol dragon-stub can only be used with riscv64, not {}",
+                        <&str>::from(arch)
+                    ));
+                }
+                if self.dragonstub.is_none() {
+                    problems.push("boot-protocol dragon-stub requires [dragonstub]".to_string());
+                }
+            }
+            BootProtocol::Direct => {}
+        }
+        if self.metadata.hypervisor == HypervisorType::Qemu && self.qemu.is_none() {
+            problems.push("hypervisor qemu requires [qemu]".to_string());
+        }
+        problems
+    }
+
+    /// Files referenced by the sections used by the boot protocol: (name, path).
+    /// Relative paths are relative to the working directory
+    pub fn referenced_paths(&self) -> Vec<(&'static str, PathBuf)> {
+        match self.metadata.boot_protocol {
+            BootProtocol::GrubLegacy | BootProtocol::GrubEFI => self
+                .grub_arch_config()
+                .map(|c| {
+                    vec![
+                        ("grub-file", PathBuf::from(&c.grub_file)),
+                        ("grub-install", PathBuf::from(&c.grub_install)),
+                    ]
+                })
+                .unwrap_or_default(),
+            BootProtocol::DragonStub => self
+                .dragonstub
+                .iter()
+                .map(|d| ("dragonstub", PathBuf::from(&d.src_path)))
+                .collect(),
+            BootProtocol::Direct => Vec::new(),
+        }
+    }
+
+    /// The `[grub.*]` section used by the grub boot protocols on x86_64
+    fn grub_arch_config(&self) -> Option<&ArchConfig> {
+        let grub = self.grub.as_ref()?;
+        match self.metadata.boot_protocol {
+            BootProtocol::GrubLegacy => grub.i386_legacy.as_ref(),
+            BootProtocol::GrubEFI => grub.x86_64_efi.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn grub_arch_name(&self) -> &'static str {
+        match self.metadata.boot_protocol {
+            BootProtocol::GrubLegacy => "i386-legacy",
+            _ => "x86_64-efi",
+        }
+    }
 }
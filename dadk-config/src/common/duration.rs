@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+
+/// 解析表示时长的字符串，例如`"90"`、`"45s"`、`"30m"`、`"1h30m"`
+///
+/// 支持的单位为s（秒）、m（分钟）、h（小时）、d（天），没有单位的数字表示秒。
+/// 多个`数字+单位`可以连写，结果为它们的和。
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid = || Error::msg(format!("invalid duration '{}', expected like \"30m\"", s));
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let n: u64 = number.parse().map_err(|_| invalid())?;
+        total = n
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        // 数字后面缺少单位，例如"1h30"
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(
+            parse_duration(" 2H ").unwrap(),
+            Duration::from_secs(2 * 3600)
+        );
+        assert_eq!(
+            parse_duration("1h30m").unwrap(),
+            Duration::from_secs(3600 + 30 * 60)
+        );
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        for s in ["", "m", "1h30", "10x", "-5s", "1.5h"] {
+            assert!(parse_duration(s).is_err(), "'{}' should be invalid", s);
+        }
+    }
+}
@@ -1,2 +1,6 @@
+pub mod duration;
+pub mod size;
 pub mod target_arch;
 pub mod task;
+pub mod template;
+pub mod version_req;
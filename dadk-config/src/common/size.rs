@@ -0,0 +1,47 @@
+use anyhow::{Error, Result};
+
+/// 解析表示大小的字符串，例如`"4096"`、`"512M"`、`"8G"`、`"1.5GiB"`
+///
+/// 单位为K、M、G、T（1024进制，可以带`B`或者`iB`后缀，不区分大小写），没有单位的数字表示字节。
+pub fn parse_size(s: &str) -> Result<u64> {
+    let invalid = |reason: &str| Error::msg(format!("Invalid size {:?}{}", s, reason));
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let upper = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let (number, unit) = match upper.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => upper.split_at(i),
+        None => (upper, ""),
+    };
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid(": unknown unit")),
+    };
+    let number: f64 = number.trim().parse().map_err(|_| invalid(""))?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid(""));
+    }
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("8G").unwrap(), 8 << 30);
+        assert_eq!(parse_size(" 512mb ").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        for s in ["", "G", "10X", "-1G"] {
+            assert!(parse_size(s).is_err(), "'{}' should be invalid", s);
+        }
+    }
+}
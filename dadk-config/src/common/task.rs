@@ -1,18 +1,41 @@
 use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+use super::{duration::parse_duration, size::parse_size, target_arch::TargetArch};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TaskSource {
     #[serde(rename = "type")]
     pub source_type: TaskSourceType,
     pub source: Source,
-    #[serde(rename = "source-path")]
+    /// 路径或URL。source为`repository`时为软件仓库中的包名（可选，默认与任务名相同）
+    #[serde(default, rename = "source-path")]
     pub source_path: String,
     /// 分支（可选，如果为空，则拉取master）branch和revision只能同时指定一个
     pub branch: Option<String>,
     /// 特定的提交的hash值（可选，如果为空，则拉取branch的最新提交）
     pub revision: Option<String>,
+    /// 拉取源文件之后、构建之前，按顺序应用到源码目录的补丁文件（相对于配置文件所在的目录）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<PathBuf>,
+    /// 源码缓存的共享键（可选）。键相同的任务共用同一个源码缓存目录，只拉取一次源码
+    #[serde(
+        default,
+        rename = "source-cache-key",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub source_cache_key: Option<String>,
+    /// 不使用dadk-manifest.toml中`[mirrors]`配置的镜像，总是从`source-path`拉取
+    #[serde(
+        default,
+        rename = "no-mirror",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub no_mirror: bool,
 }
 
 /// # 任务类型
@@ -24,10 +47,48 @@ pub enum TaskSourceType {
     /// 从预编译包安装
     #[serde(rename = "install-from-prebuilt")]
     InstallFromPrebuilt,
+    /// 从源码使用cargo构建，由DADK执行`cargo build`并复制构建结果
+    #[serde(rename = "cargo")]
+    Cargo,
+    /// 从源码使用CMake构建，由DADK生成配置、构建、安装命令
+    #[serde(rename = "cmake")]
+    Cmake,
+    /// 从源码使用autotools（`./configure && make && make install`）构建，由DADK生成构建命令
+    #[serde(rename = "autotools")]
+    Autotools,
+    /// 从`dadk user package`生成的二进制包（`.dpk`文件）安装
+    #[serde(rename = "package")]
+    Package,
+    /// 从源码构建DragonOS内核模块，安装到`/lib/modules/<内核版本>/`下
+    #[serde(rename = "kernel-module")]
+    KernelModule,
+}
+
+impl TaskSourceType {
+    /// 配置文件中的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskSourceType::BuildFromSource => "build-from-source",
+            TaskSourceType::InstallFromPrebuilt => "install-from-prebuilt",
+            TaskSourceType::Cargo => "cargo",
+            TaskSourceType::Cmake => "cmake",
+            TaskSourceType::Autotools => "autotools",
+            TaskSourceType::Package => "package",
+            TaskSourceType::KernelModule => "kernel-module",
+        }
+    }
+
+    /// 是否由DADK生成构建命令（不需要填写`build-command`）
+    pub fn generates_build_command(&self) -> bool {
+        matches!(
+            self,
+            TaskSourceType::Cargo | TaskSourceType::Cmake | TaskSourceType::Autotools
+        )
+    }
 }
 
 /// # 来源类型
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Source {
     /// 从Git仓库获取
     #[serde(rename = "git")]
@@ -38,6 +99,153 @@ pub enum Source {
     /// 从在线压缩包获取
     #[serde(rename = "archive")]
     Archive,
+    /// 从dadk-manifest.toml中配置的软件仓库获取二进制包
+    #[serde(rename = "repository")]
+    Repository,
+}
+
+/// cargo任务的构建配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CargoConfig {
+    /// 编译目标（target triple或者target JSON文件的路径），为空时根据目标架构选择
+    #[serde(
+        rename = "rust-target",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rust_target: Option<String>,
+    /// 启用的features
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// 是否禁用默认的features
+    #[serde(rename = "no-default-features", default)]
+    pub no_default_features: bool,
+    /// 只构建、复制这些二进制文件，为空时复制所有的二进制目标
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bins: Vec<String>,
+    /// 传递给`cargo build`的其他参数
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+impl CargoConfig {
+    /// 目标架构对应的默认编译目标
+    pub fn default_rust_target(arch: TargetArch) -> &'static str {
+        match arch {
+            TargetArch::X86_64 => "x86_64-unknown-linux-musl",
+            TargetArch::RiscV64 => "riscv64gc-unknown-linux-musl",
+            TargetArch::AArch64 => "aarch64-unknown-linux-musl",
+        }
+    }
+
+    /// 实际使用的编译目标
+    pub fn rust_target(&self, arch: TargetArch) -> String {
+        self.rust_target
+            .clone()
+            .unwrap_or_else(|| Self::default_rust_target(arch).to_string())
+    }
+
+    /// 构建结果所在的目录名称（`target/<name>/release`）
+    ///
+    /// 使用target JSON文件时，目录名称为文件名（不含扩展名）
+    pub fn target_dir_name(&self, arch: TargetArch) -> String {
+        let target = self.rust_target(arch);
+        if target.ends_with(".json") {
+            if let Some(stem) = Path::new(&target).file_stem() {
+                return stem.to_string_lossy().to_string();
+            }
+        }
+        target
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self
+            .rust_target
+            .as_ref()
+            .is_some_and(|t| t.trim().is_empty())
+        {
+            return Err(Error::msg("CargoConfig: rust-target is empty"));
+        }
+        if self.bins.iter().any(|b| b.trim().is_empty()) {
+            return Err(Error::msg("CargoConfig: bins contains an empty name"));
+        }
+        Ok(())
+    }
+
+    pub fn trim(&mut self) {
+        if let Some(target) = &mut self.rust_target {
+            *target = target.trim().to_string();
+        }
+        for s in self.features.iter_mut().chain(self.bins.iter_mut()) {
+            *s = s.trim().to_string();
+        }
+    }
+}
+
+/// cmake任务的构建配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CmakeConfig {
+    /// `CMAKE_BUILD_TYPE`，默认为`Release`
+    #[serde(
+        rename = "build-type",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub build_type: Option<String>,
+    /// CMake的构建目录（相对于源码目录），默认为`build`
+    #[serde(rename = "build-dir", default, skip_serializing_if = "Option::is_none")]
+    pub build_dir: Option<String>,
+    /// 传递给`cmake`配置命令的其他参数，例如`-DBUILD_SHARED_LIBS=OFF`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+impl CmakeConfig {
+    pub fn build_type(&self) -> &str {
+        self.build_type.as_deref().unwrap_or("Release")
+    }
+
+    pub fn build_dir(&self) -> &str {
+        self.build_dir.as_deref().unwrap_or("build")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.build_type().trim().is_empty() {
+            return Err(Error::msg("CmakeConfig: build-type is empty"));
+        }
+        if self.build_dir().trim().is_empty() {
+            return Err(Error::msg("CmakeConfig: build-dir is empty"));
+        }
+        Ok(())
+    }
+
+    pub fn trim(&mut self) {
+        for s in [&mut self.build_type, &mut self.build_dir]
+            .into_iter()
+            .flatten()
+        {
+            *s = s.trim().to_string();
+        }
+    }
+}
+
+/// autotools任务的构建配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AutotoolsConfig {
+    /// 传递给`./configure`的其他参数，例如`--disable-nls`
+    #[serde(
+        rename = "configure-options",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub configure_options: Vec<String>,
+    /// 传递给`make`的其他参数
+    #[serde(
+        rename = "make-options",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub make_options: Vec<String>,
 }
 
 /// @brief 构建配置
@@ -52,6 +260,100 @@ pub struct BuildConfig {
     #[serde(rename = "post-build")]
     /// 构建后执行的脚本
     pub post_build: Option<PathBuf>,
+    /// 构建命令的超时时间，例如"30m"。超时后构建命令会被终止，任务失败
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    /// 构建命令可以使用的CPU、内存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourcesConfig>,
+    /// 是否使用编译缓存（ccache/sccache），为None时使用manifest中的设置
+    #[serde(
+        rename = "compiler-cache",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub compiler_cache: Option<bool>,
+    /// 执行构建、清理命令的shell，为None时使用manifest中的设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<BuildShell>,
+    /// 是否在命令前加上`set -euo pipefail`，为None时使用manifest中的设置
+    #[serde(
+        rename = "shell-strict",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub shell_strict: Option<bool>,
+    /// 构建成功后，构建结果目录中应当存在的文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<OutputsConfig>,
+}
+
+/// # 构建结果的检查规则
+///
+/// 构建成功后，检查`DADK_CURRENT_BUILD_DIR`中是否存在这些文件，避免构建脚本把文件安装到了错误的路径
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutputsConfig {
+    /// 相对于构建结果目录的路径，支持通配符：`*`、`?`匹配路径中的一段，`**`匹配任意多段，
+    /// 例如`bin/helloworld`、`lib/*.so`、`**/*.a`
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+impl OutputsConfig {
+    pub fn validate(&self) -> Result<()> {
+        for file in &self.files {
+            if file.is_empty() {
+                return Err(Error::msg("OutputsConfig: files contains an empty path"));
+            }
+            let path = Path::new(file);
+            if path.is_absolute()
+                || path
+                    .components()
+                    .any(|c| matches!(c, std::path::Component::ParentDir))
+            {
+                return Err(Error::msg(format!(
+                    "OutputsConfig: {} should be a relative path inside the build dir",
+                    file
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn trim(&mut self) {
+        for file in &mut self.files {
+            *file = file.trim().to_string();
+        }
+    }
+}
+
+/// # 执行构建、清理命令的shell
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildShell {
+    Sh,
+    #[default]
+    Bash,
+    Zsh,
+}
+
+impl BuildShell {
+    /// shell程序的名称
+    pub fn program(&self) -> &'static str {
+        match self {
+            BuildShell::Sh => "sh",
+            BuildShell::Bash => "bash",
+            BuildShell::Zsh => "zsh",
+        }
+    }
+
+    /// 严格模式下加在命令前面的语句。POSIX sh不一定支持`pipefail`，因此只设置`-eu`
+    pub fn strict_prelude(&self) -> &'static str {
+        match self {
+            BuildShell::Sh => "set -eu",
+            BuildShell::Bash | BuildShell::Zsh => "set -euo pipefail",
+        }
+    }
 }
 
 impl BuildConfig {
@@ -65,10 +367,28 @@ impl BuildConfig {
             build_command,
             pre_build,
             post_build,
+            timeout: None,
+            resources: None,
+            compiler_cache: None,
+            shell: None,
+            shell_strict: None,
+            outputs: None,
         }
     }
 
+    /// 解析超时时间
+    pub fn timeout_duration(&self) -> Result<Option<Duration>> {
+        parse_timeout("BuildConfig", self.timeout.as_deref())
+    }
+
     pub fn validate(&self) -> Result<()> {
+        self.timeout_duration()?;
+        if let Some(resources) = &self.resources {
+            resources.validate()?;
+        }
+        if let Some(outputs) = &self.outputs {
+            outputs.validate()?;
+        }
         return Ok(());
     }
 
@@ -76,6 +396,117 @@ impl BuildConfig {
         if let Some(build_command) = &mut self.build_command {
             *build_command = build_command.trim().to_string();
         }
+        if let Some(timeout) = &mut self.timeout {
+            *timeout = timeout.trim().to_string();
+        }
+        if let Some(memory) = self.resources.as_mut().and_then(|r| r.memory.as_mut()) {
+            *memory = memory.trim().to_string();
+        }
+        if let Some(outputs) = &mut self.outputs {
+            outputs.trim();
+        }
+    }
+}
+
+/// # 构建命令的资源限制
+///
+/// 避免某个任务（例如开启了LTO的任务）占用过多的CPU和内存，影响同时构建的其他任务
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResourcesConfig {
+    /// 最多使用的CPU数量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<u32>,
+    /// 最多使用的内存，例如"8G"、"512M"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+}
+
+impl ResourcesConfig {
+    /// 解析内存限制（字节）
+    pub fn memory_bytes(&self) -> Result<Option<u64>> {
+        let Some(memory) = &self.memory else {
+            return Ok(None);
+        };
+        let bytes = parse_size(memory)
+            .map_err(|e| Error::msg(format!("ResourcesConfig: memory: {}", e)))?;
+        if bytes == 0 {
+            return Err(Error::msg(
+                "ResourcesConfig: memory should be greater than 0",
+            ));
+        }
+        Ok(Some(bytes))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.cpus == Some(0) {
+            return Err(Error::msg("ResourcesConfig: cpus should be greater than 0"));
+        }
+        self.memory_bytes()?;
+        Ok(())
+    }
+}
+
+/// # 测试配置
+///
+/// 由`dadk user test`执行，任务需要先构建成功
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TestConfig {
+    /// 在主机上执行的测试命令
+    #[serde(
+        rename = "test-command",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub test_command: Option<String>,
+    /// 复制到磁盘镜像中，在QEMU中执行的测试脚本（相对于DADK的工作目录）
+    #[serde(
+        rename = "guest-script",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub guest_script: Option<PathBuf>,
+    /// 主机上的测试命令的超时时间，例如"10m"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+impl TestConfig {
+    /// 解析超时时间
+    pub fn timeout_duration(&self) -> Result<Option<Duration>> {
+        parse_timeout("TestConfig", self.timeout.as_deref())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.test_command.is_none() && self.guest_script.is_none() {
+            return Err(Error::msg(
+                "TestConfig: test-command or guest-script should be set",
+            ));
+        }
+        if self
+            .test_command
+            .as_ref()
+            .is_some_and(|c| c.trim().is_empty())
+        {
+            return Err(Error::msg("TestConfig: test-command should not be empty"));
+        }
+        if self
+            .guest_script
+            .as_ref()
+            .is_some_and(|p| p.as_os_str().is_empty())
+        {
+            return Err(Error::msg("TestConfig: guest-script should not be empty"));
+        }
+        self.timeout_duration()?;
+        Ok(())
+    }
+
+    pub fn trim(&mut self) {
+        for s in [&mut self.test_command, &mut self.timeout]
+            .into_iter()
+            .flatten()
+        {
+            *s = s.trim().to_string();
+        }
     }
 }
 
@@ -84,27 +515,174 @@ pub struct InstallConfig {
     /// 安装到DragonOS内的目录
     #[serde(rename = "in-dragonos-path")]
     pub in_dragonos_path: Option<PathBuf>,
+    /// 对安装后的文件设置权限、属主，或者创建符号链接
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<InstallFileConfig>,
+    /// 安装时去除ELF文件中的调试信息和不需要的符号
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip: bool,
+    /// strip使用的工具（默认根据目标架构选择）
+    #[serde(
+        rename = "strip-tool",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub strip_tool: Option<String>,
+    /// 不安装的文件（通配符，相对于构建结果目录），例如`*.o`、`target/`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
 }
 
 impl InstallConfig {
     #[allow(dead_code)]
     pub fn new(in_dragonos_path: Option<PathBuf>) -> Self {
-        Self { in_dragonos_path }
+        Self {
+            in_dragonos_path,
+            files: Vec::new(),
+            strip: false,
+            strip_tool: None,
+            exclude: Vec::new(),
+        }
     }
 
     pub fn validate(&self) -> Result<()> {
         if self.in_dragonos_path.is_none() {
+            if !self.files.is_empty() {
+                return Err(Error::msg(
+                    "InstallConfig: files requires in_dragonos_path to be set",
+                ));
+            }
             return Ok(());
         }
-        if self.in_dragonos_path.as_ref().unwrap().is_relative() {
+        let in_dragonos_path = self.in_dragonos_path.as_ref().unwrap();
+        if in_dragonos_path.is_relative() {
             return Err(Error::msg(
                 "InstallConfig: in_dragonos_path should be an Absolute path",
             ));
         }
+        // 安装路径不能通过`..`跳出sysroot
+        if in_dragonos_path
+            .components()
+            .any(|c| c == std::path::Component::ParentDir)
+        {
+            return Err(Error::msg(format!(
+                "InstallConfig: in_dragonos_path '{}' should not contain '..'",
+                in_dragonos_path.display()
+            )));
+        }
+        for file in &self.files {
+            file.validate()?;
+        }
+        if self.strip_tool.as_ref().is_some_and(|t| t.is_empty()) {
+            return Err(Error::msg("InstallConfig: strip-tool should not be empty"));
+        }
+        for pattern in &self.exclude {
+            if pattern.is_empty() || pattern == "/" {
+                return Err(Error::msg(
+                    "InstallConfig: exclude pattern should not be empty",
+                ));
+            }
+            if pattern.split('/').any(|p| p == "..") {
+                return Err(Error::msg(format!(
+                    "InstallConfig: exclude pattern '{}' should not contain '..'",
+                    pattern
+                )));
+            }
+        }
         return Ok(());
     }
 
-    pub fn trim(&mut self) {}
+    pub fn trim(&mut self) {
+        for file in &mut self.files {
+            file.trim();
+        }
+        if let Some(strip_tool) = &mut self.strip_tool {
+            *strip_tool = strip_tool.trim().to_string();
+        }
+        for pattern in &mut self.exclude {
+            *pattern = pattern.trim().to_string();
+        }
+    }
+}
+
+/// # 安装文件的属性
+///
+/// 在把构建结果安装到DragonOS时，对安装目录下的某个文件：
+///
+/// - 设置权限（`mode`，八进制，可包含setuid/setgid/sticky位，如`"4755"`）
+/// - 设置属主（`uid`、`gid`）
+/// - 或者把它创建为指向`symlink`的符号链接
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InstallFileConfig {
+    /// 相对于安装目录（`in-dragonos-path`）的路径
+    pub path: PathBuf,
+    /// 八进制的权限位
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    /// 符号链接指向的目标（原样写入链接，可以是相对路径）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<PathBuf>,
+}
+
+impl InstallFileConfig {
+    /// 解析权限位
+    pub fn mode_bits(&self) -> Result<Option<u32>> {
+        let Some(mode) = &self.mode else {
+            return Ok(None);
+        };
+        let bits = u32::from_str_radix(mode.trim_start_matches("0o"), 8).map_err(|_| {
+            Error::msg(format!(
+                "InstallFileConfig: invalid mode '{}' for {}, expected octal like \"4755\"",
+                mode,
+                self.path.display()
+            ))
+        })?;
+        if bits > 0o7777 {
+            return Err(Error::msg(format!(
+                "InstallFileConfig: mode '{}' for {} is out of range",
+                mode,
+                self.path.display()
+            )));
+        }
+        Ok(Some(bits))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.path.as_os_str().is_empty() || self.path.is_absolute() {
+            return Err(Error::msg(format!(
+                "InstallFileConfig: path '{}' should be a non-empty path relative to in-dragonos-path",
+                self.path.display()
+            )));
+        }
+        if self
+            .path
+            .components()
+            .any(|c| c == std::path::Component::ParentDir)
+        {
+            return Err(Error::msg(format!(
+                "InstallFileConfig: path '{}' should not contain '..'",
+                self.path.display()
+            )));
+        }
+        if self.symlink.is_some() && self.mode.is_some() {
+            return Err(Error::msg(format!(
+                "InstallFileConfig: mode cannot be set on symlink {}",
+                self.path.display()
+            )));
+        }
+        self.mode_bits()?;
+        Ok(())
+    }
+
+    pub fn trim(&mut self) {
+        if let Some(mode) = &mut self.mode {
+            *mode = mode.trim().to_string();
+        }
+    }
 }
 /// # 清理配置
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -112,15 +690,27 @@ pub struct CleanConfig {
     /// 清理命令
     #[serde(rename = "clean-command")]
     pub clean_command: Option<String>,
+    /// 清理命令的超时时间，例如"5m"。超时后清理命令会被终止，任务失败
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
 }
 
 impl CleanConfig {
     #[allow(dead_code)]
     pub fn new(clean_command: Option<String>) -> Self {
-        Self { clean_command }
+        Self {
+            clean_command,
+            timeout: None,
+        }
+    }
+
+    /// 解析超时时间
+    pub fn timeout_duration(&self) -> Result<Option<Duration>> {
+        parse_timeout("CleanConfig", self.timeout.as_deref())
     }
 
     pub fn validate(&self) -> Result<()> {
+        self.timeout_duration()?;
         return Ok(());
     }
 
@@ -128,10 +718,187 @@ impl CleanConfig {
         if let Some(clean_command) = &mut self.clean_command {
             *clean_command = clean_command.trim().to_string();
         }
+        if let Some(timeout) = &mut self.timeout {
+            *timeout = timeout.trim().to_string();
+        }
+    }
+}
+
+fn parse_timeout(config: &str, timeout: Option<&str>) -> Result<Option<Duration>> {
+    let Some(timeout) = timeout else {
+        return Ok(None);
+    };
+    let duration =
+        parse_duration(timeout).map_err(|e| Error::msg(format!("{}: timeout: {}", config, e)))?;
+    if duration.is_zero() {
+        return Err(Error::msg(format!(
+            "{}: timeout should be greater than 0",
+            config
+        )));
+    }
+    Ok(Some(duration))
+}
+
+/// # 任务的调度优先级
+///
+/// 配置文件中可以写作`"high"`、`"normal"`、`"low"`，或者整数权重。
+/// 多个任务可以同时开始执行时，数值大的任务先执行
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskPriority(pub i32);
+
+impl TaskPriority {
+    pub const HIGH: Self = Self(10);
+    pub const NORMAL: Self = Self(0);
+    pub const LOW: Self = Self(-10);
+
+    pub fn is_normal(&self) -> bool {
+        *self == Self::NORMAL
+    }
+}
+
+impl TryFrom<&str> for TaskPriority {
+    type Error = String;
+
+    fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "high" => Ok(Self::HIGH),
+            "normal" => Ok(Self::NORMAL),
+            "low" => Ok(Self::LOW),
+            _ => Err(format!(
+                "invalid priority '{}', expected high, normal, low or an integer",
+                s
+            )),
+        }
+    }
+}
+
+impl Serialize for TaskPriority {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match *self {
+            Self::HIGH => serializer.serialize_str("high"),
+            Self::NORMAL => serializer.serialize_str("normal"),
+            Self::LOW => serializer.serialize_str("low"),
+            Self(weight) => serializer.serialize_i32(weight),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TaskPriority {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Weight(i32),
+            Level(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Weight(weight) => Ok(Self(weight)),
+            Repr::Level(level) => Self::try_from(level.as_str()).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// # 构建任务依赖的主机软件包
+///
+/// 配置文件中可以写作软件包名称（同时也是需要的命令），例如`"nasm"`；
+/// 命令与软件包不同名时写作`{ package = "dosfstools", command = "mkfs.fat" }`。
+/// 构建之前DADK检查命令是否在`PATH`中
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HostPackage {
+    /// 主机上的软件包名称，用于给出安装建议
+    pub package: String,
+    /// 需要的命令，为None时与软件包同名
+    pub command: Option<String>,
+}
+
+impl HostPackage {
+    pub fn new(package: &str) -> Self {
+        Self {
+            package: package.to_string(),
+            command: None,
+        }
+    }
+
+    pub fn trim(&mut self) {
+        self.package = self.package.trim().to_string();
+        if let Some(command) = &mut self.command {
+            *command = command.trim().to_string();
+        }
+    }
+
+    /// 需要在`PATH`中找到的命令
+    pub fn command(&self) -> &str {
+        self.command.as_deref().unwrap_or(&self.package)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.package.trim().is_empty() {
+            return Err(Error::msg("depends-on-host-packages: package is empty"));
+        }
+        let command = self.command();
+        if command.is_empty() || command.contains(char::is_whitespace) {
+            return Err(Error::msg(format!(
+                "depends-on-host-packages: invalid command '{}'",
+                command
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for HostPackage {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Table<'a> {
+            package: &'a str,
+            command: &'a str,
+        }
+        match &self.command {
+            None => serializer.serialize_str(&self.package),
+            Some(command) => Table {
+                package: &self.package,
+                command,
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HostPackage {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Name(String),
+            Table {
+                package: String,
+                command: Option<String>,
+            },
+        }
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Name(package) => Self {
+                package,
+                command: None,
+            },
+            Repr::Table { package, command } => Self { package, command },
+        })
     }
 }
 
 /// @brief 依赖项
+///
+/// 省略`version`时，依赖该名称的默认版本：manifest中`[metadata.default-versions]`指定的版本，
+/// 没有指定时为唯一的版本
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Dependency {
     #[serde(default = "default_empty_string")]
@@ -150,9 +917,6 @@ impl Dependency {
         if self.name.is_empty() {
             return Err(Error::msg("name is empty"));
         }
-        if self.version.is_empty() {
-            return Err(Error::msg("version is empty"));
-        }
         return Ok(());
     }
 
@@ -0,0 +1,115 @@
+//! 配置文件中的变量替换
+//!
+//! 字符串中的`${NAME}`会被替换为变量`NAME`的值。未定义的变量保持原样，
+//! 因此构建命令中引用的shell环境变量（例如`${DADK_CURRENT_BUILD_DIR}`）不受影响。
+//! `$${NAME}`会被替换为`${NAME}`，不进行替换。
+
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+/// 替换字符串中的变量
+pub fn expand_vars(s: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find("${") {
+        // `$${`转义为`${`
+        if rest[..pos].ends_with('$') {
+            out.push_str(&rest[..pos - 1]);
+            out.push_str("${");
+            rest = &rest[pos + 2..];
+            continue;
+        }
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let value = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|name| is_var_name(name))
+            .and_then(|name| vars.get(name).map(|value| (name, value)));
+        match value {
+            Some((name, value)) => {
+                out.push_str(value);
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                out.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 替换TOML值中所有字符串里的变量（不替换键）
+pub fn expand_value(value: &mut Value, vars: &BTreeMap<String, String>) {
+    match value {
+        Value::String(s) => *s = expand_vars(s, vars),
+        Value::Array(array) => array.iter_mut().for_each(|v| expand_value(v, vars)),
+        Value::Table(table) => table.iter_mut().for_each(|(_, v)| expand_value(v, vars)),
+        _ => {}
+    }
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("ARCH".to_string(), "riscv64".to_string()),
+            (
+                "MIRROR".to_string(),
+                "https://mirrors.dragonos.org.cn".to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let vars = vars();
+        assert_eq!(
+            expand_vars("${MIRROR}/musl-${ARCH}.tar.gz", &vars),
+            "https://mirrors.dragonos.org.cn/musl-riscv64.tar.gz"
+        );
+        assert_eq!(expand_vars("no variables", &vars), "no variables");
+        // 未定义的变量、不合法的变量名保持原样
+        assert_eq!(
+            expand_vars("cp a ${DADK_CURRENT_BUILD_DIR}/", &vars),
+            "cp a ${DADK_CURRENT_BUILD_DIR}/"
+        );
+        assert_eq!(expand_vars("${ARCH-x}${", &vars), "${ARCH-x}${");
+        assert_eq!(expand_vars("${ARCH", &vars), "${ARCH");
+        // 转义
+        assert_eq!(expand_vars("$${ARCH} ${ARCH}", &vars), "${ARCH} riscv64");
+        assert_eq!(expand_vars("$ARCH", &vars), "$ARCH");
+    }
+
+    #[test]
+    fn test_expand_value() {
+        let mut value: Value = toml::from_str(
+            r#"
+            url = "${MIRROR}/${ARCH}"
+            list = ["${ARCH}", 1]
+            [table]
+            "${ARCH}" = "${ARCH}"
+            "#,
+        )
+        .unwrap();
+        expand_value(&mut value, &vars());
+        assert_eq!(
+            value["url"].as_str(),
+            Some("https://mirrors.dragonos.org.cn/riscv64")
+        );
+        assert_eq!(value["list"][0].as_str(), Some("riscv64"));
+        assert_eq!(value["list"][1].as_integer(), Some(1));
+        assert_eq!(value["table"]["${ARCH}"].as_str(), Some("riscv64"));
+    }
+}
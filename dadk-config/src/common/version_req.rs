@@ -0,0 +1,198 @@
+use std::fmt::Display;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Deserializer};
+
+/// 版本号`major.minor.patch`（忽略`-`之后的预发布标识）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::msg(format!("Invalid version {:?}", s));
+        let core = s.trim().split(['-', '+']).next().unwrap_or_default();
+        let parts = parse_parts(core).ok_or_else(invalid)?;
+        match parts[..] {
+            [major, minor, patch] => Ok(Self {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// # 版本要求
+///
+/// 与Cargo的版本要求相同，多个条件用逗号分隔，例如`">=0.4, <0.5"`。支持的运算符：
+///
+/// - `>=`、`>`、`<=`、`<`、`=`：省略的部分按照Cargo的规则处理，例如`>0.4`表示`>=0.5.0`，`=0.4`表示`0.4.*`
+/// - `^`（没有运算符时的默认值）：不改变最左边的非零部分，例如`^0.4`表示`>=0.4.0, <0.5.0`
+/// - `~`：只允许修改补丁号（只指定主版本号时允许修改次版本号），例如`~0.4.1`表示`>=0.4.1, <0.5.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    text: String,
+    /// 每个条件对应的范围：[下界, 上界)，None表示没有限制
+    ranges: Vec<(Option<Version>, Option<Version>)>,
+}
+
+impl VersionReq {
+    pub fn parse(s: &str) -> Result<Self> {
+        let ranges = s
+            .split(',')
+            .map(|c| parse_comparator(c.trim()))
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| Error::msg(format!("Invalid version requirement {:?}: {}", s, e)))?;
+        Ok(Self {
+            text: s.trim().to_string(),
+            ranges,
+        })
+    }
+
+    /// 版本是否满足所有条件
+    pub fn matches(&self, version: &Version) -> bool {
+        self.ranges.iter().all(|(lower, upper)| {
+            lower.map_or(true, |l| *version >= l) && upper.map_or(true, |u| *version < u)
+        })
+    }
+
+    /// 版本是否低于要求的下界（用于提示升级还是降级）
+    pub fn is_too_old(&self, version: &Version) -> bool {
+        self.ranges
+            .iter()
+            .any(|(lower, _)| lower.is_some_and(|l| *version < l))
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionReq {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        VersionReq::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// 解析`1`、`1.2`、`1.2.3`，最多三个部分
+fn parse_parts(s: &str) -> Option<Vec<u64>> {
+    let parts = s
+        .split('.')
+        .map(|p| p.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    (1..=3).contains(&parts.len()).then_some(parts)
+}
+
+/// 把单个条件转换为[下界, 上界)
+fn parse_comparator(s: &str) -> Result<(Option<Version>, Option<Version>)> {
+    let op_len = s
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(|| Error::msg(format!("missing version in {:?}", s)))?;
+    let (op, version) = s.split_at(op_len);
+    let parts =
+        parse_parts(version).ok_or_else(|| Error::msg(format!("invalid version {:?}", version)))?;
+    let (major, minor, patch) = (parts[0], parts.get(1).copied(), parts.get(2).copied());
+    let lowest = Version::new(major, minor.unwrap_or(0), patch.unwrap_or(0));
+    // 省略的部分为通配符时，范围的上界（不包含）
+    let next = match (minor, patch) {
+        (None, _) => Version::new(major + 1, 0, 0),
+        (Some(minor), None) => Version::new(major, minor + 1, 0),
+        (Some(minor), Some(patch)) => Version::new(major, minor, patch + 1),
+    };
+
+    Ok(match op.trim() {
+        ">=" => (Some(lowest), None),
+        ">" => (Some(next), None),
+        "<" => (None, Some(lowest)),
+        "<=" => (None, Some(next)),
+        "=" => (Some(lowest), Some(next)),
+        "~" => {
+            let upper = match minor {
+                None => Version::new(major + 1, 0, 0),
+                Some(minor) => Version::new(major, minor + 1, 0),
+            };
+            (Some(lowest), Some(upper))
+        }
+        "^" | "" => {
+            let upper = match (major, minor, patch) {
+                (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+                (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+                _ => Version::new(major + 1, 0, 0),
+            };
+            (Some(lowest), Some(upper))
+        }
+        op => return Err(Error::msg(format!("unknown operator {:?}", op))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(req: &str, version: &str) -> bool {
+        VersionReq::parse(req)
+            .unwrap()
+            .matches(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn test_version_req() {
+        assert!(matches(">=0.4, <0.5", "0.4.0"));
+        assert!(matches(">=0.4, <0.5", "0.4.9"));
+        assert!(!matches(">=0.4, <0.5", "0.5.0"));
+        assert!(!matches(">=0.4, <0.5", "0.3.9"));
+        assert!(matches(">0.4", "0.5.0"));
+        assert!(!matches(">0.4", "0.4.9"));
+        assert!(matches("<=0.4", "0.4.9"));
+        assert!(matches("=0.4", "0.4.3"));
+        assert!(!matches("=0.4.2", "0.4.3"));
+        assert!(matches("0.4", "0.4.3"));
+        assert!(!matches("^0.4", "0.5.0"));
+        assert!(matches("^1.2", "1.9.0"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(matches("~0.4.1", "0.4.7"));
+        assert!(!matches("~0.4.1", "0.4.0"));
+        assert!(matches("~1", "1.9.0"));
+        assert!(matches(">=0.2", "0.2.0-beta.1"));
+        for req in ["", ">=", "0.4.x", "!=0.4", ">=0.4,", "1.2.3.4"] {
+            assert!(
+                VersionReq::parse(req).is_err(),
+                "{:?} should be invalid",
+                req
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_too_old() {
+        let req = VersionReq::parse(">=0.4, <0.5").unwrap();
+        assert!(req.is_too_old(&Version::parse("0.2.0").unwrap()));
+        assert!(!req.is_too_old(&Version::parse("0.5.0").unwrap()));
+        assert_eq!(req.to_string(), ">=0.4, <0.5");
+    }
+}
@@ -1,39 +1,592 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
 
-use crate::common::target_arch::TargetArch;
+use crate::common::{
+    duration::parse_duration, target_arch::TargetArch, task::BuildShell, version_req::VersionReq,
+};
 
 use std::fs;
-use toml;
+use toml::{Table, Value};
 
 /// The main configuration file for DADK
 #[derive(Debug, Clone, Deserialize)]
 pub struct DadkManifestFile {
+    /// Versions of DADK that can be used with this manifest, e.g. `">=0.4, <0.5"` (optional)
+    #[serde(default, rename = "dadk-version")]
+    pub dadk_version: Option<VersionReq>,
+
+    /// Experimental subsystems the project opts in to (optional)
+    #[serde(default)]
+    pub experimental: BTreeSet<String>,
+
     pub metadata: Metadata,
 
+    /// Run user program build commands inside a container (optional)
+    #[serde(default)]
+    pub container: Option<ContainerConfig>,
+
+    /// Scripts executed before/after the actions of DADK (optional)
+    #[serde(default)]
+    pub hooks: Hooks,
+
+    /// Environment variables of the build commands of user programs (optional)
+    #[serde(default)]
+    pub env: EnvConfig,
+
+    /// Artifacts recorded by `dadk release` (optional)
+    #[serde(default)]
+    pub release: ReleaseConfig,
+
+    /// Kernel that the kernel module tasks are built against (optional)
+    #[serde(default)]
+    pub kernel: Option<KernelConfig>,
+
+    /// Timeouts of the git commands that fetch the sources of user programs (optional)
+    #[serde(default)]
+    pub git: GitConfig,
+
+    /// URL rewrite rules applied to the git and archive URLs of user programs (optional)
+    #[serde(default)]
+    pub mirrors: MirrorConfig,
+
+    /// The profile applied when loading the manifest
+    #[serde(skip)]
+    pub profile: Option<String>,
+
+    /// Names of all profiles defined in the manifest
+    #[serde(skip)]
+    pub profiles: Vec<String>,
+
     /// A flag variable used to indicate whether
     /// the default value function was called during deserialization.
     #[serde(skip)]
     pub used_default: bool,
 }
 
+/// Key of the manifest files to include
+const INCLUDE_KEY: &str = "include";
+/// Key of the profile tables
+const PROFILE_KEY: &str = "profile";
+/// Key of the profile used when no profile is specified
+const DEFAULT_PROFILE_KEY: &str = "default-profile";
+
 impl DadkManifestFile {
-    pub fn load(path: &PathBuf) -> Result<Self> {
-        // 读取文件内容
-        let content = fs::read_to_string(path)?;
-        Self::load_from_str(&content)
+    /// Load the manifest, applying the `default-profile` (if any)
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with_profile(path, None)
+    }
+
+    /// Load the manifest and apply the given profile.
+    ///
+    /// Files listed in `include` are loaded first (paths are relative to the
+    /// including file), and the including file overrides them. Then the fields of
+    /// `[profile.<name>]` override the `[metadata]` table.
+    pub fn load_with_profile(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let mut visited = BTreeSet::new();
+        let table = load_table(path, &mut visited)?;
+        Self::from_table(table, profile)
     }
 
     pub fn load_from_str(content: &str) -> Result<Self> {
+        let table: Table = toml::from_str(content)?;
+        // Includes are relative to the current directory
+        let table = resolve_includes(table, Path::new("."), &mut BTreeSet::new())?;
+        Self::from_table(table, None)
+    }
+
+    fn from_table(mut table: Table, profile: Option<&str>) -> Result<Self> {
+        let profiles = match table.get(PROFILE_KEY) {
+            Some(Value::Table(profiles)) => profiles.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        let profile = apply_profile(&mut table, profile)?;
+
         // Parse TOML content
-        let mut manifest_toml: DadkManifestFile = toml::from_str(content)?;
+        let mut manifest_toml: DadkManifestFile = Value::Table(table).try_into()?;
 
+        manifest_toml.profile = profile;
+        manifest_toml.profiles = profiles;
         manifest_toml.used_default = check_used_default();
+        if let Some(container) = &manifest_toml.container {
+            container.validate()?;
+        }
+        manifest_toml.env.validate()?;
+        if let Some(kernel) = &manifest_toml.kernel {
+            kernel.validate()?;
+        }
+        manifest_toml.git.validate()?;
+        manifest_toml.mirrors.validate()?;
 
         Ok(manifest_toml)
     }
+
+    /// Whether the project opts in to the experimental subsystem `feature`
+    pub fn experimental_enabled(&self, feature: &str) -> bool {
+        self.experimental.contains(feature)
+    }
+}
+
+/// Read a manifest file and merge the files it includes
+fn load_table(path: &Path, visited: &mut BTreeSet<PathBuf>) -> Result<Table> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to load manifest {}: {}", path.display(), e))?;
+    if !visited.insert(canonical.clone()) {
+        return Err(anyhow!(
+            "Manifest {} is included recursively",
+            path.display()
+        ));
+    }
+    let content = fs::read_to_string(path)?;
+    let table: Table = toml::from_str(&content)
+        .map_err(|e| anyhow!("Failed to parse manifest {}: {}", path.display(), e))?;
+    let base_dir = canonical.parent().unwrap_or(Path::new("/")).to_path_buf();
+    let table = resolve_includes(table, &base_dir, visited)?;
+    visited.remove(&canonical);
+    Ok(table)
+}
+
+/// Merge the files listed in `include` (in order) and then `table` on top of them
+fn resolve_includes(
+    mut table: Table,
+    base_dir: &Path,
+    visited: &mut BTreeSet<PathBuf>,
+) -> Result<Table> {
+    let includes = match table.remove(INCLUDE_KEY) {
+        None => return Ok(table),
+        Some(Value::Array(includes)) => includes,
+        Some(_) => return Err(anyhow!("include should be an array of paths")),
+    };
+
+    let mut merged = Table::new();
+    for include in includes {
+        let include = include
+            .as_str()
+            .ok_or_else(|| anyhow!("include should be an array of paths"))?;
+        let included = load_table(&base_dir.join(include), visited)?;
+        merge_table(&mut merged, included);
+    }
+    merge_table(&mut merged, table);
+    Ok(merged)
+}
+
+/// Apply the selected profile to the `[metadata]` table, returning the profile name.
+///
+/// The profile given explicitly takes precedence over `default-profile`.
+fn apply_profile(table: &mut Table, profile: Option<&str>) -> Result<Option<String>> {
+    let default_profile = match table.remove(DEFAULT_PROFILE_KEY) {
+        None => None,
+        Some(Value::String(name)) => Some(name),
+        Some(_) => return Err(anyhow!("default-profile should be a string")),
+    };
+    let mut profiles = match table.remove(PROFILE_KEY) {
+        None => Table::new(),
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err(anyhow!("profile should be a table")),
+    };
+
+    let Some(name) = profile.map(|p| p.to_string()).or(default_profile) else {
+        return Ok(None);
+    };
+    let overrides = match profiles.remove(&name) {
+        Some(Value::Table(overrides)) => overrides,
+        Some(_) => return Err(anyhow!("profile.{} should be a table", name)),
+        None => {
+            let available: Vec<&str> = profiles.keys().map(|k| k.as_str()).collect();
+            return Err(anyhow!(
+                "Profile '{}' not found in manifest, available profiles: [{}]",
+                name,
+                available.join(", ")
+            ));
+        }
+    };
+
+    let metadata = table
+        .entry("metadata")
+        .or_insert_with(|| Value::Table(Table::new()));
+    let Value::Table(metadata) = metadata else {
+        return Err(anyhow!("metadata should be a table"));
+    };
+    merge_table(metadata, overrides);
+    Ok(Some(name))
+}
+
+/// Merge `overlay` into `base`. Tables are merged recursively, other values
+/// (including arrays) in `overlay` replace the ones in `base`.
+fn merge_table(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge_table(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Container used to run the build commands of user programs.
+///
+/// The cache root, the sysroot and the working directory of each command are
+/// mounted into the container at the same paths as on the host.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct ContainerConfig {
+    /// Container engine
+    #[serde(default)]
+    pub engine: ContainerEngine,
+    /// Image used to run the build commands
+    pub image: String,
+    /// Extra mounts, in the form of `HOST_PATH:CONTAINER_PATH[:OPTIONS]`
+    #[serde(default)]
+    pub mounts: Vec<String>,
+    /// Extra arguments passed to `<engine> run`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl ContainerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.image.trim().is_empty() {
+            return Err(anyhow::anyhow!("container: image should not be empty"));
+        }
+        for mount in &self.mounts {
+            let mut parts = mount.split(':');
+            let host = parts.next().unwrap_or_default();
+            let container = parts.next().unwrap_or_default();
+            if host.is_empty() || container.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "container: invalid mount '{}', expected HOST_PATH:CONTAINER_PATH[:OPTIONS]",
+                    mount
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Environment variables of the build commands of user programs
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvConfig {
+    /// Host environment variables passed to the build commands. When set, all other
+    /// host variables are removed. An entry ending with `*` matches a prefix, e.g. `LC_*`.
+    /// When not set, all host variables are passed.
+    #[serde(default)]
+    pub passthrough: Option<Vec<String>>,
+    /// Variables set for all build commands. They override the host variables,
+    /// but not the variables set by DADK (`DADK_*`, `ARCH`) or by the tasks.
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+}
+
+impl EnvConfig {
+    /// Whether the host variable `key` is passed to the build commands
+    pub fn passes(&self, key: &str) -> bool {
+        let Some(passthrough) = &self.passthrough else {
+            return true;
+        };
+        passthrough.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == p,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let names = self.passthrough.iter().flatten().chain(self.set.keys());
+        for name in names {
+            if name.is_empty() || name.contains('=') {
+                return Err(anyhow!("env: invalid variable name '{}'", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Kernel that the kernel module tasks (`type = "kernel-module"`) are built against
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct KernelConfig {
+    /// Kernel source directory, relative to the working directory.
+    /// Exported to the build commands as `DADK_KERNEL_SOURCE_DIR`.
+    #[serde(default = "default_kernel_source_dir", rename = "source-dir")]
+    pub source_dir: PathBuf,
+    /// Kernel version. Kernel modules are installed into `/lib/modules/<version>/` in the sysroot.
+    /// Exported to the build commands as `DADK_KERNEL_VERSION`.
+    pub version: String,
+    /// Command that regenerates the module dependency index after kernel modules are installed.
+    /// Executed with `sh -c`, with `DADK_SYSROOT_DIR` and `DADK_KERNEL_VERSION` set.
+    /// An empty string disables it.
+    #[serde(default = "default_depmod")]
+    pub depmod: String,
+}
+
+impl KernelConfig {
+    pub fn validate(&self) -> Result<()> {
+        let version = self.version.trim();
+        if version.is_empty() || version.contains('/') || version == "." || version == ".." {
+            return Err(anyhow!("kernel: invalid version '{}'", self.version));
+        }
+        Ok(())
+    }
+}
+
+/// Timeouts of the git commands that fetch the sources of user programs.
+///
+/// Git never prompts for credentials: a command that needs them fails instead of hanging.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GitConfig {
+    /// Maximum time of a single git command, e.g. `"30m"`. No limit if not set.
+    #[serde(default)]
+    pub timeout: Option<String>,
+    /// A transfer that receives no data for this long is considered stalled and aborted.
+    /// `"0"` disables the detection.
+    #[serde(default = "default_git_stall_timeout")]
+    pub stall_timeout: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            stall_timeout: default_git_stall_timeout(),
+        }
+    }
+}
+
+impl GitConfig {
+    /// Maximum time of a single git command
+    pub fn timeout_duration(&self) -> Result<Option<Duration>> {
+        let Some(timeout) = &self.timeout else {
+            return Ok(None);
+        };
+        let duration = parse_duration(timeout).map_err(|e| anyhow!("git: timeout: {}", e))?;
+        if duration.is_zero() {
+            return Err(anyhow!("git: timeout should be greater than 0"));
+        }
+        Ok(Some(duration))
+    }
+
+    /// Time without data after which a transfer is considered stalled, None if disabled
+    pub fn stall_timeout_duration(&self) -> Result<Option<Duration>> {
+        let duration = parse_duration(&self.stall_timeout)
+            .map_err(|e| anyhow!("git: stall-timeout: {}", e))?;
+        Ok(Some(duration).filter(|d| !d.is_zero()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.timeout_duration()?;
+        self.stall_timeout_duration()?;
+        Ok(())
+    }
+}
+
+/// URL rewrite rules applied to the git and archive URLs of user programs before fetching.
+///
+/// A pattern ending with `*` matches every URL starting with the part before the `*`,
+/// and the rest of the URL replaces the `*` in the replacement. Other patterns only
+/// match the same URL. When several patterns match, the longest one is used.
+///
+/// ```toml
+/// [mirrors]
+/// "https://github.com/*" = "https://mirror.example.com/github/*"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct MirrorConfig {
+    pub rules: BTreeMap<String, String>,
+}
+
+impl MirrorConfig {
+    /// The URL to fetch instead of `url`, None if no rule matches
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        self.rules
+            .iter()
+            .filter_map(|(pattern, replacement)| match pattern.strip_suffix('*') {
+                Some(prefix) => url
+                    .strip_prefix(prefix)
+                    .map(|rest| (prefix.len(), replacement.replacen('*', rest, 1))),
+                None => (url == pattern).then(|| (pattern.len(), replacement.clone())),
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, rewritten)| rewritten)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (pattern, replacement) in &self.rules {
+            let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+            if prefix.is_empty() || prefix.contains('*') {
+                return Err(anyhow!(
+                    "mirrors: invalid pattern '{}', '*' is only allowed at the end",
+                    pattern
+                ));
+            }
+            let wildcards = replacement.matches('*').count();
+            let expected = usize::from(pattern.ends_with('*'));
+            if replacement.trim().is_empty() || wildcards != expected {
+                return Err(anyhow!(
+                    "mirrors: invalid replacement '{}' of '{}', it should contain {} '*'",
+                    replacement,
+                    pattern,
+                    expected
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_git_stall_timeout() -> String {
+    "60s".to_string()
+}
+
+fn default_kernel_source_dir() -> PathBuf {
+    "kernel".into()
+}
+
+fn default_depmod() -> String {
+    r#"depmod -b "$DADK_SYSROOT_DIR" "$DADK_KERNEL_VERSION""#.to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerEngine {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl ContainerEngine {
+    /// The command used to invoke the engine
+    pub fn program(&self) -> &'static str {
+        match self {
+            ContainerEngine::Docker => "docker",
+            ContainerEngine::Podman => "podman",
+        }
+    }
+}
+
+/// Points of the DADK pipeline where hooks can be executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    PreBuild,
+    PostBuild,
+    PreInstall,
+    PostInstall,
+    PreClean,
+    PostClean,
+    PreRootfsCreate,
+    PostRootfsCreate,
+}
+
+impl HookPoint {
+    /// The key of the hook in the `[hooks]` table
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookPoint::PreBuild => "pre-build",
+            HookPoint::PostBuild => "post-build",
+            HookPoint::PreInstall => "pre-install",
+            HookPoint::PostInstall => "post-install",
+            HookPoint::PreClean => "pre-clean",
+            HookPoint::PostClean => "post-clean",
+            HookPoint::PreRootfsCreate => "pre-rootfs-create",
+            HookPoint::PostRootfsCreate => "post-rootfs-create",
+        }
+    }
+}
+
+/// Shell scripts executed at the points of the DADK pipeline.
+///
+/// Each script is executed with `sh -c` in the working directory of DADK.
+/// A failing `pre-*` hook aborts the action, `post-*` hooks are only executed
+/// when the action succeeds.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Hooks {
+    pub pre_build: Option<String>,
+    pub post_build: Option<String>,
+    pub pre_install: Option<String>,
+    pub post_install: Option<String>,
+    pub pre_clean: Option<String>,
+    pub post_clean: Option<String>,
+    pub pre_rootfs_create: Option<String>,
+    pub post_rootfs_create: Option<String>,
+}
+
+impl Hooks {
+    /// The script of the given hook point, if any
+    pub fn get(&self, point: HookPoint) -> Option<&str> {
+        let script = match point {
+            HookPoint::PreBuild => &self.pre_build,
+            HookPoint::PostBuild => &self.post_build,
+            HookPoint::PreInstall => &self.pre_install,
+            HookPoint::PostInstall => &self.post_install,
+            HookPoint::PreClean => &self.pre_clean,
+            HookPoint::PostClean => &self.post_clean,
+            HookPoint::PreRootfsCreate => &self.pre_rootfs_create,
+            HookPoint::PostRootfsCreate => &self.post_rootfs_create,
+        };
+        script.as_deref().filter(|s| !s.trim().is_empty())
+    }
+}
+
+/// Artifacts recorded in the checksum manifest written by `dadk release`,
+/// and the tool used to sign it.
+///
+/// The disk images and the packages in `bin/packages` are always recorded.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseConfig {
+    /// Kernel image, relative to the working directory
+    #[serde(default)]
+    pub kernel: Option<PathBuf>,
+    /// Extra files to record, relative to the working directory
+    #[serde(default)]
+    pub artifacts: Vec<PathBuf>,
+    /// Path of the checksum manifest, relative to the working directory.
+    /// All the recorded files should be placed under its directory.
+    #[serde(default = "default_release_manifest_path")]
+    pub manifest: PathBuf,
+    /// Sign the checksum manifest (optional)
+    #[serde(default)]
+    pub sign: Option<SignConfig>,
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        Self {
+            kernel: None,
+            artifacts: Vec::new(),
+            manifest: default_release_manifest_path(),
+            sign: None,
+        }
+    }
+}
+
+fn default_release_manifest_path() -> PathBuf {
+    "bin/SHA256SUMS".into()
+}
+
+/// How to sign the checksum manifest
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SignConfig {
+    pub tool: SignTool,
+    /// Secret key file (minisign) or key id (gpg). The default key of the tool is used if not set.
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignTool {
+    Minisign,
+    Gpg,
 }
 
 thread_local! {
@@ -73,6 +626,10 @@ pub struct Metadata {
     #[serde(default = "default_boot_config_path", rename = "boot-config")]
     pub boot_config: PathBuf,
 
+    /// (Optional) App blocklist configuration file path
+    #[serde(default, rename = "app-blocklist-config")]
+    pub app_blocklist_config: Option<PathBuf>,
+
     /// Sysroot directory path
     #[serde(default = "default_sysroot_dir", rename = "sysroot-dir")]
     pub sysroot_dir: PathBuf,
@@ -81,11 +638,99 @@ pub struct Metadata {
     #[serde(default = "default_cache_root_dir", rename = "cache-root-dir")]
     pub cache_root_dir: PathBuf,
 
-    /// User configuration directory path
+    /// User configuration directory paths.
+    ///
+    /// Either a single path or a list of paths. Directories are merged in order:
+    /// a task in a later directory replaces the task with the same name and version in an earlier one.
     /// 这个字段只是临时用于兼容旧版本，v0.2版本重构完成后会删除
     #[deprecated(note = "This field is deprecated and will be removed in DADK 0.2")]
-    #[serde(default = "default_user_config_dir", rename = "user-config-dir")]
-    pub user_config_dir: PathBuf,
+    #[serde(
+        default = "default_user_config_dirs",
+        rename = "user-config-dir",
+        deserialize_with = "deserialize_user_config_dirs"
+    )]
+    pub user_config_dirs: Vec<PathBuf>,
+
+    /// Default number of retries when fetching the source of a user program fails.
+    /// Can be overridden by the `retries` field of each task.
+    #[serde(default)]
+    pub retries: u32,
+
+    /// Restore local prebuilt artifacts into the build cache with hard links instead of copying them.
+    #[serde(default, rename = "hardlink-prebuilt")]
+    pub hardlink_prebuilt: bool,
+
+    /// Skip user program configs that fail to parse (with a warning) instead of aborting.
+    #[serde(default, rename = "skip-invalid-configs")]
+    pub skip_invalid_configs: bool,
+
+    /// Variables that can be referenced as `${NAME}` in the string fields of user program configs
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+
+    /// Version of each user program that provides its unversioned paths, when several versions
+    /// of the program are built side by side, e.g. `python = "3.12.0"`.
+    /// Dependencies that omit the version also resolve to this version.
+    #[serde(default, rename = "default-versions")]
+    pub default_versions: BTreeMap<String, String>,
+
+    /// URL of the package repository index, used by tasks with `source = "repository"`
+    #[serde(default, rename = "package-repository")]
+    pub package_repository: Option<String>,
+
+    /// Rust toolchain used to build cargo tasks whose source tree has no `rust-toolchain.toml`,
+    /// e.g. `nightly-2024-07-23`
+    #[serde(default, rename = "rust-toolchain")]
+    pub rust_toolchain: Option<String>,
+
+    /// Compiler cache (ccache/sccache) used to build user programs
+    #[serde(default, rename = "compiler-cache")]
+    pub compiler_cache: CompilerCacheConfig,
+
+    /// Shell used to run the build and clean commands of user programs (`sh`, `bash` or `zsh`).
+    /// Can be overridden by the `shell` field in the `[build]` section of each task.
+    #[serde(default)]
+    pub shell: BuildShell,
+
+    /// Prepend `set -euo pipefail` (`set -eu` for `sh`) to the build and clean commands,
+    /// so that they stop at the first failing statement.
+    /// Can be overridden by the `shell-strict` field in the `[build]` section of each task.
+    #[serde(default, rename = "shell-strict")]
+    pub shell_strict: bool,
+}
+
+/// Compiler cache wrappers exported into the build environment of user programs
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CompilerCacheConfig {
+    /// Use the compiler cache for all tasks.
+    /// Can be overridden by the `compiler-cache` field in the `[build]` section of each task.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Wrapper of the C/C++ compilers. An empty string disables it.
+    #[serde(default = "default_cc_wrapper", rename = "cc-wrapper")]
+    pub cc_wrapper: String,
+    /// Wrapper of rustc (`RUSTC_WRAPPER`). An empty string disables it.
+    #[serde(default = "default_rustc_wrapper", rename = "rustc-wrapper")]
+    pub rustc_wrapper: String,
+}
+
+impl Default for CompilerCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cc_wrapper: default_cc_wrapper(),
+            rustc_wrapper: default_rustc_wrapper(),
+        }
+    }
+}
+
+fn default_cc_wrapper() -> String {
+    "ccache".to_string()
+}
+
+fn default_rustc_wrapper() -> String {
+    "sccache".to_string()
 }
 
 /// Returns the default path for the rootfs configuration file.
@@ -118,9 +763,32 @@ fn default_cache_root_dir() -> PathBuf {
     "bin/dadk_cache".into()
 }
 
-fn default_user_config_dir() -> PathBuf {
+fn default_user_config_dirs() -> Vec<PathBuf> {
     set_used_default();
-    "user/dadk/config".into()
+    vec!["user/dadk/config".into()]
+}
+
+/// Accepts either a single directory or a non-empty list of directories.
+fn deserialize_user_config_dirs<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+    let dirs = match Repr::deserialize(deserializer)? {
+        Repr::One(dir) => vec![dir],
+        Repr::Many(dirs) => dirs,
+    };
+    if dirs.is_empty() {
+        return Err(serde::de::Error::custom("user-config-dir is empty"));
+    }
+    Ok(dirs)
 }
 
 #[cfg(test)]
@@ -168,6 +836,601 @@ mod tests {
         Ok(())
     }
 
+    /// Test loading the container section
+    #[test]
+    fn test_load_container() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [container]
+            engine = "podman"
+            image = "dragonos/dadk-build:latest"
+            mounts = ["/opt/toolchain:/opt/toolchain:ro"]
+            args = ["--network=host"]
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let container = manifest.container.unwrap();
+        assert_eq!(container.engine, ContainerEngine::Podman);
+        assert_eq!(container.image, "dragonos/dadk-build:latest");
+        assert_eq!(container.mounts, vec!["/opt/toolchain:/opt/toolchain:ro"]);
+        assert_eq!(container.args, vec!["--network=host"]);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [container]
+            image = "dragonos/dadk-build:latest"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(manifest.container.unwrap().engine, ContainerEngine::Docker);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content)?
+            .container
+            .is_none());
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [container]
+            image = "dragonos/dadk-build:latest"
+            mounts = ["/opt/toolchain"]
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+
+        Ok(())
+    }
+
+    /// Test loading the compiler cache section
+    #[test]
+    fn test_load_compiler_cache() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [metadata.compiler-cache]
+            enabled = true
+            rustc-wrapper = "/opt/sccache/bin/sccache"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let compiler_cache = &manifest.metadata.compiler_cache;
+        assert!(compiler_cache.enabled);
+        assert_eq!(compiler_cache.cc_wrapper, "ccache");
+        assert_eq!(compiler_cache.rustc_wrapper, "/opt/sccache/bin/sccache");
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(
+            manifest.metadata.compiler_cache,
+            CompilerCacheConfig::default()
+        );
+        assert!(!manifest.metadata.compiler_cache.enabled);
+
+        Ok(())
+    }
+
+    /// Test loading the environment passthrough filters
+    #[test]
+    fn test_load_env() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [env]
+            passthrough = ["PATH", "LC_*"]
+            set = { FOO = "bar" }
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let env = &manifest.env;
+        assert!(env.passes("PATH"));
+        assert!(env.passes("LC_ALL"));
+        assert!(!env.passes("PATHS"));
+        assert!(!env.passes("HOME"));
+        assert_eq!(env.set.get("FOO").map(String::as_str), Some("bar"));
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(manifest.env, EnvConfig::default());
+        assert!(manifest.env.passes("HOME"));
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [env]
+            set = { "A=B" = "c" }
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+
+        Ok(())
+    }
+
+    /// Test loading the DADK version requirement and the experimental features
+    #[test]
+    fn test_load_dadk_version() -> Result<()> {
+        let toml_content = r#"
+            dadk-version = ">=0.4, <0.5"
+            experimental = ["remote-cache"]
+
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let req = manifest.dadk_version.unwrap();
+        assert_eq!(req.to_string(), ">=0.4, <0.5");
+        assert!(req.matches(&crate::common::version_req::Version::parse("0.4.2")?));
+        assert!(manifest.experimental.contains("remote-cache"));
+
+        let manifest = DadkManifestFile::load_from_str("[metadata]\narch = \"x86_64\"")?;
+        assert!(manifest.dadk_version.is_none());
+        assert!(manifest.experimental.is_empty());
+
+        let toml_content = r#"
+            dadk-version = "0.4.x"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+        Ok(())
+    }
+
+    /// Test loading the shell used to run build commands
+    #[test]
+    fn test_load_shell() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+            shell = "sh"
+            shell-strict = true
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(manifest.metadata.shell, BuildShell::Sh);
+        assert!(manifest.metadata.shell_strict);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(manifest.metadata.shell, BuildShell::Bash);
+        assert!(!manifest.metadata.shell_strict);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+            shell = "fish"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+
+        Ok(())
+    }
+
+    /// Test loading the default versions of user programs
+    #[test]
+    fn test_load_default_versions() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [metadata.default-versions]
+            python = "3.12.0"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(
+            manifest.metadata.default_versions.get("python"),
+            Some(&"3.12.0".to_string())
+        );
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert!(manifest.metadata.default_versions.is_empty());
+        Ok(())
+    }
+
+    /// Test loading the kernel that kernel modules are built against
+    #[test]
+    fn test_load_kernel() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [kernel]
+            version = "0.1.10"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let kernel = manifest.kernel.unwrap();
+        assert_eq!(kernel.source_dir, PathBuf::from("kernel"));
+        assert_eq!(kernel.version, "0.1.10");
+        assert!(kernel.depmod.starts_with("depmod -b"));
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content)?
+            .kernel
+            .is_none());
+
+        for version in ["", "../x"] {
+            let toml_content = format!(
+                r#"
+                [metadata]
+                arch = "x86_64"
+
+                [kernel]
+                version = "{}"
+                "#,
+                version
+            );
+            assert!(DadkManifestFile::load_from_str(&toml_content).is_err());
+        }
+        Ok(())
+    }
+
+    /// Test loading the timeouts of git commands
+    #[test]
+    fn test_load_git() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let git = DadkManifestFile::load_from_str(toml_content)?.git;
+        assert_eq!(git.timeout_duration()?, None);
+        assert_eq!(git.stall_timeout_duration()?, Some(Duration::from_secs(60)));
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [git]
+            timeout = "30m"
+            stall-timeout = "0"
+        "#;
+        let git = DadkManifestFile::load_from_str(toml_content)?.git;
+        assert_eq!(git.timeout_duration()?, Some(Duration::from_secs(30 * 60)));
+        assert_eq!(git.stall_timeout_duration()?, None);
+
+        for git in [
+            r#"timeout = "0""#,
+            r#"timeout = "soon""#,
+            r#"stall-timeout = "1x""#,
+        ] {
+            let toml_content = format!(
+                r#"
+                [metadata]
+                arch = "x86_64"
+
+                [git]
+                {}
+                "#,
+                git
+            );
+            assert!(DadkManifestFile::load_from_str(&toml_content).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_mirrors() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        let mirrors = DadkManifestFile::load_from_str(toml_content)?.mirrors;
+        assert!(mirrors.rules.is_empty());
+        assert_eq!(mirrors.rewrite("https://github.com/a/b.git"), None);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [mirrors]
+            "https://github.com/*" = "https://mirror.example.com/github/*"
+            "https://github.com/DragonOS-Community/*" = "https://git.example.com/dragonos/*.git"
+            "https://example.com/a.tar.gz" = "http://10.0.0.1/a.tar.gz"
+        "#;
+        let mirrors = DadkManifestFile::load_from_str(toml_content)?.mirrors;
+        assert_eq!(
+            mirrors.rewrite("https://github.com/rust-lang/libc.git"),
+            Some("https://mirror.example.com/github/rust-lang/libc.git".to_string())
+        );
+        // The longest pattern wins
+        assert_eq!(
+            mirrors.rewrite("https://github.com/DragonOS-Community/DADK"),
+            Some("https://git.example.com/dragonos/DADK.git".to_string())
+        );
+        assert_eq!(
+            mirrors.rewrite("https://example.com/a.tar.gz"),
+            Some("http://10.0.0.1/a.tar.gz".to_string())
+        );
+        assert_eq!(mirrors.rewrite("https://example.com/a.tar.gz.sig"), None);
+        assert_eq!(mirrors.rewrite("https://gitee.com/a/b.git"), None);
+
+        for mirror in [
+            r#""*" = "https://mirror.example.com/*""#,
+            r#""https://*.com/*" = "https://mirror.example.com/*""#,
+            r#""https://github.com/*" = "https://mirror.example.com/""#,
+            r#""https://github.com/*" = "*/*""#,
+            r#""https://github.com/a.git" = "https://mirror.example.com/*""#,
+            r#""https://github.com/a.git" = " ""#,
+        ] {
+            let toml_content = format!(
+                r#"
+                [metadata]
+                arch = "x86_64"
+
+                [mirrors]
+                {}
+                "#,
+                mirror
+            );
+            assert!(DadkManifestFile::load_from_str(&toml_content).is_err());
+        }
+        Ok(())
+    }
+
+    /// Test `user-config-dir` as a single directory or a list of directories
+    #[test]
+    #[allow(deprecated)]
+    fn test_load_user_config_dirs() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+            user-config-dir = "user/dadk/config"
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(
+            manifest.metadata.user_config_dirs,
+            vec![PathBuf::from("user/dadk/config")]
+        );
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+            user-config-dir = ["user/dadk/config", "boards/visionfive2/config"]
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        assert_eq!(
+            manifest.metadata.user_config_dirs,
+            vec![
+                PathBuf::from("user/dadk/config"),
+                PathBuf::from("boards/visionfive2/config")
+            ]
+        );
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+            user-config-dir = []
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+        Ok(())
+    }
+
+    /// Test loading the hooks section
+    #[test]
+    fn test_load_hooks() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [hooks]
+            pre-build = "./scripts/check-toolchain.sh"
+            post-install = "cosign sign-blob $DADK_SYSROOT_DIR/bin/init"
+            post-rootfs-create = ""
+        "#;
+        let manifest = DadkManifestFile::load_from_str(toml_content)?;
+        let hooks = &manifest.hooks;
+        assert_eq!(
+            hooks.get(HookPoint::PreBuild),
+            Some("./scripts/check-toolchain.sh")
+        );
+        assert_eq!(
+            hooks.get(HookPoint::PostInstall),
+            Some("cosign sign-blob $DADK_SYSROOT_DIR/bin/init")
+        );
+        assert_eq!(hooks.get(HookPoint::PostBuild), None);
+        // Empty scripts are ignored
+        assert_eq!(hooks.get(HookPoint::PostRootfsCreate), None);
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        assert_eq!(
+            DadkManifestFile::load_from_str(toml_content)?.hooks,
+            Hooks::default()
+        );
+
+        // Unknown hook points are reported
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [hooks]
+            pre-bulid = "true"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+        Ok(())
+    }
+
+    /// Test loading the release section
+    #[test]
+    fn test_load_release() -> Result<()> {
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [release]
+            kernel = "bin/kernel/kernel.elf"
+            artifacts = ["bin/kernel/kernel.sym"]
+
+            [release.sign]
+            tool = "minisign"
+            key = "/secrets/minisign.key"
+        "#;
+        let release = DadkManifestFile::load_from_str(toml_content)?.release;
+        assert_eq!(release.kernel, Some(PathBuf::from("bin/kernel/kernel.elf")));
+        assert_eq!(
+            release.artifacts,
+            vec![PathBuf::from("bin/kernel/kernel.sym")]
+        );
+        assert_eq!(release.manifest, PathBuf::from("bin/SHA256SUMS"));
+        assert_eq!(
+            release.sign,
+            Some(SignConfig {
+                tool: SignTool::Minisign,
+                key: Some("/secrets/minisign.key".to_string()),
+            })
+        );
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+        "#;
+        assert_eq!(
+            DadkManifestFile::load_from_str(toml_content)?.release,
+            ReleaseConfig::default()
+        );
+
+        let toml_content = r#"
+            [metadata]
+            arch = "x86_64"
+
+            [release.sign]
+            tool = "cosign"
+        "#;
+        assert!(DadkManifestFile::load_from_str(toml_content).is_err());
+        Ok(())
+    }
+
+    /// Test sharing a base manifest with `include`
+    #[test]
+    fn test_load_include() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("common"))?;
+        fs::write(
+            dir.path().join("common/base.toml"),
+            r#"
+            [metadata]
+            arch = "x86_64"
+            sysroot-dir = "bin/sysroot"
+            cache-root-dir = "bin/dadk_cache"
+
+            [container]
+            image = "dragonos/dadk-build:latest"
+            args = ["--network=host"]
+        "#,
+        )?;
+        let path = dir.path().join("dadk-manifest.toml");
+        fs::write(
+            &path,
+            r#"
+            include = ["common/base.toml"]
+
+            [metadata]
+            arch = "riscv64"
+
+            [container]
+            args = []
+        "#,
+        )?;
+
+        let manifest = DadkManifestFile::load(&path)?;
+        assert_eq!(manifest.metadata.arch, TargetArch::RiscV64);
+        assert_eq!(manifest.metadata.sysroot_dir, PathBuf::from("bin/sysroot"));
+        let container = manifest.container.unwrap();
+        assert_eq!(container.image, "dragonos/dadk-build:latest");
+        // Arrays are replaced instead of appended
+        assert!(container.args.is_empty());
+        assert!(manifest.profile.is_none());
+        Ok(())
+    }
+
+    /// Test whether an error is reported when a manifest includes itself
+    #[test]
+    fn test_load_include_recursive() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(
+            dir.path().join("a.toml"),
+            "include = [\"b.toml\"]\n[metadata]\narch = \"x86_64\"\n",
+        )?;
+        fs::write(dir.path().join("b.toml"), "include = [\"a.toml\"]\n")?;
+        let result = DadkManifestFile::load(&dir.path().join("a.toml"));
+        assert!(result.unwrap_err().to_string().contains("recursively"));
+
+        // The same file may be included more than once if it is not a cycle
+        fs::write(dir.path().join("c.toml"), "[metadata]\narch = \"x86_64\"\n")?;
+        fs::write(
+            dir.path().join("d.toml"),
+            "include = [\"c.toml\", \"c.toml\"]\n",
+        )?;
+        assert!(DadkManifestFile::load(&dir.path().join("d.toml")).is_ok());
+        Ok(())
+    }
+
+    /// Test overriding metadata with `[profile.<name>]`
+    #[test]
+    fn test_load_profile() -> Result<()> {
+        let toml_content = r#"
+            default-profile = "x86_64"
+
+            [metadata]
+            arch = "x86_64"
+            rootfs-config = "config/rootfs.toml"
+            sysroot-dir = "bin/sysroot"
+
+            [profile.x86_64]
+
+            [profile.riscv64]
+            arch = "riscv64"
+            rootfs-config = "config/rootfs-riscv64.toml"
+            sysroot-dir = "bin/sysroot-riscv64"
+        "#;
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(toml_content.as_bytes())?;
+        let path = temp_file.path().to_path_buf();
+
+        let manifest = DadkManifestFile::load(&path)?;
+        assert_eq!(manifest.profile.as_deref(), Some("x86_64"));
+        assert_eq!(manifest.profiles, vec!["riscv64", "x86_64"]);
+        assert_eq!(manifest.metadata.arch, TargetArch::X86_64);
+        assert_eq!(manifest.metadata.sysroot_dir, PathBuf::from("bin/sysroot"));
+
+        let manifest = DadkManifestFile::load_with_profile(&path, Some("riscv64"))?;
+        assert_eq!(manifest.profile.as_deref(), Some("riscv64"));
+        assert_eq!(manifest.metadata.arch, TargetArch::RiscV64);
+        assert_eq!(
+            manifest.metadata.rootfs_config,
+            PathBuf::from("config/rootfs-riscv64.toml")
+        );
+        assert_eq!(
+            manifest.metadata.sysroot_dir,
+            PathBuf::from("bin/sysroot-riscv64")
+        );
+
+        let err = DadkManifestFile::load_with_profile(&path, Some("aarch64")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("available profiles: [riscv64, x86_64]"));
+        Ok(())
+    }
+
     /// Test whether an error is reported when the file does not exist.
     #[test]
     fn test_load_file_not_found() {
@@ -0,0 +1,101 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer};
+
+/// Output format of the rootfs disk image
+///
+/// DADK always builds, mounts and boots a raw image. For other formats, an extra
+/// compressed copy is written next to the raw image, which is smaller to archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    /// Raw disk image only
+    #[default]
+    Raw,
+    /// QEMU copy-on-write image, compressed with `qemu-img convert -c`
+    Qcow2,
+    /// Raw disk image compressed with zstd
+    Zstd,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 3] = [ImageFormat::Raw, ImageFormat::Qcow2, ImageFormat::Zstd];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Raw => "raw",
+            ImageFormat::Qcow2 => "qcow2",
+            ImageFormat::Zstd => "zstd",
+        }
+    }
+
+    /// Path of the image in this format, derived from the path of the raw image
+    ///
+    /// `disk-image-x86_64.img` becomes `disk-image-x86_64.qcow2` or `disk-image-x86_64.img.zst`.
+    pub fn image_path(&self, raw_image: &Path) -> PathBuf {
+        match self {
+            ImageFormat::Raw => raw_image.to_path_buf(),
+            ImageFormat::Qcow2 => raw_image.with_extension("qcow2"),
+            ImageFormat::Zstd => {
+                let mut path = raw_image.as_os_str().to_owned();
+                path.push(".zst");
+                PathBuf::from(path)
+            }
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" => Ok(ImageFormat::Raw),
+            "qcow2" => Ok(ImageFormat::Qcow2),
+            "zstd" => Ok(ImageFormat::Zstd),
+            _ => Err(format!(
+                "invalid image format '{}', expected one of: raw, qcow2, zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_format() {
+        assert_eq!("raw".parse(), Ok(ImageFormat::Raw));
+        assert_eq!("QCOW2".parse(), Ok(ImageFormat::Qcow2));
+        assert_eq!("zstd".parse(), Ok(ImageFormat::Zstd));
+        assert!("vmdk".parse::<ImageFormat>().is_err());
+    }
+
+    #[test]
+    fn test_image_path() {
+        let raw = Path::new("bin/disk-image-x86_64.img");
+        assert_eq!(ImageFormat::Raw.image_path(raw), raw);
+        assert_eq!(
+            ImageFormat::Qcow2.image_path(raw),
+            Path::new("bin/disk-image-x86_64.qcow2")
+        );
+        assert_eq!(
+            ImageFormat::Zstd.image_path(raw),
+            Path::new("bin/disk-image-x86_64.img.zst")
+        );
+    }
+}
@@ -1,13 +1,17 @@
 pub mod fstype;
+pub mod image_format;
 pub mod partition;
+pub mod population;
 
 mod utils;
 
 use std::{fs, path::PathBuf};
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use fstype::FsType;
-use partition::PartitionConfig;
+use image_format::ImageFormat;
+use partition::{PartitionConfig, PartitionType};
+use population::Population;
 use serde::Deserialize;
 
 /// rootfs配置文件
@@ -16,6 +20,9 @@ pub struct RootFSConfigFile {
     pub metadata: RootFSMeta,
     #[serde(default)]
     pub partition: PartitionConfig,
+    /// 用户程序安装路径的限制
+    #[serde(default)]
+    pub install: InstallPathConfig,
 }
 
 impl RootFSConfigFile {
@@ -28,9 +35,29 @@ impl RootFSConfigFile {
 
     pub fn load_from_str(content: &str) -> Result<Self> {
         let config: RootFSConfigFile = toml::from_str(content)?;
+        config.install.validate()?;
+        config.validate_population()?;
 
         Ok(config)
     }
+
+    /// 在用户态创建、写入文件系统时，只支持FAT32以及不分区或者MBR分区的磁盘镜像
+    fn validate_population(&self) -> Result<()> {
+        if self.metadata.population != Population::Userspace {
+            return Ok(());
+        }
+        if self.metadata.fs_type != FsType::Fat32 {
+            return Err(Error::msg(
+                "population = \"userspace\" only supports fat32 disk images",
+            ));
+        }
+        if self.partition.partition_type == PartitionType::Gpt {
+            return Err(Error::msg(
+                "population = \"userspace\" does not support GPT partition tables",
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
@@ -41,6 +68,34 @@ pub struct RootFSMeta {
     /// 单位：字节
     #[serde(deserialize_with = "utils::size::deserialize_size")]
     pub size: usize,
+    /// 磁盘镜像的输出格式，默认只输出raw镜像
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// 把sysroot写入磁盘镜像的方式，默认通过loop设备挂载后复制
+    #[serde(default)]
+    pub population: Population,
+}
+
+/// 用户程序安装路径的限制
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InstallPathConfig {
+    /// 允许用户程序安装文件的目录（例如`/bin`、`/usr`），为空时不限制
+    #[serde(default)]
+    pub allowed_paths: Vec<PathBuf>,
+}
+
+impl InstallPathConfig {
+    fn validate(&self) -> Result<()> {
+        for path in &self.allowed_paths {
+            if !path.is_absolute() {
+                return Err(Error::msg(format!(
+                    "install.allowed_paths: '{}' should be an absolute path",
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -132,6 +187,47 @@ mod tests {
         assert!(RootFSConfigFile::load(&config_path).is_err());
     }
 
+    #[test]
+    fn test_load_population() {
+        let config = RootFSConfigFile::load_from_str(
+            r#"
+            [metadata]
+            fs_type = "fat32"
+            size = "64M"
+            population = "userspace"
+
+            [partition]
+            type = "mbr"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.metadata.population, Population::Userspace);
+
+        let config = RootFSConfigFile::load_from_str(
+            r#"
+            [metadata]
+            fs_type = "fat32"
+            size = "64M"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.metadata.population, Population::Mount);
+
+        let e = RootFSConfigFile::load_from_str(
+            r#"
+            [metadata]
+            fs_type = "fat32"
+            size = "64M"
+            population = "userspace"
+
+            [partition]
+            type = "gpt"
+        "#,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("GPT"), "{}", e);
+    }
+
     /// Parse from an incorrect size field (string)
     #[test]
     fn test_load_from_invalid_size_str() {
@@ -0,0 +1,33 @@
+use serde::Deserialize;
+
+/// How the sysroot is copied into the disk image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+pub enum Population {
+    /// Mount the image through a loop device and copy the sysroot with `cp` (requires root)
+    #[default]
+    #[serde(rename = "mount")]
+    Mount,
+    /// Create and populate the filesystem in userspace, without loop devices or root.
+    /// Only FAT32 images without a partition table or with an MBR partition table are supported
+    #[serde(rename = "userspace")]
+    Userspace,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Meta {
+        population: Population,
+    }
+
+    #[test]
+    fn test_parse_population() {
+        let meta: Meta = toml::from_str(r#"population = "userspace""#).unwrap();
+        assert_eq!(meta.population, Population::Userspace);
+        let meta: Meta = toml::from_str(r#"population = "mount""#).unwrap();
+        assert_eq!(meta.population, Population::Mount);
+        assert!(toml::from_str::<Meta>(r#"population = "fuse""#).is_err());
+    }
+}
@@ -1,13 +1,36 @@
-use std::path::PathBuf;
+//! # 用户程序配置文件
+//!
+//! [`UserConfigFile`]是用户程序配置文件（`*.toml`）的格式，dadk-user根据它生成构建任务。
+//! 这里的类型同时实现了`Serialize`和`Deserialize`，IDE插件等外部工具可以只依赖dadk-config读取、修改配置文件：
+//!
+//! - [`config_files`]、[`load_dir`]：查找、加载一个目录（包括子目录）中的所有配置文件
+//! - [`UserConfigFile::to_toml_string`]：把修改后的配置写回文件。输出的TOML再次加载后与原来的配置相等，
+//!   取默认值的可选字段不会被写出
+//!
+//! 配置文件中的字段只会增加，不会删除或者改变含义；新增的字段都有默认值，旧的配置文件总是可以加载。
 
-use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use toml::Value;
 
 use crate::common::{
     target_arch::TargetArch,
-    task::{BuildConfig, CleanConfig, Dependency, InstallConfig, TaskEnv, TaskSource},
+    task::{
+        AutotoolsConfig, BuildConfig, CargoConfig, CleanConfig, CmakeConfig, Dependency,
+        HostPackage, InstallConfig, Source, TaskEnv, TaskPriority, TaskSource, TaskSourceType,
+        TestConfig,
+    },
+    template::expand_value,
 };
 
-use anyhow::Result;
+use anyhow::{Error, Result};
+
+/// 内核模块的安装目录（`/lib/modules/<内核版本>/`）的上级目录
+pub const KERNEL_MODULES_DIR: &str = "/lib/modules";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UserCleanLevel {
@@ -19,7 +42,7 @@ pub enum UserCleanLevel {
     Output,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 /// 用户程序配置文件
 pub struct UserConfigFile {
     /// 包名
@@ -34,12 +57,22 @@ pub struct UserConfigFile {
     /// 依赖的包
     #[serde(default = "default_empty_dep")]
     pub depends: Vec<Dependency>,
+    /// (可选) 构建时需要的主机软件包，构建之前检查对应的命令是否在`PATH`中
+    #[serde(
+        rename = "depends-on-host-packages",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub host_packages: Vec<HostPackage>,
     /// 构建配置
     pub build: BuildConfig,
     /// 安装配置
     pub install: InstallConfig,
     /// 清理配置
     pub clean: CleanConfig,
+    /// (可选) 测试配置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test: Option<TestConfig>,
     /// 环境变量
     #[serde(default = "default_empty_env")]
     pub envs: Vec<TaskEnv>,
@@ -54,6 +87,34 @@ pub struct UserConfigFile {
 
     #[serde(rename = "target-arch")]
     pub target_arch: Vec<TargetArch>,
+
+    /// (可选) 拉取源文件失败时的重试次数，未设置时使用manifest中的默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+
+    /// (可选) 调度优先级，多个任务可以同时开始执行时，优先级高的任务先执行
+    #[serde(default, skip_serializing_if = "TaskPriority::is_normal")]
+    pub priority: TaskPriority,
+
+    /// (可选) cargo任务的构建配置，只在`task-source.type = "cargo"`时有效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo: Option<CargoConfig>,
+
+    /// (可选) cmake任务的构建配置，只在`task-source.type = "cmake"`时有效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmake: Option<CmakeConfig>,
+
+    /// (可选) autotools任务的构建配置，只在`task-source.type = "autotools"`时有效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autotools: Option<AutotoolsConfig>,
+
+    /// (可选) 是否覆盖其他配置文件中同名同版本的任务。为true时，其他配置目录中的同名任务会被忽略
+    #[serde(
+        rename = "override",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub overrides: bool,
 }
 
 impl UserConfigFile {
@@ -62,10 +123,318 @@ impl UserConfigFile {
         Self::load_from_str(&content)
     }
 
+    /// 加载配置文件。没有指定目标架构，`[build.<arch>]`中的覆盖配置会被忽略
     pub fn load_from_str(content: &str) -> Result<Self> {
-        let config: UserConfigFile = toml::from_str(content)?;
-        Ok(config)
+        let mut value = Value::Table(toml::from_str(content)?);
+        apply_arch_overrides(&mut value, None)?;
+        Ok(value.try_into()?)
+    }
+
+    /// 加载配置文件，并替换所有字符串字段中的`${NAME}`变量
+    pub fn load_with_vars(path: &PathBuf, vars: &BTreeMap<String, String>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::load_from_str_with_vars(&content, vars)
+    }
+
+    /// `ARCH`变量为当前的目标架构，对应的`[build.<arch>]`会合并到基础配置中
+    pub fn load_from_str_with_vars(content: &str, vars: &BTreeMap<String, String>) -> Result<Self> {
+        let mut value = Value::Table(toml::from_str(content)?);
+        apply_arch_overrides(&mut value, vars.get("ARCH").map(|s| s.as_str()))?;
+        expand_value(&mut value, vars);
+        Ok(value.try_into()?)
+    }
+
+    /// 把配置序列化为TOML字符串
+    pub fn to_toml_string(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// 校验配置是否合法
+    ///
+    /// 只进行不依赖于文件系统、网络的静态检查
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::msg("name is empty"));
+        }
+        if self.version.trim().is_empty() {
+            return Err(Error::msg("version is empty"));
+        }
+        if self.target_arch.is_empty() {
+            return Err(Error::msg("target-arch is empty"));
+        }
+        self.validate_task_source()?;
+        if let Some(cargo) = &self.cargo {
+            cargo.validate()?;
+        }
+        if let Some(cmake) = &self.cmake {
+            cmake.validate()?;
+        }
+        self.build.validate()?;
+        self.install.validate()?;
+        self.clean.validate()?;
+        if let Some(test) = &self.test {
+            test.validate()?;
+        }
+        for dep in &self.depends {
+            dep.validate()?;
+        }
+        for package in &self.host_packages {
+            package.validate()?;
+        }
+        for env in &self.envs {
+            env.validate()?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_task_source(&self) -> Result<()> {
+        let ts = &self.task_source;
+        if ts.source_path.trim().is_empty() && ts.source != Source::Repository {
+            return Err(Error::msg("task-source: source-path is empty"));
+        }
+        if ts.source != Source::Git && (ts.branch.is_some() || ts.revision.is_some()) {
+            return Err(Error::msg(
+                "task-source: branch and revision are only available for git source",
+            ));
+        }
+        if ts.branch.is_some() && ts.revision.is_some() {
+            return Err(Error::msg(
+                "task-source: branch and revision can not be specified at the same time",
+            ));
+        }
+        if !ts.patches.is_empty()
+            && (!matches!(ts.source, Source::Git | Source::Archive)
+                || matches!(
+                    ts.source_type,
+                    TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package
+                ))
+        {
+            return Err(Error::msg(
+                "task-source: patches are only available for git and archive sources built from source",
+            ));
+        }
+        if ts.patches.iter().any(|p| p.as_os_str().is_empty()) {
+            return Err(Error::msg("task-source: patch path is empty"));
+        }
+        if let Some(key) = &ts.source_cache_key {
+            if !matches!(ts.source, Source::Git | Source::Archive)
+                || matches!(
+                    ts.source_type,
+                    TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package
+                )
+            {
+                return Err(Error::msg(
+                    "task-source: source-cache-key is only available for git and archive sources built from source",
+                ));
+            }
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                return Err(Error::msg(format!(
+                    "task-source: invalid source-cache-key '{}', only letters, digits, '_', '-' and '.' are allowed",
+                    key
+                )));
+            }
+            // 共享的源码目录中不能有某一个任务的补丁
+            if !ts.patches.is_empty() {
+                return Err(Error::msg(
+                    "task-source: patches can not be used together with source-cache-key",
+                ));
+            }
+        }
+        match ts.source_type {
+            _ if ts.source == Source::Repository
+                && !matches!(
+                    ts.source_type,
+                    TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package
+                ) =>
+            {
+                return Err(Error::msg(format!(
+                    "{} doesn't support repository source",
+                    ts.source_type.name()
+                )));
+            }
+            TaskSourceType::BuildFromSource => {
+                if self.build.build_command.is_none() {
+                    return Err(Error::msg(
+                        "build-command is required for build-from-source",
+                    ));
+                }
+            }
+            TaskSourceType::KernelModule => {
+                if self.build.build_command.is_none() {
+                    return Err(Error::msg("build-command is required for kernel-module"));
+                }
+                if let Some(path) = &self.install.in_dragonos_path {
+                    if !path.starts_with(KERNEL_MODULES_DIR) {
+                        return Err(Error::msg(format!(
+                            "in-dragonos-path of kernel-module tasks should be under {}",
+                            KERNEL_MODULES_DIR
+                        )));
+                    }
+                }
+            }
+            TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package => {
+                if ts.source == Source::Git {
+                    return Err(Error::msg(format!(
+                        "{} doesn't support git source",
+                        ts.source_type.name()
+                    )));
+                }
+                if self.build.build_command.is_some() {
+                    return Err(Error::msg(format!(
+                        "build-command should be empty for {} tasks",
+                        ts.source_type.name()
+                    )));
+                }
+            }
+            TaskSourceType::Cargo | TaskSourceType::Cmake | TaskSourceType::Autotools => {
+                if self.build.build_command.is_some() {
+                    return Err(Error::msg(format!(
+                        "build-command should be empty for {} tasks, DADK generates the build commands",
+                        ts.source_type.name()
+                    )));
+                }
+            }
+        }
+        for (section, present, source_type) in [
+            ("cargo", self.cargo.is_some(), TaskSourceType::Cargo),
+            ("cmake", self.cmake.is_some(), TaskSourceType::Cmake),
+            (
+                "autotools",
+                self.autotools.is_some(),
+                TaskSourceType::Autotools,
+            ),
+        ] {
+            if present && ts.source_type != source_type {
+                return Err(Error::msg(format!(
+                    "[{}] is only available for {} tasks",
+                    section, section
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 查找目录（包括子目录）中的所有用户程序配置文件（扩展名为`toml`，不区分大小写），按路径排序
+pub fn config_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dir_queue = vec![dir.to_path_buf()];
+    while let Some(dir) = dir_queue.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dir_queue.push(path);
+            } else if path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    // 目录的遍历顺序与文件系统有关，排序后使结果在不同机器上保持一致
+    files.sort();
+    Ok(files)
+}
+
+/// 加载目录（包括子目录）中的所有用户程序配置文件，返回(配置文件路径, 加载结果)，按路径排序
+///
+/// 一个配置文件无法解析不影响其他配置文件的加载。只有无法遍历目录时返回错误
+pub fn load_dir(dir: &Path) -> Result<Vec<(PathBuf, Result<UserConfigFile>)>> {
+    load_dir_with_vars(dir, &BTreeMap::new())
+}
+
+/// 与[`load_dir`]相同，并替换所有字符串字段中的`${NAME}`变量
+pub fn load_dir_with_vars(
+    dir: &Path,
+    vars: &BTreeMap<String, String>,
+) -> Result<Vec<(PathBuf, Result<UserConfigFile>)>> {
+    let files = config_files(dir)
+        .map_err(|e| Error::msg(format!("Failed to scan {}: {}", dir.display(), e)))?;
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let config = UserConfigFile::load_with_vars(&path, vars);
+            (path, config)
+        })
+        .collect())
+}
+
+/// `[build.<arch>]`中可以覆盖的字段
+const ARCH_OVERRIDE_KEYS: [&str; 3] = ["build-command", "envs", "source-path"];
+
+/// 把`[build.<arch>]`中与目标架构对应的配置合并到基础配置中，然后删除所有架构的覆盖配置
+///
+/// - `build-command`覆盖`build.build-command`
+/// - `envs`中的环境变量覆盖同名的环境变量，其余的追加到`envs`中
+/// - `source-path`覆盖`task-source.source-path`
+fn apply_arch_overrides(value: &mut Value, arch: Option<&str>) -> Result<()> {
+    let Some(build) = value.get_mut("build").and_then(|v| v.as_table_mut()) else {
+        return Ok(());
+    };
+    let mut active = None;
+    for name in TargetArch::EXPECTED {
+        let Some(table) = build.remove(name) else {
+            continue;
+        };
+        let Value::Table(table) = table else {
+            return Err(Error::msg(format!("build.{}: expected a table", name)));
+        };
+        if let Some(key) = table
+            .keys()
+            .find(|k| !ARCH_OVERRIDE_KEYS.contains(&k.as_str()))
+        {
+            return Err(Error::msg(format!(
+                "build.{}: unknown field `{}`, expected one of {:?}",
+                name, key, ARCH_OVERRIDE_KEYS
+            )));
+        }
+        if Some(name) == arch {
+            active = Some(table);
+        }
+    }
+    let Some(mut overrides) = active else {
+        return Ok(());
+    };
+
+    if let Some(command) = overrides.remove("build-command") {
+        build.insert("build-command".to_string(), command);
+    }
+    if let Some(path) = overrides.remove("source-path") {
+        let source = value
+            .get_mut("task-source")
+            .and_then(|v| v.as_table_mut())
+            .ok_or_else(|| Error::msg("task-source is missing"))?;
+        source.insert("source-path".to_string(), path);
+    }
+    if let Some(envs) = overrides.remove("envs") {
+        let Value::Array(envs) = envs else {
+            return Err(Error::msg("build.<arch>.envs: expected an array"));
+        };
+        let table = value.as_table_mut().expect("config file must be a table");
+        let base = table
+            .entry("envs")
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .ok_or_else(|| Error::msg("envs: expected an array"))?;
+        for env in envs {
+            let key = env.get("key").cloned();
+            match base
+                .iter_mut()
+                .find(|e| key.is_some() && e.get("key") == key.as_ref())
+            {
+                Some(existing) => *existing = env,
+                None => base.push(env),
+            }
+        }
     }
+    Ok(())
 }
 
 fn default_empty_env() -> Vec<TaskEnv> {
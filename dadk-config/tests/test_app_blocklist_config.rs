@@ -0,0 +1,22 @@
+use dadk_config::{
+    self,
+    app_blocklist::{AppBlocklistConfigFile, AppListMode},
+    common::target_arch::TargetArch,
+};
+use test_base::{
+    dadk_config::DadkConfigTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+const APP_BLOCKLIST_CONFIG_FILE_NAME: &str = "config/app_blocklist.toml";
+
+/// 测试加载模板目录中的 app_blocklist.toml 文件，验证它能被加载成功
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_load_app_blocklist_template(ctx: &DadkConfigTestContext) {
+    let path = ctx.templates_dir().join(APP_BLOCKLIST_CONFIG_FILE_NAME);
+    assert!(path.is_file());
+    let config = AppBlocklistConfigFile::load(&path).expect("Failed to load app blocklist");
+    assert_eq!(config.mode, AppListMode::Blocklist);
+    assert!(!config.is_blocked("app1", TargetArch::RiscV64));
+}
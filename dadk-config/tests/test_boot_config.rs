@@ -1,4 +1,13 @@
-use dadk_config::{self, boot::BootConfigFile};
+use std::path::PathBuf;
+
+use dadk_config::{
+    self,
+    boot::{
+        hypervisor::qemu::{ForwardProtocol, PortForward, ShareType},
+        BootConfigFile,
+    },
+    common::target_arch::TargetArch,
+};
 use test_base::{
     dadk_config::DadkConfigTestContext,
     test_context::{self as test_context, test_context},
@@ -16,3 +25,134 @@ fn test_load_boot_config_template(ctx: &DadkConfigTestContext) {
     let _manifest = BootConfigFile::load(&boot_config_path).expect("Failed to load boot config");
     // TODO 校验 manifest 中的字段是否齐全
 }
+
+/// 测试解析qemu的qmp-socket配置
+#[test]
+fn test_boot_config_qmp_socket() {
+    let content = r#"
+[metadata]
+boot-protocol = "grub-legacy"
+boot-mode = "graphic"
+hypervisor = "qemu"
+kcmd-args = []
+init-args = []
+
+[qemu]
+args = ""
+no-graphic-args = ""
+qmp-socket = "unix:/tmp/dragonos-qmp.sock"
+"#;
+    let config = BootConfigFile::load_from_str(content).expect("Failed to load boot config");
+    assert_eq!(
+        config.qemu.unwrap().qmp_socket(),
+        Some("unix:/tmp/dragonos-qmp.sock")
+    );
+}
+
+/// 测试解析qemu的端口转发以及共享目录配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_boot_config_port_forwards_and_share(ctx: &DadkConfigTestContext) {
+    let content = std::fs::read_to_string(ctx.templates_dir().join(BOOT_CONFIG_FILE_NAME)).unwrap();
+    let config = BootConfigFile::load_from_str(&content).unwrap();
+    let qemu = config.qemu.unwrap();
+    assert!(qemu.port_forwards().is_empty());
+    assert_eq!(qemu.share(), None);
+
+    let uncomment = |content: &str| {
+        content
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start_matches("# ");
+                if trimmed.starts_with("[[qemu.port-forwards]]")
+                    || trimmed.starts_with("[qemu.share]")
+                    || [
+                        "host =",
+                        "guest =",
+                        "protocol =",
+                        "path =",
+                        "tag =",
+                        "type =",
+                    ]
+                    .iter()
+                    .any(|k| trimmed.starts_with(k))
+                {
+                    trimmed
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let config = BootConfigFile::load_from_str(&uncomment(&content)).unwrap();
+    let qemu = config.qemu.unwrap();
+    assert_eq!(
+        qemu.port_forwards(),
+        &[PortForward {
+            host: 2222,
+            guest: 22,
+            protocol: ForwardProtocol::Tcp
+        }]
+    );
+    let share = qemu.share().unwrap();
+    assert_eq!(share.path, Some(PathBuf::from("bin/sysroot")));
+    assert_eq!(share.tag, "dadk");
+    assert_eq!(share.share_type, ShareType::NineP);
+    assert!(!share.readonly);
+
+    let invalid = [
+        "[[qemu.port-forwards]]\nhost = 2222\nguest = 22\n[[qemu.port-forwards]]\nhost = 2222\nguest = 23",
+        "[[qemu.port-forwards]]\nhost = 0\nguest = 22",
+        "[[qemu.port-forwards]]\nhost = 2222\nguest = 22\nprotocol = \"sctp\"",
+        "[qemu.share]\ntag = \"a,b\"",
+        "[qemu.share]\ntype = \"virtiofs\"\nreadonly = true",
+    ];
+    for extra in invalid {
+        let content = format!("{}\n{}", content, extra);
+        assert!(
+            BootConfigFile::load_from_str(&content).is_err(),
+            "{}",
+            extra
+        );
+    }
+}
+
+/// 测试检查启动协议所需的配置段
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_boot_config_check(ctx: &DadkConfigTestContext) {
+    let content = std::fs::read_to_string(ctx.templates_dir().join(BOOT_CONFIG_FILE_NAME)).unwrap();
+    let config = BootConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(config.check(TargetArch::X86_64), Vec::<String>::new());
+    let paths: Vec<&str> = config
+        .referenced_paths()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(paths, vec!["grub-file", "grub-install"]);
+    // grub只支持x86_64
+    assert_eq!(config.check(TargetArch::RiscV64).len(), 1);
+
+    let with_dragonstub = format!("{}\n[dragonstub]\nsrc-path = \"DragonStub\"\n", content);
+    let config = BootConfigFile::load_from_str(&with_dragonstub).unwrap();
+    let problems = config.check(TargetArch::X86_64);
+    assert!(problems[0].contains("mutually exclusive"), "{:?}", problems);
+
+    let dragon_stub = r#"
+[metadata]
+boot-protocol = "dragon-stub"
+boot-mode = "no-graphic"
+hypervisor = "qemu"
+"#;
+    let config = BootConfigFile::load_from_str(dragon_stub).unwrap();
+    assert_eq!(
+        config.check(TargetArch::RiscV64),
+        vec![
+            "boot-protocol dragon-stub requires [dragonstub]".to_string(),
+            "hypervisor qemu requires [qemu]".to_string(),
+        ]
+    );
+    assert_eq!(config.check(TargetArch::X86_64).len(), 3);
+    assert!(config.referenced_paths().is_empty());
+}
@@ -1,6 +1,6 @@
 use dadk_config::{
     self,
-    rootfs::{partition::PartitionType, RootFSConfigFile},
+    rootfs::{image_format::ImageFormat, partition::PartitionType, RootFSConfigFile},
 };
 use test_base::{
     dadk_config::DadkConfigTestContext,
@@ -19,5 +19,45 @@ fn test_load_rootfs_manifest_template(ctx: &DadkConfigTestContext) {
     let manifest =
         RootFSConfigFile::load(&rootfs_manifest_path).expect("Failed to load rootfs manifest");
     assert_eq!(manifest.partition.partition_type, PartitionType::None);
+    assert!(manifest.install.allowed_paths.is_empty());
+    assert_eq!(manifest.metadata.format, ImageFormat::Raw);
     // TODO 校验 manifest 中的字段是否齐全
 }
+
+/// 测试`[install]`中允许安装文件的目录
+#[test]
+fn test_rootfs_install_allowed_paths() {
+    let content = r#"
+        [metadata]
+        fs_type = "fat32"
+        size = "1G"
+
+        [install]
+        allowed_paths = ["/bin", "/usr"]
+    "#;
+    let config = RootFSConfigFile::load_from_str(content).unwrap();
+    assert_eq!(
+        config.install.allowed_paths,
+        vec![
+            std::path::PathBuf::from("/bin"),
+            std::path::PathBuf::from("/usr")
+        ]
+    );
+    let relative = content.replace("\"/usr\"", "\"usr\"");
+    assert!(RootFSConfigFile::load_from_str(&relative).is_err());
+}
+
+/// 测试`[metadata]`中磁盘镜像的输出格式
+#[test]
+fn test_rootfs_image_format() {
+    let content = r#"
+        [metadata]
+        fs_type = "fat32"
+        size = "1G"
+        format = "qcow2"
+    "#;
+    let config = RootFSConfigFile::load_from_str(content).unwrap();
+    assert_eq!(config.metadata.format, ImageFormat::Qcow2);
+    let invalid = content.replace("qcow2", "vmdk");
+    assert!(RootFSConfigFile::load_from_str(&invalid).is_err());
+}
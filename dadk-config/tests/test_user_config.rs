@@ -4,8 +4,9 @@ use dadk_config::{
     common::{
         target_arch::TargetArch,
         task::{
-            BuildConfig, CleanConfig, Dependency, InstallConfig, Source, TaskEnv, TaskSource,
-            TaskSourceType,
+            BuildConfig, CargoConfig, CleanConfig, Dependency, HostPackage, InstallConfig,
+            InstallFileConfig, ResourcesConfig, Source, TaskEnv, TaskPriority, TaskSource,
+            TaskSourceType, TestConfig,
         },
     },
     user::UserConfigFile,
@@ -40,6 +41,9 @@ fn test_parse_dadk_user_config(ctx: &mut DadkConfigTestContext) {
                 .to_string(),
             branch: None,
             revision: Some("01cdc56863".to_string()),
+            patches: Vec::new(),
+            source_cache_key: None,
+            no_mirror: false,
         },
         depends: vec![
             Dependency {
@@ -51,6 +55,7 @@ fn test_parse_dadk_user_config(ctx: &mut DadkConfigTestContext) {
                 version: "0.1.2".to_string(),
             },
         ],
+        host_packages: Vec::new(),
         build: BuildConfig::new(
             Some("make install".to_string()),
             Some(PathBuf::from("config/pre_build.sh")),
@@ -58,11 +63,18 @@ fn test_parse_dadk_user_config(ctx: &mut DadkConfigTestContext) {
         ),
         install: InstallConfig::new(Some(PathBuf::from("/bin"))),
         clean: CleanConfig::new(Some("make clean".to_string())),
+        test: None,
         envs: vec![
             TaskEnv::new("PATH".to_string(), "/usr/bin".to_string()),
             TaskEnv::new("LD_LIBRARY_PATH".to_string(), "/usr/lib".to_string()),
         ],
         target_arch: vec![TargetArch::try_from("x86_64").unwrap()],
+        retries: None,
+        priority: TaskPriority::default(),
+        cargo: None,
+        cmake: None,
+        autotools: None,
+        overrides: false,
     };
 
     user_config.target_arch.sort();
@@ -74,3 +86,725 @@ fn test_parse_dadk_user_config(ctx: &mut DadkConfigTestContext) {
 
     assert_eq!(user_config, expected_user_config)
 }
+
+/// 测试用户配置文件序列化后能被重新解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_toml_roundtrip(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert!(user_config.validate().is_ok());
+
+    let content = user_config.to_toml_string().unwrap();
+    let parsed = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(parsed, user_config);
+}
+
+/// 测试用户配置文件的校验
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_validate(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+
+    let mut user_config = UserConfigFile::load(&config_file).unwrap();
+    user_config.task_source.branch = Some("test".to_string());
+    assert!(user_config.validate().is_err());
+
+    let mut user_config = UserConfigFile::load(&config_file).unwrap();
+    user_config.task_source.source_type = TaskSourceType::InstallFromPrebuilt;
+    assert!(user_config.validate().is_err());
+
+    let mut user_config = UserConfigFile::load(&config_file).unwrap();
+    user_config.target_arch.clear();
+    assert!(user_config.validate().is_err());
+
+    let mut user_config = UserConfigFile::load(&config_file).unwrap();
+    user_config.build.build_command = None;
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试`[[install.files]]`的解析与校验
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_install_files(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let mut content = std::fs::read_to_string(config_file).unwrap();
+    content.push_str(
+        r#"
+[[install.files]]
+path = "su"
+mode = "4755"
+uid = 0
+gid = 0
+
+[[install.files]]
+path = "sh"
+symlink = "busybox"
+"#,
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    let files = &user_config.install.files;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].path, PathBuf::from("su"));
+    assert_eq!(files[0].mode_bits().unwrap(), Some(0o4755));
+    assert_eq!((files[0].uid, files[0].gid), (Some(0), Some(0)));
+    assert_eq!(files[1].symlink, Some(PathBuf::from("busybox")));
+    assert_eq!(files[1].mode_bits().unwrap(), None);
+
+    // 序列化后能被重新解析
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed.install.files, user_config.install.files);
+
+    let file = |path: &str, mode: Option<&str>, symlink: Option<&str>| InstallFileConfig {
+        path: PathBuf::from(path),
+        mode: mode.map(|m| m.to_string()),
+        uid: None,
+        gid: None,
+        symlink: symlink.map(PathBuf::from),
+    };
+    assert!(file("su", Some("0o755"), None).validate().is_ok());
+    assert!(file("su", Some("rwxr-xr-x"), None).validate().is_err());
+    assert!(file("su", Some("17777"), None).validate().is_err());
+    assert!(file("/bin/su", None, None).validate().is_err());
+    assert!(file("../su", None, None).validate().is_err());
+    assert!(file("sh", Some("755"), Some("busybox")).validate().is_err());
+
+    let mut install = InstallConfig::new(None);
+    install.files.push(file("su", None, None));
+    assert!(install.validate().is_err());
+
+    // 安装路径不能跳出sysroot
+    assert!(InstallConfig::new(Some(PathBuf::from("/usr/../bin")))
+        .validate()
+        .is_err());
+    assert!(InstallConfig::new(Some(PathBuf::from("/usr/./bin")))
+        .validate()
+        .is_ok());
+}
+
+/// 测试`install.strip`的解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_install_strip(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert!(!user_config.install.strip);
+    assert_eq!(user_config.install.strip_tool, None);
+
+    let content = std::fs::read_to_string(config_file).unwrap().replace(
+        "in-dragonos-path = \"/bin\"",
+        "in-dragonos-path = \"/bin\"\nstrip = true\nstrip-tool = \"llvm-strip\"",
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    assert!(user_config.install.strip);
+    assert_eq!(
+        user_config.install.strip_tool.as_deref(),
+        Some("llvm-strip")
+    );
+
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed.install, user_config.install);
+}
+
+/// 测试`install.exclude`的解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_install_exclude(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert!(user_config.install.exclude.is_empty());
+
+    let content = std::fs::read_to_string(&config_file).unwrap().replace(
+        "# exclude = [\"*.o\", \"target/\"]",
+        "exclude = [\"*.o\", \"target/\"]",
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    assert_eq!(user_config.install.exclude, vec!["*.o", "target/"]);
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed.install, user_config.install);
+
+    for pattern in ["", "../etc", "lib/../../etc"] {
+        let mut install = user_config.install.clone();
+        install.exclude = vec![pattern.to_string()];
+        assert!(
+            install.validate().is_err(),
+            "{:?} should be invalid",
+            pattern
+        );
+    }
+}
+
+/// 测试`build.timeout`、`clean.timeout`的解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_timeout(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert_eq!(user_config.build.timeout_duration().unwrap(), None);
+    assert_eq!(user_config.clean.timeout_duration().unwrap(), None);
+
+    let content = std::fs::read_to_string(config_file).unwrap();
+    let content = content
+        .replace("# timeout = \"30m\"", "timeout = \"1h30m\"")
+        .replace("# timeout = \"5m\"", "timeout = \"90\"");
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    assert_eq!(
+        user_config.build.timeout_duration().unwrap(),
+        Some(std::time::Duration::from_secs(90 * 60))
+    );
+    assert_eq!(
+        user_config.clean.timeout_duration().unwrap(),
+        Some(std::time::Duration::from_secs(90))
+    );
+
+    let mut build = BuildConfig::new(Some("make".to_string()), None, None);
+    build.timeout = Some("0s".to_string());
+    assert!(build.validate().is_err());
+    build.timeout = Some("30 minutes".to_string());
+    assert!(build.validate().is_err());
+    let mut clean = CleanConfig::new(None);
+    clean.timeout = Some("5x".to_string());
+    assert!(clean.validate().is_err());
+}
+
+/// 测试`build.resources`的解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_resources(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert_eq!(user_config.build.resources, None);
+
+    let content = std::fs::read_to_string(config_file).unwrap();
+    let content = content
+        .replace("# [build.resources]", "[build.resources]")
+        .replace("# cpus = 4", "cpus = 4")
+        .replace("# memory = \"8G\"", "memory = \"8G\"");
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    let resources = user_config.build.resources.unwrap();
+    assert_eq!(resources.cpus, Some(4));
+    assert_eq!(resources.memory_bytes().unwrap(), Some(8 << 30));
+
+    for (cpus, memory) in [(Some(0), None), (None, Some("0")), (None, Some("8X"))] {
+        let mut build = BuildConfig::new(Some("make".to_string()), None, None);
+        build.resources = Some(ResourcesConfig {
+            cpus,
+            memory: memory.map(|m| m.to_string()),
+        });
+        assert!(build.validate().is_err());
+    }
+}
+
+/// 测试`test`的解析
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_test(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let user_config = UserConfigFile::load(&config_file).unwrap();
+    assert_eq!(user_config.test, None);
+
+    let content = std::fs::read_to_string(config_file).unwrap();
+    let content = content
+        .replace("# [test]", "[test]")
+        .replace("# test-command = ", "test-command = ")
+        .replace("# guest-script = ", "guest-script = ")
+        .replace("# timeout = \"10m\"", "timeout = \"10m\"");
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.validate().is_ok());
+    let test = user_config.test.unwrap();
+    assert_eq!(test.test_command.as_deref(), Some("make test"));
+    assert_eq!(
+        test.guest_script,
+        Some(PathBuf::from("user/apps/test_app/test.sh"))
+    );
+    assert_eq!(
+        test.timeout_duration().unwrap(),
+        Some(std::time::Duration::from_secs(600))
+    );
+
+    assert!(TestConfig::default().validate().is_err());
+    let test = TestConfig {
+        test_command: Some(" ".to_string()),
+        ..Default::default()
+    };
+    assert!(test.validate().is_err());
+}
+
+/// 测试`priority`的解析
+#[test]
+fn test_user_config_priority() {
+    let template = "name = \"app\"\nversion = \"0.1.0\"\ndescription = \"\"\n{priority}\n\
+        target-arch = [\"x86_64\"]\n\
+        [task-source]\ntype = \"build-from-source\"\nsource = \"local\"\nsource-path = \"apps/app\"\n\
+        [build]\nbuild-command = \"make\"\n[install]\n[clean]\n";
+    let load = |priority: &str| {
+        UserConfigFile::load_from_str(&template.replace("{priority}", priority)).map(|c| c.priority)
+    };
+    assert_eq!(load("").unwrap(), TaskPriority::NORMAL);
+    assert_eq!(load("priority = \"high\"").unwrap(), TaskPriority::HIGH);
+    assert_eq!(load("priority = \"Low\"").unwrap(), TaskPriority::LOW);
+    assert_eq!(load("priority = 50").unwrap(), TaskPriority(50));
+    assert_eq!(load("priority = -3").unwrap(), TaskPriority(-3));
+    assert!(load("priority = \"urgent\"").is_err());
+
+    // 序列化时使用名称，默认优先级不输出
+    let config =
+        UserConfigFile::load_from_str(&template.replace("{priority}", "priority = 10")).unwrap();
+    let toml = config.to_toml_string().unwrap();
+    assert!(toml.contains("priority = \"high\""), "{}", toml);
+    let config = UserConfigFile::load_from_str(&template.replace("{priority}", "")).unwrap();
+    assert!(!config.to_toml_string().unwrap().contains("priority"));
+}
+
+/// 测试加载配置文件时替换字符串字段中的变量
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_with_vars(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let content = std::fs::read_to_string(config_file)
+        .unwrap()
+        .replace("https://git.mirrors.dragonos.org.cn/", "${MIRROR}/")
+        .replace(
+            "build-command = \"make install\"",
+            "build-command = \"make ARCH=${ARCH} PREFIX=${DADK_CURRENT_BUILD_DIR}\"",
+        )
+        .replace(
+            "in-dragonos-path = \"/bin\"",
+            "in-dragonos-path = \"/opt/${ARCH}/bin\"",
+        );
+    let vars = std::collections::BTreeMap::from([
+        ("ARCH".to_string(), "riscv64".to_string()),
+        (
+            "MIRROR".to_string(),
+            "https://mirrors.example.com".to_string(),
+        ),
+    ]);
+    let user_config = UserConfigFile::load_from_str_with_vars(&content, &vars).unwrap();
+    assert_eq!(
+        user_config.task_source.source_path,
+        "https://mirrors.example.com/DragonOS-Community/test_git.git"
+    );
+    // 未定义的变量保持原样，留给构建时的shell展开
+    assert_eq!(
+        user_config.build.build_command,
+        Some("make ARCH=riscv64 PREFIX=${DADK_CURRENT_BUILD_DIR}".to_string())
+    );
+    assert_eq!(
+        user_config.install.in_dragonos_path,
+        Some(PathBuf::from("/opt/riscv64/bin"))
+    );
+
+    // 不传入变量时与`load_from_str`一致
+    assert_eq!(
+        UserConfigFile::load_from_str_with_vars(&content, &Default::default()).unwrap(),
+        UserConfigFile::load_from_str(&content).unwrap()
+    );
+}
+
+/// 测试`[build.<arch>]`中的架构相关配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_arch_overrides(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file).unwrap();
+    let content = template.replace(
+        "[install]\n",
+        "[build.riscv64]\n\
+        build-command = \"make CROSS=${ARCH}\"\n\
+        source-path = \"https://example.com/app-riscv64.git\"\n\
+        envs = [{ key = \"PATH\", value = \"/opt/riscv/bin\" }, { key = \"CC\", value = \"gcc\" }]\n\
+        [build.x86_64]\nbuild-command = \"make x86\"\n\
+        [install]\n",
+    );
+    let load = |arch: &str| {
+        let vars = std::collections::BTreeMap::from([("ARCH".to_string(), arch.to_string())]);
+        UserConfigFile::load_from_str_with_vars(&content, &vars).unwrap()
+    };
+    let base = UserConfigFile::load_from_str(&template).unwrap();
+
+    let riscv = load("riscv64");
+    assert_eq!(
+        riscv.build.build_command,
+        Some("make CROSS=riscv64".to_string())
+    );
+    assert_eq!(
+        riscv.task_source.source_path,
+        "https://example.com/app-riscv64.git"
+    );
+    assert_eq!(
+        riscv.envs,
+        vec![
+            TaskEnv::new("PATH".to_string(), "/opt/riscv/bin".to_string()),
+            TaskEnv::new("LD_LIBRARY_PATH".to_string(), "/usr/lib".to_string()),
+            TaskEnv::new("CC".to_string(), "gcc".to_string()),
+        ]
+    );
+    assert_eq!(riscv.build.pre_build, base.build.pre_build);
+
+    let x86 = load("x86_64");
+    assert_eq!(x86.build.build_command, Some("make x86".to_string()));
+    assert_eq!(x86.task_source, base.task_source);
+    assert_eq!(x86.envs, base.envs);
+
+    // 没有对应的覆盖配置，或者没有指定目标架构时，使用基础配置
+    assert_eq!(load("aarch64"), base);
+    assert_eq!(UserConfigFile::load_from_str(&content).unwrap(), base);
+
+    // 覆盖配置只能包含允许的字段
+    let content = template.replace(
+        "[install]\n",
+        "[build.riscv64]\npre-build = \"a.sh\"\n[install]\n",
+    );
+    assert!(UserConfigFile::load_from_str(&content).is_err());
+}
+
+/// 测试源码补丁的配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_patches(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file).unwrap();
+    let content = template.replace(
+        "revision = \"01cdc56863\"",
+        "revision = \"01cdc56863\"\npatches = [\"patches/0001-fix.patch\", \"patches/0002-dragonos.patch\"]",
+    );
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(
+        user_config.task_source.patches,
+        vec![
+            PathBuf::from("patches/0001-fix.patch"),
+            PathBuf::from("patches/0002-dragonos.patch")
+        ]
+    );
+    assert!(user_config.validate().is_ok());
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed, user_config);
+    // 没有补丁时不输出
+    let config = UserConfigFile::load_from_str(&template).unwrap();
+    assert!(!config.to_toml_string().unwrap().contains("patches"));
+
+    // 本地源码目录不支持补丁
+    user_config.task_source.source = Source::Local;
+    user_config.task_source.revision = None;
+    assert!(user_config.validate().is_err());
+    user_config.task_source.source = Source::Archive;
+    assert!(user_config.validate().is_ok());
+    user_config.task_source.patches.push(PathBuf::new());
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试源码缓存的共享键
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_source_cache_key(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file).unwrap();
+    let content = template.replace(
+        "revision = \"01cdc56863\"",
+        "revision = \"01cdc56863\"\nsource-cache-key = \"busybox-1.36\"",
+    );
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(
+        user_config.task_source.source_cache_key.as_deref(),
+        Some("busybox-1.36")
+    );
+    assert!(user_config.validate().is_ok());
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed, user_config);
+
+    // 共享的源码目录不能打补丁
+    user_config
+        .task_source
+        .patches
+        .push(PathBuf::from("patches/0001-fix.patch"));
+    assert!(user_config.validate().is_err());
+    user_config.task_source.patches.clear();
+
+    // 键中不能有路径分隔符
+    user_config.task_source.source_cache_key = Some("../busybox".to_string());
+    assert!(user_config.validate().is_err());
+    user_config.task_source.source_cache_key = Some(String::new());
+    assert!(user_config.validate().is_err());
+
+    // 本地源码目录不需要缓存
+    user_config.task_source.source_cache_key = Some("busybox".to_string());
+    user_config.task_source.source = Source::Local;
+    user_config.task_source.revision = None;
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试不使用镜像的任务
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_no_mirror(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file).unwrap();
+    let user_config = UserConfigFile::load_from_str(&template).unwrap();
+    assert!(!user_config.task_source.no_mirror);
+    assert!(!user_config.to_toml_string().unwrap().contains("no-mirror"));
+
+    let content = template.replace(
+        "revision = \"01cdc56863\"",
+        "revision = \"01cdc56863\"\nno-mirror = true",
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert!(user_config.task_source.no_mirror);
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed, user_config);
+}
+
+/// 测试cargo任务的配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_cargo(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let content = std::fs::read_to_string(config_file)
+        .unwrap()
+        .replace("type = \"build-from-source\"", "type = \"cargo\"")
+        .replace("build-command = \"make install\"", "");
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(user_config.task_source.source_type, TaskSourceType::Cargo);
+    assert_eq!(user_config.cargo, None);
+    assert!(user_config.validate().is_ok());
+
+    let content = format!(
+        "{}\n[cargo]\nrust-target = \"x86_64-unknown-dragonos.json\"\nbins = [\"app\"]\n",
+        content
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    let cargo = user_config.cargo.as_ref().unwrap();
+    assert_eq!(cargo.bins, vec!["app".to_string()]);
+    assert_eq!(
+        cargo.rust_target(TargetArch::RiscV64),
+        "x86_64-unknown-dragonos.json"
+    );
+    assert_eq!(
+        cargo.target_dir_name(TargetArch::RiscV64),
+        "x86_64-unknown-dragonos"
+    );
+    assert!(user_config.validate().is_ok());
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed, user_config);
+
+    // cargo任务不需要构建命令
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    user_config.build.build_command = Some("make".to_string());
+    assert!(user_config.validate().is_err());
+    // [cargo]只能用于cargo任务
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    user_config.task_source.source_type = TaskSourceType::BuildFromSource;
+    user_config.build.build_command = Some("make".to_string());
+    assert!(user_config.validate().is_err());
+
+    assert_eq!(
+        CargoConfig::default().rust_target(TargetArch::RiscV64),
+        "riscv64gc-unknown-linux-musl"
+    );
+}
+
+/// 测试cmake、autotools任务的配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_cmake_autotools(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file)
+        .unwrap()
+        .replace("build-command = \"make install\"", "");
+
+    let content = format!(
+        "{}\n[cmake]\nbuild-type = \"Debug\"\noptions = [\"-DFOO=ON\"]\n",
+        template.replace("type = \"build-from-source\"", "type = \"cmake\"")
+    );
+    let user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(user_config.task_source.source_type, TaskSourceType::Cmake);
+    let cmake = user_config.cmake.as_ref().unwrap();
+    assert_eq!(cmake.build_type(), "Debug");
+    assert_eq!(cmake.build_dir(), "build");
+    assert_eq!(cmake.options, vec!["-DFOO=ON".to_string()]);
+    assert!(user_config.validate().is_ok());
+    let parsed = UserConfigFile::load_from_str(&user_config.to_toml_string().unwrap()).unwrap();
+    assert_eq!(parsed, user_config);
+
+    let content = format!(
+        "{}\n[autotools]\nconfigure-options = [\"--disable-nls\"]\n",
+        template.replace("type = \"build-from-source\"", "type = \"autotools\"")
+    );
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(
+        user_config.autotools.as_ref().unwrap().configure_options,
+        vec!["--disable-nls".to_string()]
+    );
+    assert!(user_config.validate().is_ok());
+    // [autotools]只能用于autotools任务
+    user_config.task_source.source_type = TaskSourceType::Cmake;
+    assert!(user_config.validate().is_err());
+    // 由DADK生成构建命令的任务不需要build-command
+    user_config.task_source.source_type = TaskSourceType::Autotools;
+    user_config.build.build_command = Some("make".to_string());
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试从二进制包安装的任务
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_package(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let template = std::fs::read_to_string(config_file)
+        .unwrap()
+        .replace("build-command = \"make install\"", "")
+        .replace("type = \"build-from-source\"", "type = \"package\"");
+
+    let content = template
+        .replace("source = \"git\"", "source = \"archive\"")
+        .replace(
+            "https://git.mirrors.dragonos.org.cn/DragonOS-Community/test_git.git",
+            "https://example.com/app-0.1.0-x86_64.dpk",
+        )
+        .replace("revision = \"01cdc56863\"", "");
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(user_config.task_source.source_type, TaskSourceType::Package);
+    assert_eq!(user_config.task_source.source_type.name(), "package");
+    assert!(!user_config
+        .task_source
+        .source_type
+        .generates_build_command());
+    assert!(user_config.validate().is_ok());
+
+    // 二进制包不支持从git获取
+    user_config.task_source.source = Source::Git;
+    assert!(user_config.validate().is_err());
+    // 二进制包不需要build-command
+    user_config.task_source.source = Source::Archive;
+    user_config.build.build_command = Some("make".to_string());
+    assert!(user_config.validate().is_err());
+
+    // 从软件仓库安装时，source-path可以为空
+    user_config.build.build_command = None;
+    user_config.task_source.source = Source::Repository;
+    user_config.task_source.source_path = String::new();
+    assert!(user_config.validate().is_ok());
+    user_config.task_source.source_type = TaskSourceType::InstallFromPrebuilt;
+    assert!(user_config.validate().is_ok());
+    user_config.task_source.source_type = TaskSourceType::Cargo;
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试内核模块任务的配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_kernel_module(ctx: &mut DadkConfigTestContext) {
+    let config_file = ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE);
+    let content = std::fs::read_to_string(config_file)
+        .unwrap()
+        .replace("type = \"build-from-source\"", "type = \"kernel-module\"")
+        .replace(
+            "in-dragonos-path = \"/bin\"",
+            "in-dragonos-path = \"/lib/modules/0.1.10/extra\"",
+        );
+    let mut user_config = UserConfigFile::load_from_str(&content).unwrap();
+    assert_eq!(
+        user_config.task_source.source_type,
+        TaskSourceType::KernelModule
+    );
+    assert_eq!(user_config.task_source.source_type.name(), "kernel-module");
+    assert!(user_config.validate().is_ok());
+
+    // 安装目录必须在/lib/modules下
+    user_config.install.in_dragonos_path = Some("/bin".into());
+    assert!(user_config.validate().is_err());
+    // 没有设置安装目录时使用默认目录
+    user_config.install.in_dragonos_path = None;
+    assert!(user_config.validate().is_ok());
+    // 内核模块需要构建命令
+    user_config.build.build_command = None;
+    assert!(user_config.validate().is_err());
+}
+
+/// 测试加载目录中的所有配置文件，并且序列化后再次加载得到相同的配置
+#[test_context(DadkConfigTestContext)]
+#[test]
+fn test_user_config_load_dir(ctx: &mut DadkConfigTestContext) {
+    let dir = ctx.abs_path("../tests/data/dadk_config_v2");
+    let files = dadk_config::user::config_files(&dir).unwrap();
+    assert!(!files.is_empty());
+    assert!(files.windows(2).all(|w| w[0] < w[1]));
+
+    let configs = dadk_config::user::load_dir(&dir).unwrap();
+    assert_eq!(
+        configs.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>(),
+        files
+    );
+    for (path, config) in configs {
+        let config = config.unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let parsed = UserConfigFile::load_from_str(&config.to_toml_string().unwrap()).unwrap();
+        assert_eq!(parsed, config, "{}", path.display());
+    }
+
+    // 无法解析的配置文件不影响其他配置文件
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::copy(
+        ctx.templates_dir().join(USER_CONFIG_LOCAL_FILE),
+        dir.join("sub/app.TOML"),
+    )
+    .unwrap();
+    std::fs::write(dir.join("broken.toml"), "name = ").unwrap();
+    std::fs::write(dir.join("README.md"), "not a config").unwrap();
+    let configs = dadk_config::user::load_dir(dir).unwrap();
+    assert_eq!(configs.len(), 2);
+    assert!(configs[0].0.ends_with("broken.toml") && configs[0].1.is_err());
+    assert_eq!(configs[1].1.as_ref().unwrap().name, "userapp_config");
+}
+
+/// 测试解析构建依赖的主机软件包
+#[test]
+fn test_user_config_host_packages() {
+    let config = r#"
+name = "app"
+version = "0.1.0"
+description = ""
+target-arch = ["x86_64"]
+depends-on-host-packages = ["nasm", { package = "dosfstools", command = "mkfs.fat" }]
+
+[task-source]
+type = "build-from-source"
+source = "local"
+source-path = "app"
+
+[build]
+build-command = "make"
+
+[install]
+in-dragonos-path = "/bin"
+
+[clean]
+"#;
+    let user_config = UserConfigFile::load_from_str(config).unwrap();
+    assert_eq!(
+        user_config.host_packages,
+        vec![
+            HostPackage::new("nasm"),
+            HostPackage {
+                package: "dosfstools".to_string(),
+                command: Some("mkfs.fat".to_string()),
+            },
+        ]
+    );
+    assert_eq!(user_config.host_packages[0].command(), "nasm");
+    assert!(user_config.validate().is_ok());
+
+    let toml = user_config.to_toml_string().unwrap();
+    assert_eq!(UserConfigFile::load_from_str(&toml).unwrap(), user_config);
+
+    let invalid = config.replace("\"nasm\"", "\"nasm yasm\"");
+    assert!(UserConfigFile::load_from_str(&invalid)
+        .unwrap()
+        .validate()
+        .is_err());
+}
@@ -0,0 +1,119 @@
+//! # 受文件变更影响的任务
+//!
+//! 根据发生变更的文件（例如`git diff --name-only`的输出）找到受影响的任务：
+//! 本地源码目录包含该文件，或者配置文件就是该文件的任务，以及直接或间接依赖于它们的任务。
+//!
+//! 相对路径相对于当前工作目录（DragonOS的根目录）解析。
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{parser::task::DADKTask, status::find_task, utils::path::abs_path};
+
+#[cfg(test)]
+mod tests;
+
+/// 任务的名称和版本
+pub type TaskKey = (String, String);
+
+fn key(task: &DADKTask) -> TaskKey {
+    (task.name.clone(), task.version.clone())
+}
+
+/// 计算受文件变更影响的任务（包括依赖于它们的任务）
+pub fn affected_tasks(tasks: &[(PathBuf, DADKTask)], changed: &[PathBuf]) -> BTreeSet<TaskKey> {
+    let changed: Vec<PathBuf> = changed.iter().map(|p| normalize(p)).collect();
+    let touched = |config: &Path, task: &DADKTask| {
+        let config = normalize(config);
+        let src = task.source_path().map(|p| normalize(&p));
+        changed
+            .iter()
+            .any(|p| *p == config || src.as_ref().is_some_and(|s| p.starts_with(s)))
+    };
+
+    let mut affected: BTreeSet<TaskKey> = tasks
+        .iter()
+        .filter(|(config, task)| touched(config, task))
+        .map(|(_, task)| key(task))
+        .collect();
+
+    // 不断把依赖于已受影响任务的任务加入集合，直到不再变化
+    loop {
+        let mut grown = false;
+        for (_, task) in tasks {
+            if affected.contains(&key(task)) {
+                continue;
+            }
+            if task
+                .depends
+                .iter()
+                .any(|d| affected.contains(&(d.name.clone(), d.version.clone())))
+            {
+                affected.insert(key(task));
+                grown = true;
+            }
+        }
+        if !grown {
+            break;
+        }
+    }
+    affected
+}
+
+/// 只保留给定的任务，以及它们（直接或间接）依赖的任务
+pub fn with_dependencies(
+    tasks: Vec<(PathBuf, DADKTask)>,
+    selected: &BTreeSet<TaskKey>,
+) -> Vec<(PathBuf, DADKTask)> {
+    let mut wanted = selected.clone();
+    let mut pending: Vec<TaskKey> = selected.iter().cloned().collect();
+    while let Some(current) = pending.pop() {
+        let Some((_, task)) = tasks.iter().find(|(_, t)| key(t) == current) else {
+            continue;
+        };
+        for dep in &task.depends {
+            if wanted.insert((dep.name.clone(), dep.version.clone())) {
+                pending.push((dep.name.clone(), dep.version.clone()));
+            }
+        }
+    }
+    tasks
+        .into_iter()
+        .filter(|(_, task)| wanted.contains(&key(task)))
+        .collect()
+}
+
+/// 只保留指定的任务（名称或者`name@version`，见[`find_task`]），以及它们（直接或间接）依赖的任务
+pub fn select(
+    tasks: Vec<(PathBuf, DADKTask)>,
+    queries: &[String],
+) -> Result<Vec<(PathBuf, DADKTask)>, String> {
+    let mut selected = BTreeSet::new();
+    for query in queries {
+        let (_, task) = find_task(&tasks, query)?;
+        selected.insert(key(task));
+    }
+    Ok(with_dependencies(tasks, &selected))
+}
+
+/// 转换为绝对路径，并解析其中的符号链接。
+/// 文件已经被删除时，只解析仍然存在的最深的父目录
+fn normalize(path: &Path) -> PathBuf {
+    let path = abs_path(&path.to_path_buf());
+    let mut rest = Vec::new();
+    let mut current = path.as_path();
+    loop {
+        if let Ok(real) = current.canonicalize() {
+            return rest.iter().rev().fold(real, |p, name| p.join(name));
+        }
+        match (current.parent(), current.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_owned());
+                current = parent;
+            }
+            _ => return path,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use dadk_config::common::task::{BuildConfig, CleanConfig, Dependency, InstallConfig};
+
+use super::*;
+use crate::{
+    executor::source::LocalSource,
+    parser::task::{CodeSource, TaskType},
+};
+
+fn task(name: &str, src: &str, depends: &[&str]) -> (PathBuf, DADKTask) {
+    let task = DADKTask::new(
+        name.to_string(),
+        "0.1.0".to_string(),
+        String::new(),
+        TaskType::BuildFromSource(CodeSource::Local(LocalSource::new(PathBuf::from(src)))),
+        depends
+            .iter()
+            .map(|d| Dependency::new(d.to_string(), "0.1.0".to_string()))
+            .collect(),
+        BuildConfig::new(Some("make".to_string()), None, None),
+        InstallConfig::new(None),
+        CleanConfig::new(None),
+        None,
+        false,
+        false,
+        None,
+    );
+    (PathBuf::from(format!("/config/{}.toml", name)), task)
+}
+
+fn tasks() -> Vec<(PathBuf, DADKTask)> {
+    vec![
+        task("libc", "/src/libc", &[]),
+        task("libfoo", "/src/libfoo", &["libc"]),
+        task("app", "/src/app", &["libfoo"]),
+        task("other", "/src/other", &[]),
+    ]
+}
+
+fn keys(names: &[&str]) -> BTreeSet<TaskKey> {
+    names
+        .iter()
+        .map(|n| (n.to_string(), "0.1.0".to_string()))
+        .collect()
+}
+
+#[test]
+fn affected_tasks_include_dependents() {
+    let changed = [PathBuf::from("/src/libc/string.c")];
+    assert_eq!(
+        affected_tasks(&tasks(), &changed),
+        keys(&["libc", "libfoo", "app"])
+    );
+}
+
+#[test]
+fn affected_tasks_by_config_file() {
+    let changed = [PathBuf::from("/config/app.toml")];
+    assert_eq!(affected_tasks(&tasks(), &changed), keys(&["app"]));
+}
+
+#[test]
+fn select_with_dependencies() {
+    let selected = select(tasks(), &["libfoo@0.1.0".to_string()]).unwrap();
+    let selected: BTreeSet<TaskKey> = selected.iter().map(|(_, t)| key(t)).collect();
+    assert_eq!(selected, keys(&["libc", "libfoo"]));
+
+    let err = select(tasks(), &["missing".to_string()]).unwrap_err();
+    assert!(err.contains("Task missing not found"), "{}", err);
+}
+
+#[test]
+fn affected_tasks_unrelated_path() {
+    let changed = [PathBuf::from("/src/libcx/main.c")];
+    assert!(affected_tasks(&tasks(), &changed).is_empty());
+}
+
+#[test]
+fn affected_tasks_relative_path() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("libc")).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    let src = dir.join("libc").to_string_lossy().to_string();
+    let tasks = vec![task("libc", &src, &[])];
+
+    // 相对于当前工作目录，文件已经被删除时也能找到所属的任务
+    let changed = [pathdiff(&dir.join("libc/removed/file.c"), &cwd)];
+    assert_eq!(affected_tasks(&tasks, &changed), keys(&["libc"]));
+}
+
+/// 从`base`到`path`的相对路径（两者都是绝对路径）
+fn pathdiff(path: &Path, base: &Path) -> PathBuf {
+    let common = base
+        .ancestors()
+        .find(|a| path.starts_with(a))
+        .expect("no common ancestor");
+    let ups = base.strip_prefix(common).unwrap().components().count();
+    let mut rel: PathBuf = std::iter::repeat("..").take(ups).collect();
+    rel.push(path.strip_prefix(common).unwrap());
+    rel
+}
+
+#[test]
+fn with_dependencies_keeps_transitive_dependencies() {
+    let selected = keys(&["app"]);
+    let kept: BTreeSet<TaskKey> = with_dependencies(tasks(), &selected)
+        .iter()
+        .map(|(_, t)| key(t))
+        .collect();
+    assert_eq!(kept, keys(&["libc", "libfoo", "app"]));
+}
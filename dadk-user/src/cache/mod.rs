@@ -0,0 +1,572 @@
+//! # 缓存目录的统计与清理
+//!
+//! 缓存根目录下，每个任务的每个版本都有各自的构建缓存、源码缓存、任务数据以及暂存目录
+//! （`<cache_root>/{build,source,task_data,staging}/<name_version>`）。
+//! 任务升级版本后，旧版本的目录不会被自动删除，缓存根目录会不断增长。
+//!
+//! 清理以任务的版本为单位，同时删除它的所有目录。可以组合使用以下策略：
+//!
+//! - `older_than`：删除超过指定时间没有使用过的版本
+//! - `keep_latest`：每个任务只保留最近使用过的若干个版本
+//! - `max_size`：按照最近最少使用的顺序删除，直到缓存的总大小不超过指定值
+//!
+//! 当前配置文件中的任务版本不会被删除。
+//!
+//! [`disk_usage`]统计每个任务版本的源码缓存、构建缓存以及安装到sysroot中的文件所占用的空间。
+//!
+//! [`CleanPlan`]列出`dadk user clean`在每个清理级别下将要执行的清理命令、删除的目录以及可以释放的空间，
+//! 供`dadk user clean --dry-run`在清理之前预览。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use dadk_config::user::UserCleanLevel;
+
+use crate::{
+    executor::cache::{CacheDir, CacheDirType, TaskDataDir},
+    parser::{
+        task::{CodeSource, DADKTask, TaskType, NAME_VERSION_REPLACE_TABLE},
+        task_log::TaskLog,
+    },
+    pkgdb::PackageDatabase,
+    utils::file::FileUtils,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// # 缓存根目录中的一个任务版本
+#[derive(Debug, Clone)]
+pub struct CachedTask {
+    /// 缓存目录名
+    pub name_version: String,
+    /// 任务名称，无法确定时为None
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// 存在的缓存目录及其大小
+    pub dirs: Vec<(CacheDirType, PathBuf, u64)>,
+    /// 最近一次使用的时间
+    pub last_used: SystemTime,
+}
+
+impl CachedTask {
+    pub fn size(&self) -> u64 {
+        self.dirs.iter().map(|(_, _, size)| size).sum()
+    }
+
+    /// 指定类型的缓存目录的大小，目录不存在时为0
+    pub fn dir_size(&self, cache_type: CacheDirType) -> u64 {
+        self.dirs
+            .iter()
+            .filter(|(t, _, _)| *t == cache_type)
+            .map(|(_, _, size)| size)
+            .sum()
+    }
+
+    /// 任务名称，无法确定时使用缓存目录名
+    pub fn group(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.name_version)
+    }
+
+    /// 删除这个任务版本的所有缓存目录
+    pub fn remove(&self) -> Result<(), String> {
+        for (_, path, _) in &self.dirs {
+            std::fs::remove_dir_all(path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// 遍历缓存根目录，返回其中所有的任务版本（按缓存目录名排序）
+///
+/// `known_tasks`为当前配置文件中的任务，用于确定旧的任务日志中没有记录名称的任务版本属于哪个任务
+pub fn scan(cache_root: &Path, known_tasks: &[DADKTask]) -> Result<Vec<CachedTask>, String> {
+    let mut dirs: BTreeMap<String, Vec<(CacheDirType, PathBuf, u64)>> = BTreeMap::new();
+    for cache_type in CacheDirType::ALL {
+        let parent = cache_root.join(cache_type.dir_name());
+        if !parent.is_dir() {
+            continue;
+        }
+        let entries = std::fs::read_dir(&parent)
+            .map_err(|e| format!("Failed to read {}: {}", parent.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read {}: {}", parent.display(), e))?
+                .path();
+            if !path.is_dir() {
+                continue;
+            }
+            let size = FileUtils::dir_size(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let name_version = path.file_name().unwrap().to_string_lossy().to_string();
+            dirs.entry(name_version)
+                .or_default()
+                .push((cache_type, path, size));
+        }
+    }
+
+    Ok(dirs
+        .into_iter()
+        .map(|(name_version, dirs)| {
+            let task_log = load_task_log(&dirs);
+            let (name, version) = task_name_version(&name_version, task_log.as_ref(), known_tasks);
+            let last_used = last_used(task_log.as_ref(), &dirs);
+            CachedTask {
+                name_version,
+                name,
+                version,
+                dirs,
+                last_used,
+            }
+        })
+        .collect())
+}
+
+fn load_task_log(dirs: &[(CacheDirType, PathBuf, u64)]) -> Option<TaskLog> {
+    let (_, path, _) = dirs
+        .iter()
+        .find(|(t, _, _)| matches!(t, CacheDirType::TaskData))?;
+    let content = std::fs::read_to_string(path.join(TaskDataDir::TASK_LOG_FILE_NAME)).ok()?;
+    toml::from_str(&content).ok()
+}
+
+/// 确定缓存目录属于哪个任务：优先使用任务日志中的记录，其次根据当前配置文件中的任务推断
+fn task_name_version(
+    name_version: &str,
+    task_log: Option<&TaskLog>,
+    known_tasks: &[DADKTask],
+) -> (Option<String>, Option<String>) {
+    if let Some(name) = task_log.and_then(|l| l.task_name()) {
+        let version = task_log.and_then(|l| l.task_version()).map(String::from);
+        return (Some(name.to_string()), version);
+    }
+    if let Some(task) = known_tasks
+        .iter()
+        .find(|t| t.name_version() == name_version)
+    {
+        return (Some(task.name.clone()), Some(task.version.clone()));
+    }
+    // 同一个任务的其他版本：目录名以替换了特殊字符的任务名称加`_`开头。取最长的匹配
+    let name = known_tasks
+        .iter()
+        .map(|t| &t.name)
+        .filter(|name| name_version.starts_with(&format!("{}_", replace_special_chars(name))))
+        .max_by_key(|name| name.len())
+        .cloned();
+    (name, None)
+}
+
+fn replace_special_chars(s: &str) -> String {
+    let mut s = s.to_string();
+    for (src, dst) in &NAME_VERSION_REPLACE_TABLE {
+        s = s.replace(src, dst);
+    }
+    s
+}
+
+/// 最近一次使用的时间：任务日志中的构建、安装时间，以及缓存目录的修改时间中最晚的一个
+fn last_used(task_log: Option<&TaskLog>, dirs: &[(CacheDirType, PathBuf, u64)]) -> SystemTime {
+    let logged = task_log.into_iter().flat_map(|l| {
+        [l.build_time(), l.install_time()]
+            .into_iter()
+            .flatten()
+            .map(|t| SystemTime::from(*t))
+    });
+    let modified = dirs
+        .iter()
+        .filter_map(|(_, path, _)| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+    logged
+        .chain(modified)
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// # 清理策略
+#[derive(Debug, Clone, Default)]
+pub struct GcPolicy {
+    /// 每个任务保留最近使用过的版本数
+    pub keep_latest: Option<usize>,
+    /// 删除超过此时间没有使用过的版本
+    pub older_than: Option<Duration>,
+    /// 缓存的最大总大小（字节）
+    pub max_size: Option<u64>,
+}
+
+impl GcPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_latest.is_none() && self.older_than.is_none() && self.max_size.is_none()
+    }
+}
+
+/// # 清理计划
+#[derive(Debug, Default)]
+pub struct GcPlan {
+    /// 需要删除的任务版本
+    pub remove: Vec<CachedTask>,
+    /// 保留的任务版本
+    pub keep: Vec<CachedTask>,
+}
+
+impl GcPlan {
+    /// 可以释放的空间（字节）
+    pub fn freed(&self) -> u64 {
+        self.remove.iter().map(|t| t.size()).sum()
+    }
+
+    /// 清理后剩余的大小（字节）
+    pub fn remaining(&self) -> u64 {
+        self.keep.iter().map(|t| t.size()).sum()
+    }
+}
+
+/// 根据清理策略，确定需要删除的任务版本
+///
+/// `in_use`为当前配置文件中的任务的缓存目录名，这些任务版本不会被删除
+pub fn plan_gc(
+    tasks: Vec<CachedTask>,
+    policy: &GcPolicy,
+    in_use: &BTreeSet<String>,
+    now: SystemTime,
+) -> GcPlan {
+    let mut remove = Vec::new();
+    let mut keep = Vec::new();
+    let mut candidates = Vec::new();
+    for task in tasks {
+        if in_use.contains(&task.name_version) {
+            keep.push(task);
+        } else {
+            candidates.push(task);
+        }
+    }
+    // 最近使用过的在前
+    candidates.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+
+    // 每个任务已经保留的版本数（包括正在使用的版本）
+    let mut kept: BTreeMap<String, usize> = BTreeMap::new();
+    for task in &keep {
+        *kept.entry(task.group().to_string()).or_default() += 1;
+    }
+    let mut survivors = Vec::new();
+    for task in candidates {
+        let too_old = policy
+            .older_than
+            .is_some_and(|d| now.duration_since(task.last_used).unwrap_or_default() > d);
+        let count = kept.entry(task.group().to_string()).or_default();
+        let too_many = policy.keep_latest.is_some_and(|n| *count >= n);
+        if too_old || too_many {
+            remove.push(task);
+        } else {
+            *count += 1;
+            survivors.push(task);
+        }
+    }
+
+    // 超过最大大小时，从最近最少使用的版本开始删除
+    if let Some(max_size) = policy.max_size {
+        let mut total: u64 = keep.iter().chain(&survivors).map(|t| t.size()).sum();
+        while total > max_size {
+            let Some(task) = survivors.pop() else {
+                break;
+            };
+            total -= task.size();
+            remove.push(task);
+        }
+    }
+
+    keep.extend(survivors);
+    keep.sort_by(|a, b| a.name_version.cmp(&b.name_version));
+    GcPlan { remove, keep }
+}
+
+/// # 一个任务版本占用的空间
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TaskDiskUsage {
+    /// 任务名称，无法确定时为缓存目录名
+    pub name: String,
+    pub version: Option<String>,
+    /// 缓存目录名。只在sysroot中安装过、缓存已被删除的任务为None
+    pub cache_dir: Option<String>,
+    /// 源码缓存（字节）
+    pub source: u64,
+    /// 构建缓存，包括暂存目录以及任务数据（字节）
+    pub build: u64,
+    /// 安装到sysroot中的文件（字节）
+    pub installed: u64,
+    /// 以上三者之和（字节）
+    pub total: u64,
+}
+
+/// 统计每个任务版本占用的空间，按总大小从大到小排序
+///
+/// 安装的文件来自sysroot中的软件包数据库，只计入与数据库中记录的版本相同的任务版本
+pub fn disk_usage(
+    tasks: &[CachedTask],
+    db: &PackageDatabase,
+    sysroot: &Path,
+) -> Vec<TaskDiskUsage> {
+    let mut usages: Vec<TaskDiskUsage> = tasks
+        .iter()
+        .map(|task| TaskDiskUsage {
+            name: task.group().to_string(),
+            version: task.version.clone(),
+            cache_dir: Some(task.name_version.clone()),
+            source: task.dir_size(CacheDirType::Source),
+            build: task.size() - task.dir_size(CacheDirType::Source),
+            ..Default::default()
+        })
+        .collect();
+
+    for package in db.packages() {
+        let installed = package
+            .files
+            .iter()
+            .filter_map(|f| std::fs::symlink_metadata(sysroot.join(f.trim_start_matches('/'))).ok())
+            .map(|m| m.len())
+            .sum();
+        match usages.iter_mut().find(|u| {
+            u.name == package.name && u.version.as_deref() == Some(package.version.as_str())
+        }) {
+            Some(usage) => usage.installed = installed,
+            None => usages.push(TaskDiskUsage {
+                name: package.name.clone(),
+                version: Some(package.version.clone()),
+                installed,
+                ..Default::default()
+            }),
+        }
+    }
+
+    for usage in &mut usages {
+        usage.total = usage.source + usage.build + usage.installed;
+    }
+    usages.sort_by(|a, b| b.total.cmp(&a.total).then(a.name.cmp(&b.name)));
+    usages
+}
+
+/// 生成文本格式的空间占用报告
+pub fn disk_usage_table(usages: &[TaskDiskUsage]) -> String {
+    if usages.is_empty() {
+        return "No task cached or installed.\n".to_string();
+    }
+    let mut rows: Vec<[String; 6]> = usages
+        .iter()
+        .map(|u| {
+            [
+                u.name.clone(),
+                u.version.clone().unwrap_or_else(|| "-".to_string()),
+                format_size(u.source),
+                format_size(u.build),
+                format_size(u.installed),
+                format_size(u.total),
+            ]
+        })
+        .collect();
+    let sum = |f: fn(&TaskDiskUsage) -> u64| format_size(usages.iter().map(f).sum());
+    rows.push([
+        "TOTAL".to_string(),
+        String::new(),
+        sum(|u| u.source),
+        sum(|u| u.build),
+        sum(|u| u.installed),
+        sum(|u| u.total),
+    ]);
+    let header = ["NAME", "VERSION", "SOURCE", "BUILD", "INSTALLED", "TOTAL"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in std::iter::once(header.map(String::from)).chain(rows) {
+        // 名称和版本左对齐，大小右对齐
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| match i {
+                0 | 1 => format!("{:<width$}", cell, width = width),
+                _ => format!("{:>width$}", cell, width = width),
+            })
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// # `dadk user clean`在一个任务上将要执行的清理
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskCleanPlan {
+    /// 任务名称和版本，例如`hello-0.1.0`
+    pub label: String,
+    /// 在源文件目录中执行的清理命令（`in-src`、`all`级别），以及执行命令的目录
+    pub clean_command: Option<(String, PathBuf)>,
+    /// 将要删除的目录（`output`、`all`级别）：构建缓存、暂存目录以及源码缓存，和它们的大小（字节）
+    pub dirs: Vec<(CacheDirType, PathBuf, u64)>,
+    /// 源码缓存是否与其他任务共用（设置了`source-cache-key`）
+    pub shared_source: bool,
+}
+
+/// # `dadk user clean --dry-run`的清理计划
+#[derive(Debug, Default)]
+pub struct CleanPlan {
+    pub tasks: Vec<TaskCleanPlan>,
+}
+
+impl CleanPlan {
+    /// 确定每个任务在各个清理级别下会执行的清理命令以及删除的目录（只统计已经存在的目录）
+    pub fn new(cache_root: &Path, tasks: &[DADKTask]) -> Result<Self, String> {
+        let mut plans = Vec::new();
+        for task in tasks {
+            let source_cache = matches!(
+                task.task_type,
+                TaskType::BuildFromSource(CodeSource::Git(_) | CodeSource::Archive(_))
+            );
+            let source_dir = CacheDir::get_path(cache_root, task, CacheDirType::Source);
+            let clean_command = task
+                .clean
+                .clean_command
+                .clone()
+                .filter(|c| !c.is_empty())
+                .map(|c| (c, task.source_path().unwrap_or_else(|| source_dir.clone())));
+
+            let mut cache_types = vec![CacheDirType::Build, CacheDirType::Staging];
+            if source_cache {
+                cache_types.push(CacheDirType::Source);
+            }
+            let mut dirs = Vec::new();
+            for cache_type in cache_types {
+                let path = CacheDir::get_path(cache_root, task, cache_type);
+                if !path.is_dir() {
+                    continue;
+                }
+                let size = FileUtils::dir_size(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                dirs.push((cache_type, path, size));
+            }
+            plans.push(TaskCleanPlan {
+                label: format!("{}-{}", task.name, task.version),
+                clean_command,
+                dirs,
+                shared_source: source_cache && task.source_cache_key.is_some(),
+            });
+        }
+        plans.sort_by(|a, b| a.label.cmp(&b.label));
+        Ok(Self { tasks: plans })
+    }
+
+    /// 清理后释放的空间（字节）。共用的源码缓存只计算一次；清理命令释放的空间无法预先得知，不计入
+    pub fn freed(&self, level: UserCleanLevel) -> u64 {
+        if level == UserCleanLevel::InSrc {
+            return 0;
+        }
+        let mut seen = BTreeSet::new();
+        self.tasks
+            .iter()
+            .flat_map(|t| &t.dirs)
+            .filter(|(_, path, _)| seen.insert(path))
+            .map(|(_, _, size)| size)
+            .sum()
+    }
+
+    /// 生成文本格式的清理计划：每个任务在`level`级别下的清理内容，以及各个级别可以释放的空间
+    pub fn text(&self, level: UserCleanLevel) -> String {
+        let mut out = String::new();
+        for task in &self.tasks {
+            let command = task
+                .clean_command
+                .as_ref()
+                .filter(|_| level != UserCleanLevel::Output);
+            let dirs: &[_] = match level {
+                UserCleanLevel::InSrc => &[],
+                _ => &task.dirs,
+            };
+            out.push_str(&task.label);
+            out.push('\n');
+            if command.is_none() && dirs.is_empty() {
+                out.push_str("  nothing to clean\n");
+                continue;
+            }
+            if let Some((command, dir)) = command {
+                out.push_str(&format!("  run `{}` in {}\n", command, dir.display()));
+            }
+            for (cache_type, path, size) in dirs {
+                let shared = match cache_type {
+                    CacheDirType::Source if task.shared_source => " (shared)",
+                    _ => "",
+                };
+                out.push_str(&format!(
+                    "  remove {:>10}  {}{}\n",
+                    format_size(*size),
+                    path.display(),
+                    shared
+                ));
+            }
+        }
+
+        out.push('\n');
+        for l in [
+            UserCleanLevel::InSrc,
+            UserCleanLevel::Output,
+            UserCleanLevel::All,
+        ] {
+            let commands = match l {
+                UserCleanLevel::Output => 0,
+                _ => self
+                    .tasks
+                    .iter()
+                    .filter(|t| t.clean_command.is_some())
+                    .count(),
+            };
+            let mut line = format!(
+                "{} {:<6}  {:>10} will be freed",
+                if l == level { "*" } else { " " },
+                clean_level_name(l),
+                format_size(self.freed(l))
+            );
+            if commands > 0 {
+                line.push_str(&format!(", {} clean command(s) will run", commands));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// 清理级别在命令行中的名称
+fn clean_level_name(level: UserCleanLevel) -> &'static str {
+    match level {
+        UserCleanLevel::All => "all",
+        UserCleanLevel::InSrc => "in-src",
+        UserCleanLevel::Output => "output",
+    }
+}
+
+/// 解析大小，例如`50G`、`512M`、`1.5T`、`4096`（单位为1024的幂，不区分大小写，可以带`B`/`iB`后缀）
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    dadk_config::common::size::parse_size(s).map_err(|e| e.to_string())
+}
+
+/// 以便于阅读的形式输出大小，例如`1.5 GiB`
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
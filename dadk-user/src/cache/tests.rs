@@ -0,0 +1,316 @@
+use chrono::{TimeZone, Utc};
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::{executor::source::GitSource, parser::Parser, pkgdb::InstalledPackage};
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn at(days: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + days * DAY)
+}
+
+fn cached(name: &str, version: &str, size: u64, last_used: u64) -> CachedTask {
+    let name_version = format!("{}_{}", name, version.replace('.', "_"));
+    CachedTask {
+        dirs: vec![(
+            CacheDirType::Build,
+            PathBuf::from(format!("build/{}", name_version)),
+            size,
+        )],
+        name_version,
+        name: Some(name.to_string()),
+        version: Some(version.to_string()),
+        last_used: at(last_used),
+    }
+}
+
+fn removed(plan: &GcPlan) -> Vec<&str> {
+    let mut names: Vec<&str> = plan
+        .remove
+        .iter()
+        .map(|t| t.name_version.as_str())
+        .collect();
+    names.sort();
+    names
+}
+
+/// libc有三个版本，其中0.3.0为当前配置文件中的版本；app只有一个版本
+fn tasks() -> Vec<CachedTask> {
+    vec![
+        cached("libc", "0.1.0", 100, 0),
+        cached("libc", "0.2.0", 200, 20),
+        cached("libc", "0.3.0", 300, 10),
+        cached("app", "0.1.0", 400, 5),
+    ]
+}
+
+fn in_use() -> BTreeSet<String> {
+    BTreeSet::from(["libc_0_3_0".to_string()])
+}
+
+#[test]
+fn test_keep_latest() {
+    let policy = GcPolicy {
+        keep_latest: Some(2),
+        ..Default::default()
+    };
+    let plan = plan_gc(tasks(), &policy, &in_use(), at(30));
+    // 正在使用的版本计入保留数，其余按最近使用时间保留
+    assert_eq!(removed(&plan), vec!["libc_0_1_0"]);
+    assert_eq!(plan.freed(), 100);
+    assert_eq!(plan.remaining(), 900);
+
+    let policy = GcPolicy {
+        keep_latest: Some(0),
+        ..Default::default()
+    };
+    let plan = plan_gc(tasks(), &policy, &in_use(), at(30));
+    assert_eq!(
+        removed(&plan),
+        vec!["app_0_1_0", "libc_0_1_0", "libc_0_2_0"]
+    );
+}
+
+#[test]
+fn test_older_than() {
+    let policy = GcPolicy {
+        older_than: Some(Duration::from_secs(15 * DAY)),
+        ..Default::default()
+    };
+    let plan = plan_gc(tasks(), &policy, &in_use(), at(30));
+    // libc 0.3.0虽然超过了时间，但是正在使用
+    assert_eq!(removed(&plan), vec!["app_0_1_0", "libc_0_1_0"]);
+}
+
+#[test]
+fn test_max_size() {
+    let policy = GcPolicy {
+        max_size: Some(600),
+        ..Default::default()
+    };
+    let plan = plan_gc(tasks(), &policy, &in_use(), at(30));
+    // 从最近最少使用的版本开始删除，直到不超过最大大小
+    assert_eq!(removed(&plan), vec!["app_0_1_0", "libc_0_1_0"]);
+    assert_eq!(plan.remaining(), 500);
+
+    // 正在使用的版本本身超过了最大大小
+    let policy = GcPolicy {
+        max_size: Some(0),
+        ..Default::default()
+    };
+    let plan = plan_gc(tasks(), &policy, &in_use(), at(30));
+    assert_eq!(plan.keep.len(), 1);
+    assert_eq!(plan.keep[0].name_version, "libc_0_3_0");
+}
+
+#[test]
+fn test_scan_and_remove() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let task_data = root.join("task_data").join("libc_0_1_0");
+    std::fs::create_dir_all(&task_data).unwrap();
+    let mut log = TaskLog::new();
+    log.set_task("libc", "0.1.0");
+    log.set_build_time(Utc.timestamp_opt(1_000_000_000, 0).unwrap());
+    std::fs::write(
+        task_data.join(TaskDataDir::TASK_LOG_FILE_NAME),
+        toml::to_string(&log).unwrap(),
+    )
+    .unwrap();
+    let build = root.join("build").join("libc_0_1_0");
+    std::fs::create_dir_all(build.join("lib")).unwrap();
+    std::fs::write(build.join("lib").join("libc.a"), [0u8; 1000]).unwrap();
+    // 没有任务日志的缓存目录，以及缓存根目录下的其他文件
+    std::fs::create_dir_all(root.join("source").join("unknown_0_1_0")).unwrap();
+    std::fs::write(root.join("build").join("README"), "").unwrap();
+
+    let tasks = scan(root, &[]).unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0].name_version, "libc_0_1_0");
+    assert_eq!(tasks[0].name.as_deref(), Some("libc"));
+    assert_eq!(tasks[0].version.as_deref(), Some("0.1.0"));
+    assert_eq!(tasks[0].dirs.len(), 2);
+    assert!(tasks[0].size() >= 1000);
+    assert!(tasks[0].last_used > SystemTime::from(*log.build_time().unwrap()));
+    assert_eq!(tasks[1].name, None);
+    assert_eq!(tasks[1].group(), "unknown_0_1_0");
+
+    tasks[0].remove().unwrap();
+    assert!(!build.exists());
+    assert!(!task_data.exists());
+    assert_eq!(scan(root, &[]).unwrap().len(), 1);
+
+    assert!(scan(&root.join("missing"), &[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_disk_usage() {
+    let tmp = tempfile::tempdir().unwrap();
+    let sysroot = tmp.path();
+    std::fs::create_dir_all(sysroot.join("bin")).unwrap();
+    std::fs::write(sysroot.join("bin/app"), [0u8; 2000]).unwrap();
+    std::fs::write(sysroot.join("bin/old"), [0u8; 50]).unwrap();
+
+    let mut libc = cached("libc", "0.1.0", 100, 0);
+    libc.dirs.push((
+        CacheDirType::Source,
+        PathBuf::from("source/libc_0_1_0"),
+        300,
+    ));
+    let tasks = vec![libc, cached("app", "0.1.0", 10, 0)];
+    let mut db = PackageDatabase::default();
+    db.record(InstalledPackage::new(
+        "app".to_string(),
+        "0.1.0".to_string(),
+        vec!["/bin/app".to_string(), "/bin/missing".to_string()],
+    ));
+    // 缓存已被删除的任务
+    db.record(InstalledPackage::new(
+        "old".to_string(),
+        "0.2.0".to_string(),
+        vec!["/bin/old".to_string()],
+    ));
+
+    let usages = disk_usage(&tasks, &db, sysroot);
+    let summary: Vec<(&str, u64, u64, u64)> = usages
+        .iter()
+        .map(|u| (u.name.as_str(), u.source, u.build, u.installed))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("app", 0, 10, 2000),
+            ("libc", 300, 100, 0),
+            ("old", 0, 0, 50)
+        ]
+    );
+    assert_eq!(usages[2].cache_dir, None);
+    assert_eq!(usages[0].total, 2010);
+
+    let table = disk_usage_table(&usages);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(
+        lines[0],
+        "NAME   VERSION  SOURCE  BUILD  INSTALLED    TOTAL"
+    );
+    assert_eq!(
+        lines[1],
+        "app    0.1.0       0 B   10 B    2.0 KiB  2.0 KiB"
+    );
+    assert_eq!(
+        lines[4],
+        "TOTAL            300 B  110 B    2.0 KiB  2.4 KiB"
+    );
+    assert_eq!(disk_usage_table(&[]), "No task cached or installed.\n");
+}
+
+#[test]
+fn test_parse_and_format_size() {
+    assert_eq!(parse_size("4096").unwrap(), 4096);
+    assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+    assert_eq!(parse_size("50G").unwrap(), 50 << 30);
+    assert_eq!(parse_size("50gb").unwrap(), 50 << 30);
+    assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+    assert!(parse_size("").is_err());
+    assert!(parse_size("10X").is_err());
+    assert!(parse_size("-1G").is_err());
+
+    assert_eq!(format_size(0), "0 B");
+    assert_eq!(format_size(1023), "1023 B");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(50 << 30), "50.0 GiB");
+}
+
+/// 各个清理级别将要执行的清理命令、删除的目录以及释放的空间
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn test_clean_plan(ctx: &BaseGlobalTestContext) {
+    let local = Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+        .unwrap();
+    let mut git = local.clone();
+    git.name = "git-app".to_string();
+    git.task_type = TaskType::BuildFromSource(CodeSource::Git(GitSource::new(
+        "https://example.com/app.git".to_string(),
+        None,
+        None,
+    )));
+    git.source_cache_key = Some("app".to_string());
+    git.clean.clean_command = Some("make clean".to_string());
+    let mut other = git.clone();
+    other.name = "git-other".to_string();
+    other.clean.clean_command = None;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let tasks = [local.clone(), git.clone(), other];
+    for (task, cache_type, size) in [
+        (&local, CacheDirType::Build, 100),
+        (&git, CacheDirType::Build, 200),
+        (&git, CacheDirType::Staging, 10),
+        (&git, CacheDirType::Source, 1000),
+    ] {
+        let dir = CacheDir::get_path(root, task, cache_type);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("file"), vec![0u8; size]).unwrap();
+    }
+
+    let plan = CleanPlan::new(root, &tasks).unwrap();
+    let labels: Vec<&str> = plan.tasks.iter().map(|t| t.label.as_str()).collect();
+    assert_eq!(
+        labels,
+        vec![
+            format!("{}-{}", local.name, local.version).as_str(),
+            "git-app-0.2.0",
+            "git-other-0.2.0"
+        ]
+    );
+    // 本地源文件没有源码缓存；清理命令为空时不执行
+    assert_eq!(plan.tasks[0].clean_command, None);
+    assert_eq!(plan.tasks[0].dirs.len(), 1);
+    assert_eq!(
+        plan.tasks[1].clean_command,
+        Some((
+            "make clean".to_string(),
+            CacheDir::get_path(root, &git, CacheDirType::Source)
+        ))
+    );
+    assert_eq!(plan.tasks[1].dirs.len(), 3);
+    assert!(plan.tasks[2].shared_source);
+    // 共用的源码缓存只计算一次
+    assert_eq!(plan.freed(UserCleanLevel::InSrc), 0);
+    assert_eq!(plan.freed(UserCleanLevel::Output), 1310);
+    assert_eq!(plan.freed(UserCleanLevel::All), 1310);
+
+    let text = plan.text(UserCleanLevel::InSrc);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[1], "  nothing to clean");
+    assert_eq!(
+        lines[3],
+        format!(
+            "  run `make clean` in {}",
+            CacheDir::get_path(root, &git, CacheDirType::Source).display()
+        )
+    );
+    assert_eq!(lines[5], "  nothing to clean");
+    assert_eq!(
+        &lines[7..],
+        [
+            "* in-src         0 B will be freed, 1 clean command(s) will run",
+            "  output     1.3 KiB will be freed",
+            "  all        1.3 KiB will be freed, 1 clean command(s) will run",
+        ]
+    );
+
+    let text = plan.text(UserCleanLevel::Output);
+    assert!(!text.contains("make clean"));
+    assert!(text.contains(&format!(
+        "  remove     1000 B  {} (shared)\n",
+        CacheDir::get_path(root, &git, CacheDirType::Source).display()
+    )));
+    assert!(text.contains("* output"));
+}
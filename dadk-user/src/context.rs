@@ -1,16 +1,31 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
     path::PathBuf,
-    process::exit,
-    sync::{Arc, Mutex, Weak},
+    sync::{Arc, Mutex, RwLock, Weak},
 };
 
-use dadk_config::{common::target_arch::TargetArch, user::UserCleanLevel};
+use chrono::{DateTime, Utc};
+use dadk_config::{
+    app_blocklist::AppBlocklistConfigFile,
+    common::{target_arch::TargetArch, task::BuildShell},
+    manifest::{CompilerCacheConfig, ContainerConfig, EnvConfig, KernelConfig, MirrorConfig},
+    user::UserCleanLevel,
+};
 use derive_builder::Builder;
-use log::error;
+use log::info;
 #[cfg(test)]
 use test_base::{global::BaseGlobalTestContext, test_context::TestContext};
 
-use crate::{executor::cache::cache_root_init, scheduler::task_deque::TASK_DEQUE};
+use crate::{
+    executor::{
+        cache::cache_root_init, resources::CgroupLease, source::GitTimeouts, EnvMap, ExecutorError,
+    },
+    interrupt::InterruptState,
+    lock::LockTimeout,
+    metrics::{MetricsFormat, RunMetrics, TaskMetrics},
+    scheduler::install_paths::InstallClaims,
+    utils::lazy_init::Lazy,
+};
 
 #[derive(Debug, Builder)]
 #[builder(setter(into))]
@@ -19,6 +34,9 @@ pub struct DadkUserExecuteContext {
     sysroot_dir: Option<PathBuf>,
     /// DADK任务配置文件所在目录
     config_dir: Option<PathBuf>,
+    /// 额外的任务配置文件目录，后面的目录中的任务覆盖前面的目录中同名同版本的任务
+    #[builder(default)]
+    overlay_config_dirs: Vec<PathBuf>,
     /// 要执行的操作
     action: Action,
     /// 并行线程数量
@@ -30,41 +48,239 @@ pub struct DadkUserExecuteContext {
     #[builder(default = "crate::DADKTask::default_target_arch()")]
     target_arch: TargetArch,
 
+    /// 需要忽略构建缓存、强制重新构建的任务（名称或者`name@version`）
+    #[builder(default)]
+    rebuild_tasks: Vec<String>,
+
+    /// 只执行这些任务（名称或者`name@version`）以及它们依赖的任务。为空时执行所有任务
+    #[builder(default)]
+    selected_tasks: Vec<String>,
+
+    /// 发生变更的文件。不为空时只构建受这些文件影响的任务（以及它们依赖的任务）
+    #[builder(default)]
+    affected_by: Vec<PathBuf>,
+
+    /// 忽略`build_once`、`install_once`以及输入文件的修改时间，强制重新构建、安装所有任务
+    #[builder(default)]
+    force: bool,
+
+    /// 不使用上次构建的结果：构建前清空任务的构建缓存目录
+    #[builder(default)]
+    no_build_cache: bool,
+
+    /// 忽略已经下载的在线压缩包，重新下载并构建使用在线压缩包的任务
+    #[builder(default)]
+    refetch: bool,
+
+    /// 在容器中执行构建命令（为None时在主机上执行）
+    #[builder(default)]
+    container: Option<ContainerConfig>,
+
+    /// 跳过上次被中断的执行中已经完成的任务
+    #[builder(default)]
+    resume: bool,
+
+    /// 拉取源文件失败时的默认重试次数
+    #[builder(default)]
+    retries: u32,
+
+    /// 离线模式，禁止访问网络：只使用源码缓存，cargo任务使用`--offline`构建
+    #[builder(default)]
+    offline: bool,
+
+    /// 使用硬链接把本地预编译文件放入构建缓存目录
+    #[builder(default)]
+    hardlink_prebuilt: bool,
+
+    /// 用户程序黑名单
+    #[builder(default)]
+    app_blocklist: AppBlocklistConfigFile,
+
+    /// 跳过无法解析的用户程序配置文件
+    #[builder(default)]
+    skip_invalid_configs: bool,
+
+    /// manifest中定义的、配置文件中可以引用的变量
+    #[builder(default)]
+    variables: BTreeMap<String, String>,
+
+    /// 同一个程序有多个版本时，每个程序的默认版本
+    #[builder(default)]
+    default_versions: BTreeMap<String, String>,
+
+    /// 软件仓库索引的URL
+    #[builder(default)]
+    package_repository: Option<String>,
+
+    /// 构建cargo任务使用的Rust工具链（源码中没有`rust-toolchain.toml`时使用）
+    #[builder(default)]
+    rust_toolchain: Option<String>,
+
+    /// 编译缓存（ccache/sccache）的配置
+    #[builder(default)]
+    compiler_cache: CompilerCacheConfig,
+
+    /// 传给构建命令的主机环境变量，以及为所有构建命令设置的环境变量
+    #[builder(default)]
+    env_config: EnvConfig,
+
+    /// 内核模块任务依赖的内核（manifest中的`[kernel]`）
+    #[builder(default)]
+    kernel: Option<KernelConfig>,
+
+    /// 拉取源码时git命令的超时设置（manifest中的`[git]`）
+    #[builder(default)]
+    git_timeouts: GitTimeouts,
+
+    /// 拉取源文件时的URL重写规则（manifest中的`[mirrors]`）
+    #[builder(default)]
+    mirrors: MirrorConfig,
+
+    /// 执行构建、清理命令的shell
+    #[builder(default)]
+    shell: BuildShell,
+
+    /// 在构建、清理命令前加上`set -euo pipefail`
+    #[builder(default)]
+    shell_strict: bool,
+
+    /// 允许用户程序安装文件的目录（`rootfs.toml`中的`install.allowed_paths`），为空时不限制
+    #[builder(default)]
+    install_allowed_paths: Vec<PathBuf>,
+
+    /// 安装时DragonOS sysroot目录不存在则创建
+    #[builder(default)]
+    create_sysroot: bool,
+
+    /// 不同任务导出的环境变量名称冲突时只输出警告，而不是报错
+    #[builder(default)]
+    allow_env_collisions: bool,
+
+    /// 执行每条命令之前，输出命令行、工作目录以及DADK设置的环境变量
+    #[builder(default)]
+    verbose: bool,
+
+    /// 执行结束时写入构建指标的文件（为None时不写入）
+    #[builder(default)]
+    metrics_file: Option<PathBuf>,
+
+    /// 构建指标文件的格式
+    #[builder(default)]
+    metrics_format: MetricsFormat,
+
+    /// 构建结束时写入构建报告（JSON）的文件（为None时不写入，只在构建时有效）
+    #[builder(default)]
+    build_report: Option<PathBuf>,
+
+    /// 捕获构建命令的输出，作为`task_output`事件发出，而不是直接输出到终端
+    #[builder(default)]
+    capture_output: bool,
+
+    /// 缓存目录被其他dadk进程锁定时，等待的时间
+    #[builder(default)]
+    lock_timeout: LockTimeout,
+
     #[cfg(test)]
     base_test_context: Option<BaseGlobalTestContext>,
 
     #[builder(setter(skip), default = "Mutex::new(Weak::new())")]
     self_ref: Mutex<Weak<Self>>,
+
+    /// 初始化后的缓存根目录
+    #[builder(setter(skip), default = "Lazy::new()")]
+    cache_root: Lazy<PathBuf>,
+
+    /// 全局环境变量列表（在执行任务之前准备）
+    #[builder(setter(skip), default = "RwLock::new(EnvMap::new())")]
+    global_env_list: RwLock<EnvMap>,
+
+    /// 本次执行的开始时间，用于区分不同批次的构建
+    #[builder(setter(skip), default = "Utc::now()")]
+    session_start: DateTime<Utc>,
+
+    /// 上次执行时被中断的任务（name_version），需要强制重新执行
+    #[builder(setter(skip), default = "RwLock::new(BTreeSet::new())")]
+    dirty_tasks: RwLock<BTreeSet<String>>,
+
+    /// 本次执行的构建指标
+    #[builder(setter(skip), default = "Mutex::new(RunMetrics::default())")]
+    metrics: Mutex<RunMetrics>,
+
+    /// 本次安装中各个任务登记的文件
+    #[builder(setter(skip), default = "InstallClaims::default()")]
+    install_claims: InstallClaims,
+
+    /// 本次执行的中断、取消状态
+    #[builder(setter(skip), default = "InterruptState::new()")]
+    interrupt_state: Arc<InterruptState>,
+
+    /// 限制构建命令的资源时使用的父cgroup
+    #[builder(setter(skip), default = "CgroupLease::default()")]
+    cgroup_lease: CgroupLease,
 }
 
 impl DadkUserExecuteContext {
-    pub fn init(&self, self_arc: Arc<Self>) {
+    pub fn init(&self, self_arc: Arc<Self>) -> Result<(), ExecutorError> {
         self.set_self_ref(Arc::downgrade(&self_arc));
 
-        // 初始化缓存目录
-        let r: Result<(), crate::executor::ExecutorError> =
-            cache_root_init(self.cache_dir().cloned());
-        if r.is_err() {
-            error!("Failed to init cache root: {:?}", r.unwrap_err());
-            exit(1);
+        if self.config_dir().is_none() {
+            return Err(ExecutorError::PrepareEnvError(format!(
+                "Config dir is required for action: {:?}",
+                self.action()
+            )));
         }
 
-        if let Some(thread) = self.thread_num() {
-            TASK_DEQUE.lock().unwrap().set_thread(thread);
-        }
+        self.check_sysroot()?;
 
-        if self.config_dir().is_none() {
-            error!("Config dir is required for action: {:?}", self.action());
-            exit(1);
+        // 初始化缓存目录
+        if !self.cache_root.initialized() {
+            let cache_root = cache_root_init(self.cache_dir().cloned()).map_err(|e| {
+                ExecutorError::PrepareEnvError(format!("Failed to init cache root: {:?}", e))
+            })?;
+            self.cache_root.init(cache_root);
         }
 
-        if self.sysroot_dir().is_none() {
-            error!(
+        Ok(())
+    }
+
+    /// # 检查DragonOS sysroot目录
+    ///
+    /// 只有安装需要sysroot目录，构建、清理时可以不指定或者目录不存在。
+    /// 安装时目录不存在，如果设置了`create_sysroot`则创建它，否则返回错误
+    fn check_sysroot(&self) -> Result<(), ExecutorError> {
+        if self.action != Action::Install {
+            return Ok(());
+        }
+        let Some(sysroot_dir) = self.sysroot_dir() else {
+            return Err(ExecutorError::PrepareEnvError(format!(
                 "dragonos sysroot dir is required for action: {:?}",
                 self.action()
-            );
-            exit(1);
+            )));
+        };
+        if sysroot_dir.is_dir() {
+            return Ok(());
+        }
+        if sysroot_dir.exists() {
+            return Err(ExecutorError::PrepareEnvError(format!(
+                "DragonOS sysroot {} is not a directory",
+                sysroot_dir.display()
+            )));
+        }
+        if !self.create_sysroot {
+            return Err(ExecutorError::PrepareEnvError(format!(
+                "DragonOS sysroot dir {} does not exist, create it or use --create-sysroot",
+                sysroot_dir.display()
+            )));
         }
+        std::fs::create_dir_all(sysroot_dir).map_err(|e| {
+            ExecutorError::PrepareEnvError(format!(
+                "Failed to create DragonOS sysroot dir {}: {}",
+                sysroot_dir.display(),
+                e
+            ))
+        })?;
+        info!("Created DragonOS sysroot dir {}", sysroot_dir.display());
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -88,6 +304,10 @@ impl DadkUserExecuteContext {
         self.config_dir.as_ref()
     }
 
+    pub fn overlay_config_dirs(&self) -> &[PathBuf] {
+        &self.overlay_config_dirs
+    }
+
     pub fn action(&self) -> &Action {
         &self.action
     }
@@ -99,6 +319,184 @@ impl DadkUserExecuteContext {
     pub fn cache_dir(&self) -> Option<&PathBuf> {
         self.cache_dir.as_ref()
     }
+
+    pub fn rebuild_tasks(&self) -> &Vec<String> {
+        &self.rebuild_tasks
+    }
+
+    pub fn selected_tasks(&self) -> &[String] {
+        &self.selected_tasks
+    }
+
+    pub fn affected_by(&self) -> &[PathBuf] {
+        &self.affected_by
+    }
+
+    pub fn force(&self) -> bool {
+        self.force
+    }
+
+    pub fn no_build_cache(&self) -> bool {
+        self.no_build_cache
+    }
+
+    pub fn refetch(&self) -> bool {
+        self.refetch
+    }
+
+    pub fn verbose(&self) -> bool {
+        self.verbose
+    }
+
+    pub fn allow_env_collisions(&self) -> bool {
+        self.allow_env_collisions
+    }
+
+    pub fn create_sysroot(&self) -> bool {
+        self.create_sysroot
+    }
+
+    pub fn metrics_file(&self) -> Option<&PathBuf> {
+        self.metrics_file.as_ref()
+    }
+
+    pub fn build_report(&self) -> Option<&PathBuf> {
+        self.build_report.as_ref()
+    }
+
+    pub fn metrics_format(&self) -> MetricsFormat {
+        self.metrics_format
+    }
+
+    /// 记录一个任务的执行结果
+    pub fn record_metrics(&self, task: TaskMetrics) {
+        self.metrics.lock().unwrap().record(task);
+    }
+
+    pub fn metrics(&self) -> std::sync::MutexGuard<'_, RunMetrics> {
+        self.metrics.lock().unwrap()
+    }
+
+    pub(crate) fn install_claims(&self) -> &InstallClaims {
+        &self.install_claims
+    }
+
+    pub(crate) fn cgroup_lease(&self) -> &CgroupLease {
+        &self.cgroup_lease
+    }
+
+    pub fn container(&self) -> Option<&ContainerConfig> {
+        self.container.as_ref()
+    }
+
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn hardlink_prebuilt(&self) -> bool {
+        self.hardlink_prebuilt
+    }
+
+    pub fn app_blocklist(&self) -> &AppBlocklistConfigFile {
+        &self.app_blocklist
+    }
+
+    pub fn skip_invalid_configs(&self) -> bool {
+        self.skip_invalid_configs
+    }
+
+    pub fn variables(&self) -> &BTreeMap<String, String> {
+        &self.variables
+    }
+
+    pub fn default_versions(&self) -> &BTreeMap<String, String> {
+        &self.default_versions
+    }
+
+    pub fn package_repository(&self) -> Option<&str> {
+        self.package_repository.as_deref()
+    }
+
+    pub fn rust_toolchain(&self) -> Option<&str> {
+        self.rust_toolchain.as_deref()
+    }
+
+    pub fn compiler_cache(&self) -> &CompilerCacheConfig {
+        &self.compiler_cache
+    }
+
+    pub fn env_config(&self) -> &EnvConfig {
+        &self.env_config
+    }
+
+    pub fn kernel(&self) -> Option<&KernelConfig> {
+        self.kernel.as_ref()
+    }
+
+    pub fn git_timeouts(&self) -> &GitTimeouts {
+        &self.git_timeouts
+    }
+
+    pub fn mirrors(&self) -> &MirrorConfig {
+        &self.mirrors
+    }
+
+    pub fn shell(&self) -> BuildShell {
+        self.shell
+    }
+
+    pub fn shell_strict(&self) -> bool {
+        self.shell_strict
+    }
+
+    pub fn install_allowed_paths(&self) -> &[PathBuf] {
+        &self.install_allowed_paths
+    }
+
+    pub fn capture_output(&self) -> bool {
+        self.capture_output
+    }
+
+    pub fn lock_timeout(&self) -> LockTimeout {
+        self.lock_timeout
+    }
+
+    /// 任务在上次执行时是否被中断
+    pub fn is_dirty(&self, name_version: &str) -> bool {
+        self.dirty_tasks.read().unwrap().contains(name_version)
+    }
+
+    pub(crate) fn set_dirty_tasks(&self, tasks: BTreeSet<String>) {
+        *self.dirty_tasks.write().unwrap() = tasks;
+    }
+
+    /// # 获取缓存根目录
+    ///
+    /// 必须在`init()`之后调用
+    pub fn cache_root(&self) -> &PathBuf {
+        self.cache_root.get()
+    }
+
+    pub(crate) fn global_env_list(&self) -> &RwLock<EnvMap> {
+        &self.global_env_list
+    }
+
+    pub fn session_start(&self) -> &DateTime<Utc> {
+        &self.session_start
+    }
+
+    /// 本次执行的中断状态，可以在其他线程中用来中断执行或者取消单个任务
+    pub fn interrupt_state(&self) -> &Arc<InterruptState> {
+        &self.interrupt_state
+    }
 }
 
 #[cfg(test)]
@@ -148,7 +546,9 @@ impl TestContext for DadkExecuteContextTestBuildX86_64V1 {
                 .build()
                 .expect("Failed to build DadkExecuteContextTestBuildX86_64V1");
         let context = Arc::new(context);
-        context.init(context.clone());
+        context
+            .init(context.clone())
+            .expect("Failed to init DadkExecuteContextTestBuildX86_64V1");
         DadkExecuteContextTestBuildX86_64V1 { context }
     }
 }
@@ -169,7 +569,9 @@ impl TestContext for DadkExecuteContextTestBuildRiscV64V1 {
                 .build()
                 .expect("Failed to build DadkExecuteContextTestBuildRiscV64V1");
         let context = Arc::new(context);
-        context.init(context.clone());
+        context
+            .init(context.clone())
+            .expect("Failed to init DadkExecuteContextTestBuildRiscV64V1");
         DadkExecuteContextTestBuildRiscV64V1 { context }
     }
 }
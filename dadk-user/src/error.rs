@@ -0,0 +1,142 @@
+//! # 错误码
+//!
+//! dadk-user对外返回的错误都是[`DadkUserError`]，其中带有稳定的错误码（[`ErrorCode`]），
+//! 调用DADK的脚本可以通过错误码区分“依赖不存在”、“下载失败”、“编译失败”等情况，而不需要解析错误信息。
+//!
+//! 多个任务同时失败时，每个任务的错误放在`errors`中，外层错误的错误码为它们共同的错误码
+//! （错误码不同时为`multiple`）。
+//!
+//! 错误码一旦发布就不再修改，只会增加新的错误码。
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// # 错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// 执行上下文不完整，或初始化失败
+    InvalidContext,
+    /// 配置文件无法解析，或者任务配置有误
+    InvalidConfig,
+    /// 依赖的任务不存在
+    DependencyMissing,
+    /// 任务之间存在环形依赖
+    DependencyCycle,
+    /// 拉取git仓库、下载压缩包或者二进制包失败
+    FetchFailed,
+    /// 准备构建环境失败，例如工具链没有安装
+    PrepareFailed,
+    /// 构建命令执行失败
+    BuildFailed,
+    /// 安装失败
+    InstallFailed,
+    /// 清理失败
+    CleanFailed,
+    /// 命令执行超时
+    Timeout,
+    /// 收到中断信号，或者任务被取消
+    Interrupted,
+    /// 缓存目录被其他dadk进程锁定
+    Locked,
+    /// 读写文件失败
+    Io,
+    /// 多个不同类型的错误
+    Multiple,
+    /// 其他错误
+    Other,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidContext => "invalid-context",
+            ErrorCode::InvalidConfig => "invalid-config",
+            ErrorCode::DependencyMissing => "dependency-missing",
+            ErrorCode::DependencyCycle => "dependency-cycle",
+            ErrorCode::FetchFailed => "fetch-failed",
+            ErrorCode::PrepareFailed => "prepare-failed",
+            ErrorCode::BuildFailed => "build-failed",
+            ErrorCode::InstallFailed => "install-failed",
+            ErrorCode::CleanFailed => "clean-failed",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Interrupted => "interrupted",
+            ErrorCode::Locked => "locked",
+            ErrorCode::Io => "io",
+            ErrorCode::Multiple => "multiple",
+            ErrorCode::Other => "other",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// # dadk-user的错误
+#[derive(Debug, Clone, Serialize, thiserror::Error)]
+#[error("{message}")]
+pub struct DadkUserError {
+    code: ErrorCode,
+    message: String,
+    /// 出错的任务（`name-version`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task: Option<String>,
+    /// 同时发生的多个错误
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<DadkUserError>,
+}
+
+impl DadkUserError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            task: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// 多个错误的汇总，错误码为它们共同的错误码
+    pub fn multiple(message: impl Into<String>, errors: Vec<DadkUserError>) -> Self {
+        let code = match errors.first() {
+            Some(first) if errors.iter().all(|e| e.code == first.code) => first.code,
+            _ => ErrorCode::Multiple,
+        };
+        Self {
+            code,
+            message: message.into(),
+            task: None,
+            errors,
+        }
+    }
+
+    pub fn with_task(mut self, task: impl Into<String>) -> Self {
+        self.task = Some(task.into());
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn task(&self) -> Option<&str> {
+        self.task.as_deref()
+    }
+
+    pub fn errors(&self) -> &[DadkUserError] {
+        &self.errors
+    }
+
+    /// 单行JSON格式的错误
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize error")
+    }
+}
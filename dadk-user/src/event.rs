@@ -0,0 +1,127 @@
+//! # 结构化事件
+//!
+//! dadk-user在任务执行的关键节点上，以`log`记录的形式发出结构化的事件。
+//! 所有事件记录的target均为[`EVENT_TARGET`]，事件的字段以key-value的形式附加在记录上，
+//! 其中`event`字段为事件名称。
+//!
+//! 日志的使用者（例如dadk的`--log-format json`）可以据此输出机器可读的事件流。
+//!
+//! 目前支持的事件：
+//!
+//! - `task_queued`：任务等待执行（`depends`为逗号分隔的依赖任务）
+//! - `task_started`：任务开始执行
+//! - `task_finished`：任务执行结束（`status`为`ok`或`failed`）
+//! - `download_progress`：下载压缩包的进度
+//! - `extract_progress`：解压压缩包的进度
+//! - `install_done`：任务已安装到sysroot
+//! - `task_output`：构建命令输出的一行（仅当上下文启用了`capture_output`时发出，`stream`为`stdout`或`stderr`）
+
+use std::time::Duration;
+
+use log::info;
+
+use crate::{context::Action, scheduler::SchedEntity};
+
+/// 事件记录的target
+pub const EVENT_TARGET: &str = "dadk::event";
+
+pub(crate) fn task_queued(entity: &SchedEntity) {
+    let task = entity.task();
+    let name_version = task.name_version();
+    let depends = task
+        .depends
+        .iter()
+        .map(|d| d.name_version())
+        .collect::<Vec<_>>()
+        .join(",");
+    info!(
+        target: EVENT_TARGET,
+        event = "task_queued",
+        task_id = entity.id(),
+        task = name_version.as_str(),
+        depends = depends.as_str();
+        "Task {} queued", name_version
+    );
+}
+
+pub(crate) fn task_started(entity: &SchedEntity, action: &Action) {
+    let task = entity.task().name_version();
+    let action = format!("{:?}", action);
+    info!(
+        target: EVENT_TARGET,
+        event = "task_started",
+        task_id = entity.id(),
+        task = task.as_str(),
+        action = action.as_str();
+        "Task {} started", task
+    );
+}
+
+pub(crate) fn task_finished(entity: &SchedEntity, elapsed: Duration, error: Option<&str>) {
+    let task = entity.task().name_version();
+    let status = if error.is_none() { "ok" } else { "failed" };
+    info!(
+        target: EVENT_TARGET,
+        event = "task_finished",
+        task_id = entity.id(),
+        task = task.as_str(),
+        status = status,
+        elapsed_ms = elapsed.as_millis() as u64,
+        error = error.unwrap_or_default();
+        "Task {} finished: {}", task, status
+    );
+}
+
+pub(crate) fn download_progress(entity: &SchedEntity, url: &str, downloaded: u64, total: u64) {
+    let task = entity.task().name_version();
+    // total为0表示服务器没有返回文件大小
+    info!(
+        target: EVENT_TARGET,
+        event = "download_progress",
+        task_id = entity.id(),
+        task = task.as_str(),
+        url = url,
+        downloaded = downloaded,
+        total = total;
+        "Downloading {}: {}/{} bytes", url, downloaded, total
+    );
+}
+
+pub(crate) fn extract_progress(entity: &SchedEntity, archive: &str, extracted: u64, total: u64) {
+    let task = entity.task().name_version();
+    info!(
+        target: EVENT_TARGET,
+        event = "extract_progress",
+        task_id = entity.id(),
+        task = task.as_str(),
+        archive = archive,
+        extracted = extracted,
+        total = total;
+        "Extracting {}: {}/{} bytes", archive, extracted, total
+    );
+}
+
+pub(crate) fn install_done(entity: &SchedEntity, install_path: &str) {
+    let task = entity.task().name_version();
+    info!(
+        target: EVENT_TARGET,
+        event = "install_done",
+        task_id = entity.id(),
+        task = task.as_str(),
+        install_path = install_path;
+        "Task {} installed to {}", task, install_path
+    );
+}
+
+/// 构建命令输出的一行。每行都会发出一个事件，因此由调用者预先计算任务名称
+pub(crate) fn task_output(task_id: i32, task: &str, stream: &str, line: &str) {
+    info!(
+        target: EVENT_TARGET,
+        event = "task_output",
+        task_id = task_id,
+        task = task,
+        stream = stream,
+        line = line;
+        "[{}] {}", task, line
+    );
+}
@@ -0,0 +1,161 @@
+//! # 执行后端
+//!
+//! 决定任务的构建命令（以及清理命令）在哪里执行：
+//!
+//! - [`HostBackend`]：直接在主机上执行
+//! - [`ContainerBackend`]：在docker/podman容器中执行。缓存根目录、sysroot以及命令的工作目录
+//!   会以相同的路径挂载到容器中，因此DADK导出的环境变量在容器内同样有效
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fmt::Debug,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use dadk_config::manifest::{ContainerConfig, ContainerEngine};
+
+use super::resources::ResourceLimits;
+use crate::context::DadkUserExecuteContext;
+
+pub trait ExecutorBackend: Debug + Send + Sync {
+    /// 把在主机上执行的命令转换为实际要执行的命令
+    fn command(&self, command: Command) -> Command;
+}
+
+/// 根据执行上下文创建执行后端
+pub(crate) fn create_backend(
+    context: &DadkUserExecuteContext,
+    resources: Option<ResourceLimits>,
+) -> Arc<dyn ExecutorBackend> {
+    match context.container() {
+        Some(config) => {
+            let mut mounts = vec![context.cache_root().clone()];
+            // 还没有创建的sysroot目录不挂载，否则容器引擎会以root身份创建它
+            mounts.extend(context.sysroot_dir().filter(|dir| dir.is_dir()).cloned());
+            Arc::new(ContainerBackend::new(config.clone(), mounts).resources(resources))
+        }
+        None => Arc::new(HostBackend),
+    }
+}
+
+#[derive(Debug)]
+pub struct HostBackend;
+
+impl ExecutorBackend for HostBackend {
+    fn command(&self, command: Command) -> Command {
+        command
+    }
+}
+
+#[derive(Debug)]
+pub struct ContainerBackend {
+    config: ContainerConfig,
+    /// 需要以相同路径挂载到容器中的目录
+    mounts: Vec<PathBuf>,
+    /// 容器可以使用的CPU、内存
+    resources: Option<ResourceLimits>,
+}
+
+impl ContainerBackend {
+    pub fn new(config: ContainerConfig, mounts: Vec<PathBuf>) -> Self {
+        Self {
+            config,
+            mounts,
+            resources: None,
+        }
+    }
+
+    /// 设置容器的资源限制
+    pub fn resources(mut self, resources: Option<ResourceLimits>) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// 要挂载的目录（已去除被其他目录包含的目录）
+    fn mount_dirs(&self, work_dir: Option<&Path>) -> Vec<PathBuf> {
+        let dirs: BTreeSet<PathBuf> = self
+            .mounts
+            .iter()
+            .map(|p| p.as_path())
+            .chain(work_dir)
+            .map(|p| p.to_path_buf())
+            .collect();
+        // BTreeSet有序，父目录总是排在子目录之前
+        let mut result: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            if !result.iter().any(|p| dir.starts_with(p)) {
+                result.push(dir);
+            }
+        }
+        result
+    }
+}
+
+impl ExecutorBackend for ContainerBackend {
+    fn command(&self, command: Command) -> Command {
+        let work_dir = command.get_current_dir().map(|p| p.to_path_buf());
+        let mut cmd = Command::new(self.config.engine.program());
+        cmd.arg("run").arg("--rm").arg("-i");
+
+        // 让容器内创建的文件属于当前用户
+        match self.config.engine {
+            ContainerEngine::Docker => {
+                if let Ok(metadata) = std::fs::metadata("/proc/self") {
+                    cmd.arg("--user")
+                        .arg(format!("{}:{}", metadata.uid(), metadata.gid()));
+                }
+            }
+            ContainerEngine::Podman => {
+                cmd.arg("--userns=keep-id");
+            }
+        }
+
+        for dir in self.mount_dirs(work_dir.as_deref()) {
+            let mut volume = OsString::from(&dir);
+            volume.push(":");
+            volume.push(&dir);
+            cmd.arg("-v").arg(volume);
+        }
+        for mount in &self.config.mounts {
+            cmd.arg("-v").arg(mount);
+        }
+        if let Some(work_dir) = &work_dir {
+            cmd.arg("-w").arg(work_dir);
+        }
+
+        // 主机上的环境变量（例如PATH）不应传入容器，只传入DADK设置或修改过的环境变量
+        for (key, value) in command.get_envs() {
+            let Some(value) = value else {
+                continue;
+            };
+            let is_dadk_env = key
+                .to_str()
+                .is_some_and(|k| k.starts_with("DADK") || k == "ARCH");
+            if is_dadk_env || std::env::var_os(key).as_deref() != Some(value) {
+                let mut env = key.to_os_string();
+                env.push("=");
+                env.push(value);
+                cmd.arg("-e").arg(env);
+            }
+        }
+
+        if let Some(resources) = &self.resources {
+            if let Some(cpus) = resources.cpus {
+                cmd.arg("--cpus").arg(cpus.to_string());
+            }
+            if let Some(memory) = resources.memory {
+                cmd.arg("--memory").arg(format!("{}b", memory));
+            }
+        }
+
+        cmd.args(&self.config.args);
+        cmd.arg(&self.config.image);
+        cmd.arg(command.get_program());
+        cmd.args(command.get_args());
+        cmd
+    }
+}
@@ -0,0 +1,124 @@
+//! # cmake、autotools任务
+//!
+//! `task-source.type`为`cmake`或者`autotools`的任务，由DADK生成配置、构建、安装命令，
+//! 安装前缀为任务的构建缓存目录。目标架构与本机不同时，会设置交叉编译的参数和环境变量
+//! （`CC`、`CXX`等使用`<arch>-linux-musl-`前缀的工具链）。
+
+use std::path::Path;
+
+use dadk_config::common::{
+    target_arch::TargetArch,
+    task::{AutotoolsConfig, CmakeConfig},
+};
+
+/// 交叉编译工具链的前缀。目标架构与本机相同时返回None
+pub(super) fn cross_prefix(arch: TargetArch) -> Option<String> {
+    let arch: &str = arch.into();
+    if arch == std::env::consts::ARCH {
+        None
+    } else {
+        Some(format!("{}-linux-musl-", arch))
+    }
+}
+
+/// 交叉编译时设置的环境变量
+///
+/// 任务在`[[envs]]`中设置的同名环境变量优先
+pub(super) fn cross_envs(arch: TargetArch, sysroot: &Path) -> Vec<(String, String)> {
+    let Some(prefix) = cross_prefix(arch) else {
+        return Vec::new();
+    };
+    let mut envs: Vec<(String, String)> = [
+        ("CC", "gcc"),
+        ("CXX", "g++"),
+        ("AR", "ar"),
+        ("RANLIB", "ranlib"),
+        ("STRIP", "strip"),
+    ]
+    .iter()
+    .map(|(key, tool)| (key.to_string(), format!("{}{}", prefix, tool)))
+    .collect();
+    // 使用已经安装到sysroot中的库
+    envs.push((
+        "PKG_CONFIG_PATH".to_string(),
+        format!(
+            "{}:{}",
+            sysroot.join("lib/pkgconfig").display(),
+            sysroot.join("usr/lib/pkgconfig").display()
+        ),
+    ));
+    envs
+}
+
+/// cmake任务的构建命令
+pub(super) fn cmake_script(
+    cmake: &CmakeConfig,
+    arch: TargetArch,
+    build_dir: &Path,
+    sysroot: &Path,
+) -> String {
+    let binary_dir = quote(cmake.build_dir());
+    let mut configure = vec![
+        "cmake".to_string(),
+        "-S".to_string(),
+        ".".to_string(),
+        "-B".to_string(),
+        binary_dir.clone(),
+        quote(&format!("-DCMAKE_BUILD_TYPE={}", cmake.build_type())),
+        quote(&format!("-DCMAKE_INSTALL_PREFIX={}", build_dir.display())),
+    ];
+    if cross_prefix(arch).is_some() {
+        let arch: &str = arch.into();
+        configure.push("-DCMAKE_SYSTEM_NAME=Linux".to_string());
+        configure.push(quote(&format!("-DCMAKE_SYSTEM_PROCESSOR={}", arch)));
+        configure.push(quote(&format!(
+            "-DCMAKE_FIND_ROOT_PATH={}",
+            sysroot.display()
+        )));
+    }
+    configure.extend(cmake.options.iter().map(|o| quote(o)));
+
+    format!(
+        "{} && cmake --build {} --parallel && cmake --install {}",
+        configure.join(" "),
+        binary_dir,
+        binary_dir
+    )
+}
+
+/// autotools任务的构建命令
+///
+/// 源码目录中没有`configure`脚本时（例如从git仓库获取的源码），先执行`autoreconf -fi`生成
+pub(super) fn autotools_script(
+    autotools: &AutotoolsConfig,
+    arch: TargetArch,
+    build_dir: &Path,
+) -> String {
+    let mut configure = vec![
+        "./configure".to_string(),
+        quote(&format!("--prefix={}", build_dir.display())),
+    ];
+    if let Some(prefix) = cross_prefix(arch) {
+        configure.push(quote(&format!("--host={}", prefix.trim_end_matches('-'))));
+    }
+    configure.extend(autotools.configure_options.iter().map(|o| quote(o)));
+
+    let make_options: Vec<String> = autotools.make_options.iter().map(|o| quote(o)).collect();
+    let make = format!("make -j\"$(nproc)\" {}", make_options.join(" "));
+    format!(
+        "{{ [ -x ./configure ] || autoreconf -fi; }} && {} && {} && make install",
+        configure.join(" "),
+        make.trim_end()
+    )
+}
+
+/// 用单引号包裹shell参数
+fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=./:,+@%".contains(c))
+    {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
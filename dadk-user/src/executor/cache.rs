@@ -1,6 +1,6 @@
 use std::{
-    path::PathBuf,
-    sync::{Arc, Once},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use log::info;
@@ -11,19 +11,21 @@ use crate::{
         task_log::TaskLog,
     },
     scheduler::SchedEntity,
-    utils::{lazy_init::Lazy, path::abs_path},
+    utils::path::abs_path,
 };
 
 use super::ExecutorError;
 
-pub static CACHE_ROOT: Lazy<PathBuf> = Lazy::new();
-
 /// # 初始化缓存根目录
 ///
 /// ## 参数
 ///
 /// - `path` 缓存根目录的路径
-pub fn cache_root_init(path: Option<PathBuf>) -> Result<(), ExecutorError> {
+///
+/// ## 返回值
+///
+/// 缓存根目录的路径。如果目录不存在，会自动创建。
+pub fn cache_root_init(path: Option<PathBuf>) -> Result<PathBuf, ExecutorError> {
     let cache_root: String;
     if path.is_none() {
         // 查询环境变量，是否有设置缓存根目录
@@ -70,26 +72,17 @@ pub fn cache_root_init(path: Option<PathBuf>) -> Result<(), ExecutorError> {
         std::fs::create_dir_all(&cache_root).map_err(|e| ExecutorError::IoError(e.to_string()))?;
     } else if !cache_root.is_dir() {
         // 如果缓存根目录不是目录，则报错
-        return Err(ExecutorError::IoError(
-            std::io::Error::new(
-                std::io::ErrorKind::NotADirectory,
-                format!("Cache root dir is not a directory: {:?}", cache_root),
-            )
-            .to_string(),
-        ));
+        return Err(ExecutorError::IoError(format!(
+            "Cache root dir is not a directory: {:?}",
+            cache_root
+        )));
     }
 
-    // 初始化缓存根目录
-    static CACHE_ROOT_INIT_ONCE: Once = Once::new();
-    CACHE_ROOT_INIT_ONCE.call_once(|| CACHE_ROOT.init(cache_root));
-
-    // 设置环境变量
-    std::env::set_var("DADK_CACHE_ROOT", CACHE_ROOT.get().to_str().unwrap());
-    info!("Cache root dir: {:?}", CACHE_ROOT.get());
-    return Ok(());
+    info!("Cache root dir: {:?}", cache_root);
+    Ok(cache_root)
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheDirType {
     /// 构建缓存目录
     Build,
@@ -97,11 +90,31 @@ pub enum CacheDirType {
     Source,
     /// 每个任务执行数据缓存目录
     TaskData,
+    /// 安装前的暂存目录
+    Staging,
+}
+
+impl CacheDirType {
+    pub const ALL: [CacheDirType; 4] = [
+        CacheDirType::Build,
+        CacheDirType::Source,
+        CacheDirType::TaskData,
+        CacheDirType::Staging,
+    ];
+
+    /// 缓存根目录下，存放此类缓存目录的子目录名
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            CacheDirType::Build => "build",
+            CacheDirType::Source => "source",
+            CacheDirType::TaskData => "task_data",
+            CacheDirType::Staging => "staging",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CacheDir {
-    #[allow(dead_code)]
     entity: Arc<SchedEntity>,
     pub path: PathBuf,
     pub cache_type: CacheDirType,
@@ -110,9 +123,13 @@ pub struct CacheDir {
 impl CacheDir {
     pub const DADK_BUILD_CACHE_DIR_ENV_KEY_PREFIX: &'static str = "DADK_BUILD_CACHE_DIR";
     pub const DADK_SOURCE_CACHE_DIR_ENV_KEY_PREFIX: &'static str = "DADK_SOURCE_CACHE_DIR";
-    pub fn new(entity: Arc<SchedEntity>, cache_type: CacheDirType) -> Result<Self, ExecutorError> {
+    pub fn new(
+        cache_root: &Path,
+        entity: Arc<SchedEntity>,
+        cache_type: CacheDirType,
+    ) -> Result<Self, ExecutorError> {
         let task = entity.task();
-        let path = Self::get_path(&task, cache_type);
+        let path = Self::get_path(cache_root, &task, cache_type);
 
         let result = Self {
             entity,
@@ -125,33 +142,36 @@ impl CacheDir {
         return Ok(result);
     }
 
-    fn get_path(task: &DADKTask, cache_type: CacheDirType) -> PathBuf {
-        let cache_root = CACHE_ROOT.get();
-        let name_version = task.name_version();
-        let cache_dir = match cache_type {
-            CacheDirType::Build => {
-                format!("{}/build/{}", cache_root.to_str().unwrap(), name_version)
-            }
-            CacheDirType::Source => {
-                format!("{}/source/{}", cache_root.to_str().unwrap(), name_version)
-            }
-            CacheDirType::TaskData => {
-                format!(
-                    "{}/task_data/{}",
-                    cache_root.to_str().unwrap(),
-                    name_version
-                )
-            }
+    pub(crate) fn get_path(
+        cache_root: &Path,
+        task: &DADKTask,
+        cache_type: CacheDirType,
+    ) -> PathBuf {
+        let dir_name = match cache_type {
+            CacheDirType::Source => task.source_cache_name(),
+            _ => task.name_version(),
         };
+        let cache_dir = format!(
+            "{}/{}/{}",
+            cache_root.to_str().unwrap(),
+            cache_type.dir_name(),
+            dir_name
+        );
         abs_path(&PathBuf::from(cache_dir))
     }
 
-    pub fn build_dir(entity: Arc<SchedEntity>) -> Result<PathBuf, ExecutorError> {
-        return Ok(Self::new(entity.clone(), CacheDirType::Build)?.path);
+    pub fn build_dir(
+        cache_root: &Path,
+        entity: Arc<SchedEntity>,
+    ) -> Result<PathBuf, ExecutorError> {
+        Ok(Self::new(cache_root, entity.clone(), CacheDirType::Build)?.path)
     }
 
-    pub fn source_dir(entity: Arc<SchedEntity>) -> Result<PathBuf, ExecutorError> {
-        return Ok(Self::new(entity.clone(), CacheDirType::Source)?.path);
+    pub fn source_dir(
+        cache_root: &Path,
+        entity: Arc<SchedEntity>,
+    ) -> Result<PathBuf, ExecutorError> {
+        Ok(Self::new(cache_root, entity.clone(), CacheDirType::Source)?.path)
     }
 
     pub fn build_dir_env_key(entity: &Arc<SchedEntity>) -> Result<String, ExecutorError> {
@@ -188,6 +208,7 @@ impl CacheDir {
             match ps {
                 crate::parser::task::PrebuiltSource::Archive(_) => return false,
                 crate::parser::task::PrebuiltSource::Local(_) => return false,
+                crate::parser::task::PrebuiltSource::Repository(_) => return false,
             }
         }
         unimplemented!("Not fully implemented task type: {:?}", task_type);
@@ -201,18 +222,20 @@ impl CacheDir {
             info!("Cache dir: [{:?}] created.", self.path);
         } else if !self.path.is_dir() {
             // 如果路径类别不是目录，则报错
-            return Err(ExecutorError::IoError(
-                std::io::Error::new(
-                    std::io::ErrorKind::NotADirectory,
-                    format!("Cache dir is not a directory: {:?}", self.path),
-                )
-                .to_string(),
-            ));
+            return Err(ExecutorError::IoError(format!(
+                "Cache dir is not a directory: {:?}",
+                self.path
+            )));
         }
 
         return Ok(());
     }
 
+    /// 缓存目录所属的任务实体
+    pub fn entity(&self) -> &Arc<SchedEntity> {
+        &self.entity
+    }
+
     /// 判断缓存目录是否为空
     pub fn is_empty(&self) -> Result<bool, ExecutorError> {
         let x = self
@@ -245,22 +268,48 @@ pub struct TaskDataDir {
 }
 
 impl TaskDataDir {
-    const TASK_LOG_FILE_NAME: &'static str = "task_log.toml";
-    pub fn new(entity: Arc<SchedEntity>) -> Result<Self, ExecutorError> {
-        let dir = CacheDir::new(entity.clone(), CacheDirType::TaskData)?;
+    pub(crate) const TASK_LOG_FILE_NAME: &'static str = "task_log.toml";
+    pub fn new(cache_root: &Path, entity: Arc<SchedEntity>) -> Result<Self, ExecutorError> {
+        let dir = CacheDir::new(cache_root, entity.clone(), CacheDirType::TaskData)?;
         return Ok(Self { dir });
     }
 
+    pub fn path(&self) -> &Path {
+        &self.dir.path
+    }
+
     /// # 获取任务日志
-    pub fn task_log(&self) -> TaskLog {
+    ///
+    /// 任务日志不存在时返回空的任务日志
+    pub fn task_log(&self) -> Result<TaskLog, ExecutorError> {
         let path = self.dir.path.join(Self::TASK_LOG_FILE_NAME);
-        if path.exists() {
-            let content = std::fs::read_to_string(&path).unwrap();
-            let task_log: TaskLog = toml::from_str(&content).unwrap();
-            return task_log;
-        } else {
-            return TaskLog::new();
+        if !path.exists() {
+            return Ok(TaskLog::new());
         }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| ExecutorError::IoError(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&content).map_err(|e| {
+            ExecutorError::IoError(format!("Failed to parse {}: {}", path.display(), e))
+        })
+    }
+
+    /// # 读取任务日志
+    ///
+    /// 不需要创建任务数据目录，任务从未执行过时返回None
+    pub fn load_task_log(cache_root: &Path, task: &DADKTask) -> Option<TaskLog> {
+        Self::load_task_log_of(cache_root, &task.name_version())
+    }
+
+    /// # 按`name_version`读取任务日志
+    ///
+    /// 用于读取依赖的任务的日志（只知道依赖的名称和版本）
+    pub fn load_task_log_of(cache_root: &Path, name_version: &str) -> Option<TaskLog> {
+        let path = cache_root
+            .join(CacheDirType::TaskData.dir_name())
+            .join(name_version)
+            .join(Self::TASK_LOG_FILE_NAME);
+        let content = std::fs::read_to_string(path).ok()?;
+        toml::from_str(&content).ok()
     }
 
     /// # 设置任务日志
@@ -0,0 +1,125 @@
+//! # cargo任务
+//!
+//! `task-source.type = "cargo"`的任务由DADK执行`cargo build --target <rust-target> --release`，
+//! 构建完成后通过`cargo metadata`找到二进制目标以及target目录，
+//! 把`<target目录>/<rust-target>/release/`下的二进制文件复制到任务的构建缓存目录。
+
+use std::path::{Path, PathBuf};
+
+use dadk_config::common::{target_arch::TargetArch, task::CargoConfig};
+use serde_json::Value;
+
+use super::ExecutorError;
+
+/// `cargo build`的参数
+///
+/// 离线模式下添加`--offline`，只使用本地已经下载的依赖
+pub(super) fn build_args(cargo: &CargoConfig, arch: TargetArch, offline: bool) -> Vec<String> {
+    let mut args = vec![
+        "build".to_string(),
+        "--target".to_string(),
+        cargo.rust_target(arch),
+        "--release".to_string(),
+    ];
+    if !cargo.features.is_empty() {
+        args.push("--features".to_string());
+        args.push(cargo.features.join(","));
+    }
+    if cargo.no_default_features {
+        args.push("--no-default-features".to_string());
+    }
+    if offline {
+        args.push("--offline".to_string());
+    }
+    for bin in &cargo.bins {
+        args.push("--bin".to_string());
+        args.push(bin.clone());
+    }
+    args.extend(cargo.args.iter().cloned());
+    args
+}
+
+/// `cargo metadata`的参数
+pub(super) const METADATA_ARGS: [&str; 4] = ["metadata", "--no-deps", "--format-version", "1"];
+
+/// 从`cargo metadata`的输出中解析出target目录，以及需要复制的二进制目标名称
+///
+/// `bins`为空时返回工作空间中所有的二进制目标
+pub(super) fn parse_metadata(
+    metadata: &str,
+    bins: &[String],
+) -> Result<(PathBuf, Vec<String>), ExecutorError> {
+    let metadata: Value = serde_json::from_str(metadata)
+        .map_err(|e| cargo_error(&format!("Failed to parse cargo metadata: {}", e)))?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .map(PathBuf::from)
+        .ok_or_else(|| cargo_error("cargo metadata: target_directory not found"))?;
+
+    let all_bins: Vec<String> = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|pkg| pkg["targets"].as_array().into_iter().flatten())
+        .filter(|target| {
+            target["kind"]
+                .as_array()
+                .is_some_and(|kinds| kinds.iter().any(|k| k == "bin"))
+        })
+        .filter_map(|target| target["name"].as_str().map(String::from))
+        .collect();
+
+    if bins.is_empty() {
+        if all_bins.is_empty() {
+            return Err(cargo_error("No binary target found in the cargo project"));
+        }
+        return Ok((target_dir, all_bins));
+    }
+    if let Some(missing) = bins.iter().find(|b| !all_bins.contains(b)) {
+        return Err(cargo_error(&format!(
+            "Binary target {} not found in the cargo project",
+            missing
+        )));
+    }
+    Ok((target_dir, bins.to_vec()))
+}
+
+/// 构建结果所在的目录
+pub(super) fn artifact_dir(target_dir: &Path, cargo: &CargoConfig, arch: TargetArch) -> PathBuf {
+    target_dir.join(cargo.target_dir_name(arch)).join("release")
+}
+
+/// 把构建出来的二进制文件复制到构建缓存目录，返回复制的文件数
+pub(super) fn copy_artifacts(
+    artifact_dir: &Path,
+    bins: &[String],
+    build_dir: &Path,
+) -> Result<usize, ExecutorError> {
+    for bin in bins {
+        let src = artifact_dir.join(bin);
+        if !src.is_file() {
+            return Err(cargo_error(&format!(
+                "Build artifact {} not found",
+                src.display()
+            )));
+        }
+        // 先删除旧文件，避免覆盖已经安装到sysroot中的硬链接
+        let dst = build_dir.join(bin);
+        if dst.exists() {
+            std::fs::remove_file(&dst).map_err(|e| ExecutorError::IoError(e.to_string()))?;
+        }
+        std::fs::copy(&src, &dst).map_err(|e| {
+            cargo_error(&format!(
+                "Failed to copy {} to {}: {}",
+                src.display(),
+                dst.display(),
+                e
+            ))
+        })?;
+    }
+    Ok(bins.len())
+}
+
+fn cargo_error(msg: &str) -> ExecutorError {
+    ExecutorError::TaskFailed(msg.to_string())
+}
@@ -0,0 +1,137 @@
+//! # 编译缓存
+//!
+//! 启用了编译缓存的任务（任务的`build.compiler-cache`，未设置时使用manifest中的`compiler-cache.enabled`），
+//! 构建时按照任务的类型设置环境变量，而不需要在每个任务的`envs`中重复设置：
+//!
+//! - cargo任务：`RUSTC_WRAPPER`
+//! - cmake任务：`CMAKE_C_COMPILER_LAUNCHER`、`CMAKE_CXX_COMPILER_LAUNCHER`
+//! - autotools任务：在`CC`、`CXX`前加上wrapper，例如`CC="ccache gcc"`
+//! - 其他任务（自定义构建命令）：同时设置`CC`、`CXX`以及`RUSTC_WRAPPER`
+//!
+//! 任务在`envs`中设置的同名变量优先（`CC`、`CXX`会加上wrapper）。
+//! 在主机上构建时，构建前检查wrapper已经安装；构建结束后输出各个wrapper的统计信息。
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use dadk_config::manifest::CompilerCacheConfig;
+use log::{info, warn};
+
+use super::backend;
+use crate::{context::DadkUserExecuteContext, parser::task::DADKTask, utils::stdio::StdioUtils};
+
+/// 任务是否使用编译缓存
+pub fn enabled(config: &CompilerCacheConfig, task: &DADKTask) -> bool {
+    task.build.compiler_cache.unwrap_or(config.enabled)
+}
+
+fn uses_cc(task: &DADKTask) -> bool {
+    task.cargo.is_none()
+}
+
+fn uses_rustc(task: &DADKTask) -> bool {
+    task.cmake.is_none() && task.autotools.is_none()
+}
+
+/// 任务使用的wrapper（不包括配置为空字符串的wrapper）
+pub fn wrappers(config: &CompilerCacheConfig, task: &DADKTask) -> Vec<String> {
+    if !enabled(config, task) {
+        return Vec::new();
+    }
+    [
+        (uses_cc(task), &config.cc_wrapper),
+        (uses_rustc(task), &config.rustc_wrapper),
+    ]
+    .into_iter()
+    .filter(|(used, wrapper)| *used && !wrapper.is_empty())
+    .map(|(_, wrapper)| wrapper.clone())
+    .collect()
+}
+
+/// 任务构建时需要设置的环境变量
+///
+/// `local`返回任务中已经设置的环境变量的值
+pub fn envs(
+    config: &CompilerCacheConfig,
+    task: &DADKTask,
+    local: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let mut envs = Vec::new();
+    if !enabled(config, task) {
+        return envs;
+    }
+    let cc_wrapper = &config.cc_wrapper;
+    if uses_cc(task) && !cc_wrapper.is_empty() {
+        if task.cmake.is_some() {
+            for key in ["CMAKE_C_COMPILER_LAUNCHER", "CMAKE_CXX_COMPILER_LAUNCHER"] {
+                if local(key).is_none() {
+                    envs.push((key.to_string(), cc_wrapper.clone()));
+                }
+            }
+        } else {
+            for (key, default) in [("CC", "cc"), ("CXX", "c++")] {
+                let compiler = local(key)
+                    .or_else(|| std::env::var(key).ok())
+                    .unwrap_or_else(|| default.to_string());
+                // 任务中已经手动加上了wrapper
+                if compiler.split_whitespace().next() != Some(cc_wrapper.as_str()) {
+                    envs.push((key.to_string(), format!("{} {}", cc_wrapper, compiler)));
+                }
+            }
+        }
+    }
+    if uses_rustc(task) && !config.rustc_wrapper.is_empty() && local("RUSTC_WRAPPER").is_none() {
+        envs.push(("RUSTC_WRAPPER".to_string(), config.rustc_wrapper.clone()));
+    }
+    envs
+}
+
+/// 在`PATH`中查找wrapper。名称中包含`/`时直接检查该路径
+pub(super) fn find(wrapper: &str) -> Option<PathBuf> {
+    if wrapper.contains('/') {
+        let path = PathBuf::from(wrapper);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(wrapper))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// 输出任务使用的各个wrapper的统计信息（`--show-stats`，ccache和sccache都支持）
+pub fn report(context: &DadkUserExecuteContext, tasks: &[DADKTask]) {
+    let wrappers: BTreeSet<String> = tasks
+        .iter()
+        .flat_map(|task| wrappers(context.compiler_cache(), task))
+        .collect();
+    if wrappers.is_empty() {
+        return;
+    }
+    // 在容器中构建时，缓存也在容器中
+    let backend = backend::create_backend(context, None);
+    for wrapper in wrappers {
+        let mut command = Command::new(&wrapper);
+        command.arg("--show-stats");
+        match backend.command(command).stdin(Stdio::null()).output() {
+            Ok(output) if output.status.success() => info!(
+                "{} statistics:\n{}",
+                wrapper,
+                String::from_utf8_lossy(&output.stdout).trim_end()
+            ),
+            Ok(output) => warn!(
+                "Failed to get statistics of {}: {}",
+                wrapper,
+                StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
+            ),
+            Err(e) => warn!("Failed to get statistics of {}: {}", wrapper, e),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! # 任务执行环境的说明
+//!
+//! 构建脚本找不到依赖的目录时，需要知道DADK实际执行的命令、工作目录以及传给它的环境变量。
+//! [`CommandPlan`]描述一条将要执行的命令，其中只列出与dadk进程自身的环境变量不同的变量：
+//! DADK添加的变量，以及任务配置中覆盖的同名变量。
+//!
+//! 指定`-v/--verbose`时，执行器在执行每条命令之前输出它的说明；
+//! `dadk user explain-env`输出任务构建时将要执行的命令，但不执行。
+
+use std::{collections::BTreeMap, path::PathBuf, process::Command};
+
+use serde::Serialize;
+
+/// 一条将要执行的命令
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandPlan {
+    /// 任务（`name-version`）
+    pub task: String,
+    /// 命令行（参数已按照shell的规则加上引号）。任务没有构建命令时为None
+    pub command: Option<String>,
+    /// 工作目录
+    pub work_dir: Option<PathBuf>,
+    /// 与dadk进程不同的环境变量（按名称排序）
+    pub envs: Vec<EnvChange>,
+}
+
+/// 与dadk进程不同的环境变量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvChange {
+    pub key: String,
+    pub value: String,
+    /// dadk进程中同名变量的值（没有同名变量时为None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+impl CommandPlan {
+    /// 根据已经设置好工作目录和环境变量的命令生成说明
+    pub fn from_command(task: &str, command: &Command, parent: &BTreeMap<String, String>) -> Self {
+        let command_line = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| quote(&arg.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let envs = command.get_envs().filter_map(|(key, value)| {
+            Some((
+                key.to_string_lossy().to_string(),
+                value?.to_string_lossy().to_string(),
+            ))
+        });
+        Self {
+            task: task.to_string(),
+            command: Some(command_line),
+            work_dir: command.get_current_dir().map(|p| p.to_path_buf()),
+            envs: env_diff(envs, parent),
+        }
+    }
+
+    /// 任务没有需要执行的命令
+    pub fn without_command(task: &str) -> Self {
+        Self {
+            task: task.to_string(),
+            command: None,
+            work_dir: None,
+            envs: Vec::new(),
+        }
+    }
+
+    /// 单行JSON格式的说明
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize command plan")
+    }
+
+    /// 便于人阅读的说明
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("task:      {}\n", self.task));
+        out.push_str(&format!(
+            "command:   {}\n",
+            self.command.as_deref().unwrap_or("(none)")
+        ));
+        if let Some(work_dir) = &self.work_dir {
+            out.push_str(&format!("work dir:  {}\n", work_dir.display()));
+        }
+        if !self.envs.is_empty() {
+            out.push_str("env (differs from the dadk process):\n");
+        }
+        for env in &self.envs {
+            match &env.parent {
+                Some(parent) => out.push_str(&format!(
+                    "  {}={}  (was {})\n",
+                    env.key,
+                    quote(&env.value),
+                    quote(parent)
+                )),
+                None => out.push_str(&format!("  {}={}\n", env.key, quote(&env.value))),
+            }
+        }
+        out
+    }
+}
+
+/// dadk进程自身的环境变量
+pub fn parent_envs() -> BTreeMap<String, String> {
+    std::env::vars_os()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().to_string(),
+                v.to_string_lossy().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// 找出与dadk进程不同的环境变量
+pub fn env_diff(
+    envs: impl IntoIterator<Item = (String, String)>,
+    parent: &BTreeMap<String, String>,
+) -> Vec<EnvChange> {
+    let envs: BTreeMap<String, String> = envs.into_iter().collect();
+    envs.into_iter()
+        .filter(|(key, value)| parent.get(key) != Some(value))
+        .map(|(key, value)| EnvChange {
+            parent: parent.get(&key).cloned(),
+            key,
+            value,
+        })
+        .collect()
+}
+
+/// 按照shell的规则给参数加上引号，不需要时原样返回
+pub fn quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || matches!(c, '-' | '_' | '.' | '/' | ':' | '=' | ',' | '+' | '@' | '%')
+        });
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
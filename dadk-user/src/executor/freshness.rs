@@ -0,0 +1,209 @@
+//! # 构建、安装结果是否过时
+//!
+//! 执行器根据任务日志以及输入文件的修改时间，决定是否跳过构建、安装。
+//! `dadk user status`使用相同的判断逻辑，解释下一次构建、安装会不会被跳过以及原因。
+//!
+//! 依赖的任务在本任务上一次构建之后被重新构建过（依赖的任务日志中记录的构建批次晚于本任务的构建时间）时，
+//! 本任务也需要重新构建。
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::parser::{
+    task::DADKTask,
+    task_log::{BuildStatus, InstallStatus, RebuildReason, TaskLog},
+};
+
+use super::{cache::TaskDataDir, last_modified_time, ExecutorError};
+
+/// # 是否跳过构建/安装，以及原因
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkipDecision {
+    pub skip: bool,
+    pub reason: String,
+    /// 不跳过构建时，重新构建的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rebuild: Option<RebuildReason>,
+    /// 参与比较的输入，及其最后修改时间
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputModified>,
+}
+
+/// # 输入文件（或目录）的最后修改时间
+///
+/// 目录中有文件晚于上次执行的时间时，停止遍历，`modified`为找到的第一个较新的修改时间。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InputModified {
+    pub path: PathBuf,
+    pub modified: DateTime<Utc>,
+    /// 修改时间晚于上次执行的时间
+    pub newer: bool,
+}
+
+impl SkipDecision {
+    fn run(reason: impl Into<String>) -> Self {
+        Self {
+            skip: false,
+            reason: reason.into(),
+            rebuild: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    fn rebuild(rebuild: RebuildReason, reason: impl Into<String>) -> Self {
+        Self {
+            rebuild: Some(rebuild),
+            ..Self::run(reason)
+        }
+    }
+
+    /// 上次执行时被中断，结果可能不完整
+    pub(crate) fn interrupted(what: &str) -> Self {
+        Self::run(format!("interrupted during the last {}", what))
+    }
+
+    /// 无法读取输入的修改时间，执行时会报错
+    pub(crate) fn failed(error: &str) -> Self {
+        Self::run(format!("failed to check the inputs: {}", error))
+    }
+}
+
+/// 判断是否跳过构建
+///
+/// - `cache_root` : 缓存根目录，用于读取依赖的任务的日志
+/// - `config_file` : 任务的配置文件
+/// - `src_dir` : 源文件的工作目录
+pub fn build_decision(
+    task: &DADKTask,
+    task_log: &TaskLog,
+    cache_root: &Path,
+    config_file: &Path,
+    src_dir: &Path,
+) -> Result<SkipDecision, ExecutorError> {
+    let (Some(status), Some(build_time)) = (task_log.build_status(), task_log.build_time()) else {
+        return Ok(SkipDecision::rebuild(
+            RebuildReason::FirstBuild,
+            "never built",
+        ));
+    };
+    if *status != BuildStatus::Success {
+        return Ok(SkipDecision::rebuild(
+            RebuildReason::LastBuildFailed,
+            "the last build failed",
+        ));
+    }
+    if task.build_once {
+        return Ok(SkipDecision {
+            skip: true,
+            reason: "built successfully and build_once is set".to_string(),
+            rebuild: None,
+            inputs: Vec::new(),
+        });
+    }
+    // 补丁文件被修改后需要重新构建
+    let inputs = std::iter::once(config_file)
+        .chain(std::iter::once(src_dir))
+        .chain(task.patches.iter().map(|p| p.as_path()));
+    let mut decision = compare_inputs(inputs, build_time, "build")?;
+    if let Some(newer) = decision.inputs.iter().find(|i| i.newer) {
+        decision.rebuild = Some(if newer.path == config_file {
+            RebuildReason::ConfigChanged
+        } else {
+            RebuildReason::SourceChanged
+        });
+        return Ok(decision);
+    }
+
+    for dep in &task.depends {
+        let name_version = DADKTask::name_version_of(&dep.name, &dep.version);
+        let rebuilt = TaskDataDir::load_task_log_of(cache_root, &name_version)
+            .and_then(|log| log.build_session().cloned())
+            .filter(|session| session > build_time);
+        if let Some(session) = rebuilt {
+            return Ok(SkipDecision {
+                inputs: decision.inputs,
+                ..SkipDecision::rebuild(
+                    RebuildReason::DependencyRebuilt,
+                    format!(
+                        "dependency {} was rebuilt in the build started at {}, after the last build at {}",
+                        name_version,
+                        session.to_rfc3339(),
+                        build_time.to_rfc3339()
+                    ),
+                )
+            });
+        }
+    }
+    Ok(decision)
+}
+
+/// 判断是否跳过安装
+///
+/// - `config_file` : 任务的配置文件
+/// - `build_dir` : 构建结果的缓存目录
+pub fn install_decision(
+    task: &DADKTask,
+    task_log: &TaskLog,
+    config_file: &Path,
+    build_dir: &Path,
+) -> Result<SkipDecision, ExecutorError> {
+    let (Some(status), Some(install_time)) = (task_log.install_status(), task_log.install_time())
+    else {
+        return Ok(SkipDecision::run("never installed"));
+    };
+    if *status != InstallStatus::Success {
+        return Ok(SkipDecision::run("the last install failed"));
+    }
+    if task.install_once {
+        return Ok(SkipDecision {
+            skip: true,
+            reason: "installed successfully and install_once is set".to_string(),
+            rebuild: None,
+            inputs: Vec::new(),
+        });
+    }
+    compare_inputs(
+        [build_dir, config_file].into_iter(),
+        install_time,
+        "install",
+    )
+}
+
+/// 比较输入的修改时间与上次执行的时间，所有输入都早于上次执行时跳过
+fn compare_inputs<'a>(
+    inputs: impl Iterator<Item = &'a Path>,
+    last_run: &DateTime<Utc>,
+    what: &str,
+) -> Result<SkipDecision, ExecutorError> {
+    let mut compared = Vec::new();
+    for path in inputs {
+        let modified = last_modified_time(&path.to_path_buf(), last_run)?;
+        compared.push(InputModified {
+            path: path.to_path_buf(),
+            modified,
+            newer: modified >= *last_run,
+        });
+    }
+    let reason = match compared.iter().find(|i| i.newer) {
+        Some(newer) => format!(
+            "{} was modified at {}, not before the last {} at {}",
+            newer.path.display(),
+            newer.modified.to_rfc3339(),
+            what,
+            last_run.to_rfc3339()
+        ),
+        None => format!(
+            "no input was modified since the last {} at {}",
+            what,
+            last_run.to_rfc3339()
+        ),
+    };
+    Ok(SkipDecision {
+        skip: compared.iter().all(|i| !i.newer),
+        reason,
+        rebuild: None,
+        inputs: compared,
+    })
+}
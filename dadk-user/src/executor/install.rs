@@ -0,0 +1,224 @@
+//! # 安装流水线
+//!
+//! 安装任务时，先把构建结果拷贝到任务的暂存目录，删除`install.exclude`排除的文件，
+//! 在暂存目录中strip ELF文件（`install.strip`），
+//! 按照`[[install.files]]`创建符号链接、设置权限，然后把暂存目录同步到安装目录（保留权限和符号链接）。
+//!
+//! 属主只能在安装目录中设置（需要root权限），设置完属主之后会重新设置权限，
+//! 因为修改属主时内核会清除setuid/setgid位。
+
+use std::{
+    fs::Permissions,
+    io::Read,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+    process::Command,
+};
+
+use dadk_config::common::{target_arch::TargetArch, task::InstallFileConfig};
+
+use crate::utils::stdio::StdioUtils;
+
+use super::{build_system::cross_prefix, outputs::glob_match, ExecutorError};
+
+/// 默认的strip工具：目标架构与本机相同时使用`strip`，否则使用`<arch>-linux-musl-strip`
+pub(super) fn default_strip_tool(arch: TargetArch) -> String {
+    format!("{}strip", cross_prefix(arch).unwrap_or_default())
+}
+
+/// 对暂存目录下所有的ELF文件执行strip，返回处理的文件数
+///
+/// 使用`--strip-unneeded`，对可执行文件和动态库都是安全的。不会跟随符号链接
+pub(super) fn strip_elf_files(staging: &Path, tool: &str) -> Result<usize, ExecutorError> {
+    let mut stripped = 0;
+    let entries = std::fs::read_dir(staging)
+        .map_err(|e| install_error(&format!("Failed to read {}", staging.display()), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| install_error("Failed to read dir entry", e))?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| install_error(&format!("Failed to stat {}", path.display()), e))?;
+        if file_type.is_dir() {
+            stripped += strip_elf_files(&path, tool)?;
+        } else if file_type.is_file() && is_elf(&path) {
+            let output = Command::new(tool)
+                .arg("--strip-unneeded")
+                .arg(&path)
+                .output()
+                .map_err(|e| install_error(&format!("Failed to run {}", tool), e))?;
+            if !output.status.success() {
+                return Err(ExecutorError::InstallError(format!(
+                    "Failed to strip {}: {}",
+                    path.display(),
+                    StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
+                )));
+            }
+            stripped += 1;
+        }
+    }
+    Ok(stripped)
+}
+
+/// 判断构建结果中的文件（相对于构建结果目录的路径，以`/`分隔）是否被`install.exclude`排除。
+/// 文件所在的目录被排除时，文件同样被排除
+///
+/// - 不含`/`的模式匹配任意目录下的文件名或目录名，例如`*.o`
+/// - 以`/`结尾的模式只匹配目录，例如`target/`
+/// - 其他模式匹配相对于构建结果目录的路径，例如`tests/fixtures/**`
+pub(crate) fn is_excluded(patterns: &[String], path: &str, is_dir: bool) -> bool {
+    let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    (1..=parts.len()).any(|n| {
+        let prefix = parts[..n].join("/");
+        let dir = n < parts.len() || is_dir;
+        patterns.iter().any(|p| pattern_matches(p, &prefix, dir))
+    })
+}
+
+fn pattern_matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+    let (pattern, dir_only) = match pattern.strip_suffix('/') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    if dir_only && !is_dir {
+        return false;
+    }
+    if pattern.contains('/') {
+        glob_match(pattern, path)
+    } else {
+        glob_match(&format!("**/{}", pattern), path)
+    }
+}
+
+/// 删除暂存目录中被`install.exclude`排除的文件和目录，返回删除的数量
+pub(super) fn remove_excluded(staging: &Path, patterns: &[String]) -> Result<usize, ExecutorError> {
+    if patterns.is_empty() {
+        return Ok(0);
+    }
+    remove_excluded_in(staging, "", patterns)
+}
+
+fn remove_excluded_in(
+    dir: &Path,
+    prefix: &str,
+    patterns: &[String],
+) -> Result<usize, ExecutorError> {
+    let mut removed = 0;
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| install_error(&format!("Failed to read {}", dir.display()), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| install_error("Failed to read dir entry", e))?;
+        let path = entry.path();
+        let rel = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        // 不跟随符号链接，指向目录的符号链接按文件处理
+        let is_dir = entry
+            .file_type()
+            .map_err(|e| install_error(&format!("Failed to stat {}", path.display()), e))?
+            .is_dir();
+        if is_excluded(patterns, &rel, is_dir) {
+            let r = if is_dir {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            r.map_err(|e| install_error(&format!("Failed to remove {}", path.display()), e))?;
+            removed += 1;
+        } else if is_dir {
+            removed += remove_excluded_in(&path, &format!("{}/", rel), patterns)?;
+        }
+    }
+    Ok(removed)
+}
+
+/// 根据文件头判断是否为ELF文件
+pub(super) fn is_elf(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| magic == *b"\x7fELF")
+}
+
+/// 在暂存目录中创建符号链接、设置权限
+pub(super) fn prepare_staging(
+    files: &[InstallFileConfig],
+    staging: &Path,
+) -> Result<(), ExecutorError> {
+    for file in files {
+        let path = staging.join(&file.path);
+        if let Some(target) = &file.symlink {
+            if path.symlink_metadata().is_ok() {
+                std::fs::remove_file(&path).map_err(|e| {
+                    install_error(&format!("Failed to replace {}", path.display()), e)
+                })?;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    install_error(&format!("Failed to create {}", parent.display()), e)
+                })?;
+            }
+            std::os::unix::fs::symlink(target, &path).map_err(|e| {
+                install_error(&format!("Failed to create symlink {}", path.display()), e)
+            })?;
+            continue;
+        }
+
+        if path.symlink_metadata().is_err() {
+            return Err(ExecutorError::InstallError(format!(
+                "Install file {} not found in build output",
+                file.path.display()
+            )));
+        }
+        set_mode(file, &path)?;
+    }
+    Ok(())
+}
+
+/// 在安装目录中设置属主
+///
+/// 属主已经符合要求时不会调用chown，因此非root用户也可以安装属主为自己的文件
+pub(super) fn apply_ownership(
+    files: &[InstallFileConfig],
+    install_path: &Path,
+) -> Result<(), ExecutorError> {
+    for file in files {
+        if file.uid.is_none() && file.gid.is_none() {
+            continue;
+        }
+        let path = install_path.join(&file.path);
+        let metadata = path
+            .symlink_metadata()
+            .map_err(|e| install_error(&format!("Failed to stat {}", path.display()), e))?;
+        if file.uid.map_or(true, |uid| uid == metadata.uid())
+            && file.gid.map_or(true, |gid| gid == metadata.gid())
+        {
+            continue;
+        }
+
+        std::os::unix::fs::lchown(&path, file.uid, file.gid).map_err(|e| {
+            let mut msg = format!("Failed to change owner of {}", path.display());
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                msg.push_str(" (setting uid/gid requires root privileges)");
+            }
+            install_error(&msg, e)
+        })?;
+        if file.symlink.is_none() {
+            set_mode(file, &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_mode(file: &InstallFileConfig, path: &Path) -> Result<(), ExecutorError> {
+    let mode = file
+        .mode_bits()
+        .map_err(|e| ExecutorError::InstallError(e.to_string()))?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, Permissions::from_mode(mode))
+            .map_err(|e| install_error(&format!("Failed to set mode of {}", path.display()), e))?;
+    }
+    Ok(())
+}
+
+fn install_error(msg: &str, e: std::io::Error) -> ExecutorError {
+    ExecutorError::InstallError(format!("{}: {}", msg, e))
+}
@@ -0,0 +1,70 @@
+//! # 内核模块
+//!
+//! `task-source.type = "kernel-module"`的任务根据manifest中`[kernel]`指定的内核源码构建，
+//! 构建命令可以通过`DADK_KERNEL_SOURCE_DIR`、`DADK_KERNEL_VERSION`获取内核源码目录和内核版本。
+//!
+//! 构建结果默认安装到`/lib/modules/<内核版本>/extra`。安装了内核模块时，
+//! 所有任务安装完成之后执行`[kernel] depmod`，重新生成模块依赖索引。
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use log::info;
+
+use crate::{
+    context::DadkUserExecuteContext,
+    parser::task::DADKTask,
+    utils::{path::abs_path, stdio::StdioUtils},
+};
+
+use super::ExecutorError;
+
+/// 内核源码目录的环境变量
+pub const KERNEL_SOURCE_DIR_ENV: &str = "DADK_KERNEL_SOURCE_DIR";
+/// 内核版本的环境变量
+pub const KERNEL_VERSION_ENV: &str = "DADK_KERNEL_VERSION";
+
+/// # 重新生成模块依赖索引
+///
+/// 在DADK的工作目录中通过`sh -c`执行`[kernel] depmod`，`DADK_SYSROOT_DIR`为sysroot的绝对路径。
+/// 没有安装内核模块，或者命令为空时不执行
+pub(crate) fn update_module_index(
+    context: &DadkUserExecuteContext,
+    sysroot: &Path,
+    tasks: &[DADKTask],
+) -> Result<(), ExecutorError> {
+    if !tasks.iter().any(|task| task.kernel_module) {
+        return Ok(());
+    }
+    let Some(kernel) = context.kernel() else {
+        return Ok(());
+    };
+    if kernel.depmod.trim().is_empty() {
+        return Ok(());
+    }
+
+    info!(
+        "Regenerating the module dependency index of kernel {}",
+        kernel.version
+    );
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&kernel.depmod)
+        .env("DADK_SYSROOT_DIR", abs_path(&sysroot.to_path_buf()))
+        .env(KERNEL_VERSION_ENV, &kernel.version)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| {
+            ExecutorError::InstallError(format!("Failed to run `{}`: {}", kernel.depmod, e))
+        })?;
+    if !output.status.success() {
+        return Err(ExecutorError::InstallError(format!(
+            "Failed to regenerate the module dependency index with `{}`: {}",
+            kernel.depmod,
+            StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
+        )));
+    }
+    Ok(())
+}
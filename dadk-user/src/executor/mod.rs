@@ -1,10 +1,9 @@
 use std::{
     collections::{BTreeMap, VecDeque},
-    env::Vars,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::{Arc, RwLock},
-    time::SystemTime,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
 use chrono::{DateTime, Utc};
@@ -13,31 +12,70 @@ use log::{debug, error, info, warn};
 
 use crate::{
     context::{Action, DadkUserExecuteContext},
+    error::ErrorCode,
+    event,
     executor::cache::CacheDir,
+    interrupt,
+    lock::{self, FileLock, LockMode},
+    metrics::TaskMetrics,
+    package,
     parser::{
-        task::{CodeSource, PrebuiltSource, TaskType},
-        task_log::{BuildStatus, InstallStatus, TaskLog},
+        task::{CodeSource, DADKTask, PrebuiltSource, TaskType},
+        task_log::{
+            BuildStatus, InstallStatus, Provenance, RebuildReason, SourceRevision, TaskLog,
+        },
     },
+    pkgdb::{self, InstalledPackage, PackageDatabase},
+    repository::{PackageRepository, RepositoryIndex},
     scheduler::{SchedEntities, SchedEntity},
-    utils::{file::FileUtils, path::abs_path},
+    utils::{
+        file::{CopyMode, CopyOptions, FileUtils},
+        path::abs_path,
+        stdio::StdioUtils,
+    },
 };
 
-use dadk_config::common::task::TaskEnv;
+use dadk_config::common::task::{CargoConfig, Dependency, TaskEnv};
 
-use self::cache::{CacheDirType, TaskDataDir};
+use self::{
+    backend::ExecutorBackend,
+    cache::{CacheDirType, TaskDataDir},
+    explain::CommandPlan,
+    patch::PatchTool,
+    resources::ResourceLimits,
+    source::ArchiveSource,
+};
 
+pub mod backend;
+mod build_system;
 pub mod cache;
+mod cargo;
+pub mod compiler_cache;
+pub mod explain;
+pub mod freshness;
+pub(crate) mod install;
+pub mod kernel_module;
+mod outputs;
+mod patch;
+pub mod provenance;
+pub(crate) mod resources;
+mod retry;
+pub mod shell;
 pub mod source;
 #[cfg(test)]
 mod tests;
+mod toolchain;
 
-lazy_static! {
-    // 全局环境变量的列表
-    pub static ref ENV_LIST: RwLock<EnvMap> = RwLock::new(EnvMap::new());
-}
+/// 命令执行失败时，输出的标准错误输出的行数
+const STDERR_TAIL_LINES: usize = 100;
+
+/// 命令退出后，等待读取剩余输出的最长时间
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Clone)]
 pub struct Executor {
+    /// dadk执行的上下文
+    context: Arc<DadkUserExecuteContext>,
     entity: Arc<SchedEntity>,
     action: Action,
     local_envs: EnvMap,
@@ -49,6 +87,16 @@ pub struct Executor {
     task_data_dir: TaskDataDir,
     /// DragonOS sysroot的路径
     dragonos_sysroot: PathBuf,
+    /// 实际执行构建/安装所花费的时间（因为没有变化而跳过时为None）
+    elapsed: Option<Duration>,
+    /// 实际执行构建的原因及其说明（跳过构建时为None）
+    rebuild_reason: Option<(RebuildReason, String)>,
+    /// 实际执行的构建的来源信息（跳过构建或者源文件没有准备好时为None）
+    provenance: Option<Provenance>,
+    /// 构建命令的执行后端
+    backend: Arc<dyn ExecutorBackend>,
+    /// 构建命令的资源限制
+    resources: Option<ResourceLimits>,
 }
 
 impl Executor {
@@ -58,6 +106,7 @@ impl Executor {
     ///
     /// ## 参数
     ///
+    /// * `context` - dadk执行的上下文
     /// * `entity` - 任务调度实体
     ///
     /// ## 返回值
@@ -65,21 +114,35 @@ impl Executor {
     /// * `Ok(Executor)` - 创建成功
     /// * `Err(ExecutorError)` - 创建失败
     pub fn new(
+        context: Arc<DadkUserExecuteContext>,
         entity: Arc<SchedEntity>,
         action: Action,
         dragonos_sysroot: PathBuf,
     ) -> Result<Self, ExecutorError> {
         let local_envs = EnvMap::new();
-        let build_dir = CacheDir::new(entity.clone(), CacheDirType::Build)?;
-        let task_data_dir = TaskDataDir::new(entity.clone())?;
+        let cache_root = context.cache_root();
+        let build_dir = CacheDir::new(cache_root, entity.clone(), CacheDirType::Build)?;
+        let task_data_dir = TaskDataDir::new(cache_root, entity.clone())?;
 
         let source_dir = if CacheDir::need_source_cache(&entity) {
-            Some(CacheDir::new(entity.clone(), CacheDirType::Source)?)
+            Some(CacheDir::new(
+                cache_root,
+                entity.clone(),
+                CacheDirType::Source,
+            )?)
         } else {
             None
         };
 
+        let resources = match (&action, &entity.task().build.resources) {
+            (Action::Build, Some(config)) => {
+                Some(ResourceLimits::from_config(config).map_err(ExecutorError::TaskFailed)?)
+            }
+            _ => None,
+        };
+        let backend = backend::create_backend(&context, resources);
         let result: Executor = Self {
+            context,
             action,
             entity,
             local_envs,
@@ -87,6 +150,11 @@ impl Executor {
             source_dir,
             task_data_dir,
             dragonos_sysroot,
+            elapsed: None,
+            rebuild_reason: None,
+            provenance: None,
+            backend,
+            resources,
         };
 
         return Ok(result);
@@ -104,15 +172,39 @@ impl Executor {
     pub fn execute(&mut self) -> Result<(), ExecutorError> {
         info!("Execute task: {}", self.entity.task().name_version());
 
+        // 执行期间持有任务的排他锁，防止其他dadk进程同时读写任务的缓存目录
+        let _lock = lock::lock_task(
+            self.context.cache_root(),
+            &self.entity.task().name_version(),
+            LockMode::Exclusive,
+            self.context.lock_timeout(),
+        )
+        .map_err(ExecutorError::PrepareEnvError)?;
         let r = self.do_execute();
-        self.save_task_data(r.clone());
+        let saved = self.save_task_data(r.clone());
+        self.record_metrics(&r);
         info!("Task {} finished", self.entity.task().name_version());
-        return r;
+        r.and(saved)
+    }
+
+    /// 记录构建和安装任务的执行结果，用于输出构建指标
+    fn record_metrics(&self, r: &Result<(), ExecutorError>) {
+        if matches!(self.action, Action::Clean(_)) {
+            return;
+        }
+        self.context.record_metrics(TaskMetrics {
+            task: self.entity.task().name_version(),
+            elapsed: self.elapsed,
+            failed: r.is_err(),
+            downloaded_bytes: self.entity.downloaded_bytes(),
+        });
     }
 
     /// # 保存任务数据
-    fn save_task_data(&self, r: Result<(), ExecutorError>) {
-        let mut task_log = self.task_data_dir.task_log();
+    fn save_task_data(&self, r: Result<(), ExecutorError>) -> Result<(), ExecutorError> {
+        let mut task_log = self.task_data_dir.task_log()?;
+        let task = self.entity.task();
+        task_log.set_task(&task.name, &task.version);
         match self.action {
             Action::Build => {
                 if r.is_ok() {
@@ -122,6 +214,16 @@ impl Executor {
                 }
 
                 task_log.set_build_time_now();
+                if let Some(elapsed) = self.elapsed {
+                    task_log.set_build_duration(elapsed, *self.context.session_start());
+                }
+                if let Some((reason, detail)) = &self.rebuild_reason {
+                    task_log.set_build_reason(*reason, detail);
+                }
+                // 构建失败时，构建目录中的内容不再对应之前记录的来源
+                if self.elapsed.is_some() {
+                    task_log.set_provenance(self.provenance.clone().filter(|_| r.is_ok()));
+                }
             }
 
             Action::Install => {
@@ -131,17 +233,19 @@ impl Executor {
                     task_log.set_install_status(InstallStatus::Failed);
                 }
                 task_log.set_install_time_now();
+                if let Some(elapsed) = self.elapsed {
+                    task_log.set_install_duration(elapsed);
+                }
             }
 
             Action::Clean(_) => {
                 task_log.clean_build_status();
                 task_log.clean_install_status();
+                task_log.clean_durations();
             }
         }
 
-        self.task_data_dir
-            .save_task_log(&task_log)
-            .expect("Failed to save task log");
+        self.task_data_dir.save_task_log(&task_log)
     }
 
     fn do_execute(&mut self) -> Result<(), ExecutorError> {
@@ -197,24 +301,85 @@ impl Executor {
     }
 
     fn build(&mut self) -> Result<(), ExecutorError> {
-        if let Some(status) = self.task_log().build_status() {
-            if let Some(build_time) = self.task_log().build_time() {
-                let mut last_modified = last_modified_time(&self.entity.file_path(), build_time)?;
-                last_modified = core::cmp::max(
-                    last_modified,
-                    last_modified_time(&self.src_work_dir(), build_time)?,
-                );
+        if self.context.is_dirty(&self.entity.task().name_version()) {
+            // 上次执行时被中断，构建结果可能不完整
+            info!(
+                "Task {} was interrupted last time, clean build dir and rebuild.",
+                self.entity.task().name_version()
+            );
+            self.rebuild_reason = Some((
+                RebuildReason::Interrupted,
+                "interrupted during the last build".to_string(),
+            ));
+            self.build_dir.remove_self_recursive()?;
+            self.build_dir.create()?;
+            return self.do_build();
+        }
 
-                if *status == BuildStatus::Success
-                    && (self.entity.task().build_once || last_modified < *build_time)
-                {
-                    info!(
-                        "Task {} has been built successfully, skip build.",
-                        self.entity.task().name_version()
-                    );
-                    return Ok(());
-                }
-            }
+        if self.context.no_build_cache() {
+            info!(
+                "Task {}: build cache is disabled, clean build dir and rebuild.",
+                self.entity.task().name_version()
+            );
+            self.rebuild_reason = Some((
+                RebuildReason::CacheDisabled,
+                "build cache is disabled".to_string(),
+            ));
+            self.build_dir.remove_self_recursive()?;
+            self.build_dir.create()?;
+            return self.do_build();
+        }
+
+        if self.context.refetch() && self.fetches_archive() {
+            info!(
+                "Task {} is requested to refetch the archive, ignore build cache.",
+                self.entity.task().name_version()
+            );
+            self.rebuild_reason = Some((
+                RebuildReason::Forced,
+                "requested to refetch the archive".to_string(),
+            ));
+            return self.do_build();
+        }
+
+        let task = self.entity.task();
+        let spec = format!("{}@{}", task.name, task.version);
+        if self.context.force()
+            || self
+                .context
+                .rebuild_tasks()
+                .iter()
+                .any(|t| *t == task.name || *t == spec)
+        {
+            info!(
+                "Task {} is requested to rebuild, ignore build cache.",
+                self.entity.task().name_version()
+            );
+            self.rebuild_reason = Some((RebuildReason::Forced, "requested to rebuild".to_string()));
+            return self.do_build();
+        }
+
+        let decision = freshness::build_decision(
+            &self.entity.task(),
+            &self.task_log()?,
+            self.context.cache_root(),
+            &self.entity.file_path(),
+            &self.src_work_dir(),
+        )?;
+        if decision.skip {
+            info!(
+                "Task {} has been built successfully, skip build.",
+                self.entity.task().name_version()
+            );
+            return Ok(());
+        }
+        info!(
+            "Task {}: {}, build.",
+            self.entity.task().name_version(),
+            decision.reason
+        );
+        if let Some(reason) = decision.rebuild {
+            self.rebuild_reason = Some((reason, decision.reason));
         }
 
         return self.do_build();
@@ -241,16 +406,42 @@ impl Executor {
 
     /// # 执行build操作
     fn do_build(&mut self) -> Result<(), ExecutorError> {
-        // 确认源文件就绪
+        let start = Instant::now();
+        let r = self.do_build_inner();
+        self.elapsed = Some(start.elapsed());
+        r
+    }
+
+    fn do_build_inner(&mut self) -> Result<(), ExecutorError> {
+        // 确认源文件就绪。共享的源码目录在拉取时不能被其他任务使用，构建时不能被其他任务更新
+        let source_lock = self.lock_shared_source(LockMode::Exclusive)?;
         self.prepare_input()?;
+        self.provenance = Some(self.collect_provenance()?);
+        drop(source_lock);
+        let _source_lock = self.lock_shared_source(LockMode::Shared)?;
+        if self.entity.task().cargo.is_some() {
+            self.prepare_rust_toolchain()?;
+        }
+        self.prepare_compiler_cache()?;
 
         let command: Option<Command> = self.create_command()?;
         if let Some(cmd) = command {
             self.run_command(cmd)?;
         }
+        if let Some(cargo) = &self.entity.task().cargo {
+            self.copy_cargo_artifacts(cargo)?;
+        }
 
-        // 检查构建结果，如果为空，则抛出警告
-        if self.build_dir.is_empty()? {
+        // 检查构建结果：配置了期望的文件时逐个检查，否则在构建结果为空时抛出警告
+        if let Some(outputs) = &self.entity.task().build.outputs {
+            outputs::check_outputs(&self.build_dir.path, &outputs.files).map_err(|e| {
+                ExecutorError::TaskFailed(format!(
+                    "Task {}: {}",
+                    self.entity.task().name_version(),
+                    e
+                ))
+            })?;
+        } else if self.build_dir.is_empty()? {
             warn!(
                 "Task {}: build result is empty, do you forget to copy the result to [$DADK_CURRENT_BUILD_DIR]?",
                 self.entity.task().name_version(),
@@ -259,32 +450,66 @@ impl Executor {
         return Ok(());
     }
 
-    fn install(&self) -> Result<(), ExecutorError> {
+    fn install(&mut self) -> Result<(), ExecutorError> {
         log::trace!("dadk-user: install {}", self.entity.task().name_version());
-        if let Some(status) = self.task_log().install_status() {
-            if let Some(install_time) = self.task_log().install_time() {
-                let last_modified = last_modified_time(&self.build_dir.path, install_time)?;
-                let last_modified = core::cmp::max(
-                    last_modified,
-                    last_modified_time(&self.entity.file_path(), install_time)?,
+        let dirty = self.context.is_dirty(&self.entity.task().name_version());
+        if dirty {
+            info!(
+                "Task {} was interrupted last time, reinstall.",
+                self.entity.task().name_version()
+            );
+        } else if self.context.force() {
+            info!(
+                "Task {} is requested to reinstall.",
+                self.entity.task().name_version()
+            );
+        } else {
+            let decision = freshness::install_decision(
+                &self.entity.task(),
+                &self.task_log()?,
+                &self.entity.file_path(),
+                &self.build_dir.path,
+            )?;
+            if decision.skip && self.removed_from_sysroot()? {
+                // 任务安装的文件已经被`dadk rootfs delete-sysroot`删除
+                info!(
+                    "Task {} is not installed in the sysroot, reinstall.",
+                    self.entity.task().name_version()
                 );
-
-                if *status == InstallStatus::Success
-                    && (self.entity.task().install_once || last_modified < *install_time)
-                {
-                    info!(
-                        "install: Task {} not changed.",
-                        self.entity.task().name_version()
-                    );
-                    return Ok(());
-                }
+            } else if decision.skip {
+                info!(
+                    "install: Task {} not changed.",
+                    self.entity.task().name_version()
+                );
+                return Ok(());
             }
+            debug!(
+                "Task {}: {}, install.",
+                self.entity.task().name_version(),
+                decision.reason
+            );
         }
         log::trace!(
             "dadk-user: to do install {}",
             self.entity.task().name_version()
         );
-        return self.do_install();
+        let start = Instant::now();
+        let r = self.do_install();
+        self.elapsed = Some(start.elapsed());
+        r
+    }
+
+    /// 任务在sysroot的软件包数据库中的记录是否被`dadk rootfs delete-sysroot`删除。
+    ///
+    /// 数据库中没有记录的任务（例如在引入数据库之前安装的任务）不会因此被重新安装
+    fn removed_from_sysroot(&self) -> Result<bool, ExecutorError> {
+        let task = self.entity.task();
+        if task.install.in_dragonos_path.is_none() {
+            return Ok(false);
+        }
+        let db = PackageDatabase::load(&abs_path(&self.dragonos_sysroot))
+            .map_err(ExecutorError::InstallError)?;
+        Ok(db.is_removed(&task.name))
     }
 
     /// # 执行安装操作，把构建结果安装到DragonOS
@@ -305,18 +530,82 @@ impl Executor {
             in_dragonos_path = in_dragonos_path[count_leading_slashes..].to_string();
         }
         // 拼接最终的安装路径
-        let install_path = abs_path(&self.dragonos_sysroot.join(in_dragonos_path));
+        let install_path = abs_path(&self.dragonos_sysroot.join(&in_dragonos_path));
         debug!("install_path: {:?}", install_path);
         // 创建安装路径
         std::fs::create_dir_all(&install_path).map_err(|e| {
             ExecutorError::InstallError(format!("Failed to create install path: {}", e.to_string()))
         })?;
 
-        // 拷贝构建结果到安装路径
+        // 拷贝构建结果到暂存目录，并在暂存目录中strip ELF文件、按照配置创建符号链接、设置权限
+        // 不是默认版本的任务不创建不带版本号的符号链接
+        let files = &binding.install_files();
+        let staging = CacheDir::new(
+            self.context.cache_root(),
+            self.entity.clone(),
+            CacheDirType::Staging,
+        )?;
+        staging.remove_self_recursive()?;
+        staging.create()?;
         let build_dir: PathBuf = self.build_dir.path.clone();
-        FileUtils::copy_dir_all(&build_dir, &install_path)
-            .map_err(|e| ExecutorError::InstallError(e))?;
+        // 保留构建结果的修改时间，安装到sysroot中的文件不会因为重新安装而变成“新”文件
+        FileUtils::copy_dir_with_options(
+            &build_dir,
+            &staging.path,
+            CopyOptions {
+                preserve_times: true,
+                ..Default::default()
+            },
+        )
+        .map_err(ExecutorError::InstallError)?;
+        let excluded = install::remove_excluded(&staging.path, &binding.install.exclude)?;
+        if excluded > 0 {
+            info!(
+                "Task {}: excluded {} files and directories from installation",
+                self.entity.task().name_version(),
+                excluded
+            );
+        }
+        if binding.install.strip {
+            let tool = binding
+                .install
+                .strip_tool
+                .clone()
+                .unwrap_or_else(|| install::default_strip_tool(*self.context.target_arch()));
+            let stripped = install::strip_elf_files(&staging.path, &tool)?;
+            info!(
+                "Task {}: stripped {} ELF files with {}",
+                self.entity.task().name_version(),
+                stripped,
+                tool
+            );
+        }
+        install::prepare_staging(files, &staging.path)?;
+
+        // 登记要安装的文件，确认没有与其他任务冲突
+        let installed = pkgdb::installed_files(&staging.path, Path::new(&in_dragonos_path))
+            .map_err(ExecutorError::InstallError)?;
+        self.context
+            .install_claims()
+            .claim(
+                &self.entity.task().name_version(),
+                self.entity.dependents(),
+                &installed,
+            )
+            .map_err(ExecutorError::InstallError)?;
+
+        // 把暂存目录同步到安装路径（保留权限和符号链接），然后设置属主
+        FileUtils::sync_dir_all(&staging.path, &install_path)
+            .map_err(ExecutorError::InstallError)?;
+        install::apply_ownership(files, &install_path)?;
+
+        // 在sysroot的软件包数据库中记录安装的文件
+        let package =
+            InstalledPackage::new(binding.name.clone(), binding.version.clone(), installed);
+        PackageDatabase::update(&abs_path(&self.dragonos_sysroot), |db| db.record(package))
+            .map_err(ExecutorError::InstallError)?;
         info!("Task {} installed.", self.entity.task().name_version());
+        event::install_done(&self.entity, &install_path.to_string_lossy());
 
         return Ok(());
     }
@@ -373,6 +662,7 @@ impl Executor {
             // 如果这里没有命令，则认为用户不需要在源文件目录执行清理
             return Ok(());
         }
+        let _source_lock = self.lock_shared_source(LockMode::Exclusive)?;
         info!(
             "{}: Cleaning in source directory: {:?}",
             self.entity.task().name_version(),
@@ -392,7 +682,16 @@ impl Executor {
             self.build_dir.path
         );
 
-        return self.build_dir.remove_self_recursive();
+        self.build_dir.remove_self_recursive()?;
+        let staging = CacheDir::get_path(
+            self.context.cache_root(),
+            &self.entity.task(),
+            CacheDirType::Staging,
+        );
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging).map_err(|e| ExecutorError::IoError(e.to_string()))?;
+        }
+        Ok(())
     }
 
     /// 清理下载缓存
@@ -407,9 +706,28 @@ impl Executor {
             self.entity.task().name_version(),
             self.src_work_dir().display()
         );
+        let _source_lock = self.lock_shared_source(LockMode::Exclusive)?;
         return cache_dir.unwrap().remove_self_recursive();
     }
 
+    /// 对共用的源码缓存目录加锁，任务没有设置`source-cache-key`时返回None
+    fn lock_shared_source(&self, mode: LockMode) -> Result<Option<FileLock>, ExecutorError> {
+        let Some(key) = &self.entity.task().source_cache_key else {
+            return Ok(None);
+        };
+        if self.source_dir.is_none() {
+            return Ok(None);
+        }
+        lock::lock_shared_source(
+            self.context.cache_root(),
+            key,
+            mode,
+            self.context.lock_timeout(),
+        )
+        .map(Some)
+        .map_err(ExecutorError::PrepareEnvError)
+    }
+
     /// 获取源文件的工作目录
     fn src_work_dir(&self) -> PathBuf {
         if let Some(local_path) = self.entity.task().source_path() {
@@ -418,16 +736,34 @@ impl Executor {
         return self.source_dir.as_ref().unwrap().path.clone();
     }
 
-    fn task_log(&self) -> TaskLog {
+    fn task_log(&self) -> Result<TaskLog, ExecutorError> {
         return self.task_data_dir.task_log();
     }
 
     /// 为任务创建命令
     fn create_command(&self) -> Result<Option<Command>, ExecutorError> {
+        Ok(self
+            .task_command()?
+            .map(|command| self.prepare_command(command)))
+    }
+
+    /// 任务的构建、清理命令（尚未设置工作目录和环境变量）
+    fn task_command(&self) -> Result<Option<Command>, ExecutorError> {
+        // cargo任务由DADK执行cargo build
+        if let (Some(cargo), Action::Build) = (&self.entity.task().cargo, &self.action) {
+            let mut command = Command::new("cargo");
+            command.args(cargo::build_args(
+                cargo,
+                *self.context.target_arch(),
+                self.context.offline(),
+            ));
+            return Ok(Some(command));
+        }
+
         // 获取命令
         let raw_cmd = match self.entity.task().task_type {
             TaskType::BuildFromSource(_) => match self.action {
-                Action::Build => self.entity.task().build.build_command.clone(),
+                Action::Build => self.generated_build_command(),
                 Action::Clean(_) => self.entity.task().clean.clean_command.clone(),
                 _ => unimplemented!(
                     "create_command: Action {:?} not supported yet.",
@@ -450,16 +786,55 @@ impl Executor {
         }
 
         let raw_cmd = raw_cmd.unwrap();
+        let build = &self.entity.task().build;
+        Ok(Some(shell::command(
+            build.shell.unwrap_or(self.context.shell()),
+            build.shell_strict.unwrap_or(self.context.shell_strict()),
+            &raw_cmd,
+        )))
+    }
 
-        let mut command = Command::new("bash");
-        command.current_dir(self.src_work_dir());
+    /// 构建命令：cmake、autotools任务由DADK生成，其他任务使用配置文件中的构建命令
+    fn generated_build_command(&self) -> Option<String> {
+        let task = self.entity.task();
+        let arch = *self.context.target_arch();
+        let build_dir = abs_path(&self.build_dir.path);
+        if let Some(cmake) = &task.cmake {
+            let sysroot = abs_path(&self.dragonos_sysroot);
+            return Some(build_system::cmake_script(
+                cmake, arch, &build_dir, &sysroot,
+            ));
+        }
+        if let Some(autotools) = &task.autotools {
+            return Some(build_system::autotools_script(autotools, arch, &build_dir));
+        }
+        task.build.build_command.clone()
+    }
 
-        // 设置参数
-        command.arg("-c");
-        command.arg(raw_cmd);
+    /// 设置命令的工作目录、环境变量，并转换为在执行后端中执行的命令
+    fn prepare_command(&self, command: Command) -> Command {
+        let command = self.setup_command(command);
+        if self.context.verbose() {
+            let plan = CommandPlan::from_command(
+                &self.entity.task().name_version(),
+                &command,
+                &explain::parent_envs(),
+            );
+            info!("Running command:\n{}", plan.text().trim_end());
+        }
+        self.backend.command(command)
+    }
 
+    /// 设置命令的工作目录和环境变量
+    fn setup_command(&self, mut command: Command) -> Command {
+        command.current_dir(self.src_work_dir());
+
+        // 指定了`[env] passthrough`时，不继承dadk进程的环境变量，只使用全局环境变量列表中的变量
+        if self.context.env_config().passthrough.is_some() {
+            command.env_clear();
+        }
         // 设置环境变量
-        let env_list = ENV_LIST.read().unwrap();
+        let env_list = self.context.global_env_list().read().unwrap();
         for (key, value) in env_list.envs.iter() {
             // if key.starts_with("DADK") {
             //     debug!("DADK env found: {}={}", key, value.value);
@@ -471,8 +846,119 @@ impl Executor {
             debug!("Local env found: {}={}", key, value.value);
             command.env(key, value.value.clone());
         }
+        command
+    }
 
-        return Ok(Some(command));
+    /// # 说明任务构建时将要执行的命令
+    ///
+    /// 准备任务的环境变量，返回构建命令、工作目录以及与dadk进程不同的环境变量，但不执行命令
+    pub fn explain(&mut self) -> Result<CommandPlan, ExecutorError> {
+        self.prepare_local_env()?;
+        if self.entity.task().cargo.is_some() {
+            self.prepare_rust_toolchain()?;
+        }
+        self.prepare_compiler_cache()?;
+        let name_version = self.entity.task().name_version();
+        let plan = match self.task_command()? {
+            Some(command) => CommandPlan::from_command(
+                &name_version,
+                &self.setup_command(command),
+                &explain::parent_envs(),
+            ),
+            None => CommandPlan::without_command(&name_version),
+        };
+        Ok(plan)
+    }
+
+    /// 确定cargo任务使用的Rust工具链，检查已经安装后通过`RUSTUP_TOOLCHAIN`传给cargo
+    fn prepare_rust_toolchain(&mut self) -> Result<(), ExecutorError> {
+        let task = self.entity.task();
+        let toolchain = match self.local_envs.get(toolchain::RUSTUP_TOOLCHAIN) {
+            // 任务中设置的工具链优先
+            Some(env) => Some(toolchain::Toolchain {
+                channel: env.value.clone(),
+                source: format!("envs of task {}", task.name_version()),
+            }),
+            None => toolchain::resolve(
+                &abs_path(&self.src_work_dir()),
+                self.context.rust_toolchain(),
+            )
+            .map_err(ExecutorError::PrepareEnvError)?,
+        };
+        let Some(toolchain) = toolchain else {
+            return Ok(());
+        };
+        // 在容器中构建时，工具链由容器镜像提供
+        if self.context.container().is_none() {
+            toolchain::check_installed(&toolchain).map_err(|e| {
+                ExecutorError::PrepareEnvError(format!("Task {}: {}", task.name_version(), e))
+            })?;
+        }
+        info!(
+            "Task {}: using rust toolchain {} (from {})",
+            task.name_version(),
+            toolchain.channel,
+            toolchain.source
+        );
+        self.local_envs.add(EnvVar::new(
+            toolchain::RUSTUP_TOOLCHAIN.to_string(),
+            toolchain.channel,
+        ));
+        Ok(())
+    }
+
+    /// 任务启用了编译缓存时，检查wrapper已经安装，并设置使用它的环境变量
+    fn prepare_compiler_cache(&mut self) -> Result<(), ExecutorError> {
+        let task = self.entity.task();
+        let config = self.context.compiler_cache();
+        // 在容器中构建时，wrapper由容器镜像提供
+        if self.context.container().is_none() {
+            for wrapper in compiler_cache::wrappers(config, &task) {
+                if compiler_cache::find(&wrapper).is_none() {
+                    return Err(ExecutorError::PrepareEnvError(format!(
+                        "Task {}: compiler cache wrapper {} not found, install it or disable compiler-cache",
+                        task.name_version(),
+                        wrapper
+                    )));
+                }
+            }
+        }
+        let envs = compiler_cache::envs(config, &task, |key| {
+            self.local_envs.get(key).map(|env| env.value.clone())
+        });
+        for (key, value) in envs {
+            self.local_envs.add(EnvVar::new(key, value));
+        }
+        Ok(())
+    }
+
+    /// 把cargo构建出来的二进制文件复制到构建缓存目录
+    fn copy_cargo_artifacts(&self, cargo: &CargoConfig) -> Result<(), ExecutorError> {
+        let mut command = Command::new("cargo");
+        command.args(cargo::METADATA_ARGS);
+        let output = self
+            .prepare_command(command)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| ExecutorError::IoError(e.to_string()))?;
+        if !output.status.success() {
+            return Err(ExecutorError::TaskFailed(format!(
+                "Task {}: cargo metadata failed: {}",
+                self.entity.task().name_version(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let (target_dir, bins) =
+            cargo::parse_metadata(&String::from_utf8_lossy(&output.stdout), &cargo.bins)?;
+        let artifact_dir = cargo::artifact_dir(&target_dir, cargo, *self.context.target_arch());
+        let copied = cargo::copy_artifacts(&artifact_dir, &bins, &self.build_dir.path)?;
+        info!(
+            "Task {}: copied {} cargo artifact(s) from {}",
+            self.entity.task().name_version(),
+            copied,
+            artifact_dir.display()
+        );
+        Ok(())
     }
 
     /// # 准备工作线程本地环境变量
@@ -493,9 +979,91 @@ impl Executor {
             self.build_dir.path.to_str().unwrap().to_string(),
         ));
 
+        // 添加`DADK_CURRENT_SOURCE_DIR`环境变量，指向当前任务的源码目录（本地路径或者源码缓存目录）
+        if self.source_dir.is_some() || binding.source_path().is_some() {
+            self.local_envs.add(EnvVar::new(
+                "DADK_CURRENT_SOURCE_DIR".to_string(),
+                abs_path(&self.src_work_dir()).to_str().unwrap().to_string(),
+            ));
+        }
+
+        // cmake、autotools任务交叉编译时，设置工具链相关的环境变量（任务中设置的同名变量优先）
+        if binding.cmake.is_some() || binding.autotools.is_some() {
+            if self.dragonos_sysroot.as_os_str().is_empty() {
+                return Err(ExecutorError::PrepareEnvError(format!(
+                    "Task {}: cmake and autotools tasks need the DragonOS sysroot dir",
+                    binding.name_version()
+                )));
+            }
+            let sysroot = abs_path(&self.dragonos_sysroot);
+            for (key, value) in build_system::cross_envs(*self.context.target_arch(), &sysroot) {
+                if self.local_envs.get(&key).is_none() {
+                    self.local_envs.add(EnvVar::new(key, value));
+                }
+            }
+        }
+
+        // 当前任务以及直接依赖的版本号，便于构建脚本把版本号写入程序或者pkg-config文件
+        self.local_envs.add(EnvVar::new(
+            "DADK_TASK_VERSION".to_string(),
+            binding.version.clone(),
+        ));
+        let aliased = self.aliased_depends(&binding.depends);
+        for dep in aliased.iter() {
+            self.local_envs.add(EnvVar::new(
+                format!("DADK_DEP_{}_VERSION", DADKTask::env_name(&dep.name)),
+                dep.version.clone(),
+            ));
+        }
+
+        // 为直接依赖的构建目录、源码目录添加不带版本号的别名
+        let global_envs = self.context.global_env_list().read().unwrap();
+        for dep in aliased.iter() {
+            let dep_name_version = DADKTask::name_version_uppercase(&dep.name, &dep.version);
+            for prefix in [
+                CacheDir::DADK_BUILD_CACHE_DIR_ENV_KEY_PREFIX,
+                CacheDir::DADK_SOURCE_CACHE_DIR_ENV_KEY_PREFIX,
+            ] {
+                if let Some(env) = global_envs.get(&format!("{}_{}", prefix, dep_name_version)) {
+                    self.local_envs.add(EnvVar::new(
+                        format!("{}_{}", prefix, DADKTask::env_name(&dep.name)),
+                        env.value.clone(),
+                    ));
+                }
+            }
+        }
+
         return Ok(());
     }
 
+    /// # 使用不带版本号的环境变量的直接依赖
+    ///
+    /// 依赖同一个程序的多个版本时，只有默认版本（`default-versions`）使用不带版本号的环境变量，
+    /// 没有默认版本时都不使用，只能通过带版本号的环境变量访问
+    fn aliased_depends<'a>(&self, depends: &'a [Dependency]) -> Vec<&'a Dependency> {
+        let mut by_name: BTreeMap<&str, Vec<&Dependency>> = BTreeMap::new();
+        for dep in depends {
+            by_name.entry(dep.name.as_str()).or_default().push(dep);
+        }
+        let mut result = Vec::new();
+        for (name, deps) in by_name {
+            if deps.len() == 1 {
+                result.extend(deps);
+                continue;
+            }
+            let default = self.context.default_versions().get(name);
+            match deps.iter().find(|d| Some(&d.version) == default) {
+                Some(dep) => result.push(*dep),
+                None => warn!(
+                    "Task {} depends on several versions of {}, set its default version to get the unversioned environment variables",
+                    self.entity.task().name_version(),
+                    name
+                ),
+            }
+        }
+        result
+    }
+
     fn prepare_input(&self) -> Result<(), ExecutorError> {
         // 拉取源文件
         let task = self.entity.task();
@@ -505,37 +1073,71 @@ impl Executor {
                     return Ok(());
                 }
                 let source_dir = self.source_dir.as_ref().unwrap();
-                match cs {
+                let tool = match cs {
                     CodeSource::Git(git) => {
-                        git.prepare(source_dir)
-                            .map_err(|e| ExecutorError::PrepareEnvError(e))?;
+                        if !task.patches.is_empty() {
+                            patch::reset_git_source(&source_dir.path)
+                                .map_err(ExecutorError::PrepareEnvError)?;
+                        }
+                        let git = git
+                            .clone()
+                            .with_timeouts(*self.context.git_timeouts())
+                            .with_mirror(self.mirror(git.url()));
+                        if self.context.offline() {
+                            git.prepare_offline(source_dir)
+                                .map_err(ExecutorError::FetchFailed)?;
+                        } else {
+                            self.fetch_with_retries(|| git.prepare(source_dir))?;
+                        }
+                        PatchTool::Git
                     }
                     // 本地源文件，不需要拉取
                     CodeSource::Local(_) => return Ok(()),
                     // 在线压缩包，需要下载
                     CodeSource::Archive(archive) => {
-                        archive
-                            .download_unzip(source_dir)
-                            .map_err(|e| ExecutorError::PrepareEnvError(e))?;
+                        let archive = &archive.clone().with_mirror(self.mirror(archive.url()));
+                        self.check_offline_cache(archive, source_dir)?;
+                        let stamp = self.archive_stamp(source_dir);
+                        self.fetch_with_retries(|| {
+                            archive.download_unzip(source_dir, &stamp, self.context.refetch())
+                        })?;
+                        PatchTool::Patch
                     }
+                };
+                if !task.patches.is_empty() {
+                    patch::apply_patches(&source_dir.path, &task.patches, tool)
+                        .map_err(ExecutorError::TaskFailed)?;
                 }
             }
+            TaskType::InstallFromPrebuilt(pb) if task.from_package => {
+                return self.prepare_package(pb);
+            }
             TaskType::InstallFromPrebuilt(pb) => {
                 match pb {
                     // 本地源文件，不需要拉取
                     PrebuiltSource::Local(local_source) => {
                         let local_path = local_source.path();
                         let target_path = &self.build_dir.path;
-                        FileUtils::copy_dir_all(&local_path, &target_path)
-                            .map_err(|e| ExecutorError::TaskFailed(e))?; // let mut cmd = "cp -r ".to_string();
+                        let mode = if self.context.hardlink_prebuilt() {
+                            CopyMode::Hardlink
+                        } else {
+                            CopyMode::Auto
+                        };
+                        FileUtils::copy_dir_with(local_path, target_path, mode)
+                            .map_err(ExecutorError::TaskFailed)?;
                         return Ok(());
                     }
                     // 在线压缩包，需要下载
                     PrebuiltSource::Archive(archive) => {
-                        archive
-                            .download_unzip(&self.build_dir)
-                            .map_err(|e| ExecutorError::PrepareEnvError(e))?;
+                        let archive = &archive.clone().with_mirror(self.mirror(archive.url()));
+                        self.check_offline_cache(archive, &self.build_dir)?;
+                        let stamp = self.archive_stamp(&self.build_dir);
+                        self.fetch_with_retries(|| {
+                            archive.download_unzip(&self.build_dir, &stamp, self.context.refetch())
+                        })?;
                     }
+                    // 软件仓库中的包都是二进制包
+                    PrebuiltSource::Repository(_) => return self.prepare_package(pb),
                 }
             }
         }
@@ -543,45 +1145,357 @@ impl Executor {
         return Ok(());
     }
 
-    fn run_command(&self, mut command: Command) -> Result<(), ExecutorError> {
-        let mut child = command
-            .stdin(Stdio::inherit())
-            .spawn()
-            .map_err(|e| ExecutorError::IoError(e.to_string()))?;
+    /// 把二进制包中的文件解压到构建缓存目录
+    fn prepare_package(&self, source: &PrebuiltSource) -> Result<(), ExecutorError> {
+        let task = self.entity.task();
+        let arch = *self.context.target_arch();
+        let manifest = match source {
+            PrebuiltSource::Local(local) => {
+                package::unpack(local.path(), &self.build_dir.path, arch)
+                    .map_err(ExecutorError::TaskFailed)?
+            }
+            PrebuiltSource::Archive(_) if self.context.offline() => {
+                return Err(ExecutorError::FetchFailed(format!(
+                    "Task {}: can't download the package in offline mode",
+                    task.name_version()
+                )));
+            }
+            PrebuiltSource::Archive(archive) => {
+                let archive = archive.clone().with_mirror(self.mirror(archive.url()));
+                let download_dir = self.task_data_dir.path().join("package");
+                let mut downloaded = PathBuf::new();
+                self.fetch_with_retries(|| {
+                    downloaded = archive.download(&download_dir, &self.entity)?;
+                    Ok(())
+                })?;
+                let r = package::unpack(&downloaded, &self.build_dir.path, arch);
+                std::fs::remove_dir_all(&download_dir).ok();
+                r.map_err(ExecutorError::TaskFailed)?
+            }
+            PrebuiltSource::Repository(repo) => {
+                let downloaded = self.fetch_from_repository(repo.name())?;
+                package::unpack(&downloaded, &self.build_dir.path, arch)
+                    .map_err(ExecutorError::TaskFailed)?
+            }
+        };
+        if manifest.name != task.name || manifest.version != task.version {
+            warn!(
+                "Task {}: installing package {}-{}",
+                task.name_version(),
+                manifest.name,
+                manifest.version
+            );
+        }
+        Ok(())
+    }
 
-        // 等待子进程结束
-        let r = child
-            .wait()
-            .map_err(|e| ExecutorError::IoError(e.to_string()));
+    /// 从软件仓库获取任务的二进制包，返回本地缓存中的文件路径
+    fn fetch_from_repository(&self, name: &str) -> Result<PathBuf, ExecutorError> {
+        let task = self.entity.task();
+        let arch = *self.context.target_arch();
+        let url = self.context.package_repository().ok_or_else(|| {
+            ExecutorError::PrepareEnvError(format!(
+                "Task {}: package-repository is not set in dadk-manifest.toml",
+                task.name_version()
+            ))
+        })?;
+        let repo = PackageRepository::new(url, self.context.cache_root())
+            .map_err(ExecutorError::PrepareEnvError)?
+            .offline(self.context.offline());
+
+        let mut index = RepositoryIndex::default();
+        self.fetch_with_retries(|| {
+            index = repo.index()?;
+            Ok(())
+        })?;
+        let entry = index.find(name, &task.version, arch).ok_or_else(|| {
+            let arch: &str = arch.into();
+            ExecutorError::FetchFailed(format!(
+                "Package {}-{} ({}) not found in the package repository",
+                name, task.version, arch
+            ))
+        })?;
+
+        let mut downloaded = PathBuf::new();
+        // 下载完成时进度回调的参数就是下载的字节数，使用缓存时不会调用
+        let mut bytes = 0;
+        let url = repo
+            .package_url(entry)
+            .map_err(ExecutorError::PrepareEnvError)?;
+        self.fetch_with_retries(|| {
+            bytes = 0;
+            downloaded = repo.fetch_package(entry, |done, total| {
+                bytes = done;
+                event::download_progress(&self.entity, &url, done, total)
+            })?;
+            Ok(())
+        })?;
+        self.entity.add_downloaded_bytes(bytes);
+        Ok(downloaded)
+    }
+
+    /// 本次构建的来源信息。在源文件准备好（拉取、解压、打补丁）之后调用
+    fn collect_provenance(&self) -> Result<Provenance, ExecutorError> {
+        let task = self.entity.task();
+        let source = match &task.task_type {
+            TaskType::BuildFromSource(CodeSource::Git(git)) => SourceRevision::Git {
+                url: git.url().to_string(),
+                commit: self
+                    .source_dir
+                    .as_ref()
+                    .and_then(|dir| provenance::git_commit(&dir.path))
+                    .unwrap_or_default(),
+            },
+            TaskType::BuildFromSource(CodeSource::Archive(archive)) => SourceRevision::Archive {
+                url: archive.url().to_string(),
+                sha256: self
+                    .source_dir
+                    .as_ref()
+                    .and_then(|dir| archive.cached_sha256(&self.archive_stamp(dir))),
+            },
+            TaskType::BuildFromSource(CodeSource::Local(local))
+            | TaskType::InstallFromPrebuilt(PrebuiltSource::Local(local)) => {
+                provenance::local_revision(local.path())
+            }
+            TaskType::InstallFromPrebuilt(PrebuiltSource::Archive(archive)) => {
+                SourceRevision::Archive {
+                    url: archive.url().to_string(),
+                    sha256: (!task.from_package)
+                        .then(|| archive.cached_sha256(&self.archive_stamp(&self.build_dir)))
+                        .flatten(),
+                }
+            }
+            TaskType::InstallFromPrebuilt(PrebuiltSource::Repository(repo)) => {
+                SourceRevision::Repository {
+                    name: repo.name().to_string(),
+                    sha256: None,
+                }
+            }
+        };
+        let patches =
+            provenance::patch_digests(&task.patches).map_err(ExecutorError::TaskFailed)?;
+        Ok(Provenance {
+            source,
+            patches,
+            builder: provenance::builder_info(self.context.container()),
+            dadk_version: provenance::DADK_VERSION.to_string(),
+        })
+    }
+
+    /// 拉取`url`时使用的镜像地址（manifest中的`[mirrors]`），任务设置了`no-mirror`时不使用镜像
+    fn mirror(&self, url: &str) -> Option<String> {
+        if self.entity.task().no_mirror {
+            return None;
+        }
+        self.context.mirrors().rewrite(url)
+    }
+
+    /// 记录压缩包来源的文件
+    ///
+    /// 源码缓存目录中的文件不会被安装，记录在源码目录中；
+    /// 二进制压缩包解压到构建缓存目录，其中的文件都会被安装，因此记录在任务数据目录中
+    fn archive_stamp(&self, target_dir: &CacheDir) -> PathBuf {
+        match target_dir.cache_type {
+            CacheDirType::Source => target_dir.path.join(source::ARCHIVE_STAMP),
+            _ => self.task_data_dir.path().join(source::ARCHIVE_STAMP),
+        }
+    }
+
+    /// 任务是否从在线压缩包获取源码或者构建结果
+    fn fetches_archive(&self) -> bool {
+        let task = self.entity.task();
+        match &task.task_type {
+            TaskType::BuildFromSource(CodeSource::Archive(_)) => true,
+            TaskType::InstallFromPrebuilt(PrebuiltSource::Archive(_)) => !task.from_package,
+            _ => false,
+        }
+    }
+
+    /// 离线模式下，压缩包必须已经下载并解压到缓存目录中
+    fn check_offline_cache(
+        &self,
+        archive: &ArchiveSource,
+        target_dir: &CacheDir,
+    ) -> Result<(), ExecutorError> {
+        if !self.context.offline() {
+            return Ok(());
+        }
+        if self.context.refetch() {
+            return Err(ExecutorError::FetchFailed(format!(
+                "Task {}: can't refetch archive {} in offline mode",
+                self.entity.task().name_version(),
+                archive.url()
+            )));
+        }
+        if archive
+            .is_cached(target_dir, &self.archive_stamp(target_dir))
+            .map_err(ExecutorError::IoError)?
+        {
+            return Ok(());
+        }
+        Err(ExecutorError::FetchFailed(format!(
+            "Task {}: archive {} is not in the cache {}, can't download it in offline mode",
+            self.entity.task().name_version(),
+            archive.url(),
+            target_dir.path.display()
+        )))
+    }
+
+    /// 拉取源文件，失败时按照任务的`retries`（未设置时使用全局默认值）重试
+    fn fetch_with_retries(
+        &self,
+        fetch: impl FnMut() -> Result<(), String>,
+    ) -> Result<(), ExecutorError> {
+        let task = self.entity.task();
+        let retries = task.retries.unwrap_or(self.context.retries());
+        let what = format!("Fetching source of task {}", task.name_version());
+        retry::retry(
+            &what,
+            retries,
+            retry::RETRY_BASE_DELAY,
+            self.context.interrupt_state(),
+            fetch,
+        )
+        .map_err(ExecutorError::FetchFailed)
+    }
+
+    /// 当前操作的命令的超时时间
+    fn command_timeout(&self) -> Result<Option<Duration>, ExecutorError> {
+        let task = self.entity.task();
+        let timeout = match self.action {
+            Action::Build => task.build.timeout_duration(),
+            Action::Clean(_) => task.clean.timeout_duration(),
+            Action::Install => Ok(None),
+        };
+        timeout.map_err(|e| ExecutorError::TaskFailed(e.to_string()))
+    }
+
+    fn run_command(&self, mut command: Command) -> Result<(), ExecutorError> {
+        let timeout = self.command_timeout()?;
+        let capture = self.context.capture_output();
+        if capture {
+            command.stdout(Stdio::piped());
+        }
+        // 在容器中构建时，资源限制由容器引擎实现
+        let _cgroup = match (&self.resources, self.context.container()) {
+            (Some(limits), None) => resources::apply(
+                &mut command,
+                limits,
+                &format!(
+                    "{}-{}",
+                    self.entity.task().name_version(),
+                    String::from(*self.context.target_arch())
+                ),
+                self.context.cgroup_lease(),
+            ),
+            _ => None,
+        };
+        // 构建命令运行在独立的进程组中，不在终端的前台进程组，因此不能从终端读取输入
+        let (mut child, tracked) =
+            interrupt::spawn_tracked(command.stdin(Stdio::null()).stderr(Stdio::piped()))
+                .map_err(|e| ExecutorError::IoError(e.to_string()))?;
+        let task_id = self.entity.id();
+        let name_version = self.entity.task().name_version();
+        let capture_stream = |stream: &'static str| {
+            let name_version = name_version.clone();
+            move |line: &str| event::task_output(task_id, &name_version, stream, line)
+        };
+        let stdout = child
+            .stdout
+            .take()
+            .map(|stdout| StdioUtils::capture_lines(stdout, 0, capture_stream("stdout")));
+        // 转发标准错误输出，同时保留最后的若干行，用于在失败时输出
+        let stderr_tail = child.stderr.take().map(|stderr| {
+            if capture {
+                StdioUtils::capture_lines(stderr, STDERR_TAIL_LINES, capture_stream("stderr"))
+            } else {
+                StdioUtils::tee_stderr(stderr, STDERR_TAIL_LINES)
+            }
+        });
+
+        // 等待子进程结束。收到中断信号后，给子进程一段时间自行退出，超时后强制终止
+        let started_at = Instant::now();
+        let mut interrupted_at: Option<Instant> = None;
+        let mut timed_out = false;
+        let mut cancelled = false;
+        let r = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => {}
+                Err(e) => break Err(ExecutorError::IoError(e.to_string())),
+            }
+            if self.context.interrupt_state().is_interrupted() {
+                let since = *interrupted_at.get_or_insert_with(Instant::now);
+                if since.elapsed() >= interrupt::TERMINATE_GRACE_PERIOD {
+                    warn!(
+                        "Task {}: killing build command after interrupt",
+                        self.entity.task().name_version()
+                    );
+                    tracked.kill_group();
+                }
+            } else if !timed_out && timeout.is_some_and(|t| started_at.elapsed() >= t) {
+                warn!(
+                    "Task {}: command timed out after {:?}, killing it",
+                    self.entity.task().name_version(),
+                    timeout.unwrap()
+                );
+                tracked.kill_group();
+                timed_out = true;
+            } else if !cancelled && self.context.interrupt_state().is_task_cancelled(task_id) {
+                warn!("Task {}: cancelled, killing build command", name_version);
+                tracked.kill_group();
+                cancelled = true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        if interrupted_at.is_some() {
+            child.wait().ok();
+        }
+        // 命令在后台启动的进程可能继承了输出管道，不能无限期地等待管道关闭
+        let drain_deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+        let stdout_drained = stdout
+            .map(|h| StdioUtils::join_until(h, drain_deadline).is_some())
+            .unwrap_or(true);
+        let stderr_tail = stderr_tail.map(|h| StdioUtils::join_until(h, drain_deadline));
+        if !stdout_drained || matches!(stderr_tail, Some(None)) {
+            warn!(
+                "Task {}: output is still open after the command exited \
+                 (probably held by a background process), ignoring the rest of it",
+                name_version
+            );
+        }
+        let stderr_tail = stderr_tail.flatten().unwrap_or_default();
+        if interrupted_at.is_some() {
+            return Err(ExecutorError::Interrupted(format!(
+                "Task {} interrupted",
+                self.entity.task().name_version()
+            )));
+        }
+        if cancelled {
+            let errmsg = format!("Task {} cancelled", name_version);
+            error!("{errmsg}");
+            return Err(ExecutorError::TaskFailed(errmsg));
+        }
+        if timed_out {
+            let errmsg = format!(
+                "Task {} timed out after {:?}",
+                self.entity.task().name_version(),
+                timeout.unwrap()
+            );
+            error!("{errmsg}");
+            return Err(ExecutorError::Timeout(errmsg));
+        }
         debug!("Command finished: {:?}", r);
         if r.is_ok() {
             let r = r.unwrap();
             if r.success() {
                 return Ok(());
             } else {
-                // 执行失败，获取最后100行stderr输出
-                let errmsg = format!(
-                    "Task {} failed, exit code = {}",
-                    self.entity.task().name_version(),
-                    r.code().unwrap()
-                );
+                // 执行失败，输出stderr的最后若干行
+                let errmsg = format!("Task {} failed, {}", self.entity.task().name_version(), r);
                 error!("{errmsg}");
-                let command_opt = command.output();
-                if command_opt.is_err() {
-                    return Err(ExecutorError::TaskFailed(
-                        "Failed to get command output".to_string(),
-                    ));
-                }
-                let command_opt = command_opt.unwrap();
-                let command_output = String::from_utf8_lossy(&command_opt.stderr);
-                let mut last_100_outputs = command_output
-                    .lines()
-                    .rev()
-                    .take(100)
-                    .collect::<Vec<&str>>();
-                last_100_outputs.reverse();
-                error!("Last 100 lines msg of stderr:");
-                for line in last_100_outputs {
+                error!("Last {} lines msg of stderr:", stderr_tail.len());
+                for line in stderr_tail {
                     error!("{}", line);
                 }
                 return Err(ExecutorError::TaskFailed(errmsg));
@@ -619,7 +1533,7 @@ impl EnvMap {
         self.envs.get(key)
     }
 
-    pub fn add_vars(&mut self, vars: Vars) {
+    pub fn add_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
         for (key, value) in vars {
             self.add(EnvVar::new(key, value));
         }
@@ -641,17 +1555,46 @@ impl EnvVar {
 
 /// # 任务执行器错误枚举
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ExecutorError {
     /// 准备执行环境错误
+    #[error("Failed to prepare environment: {0}")]
     PrepareEnvError(String),
+    #[error("IO error: {0}")]
     IoError(String),
+    /// 拉取源文件、下载压缩包或二进制包失败
+    #[error("Failed to fetch: {0}")]
+    FetchFailed(String),
     /// 构建执行错误
+    #[error("{0}")]
     TaskFailed(String),
     /// 安装错误
+    #[error("Failed to install: {0}")]
     InstallError(String),
     /// 清理错误
+    #[error("Failed to clean: {0}")]
     CleanError(String),
+    /// 收到中断信号，构建命令已被终止
+    #[error("{0}")]
+    Interrupted(String),
+    /// 命令执行超时，已被终止
+    #[error("{0}")]
+    Timeout(String),
+}
+
+impl ExecutorError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ExecutorError::PrepareEnvError(_) => ErrorCode::PrepareFailed,
+            ExecutorError::IoError(_) => ErrorCode::Io,
+            ExecutorError::FetchFailed(_) => ErrorCode::FetchFailed,
+            ExecutorError::TaskFailed(_) => ErrorCode::BuildFailed,
+            ExecutorError::InstallError(_) => ErrorCode::InstallFailed,
+            ExecutorError::CleanError(_) => ErrorCode::CleanFailed,
+            ExecutorError::Interrupted(_) => ErrorCode::Interrupted,
+            ExecutorError::Timeout(_) => ErrorCode::Timeout,
+        }
+    }
 }
 
 /// # 准备全局环境变量
@@ -662,7 +1605,7 @@ pub fn prepare_env(
     info!("Preparing environment variables...");
     let env_list = create_global_env_list(sched_entities, execute_ctx)?;
     // 写入全局环境变量列表
-    let mut global_env_list = ENV_LIST.write().unwrap();
+    let mut global_env_list = execute_ctx.global_env_list().write().unwrap();
     *global_env_list = env_list;
     return Ok(());
 }
@@ -673,31 +1616,66 @@ fn create_global_env_list(
     execute_ctx: &Arc<DadkUserExecuteContext>,
 ) -> Result<EnvMap, ExecutorError> {
     let mut env_list = EnvMap::new();
-    let envs: Vars = std::env::vars();
-    env_list.add_vars(envs);
+    // 主机上的环境变量只保留manifest中`[env] passthrough`允许的变量，然后加上`[env] set`中的变量
+    let env_config = execute_ctx.env_config();
+    env_list.add_vars(std::env::vars().filter(|(key, _)| env_config.passes(key)));
+    env_list.add_vars(env_config.set.clone());
+
+    let cache_root = execute_ctx.cache_root();
+    env_list.add(EnvVar::new(
+        "DADK_CACHE_ROOT".to_string(),
+        cache_root.to_str().unwrap().to_string(),
+    ));
+    // 内核模块根据内核源码构建
+    if let Some(kernel) = execute_ctx.kernel() {
+        env_list.add(EnvVar::new(
+            kernel_module::KERNEL_SOURCE_DIR_ENV.to_string(),
+            abs_path(&kernel.source_dir).to_string_lossy().to_string(),
+        ));
+        env_list.add(EnvVar::new(
+            kernel_module::KERNEL_VERSION_ENV.to_string(),
+            kernel.version.clone(),
+        ));
+    }
 
+    // 环境变量名称 -> 导出它的任务，用于检查不同任务的变量名称冲突
+    let mut owners: BTreeMap<String, Vec<String>> = BTreeMap::new();
     // 为每个任务创建特定的环境变量
     for entity in sched_entities.entities().iter() {
+        let task = entity.task();
         // 导出任务的构建目录环境变量
-        let build_dir = CacheDir::build_dir(entity.clone())?;
+        let build_dir = CacheDir::build_dir(cache_root, entity.clone())?;
 
         let build_dir_key = CacheDir::build_dir_env_key(&entity)?;
+        let source_dir_key = CacheDir::source_dir_env_key(&entity)?;
+        for key in [&build_dir_key, &source_dir_key] {
+            owners
+                .entry(key.clone())
+                .or_default()
+                .push(format!("{}@{}", task.name, task.version));
+        }
         env_list.add(EnvVar::new(
             build_dir_key,
             build_dir.to_str().unwrap().to_string(),
         ));
 
-        // 如果需要源码缓存目录，则导出
+        // 导出源码目录：需要源码缓存目录的任务为缓存目录，从本地路径构建的任务为本地路径
         if CacheDir::need_source_cache(entity) {
-            let source_dir = CacheDir::source_dir(entity.clone())?;
-            let source_dir_key = CacheDir::source_dir_env_key(&entity)?;
+            let source_dir = CacheDir::source_dir(cache_root, entity.clone())?;
             env_list.add(EnvVar::new(
                 source_dir_key,
                 source_dir.to_str().unwrap().to_string(),
             ));
+        } else if let TaskType::BuildFromSource(CodeSource::Local(local)) = &task.task_type {
+            env_list.add(EnvVar::new(
+                source_dir_key,
+                abs_path(local.path()).to_str().unwrap().to_string(),
+            ));
         }
     }
 
+    check_env_collisions(&owners, execute_ctx.allow_env_collisions())?;
+
     // 创建ARCH环境变量
     let target_arch = execute_ctx.target_arch();
     env_list.add(EnvVar::new("ARCH".to_string(), (*target_arch).into()));
@@ -705,6 +1683,37 @@ fn create_global_env_list(
     return Ok(env_list);
 }
 
+/// # 检查环境变量名称冲突
+///
+/// 任务名称和版本中的`-`、`.`等字符在环境变量名称中都被替换为`_`，
+/// 因此`libc-0.1.0`和`libc.0.1.0`会导出同名的环境变量，后添加的任务会覆盖前一个任务的变量。
+/// 发现冲突时返回错误，`allow`为true时只输出警告
+fn check_env_collisions(
+    owners: &BTreeMap<String, Vec<String>>,
+    allow: bool,
+) -> Result<(), ExecutorError> {
+    let collisions: Vec<String> = owners
+        .iter()
+        .filter(|(_, tasks)| tasks.len() > 1)
+        .map(|(key, tasks)| format!("{} is exported by {}", key, tasks.join(", ")))
+        .collect();
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    let msg = format!(
+        "Environment variable name collisions between tasks:\n{}",
+        collisions.join("\n")
+    );
+    if allow {
+        warn!("{}", msg);
+        return Ok(());
+    }
+    Err(ExecutorError::PrepareEnvError(format!(
+        "{}\nRename the tasks, or use --allow-env-collisions to ignore it",
+        msg
+    )))
+}
+
 /// # 获取文件最后的更新时间
 ///
 /// ## 参数
@@ -0,0 +1,78 @@
+//! # 构建结果检查
+//!
+//! 任务配置中的`[build.outputs]`列出构建成功后`DADK_CURRENT_BUILD_DIR`中应当存在的文件。
+//! 构建命令执行成功后逐个检查，任何一个不存在时任务失败，并列出所有缺少的文件。
+
+use std::path::Path;
+
+/// 检查构建结果目录中是否存在所有期望的文件，返回缺少的文件列表
+pub(super) fn check_outputs(build_dir: &Path, patterns: &[String]) -> Result<(), String> {
+    let mut entries = Vec::new();
+    collect_entries(build_dir, "", &mut entries)
+        .map_err(|e| format!("Failed to read {}: {}", build_dir.display(), e))?;
+    let missing: Vec<&str> = patterns
+        .iter()
+        .filter(|pattern| !entries.iter().any(|entry| glob_match(pattern, entry)))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "{} expected output(s) not found in {}: {}",
+        missing.len(),
+        build_dir.display(),
+        missing.join(", ")
+    ))
+}
+
+/// 递归列出目录下所有文件、目录的相对路径（以`/`分隔），不进入符号链接指向的目录
+fn collect_entries(dir: &Path, prefix: &str, entries: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_entries(&entry.path(), &format!("{}/", name), entries)?;
+        }
+        entries.push(name);
+    }
+    Ok(())
+}
+
+/// 判断路径是否匹配通配符：`*`、`?`匹配路径中的一段，`**`匹配任意多段
+pub(super) fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match_segments(&pattern, &path)
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| match_name(first, name) && match_segments(rest, path)),
+    }
+}
+
+/// 匹配路径中的一段
+fn match_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // 通配符匹配的动态规划：matched[j]表示pattern的前i个字符能否匹配name的前j个字符
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for p in &pattern {
+        let mut next = vec![false; name.len() + 1];
+        for j in 0..=name.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                c => j > 0 && matched[j - 1] && name[j - 1] == *c,
+            };
+        }
+        matched = next;
+    }
+    matched[name.len()]
+}
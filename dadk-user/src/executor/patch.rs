@@ -0,0 +1,131 @@
+//! # 源码补丁
+//!
+//! 任务配置了`task-source.patches`时，拉取源文件之后、构建之前，按顺序把补丁应用到源码目录：
+//!
+//! - git仓库使用`git apply`，在线压缩包使用`patch -p1`
+//! - 已经应用的补丁记录在源码目录下的`.dadk_patches`中（每行为补丁的sha256和文件名），
+//!   再次构建时只应用新增的补丁。已经应用的补丁被修改或删除时报错，需要清理源码缓存后重新构建
+//! - git仓库在拉取之前恢复到未打补丁的状态，拉取之后重新应用所有补丁
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use log::info;
+
+use crate::{repository::sha256_file, utils::stdio::StdioUtils};
+
+/// 记录已经应用的补丁的文件
+pub(super) const PATCH_STAMP: &str = ".dadk_patches";
+
+/// 应用补丁使用的工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PatchTool {
+    /// `git apply`
+    Git,
+    /// `patch -p1`
+    Patch,
+}
+
+/// 把补丁按顺序应用到源码目录，跳过已经应用过的补丁
+pub(super) fn apply_patches(
+    source_dir: &Path,
+    patches: &[PathBuf],
+    tool: PatchTool,
+) -> Result<(), String> {
+    let stamp = source_dir.join(PATCH_STAMP);
+    let applied: Vec<String> = match std::fs::read_to_string(&stamp) {
+        Ok(content) => content.lines().map(|l| l.to_string()).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("Failed to read {}: {}", stamp.display(), e)),
+    };
+    let wanted = patches
+        .iter()
+        .map(|p| stamp_line(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    if applied.len() > wanted.len() || applied[..] != wanted[..applied.len()] {
+        return Err(format!(
+            "Patches applied to {} don't match the task config, clean the source cache and rebuild",
+            source_dir.display()
+        ));
+    }
+
+    for (patch, line) in patches.iter().zip(&wanted).skip(applied.len()) {
+        info!("Applying patch {}", patch.display());
+        apply_patch(source_dir, patch, tool)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&stamp)
+            .map_err(|e| format!("Failed to open {}: {}", stamp.display(), e))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| format!("Failed to write {}: {}", stamp.display(), e))?;
+    }
+    Ok(())
+}
+
+/// 把打过补丁的git仓库恢复到未打补丁的状态，以便拉取更新后重新应用补丁
+pub(super) fn reset_git_source(source_dir: &Path) -> Result<(), String> {
+    if !source_dir.join(PATCH_STAMP).exists() {
+        return Ok(());
+    }
+    info!("Reverting patches in {}", source_dir.display());
+    run(Command::new("git")
+        .args(["reset", "--hard", "-q"])
+        .current_dir(source_dir))?;
+    // 删除补丁新增的文件以及补丁记录
+    run(Command::new("git")
+        .args(["clean", "-fdq"])
+        .current_dir(source_dir))?;
+    std::fs::remove_file(source_dir.join(PATCH_STAMP)).ok();
+    Ok(())
+}
+
+/// 补丁记录中的一行：sha256以及文件名
+fn stamp_line(patch: &Path) -> Result<String, String> {
+    let name = patch
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(format!("{} {}", sha256_file(patch)?, name))
+}
+
+fn apply_patch(source_dir: &Path, patch: &Path, tool: PatchTool) -> Result<(), String> {
+    // 命令在源码目录中执行，补丁的路径需要是绝对路径
+    let patch = std::fs::canonicalize(patch)
+        .map_err(|e| format!("Failed to find patch {}: {}", patch.display(), e))?;
+    let mut command = match tool {
+        PatchTool::Git => {
+            let mut cmd = Command::new("git");
+            cmd.arg("apply").arg(&patch);
+            cmd
+        }
+        PatchTool::Patch => {
+            let mut cmd = Command::new("patch");
+            cmd.args(["-p1", "--batch", "-i"]).arg(&patch);
+            cmd
+        }
+    };
+    command.current_dir(source_dir);
+    run(&mut command).map_err(|e| format!("Failed to apply patch {}: {}", patch.display(), e))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to execute {:?}: {}", command.get_program(), e))?;
+    if !output.status.success() {
+        let mut lines = StdioUtils::stderr_to_lines(&output.stdout);
+        lines.extend(StdioUtils::stderr_to_lines(&output.stderr));
+        return Err(format!(
+            "{:?} exited with {}: {}",
+            command.get_program(),
+            output.status,
+            StdioUtils::tail_n_str(lines, 5)
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! # 构建结果的来源信息
+//!
+//! 每次实际执行构建时，记录源文件的确切版本（git提交、压缩包的sha256）、补丁文件、
+//! 执行构建的主机以及DADK的版本，写入任务日志和构建报告，
+//! 以便把发布的DragonOS镜像追溯到确切的输入。
+
+use std::{
+    ffi::CStr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use dadk_config::manifest::ContainerConfig;
+
+use crate::{
+    parser::task_log::{BuilderInfo, PatchDigest, SourceRevision},
+    repository::sha256_file,
+};
+
+/// 记录在来源信息中的DADK版本
+pub const DADK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 目录所在的git仓库检出的提交，不在git仓库中时为None
+pub(super) fn git_commit(dir: &Path) -> Option<String> {
+    let output = git(dir, &["rev-parse", "HEAD"])?;
+    Some(output.trim().to_string()).filter(|c| !c.is_empty())
+}
+
+/// 本地源文件的版本：位于git仓库中时，记录检出的提交以及目录中是否有未提交的修改
+pub(super) fn local_revision(path: &Path) -> SourceRevision {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    // 本地源文件也可能是单个文件（二进制包），在它所在的目录中执行git命令
+    let dir = if path.is_dir() {
+        path.clone()
+    } else {
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+    let commit = git_commit(&dir);
+    let dirty = commit.is_some()
+        && git(
+            &dir,
+            &["status", "--porcelain", "--", &path.to_string_lossy()],
+        )
+        .is_some_and(|s| !s.trim().is_empty());
+    SourceRevision::Local {
+        path,
+        commit,
+        dirty,
+    }
+}
+
+/// 补丁文件的sha256
+pub(super) fn patch_digests(patches: &[PathBuf]) -> Result<Vec<PatchDigest>, String> {
+    patches
+        .iter()
+        .map(|path| {
+            Ok(PatchDigest {
+                path: path.clone(),
+                sha256: sha256_file(path)?,
+            })
+        })
+        .collect()
+}
+
+/// 执行构建的主机的信息
+pub fn builder_info(container: Option<&ContainerConfig>) -> BuilderInfo {
+    let (hostname, kernel) = match uname() {
+        Some((hostname, release)) => (hostname, Some(release)),
+        None => (String::new(), None),
+    };
+    BuilderInfo {
+        hostname,
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        kernel,
+        container_image: container.map(|c| c.image.clone()),
+    }
+}
+
+/// (主机名, 内核版本)
+fn uname() -> Option<(String, String)> {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+    let field = |f: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(f.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Some((field(&name.nodename), field(&name.release)))
+}
+
+/// 在`dir`中执行git命令，返回标准输出。命令失败时返回None
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
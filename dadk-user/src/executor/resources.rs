@@ -0,0 +1,470 @@
+//! # 构建命令的资源限制
+//!
+//! 任务配置了`[build.resources]`时，限制构建命令（及其子进程）可以使用的CPU和内存：
+//!
+//! - 优先使用cgroup v2：把DADK移入自己创建的cgroup（见[`CgroupParent`]），在其下为构建命令创建子cgroup，
+//!   通过`cpu.max`、`memory.max`限制，命令结束后删除。执行结束后DADK回到原来的cgroup（见[`CgroupLease`]）
+//! - cgroup v2不可用（例如没有权限）时，降低构建命令的优先级（nice），并通过`RLIMIT_AS`限制内存
+//!
+//! 在容器中构建时，资源限制通过容器引擎的`--cpus`、`--memory`参数实现，见[`super::backend`]。
+
+use std::{
+    fs::File,
+    io::Write,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, Once, OnceLock,
+    },
+    time::Duration,
+};
+
+use dadk_config::common::task::ResourcesConfig;
+use log::{debug, warn};
+
+/// cgroup v2的挂载点
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// `cpu.max`中的周期（微秒）
+const CPU_PERIOD_US: u64 = 100_000;
+/// 无法使用cgroup时，构建命令的nice值
+const FALLBACK_NICE: i32 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// 最多使用的CPU数量
+    pub cpus: Option<u32>,
+    /// 最多使用的内存（字节）
+    pub memory: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn from_config(config: &ResourcesConfig) -> Result<Self, String> {
+        Ok(Self {
+            cpus: config.cpus,
+            memory: config.memory_bytes().map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// `cpu.max`的内容
+    pub(super) fn cpu_max(&self) -> Option<String> {
+        self.cpus
+            .map(|cpus| format!("{} {}", cpus as u64 * CPU_PERIOD_US, CPU_PERIOD_US))
+    }
+}
+
+/// # DADK为构建命令创建的cgroup的父cgroup
+///
+/// cgroup v2中，有进程的cgroup不能为子cgroup启用控制器，而DADK本身就在它所在的cgroup中。
+/// 因此先在DADK所在的cgroup下创建`dadk-<pid>`，把DADK移入其中的叶子cgroup`supervisor`，
+/// 然后在原来的cgroup以及`dadk-<pid>`中启用cpu、memory控制器，构建命令的cgroup创建在`dadk-<pid>`下：
+///
+/// ```text
+/// <DADK原来所在的cgroup>/dadk-<pid>/supervisor   DADK
+/// <DADK原来所在的cgroup>/dadk-<pid>/<task>       构建命令
+/// ```
+///
+/// DADK原来所在的cgroup中还有其他进程时无法启用控制器，需要在单独的cgroup中运行DADK
+/// （例如`systemd-run --user --scope dadk ...`）。
+///
+/// drop时把进程移回原来的cgroup，关闭在原来的cgroup中启用的控制器，并删除创建的cgroup。
+/// DADK异常退出后留下的空cgroup在下一次创建时删除。
+#[derive(Debug)]
+pub(super) struct CgroupParent {
+    path: PathBuf,
+    /// DADK原来所在的cgroup
+    original: PathBuf,
+    pid: u32,
+    /// 在原来的cgroup中启用的控制器（之前已经启用的不包括在内）
+    enabled: Vec<&'static str>,
+}
+
+impl CgroupParent {
+    /// 在`root`下，`/proc/<pid>/cgroup`的内容为`self_cgroup`的进程`pid`所在的cgroup中，
+    /// 创建`dadk-<pid>`，并把进程移入`dadk-<pid>/supervisor`
+    pub(super) fn setup(root: &Path, self_cgroup: &str, pid: u32) -> Result<Self, String> {
+        if !root.join("cgroup.controllers").is_file() {
+            return Err(format!("cgroup v2 is not mounted at {}", root.display()));
+        }
+        let current = current_cgroup(self_cgroup)
+            .map(|p| root.join(p))
+            .ok_or_else(|| "Failed to find the cgroup of dadk".to_string())?;
+        let controllers = available_controllers(&current)?;
+        if controllers.is_empty() {
+            return Err(format!(
+                "The cpu and memory controllers are not available in cgroup {}",
+                current.display()
+            ));
+        }
+        remove_stale(&current);
+
+        let path = current.join(format!("dadk-{}", pid));
+        let supervisor = path.join("supervisor");
+        create_dir(&path)?;
+        if let Err(e) = create_dir(&supervisor) {
+            std::fs::remove_dir(&path).ok();
+            return Err(e);
+        }
+        let r = move_process(&supervisor, pid).and_then(|_| {
+            let r = enable_controllers(&current, &controllers).and_then(|enabled| {
+                enable_controllers(&path, &controllers)
+                    .map(|_| enabled.clone())
+                    .inspect_err(|_| disable_controllers(&current, &enabled))
+            });
+            if r.is_err() {
+                // 回到原来的cgroup，以便删除创建的cgroup
+                move_process(&current, pid).ok();
+            }
+            r
+        });
+        match r {
+            Ok(enabled) => Ok(Self {
+                path,
+                original: current,
+                pid,
+                enabled,
+            }),
+            Err(e) => {
+                std::fs::remove_dir(&supervisor).ok();
+                std::fs::remove_dir(&path).ok();
+                Err(e)
+            }
+        }
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CgroupParent {
+    fn drop(&mut self) {
+        let r = move_process(&self.original, self.pid)
+            .and_then(|_| remove_dir(&self.path.join("supervisor")))
+            .and_then(|_| remove_dir(&self.path));
+        match r {
+            // 启用了控制器的子cgroup删除之后才能关闭控制器
+            Ok(()) => disable_controllers(&self.original, &self.enabled),
+            Err(e) => debug!("Failed to restore the cgroup of dadk: {}", e),
+        }
+    }
+}
+
+/// DADK进程中所有执行上下文共享的父cgroup，以及正在使用它的执行上下文的数量
+static SHARED_PARENT: Mutex<(Option<CgroupParent>, usize)> = Mutex::new((None, 0));
+
+/// # 执行上下文对[`CgroupParent`]的使用
+///
+/// 第一次限制构建命令的资源时才创建父cgroup，同一个进程中的多个执行上下文（例如同时构建多个架构）
+/// 共享同一个父cgroup。所有使用它的执行上下文都结束后，DADK回到原来的cgroup。
+/// 没有任务配置`[build.resources]`时，不会移动DADK进程
+#[derive(Debug, Default)]
+pub(crate) struct CgroupLease {
+    /// 父cgroup的路径，创建失败时为错误信息
+    parent: OnceLock<Result<PathBuf, String>>,
+}
+
+impl CgroupLease {
+    fn parent(&self) -> Result<&Path, String> {
+        self.parent
+            .get_or_init(|| {
+                let mut shared = SHARED_PARENT.lock().unwrap();
+                if shared.0.is_none() {
+                    let self_cgroup =
+                        std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+                    shared.0 = Some(CgroupParent::setup(
+                        Path::new(CGROUP_ROOT),
+                        &self_cgroup,
+                        std::process::id(),
+                    )?);
+                }
+                shared.1 += 1;
+                Ok(shared.0.as_ref().unwrap().path().to_path_buf())
+            })
+            .as_deref()
+            .map_err(Clone::clone)
+    }
+}
+
+impl Drop for CgroupLease {
+    fn drop(&mut self) {
+        if let Some(Ok(_)) = self.parent.get() {
+            let mut shared = SHARED_PARENT.lock().unwrap();
+            shared.1 -= 1;
+            if shared.1 == 0 {
+                shared.0 = None;
+            }
+        }
+    }
+}
+
+/// # 为构建命令创建的cgroup
+///
+/// drop时删除
+#[derive(Debug)]
+pub(super) struct Cgroup {
+    path: PathBuf,
+    /// 子进程通过写入此文件加入cgroup
+    procs: File,
+}
+
+impl Cgroup {
+    /// 在[`CgroupParent`]下创建子cgroup，并设置资源限制
+    pub(super) fn create(
+        parent: &Path,
+        name: &str,
+        limits: &ResourceLimits,
+    ) -> Result<Self, String> {
+        let path = parent.join(name);
+        create_dir(&path)?;
+        let write = |file: &str, value: String| {
+            std::fs::write(path.join(file), value)
+                .map_err(|e| format!("Failed to write {}: {}", path.join(file).display(), e))
+        };
+        let r = limits
+            .cpu_max()
+            .map_or(Ok(()), |cpu_max| write("cpu.max", cpu_max))
+            .and_then(|_| {
+                limits
+                    .memory
+                    .map_or(Ok(()), |memory| write("memory.max", memory.to_string()))
+            })
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(path.join("cgroup.procs"))
+                    .map_err(|e| format!("Failed to open cgroup.procs: {}", e))
+            });
+        match r {
+            Ok(procs) => Ok(Self { path, procs }),
+            Err(e) => {
+                std::fs::remove_dir(&path).ok();
+                Err(e)
+            }
+        }
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 让命令启动后、执行前加入cgroup
+    pub(super) fn attach(&self, command: &mut Command) {
+        let fd = self.procs.as_raw_fd();
+        unsafe {
+            command.pre_exec(move || {
+                // 写入"0"表示把当前进程加入cgroup。失败时命令在cgroup之外执行
+                libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // 构建命令在后台留下的进程会导致无法删除cgroup，先终止它们
+        if std::fs::remove_dir(&self.path).is_ok() {
+            return;
+        }
+        if let Ok(mut kill) = std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.kill"))
+        {
+            kill.write_all(b"1").ok();
+        }
+        for _ in 0..10 {
+            std::thread::sleep(Duration::from_millis(50));
+            if std::fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+        }
+        debug!("Failed to remove cgroup {}", self.path.display());
+    }
+}
+
+/// 从`/proc/self/cgroup`的内容中，找到cgroup v2中的路径（相对于挂载点）
+pub(super) fn current_cgroup(content: &str) -> Option<PathBuf> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| PathBuf::from(path.trim().trim_start_matches('/')))
+}
+
+fn create_dir(path: &Path) -> Result<(), String> {
+    std::fs::create_dir(path)
+        .map_err(|e| format!("Failed to create cgroup {}: {}", path.display(), e))
+}
+
+fn remove_dir(path: &Path) -> Result<(), String> {
+    std::fs::remove_dir(path)
+        .map_err(|e| format!("Failed to remove cgroup {}: {}", path.display(), e))
+}
+
+/// 把进程移入cgroup
+fn move_process(cgroup: &Path, pid: u32) -> Result<(), String> {
+    std::fs::write(cgroup.join("cgroup.procs"), pid.to_string()).map_err(|e| {
+        format!(
+            "Failed to move dadk into cgroup {}: {}",
+            cgroup.display(),
+            e
+        )
+    })
+}
+
+/// cgroup中可以为子cgroup启用的cpu、memory控制器
+fn available_controllers(cgroup: &Path) -> Result<Vec<&'static str>, String> {
+    let path = cgroup.join("cgroup.controllers");
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let available: Vec<&str> = content.split_whitespace().collect();
+    Ok(["cpu", "memory"]
+        .into_iter()
+        .filter(|c| available.contains(c))
+        .collect())
+}
+
+/// 在cgroup中为子cgroup启用控制器（已经启用的不再写入），返回本次启用的控制器
+fn enable_controllers(
+    cgroup: &Path,
+    controllers: &[&'static str],
+) -> Result<Vec<&'static str>, String> {
+    let path = cgroup.join("cgroup.subtree_control");
+    let enabled = std::fs::read_to_string(&path).unwrap_or_default();
+    let enabled: Vec<&str> = enabled.split_whitespace().collect();
+    let missing: Vec<&'static str> = controllers
+        .iter()
+        .copied()
+        .filter(|c| !enabled.contains(c))
+        .collect();
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+    let value: Vec<String> = missing.iter().map(|c| format!("+{}", c)).collect();
+    std::fs::write(&path, value.join(" "))
+        .map(|_| missing)
+        .map_err(|e| {
+            let hint = match e.raw_os_error() {
+                Some(libc::EBUSY) => {
+                    " (other processes are in this cgroup, run dadk in a cgroup of its own, \
+                 e.g. with `systemd-run --user --scope`)"
+                }
+                _ => "",
+            };
+            format!(
+                "Failed to enable {} in {}: {}{}",
+                value.join(" "),
+                path.display(),
+                e,
+                hint
+            )
+        })
+}
+
+/// 关闭[`enable_controllers`]启用的控制器
+fn disable_controllers(cgroup: &Path, controllers: &[&str]) {
+    if controllers.is_empty() {
+        return;
+    }
+    let path = cgroup.join("cgroup.subtree_control");
+    let value: Vec<String> = controllers.iter().map(|c| format!("-{}", c)).collect();
+    if let Err(e) = std::fs::write(&path, value.join(" ")) {
+        debug!(
+            "Failed to disable {} in {}: {}",
+            value.join(" "),
+            path.display(),
+            e
+        );
+    }
+}
+
+/// 删除已经退出的DADK进程留下的`dadk-<pid>`
+pub(super) fn remove_stale(cgroup: &Path) {
+    let Ok(entries) = std::fs::read_dir(cgroup) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(pid) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix("dadk-"))
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if Path::new("/proc").join(pid.to_string()).exists() {
+            continue;
+        }
+        if let Ok(children) = std::fs::read_dir(&path) {
+            for child in children.flatten().filter(|c| c.path().is_dir()) {
+                std::fs::remove_dir(child.path()).ok();
+            }
+        }
+        if std::fs::remove_dir(&path).is_ok() {
+            debug!("Removed stale cgroup {}", path.display());
+        }
+    }
+}
+
+/// 限制命令可以使用的资源。返回的cgroup需要一直持有到命令结束
+///
+/// `name`为任务的名称、版本和目标架构，cgroup的名称再加上一个序号，
+/// 同时执行的命令（例如同时为多个架构构建同一个任务）不会使用同一个cgroup
+pub(super) fn apply(
+    command: &mut Command,
+    limits: &ResourceLimits,
+    name: &str,
+    lease: &CgroupLease,
+) -> Option<Cgroup> {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let r = lease.parent().and_then(|parent| {
+        let name = format!("{}-{}", name, SEQ.fetch_add(1, Ordering::Relaxed));
+        Cgroup::create(parent, &name, limits)
+    });
+    match r {
+        Ok(cgroup) => {
+            debug!(
+                "Task {}: limiting resources with {}",
+                name,
+                cgroup.path().display()
+            );
+            cgroup.attach(command);
+            Some(cgroup)
+        }
+        Err(e) => {
+            static WARN_ONCE: Once = Once::new();
+            WARN_ONCE.call_once(|| {
+                warn!(
+                    "{}, falling back to nice/ulimit to limit the resources of build commands",
+                    e
+                )
+            });
+            apply_fallback(command, limits);
+            None
+        }
+    }
+}
+
+/// 无法使用cgroup时：降低优先级，并限制虚拟内存的大小
+pub(super) fn apply_fallback(command: &mut Command, limits: &ResourceLimits) {
+    let limits = *limits;
+    unsafe {
+        command.pre_exec(move || {
+            if limits.cpus.is_some() {
+                libc::setpriority(libc::PRIO_PROCESS, 0, FALLBACK_NICE);
+            }
+            if let Some(memory) = limits.memory {
+                let rlimit = libc::rlimit {
+                    rlim_cur: memory as libc::rlim_t,
+                    rlim_max: memory as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &rlimit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,62 @@
+//! # 重试
+//!
+//! 拉取源文件（git、下载压缩包）时，网络抖动等临时错误会导致整个构建失败。
+//! 这类操作失败后会按照指数退避重试，编译错误等其他错误不会重试。
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::interrupt::InterruptState;
+
+/// 第一次重试前等待的时间，之后每次翻倍
+pub(super) const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+/// 两次重试之间最长的等待时间
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// 第`attempt`次重试（从1开始）前等待的时间
+pub(super) fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// 执行`f`，失败时最多重试`retries`次。会话被中断后不再重试
+pub(super) fn retry<T, E: Display>(
+    what: &str,
+    retries: u32,
+    base: Duration,
+    interrupts: &InterruptState,
+    mut f: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < retries && !interrupts.is_interrupted() => {
+                attempt += 1;
+                let delay = backoff_delay(base, attempt);
+                warn!(
+                    "{} failed: {}, retrying in {:?} ({}/{})",
+                    what, e, delay, attempt, retries
+                );
+                sleep_interruptible(delay, interrupts);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 等待一段时间，会话被中断时提前返回
+fn sleep_interruptible(duration: Duration, interrupts: &InterruptState) {
+    let deadline = Instant::now() + duration;
+    while !interrupts.is_interrupted() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,32 @@
+//! # 执行构建命令的shell
+//!
+//! 任务配置中的构建、清理命令是shell脚本。Linux、macOS等类Unix系统上使用`bash -c`执行
+//! （可以在manifest或者任务配置中改为`sh`、`zsh`），Windows上使用`cmd /C`执行。
+//!
+//! 启用严格模式时，在脚本前加上`set -euo pipefail`，多条语句组成的命令在第一条失败的语句处停止，
+//! 而不是继续执行并留下空的构建目录。
+
+use std::process::Command;
+
+use dadk_config::common::task::BuildShell;
+
+/// 创建通过shell执行脚本的命令
+#[cfg(unix)]
+pub fn command(shell: BuildShell, strict: bool, script: &str) -> Command {
+    let mut command = Command::new(shell.program());
+    command.arg("-c");
+    if strict {
+        command.arg(format!("{}\n{}", shell.strict_prelude(), script));
+    } else {
+        command.arg(script);
+    }
+    command
+}
+
+/// 创建通过shell执行脚本的命令（Windows上总是使用`cmd /C`，不支持严格模式）
+#[cfg(windows)]
+pub fn command(_shell: BuildShell, _strict: bool, script: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(script);
+    command
+}
@@ -1,21 +1,61 @@
-use log::info;
+use flate2::read::GzDecoder;
+use log::{info, warn};
 use regex::Regex;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use std::os::unix::fs::PermissionsExt;
 use std::{
     fs::File,
-    path::PathBuf,
-    process::{Command, Stdio},
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
+use xz2::read::XzDecoder;
 use zip::ZipArchive;
 
-use crate::utils::{file::FileUtils, stdio::StdioUtils};
+use crate::{
+    event, interrupt,
+    repository::sha256_file,
+    scheduler::SchedEntity,
+    utils::{file::FileUtils, stdio::StdioUtils},
+};
 
 use super::cache::CacheDir;
 
 use anyhow::{Error, Result};
 
+/// 下载压缩包时使用的临时目录
+const ARCHIVE_TEMP_DIR: &str = "DRAGONOS_ARCHIVE_TEMP";
+
+/// 记录缓存目录中的文件来自哪个压缩包的文件（第一行为压缩包的URL，第二行为压缩包的sha256）
+pub(super) const ARCHIVE_STAMP: &str = ".dadk_archive";
+
+/// 访问远程仓库的git命令没有任何输出时，默认认为传输已经停滞的时间
+const DEFAULT_GIT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// # git命令的超时设置
+///
+/// 网络故障，或者git等待用户输入（例如SSH密码）时，git命令会一直挂起，占用一个工作线程
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GitTimeouts {
+    /// 每条git命令最长的执行时间，None表示不限制
+    pub timeout: Option<Duration>,
+    /// 访问远程仓库的git命令超过这段时间没有任何输出时，认为传输已经停滞，None表示不检测
+    pub stall: Option<Duration>,
+}
+
+impl Default for GitTimeouts {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            stall: Some(DEFAULT_GIT_STALL_TIMEOUT),
+        }
+    }
+}
+
 /// # Git源
 ///
 /// 从Git仓库获取源码
@@ -27,6 +67,12 @@ pub struct GitSource {
     branch: Option<String>,
     /// 特定的提交的hash值（可选，如果为空，则拉取branch的最新提交）
     revision: Option<String>,
+    /// git命令的超时设置。由执行器根据manifest设置，不属于任务配置
+    #[serde(skip)]
+    timeouts: GitTimeouts,
+    /// 实际拉取仓库的镜像地址。由执行器根据manifest中的`[mirrors]`设置，不属于任务配置
+    #[serde(skip)]
+    mirror: Option<String>,
 }
 
 impl GitSource {
@@ -35,8 +81,40 @@ impl GitSource {
             url,
             branch,
             revision,
+            timeouts: GitTimeouts::default(),
+            mirror: None,
         }
     }
+
+    /// 设置git命令的超时时间
+    pub fn with_timeouts(mut self, timeouts: GitTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// 从镜像地址（而不是`url`）克隆、拉取仓库
+    pub fn with_mirror(mut self, mirror: Option<String>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// 实际访问的远程仓库地址
+    pub fn fetch_url(&self) -> &str {
+        self.mirror.as_deref().unwrap_or(&self.url)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
     /// # 验证参数合法性
     ///
     /// 仅进行形式校验，不会检查Git仓库是否存在，以及分支是否存在、是否有权限访问等
@@ -94,6 +172,9 @@ impl GitSource {
             "Preparing git repo: {}, branch: {:?}, revision: {:?}",
             self.url, self.branch, self.revision
         );
+        if let Some(mirror) = &self.mirror {
+            info!("Using mirror {} for {}", mirror, self.url);
+        }
 
         target_dir.create().map_err(|e| {
             format!(
@@ -112,13 +193,41 @@ impl GitSource {
             self.clone_repo(target_dir)?;
         }
 
-        self.checkout(target_dir)?;
+        self.checkout(target_dir, false)?;
 
         self.pull(target_dir)?;
 
         return Ok(());
     }
 
+    /// # 离线模式下准备GitSource
+    ///
+    /// 只使用源码缓存中已有的仓库，切换到指定的分支或者提交，不从远程仓库拉取
+    ///
+    /// ## 返回
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(String)` - 源码缓存为空，或者缓存中没有指定的分支或提交
+    pub fn prepare_offline(&self, target_dir: &CacheDir) -> Result<(), String> {
+        info!(
+            "Preparing git repo offline: {}, branch: {:?}, revision: {:?}",
+            self.url, self.branch, self.revision
+        );
+        if !target_dir.path.exists() || target_dir.is_empty().unwrap_or(true) {
+            return Err(format!(
+                "Git repo {} is not in the source cache {}, can't clone it in offline mode",
+                self.url,
+                target_dir.path.display()
+            ));
+        }
+        self.checkout(target_dir, true)
+    }
+
+    /// 执行git命令，见[`run_git`]
+    fn run(&self, cmd: &mut Command, remote: bool) -> Result<Output, String> {
+        run_git(cmd, &self.timeouts, remote)
+    }
+
     fn check_repo(&self, target_dir: &CacheDir) -> Result<bool, String> {
         let path: &PathBuf = &target_dir.path;
         let mut cmd = Command::new("git");
@@ -127,18 +236,12 @@ impl GitSource {
         // 设置工作目录
         cmd.current_dir(path);
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, false)?;
 
         if output.status.success() {
             let mut r = String::from_utf8(output.stdout).unwrap();
             r.pop();
-            Ok(r == self.url)
+            Ok(r == self.fetch_url())
         } else {
             return Err(format!(
                 "git remote get-url origin failed, status: {:?},  stderr: {:?}",
@@ -154,17 +257,12 @@ impl GitSource {
         cmd.arg("remote")
             .arg("set-url")
             .arg("origin")
-            .arg(self.url.as_str());
+            .arg(self.fetch_url());
 
         // 设置工作目录
         cmd.current_dir(path);
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, false)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -176,7 +274,8 @@ impl GitSource {
         Ok(())
     }
 
-    fn checkout(&self, target_dir: &CacheDir) -> Result<(), String> {
+    /// 切换到指定的分支或者提交。离线模式下不会从远程仓库拉取
+    fn checkout(&self, target_dir: &CacheDir, offline: bool) -> Result<(), String> {
         // 确保目标目录中的仓库为所指定仓库
         if !self.check_repo(target_dir).map_err(|e| {
             format!(
@@ -203,12 +302,7 @@ impl GitSource {
             // 强制切换分支，且安静模式
             cmd.arg("-f").arg("-q");
 
-            // 创建子进程，执行命令
-            let proc: std::process::Child = cmd
-                .stderr(Stdio::piped())
-                .spawn()
-                .map_err(|e| e.to_string())?;
-            let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+            let output = self.run(&mut cmd, false)?;
 
             if !output.status.success() {
                 return Err(format!(
@@ -221,13 +315,11 @@ impl GitSource {
             let mut subcmd = Command::new("git");
             subcmd.current_dir(&target_dir.path);
             subcmd.arg("submodule").arg("update").arg("--remote");
+            if offline {
+                subcmd.arg("--no-fetch");
+            }
 
-            //当checkout仓库的子进程结束后，启动checkout子模块的子进程
-            let subproc: std::process::Child = subcmd
-                .stderr(Stdio::piped())
-                .spawn()
-                .map_err(|e| e.to_string())?;
-            let suboutput = subproc.wait_with_output().map_err(|e| e.to_string())?;
+            let suboutput = self.run(&mut subcmd, !offline)?;
 
             if !suboutput.status.success() {
                 return Err(format!(
@@ -239,6 +331,9 @@ impl GitSource {
             return Ok(());
         };
 
+        if offline {
+            return do_checkout();
+        }
         if let Err(_) = do_checkout() {
             // 如果切换分支失败，则尝试重新fetch
             if self.revision.is_some() {
@@ -256,7 +351,10 @@ impl GitSource {
     pub fn clone_repo(&self, cache_dir: &CacheDir) -> Result<(), String> {
         let path: &PathBuf = &cache_dir.path;
         let mut cmd = Command::new("git");
-        cmd.arg("clone").arg(&self.url).arg(".").arg("--recursive");
+        cmd.arg("clone")
+            .arg(self.fetch_url())
+            .arg(".")
+            .arg("--recursive");
 
         if let Some(branch) = &self.branch {
             cmd.arg("--branch").arg(branch).arg("--depth").arg("1");
@@ -267,13 +365,7 @@ impl GitSource {
         // 设置工作目录
         cmd.current_dir(path);
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .stdout(Stdio::inherit())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, true)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -293,13 +385,7 @@ impl GitSource {
 
         subcmd.current_dir(path);
 
-        //当克隆仓库的子进程结束后，启动保证克隆子模块的子进程
-        let subproc: std::process::Child = subcmd
-            .stderr(Stdio::piped())
-            .stdout(Stdio::inherit())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let suboutput = subproc.wait_with_output().map_err(|e| e.to_string())?;
+        let suboutput = self.run(&mut subcmd, true)?;
 
         if !suboutput.status.success() {
             return Err(format!(
@@ -319,12 +405,7 @@ impl GitSource {
             .arg("remote.origin.fetch")
             .arg("+refs/heads/*:refs/remotes/origin/*");
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, false)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -347,12 +428,7 @@ impl GitSource {
 
         cmd.arg("-f");
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, true)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -370,11 +446,7 @@ impl GitSource {
         cmd.current_dir(&target_dir.path);
         cmd.arg("rev-parse").arg("--is-shallow-repository");
 
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, false)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -397,12 +469,7 @@ impl GitSource {
         // 安静模式
         cmd.arg("-f").arg("-q");
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, true)?;
 
         if !output.status.success() {
             return Err(format!(
@@ -429,12 +496,7 @@ impl GitSource {
         // 安静模式
         cmd.arg("-f").arg("-q");
 
-        // 创建子进程，执行命令
-        let proc: std::process::Child = cmd
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| e.to_string())?;
-        let output = proc.wait_with_output().map_err(|e| e.to_string())?;
+        let output = self.run(&mut cmd, true)?;
 
         // 如果pull失败，且指定了branch，则报错
         if !output.status.success() {
@@ -449,6 +511,141 @@ impl GitSource {
     }
 }
 
+/// # 执行git命令
+///
+/// - 禁止git交互式地询问用户名和密码（`GIT_TERMINAL_PROMPT=0`），标准输入为空。
+///   命令运行在独立的进程组中，不能从终端读取输入
+/// - 执行时间超过`timeouts.timeout`时，终止整个进程组
+/// - `remote`为true时，命令会访问远程仓库：加上`--progress`，使git在传输过程中持续输出进度，
+///   超过`timeouts.stall`没有任何输出时，认为传输已经停滞，终止整个进程组
+///
+/// 超时或者停滞时返回错误，由调用者决定是否重试。标准错误输出中的进度信息只保留每一行最后一次更新的内容
+pub(crate) fn run_git(
+    cmd: &mut Command,
+    timeouts: &GitTimeouts,
+    remote: bool,
+) -> Result<Output, String> {
+    let what = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args().take(1))
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if remote {
+        cmd.arg("--progress");
+    }
+    cmd.env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let (mut child, tracked) =
+        interrupt::spawn_tracked(cmd).map_err(|e| format!("Failed to run {}: {}", what, e))?;
+    let last_output = Arc::new(Mutex::new(Instant::now()));
+    let stdout = child
+        .stdout
+        .take()
+        .map(|stdout| read_output(stdout, last_output.clone()));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|stderr| read_output(stderr, last_output.clone()));
+
+    // 等待git退出。收到中断信号后，给git一段时间自行退出，超时后强制终止
+    let started_at = Instant::now();
+    let mut interrupted_at: Option<Instant> = None;
+    let mut failure: Option<String> = None;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {}
+            Err(e) => {
+                tracked.kill_group();
+                return Err(format!("Failed to wait for {}: {}", what, e));
+            }
+        }
+        if interrupt::signal_received() {
+            let since = *interrupted_at.get_or_insert_with(Instant::now);
+            if since.elapsed() >= interrupt::TERMINATE_GRACE_PERIOD {
+                tracked.kill_group();
+            }
+        } else if failure.is_none() {
+            let silent = last_output.lock().unwrap().elapsed();
+            if let Some(timeout) = timeouts.timeout.filter(|t| started_at.elapsed() >= *t) {
+                failure = Some(format!("{} timed out after {:?}", what, timeout));
+            } else if let Some(stall) = timeouts.stall.filter(|s| remote && silent >= *s) {
+                failure = Some(format!(
+                    "{} stalled: no data received for {:?}, the network may be down or git may be waiting for input (e.g. an SSH passphrase)",
+                    what, stall
+                ));
+            }
+            if let Some(failure) = &failure {
+                warn!("{}, killing it", failure);
+                tracked.kill_group();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let stdout = stdout.map(|h| h.join().unwrap_or_default());
+    let stderr = final_progress(
+        &stderr
+            .map(|h| h.join().unwrap_or_default())
+            .unwrap_or_default(),
+    );
+    if let Some(failure) = failure {
+        return Err(format!(
+            "{}, stderr: {:?}",
+            failure,
+            StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&stderr), 5)
+        ));
+    }
+    if interrupted_at.is_some() {
+        return Err(format!("{} interrupted", what));
+    }
+    Ok(Output {
+        status,
+        stdout: stdout.unwrap_or_default(),
+        stderr,
+    })
+}
+
+/// 在新线程中读取子进程的输出，每次读到数据时更新`last_output`
+fn read_output(
+    mut reader: impl Read + Send + 'static,
+    last_output: Arc<Mutex<Instant>>,
+) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    output.extend_from_slice(&buf[..n]);
+                    *last_output.lock().unwrap() = Instant::now();
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        output
+    })
+}
+
+/// git用`\r`覆盖同一行来更新进度，每一行只保留最后一次更新的内容（与终端上显示的相同）
+fn final_progress(output: &[u8]) -> Vec<u8> {
+    output
+        .split_inclusive(|b| *b == b'\n')
+        .flat_map(|line| {
+            let content = line.strip_suffix(b"\n").unwrap_or(line);
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+            let last = content.rsplit(|b| *b == b'\r').next().unwrap_or_default();
+            last.iter()
+                .chain(line.ends_with(b"\n").then_some(&b'\n'))
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
 /// # 本地源
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LocalSource {
@@ -490,17 +687,61 @@ impl LocalSource {
     }
 }
 
+/// # 软件仓库源
+///
+/// 从dadk-manifest.toml中配置的软件仓库获取二进制包，版本与任务的版本相同
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RepositorySource {
+    /// 软件仓库中的包名。为空时使用任务名
+    name: String,
+}
+
+impl RepositorySource {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    pub fn trim(&mut self) {
+        self.name = self.name.trim().to_string();
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 包名为空时，使用任务名
+    pub(crate) fn set_default_name(&mut self, task_name: &str) {
+        if self.name.is_empty() {
+            self.name = task_name.to_string();
+        }
+    }
+}
+
 /// # 在线压缩包源
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ArchiveSource {
     /// 压缩包的URL
     url: String,
+    /// 实际下载压缩包的镜像地址。由执行器根据manifest中的`[mirrors]`设置，不属于任务配置
+    #[serde(skip)]
+    mirror: Option<String>,
 }
 
 impl ArchiveSource {
     #[allow(dead_code)]
     pub fn new(url: String) -> Self {
-        Self { url }
+        Self { url, mirror: None }
+    }
+
+    /// 从镜像地址（而不是`url`）下载压缩包。缓存中记录的仍然是`url`，切换镜像不会重新下载
+    pub fn with_mirror(mut self, mirror: Option<String>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// 实际下载的地址
+    pub fn fetch_url(&self) -> &str {
+        self.mirror.as_deref().unwrap_or(&self.url)
     }
     pub fn validate(&self) -> Result<()> {
         if self.url.is_empty() {
@@ -525,48 +766,144 @@ impl ArchiveSource {
         self.url = self.url.trim().to_string();
     }
 
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// @brief 检查target_dir中是否已经有之前从这个URL下载并解压的文件
+    ///
+    /// 目录中没有临时文件夹且不为空，并且stamp文件中记录的URL与配置相同，
+    /// 说明之前成功从这个URL执行过一次下载和解压
+    ///
+    /// @param stamp 记录压缩包来源的文件
+    pub fn is_cached(&self, target_dir: &CacheDir, stamp: &Path) -> Result<bool, String> {
+        if !target_dir.path.exists() {
+            return Ok(false);
+        }
+        let empty = target_dir.is_empty().map_err(|e| {
+            format!(
+                "Failed to check if target dir is empty: {}, message: {e:?}",
+                target_dir.path.display()
+            )
+        })?;
+        if empty || target_dir.path.join(ARCHIVE_TEMP_DIR).exists() {
+            return Ok(false);
+        }
+        Ok(self.cached_url(stamp).as_deref() == Some(self.url.as_str()))
+    }
+
+    /// 缓存中的文件来自的压缩包的URL（没有记录时为None）
+    pub fn cached_url(&self, stamp: &Path) -> Option<String> {
+        let content = std::fs::read_to_string(stamp).ok()?;
+        content.lines().next().map(|l| l.to_string())
+    }
+
+    /// 缓存中的文件来自的压缩包的sha256（没有记录时为None）
+    pub fn cached_sha256(&self, stamp: &Path) -> Option<String> {
+        let content = std::fs::read_to_string(stamp).ok()?;
+        content
+            .lines()
+            .nth(1)
+            .map(|l| l.to_string())
+            .filter(|l| !l.is_empty())
+    }
+
     /// @brief 下载压缩包并把其中的文件提取至target_dir目录下
     ///
     ///从URL中下载压缩包到临时文件夹 target_dir/DRAGONOS_ARCHIVE_TEMP 后
-    ///原地解压，提取文件后删除下载的压缩包。如果 target_dir 非空，就直接使用
-    ///其中内容，不进行重复下载和覆盖
+    ///原地解压，提取文件后删除下载的压缩包，并在stamp文件中记录压缩包的URL和sha256。
+    ///如果 target_dir 中已经有从同一个URL下载的文件，就直接使用其中内容，不进行重复下载和覆盖；
+    ///URL改变时（或者指定了refetch）先清空 target_dir 再重新下载
     ///
     /// @param target_dir 文件缓存目录
+    /// @param stamp 记录压缩包来源的文件
+    /// @param refetch 忽略缓存，强制重新下载
     ///
     /// @return 根据结果返回OK或Err
-    pub fn download_unzip(&self, target_dir: &CacheDir) -> Result<(), String> {
-        let url = Url::parse(&self.url).unwrap();
-        let archive_name = url.path_segments().unwrap().last().unwrap();
-        let path = &(target_dir.path.join("DRAGONOS_ARCHIVE_TEMP"));
-        //如果source目录没有临时文件夹，且不为空，说明之前成功执行过一次，那么就直接使用之前的缓存
-        if !path.exists()
-            && !target_dir.is_empty().map_err(|e| {
-                format!(
-                    "Failed to check if target dir is empty: {}, message: {e:?}",
-                    target_dir.path.display()
-                )
-            })?
-        {
-            //如果source文件夹非空，就直接使用，不再重复下载压缩文件，这里可以考虑加入交互
-            info!("Source files already exist. Using previous source file cache. You should clean {:?} before re-download the archive ", target_dir.path);
+    pub fn download_unzip(
+        &self,
+        target_dir: &CacheDir,
+        stamp: &Path,
+        refetch: bool,
+    ) -> Result<(), String> {
+        // 下载的文件以实际下载地址中的文件名保存
+        let url =
+            Url::parse(self.fetch_url()).map_err(|e| format!("{}: {}", self.fetch_url(), e))?;
+        let archive_name = url
+            .path_segments()
+            .and_then(|s| s.last())
+            .ok_or_else(|| format!("Failed to get the file name from url {}", self.fetch_url()))?;
+        let path = &(target_dir.path.join(ARCHIVE_TEMP_DIR));
+        if !refetch && self.is_cached(target_dir, stamp)? {
+            info!(
+                "Source files of {} already exist. Using previous source file cache {:?}",
+                self.url, target_dir.path
+            );
             return Ok(());
         }
 
-        if path.exists() {
-            std::fs::remove_dir_all(path).map_err(|e| e.to_string())?;
+        // 缓存来自其他压缩包，或者要求重新下载：清空缓存目录，避免新旧文件混在一起
+        let empty = target_dir.is_empty().map_err(|e| format!("{e:?}"))?;
+        if !empty {
+            match self.cached_url(stamp) {
+                Some(old) if old != self.url => info!(
+                    "Archive url changed from {} to {}, clean {:?} and re-download",
+                    old, self.url, target_dir.path
+                ),
+                _ => info!("Clean {:?} and re-download {}", target_dir.path, self.url),
+            }
+            target_dir
+                .remove_self_recursive()
+                .and_then(|_| target_dir.create())
+                .map_err(|e| format!("{e:?}"))?;
+        }
+        if stamp.exists() {
+            std::fs::remove_file(stamp).map_err(|e| e.to_string())?;
         }
         //创建临时目录
         std::fs::create_dir(path).map_err(|e| e.to_string())?;
-        info!("downloading {:?}", archive_name);
-        FileUtils::download_file(&self.url, path).map_err(|e| e.to_string())?;
+        info!("downloading {:?} from {}", archive_name, self.fetch_url());
+        let entity = target_dir.entity();
+        let bytes = FileUtils::download_file(self.fetch_url(), path, |downloaded, total| {
+            event::download_progress(entity, self.fetch_url(), downloaded, total)
+        })
+        .map_err(|e| e.to_string())?;
+        entity.add_downloaded_bytes(bytes);
         //下载成功，开始尝试解压
         info!("download {:?} finished, start unzip", archive_name);
+        let sha256 = sha256_file(&path.join(archive_name))?;
         let archive_file = ArchiveFile::new(&path.join(archive_name));
-        archive_file.unzip()?;
+        archive_file.unzip(|extracted, total| {
+            event::extract_progress(entity, archive_name, extracted, total)
+        })?;
         //删除创建的临时文件夹
         std::fs::remove_dir_all(path).map_err(|e| e.to_string())?;
+        std::fs::write(stamp, format!("{}\n{}\n", self.url, sha256))
+            .map_err(|e| format!("Failed to write {}: {}", stamp.display(), e))?;
         return Ok(());
     }
+
+    /// @brief 把文件下载到dir目录下（不解压），用于下载二进制包
+    ///
+    /// @param dir 下载到的目录
+    /// @param entity 下载文件的任务，用于报告下载进度
+    ///
+    /// @return 下载的文件的路径
+    pub fn download(&self, dir: &Path, entity: &Arc<SchedEntity>) -> Result<PathBuf, String> {
+        let url = Url::parse(self.fetch_url()).map_err(|e| e.to_string())?;
+        let file_name = url
+            .path_segments()
+            .and_then(|s| s.last())
+            .ok_or_else(|| format!("Failed to get the file name from url {}", self.fetch_url()))?;
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        info!("downloading {:?} from {}", file_name, self.fetch_url());
+        let bytes = FileUtils::download_file(self.fetch_url(), dir, |downloaded, total| {
+            event::download_progress(entity, self.fetch_url(), downloaded, total)
+        })
+        .map_err(|e| e.to_string())?;
+        entity.add_downloaded_bytes(bytes);
+        Ok(dir.join(file_name))
+    }
 }
 
 pub struct ArchiveFile {
@@ -600,16 +937,15 @@ impl ArchiveFile {
         }
     }
 
-    /// @brief 对self.archive_path路径下名为self.archive_name的压缩文件(tar.gz或zip)进行解压缩
+    /// @brief 对self.archive_path路径下名为self.archive_name的压缩文件(tar.gz、tar.xz或zip)进行解压缩
     ///
-    /// 在此函数中进行路径和文件名有效性的判断，如果有效的话就开始解压缩，根据ArchiveType枚举类型来
-    /// 生成不同的命令来对压缩文件进行解压缩，暂时只支持tar.gz和zip格式，并且都是通过调用bash来解压缩
-    /// 没有引入第三方rust库
+    /// 在此函数中进行路径和文件名有效性的判断，如果有效的话就开始解压缩。解压缩使用tar、flate2、xz2、zip库完成，
+    /// 不依赖主机上的tar命令，并且会保留压缩包中记录的文件权限
     ///
+    /// @param on_progress 解压tar包的进度回调，参数为（已读取的压缩包字节数，压缩包总字节数）
     ///
     /// @return 根据结果返回OK或Err
-
-    pub fn unzip(&self) -> Result<(), String> {
+    pub fn unzip(&self, on_progress: impl FnMut(u64, u64)) -> Result<(), String> {
         let path = &self.archive_path;
         if !path.is_dir() {
             return Err(format!("Archive directory {:?} is wrong", path));
@@ -620,25 +956,10 @@ impl ArchiveFile {
                 path.join(&self.archive_name)
             ));
         }
-        //根据压缩文件的类型生成cmd指令
+        //根据压缩文件的类型进行解压
         match &self.archive_type {
             ArchiveType::TarGz | ArchiveType::TarXz => {
-                let mut cmd = Command::new("tar");
-                cmd.arg("-xf").arg(&self.archive_name);
-                let proc: std::process::Child = cmd
-                    .current_dir(path)
-                    .stderr(Stdio::piped())
-                    .stdout(Stdio::inherit())
-                    .spawn()
-                    .map_err(|e| e.to_string())?;
-                let output = proc.wait_with_output().map_err(|e| e.to_string())?;
-                if !output.status.success() {
-                    return Err(format!(
-                        "unzip failed, status: {:?},  stderr: {:?}",
-                        output.status,
-                        StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
-                    ));
-                }
+                self.unpack_tar(on_progress)?;
             }
 
             ArchiveType::Zip => {
@@ -693,6 +1014,66 @@ impl ArchiveFile {
         }
         return Ok(());
     }
+
+    /// 解压tar.gz或tar.xz压缩包到self.archive_path目录下
+    fn unpack_tar(&self, on_progress: impl FnMut(u64, u64)) -> Result<(), String> {
+        let archive_file = self.archive_path.join(&self.archive_name);
+        let file = File::open(&archive_file)
+            .map_err(|e| format!("Failed to open {}: {}", archive_file.display(), e))?;
+        let total = file
+            .metadata()
+            .map_err(|e| format!("Failed to stat {}: {}", archive_file.display(), e))?
+            .len();
+        let reader = ProgressReader::new(file, total, on_progress);
+        let decoder: Box<dyn Read> = match self.archive_type {
+            ArchiveType::TarGz => Box::new(GzDecoder::new(reader)),
+            ArchiveType::TarXz => Box::new(XzDecoder::new(reader)),
+            _ => return Err("unsupported archive type".to_string()),
+        };
+        let mut archive = tar::Archive::new(decoder);
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_mtime(true);
+        archive.set_overwrite(true);
+        archive
+            .unpack(&self.archive_path)
+            .map_err(|e| format!("Failed to extract {}: {}", self.archive_name, e))
+    }
+}
+
+/// 统计已读取字节数的Reader，用于报告解压进度
+struct ProgressReader<R, F: FnMut(u64, u64)> {
+    inner: R,
+    total: u64,
+    read: u64,
+    reported: u64,
+    step: u64,
+    on_progress: F,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> ProgressReader<R, F> {
+    fn new(inner: R, total: u64, mut on_progress: F) -> Self {
+        on_progress(0, total);
+        Self {
+            inner,
+            total,
+            read: 0,
+            reported: 0,
+            step: (total / 100).max(1),
+            on_progress,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.read - self.reported >= self.step || (n == 0 && self.read != self.reported) {
+            (self.on_progress)(self.read, self.total);
+            self.reported = self.read;
+        }
+        Ok(n)
+    }
 }
 
 pub enum ArchiveType {
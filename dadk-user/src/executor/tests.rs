@@ -1,16 +1,20 @@
-use std::path::PathBuf;
-use test_base::test_context::{self as test_context, test_context};
+use std::{path::PathBuf, sync::Arc};
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
 
 use crate::{
     context::{
-        DadkExecuteContextTestBuildRiscV64V1, DadkExecuteContextTestBuildX86_64V1, TestContextExt,
+        Action, DadkExecuteContextTestBuildRiscV64V1, DadkExecuteContextTestBuildX86_64V1,
+        TestContextExt,
     },
-    executor::Executor,
+    executor::{Executor, ExecutorError},
     parser::Parser,
     scheduler::{SchedEntities, Scheduler},
 };
 
-use super::create_global_env_list;
+use super::{check_env_collisions, create_global_env_list};
 
 fn setup_executor<T: TestContextExt>(config_file: PathBuf, ctx: &T) -> Executor {
     let task = Parser::new(ctx.base_context().config_v2_dir()).parse_config_file(&config_file);
@@ -31,6 +35,7 @@ fn setup_executor<T: TestContextExt>(config_file: PathBuf, ctx: &T) -> Executor
     assert!(entity.is_ok(), "Add task error: {:?}", entity);
     let entity = entity.unwrap();
     let executor = Executor::new(
+        ctx.execute_context().self_ref().unwrap(),
         entity.clone(),
         *ctx.execute_context().action(),
         ctx.base_context().fake_dragonos_sysroot(),
@@ -64,6 +69,179 @@ fn set_local_env(ctx: &DadkExecuteContextTestBuildX86_64V1) {
     assert!(x.is_ok(), "Execute error: {:?}", x);
 }
 
+/// 测试`explain-env`输出的构建命令、工作目录以及与dadk进程不同的环境变量
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn explain_task_env(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let mut executor = setup_executor(config_file_path, ctx);
+
+    let plan = executor.explain().unwrap();
+    assert_eq!(plan.task, "app_normal_with_env_0_2_0");
+    assert!(plan.command.as_deref().unwrap().starts_with("bash -c "));
+    assert!(plan.work_dir.is_some());
+    let cc = plan.envs.iter().find(|e| e.key == "CC").unwrap();
+    assert_eq!(cc.value, "abc-gcc");
+    assert!(plan.envs.iter().any(|e| e.key == "DADK_CURRENT_BUILD_DIR"));
+    // 从dadk进程继承、没有修改的变量不列出
+    if let Ok(path) = std::env::var("PATH") {
+        assert!(!plan.envs.iter().any(|e| e.key == "PATH" && e.value == path));
+    }
+    assert!(plan.text().contains("CC=abc-gcc\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn shell_command() {
+    use dadk_config::common::task::BuildShell;
+
+    let command = super::shell::command(BuildShell::Bash, false, "echo hello");
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(command.get_program(), "bash");
+    assert_eq!(args, ["-c", "echo hello"]);
+
+    let command = super::shell::command(BuildShell::Zsh, true, "echo hello");
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(command.get_program(), "zsh");
+    assert_eq!(args, ["-c", "set -euo pipefail\necho hello"]);
+
+    // 严格模式下，命令在第一条失败的语句处停止
+    let script = "false\necho done";
+    let output = super::shell::command(BuildShell::Sh, false, script)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+    let output = super::shell::command(BuildShell::Sh, true, script)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn explain_env_diff_and_quote() {
+    use super::explain::{env_diff, quote};
+
+    let parent = [("HOME", "/root"), ("PATH", "/usr/bin")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let envs = [("HOME", "/root"), ("PATH", "/opt/bin"), ("ARCH", "x86_64")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+    let diff = env_diff(envs, &parent);
+    assert_eq!(diff.len(), 2);
+    assert_eq!(diff[0].key, "ARCH");
+    assert_eq!(diff[0].parent, None);
+    assert_eq!(diff[1].key, "PATH");
+    assert_eq!(diff[1].parent.as_deref(), Some("/usr/bin"));
+
+    assert_eq!(quote("/usr/bin/make"), "/usr/bin/make");
+    assert_eq!(quote("make -j4"), "'make -j4'");
+    assert_eq!(quote("it's"), r"'it'\''s'");
+    assert_eq!(quote(""), "''");
+}
+
+/// 测试从本地路径构建的任务也能获得源码目录，以及直接依赖的目录别名
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn set_source_and_depends_env(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let execute_ctx = ctx.execute_context().self_ref().unwrap();
+    let mut scheduler = Scheduler::new(
+        execute_ctx.clone(),
+        ctx.base_context().fake_dragonos_sysroot(),
+        *ctx.execute_context().action(),
+        vec![],
+    )
+    .unwrap();
+    let parser = Parser::new(ctx.base_context().config_v2_dir());
+    let mut entities = SchedEntities::new();
+    for name in [
+        "app_normal_with_env_0_2_0.toml",
+        "app_with_depends_0_2_0.toml",
+    ] {
+        let config_file = ctx.base_context().config_v2_dir().join(name);
+        let task = parser.parse_config_file(&config_file).unwrap();
+        entities.add(scheduler.add_task(config_file, task).unwrap());
+    }
+    super::prepare_env(&entities, &execute_ctx).unwrap();
+
+    let source_dir = ctx
+        .base_context()
+        .abs_path("tests/data/apps/app_normal_with_env");
+    let global_envs = execute_ctx.global_env_list().read().unwrap();
+    assert_eq!(
+        global_envs
+            .get("DADK_SOURCE_CACHE_DIR_APP_NORMAL_WITH_ENV_0_2_0")
+            .unwrap()
+            .value,
+        source_dir.to_str().unwrap()
+    );
+    let dep_build_dir = global_envs
+        .get("DADK_BUILD_CACHE_DIR_APP_NORMAL_WITH_ENV_0_2_0")
+        .unwrap()
+        .value
+        .clone();
+    drop(global_envs);
+
+    let entity = entities
+        .get_by_name_version("app_with_depends", "0.2.0")
+        .unwrap();
+    let mut executor = Executor::new(
+        execute_ctx,
+        entity,
+        *ctx.execute_context().action(),
+        ctx.base_context().fake_dragonos_sysroot(),
+    )
+    .unwrap();
+    executor.prepare_local_env().unwrap();
+    assert_eq!(
+        executor
+            .local_envs
+            .get("DADK_CURRENT_SOURCE_DIR")
+            .unwrap()
+            .value,
+        source_dir.to_str().unwrap()
+    );
+    assert_eq!(
+        executor
+            .local_envs
+            .get("DADK_BUILD_CACHE_DIR_APP_NORMAL_WITH_ENV")
+            .unwrap()
+            .value,
+        dep_build_dir
+    );
+    assert_eq!(
+        executor
+            .local_envs
+            .get("DADK_SOURCE_CACHE_DIR_APP_NORMAL_WITH_ENV")
+            .unwrap()
+            .value,
+        source_dir.to_str().unwrap()
+    );
+    // 只为直接依赖添加别名
+    assert!(executor
+        .local_envs
+        .get("DADK_BUILD_CACHE_DIR_APP_WITH_DEPENDS")
+        .is_none());
+    assert_eq!(
+        executor.local_envs.get("DADK_TASK_VERSION").unwrap().value,
+        "0.2.0"
+    );
+    assert_eq!(
+        executor
+            .local_envs
+            .get("DADK_DEP_APP_NORMAL_WITH_ENV_VERSION")
+            .unwrap()
+            .value,
+        "0.2.0"
+    );
+}
+
 /// 测试执行错误时，能否感知到错误
 #[test_context(DadkExecuteContextTestBuildX86_64V1)]
 #[test]
@@ -86,6 +264,64 @@ fn execute_should_capture_error(ctx: &DadkExecuteContextTestBuildX86_64V1) {
     assert!(x.is_err(), "Executor cannot catch error when build error");
 }
 
+/// 测试构建命令超时后会被终止，并返回超时错误
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn execute_should_timeout(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_build_timeout_0_2_0.toml");
+    let mut executor = setup_executor(config_file_path, ctx);
+
+    let start = std::time::Instant::now();
+    let x = executor.execute();
+    assert!(
+        matches!(x, Err(ExecutorError::Timeout(_))),
+        "Expected timeout error, got: {:?}",
+        x
+    );
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+}
+
+/// 测试构建命令在后台留下的进程保持输出管道打开时，任务不会一直等待
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn background_process_does_not_hang(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_build_daemon_0_2_0.toml");
+    let mut executor = setup_executor(config_file_path, ctx);
+
+    let start = std::time::Instant::now();
+    let x = executor.execute();
+    assert!(x.is_ok(), "Executor error: {:?}", x);
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+}
+
+/// 测试构建失败时不会为了获取stderr而重新执行构建命令
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn failed_command_runs_once(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_build_fail_0_2_0.toml");
+    let mut executor = setup_executor(config_file_path, ctx);
+    let runs = executor.build_dir.path.join("runs");
+    std::fs::remove_file(&runs).ok();
+
+    let x = executor.execute();
+    assert!(
+        matches!(x, Err(ExecutorError::TaskFailed(_))),
+        "Expected task failed error, got: {:?}",
+        x
+    );
+    assert_eq!(std::fs::read_to_string(&runs).unwrap(), "run\n");
+    std::fs::remove_file(&runs).ok();
+}
+
 /// 测试能否正确设置ARCH全局环境变量为x86_64
 #[test_context(DadkExecuteContextTestBuildX86_64V1)]
 #[test]
@@ -102,6 +338,134 @@ fn check_arch_env_x86_64(ctx: &DadkExecuteContextTestBuildX86_64V1) {
     assert_eq!(env_list.get("ARCH").unwrap().value, "x86_64");
 }
 
+/// 测试不同任务导出同名环境变量时报错
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn global_env_name_collisions(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let task = Parser::new(ctx.base_context().config_v2_dir())
+        .parse_config_file(&config_file)
+        .unwrap();
+    let mut scheduler = Scheduler::new(
+        ctx.execute_context().self_ref().unwrap(),
+        ctx.base_context().fake_dragonos_sysroot(),
+        *ctx.execute_context().action(),
+        vec![],
+    )
+    .unwrap();
+    let mut entities = SchedEntities::new();
+    for name in ["app-normal", "app.normal"] {
+        let mut task = task.clone();
+        task.name = name.to_string();
+        entities.add(scheduler.add_task(config_file.clone(), task).unwrap());
+    }
+
+    let err = create_global_env_list(&entities, &ctx.execute_context().self_ref().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("DADK_BUILD_CACHE_DIR_APP_NORMAL_0_2_0 is exported by"),
+        "{}",
+        err
+    );
+    assert!(err.contains("app-normal@0.2.0") && err.contains("app.normal@0.2.0"));
+
+    let mut owners = std::collections::BTreeMap::new();
+    owners.insert(
+        "KEY".to_string(),
+        vec!["a@1".to_string(), "b@1".to_string()],
+    );
+    assert!(check_env_collisions(&owners, false).is_err());
+    assert!(check_env_collisions(&owners, true).is_ok());
+}
+
+/// 测试manifest中的`[env]`：只保留passthrough允许的主机环境变量，并加上set中的变量
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn global_env_passthrough(ctx: &BaseGlobalTestContext) {
+    use dadk_config::manifest::EnvConfig;
+
+    use crate::context::DadkUserExecuteContextBuilder;
+
+    let env_config = EnvConfig {
+        passthrough: Some(vec!["PATH".to_string(), "CARGO_*".to_string()]),
+        set: [("DADK_TEST_SET".to_string(), "1".to_string())].into(),
+    };
+    let context = DadkUserExecuteContextBuilder::default()
+        .sysroot_dir(Some(ctx.fake_dragonos_sysroot()))
+        .config_dir(Some(ctx.config_v2_dir()))
+        .action(Action::Build)
+        .thread_num(None)
+        .cache_dir(Some(ctx.fake_dadk_cache_root()))
+        .env_config(env_config)
+        .base_test_context(Some(ctx.clone()))
+        .build()
+        .unwrap();
+    let context = Arc::new(context);
+    context.init(context.clone()).unwrap();
+
+    let env_list = create_global_env_list(&SchedEntities::new(), &context).unwrap();
+    let keys: Vec<&str> = env_list.envs.keys().map(String::as_str).collect();
+    assert!(keys.contains(&"PATH"));
+    assert!(keys.contains(&"DADK_TEST_SET"));
+    assert!(keys.contains(&"DADK_CACHE_ROOT") && keys.contains(&"ARCH"));
+    // cargo test设置了CARGO_PKG_NAME等变量，但没有设置DADK_开头的主机变量
+    assert!(keys.contains(&"CARGO_PKG_NAME"));
+    assert!(keys.iter().all(|k| k.starts_with("CARGO_")
+        || matches!(*k, "PATH" | "DADK_TEST_SET" | "DADK_CACHE_ROOT" | "ARCH")));
+}
+
+/// 测试内核模块：导出内核源码目录和内核版本，安装之后重新生成模块依赖索引
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn kernel_module_env_and_index(ctx: &BaseGlobalTestContext) {
+    use dadk_config::manifest::KernelConfig;
+
+    use super::kernel_module::update_module_index;
+    use crate::context::DadkUserExecuteContextBuilder;
+
+    let kernel = KernelConfig {
+        source_dir: PathBuf::from("kernel"),
+        version: "0.1.10".to_string(),
+        depmod: r#"echo "$DADK_KERNEL_VERSION" > "$DADK_SYSROOT_DIR/modules.index""#.to_string(),
+    };
+    let context = DadkUserExecuteContextBuilder::default()
+        .sysroot_dir(Some(ctx.fake_dragonos_sysroot()))
+        .config_dir(Some(ctx.config_v2_dir()))
+        .action(Action::Install)
+        .thread_num(None)
+        .cache_dir(Some(ctx.fake_dadk_cache_root()))
+        .kernel(Some(kernel))
+        .base_test_context(Some(ctx.clone()))
+        .build()
+        .unwrap();
+    let context = Arc::new(context);
+    context.init(context.clone()).unwrap();
+
+    let env_list = create_global_env_list(&SchedEntities::new(), &context).unwrap();
+    assert_eq!(env_list.get("DADK_KERNEL_VERSION").unwrap().value, "0.1.10");
+    assert!(PathBuf::from(&env_list.get("DADK_KERNEL_SOURCE_DIR").unwrap().value).is_absolute());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let sysroot = tmp.path();
+    let mut task = Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+        .unwrap();
+    // 没有安装内核模块时不执行
+    update_module_index(&context, sysroot, &[task.clone()]).unwrap();
+    assert!(!sysroot.join("modules.index").exists());
+
+    task.kernel_module = true;
+    update_module_index(&context, sysroot, &[task]).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(sysroot.join("modules.index")).unwrap(),
+        "0.1.10\n"
+    );
+}
+
 /// 测试能否正确设置ARCH全局环境变量为riscv64
 #[test_context(DadkExecuteContextTestBuildRiscV64V1)]
 #[test]
@@ -117,3 +481,1151 @@ fn check_arch_env_riscv64(ctx: &DadkExecuteContextTestBuildRiscV64V1) {
     assert!(env_list.get("ARCH").is_some());
     assert_eq!(env_list.get("ARCH").unwrap().value, "riscv64");
 }
+
+/// 测试在暂存目录中创建符号链接、设置权限，以及属主已满足要求时不修改属主
+#[test]
+fn install_files_in_staging() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use dadk_config::common::task::InstallFileConfig;
+
+    use super::install::{apply_ownership, prepare_staging};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let staging = tmp.path();
+    std::fs::create_dir_all(staging.join("bin")).unwrap();
+    std::fs::write(staging.join("bin/busybox"), "").unwrap();
+    std::fs::write(staging.join("bin/sh"), "").unwrap();
+
+    let metadata = std::fs::metadata(staging.join("bin/busybox")).unwrap();
+    let files = vec![
+        InstallFileConfig {
+            path: PathBuf::from("bin/busybox"),
+            mode: Some("4755".to_string()),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+            symlink: None,
+        },
+        InstallFileConfig {
+            path: PathBuf::from("bin/sh"),
+            mode: None,
+            uid: None,
+            gid: None,
+            symlink: Some(PathBuf::from("busybox")),
+        },
+    ];
+    let r = prepare_staging(&files, staging);
+    assert!(r.is_ok(), "prepare staging error: {:?}", r);
+    let r = apply_ownership(&files, staging);
+    assert!(r.is_ok(), "apply ownership error: {:?}", r);
+
+    let mode = std::fs::metadata(staging.join("bin/busybox"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o4755);
+    assert_eq!(
+        std::fs::read_link(staging.join("bin/sh")).unwrap(),
+        PathBuf::from("busybox")
+    );
+
+    let missing = vec![InstallFileConfig {
+        path: PathBuf::from("bin/missing"),
+        mode: Some("755".to_string()),
+        uid: None,
+        gid: None,
+        symlink: None,
+    }];
+    assert!(prepare_staging(&missing, staging).is_err());
+}
+
+/// 测试只对ELF文件执行strip
+#[test]
+fn strip_only_elf_files() {
+    use dadk_config::common::target_arch::TargetArch;
+
+    use super::install::{default_strip_tool, is_elf, strip_elf_files};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let staging = tmp.path();
+    std::fs::create_dir_all(staging.join("lib")).unwrap();
+    std::fs::write(staging.join("app"), b"\x7fELF\x02\x01\x01").unwrap();
+    std::fs::write(staging.join("lib/libfoo.so"), b"\x7fELF\x02\x01\x01").unwrap();
+    std::fs::write(staging.join("run.sh"), "#!/bin/sh\n").unwrap();
+    std::fs::write(staging.join("empty"), "").unwrap();
+    std::os::unix::fs::symlink("app", staging.join("app-link")).unwrap();
+
+    assert!(is_elf(&staging.join("app")));
+    assert!(!is_elf(&staging.join("run.sh")));
+    assert!(!is_elf(&staging.join("empty")));
+
+    // `true`接受任意参数并成功退出，用来代替真实的strip工具
+    assert_eq!(strip_elf_files(staging, "true").unwrap(), 2);
+    assert!(strip_elf_files(staging, "false").is_err());
+
+    let native = TargetArch::try_from(std::env::consts::ARCH);
+    if let Ok(native) = native {
+        assert_eq!(default_strip_tool(native), "strip");
+    }
+    if std::env::consts::ARCH != "riscv64" {
+        assert_eq!(
+            default_strip_tool(TargetArch::RiscV64),
+            "riscv64-linux-musl-strip"
+        );
+    }
+}
+
+/// 测试安装时排除`install.exclude`匹配的文件
+#[test]
+fn install_exclude() {
+    use super::install::{is_excluded, remove_excluded};
+
+    let patterns: Vec<String> = ["*.o", "target/", "tests/fixtures/**", "/build.log"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert!(is_excluded(&patterns, "main.o", false));
+    assert!(is_excluded(&patterns, "lib/x/util.o", false));
+    assert!(is_excluded(&patterns, "target", true));
+    assert!(is_excluded(&patterns, "src/target/debug/app", false));
+    // 以`/`结尾的模式只匹配目录
+    assert!(!is_excluded(&patterns, "bin/target", false));
+    assert!(is_excluded(&patterns, "tests/fixtures/a/b.txt", false));
+    assert!(!is_excluded(&patterns, "tests/run.sh", false));
+    assert!(is_excluded(&patterns, "build.log", false));
+    assert!(!is_excluded(&patterns, "logs/build.log", false));
+    assert!(!is_excluded(&patterns, "bin/app", false));
+    assert!(!is_excluded(&[], "main.o", false));
+
+    let tmp = tempfile::tempdir().unwrap();
+    let staging = tmp.path();
+    std::fs::create_dir_all(staging.join("bin")).unwrap();
+    std::fs::create_dir_all(staging.join("target/debug")).unwrap();
+    std::fs::write(staging.join("bin/app"), "app").unwrap();
+    std::fs::write(staging.join("bin/app.o"), "").unwrap();
+    std::fs::write(staging.join("target/debug/app"), "").unwrap();
+    std::os::unix::fs::symlink("bin/app", staging.join("app.o")).unwrap();
+
+    assert_eq!(remove_excluded(staging, &patterns).unwrap(), 3);
+    assert!(staging.join("bin/app").exists());
+    assert!(!staging.join("bin/app.o").exists());
+    assert!(!staging.join("target").exists());
+    assert!(staging.join("app.o").symlink_metadata().is_err());
+    assert_eq!(remove_excluded(staging, &[]).unwrap(), 0);
+}
+
+/// 测试容器后端生成的命令
+#[test]
+fn container_backend_command() {
+    use std::process::Command;
+
+    use dadk_config::manifest::{ContainerConfig, ContainerEngine};
+
+    use super::backend::{ContainerBackend, ExecutorBackend, HostBackend};
+
+    let backend = ContainerBackend::new(
+        ContainerConfig {
+            engine: ContainerEngine::Podman,
+            image: "dragonos/dadk-build:latest".to_string(),
+            mounts: vec!["/opt/toolchain:/opt/toolchain:ro".to_string()],
+            args: vec!["--network=host".to_string()],
+        },
+        vec![
+            PathBuf::from("/dadk_cache"),
+            PathBuf::from("/sysroot"),
+            PathBuf::from("/dadk_cache/source"),
+        ],
+    );
+
+    let mut command = Command::new("bash");
+    command
+        .current_dir("/work/app")
+        .arg("-c")
+        .arg("make install")
+        .env("DADK_CURRENT_BUILD_DIR", "/dadk_cache/build/app-0.1.0")
+        .env("CC", "x86_64-linux-musl-gcc");
+    if let Some(path) = std::env::var_os("PATH") {
+        command.env("PATH", path);
+    }
+
+    let command = backend.command(command);
+    assert_eq!(command.get_program(), "podman");
+    let args: Vec<String> = command
+        .get_args()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+    assert_eq!(
+        args,
+        vec![
+            "run",
+            "--rm",
+            "-i",
+            "--userns=keep-id",
+            "-v",
+            "/dadk_cache:/dadk_cache",
+            "-v",
+            "/sysroot:/sysroot",
+            "-v",
+            "/work/app:/work/app",
+            "-v",
+            "/opt/toolchain:/opt/toolchain:ro",
+            "-w",
+            "/work/app",
+            "-e",
+            "CC=x86_64-linux-musl-gcc",
+            "-e",
+            "DADK_CURRENT_BUILD_DIR=/dadk_cache/build/app-0.1.0",
+            "--network=host",
+            "dragonos/dadk-build:latest",
+            "bash",
+            "-c",
+            "make install",
+        ]
+    );
+
+    // 主机后端不修改命令
+    let command = HostBackend.command(Command::new("bash"));
+    assert_eq!(command.get_program(), "bash");
+    assert_eq!(command.get_args().count(), 0);
+}
+
+/// 测试失败后按照次数重试，成功后不再重试
+#[test]
+fn retry_until_success() {
+    use std::time::Duration;
+
+    use super::retry::retry;
+    use crate::interrupt::InterruptState;
+
+    let interrupts = InterruptState::new();
+    let mut attempts = 0;
+    let r: Result<u32, String> = retry("fetch", 3, Duration::ZERO, &interrupts, || {
+        attempts += 1;
+        if attempts < 3 {
+            Err(format!("attempt {} failed", attempts))
+        } else {
+            Ok(attempts)
+        }
+    });
+    assert_eq!(r, Ok(3));
+
+    let mut attempts = 0;
+    let r: Result<(), String> = retry("fetch", 2, Duration::ZERO, &interrupts, || {
+        attempts += 1;
+        Err("network unreachable".to_string())
+    });
+    assert_eq!(r, Err("network unreachable".to_string()));
+    assert_eq!(attempts, 3);
+}
+
+#[test]
+fn retry_backoff_delay() {
+    use std::time::Duration;
+
+    use super::retry::backoff_delay;
+
+    let base = Duration::from_secs(2);
+    assert_eq!(backoff_delay(base, 1), Duration::from_secs(2));
+    assert_eq!(backoff_delay(base, 2), Duration::from_secs(4));
+    assert_eq!(backoff_delay(base, 3), Duration::from_secs(8));
+    assert_eq!(backoff_delay(base, 10), Duration::from_secs(60));
+    assert_eq!(backoff_delay(base, u32::MAX), Duration::from_secs(60));
+}
+
+/// 把tests/data/archives下的压缩包拷贝到临时目录中解压，返回解压后的源码目录
+fn unpack_fixture_archive(name: &str) -> Result<tempfile::TempDir, String> {
+    use super::source::ArchiveFile;
+
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../tests/data/archives")
+        .join(name);
+    let source_dir = tempfile::tempdir().unwrap();
+    let temp_dir = source_dir.path().join("DRAGONOS_ARCHIVE_TEMP");
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    std::fs::copy(&fixture, temp_dir.join(name)).unwrap();
+
+    let total = std::fs::metadata(&fixture).unwrap().len();
+    let mut progress = Vec::new();
+    ArchiveFile::new(&temp_dir.join(name)).unzip(|extracted, t| progress.push((extracted, t)))?;
+    assert_eq!(progress.first(), Some(&(0, total)));
+    assert_eq!(progress.last(), Some(&(total, total)));
+    Ok(source_dir)
+}
+
+/// 测试不依赖tar命令解压tar.gz、tar.xz，并保留文件权限
+#[test]
+fn unpack_tar_archives() {
+    use std::os::unix::fs::PermissionsExt;
+
+    for name in ["hello-1.0.tar.gz", "hello-1.0.tar.xz"] {
+        let tmp = unpack_fixture_archive(name).unwrap();
+        let source_dir = tmp.path();
+        let script = source_dir.join("bin/hello.sh");
+        assert_eq!(
+            std::fs::read_to_string(&script).unwrap(),
+            "#!/bin/sh\necho hello\n"
+        );
+        let mode = std::fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755, "{}: wrong mode {:o}", name, mode);
+        assert_eq!(
+            std::fs::read_to_string(source_dir.join("README")).unwrap(),
+            "hello archive\n"
+        );
+        // 压缩包和解压出的顶层目录都已被删除
+        assert!(!source_dir.join("DRAGONOS_ARCHIVE_TEMP/hello-1.0").exists());
+        assert!(!source_dir
+            .join(format!("DRAGONOS_ARCHIVE_TEMP/{}", name))
+            .exists());
+    }
+}
+
+/// 测试损坏的压缩包会返回带有压缩包名称的错误
+#[test]
+fn unpack_corrupted_tar_archive() {
+    let err = unpack_fixture_archive("corrupted.tar.gz").unwrap_err();
+    assert!(
+        err.contains("corrupted.tar.gz"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+/// 测试cargo任务的`cargo build`参数
+#[test]
+fn cargo_build_args() {
+    use dadk_config::common::{target_arch::TargetArch, task::CargoConfig};
+
+    let mut cargo = CargoConfig::default();
+    assert_eq!(
+        super::cargo::build_args(&cargo, TargetArch::RiscV64, false),
+        vec![
+            "build",
+            "--target",
+            "riscv64gc-unknown-linux-musl",
+            "--release"
+        ]
+    );
+
+    cargo.rust_target = Some("targets/x86_64-unknown-dragonos.json".to_string());
+    cargo.features = vec!["a".to_string(), "b".to_string()];
+    cargo.no_default_features = true;
+    cargo.bins = vec!["app".to_string()];
+    cargo.args = vec!["-Zbuild-std=core,alloc".to_string()];
+    assert_eq!(
+        super::cargo::build_args(&cargo, TargetArch::X86_64, true),
+        vec![
+            "build",
+            "--target",
+            "targets/x86_64-unknown-dragonos.json",
+            "--release",
+            "--features",
+            "a,b",
+            "--no-default-features",
+            "--offline",
+            "--bin",
+            "app",
+            "-Zbuild-std=core,alloc",
+        ]
+    );
+    // 使用target JSON文件时，构建结果位于以文件名命名的目录中
+    assert_eq!(
+        super::cargo::artifact_dir(&PathBuf::from("/src/target"), &cargo, TargetArch::X86_64),
+        PathBuf::from("/src/target/x86_64-unknown-dragonos/release")
+    );
+}
+
+/// 测试cargo任务由DADK执行cargo build，而不是执行构建命令
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn cargo_task_command(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_cargo_0_2_0.toml");
+    let executor = setup_executor(config_file_path, ctx);
+
+    let command = executor.create_command().unwrap().unwrap();
+    assert_eq!(command.get_program(), "cargo");
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(
+        args,
+        vec![
+            "build",
+            "--target",
+            "x86_64-unknown-linux-musl",
+            "--release",
+            "--bin",
+            "app_cargo"
+        ]
+    );
+    assert!(command
+        .get_current_dir()
+        .unwrap()
+        .ends_with("tests/data/apps/app_cargo"));
+}
+
+/// 测试按照任务类型设置编译缓存的环境变量
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn compiler_cache_envs(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use dadk_config::manifest::CompilerCacheConfig;
+
+    use super::compiler_cache::{envs, wrappers};
+
+    let parse = |name: &str| {
+        Parser::new(ctx.base_context().config_v2_dir())
+            .parse_config_file(&ctx.base_context().config_v2_dir().join(name))
+            .unwrap()
+    };
+    let mut config = CompilerCacheConfig::default();
+    let mut cargo = parse("app_cargo_0_2_0.toml");
+    assert!(wrappers(&config, &cargo).is_empty());
+    assert!(envs(&config, &cargo, |_| None).is_empty());
+
+    config.enabled = true;
+    assert_eq!(wrappers(&config, &cargo), vec!["sccache"]);
+    assert_eq!(
+        envs(&config, &cargo, |_| None),
+        vec![("RUSTC_WRAPPER".to_string(), "sccache".to_string())]
+    );
+    // 任务中设置的同名变量优先
+    assert!(envs(&config, &cargo, |_| Some("other".to_string())).is_empty());
+    // 任务可以单独关闭编译缓存
+    cargo.build.compiler_cache = Some(false);
+    assert!(wrappers(&config, &cargo).is_empty());
+
+    let cmake = parse("app_cmake_0_2_0.toml");
+    assert_eq!(wrappers(&config, &cmake), vec!["ccache"]);
+    assert_eq!(
+        envs(&config, &cmake, |_| None),
+        vec![
+            (
+                "CMAKE_C_COMPILER_LAUNCHER".to_string(),
+                "ccache".to_string()
+            ),
+            (
+                "CMAKE_CXX_COMPILER_LAUNCHER".to_string(),
+                "ccache".to_string()
+            ),
+        ]
+    );
+
+    // 自定义构建命令的任务：在CC、CXX前加上wrapper，已经加上的不重复添加
+    config.rustc_wrapper = String::new();
+    let normal = parse("app_normal_with_env_0_2_0.toml");
+    assert_eq!(wrappers(&config, &normal), vec!["ccache"]);
+    let local = |key: &str| match key {
+        "CC" => Some("x86_64-linux-musl-gcc".to_string()),
+        "CXX" => Some("ccache g++".to_string()),
+        _ => None,
+    };
+    assert_eq!(
+        envs(&config, &normal, local),
+        vec![("CC".to_string(), "ccache x86_64-linux-musl-gcc".to_string())]
+    );
+}
+
+/// 测试从`cargo metadata`的输出中找到二进制目标，并复制构建结果
+#[test]
+fn cargo_metadata_artifacts() {
+    use super::cargo::{copy_artifacts, parse_metadata, METADATA_ARGS};
+
+    let project = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/apps/app_cargo");
+    let output = std::process::Command::new("cargo")
+        .args(METADATA_ARGS)
+        .current_dir(&project)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let metadata = String::from_utf8(output.stdout).unwrap();
+
+    let (target_dir, mut bins) = parse_metadata(&metadata, &[]).unwrap();
+    bins.sort();
+    assert_eq!(bins, vec!["app_cargo", "app_cargo_helper"]);
+    assert!(target_dir.ends_with("target"));
+    let (_, bins) = parse_metadata(&metadata, &["app_cargo_helper".to_string()]).unwrap();
+    assert_eq!(bins, vec!["app_cargo_helper"]);
+    assert!(parse_metadata(&metadata, &["missing".to_string()]).is_err());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let (artifact_dir, build_dir) = (dir.join("release"), dir.join("build"));
+    std::fs::create_dir_all(&artifact_dir).unwrap();
+    std::fs::create_dir_all(&build_dir).unwrap();
+    std::fs::write(artifact_dir.join("app_cargo"), "new").unwrap();
+    std::fs::write(build_dir.join("app_cargo"), "old").unwrap();
+    assert_eq!(
+        copy_artifacts(&artifact_dir, &["app_cargo".to_string()], &build_dir).unwrap(),
+        1
+    );
+    assert_eq!(
+        std::fs::read_to_string(build_dir.join("app_cargo")).unwrap(),
+        "new"
+    );
+    assert!(copy_artifacts(&artifact_dir, &["app_cargo_helper".to_string()], &build_dir).is_err());
+}
+
+/// 测试cmake任务生成的构建命令，以及交叉编译时设置的环境变量
+#[test_context(DadkExecuteContextTestBuildRiscV64V1)]
+#[test]
+fn cmake_task_command(ctx: &DadkExecuteContextTestBuildRiscV64V1) {
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_cmake_0_2_0.toml");
+    let mut executor = setup_executor(config_file_path, ctx);
+    executor.prepare_local_env().unwrap();
+
+    let command = executor.create_command().unwrap().unwrap();
+    assert_eq!(command.get_program(), "bash");
+    let script = command.get_args().nth(1).unwrap().to_str().unwrap();
+    let build_dir = crate::utils::path::abs_path(&executor.build_dir.path);
+    assert!(
+        script.starts_with(&format!(
+            "cmake -S . -B build -DCMAKE_BUILD_TYPE=Release -DCMAKE_INSTALL_PREFIX={}",
+            build_dir.display()
+        )),
+        "{}",
+        script
+    );
+    assert!(script.contains("-DBUILD_SHARED_LIBS=OFF"), "{}", script);
+    assert!(
+        script.ends_with("&& cmake --build build --parallel && cmake --install build"),
+        "{}",
+        script
+    );
+
+    if std::env::consts::ARCH != "riscv64" {
+        assert!(
+            script.contains("-DCMAKE_SYSTEM_PROCESSOR=riscv64"),
+            "{}",
+            script
+        );
+        // 任务中设置的环境变量优先
+        assert_eq!(
+            executor.local_envs.get("CC").unwrap().value,
+            "riscv64-linux-musl-clang"
+        );
+        assert_eq!(
+            executor.local_envs.get("CXX").unwrap().value,
+            "riscv64-linux-musl-g++"
+        );
+    }
+}
+
+/// 测试autotools任务生成的构建命令
+#[test]
+fn autotools_script() {
+    use dadk_config::common::{target_arch::TargetArch, task::AutotoolsConfig};
+
+    use super::build_system::{autotools_script, cross_envs};
+
+    let native = TargetArch::try_from(std::env::consts::ARCH).unwrap();
+    let mut autotools = AutotoolsConfig::default();
+    assert_eq!(
+        autotools_script(&autotools, native, &PathBuf::from("/cache/build/app")),
+        "{ [ -x ./configure ] || autoreconf -fi; } && ./configure --prefix=/cache/build/app \
+         && make -j\"$(nproc)\" && make install"
+    );
+    assert!(cross_envs(native, &PathBuf::from("/sysroot")).is_empty());
+
+    if native != TargetArch::RiscV64 {
+        autotools.configure_options =
+            vec!["--disable-nls".to_string(), "CFLAGS=-O2 -g".to_string()];
+        autotools.make_options = vec!["V=1".to_string()];
+        assert_eq!(
+            autotools_script(
+                &autotools,
+                TargetArch::RiscV64,
+                &PathBuf::from("/cache/my app")
+            ),
+            "{ [ -x ./configure ] || autoreconf -fi; } && ./configure '--prefix=/cache/my app' \
+             --host=riscv64-linux-musl --disable-nls 'CFLAGS=-O2 -g' \
+             && make -j\"$(nproc)\" V=1 && make install"
+        );
+        let envs = cross_envs(TargetArch::RiscV64, &PathBuf::from("/sysroot"));
+        assert!(envs.contains(&("CC".to_string(), "riscv64-linux-musl-gcc".to_string())));
+        assert!(envs.contains(&(
+            "PKG_CONFIG_PATH".to_string(),
+            "/sysroot/lib/pkgconfig:/sysroot/usr/lib/pkgconfig".to_string()
+        )));
+    }
+}
+
+/// 测试构建命令的资源限制：cgroup以及无法使用cgroup时的回退
+#[test]
+fn resource_limits() {
+    use std::process::Command;
+
+    use dadk_config::{common::task::ResourcesConfig, manifest::ContainerConfig};
+
+    use super::{
+        backend::{ContainerBackend, ExecutorBackend},
+        resources::{self, CgroupParent, ResourceLimits},
+    };
+
+    let limits = ResourceLimits::from_config(&ResourcesConfig {
+        cpus: Some(4),
+        memory: Some("512M".to_string()),
+    })
+    .unwrap();
+    assert_eq!(limits.memory, Some(512 << 20));
+    assert_eq!(limits.cpu_max().as_deref(), Some("400000 100000"));
+
+    assert_eq!(
+        resources::current_cgroup("0::/user.slice/dadk.scope\n"),
+        Some(PathBuf::from("user.slice/dadk.scope"))
+    );
+    assert_eq!(
+        resources::current_cgroup("1:name=systemd:/init.scope\n"),
+        None
+    );
+
+    assert!(CgroupParent::setup(std::path::Path::new("/nonexistent"), "0::/\n", 1).is_err());
+    cgroup_real_flow(&limits);
+
+    // 回退：限制虚拟内存的大小（ulimit -v的单位为KiB）
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("ulimit -v");
+    resources::apply_fallback(&mut command, &limits);
+    let output = command.output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "524288");
+
+    // 容器后端通过容器引擎的参数限制资源
+    let backend = ContainerBackend::new(
+        ContainerConfig {
+            engine: Default::default(),
+            image: "dragonos/dadk-build:latest".to_string(),
+            mounts: vec![],
+            args: vec![],
+        },
+        vec![],
+    )
+    .resources(Some(limits));
+    let command = backend.command(Command::new("make"));
+    let args: Vec<String> = command
+        .get_args()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+    let image = args.iter().position(|a| a == "dragonos/dadk-build:latest");
+    assert_eq!(
+        args[image.unwrap() - 4..image.unwrap()],
+        ["--cpus", "4", "--memory", "536870912b"]
+    );
+}
+
+/// 在真实的cgroup v2中创建DADK的cgroup：没有cgroup v2或者没有写权限时跳过。
+///
+/// 用`sleep`进程代替DADK，避免把测试进程移入其他cgroup
+fn cgroup_real_flow(limits: &super::resources::ResourceLimits) {
+    use std::process::{Child, Command};
+
+    use super::resources::{self, Cgroup, CgroupParent};
+
+    let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    let Some(root) = mounts.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.get(2) == Some(&"cgroup2")).then(|| PathBuf::from(fields[1]))
+    }) else {
+        eprintln!("cgroup v2 is not mounted, skipped");
+        return;
+    };
+    let proc_cgroup = |pid: u32| std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).unwrap();
+    let own = resources::current_cgroup(&proc_cgroup(std::process::id())).unwrap();
+    let test_cgroup = root
+        .join(own)
+        .join(format!("dadk-test-{}", std::process::id()));
+    if std::fs::create_dir(&test_cgroup).is_err() {
+        eprintln!(
+            "No permission to create cgroups in {}, skipped",
+            root.display()
+        );
+        return;
+    }
+    let spawn = || {
+        let child = Command::new("sleep").arg("60").spawn().unwrap();
+        std::fs::write(test_cgroup.join("cgroup.procs"), child.id().to_string()).unwrap();
+        child
+    };
+    let kill = |mut child: Child| {
+        child.kill().ok();
+        child.wait().ok();
+    };
+    let dadk = spawn();
+    let pid = dadk.id();
+    let relative = |pid: u32| {
+        resources::current_cgroup(&proc_cgroup(pid))
+            .map(|p| root.join(p))
+            .unwrap()
+    };
+
+    let available = std::fs::read_to_string(test_cgroup.join("cgroup.controllers")).unwrap();
+    if !available.contains("cpu") || !available.contains("memory") {
+        let e = CgroupParent::setup(&root, &proc_cgroup(pid), pid).unwrap_err();
+        assert!(e.contains("not available"), "{}", e);
+        assert_eq!(relative(pid), test_cgroup);
+        kill(dadk);
+    } else {
+        // 原来的cgroup中还有其他进程：无法启用控制器，回到原来的cgroup，并报告错误
+        let other = spawn();
+        let e = CgroupParent::setup(&root, &proc_cgroup(pid), pid).unwrap_err();
+        assert!(e.contains("cgroup.subtree_control"), "{}", e);
+        assert_eq!(relative(pid), test_cgroup);
+        assert!(!test_cgroup.join(format!("dadk-{}", pid)).exists());
+        kill(other);
+
+        let parent = CgroupParent::setup(&root, &proc_cgroup(pid), pid).unwrap();
+        assert_eq!(parent.path(), test_cgroup.join(format!("dadk-{}", pid)));
+        assert_eq!(relative(pid), parent.path().join("supervisor"));
+        let cgroup = Cgroup::create(parent.path(), "app_0_1_0-x86_64-0", limits).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(cgroup.path().join("cpu.max"))
+                .unwrap()
+                .trim(),
+            "400000 100000"
+        );
+        assert_eq!(
+            std::fs::read_to_string(cgroup.path().join("memory.max"))
+                .unwrap()
+                .trim(),
+            (512 << 20).to_string()
+        );
+        let mut command = Command::new("cat");
+        command.arg("/proc/self/cgroup");
+        cgroup.attach(&mut command);
+        let output = String::from_utf8(command.output().unwrap().stdout).unwrap();
+        assert_eq!(
+            root.join(resources::current_cgroup(&output).unwrap()),
+            cgroup.path()
+        );
+        let path = cgroup.path().to_path_buf();
+        drop(cgroup);
+        assert!(!path.exists());
+
+        // 不再使用时回到原来的cgroup，关闭启用的控制器，并删除创建的cgroup
+        let path = parent.path().to_path_buf();
+        drop(parent);
+        assert_eq!(relative(pid), test_cgroup);
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(test_cgroup.join("cgroup.subtree_control"))
+                .unwrap()
+                .trim(),
+            ""
+        );
+
+        // DADK异常退出后，下一次创建时删除留下的cgroup
+        let parent = CgroupParent::setup(&root, &proc_cgroup(pid), pid).unwrap();
+        kill(dadk);
+        resources::remove_stale(&test_cgroup);
+        assert!(!parent.path().exists());
+    }
+    std::fs::remove_dir(&test_cgroup).unwrap();
+}
+
+/// 测试按顺序应用补丁，并且再次执行时不会重复应用
+#[test]
+fn apply_source_patches() {
+    use std::process::Command;
+
+    use super::patch::{apply_patches, reset_git_source, PatchTool, PATCH_STAMP};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let (src, patches) = (root.join("src"), root.join("patches"));
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::create_dir_all(&patches).unwrap();
+    std::fs::write(src.join("main.c"), "int main() {\n    return 1;\n}\n").unwrap();
+    let p1 = patches.join("0001-return-0.patch");
+    std::fs::write(
+        &p1,
+        "--- a/main.c\n+++ b/main.c\n@@ -1,3 +1,3 @@\n int main() {\n-    return 1;\n+    return 0;\n }\n",
+    )
+    .unwrap();
+    let p2 = patches.join("0002-add-config.patch");
+    std::fs::write(
+        &p2,
+        "--- /dev/null\n+++ b/config.h\n@@ -0,0 +1 @@\n+#define DRAGONOS 1\n",
+    )
+    .unwrap();
+
+    apply_patches(&src, &[p1.clone()], PatchTool::Patch).unwrap();
+    assert!(std::fs::read_to_string(src.join("main.c"))
+        .unwrap()
+        .contains("return 0;"));
+    // 已经应用的补丁不会重复应用，只应用新增的补丁
+    apply_patches(&src, &[p1.clone()], PatchTool::Patch).unwrap();
+    apply_patches(&src, &[p1.clone(), p2.clone()], PatchTool::Patch).unwrap();
+    assert!(src.join("config.h").exists());
+    let stamp = std::fs::read_to_string(src.join(PATCH_STAMP)).unwrap();
+    assert_eq!(stamp.lines().count(), 2);
+    assert!(stamp.contains("0002-add-config.patch"), "{}", stamp);
+
+    // 已经应用的补丁发生变化时报错
+    assert!(apply_patches(&src, &[p2.clone()], PatchTool::Patch).is_err());
+
+    // git仓库：恢复到未打补丁的状态后可以重新应用
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=dadk", "-c", "user.email=dadk@localhost"])
+            .args(args)
+            .current_dir(&src)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    std::fs::remove_dir_all(&src).unwrap();
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("main.c"), "int main() {\n    return 1;\n}\n").unwrap();
+    git(&["init", "-q"]);
+    git(&["add", "main.c"]);
+    git(&["commit", "-q", "-m", "init"]);
+    let both = [p1.clone(), p2.clone()];
+    apply_patches(&src, &both, PatchTool::Git).unwrap();
+    assert!(src.join("config.h").exists());
+    reset_git_source(&src).unwrap();
+    assert!(!src.join("config.h").exists());
+    assert!(!src.join(PATCH_STAMP).exists());
+    apply_patches(&src, &both, PatchTool::Git).unwrap();
+    assert!(std::fs::read_to_string(src.join("main.c"))
+        .unwrap()
+        .contains("return 0;"));
+    // 补丁无法应用时报错
+    reset_git_source(&src).unwrap();
+    std::fs::write(src.join("main.c"), "int main() {}\n").unwrap();
+    assert!(apply_patches(&src, &both, PatchTool::Git).is_err());
+}
+
+/// 测试git命令超时、传输停滞时被终止，并且不会询问用户名和密码
+#[test]
+fn git_timeouts() {
+    use std::{
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use super::source::{run_git, GitTimeouts};
+
+    let sh = |script: &str| {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    };
+    let timeouts = GitTimeouts {
+        timeout: Some(Duration::from_millis(500)),
+        stall: Some(Duration::from_millis(200)),
+    };
+
+    // 没有任何输出的远程操作被认为已经停滞
+    let started_at = Instant::now();
+    let err = run_git(&mut sh("sleep 10"), &timeouts, true).unwrap_err();
+    assert!(err.contains("stalled"), "{}", err);
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+
+    // 持续输出进度，但是超过了超时时间
+    let err = run_git(
+        &mut sh("while :; do echo progress >&2; sleep 0.05; done"),
+        &timeouts,
+        true,
+    )
+    .unwrap_err();
+    assert!(err.contains("timed out after 500ms"), "{}", err);
+
+    // 本地操作不检测停滞
+    let local = GitTimeouts {
+        timeout: None,
+        stall: Some(Duration::from_millis(100)),
+    };
+    assert!(run_git(&mut sh("sleep 0.3"), &local, false)
+        .unwrap()
+        .status
+        .success());
+
+    // 进度信息只保留最后一次更新的内容
+    let output = run_git(
+        &mut sh(r#"printf '1%%\r2%%\r3%%\n' >&2; echo "$GIT_TERMINAL_PROMPT""#),
+        &GitTimeouts::default(),
+        false,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "3%\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+}
+
+/// 测试离线模式下只使用源码缓存，缓存中没有的源码直接报错
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn offline_sources(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use std::process::Command;
+
+    use super::{
+        cache::{CacheDir, CacheDirType},
+        source::{ArchiveSource, GitSource},
+    };
+
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let executor = setup_executor(config_file_path, ctx);
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let upstream = root.join("upstream");
+    std::fs::create_dir_all(&upstream).unwrap();
+    let git = |dir: &PathBuf, args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=dadk", "-c", "user.email=dadk@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    git(&upstream, &["init", "-q", "-b", "dadk"]);
+    std::fs::write(upstream.join("main.c"), "int main() {}\n").unwrap();
+    git(&upstream, &["add", "main.c"]);
+    git(&upstream, &["commit", "-q", "-m", "init"]);
+
+    let source_dir = CacheDir::new(
+        &root.join("cache"),
+        executor.entity.clone(),
+        CacheDirType::Source,
+    )
+    .unwrap();
+    let url = upstream.to_string_lossy().to_string();
+    let source = GitSource::new(url.clone(), Some("dadk".to_string()), None);
+    let err = source.prepare_offline(&source_dir).unwrap_err();
+    assert!(err.contains("offline"), "{}", err);
+
+    // 源码缓存中已有仓库时，不需要访问远程仓库
+    git(
+        &root,
+        &["clone", "-q", &url, &source_dir.path.to_string_lossy()],
+    );
+    std::fs::write(source_dir.path.join("main.c"), "modified").unwrap();
+    source.prepare_offline(&source_dir).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(source_dir.path.join("main.c")).unwrap(),
+        "int main() {}\n"
+    );
+    // 缓存中没有的提交不会从远程仓库拉取
+    let missing = GitSource::new(url, None, Some("0123456789abcdef".to_string()));
+    assert!(missing.prepare_offline(&source_dir).is_err());
+
+    let archive = ArchiveSource::new("https://example.com/app.tar.gz".to_string());
+    let archive_dir = CacheDir::new(
+        &root.join("archive"),
+        executor.entity.clone(),
+        CacheDirType::Build,
+    )
+    .unwrap();
+    let stamp = root.join("archive_stamp");
+    assert!(!archive.is_cached(&archive_dir, &stamp).unwrap());
+    std::fs::write(archive_dir.path.join("app"), "app").unwrap();
+    std::fs::write(&stamp, "https://example.com/app.tar.gz\n").unwrap();
+    assert!(archive.is_cached(&archive_dir, &stamp).unwrap());
+    // 上次下载没有完成
+    std::fs::create_dir(archive_dir.path.join("DRAGONOS_ARCHIVE_TEMP")).unwrap();
+    assert!(!archive.is_cached(&archive_dir, &stamp).unwrap());
+}
+
+/// 测试在线压缩包的缓存只在记录的URL与配置相同时使用
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn archive_cache_stamp(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use super::{
+        cache::{CacheDir, CacheDirType},
+        source::{ArchiveSource, ARCHIVE_STAMP},
+    };
+
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let executor = setup_executor(config_file_path, ctx);
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let source_dir = CacheDir::new(root, executor.entity.clone(), CacheDirType::Source).unwrap();
+    let stamp = source_dir.path.join(ARCHIVE_STAMP);
+    std::fs::write(source_dir.path.join("main.c"), "int main() {}\n").unwrap();
+
+    let old = ArchiveSource::new("https://example.com/app-1.0.tar.gz".to_string());
+    let new = ArchiveSource::new("https://example.com/app-2.0.tar.gz".to_string());
+    // 旧版本的dadk没有记录压缩包的来源，不知道缓存来自哪个压缩包
+    assert!(!old.is_cached(&source_dir, &stamp).unwrap());
+    assert_eq!(old.cached_url(&stamp), None);
+
+    std::fs::write(&stamp, "https://example.com/app-1.0.tar.gz\nabcdef\n").unwrap();
+    assert!(old.is_cached(&source_dir, &stamp).unwrap());
+    assert_eq!(
+        new.cached_url(&stamp).as_deref(),
+        Some("https://example.com/app-1.0.tar.gz")
+    );
+    // 配置中的URL改变后，缓存失效
+    assert!(!new.is_cached(&source_dir, &stamp).unwrap());
+}
+
+/// 测试从镜像拉取源文件：访问镜像地址，缓存仍然以配置中的URL为准
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn fetch_from_mirror(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use std::process::Command;
+
+    use super::{
+        cache::{CacheDir, CacheDirType},
+        source::{ArchiveSource, GitSource},
+    };
+
+    let config_file_path = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let executor = setup_executor(config_file_path, ctx);
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let mirror = root.join("mirror");
+    std::fs::create_dir_all(&mirror).unwrap();
+    let git = |dir: &PathBuf, args: &[&str]| {
+        let output = Command::new("git")
+            .args(["-c", "user.name=dadk", "-c", "user.email=dadk@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    git(&mirror, &["init", "-q", "-b", "dadk"]);
+    std::fs::write(mirror.join("main.c"), "int main() {}\n").unwrap();
+    git(&mirror, &["add", "main.c"]);
+    git(&mirror, &["commit", "-q", "-m", "init"]);
+
+    let source_dir = CacheDir::new(
+        &root.join("cache"),
+        executor.entity.clone(),
+        CacheDirType::Source,
+    )
+    .unwrap();
+    // 原始地址无法访问，只能从镜像克隆
+    let mirror_url = mirror.to_string_lossy().to_string();
+    let source = GitSource::new(
+        "https://invalid.dragonos.org/app.git".to_string(),
+        Some("dadk".to_string()),
+        None,
+    )
+    .with_mirror(Some(mirror_url.clone()));
+    assert_eq!(source.fetch_url(), mirror_url);
+    source.prepare(&source_dir).unwrap();
+    assert!(source_dir.path.join("main.c").exists());
+    assert_eq!(
+        git(&source_dir.path, &["remote", "get-url", "origin"]).trim(),
+        mirror_url
+    );
+
+    let archive = ArchiveSource::new("https://example.com/app.tar.gz".to_string())
+        .with_mirror(Some("http://10.0.0.1/app.tar.gz".to_string()));
+    assert_eq!(archive.fetch_url(), "http://10.0.0.1/app.tar.gz");
+    let archive_dir = CacheDir::new(
+        &root.join("archive"),
+        executor.entity.clone(),
+        CacheDirType::Build,
+    )
+    .unwrap();
+    let stamp = root.join("archive_stamp");
+    std::fs::write(archive_dir.path.join("app"), "app").unwrap();
+    std::fs::write(&stamp, "https://example.com/app.tar.gz\n").unwrap();
+    // 切换镜像不会让缓存失效
+    assert!(archive.is_cached(&archive_dir, &stamp).unwrap());
+}
+
+/// 测试确定cargo任务使用的Rust工具链
+#[test]
+fn rust_toolchain_resolve() {
+    use super::toolchain::{find_toolchain_file, is_installed, parse_toolchain_file, resolve};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let app = root.join("user/apps/app");
+    std::fs::create_dir_all(&app).unwrap();
+
+    // 没有工具链文件时使用manifest中的工具链
+    assert_eq!(resolve(&app, None).unwrap(), None);
+    let pinned = resolve(&app, Some("nightly-2024-07-23")).unwrap().unwrap();
+    assert_eq!(pinned.channel, "nightly-2024-07-23");
+    assert_eq!(pinned.source, "dadk-manifest.toml");
+
+    // 上级目录中的工具链文件优先于manifest
+    std::fs::write(
+        root.join("rust-toolchain.toml"),
+        "[toolchain]\nchannel = \"nightly-2024-04-25\"\ncomponents = [\"rust-src\"]\n",
+    )
+    .unwrap();
+    assert_eq!(
+        find_toolchain_file(&app),
+        Some(root.join("rust-toolchain.toml"))
+    );
+    let found = resolve(&app, Some("nightly-2024-07-23")).unwrap().unwrap();
+    assert_eq!(found.channel, "nightly-2024-04-25");
+
+    // 旧格式的工具链文件，离源码目录更近的优先
+    std::fs::write(app.join("rust-toolchain"), "stable\n").unwrap();
+    assert_eq!(resolve(&app, None).unwrap().unwrap().channel, "stable");
+    std::fs::write(
+        app.join("rust-toolchain"),
+        "[toolchain]\npath = \"/opt/rust\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        parse_toolchain_file(&app.join("rust-toolchain")).unwrap(),
+        None
+    );
+    assert_eq!(
+        resolve(&app, Some("stable")).unwrap().unwrap().channel,
+        "stable"
+    );
+    std::fs::write(app.join("rust-toolchain"), "a\nb\n").unwrap();
+    assert!(resolve(&app, None).is_err());
+
+    let list = "stable-x86_64-unknown-linux-gnu (default)\n\
+                nightly-2024-04-25-x86_64-unknown-linux-gnu (active)\n\
+                dragonos\n";
+    assert!(is_installed(list, "stable"));
+    assert!(is_installed(list, "nightly-2024-04-25"));
+    assert!(is_installed(list, "dragonos"));
+    assert!(!is_installed(list, "nightly"));
+    assert!(!is_installed(list, "nightly-2024-07-23"));
+}
+
+#[test]
+fn build_outputs_glob() {
+    use super::outputs::{check_outputs, glob_match};
+
+    assert!(glob_match("bin/helloworld", "bin/helloworld"));
+    assert!(!glob_match("bin/helloworld", "bin/helloworld2"));
+    assert!(glob_match("lib/*.so", "lib/libc.so"));
+    assert!(!glob_match("lib/*.so", "lib/x/libc.so"));
+    assert!(glob_match("lib/lib?.a", "lib/libc.a"));
+    assert!(glob_match("**/*.a", "libc.a"));
+    assert!(glob_match("**/*.a", "usr/lib/libc.a"));
+    assert!(glob_match("usr/**", "usr/lib/libc.a"));
+
+    let tmp = tempfile::tempdir().unwrap();
+    let build_dir = tmp.path();
+    std::fs::create_dir_all(build_dir.join("bin")).unwrap();
+    std::fs::write(build_dir.join("bin/helloworld"), "").unwrap();
+    assert!(check_outputs(build_dir, &["bin/helloworld".to_string()]).is_ok());
+
+    let err = check_outputs(
+        build_dir,
+        &[
+            "bin/*".to_string(),
+            "lib/*.so".to_string(),
+            "usr/bin/helloworld".to_string(),
+        ],
+    )
+    .unwrap_err();
+    assert!(err.starts_with("2 expected output(s) not found"), "{}", err);
+    assert!(err.ends_with("lib/*.so, usr/bin/helloworld"), "{}", err);
+}
@@ -0,0 +1,128 @@
+//! # Rust工具链
+//!
+//! cargo任务构建前确定使用的Rust工具链，通过`RUSTUP_TOOLCHAIN`环境变量传给cargo：
+//!
+//! 1. 任务的`envs`中设置的`RUSTUP_TOOLCHAIN`
+//! 2. 源码目录（或者它的上级目录）中的`rust-toolchain`、`rust-toolchain.toml`指定的channel
+//! 3. `dadk-manifest.toml`中的`rust-toolchain`
+//!
+//! 构建前检查工具链已经通过rustup安装，避免多个任务使用不同的工具链时，
+//! 在构建中途才出现`error[E0514]`这样难以理解的错误。
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::utils::stdio::StdioUtils;
+
+/// 指定rustup使用的工具链的环境变量
+pub(super) const RUSTUP_TOOLCHAIN: &str = "RUSTUP_TOOLCHAIN";
+
+/// rustup查找的工具链文件，同一目录中有多个时使用前面的
+const TOOLCHAIN_FILES: [&str; 2] = ["rust-toolchain", "rust-toolchain.toml"];
+
+/// 任务使用的工具链
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Toolchain {
+    pub channel: String,
+    /// 工具链的来源，用于错误信息
+    pub source: String,
+}
+
+/// 从源码目录开始向上查找工具链文件
+pub(super) fn find_toolchain_file(source_dir: &Path) -> Option<PathBuf> {
+    source_dir.ancestors().find_map(|dir| {
+        TOOLCHAIN_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// 读取工具链文件中的channel
+///
+/// 支持toml格式（`[toolchain] channel = "..."`）以及只有一行channel的旧格式。
+/// 没有指定channel（例如只指定了`path`）时返回None
+pub(super) fn parse_toolchain_file(path: &Path) -> Result<Option<String>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if let Ok(value) = toml::from_str::<toml::Value>(&content) {
+        return match value.get("toolchain").and_then(|t| t.get("channel")) {
+            Some(toml::Value::String(channel)) => Ok(Some(channel.trim().to_string())),
+            Some(_) => Err(format!(
+                "{}: toolchain.channel is not a string",
+                path.display()
+            )),
+            None => Ok(None),
+        };
+    }
+    let mut lines = content.lines().map(str::trim).filter(|l| !l.is_empty());
+    match (lines.next(), lines.next()) {
+        (Some(channel), None) => Ok(Some(channel.to_string())),
+        _ => Err(format!("Invalid rust toolchain file {}", path.display())),
+    }
+}
+
+/// 确定源码目录中的项目使用的工具链，源码中没有工具链文件时使用`pinned`
+pub(super) fn resolve(
+    source_dir: &Path,
+    pinned: Option<&str>,
+) -> Result<Option<Toolchain>, String> {
+    if let Some(path) = find_toolchain_file(source_dir) {
+        if let Some(channel) = parse_toolchain_file(&path)? {
+            return Ok(Some(Toolchain {
+                channel,
+                source: path.display().to_string(),
+            }));
+        }
+    }
+    Ok(pinned.map(|channel| Toolchain {
+        channel: channel.to_string(),
+        source: "dadk-manifest.toml".to_string(),
+    }))
+}
+
+/// `rustup toolchain list`的输出中是否有指定的工具链
+///
+/// 输出中的工具链名称带有主机的target triple，例如`nightly-2024-07-23-x86_64-unknown-linux-gnu`
+pub(super) fn is_installed(toolchain_list: &str, channel: &str) -> bool {
+    toolchain_list
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .any(|name| {
+            name == channel
+                || name
+                    .strip_prefix(channel)
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    // `nightly`不能匹配`nightly-2024-07-23-...`
+                    .is_some_and(|triple| !triple.starts_with(|c: char| c.is_ascii_digit()))
+        })
+}
+
+/// 检查工具链已经通过rustup安装
+pub(super) fn check_installed(toolchain: &Toolchain) -> Result<(), String> {
+    let output = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| {
+            format!(
+                "rustup is required to use rust toolchain {} (from {}): {}",
+                toolchain.channel, toolchain.source, e
+            )
+        })?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustup toolchain list failed: {}",
+            StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
+        ));
+    }
+    if !is_installed(&String::from_utf8_lossy(&output.stdout), &toolchain.channel) {
+        return Err(format!(
+            "Rust toolchain {} (from {}) is not installed, install it with `rustup toolchain install {}`",
+            toolchain.channel, toolchain.source, toolchain.channel
+        ));
+    }
+    Ok(())
+}
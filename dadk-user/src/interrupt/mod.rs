@@ -0,0 +1,285 @@
+//! # 信号处理
+//!
+//! 处理SIGINT（Ctrl+C）、SIGTERM和SIGHUP：
+//!
+//! - 构建命令运行在独立的进程组中（见[`spawn_tracked`]），收到信号后，信号会被转发给这些进程组，
+//!   因此`bash -c make`及其子进程都会收到信号，不会成为孤儿进程
+//! - 通过[`register_cleanup`]注册的清理函数（例如detach loop设备）会在进程因信号退出前执行
+//! - 调度器正在执行任务时（见[`GracefulScope`]），第一次收到信号只会停止调度新的任务，
+//!   等待正在执行的任务结束后正常返回。正在执行的任务会在运行日志中被标记为dirty，下次执行时会强制重新构建
+//! - 其他情况下，或者再次收到信号时，会终止所有子进程组、执行清理函数，然后立即退出
+//!
+//! 进程因信号退出时的退出码为`128 + 信号编号`（SIGINT为130）。
+//!
+//! 信号处理函数是全局的，但中断、取消的状态属于每个执行上下文（见[`InterruptState`]），
+//! 同一进程内同时运行的多个会话不会清除或者看到彼此的取消状态。收到信号时，所有存活的会话都会被标记为中断。
+//! 除此之外，还可以通过[`InterruptState::cancel_task`]只取消单个任务，其他任务会继续执行。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    process::{Child, Command},
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+        Arc, Mutex, Once, Weak,
+    },
+    time::Duration,
+};
+
+use log::warn;
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+/// 收到中断信号后，等待构建命令自行退出的时间，超时后强制终止
+pub(crate) const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// 进程是否已经收到过信号（不会被清除）
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+/// 第一次收到的信号编号
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// 当前有多少个调度器在执行任务
+static GRACEFUL: AtomicUsize = AtomicUsize::new(0);
+static INSTALL_HANDLER: Once = Once::new();
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    next_id: 0,
+    children: BTreeMap::new(),
+    cleanups: BTreeMap::new(),
+    sessions: Vec::new(),
+});
+
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+struct Registry {
+    next_id: u64,
+    /// 正在运行的子进程组（进程组ID）
+    children: BTreeMap<u64, i32>,
+    /// 退出前需要执行的清理函数
+    cleanups: BTreeMap<u64, Cleanup>,
+    /// 收到信号时需要标记为中断的会话
+    sessions: Vec<Weak<InterruptState>>,
+}
+
+impl Registry {
+    fn alloc_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// 安装信号处理函数（重复调用是安全的）
+///
+/// 以库的形式使用dadk-user时，可以不安装处理函数，而是调用[`InterruptState::interrupt`]请求中断
+pub fn install_handler() {
+    INSTALL_HANDLER.call_once(|| {
+        let mut signals = match Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                warn!("Failed to install signal handler: {}", e);
+                return;
+            }
+        };
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                handle_signal(signal);
+            }
+        });
+    });
+}
+
+fn handle_signal(signal: i32) {
+    let first = !SIGNALLED.swap(true, Ordering::SeqCst);
+    if first {
+        SIGNAL.store(signal, Ordering::SeqCst);
+        interrupt_sessions();
+        signal_children(signal);
+        if GRACEFUL.load(Ordering::SeqCst) > 0 {
+            warn!("Interrupted, waiting for running tasks to stop (press Ctrl+C again to force exit)...");
+            return;
+        }
+    }
+    terminate();
+}
+
+/// 终止所有子进程组，执行清理函数，然后退出进程
+fn terminate() -> ! {
+    signal_children(libc::SIGKILL);
+    run_cleanups();
+    std::process::exit(exit_code());
+}
+
+/// 向所有正在运行的子进程组发送信号
+fn signal_children(signal: i32) {
+    let registry = REGISTRY.lock().unwrap();
+    for pgid in registry.children.values() {
+        signal_group(*pgid, signal);
+    }
+}
+
+/// 把所有存活的会话标记为中断
+fn interrupt_sessions() {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.sessions.retain(|session| match session.upgrade() {
+        Some(session) => {
+            session.interrupt();
+            true
+        }
+        None => false,
+    });
+}
+
+fn signal_group(pgid: i32, signal: i32) {
+    // SAFETY: kill只是发送信号，传入负数表示发送给整个进程组
+    unsafe {
+        libc::kill(-pgid, signal);
+    }
+}
+
+fn run_cleanups() {
+    let cleanups = std::mem::take(&mut REGISTRY.lock().unwrap().cleanups);
+    // 后注册的先执行
+    for (_, cleanup) in cleanups.into_iter().rev() {
+        cleanup();
+    }
+}
+
+/// 按照收到SIGINT时的方式处理中断请求：第一次请求时等待正在执行的任务结束，再次请求时立即退出
+///
+/// 终端处于raw模式时，Ctrl+C不会产生信号，此时由程序读取按键后调用此函数
+pub fn interrupt_as_signal() {
+    handle_signal(SIGINT);
+}
+
+/// 进程是否收到过中断信号（或者[`interrupt_as_signal`]）
+///
+/// 不属于任何会话的代码（例如`dadk daemon`的主循环、等待文件锁）通过它判断是否需要停止
+pub fn signal_received() -> bool {
+    SIGNALLED.load(Ordering::SeqCst)
+}
+
+/// 因信号退出时应使用的退出码
+pub fn exit_code() -> i32 {
+    match SIGNAL.load(Ordering::SeqCst) {
+        0 => 128 + SIGINT,
+        signal => 128 + signal,
+    }
+}
+
+/// # 会话的中断状态
+///
+/// 每个执行上下文持有一个，记录本次执行是否被中断，以及被取消的任务（任务ID只在会话内唯一）。
+/// 创建时进程已经收到过信号，则直接处于中断状态
+#[derive(Debug)]
+pub struct InterruptState {
+    interrupted: AtomicBool,
+    /// 被取消的任务（任务ID）
+    cancelled: Mutex<BTreeSet<i32>>,
+}
+
+impl InterruptState {
+    /// 创建中断状态，并登记到信号处理函数
+    pub fn new() -> Arc<Self> {
+        let mut registry = REGISTRY.lock().unwrap();
+        let state = Arc::new(Self {
+            interrupted: AtomicBool::new(signal_received()),
+            cancelled: Mutex::new(BTreeSet::new()),
+        });
+        registry
+            .sessions
+            .retain(|session| session.strong_count() > 0);
+        registry.sessions.push(Arc::downgrade(&state));
+        state
+    }
+
+    /// 请求中断本次执行
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// 取消指定的任务
+    ///
+    /// 任务正在执行时，它的构建命令会被终止；尚未开始执行时，任务开始后会直接失败
+    pub fn cancel_task(&self, task_id: i32) {
+        self.cancelled.lock().unwrap().insert(task_id);
+    }
+
+    pub(crate) fn is_task_cancelled(&self, task_id: i32) -> bool {
+        self.cancelled.lock().unwrap().contains(&task_id)
+    }
+}
+
+/// # 优雅退出的作用域
+///
+/// 存在期间，第一次收到信号时不会立即退出，由持有者负责检查[`InterruptState::is_interrupted`]并结束执行
+pub(crate) struct GracefulScope(());
+
+impl GracefulScope {
+    pub fn new() -> Self {
+        GRACEFUL.fetch_add(1, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Drop for GracefulScope {
+    fn drop(&mut self) {
+        GRACEFUL.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 被跟踪的子进程组，drop时停止跟踪
+pub struct TrackedChild {
+    id: u64,
+    pgid: i32,
+}
+
+impl TrackedChild {
+    /// 强制终止整个进程组
+    pub fn kill_group(&self) {
+        signal_group(self.pgid, libc::SIGKILL);
+    }
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        REGISTRY.lock().unwrap().children.remove(&self.id);
+    }
+}
+
+/// 在独立的进程组中启动子进程，并跟踪它，以便收到信号时转发给整个进程组
+pub fn spawn_tracked(command: &mut Command) -> std::io::Result<(Child, TrackedChild)> {
+    use std::os::unix::process::CommandExt;
+
+    // 持有锁，避免子进程启动后、登记前收到的信号没有被转发
+    let mut registry = REGISTRY.lock().unwrap();
+    let child = command.process_group(0).spawn()?;
+    let pgid = child.id() as i32;
+    let id = registry.alloc_id();
+    registry.children.insert(id, pgid);
+    Ok((child, TrackedChild { id, pgid }))
+}
+
+/// 注册的清理函数，drop时取消注册（不会执行清理函数）
+pub struct CleanupGuard {
+    id: u64,
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        REGISTRY.lock().unwrap().cleanups.remove(&self.id);
+    }
+}
+
+/// 注册进程因信号退出前需要执行的清理函数
+pub fn register_cleanup(cleanup: impl FnOnce() + Send + 'static) -> CleanupGuard {
+    let mut registry = REGISTRY.lock().unwrap();
+    let id = registry.alloc_id();
+    registry.cleanups.insert(id, Box::new(cleanup));
+    CleanupGuard { id }
+}
+
+#[cfg(test)]
+mod tests;
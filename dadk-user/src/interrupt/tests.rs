@@ -0,0 +1,78 @@
+use std::{
+    os::unix::process::ExitStatusExt,
+    sync::{Arc, Mutex},
+};
+
+use super::*;
+
+/// 子进程运行在独立的进程组中，终止进程组时后台的孙进程也会被终止
+#[test]
+fn spawn_tracked_kills_process_group() {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("sleep 30 & wait");
+    let (mut child, tracked) = spawn_tracked(&mut command).unwrap();
+    let pid = child.id() as i32;
+    // SAFETY: getpgid只是查询进程组
+    assert_eq!(unsafe { libc::getpgid(pid) }, pid);
+    assert_eq!(
+        REGISTRY.lock().unwrap().children.get(&tracked.id),
+        Some(&pid)
+    );
+
+    tracked.kill_group();
+    let status = child.wait().unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGKILL));
+
+    let id = tracked.id;
+    drop(tracked);
+    assert!(!REGISTRY.lock().unwrap().children.contains_key(&id));
+}
+
+#[test]
+fn cleanups_run_in_reverse_order() {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let guards: Vec<CleanupGuard> = (0..3)
+        .map(|i| {
+            let order = order.clone();
+            register_cleanup(move || order.lock().unwrap().push(i))
+        })
+        .collect();
+    // 取消注册的清理函数不会被执行
+    let mut guards = guards.into_iter();
+    let first = guards.next().unwrap();
+    drop(first);
+
+    run_cleanups();
+    assert_eq!(*order.lock().unwrap(), vec![2, 1]);
+    // 已经执行过的清理函数不会再次执行
+    drop(guards);
+    run_cleanups();
+    assert_eq!(*order.lock().unwrap(), vec![2, 1]);
+}
+
+/// 中断、取消的状态属于各自的会话，互不影响
+#[test]
+fn sessions_do_not_share_state() {
+    let a = InterruptState::new();
+    let b = InterruptState::new();
+    let id = 42;
+    a.cancel_task(id);
+    assert!(a.is_task_cancelled(id));
+    assert!(!a.is_task_cancelled(id - 1));
+    assert!(!b.is_task_cancelled(id));
+
+    a.interrupt();
+    assert!(a.is_interrupted());
+    assert!(!b.is_interrupted());
+
+    // 会话结束后不再登记在信号处理函数中
+    let weak = Arc::downgrade(&a);
+    drop(a);
+    InterruptState::new();
+    let registry = REGISTRY.lock().unwrap();
+    assert!(!registry.sessions.iter().any(|s| s.ptr_eq(&weak)));
+    assert!(registry
+        .sessions
+        .iter()
+        .any(|s| s.ptr_eq(&Arc::downgrade(&b))));
+}
@@ -79,15 +79,8 @@
 //!
 //! ## TODO
 //!
-//! - 支持从在线归档文件下载源码、构建好的软件库
-//! - 支持自动更新
 //! - 完善clean命令的逻辑
 
-#![feature(extract_if)]
-#![feature(io_error_more)]
-
-#[macro_use]
-extern crate lazy_static;
 pub extern crate clap;
 extern crate log;
 extern crate serde;
@@ -96,23 +89,46 @@ extern crate serde_json;
 #[cfg(test)]
 extern crate test_base;
 
-use std::{path::PathBuf, process::exit, sync::Arc};
-
 use context::DadkUserExecuteContext;
 use log::info;
 use parser::task::DADKTask;
 
-use crate::scheduler::Scheduler;
+pub use crate::{
+    error::{DadkUserError, ErrorCode},
+    session::BuildSession,
+};
 
+pub mod affected;
+pub mod cache;
 pub mod context;
+pub mod error;
+pub mod event;
 pub mod executor;
+pub mod interrupt;
+pub mod list;
+pub mod lock;
+pub mod metrics;
+pub mod outdated;
+pub mod package;
 pub mod parser;
+pub mod pkgdb;
+pub mod rdeps;
+pub mod report;
+pub mod repository;
 mod scheduler;
+mod session;
+pub mod stats;
+pub mod status;
+pub mod test;
 mod utils;
 
-pub fn dadk_user_main(context: DadkUserExecuteContext) {
-    let context = Arc::new(context);
-    context.init(context.clone());
+/// # dadk-user的入口
+///
+/// 解析配置文件并执行上下文中指定的操作。执行失败时返回带有错误码的错误，由调用者决定如何退出。
+pub fn dadk_user_main(context: DadkUserExecuteContext) -> Result<(), DadkUserError> {
+    interrupt::install_handler();
+    let session = BuildSession::new(context)?;
+    let context = session.context();
     // DragonOS sysroot在主机上的路径
 
     info!(
@@ -133,26 +149,5 @@ pub fn dadk_user_main(context: DadkUserExecuteContext) {
         context.thread_num().map_or_else(|| 0, |t| t)
     );
 
-    let mut parser = parser::Parser::new(context.config_dir().unwrap().clone());
-    let r = parser.parse();
-    if r.is_err() {
-        exit(1);
-    }
-    let tasks: Vec<(PathBuf, DADKTask)> = r.unwrap();
-    // info!("Parsed tasks: {:?}", tasks);
-
-    let scheduler = Scheduler::new(
-        context.clone(),
-        context.sysroot_dir().cloned().unwrap(),
-        *context.action(),
-        tasks,
-    );
-    if scheduler.is_err() {
-        exit(1);
-    }
-
-    let r = scheduler.unwrap().run();
-    if r.is_err() {
-        exit(1);
-    }
+    session.run()
 }
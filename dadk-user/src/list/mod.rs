@@ -0,0 +1,209 @@
+//! # 任务列表
+//!
+//! 列出所有解析到的任务，以及任务日志中记录的最近一次构建、安装的状态和时间，
+//! 用于在安装之前快速检查哪些任务的安装结果已经过时。
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    executor::cache::TaskDataDir,
+    parser::{
+        task::{CodeSource, DADKTask, PrebuiltSource, TaskType},
+        task_log::{BuildStatus, InstallStatus, TaskLog},
+    },
+};
+
+#[cfg(test)]
+mod tests;
+
+/// # 单个任务的概要
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskSummary {
+    pub name: String,
+    pub version: String,
+    pub target_arch: Vec<String>,
+    /// 任务类型：`build-from-source`、`install-from-prebuilt`、`cargo`、`cmake`、`autotools`、`package`或`kernel-module`
+    pub task_type: String,
+    /// 源文件类型：`git`、`local`或`archive`
+    pub source: String,
+    /// 最近一次构建的状态，从未构建过时为None
+    pub build_status: Option<String>,
+    pub build_time: Option<DateTime<Utc>>,
+    /// 最近一次安装的状态，从未安装过时为None
+    pub install_status: Option<String>,
+    pub install_time: Option<DateTime<Utc>>,
+    /// 构建成功，但还没有安装，或者安装时间早于构建时间
+    pub stale: bool,
+}
+
+impl TaskSummary {
+    pub fn new(task: &DADKTask, task_log: Option<&TaskLog>) -> Self {
+        let task_type = task.task_type_name();
+        let source = match &task.task_type {
+            TaskType::BuildFromSource(source) => match source {
+                CodeSource::Git(_) => "git",
+                CodeSource::Local(_) => "local",
+                CodeSource::Archive(_) => "archive",
+            },
+            TaskType::InstallFromPrebuilt(source) => match source {
+                PrebuiltSource::Local(_) => "local",
+                PrebuiltSource::Archive(_) => "archive",
+                PrebuiltSource::Repository(_) => "repository",
+            },
+        };
+        let build_status = task_log.and_then(|l| l.build_status().cloned());
+        let install_status = task_log.and_then(|l| l.install_status().cloned());
+        let build_time = task_log.and_then(|l| l.build_time().cloned());
+        let install_time = task_log.and_then(|l| l.install_time().cloned());
+        let stale = build_status == Some(BuildStatus::Success)
+            && (install_status != Some(InstallStatus::Success) || install_time < build_time);
+
+        Self {
+            name: task.name.clone(),
+            version: task.version.clone(),
+            target_arch: task
+                .target_arch
+                .iter()
+                .map(|arch| {
+                    let arch: &str = (*arch).into();
+                    arch.to_string()
+                })
+                .collect(),
+            task_type: task_type.to_string(),
+            source: source.to_string(),
+            build_status: build_status.map(|s| {
+                match s {
+                    BuildStatus::Success => "success",
+                    BuildStatus::Failed => "failed",
+                }
+                .to_string()
+            }),
+            build_time,
+            install_status: install_status.map(|s| {
+                match s {
+                    InstallStatus::Success => "success",
+                    InstallStatus::Failed => "failed",
+                }
+                .to_string()
+            }),
+            install_time,
+            stale,
+        }
+    }
+
+    /// 从任务日志中读取任务的状态
+    pub fn load(cache_root: &Path, task: &DADKTask) -> Self {
+        let task_log = TaskDataDir::load_task_log(cache_root, task);
+        Self::new(task, task_log.as_ref())
+    }
+}
+
+/// # 任务列表
+#[derive(Debug)]
+pub struct TaskList {
+    tasks: Vec<TaskSummary>,
+}
+
+impl TaskList {
+    /// 按照名称、版本排序
+    pub fn new(mut tasks: Vec<TaskSummary>) -> Self {
+        tasks.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+        Self { tasks }
+    }
+
+    /// 读取给定任务的状态
+    pub fn collect(cache_root: &Path, tasks: &[(PathBuf, DADKTask)]) -> Self {
+        Self::new(
+            tasks
+                .iter()
+                .map(|(_, task)| TaskSummary::load(cache_root, task))
+                .collect(),
+        )
+    }
+
+    pub fn tasks(&self) -> &[TaskSummary] {
+        &self.tasks
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.tasks).expect("Failed to serialize task list")
+    }
+
+    /// 生成文本格式的表格。过时的任务在名称后面标记`*`
+    pub fn table(&self) -> String {
+        if self.tasks.is_empty() {
+            return "No task found.\n".to_string();
+        }
+        let header = [
+            "NAME",
+            "VERSION",
+            "ARCH",
+            "TYPE",
+            "SOURCE",
+            "BUILD",
+            "BUILT AT",
+            "INSTALL",
+            "INSTALLED AT",
+        ];
+        let rows: Vec<[String; 9]> = self
+            .tasks
+            .iter()
+            .map(|t| {
+                [
+                    if t.stale {
+                        format!("{}*", t.name)
+                    } else {
+                        t.name.clone()
+                    },
+                    t.version.clone(),
+                    t.target_arch.join(","),
+                    t.task_type.clone(),
+                    t.source.clone(),
+                    t.build_status.clone().unwrap_or_else(|| "-".to_string()),
+                    format_time(t.build_time),
+                    t.install_status.clone().unwrap_or_else(|| "-".to_string()),
+                    format_time(t.install_time),
+                ]
+            })
+            .collect();
+
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut out = String::new();
+        let mut push_row = |cells: Vec<&str>| {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        };
+        push_row(header.to_vec());
+        for row in &rows {
+            push_row(row.iter().map(String::as_str).collect());
+        }
+
+        let stale = self.tasks.iter().filter(|t| t.stale).count();
+        if stale > 0 {
+            out.push_str(&format!(
+                "\n{} task(s) marked with * have been built but not installed since.\n",
+                stale
+            ));
+        }
+        out
+    }
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| "-".to_string())
+}
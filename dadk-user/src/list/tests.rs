@@ -0,0 +1,106 @@
+use chrono::TimeZone;
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::parser::Parser;
+
+fn at(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap()
+}
+
+fn parse_task(ctx: &BaseGlobalTestContext, config: &str) -> DADKTask {
+    Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join(config))
+        .unwrap()
+}
+
+fn task_log(build: Option<(BuildStatus, i64)>, install: Option<(InstallStatus, i64)>) -> TaskLog {
+    let mut log = TaskLog::new();
+    if let Some((status, time)) = build {
+        log.set_build_status(status);
+        log.set_build_time(at(time));
+    }
+    if let Some((status, time)) = install {
+        log.set_install_status(status);
+        log.set_install_time(at(time));
+    }
+    log
+}
+
+/// 测试从任务配置和任务日志生成任务概要
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn task_summary_status(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx, "app_target_arch_riscv64_only_0_2_0.toml");
+
+    let summary = TaskSummary::new(&task, None);
+    assert_eq!(summary.target_arch, vec!["riscv64"]);
+    assert_eq!(summary.task_type, "build-from-source");
+    assert_eq!(summary.source, "git");
+    assert_eq!(summary.build_status, None);
+    assert_eq!(summary.install_time, None);
+    assert!(!summary.stale);
+
+    // 构建成功但从未安装
+    let log = task_log(Some((BuildStatus::Success, 10)), None);
+    let summary = TaskSummary::new(&task, Some(&log));
+    assert_eq!(summary.build_status.as_deref(), Some("success"));
+    assert_eq!(summary.build_time, Some(at(10)));
+    assert!(summary.stale);
+
+    // 安装早于最近一次构建
+    let log = task_log(
+        Some((BuildStatus::Success, 10)),
+        Some((InstallStatus::Success, 5)),
+    );
+    assert!(TaskSummary::new(&task, Some(&log)).stale);
+
+    // 安装晚于构建
+    let log = task_log(
+        Some((BuildStatus::Success, 10)),
+        Some((InstallStatus::Success, 20)),
+    );
+    assert!(!TaskSummary::new(&task, Some(&log)).stale);
+
+    // 构建失败时没有可安装的结果
+    let log = task_log(Some((BuildStatus::Failed, 10)), None);
+    let summary = TaskSummary::new(&task, Some(&log));
+    assert_eq!(summary.build_status.as_deref(), Some("failed"));
+    assert!(!summary.stale);
+}
+
+/// 测试表格和JSON输出
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn task_list_output(ctx: &BaseGlobalTestContext) {
+    let riscv = parse_task(ctx, "app_target_arch_riscv64_only_0_2_0.toml");
+    let x86 = parse_task(ctx, "app_target_arch_x86_64_only_0_2_0.toml");
+    let log = task_log(Some((BuildStatus::Success, 10)), None);
+    let list = TaskList::new(vec![
+        TaskSummary::new(&x86, None),
+        TaskSummary::new(&riscv, Some(&log)),
+    ]);
+
+    let names: Vec<&str> = list.tasks().iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec![riscv.name.as_str(), x86.name.as_str()]);
+
+    let table = list.table();
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("NAME"));
+    assert!(lines[1].starts_with(&format!("{}*", riscv.name)));
+    assert!(lines[1].contains("2023-11-14T22:13:30Z"));
+    assert!(lines[2].starts_with(&x86.name));
+    assert!(table.contains("1 task(s) marked with *"));
+
+    let json: serde_json::Value = serde_json::from_str(&list.to_json()).unwrap();
+    assert_eq!(json[0]["name"], riscv.name.as_str());
+    assert_eq!(json[0]["build_status"], "success");
+    assert_eq!(json[0]["stale"], true);
+    assert_eq!(json[1]["target_arch"][0], "x86_64");
+    assert!(json[1]["install_time"].is_null());
+
+    assert_eq!(TaskList::new(Vec::new()).table(), "No task found.\n");
+}
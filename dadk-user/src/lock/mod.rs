@@ -0,0 +1,245 @@
+//! # 缓存目录的文件锁
+//!
+//! 同时运行的多个dadk进程（例如IDE中的任务和终端中的命令）可能会同时操作同一个缓存根目录，
+//! 导致git仓库、构建目录被破坏。因此使用`flock`对缓存目录加锁：
+//!
+//! - 缓存根目录的锁（`<cache_root>/.dadk.lock`）：构建、安装、清理时持有排他锁，
+//!   因为运行日志等数据是整个缓存根目录共享的；只读取构建结果的操作（例如打包）持有共享锁
+//! - 任务的锁（`<cache_root>/locks/<name_version>.lock`）：执行任务、删除任务的缓存目录时持有排他锁，
+//!   读取任务的构建结果时持有共享锁
+//! - 共享源码目录的锁（`<cache_root>/locks/shared-<key>.lock`）：设置了相同`source-cache-key`的任务
+//!   拉取源码、清理源码目录时持有排他锁，使用源码构建时持有共享锁
+//!
+//! 锁已被其他进程持有时，按照[`LockTimeout`]等待或者立即失败。进程退出时，锁会被操作系统自动释放。
+
+use std::{
+    fs::File,
+    io::{Seek, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::interrupt;
+
+#[cfg(test)]
+mod tests;
+
+/// 缓存根目录的锁文件名
+const CACHE_ROOT_LOCK_FILE: &str = ".dadk.lock";
+/// 存放任务的锁文件的目录
+const TASK_LOCK_DIR: &str = "locks";
+/// 等待锁时，重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// 共享锁，可以被多个进程同时持有
+    Shared,
+    /// 排他锁
+    Exclusive,
+}
+
+/// # 等待锁的时间
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockTimeout {
+    /// 一直等待，直到获得锁
+    #[default]
+    Wait,
+    /// 最多等待指定的时间，`Duration::ZERO`表示不等待
+    Timeout(Duration),
+}
+
+impl LockTimeout {
+    fn deadline(&self, start: Instant) -> Option<Instant> {
+        match self {
+            LockTimeout::Wait => None,
+            LockTimeout::Timeout(d) => Some(start + *d),
+        }
+    }
+}
+
+/// # 文件锁
+///
+/// drop时释放锁
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+    path: PathBuf,
+    mode: LockMode,
+}
+
+impl FileLock {
+    /// 对`path`加锁，文件不存在时会自动创建
+    ///
+    /// `what`为被锁定的对象的描述，用于日志以及错误信息
+    pub fn acquire(
+        path: &Path,
+        mode: LockMode,
+        timeout: LockTimeout,
+        what: &str,
+    ) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| format!("Failed to open lock file {}: {}", path.display(), e))?;
+
+        let start = Instant::now();
+        let deadline = timeout.deadline(start);
+        let mut waiting = false;
+        loop {
+            match try_flock(&file, mode) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) => {
+                    return Err(format!("Failed to lock {}: {}", path.display(), e));
+                }
+            }
+            let holder = lock_holder(path);
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(format!(
+                    "{} is locked by another dadk process{} (waited {:.1}s, lock file: {}). \
+                     Wait for it to finish, or use --lock-timeout to wait longer",
+                    what,
+                    holder,
+                    start.elapsed().as_secs_f64(),
+                    path.display()
+                ));
+            }
+            if interrupt::signal_received() {
+                return Err(format!(
+                    "Interrupted while waiting for the lock of {}",
+                    what
+                ));
+            }
+            if !waiting {
+                info!(
+                    "Waiting for another dadk process{} to release {}...",
+                    holder, what
+                );
+                waiting = true;
+            }
+            std::thread::sleep(RETRY_INTERVAL);
+        }
+
+        let mut lock = Self {
+            file,
+            path: path.to_path_buf(),
+            mode,
+        };
+        if mode == LockMode::Exclusive {
+            lock.write_pid();
+        }
+        Ok(lock)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 把当前进程的pid写入锁文件，便于其他进程在等待时提示
+    fn write_pid(&mut self) {
+        let r = self
+            .file
+            .set_len(0)
+            .and_then(|_| self.file.rewind())
+            .and_then(|_| writeln!(self.file, "{}", std::process::id()));
+        if let Err(e) = r {
+            log::debug!("Failed to write pid to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // 释放排他锁之前清除pid，锁在关闭文件时释放
+        if self.mode == LockMode::Exclusive {
+            self.file.set_len(0).ok();
+        }
+    }
+}
+
+/// 尝试加锁，锁被其他进程持有时返回`Ok(false)`
+fn try_flock(file: &File, mode: LockMode) -> std::io::Result<bool> {
+    let op = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+    };
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), op | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+        let e = std::io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EWOULDBLOCK) => return Ok(false),
+            Some(libc::EINTR) => continue,
+            _ => return Err(e),
+        }
+    }
+}
+
+/// 锁文件中记录的持有排他锁的进程，用于提示信息
+fn lock_holder(path: &Path) -> String {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .map(|pid| format!(" (pid {})", pid))
+        .unwrap_or_default()
+}
+
+/// 对缓存根目录加锁
+pub fn lock_cache_root(
+    cache_root: &Path,
+    mode: LockMode,
+    timeout: LockTimeout,
+) -> Result<FileLock, String> {
+    FileLock::acquire(
+        &cache_root.join(CACHE_ROOT_LOCK_FILE),
+        mode,
+        timeout,
+        &format!("Cache root {}", cache_root.display()),
+    )
+}
+
+/// 对任务的缓存目录加锁
+pub fn lock_task(
+    cache_root: &Path,
+    name_version: &str,
+    mode: LockMode,
+    timeout: LockTimeout,
+) -> Result<FileLock, String> {
+    FileLock::acquire(
+        &cache_root
+            .join(TASK_LOCK_DIR)
+            .join(format!("{}.lock", name_version)),
+        mode,
+        timeout,
+        &format!("Task {}", name_version),
+    )
+}
+
+/// 对多个任务共用的源码缓存目录（`source-cache-key`为`key`）加锁
+pub fn lock_shared_source(
+    cache_root: &Path,
+    key: &str,
+    mode: LockMode,
+    timeout: LockTimeout,
+) -> Result<FileLock, String> {
+    FileLock::acquire(
+        &cache_root
+            .join(TASK_LOCK_DIR)
+            .join(format!("shared-{}.lock", key)),
+        mode,
+        timeout,
+        &format!("Shared source {}", key),
+    )
+}
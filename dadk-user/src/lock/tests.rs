@@ -0,0 +1,66 @@
+use super::*;
+
+const NO_WAIT: LockTimeout = LockTimeout::Timeout(Duration::ZERO);
+
+/// 每次打开锁文件都是独立的文件描述，因此同一进程内也可以测试锁的互斥
+#[test]
+fn test_exclusive_lock_fails_fast() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let lock = lock_cache_root(root, LockMode::Exclusive, NO_WAIT).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(lock.path()).unwrap().trim(),
+        std::process::id().to_string()
+    );
+
+    let e = lock_cache_root(root, LockMode::Exclusive, NO_WAIT).unwrap_err();
+    assert!(e.contains("is locked by another dadk process"), "{}", e);
+    assert!(e.contains(&format!("pid {}", std::process::id())), "{}", e);
+    assert!(lock_cache_root(root, LockMode::Shared, NO_WAIT).is_err());
+
+    drop(lock);
+    assert_eq!(
+        std::fs::read_to_string(root.join(".dadk.lock")).unwrap(),
+        ""
+    );
+    lock_cache_root(root, LockMode::Exclusive, NO_WAIT).unwrap();
+}
+
+#[test]
+fn test_shared_lock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let a = lock_task(root, "app_0_1_0", LockMode::Shared, NO_WAIT).unwrap();
+    let b = lock_task(root, "app_0_1_0", LockMode::Shared, NO_WAIT).unwrap();
+    assert!(root.join("locks/app_0_1_0.lock").is_file());
+    assert!(lock_task(root, "app_0_1_0", LockMode::Exclusive, NO_WAIT).is_err());
+    // 其他任务的锁不受影响
+    lock_task(root, "libc_0_1_0", LockMode::Exclusive, NO_WAIT).unwrap();
+
+    drop(a);
+    drop(b);
+    lock_task(root, "app_0_1_0", LockMode::Exclusive, NO_WAIT).unwrap();
+}
+
+#[test]
+fn test_wait_for_lock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let lock = lock_cache_root(root, LockMode::Exclusive, NO_WAIT).unwrap();
+    let start = Instant::now();
+    let e = lock_cache_root(
+        root,
+        LockMode::Exclusive,
+        LockTimeout::Timeout(Duration::from_millis(300)),
+    )
+    .unwrap_err();
+    assert!(start.elapsed() >= Duration::from_millis(300), "{}", e);
+
+    // 其他线程释放锁后，等待的一方获得锁
+    let releaser = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        drop(lock);
+    });
+    lock_cache_root(root, LockMode::Exclusive, LockTimeout::Wait).unwrap();
+    releaser.join().unwrap();
+}
@@ -0,0 +1,189 @@
+//! # 构建指标
+//!
+//! 一次构建、安装或者清理结束时，可以把本次执行的指标写入Prometheus的textfile
+//! （供node_exporter的textfile collector读取）或者OpenMetrics格式的文件，
+//! 构建集群可以据此绘制构建状况的变化趋势，而不需要从日志中提取。指标只写入本地文件。
+//!
+//! 所有指标都是描述最近一次执行的gauge，带有`action`和`arch`标签：
+//!
+//! - `dadk_run_timestamp_seconds`：执行结束的时间
+//! - `dadk_run_duration_seconds`：执行的墙钟时间
+//! - `dadk_run_success`：执行成功时为1，否则为0
+//! - `dadk_tasks{result}`：实际执行（`executed`）、因为没有变化而跳过（`skipped`）以及失败（`failed`）的任务数量
+//! - `dadk_cache_hit_ratio`：跳过的任务占成功的任务的比例
+//! - `dadk_download_bytes`：下载的源码压缩包以及二进制包的总大小
+//! - `dadk_task_duration_seconds{task}`：每个实际执行的任务的耗时
+//!
+//! 只统计构建和安装任务，清理时只输出执行本身的指标。
+
+use std::{
+    fmt::Write,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// 指标文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsFormat {
+    /// Prometheus的文本格式（textfile collector）
+    #[default]
+    Prometheus,
+    /// OpenMetrics格式，以`# EOF`结尾
+    OpenMetrics,
+}
+
+/// 单个任务的执行结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskMetrics {
+    /// 任务（`name-version`）
+    pub task: String,
+    /// 实际执行所花费的时间，因为没有变化而跳过时为None
+    pub elapsed: Option<Duration>,
+    pub failed: bool,
+    /// 下载的字节数
+    pub downloaded_bytes: u64,
+}
+
+/// # 一次执行的指标
+#[derive(Debug, Default)]
+pub struct RunMetrics {
+    tasks: Vec<TaskMetrics>,
+}
+
+/// 执行本身的信息
+#[derive(Debug, Clone, Copy)]
+pub struct RunInfo<'a> {
+    pub action: &'a str,
+    pub arch: &'a str,
+    pub duration: Duration,
+    pub finished: SystemTime,
+    pub success: bool,
+}
+
+impl RunMetrics {
+    pub fn record(&mut self, task: TaskMetrics) {
+        self.tasks.push(task);
+    }
+
+    pub fn tasks(&self) -> &[TaskMetrics] {
+        &self.tasks
+    }
+
+    /// 跳过的任务占成功的任务的比例，没有成功的任务时为None
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        let (executed, skipped, _) = self.counts();
+        match executed + skipped {
+            0 => None,
+            ok => Some(skipped as f64 / ok as f64),
+        }
+    }
+
+    /// (实际执行, 跳过, 失败)的任务数量
+    fn counts(&self) -> (usize, usize, usize) {
+        let failed = self.tasks.iter().filter(|t| t.failed).count();
+        let skipped = self
+            .tasks
+            .iter()
+            .filter(|t| !t.failed && t.elapsed.is_none())
+            .count();
+        (self.tasks.len() - failed - skipped, skipped, failed)
+    }
+
+    /// 按照指定的格式输出指标
+    pub fn render(&self, format: MetricsFormat, run: &RunInfo) -> String {
+        let base = format!(
+            "action=\"{}\",arch=\"{}\"",
+            escape_label(run.action),
+            escape_label(run.arch)
+        );
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, samples: &[(String, f64)]| {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} gauge", name).unwrap();
+            for (labels, value) in samples {
+                writeln!(out, "{}{{{}}} {}", name, labels, value).unwrap();
+            }
+        };
+
+        let finished = run
+            .finished
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        gauge(
+            "dadk_run_timestamp_seconds",
+            "Unix time at which the last dadk run finished.",
+            &[(base.clone(), finished)],
+        );
+        gauge(
+            "dadk_run_duration_seconds",
+            "Wall-clock duration of the last dadk run.",
+            &[(base.clone(), run.duration.as_secs_f64())],
+        );
+        gauge(
+            "dadk_run_success",
+            "Whether the last dadk run succeeded.",
+            &[(base.clone(), run.success as u8 as f64)],
+        );
+        let (executed, skipped, failed) = self.counts();
+        gauge(
+            "dadk_tasks",
+            "Tasks of the last dadk run by result.",
+            &[
+                (format!("{},result=\"executed\"", base), executed as f64),
+                (format!("{},result=\"skipped\"", base), skipped as f64),
+                (format!("{},result=\"failed\"", base), failed as f64),
+            ],
+        );
+        if let Some(ratio) = self.cache_hit_ratio() {
+            gauge(
+                "dadk_cache_hit_ratio",
+                "Share of successful tasks that were skipped because nothing changed.",
+                &[(base.clone(), ratio)],
+            );
+        }
+        let downloaded: u64 = self.tasks.iter().map(|t| t.downloaded_bytes).sum();
+        gauge(
+            "dadk_download_bytes",
+            "Bytes of source archives and packages downloaded by the last dadk run.",
+            &[(base.clone(), downloaded as f64)],
+        );
+        let mut durations: Vec<_> = self
+            .tasks
+            .iter()
+            .filter_map(|t| Some((t, t.elapsed?)))
+            .map(|(t, elapsed)| {
+                (
+                    format!("{},task=\"{}\"", base, escape_label(&t.task)),
+                    elapsed.as_secs_f64(),
+                )
+            })
+            .collect();
+        durations.sort_by(|a, b| a.0.cmp(&b.0));
+        gauge(
+            "dadk_task_duration_seconds",
+            "Duration of each task executed by the last dadk run.",
+            &durations,
+        );
+
+        if format == MetricsFormat::OpenMetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+/// 写入指标文件。先写入临时文件再重命名，textfile collector不会读到写了一半的文件
+pub fn write(path: &Path, content: &str) -> Result<(), String> {
+    crate::repository::write_atomically(path, content.as_bytes())
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
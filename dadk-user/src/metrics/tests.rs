@@ -0,0 +1,82 @@
+use super::*;
+
+fn task(name: &str, elapsed: Option<u64>, failed: bool, downloaded_bytes: u64) -> TaskMetrics {
+    TaskMetrics {
+        task: name.to_string(),
+        elapsed: elapsed.map(Duration::from_secs),
+        failed,
+        downloaded_bytes,
+    }
+}
+
+fn metrics() -> RunMetrics {
+    let mut metrics = RunMetrics::default();
+    metrics.record(task("libc-0.1.0", Some(10), false, 1024));
+    metrics.record(task("app-0.1.0", None, false, 0));
+    metrics.record(task("tool-0.1.0", None, false, 0));
+    metrics.record(task("docs-0.1.0", Some(2), true, 512));
+    metrics
+}
+
+fn run(success: bool) -> RunInfo<'static> {
+    RunInfo {
+        action: "build",
+        arch: "x86_64",
+        duration: Duration::from_millis(12_500),
+        finished: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        success,
+    }
+}
+
+#[test]
+fn test_render_prometheus() {
+    let out = metrics().render(MetricsFormat::Prometheus, &run(false));
+    let base = r#"action="build",arch="x86_64""#;
+    for line in [
+        format!("dadk_run_timestamp_seconds{{{}}} 1700000000", base),
+        format!("dadk_run_duration_seconds{{{}}} 12.5", base),
+        format!("dadk_run_success{{{}}} 0", base),
+        format!("dadk_tasks{{{},result=\"executed\"}} 1", base),
+        format!("dadk_tasks{{{},result=\"skipped\"}} 2", base),
+        format!("dadk_tasks{{{},result=\"failed\"}} 1", base),
+        format!("dadk_download_bytes{{{}}} 1536", base),
+        format!(
+            "dadk_task_duration_seconds{{{},task=\"docs-0.1.0\"}} 2",
+            base
+        ),
+        format!(
+            "dadk_task_duration_seconds{{{},task=\"libc-0.1.0\"}} 10",
+            base
+        ),
+        "# TYPE dadk_cache_hit_ratio gauge".to_string(),
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {}:\n{}", line, out);
+    }
+    assert!(!out.contains("# EOF"));
+    assert_eq!(metrics().cache_hit_ratio(), Some(2.0 / 3.0));
+}
+
+#[test]
+fn test_render_openmetrics() {
+    let out = RunMetrics::default().render(MetricsFormat::OpenMetrics, &run(true));
+    assert!(out.ends_with("# EOF\n"));
+    assert!(out.contains(r#"dadk_run_success{action="build",arch="x86_64"} 1"#));
+    // 没有任务时不输出命中率
+    assert!(!out.contains("dadk_cache_hit_ratio"));
+}
+
+#[test]
+fn test_escape_label() {
+    assert_eq!(escape_label("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+}
+
+#[test]
+fn test_write() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("metrics").join("dadk.prom");
+    write(&path, "dadk_run_success 1\n").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "dadk_run_success 1\n"
+    );
+}
@@ -0,0 +1,318 @@
+//! # 检查git源码的上游更新
+//!
+//! 对于从git仓库的某个分支构建的任务，通过`git ls-remote`查询远程分支的最新提交，
+//! 与源码缓存中检出的提交比较，报告哪些任务落后于上游，便于维护者决定何时更新应用。
+//!
+//! 固定了`revision`的任务不会被检查。查询结果可以写入锁文件，记录各个任务上游分支的最新提交：
+//!
+//! ```toml
+//! [[task]]
+//! name = "hello"
+//! version = "0.1.0"
+//! url = "https://git.example.com/hello.git"
+//! branch = "main"
+//! revision = "0123456789abcdef0123456789abcdef01234567"
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executor::{
+        cache::{CacheDir, CacheDirType},
+        source::GitSource,
+    },
+    parser::task::{CodeSource, DADKTask, TaskType},
+    utils::stdio::StdioUtils,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// 任务与上游的比较结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutdatedStatus {
+    /// 源码缓存与远程分支的最新提交相同
+    UpToDate,
+    /// 远程分支有新的提交
+    Behind,
+    /// 还没有拉取过源码
+    NotFetched,
+    /// 查询远程分支失败
+    Unknown,
+}
+
+impl OutdatedStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutdatedStatus::UpToDate => "up-to-date",
+            OutdatedStatus::Behind => "behind",
+            OutdatedStatus::NotFetched => "not-fetched",
+            OutdatedStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// # 单个任务的检查结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutdatedTask {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub branch: String,
+    /// 源码缓存中检出的提交，还没有拉取过源码时为None
+    pub current: Option<String>,
+    /// 远程分支的最新提交，查询失败时为None
+    pub upstream: Option<String>,
+    pub status: OutdatedStatus,
+    /// 查询远程分支失败的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// # 所有任务的检查结果
+#[derive(Debug)]
+pub struct OutdatedReport {
+    tasks: Vec<OutdatedTask>,
+}
+
+impl OutdatedReport {
+    /// 检查所有从git分支构建的任务（同时查询各个远程仓库）
+    pub fn check(cache_root: &Path, tasks: &[(PathBuf, DADKTask)]) -> Self {
+        Self::check_with(cache_root, tasks, ls_remote)
+    }
+
+    /// `ls_remote`返回远程仓库（url）中分支（branch）的最新提交
+    pub(crate) fn check_with(
+        cache_root: &Path,
+        tasks: &[(PathBuf, DADKTask)],
+        ls_remote: impl Fn(&str, &str) -> Result<String, String> + Sync,
+    ) -> Self {
+        let git_tasks: Vec<(&DADKTask, &GitSource, &str)> = tasks
+            .iter()
+            .filter_map(|(_, task)| match &task.task_type {
+                TaskType::BuildFromSource(CodeSource::Git(git)) => {
+                    git.branch().map(|branch| (task, git, branch))
+                }
+                _ => None,
+            })
+            .collect();
+        let ls_remote = &ls_remote;
+        let mut tasks: Vec<OutdatedTask> = std::thread::scope(|s| {
+            let handles: Vec<_> = git_tasks
+                .into_iter()
+                .map(|(task, git, branch)| {
+                    s.spawn(move || {
+                        let source_dir = CacheDir::get_path(cache_root, task, CacheDirType::Source);
+                        let current = current_revision(&source_dir);
+                        let upstream = ls_remote(git.url(), branch);
+                        let status = match (&current, &upstream) {
+                            (_, Err(_)) => OutdatedStatus::Unknown,
+                            (None, Ok(_)) => OutdatedStatus::NotFetched,
+                            (Some(current), Ok(upstream)) if current == upstream => {
+                                OutdatedStatus::UpToDate
+                            }
+                            (Some(_), Ok(_)) => OutdatedStatus::Behind,
+                        };
+                        OutdatedTask {
+                            name: task.name.clone(),
+                            version: task.version.clone(),
+                            url: git.url().to_string(),
+                            branch: branch.to_string(),
+                            current,
+                            upstream: upstream.as_ref().ok().cloned(),
+                            status,
+                            error: upstream.err(),
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Failed to check upstream"))
+                .collect()
+        });
+        tasks.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+        Self { tasks }
+    }
+
+    pub fn tasks(&self) -> &[OutdatedTask] {
+        &self.tasks
+    }
+
+    /// 落后于上游的任务
+    pub fn behind(&self) -> impl Iterator<Item = &OutdatedTask> {
+        self.tasks
+            .iter()
+            .filter(|t| t.status == OutdatedStatus::Behind)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.tasks).expect("Failed to serialize outdated report")
+    }
+
+    /// 生成文本格式的表格
+    pub fn table(&self) -> String {
+        if self.tasks.is_empty() {
+            return "No task is built from a git branch.\n".to_string();
+        }
+        let header = ["NAME", "VERSION", "BRANCH", "CURRENT", "UPSTREAM", "STATUS"];
+        let rows: Vec<[String; 6]> = self
+            .tasks
+            .iter()
+            .map(|t| {
+                [
+                    t.name.clone(),
+                    t.version.clone(),
+                    t.branch.clone(),
+                    short_revision(t.current.as_deref()),
+                    short_revision(t.upstream.as_deref()),
+                    t.status.as_str().to_string(),
+                ]
+            })
+            .collect();
+
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut out = String::new();
+        let mut push_row = |cells: Vec<&str>| {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        };
+        push_row(header.to_vec());
+        for row in &rows {
+            push_row(row.iter().map(String::as_str).collect());
+        }
+
+        let behind = self.behind().count();
+        if behind > 0 {
+            out.push_str(&format!("\n{} task(s) are behind upstream.\n", behind));
+        }
+        for t in self.tasks.iter().filter(|t| t.error.is_some()) {
+            out.push_str(&format!(
+                "{}-{}: {}\n",
+                t.name,
+                t.version,
+                t.error.as_deref().unwrap_or_default()
+            ));
+        }
+        out
+    }
+
+    /// 把各个任务上游分支的最新提交写入锁文件
+    ///
+    /// 锁文件已经存在时，只更新本次查询成功的任务，其他任务的记录保持不变
+    pub fn update_lockfile(&self, path: &Path) -> Result<(), String> {
+        let mut lockfile = match std::fs::read_to_string(path) {
+            Ok(content) => toml::from_str::<Lockfile>(&content)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Lockfile::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        for t in &self.tasks {
+            let Some(upstream) = &t.upstream else {
+                continue;
+            };
+            lockfile
+                .tasks
+                .retain(|e| !(e.name == t.name && e.version == t.version));
+            lockfile.tasks.push(LockEntry {
+                name: t.name.clone(),
+                version: t.version.clone(),
+                url: t.url.clone(),
+                branch: t.branch.clone(),
+                revision: upstream.clone(),
+            });
+        }
+        lockfile
+            .tasks
+            .sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+        let content = toml::to_string(&lockfile).map_err(|e| e.to_string())?;
+        crate::repository::write_atomically(path, content.as_bytes())
+    }
+}
+
+/// # 锁文件
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "task")]
+    pub tasks: Vec<LockEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockEntry {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub branch: String,
+    pub revision: String,
+}
+
+/// 源码缓存中检出的提交
+fn current_revision(source_dir: &Path) -> Option<String> {
+    if !source_dir.join(".git").exists() {
+        return None;
+    }
+    let output = Command::new("git")
+        .current_dir(source_dir)
+        .args(["rev-parse", "HEAD"])
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 查询远程分支的最新提交。禁止git交互式地询问用户名和密码
+fn ls_remote(url: &str, branch: &str) -> Result<String, String> {
+    let output = Command::new("git")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("ls-remote")
+        .arg(url)
+        .arg(format!("refs/heads/{}", branch))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run git ls-remote: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git ls-remote {} failed: {}",
+            url,
+            StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
+        ));
+    }
+    parse_ls_remote(&String::from_utf8_lossy(&output.stdout), branch)
+        .ok_or_else(|| format!("Branch {} not found in {}", branch, url))
+}
+
+/// 从`git ls-remote`的输出中找到分支的提交
+fn parse_ls_remote(output: &str, branch: &str) -> Option<String> {
+    let reference = format!("refs/heads/{}", branch);
+    output.lines().find_map(|line| {
+        let (revision, name) = line.split_once('\t')?;
+        (name.trim() == reference).then(|| revision.trim().to_string())
+    })
+}
+
+fn short_revision(revision: Option<&str>) -> String {
+    match revision {
+        Some(revision) => revision.chars().take(12).collect(),
+        None => "-".to_string(),
+    }
+}
@@ -0,0 +1,125 @@
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::parser::Parser;
+
+fn parse_tasks(ctx: &BaseGlobalTestContext, configs: &[&str]) -> Vec<(PathBuf, DADKTask)> {
+    configs
+        .iter()
+        .map(|config| {
+            let path = ctx.config_v2_dir().join(config);
+            let task = Parser::new(ctx.config_v2_dir())
+                .parse_config_file(&path)
+                .unwrap();
+            (path, task)
+        })
+        .collect()
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=dadk", "-c", "user.email=dadk@localhost"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// 测试比较源码缓存与远程分支的提交，并更新锁文件
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn check_outdated_tasks(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let tasks = parse_tasks(
+        ctx,
+        &[
+            "app_all_target_arch_0_2_0.toml",
+            "app_target_arch_x86_64_only_0_2_0.toml",
+            // 固定了revision的任务不检查
+            "app_target_arch_riscv64_only_0_2_0.toml",
+        ],
+    );
+    let source_dir = CacheDir::get_path(cache_root, &tasks[0].1, CacheDirType::Source);
+    std::fs::create_dir_all(&source_dir).unwrap();
+    git(&source_dir, &["init", "-q"]);
+    git(
+        &source_dir,
+        &["commit", "-q", "--allow-empty", "-m", "init"],
+    );
+    let head = git(&source_dir, &["rev-parse", "HEAD"]);
+
+    let upstream = head.clone();
+    let report = OutdatedReport::check_with(cache_root, &tasks, |url, branch| {
+        assert_eq!((url, branch), ("1", "1"));
+        Ok(upstream.clone())
+    });
+    let status: Vec<_> = report
+        .tasks()
+        .iter()
+        .map(|t| (t.name.as_str(), t.status))
+        .collect();
+    assert_eq!(
+        status,
+        vec![
+            ("app_all_target_arch", OutdatedStatus::UpToDate),
+            ("app_target_arch_x86_64_only", OutdatedStatus::NotFetched),
+        ]
+    );
+    assert_eq!(report.tasks()[0].current.as_deref(), Some(head.as_str()));
+
+    let report = OutdatedReport::check_with(cache_root, &tasks, |_, _| Ok("f".repeat(40)));
+    let behind: Vec<_> = report.behind().map(|t| t.name.as_str()).collect();
+    assert_eq!(behind, vec!["app_all_target_arch"]);
+    assert!(report.table().contains("1 task(s) are behind upstream."));
+
+    // 锁文件中保留本次没有查询的任务
+    let lockfile = cache_root.join("dadk-user.lock");
+    std::fs::write(
+        &lockfile,
+        "[[task]]\nname = \"other\"\nversion = \"1.0.0\"\nurl = \"u\"\nbranch = \"main\"\nrevision = \"abc\"\n",
+    )
+    .unwrap();
+    report.update_lockfile(&lockfile).unwrap();
+    let content: Lockfile = toml::from_str(&std::fs::read_to_string(&lockfile).unwrap()).unwrap();
+    let entries: Vec<_> = content
+        .tasks
+        .iter()
+        .map(|e| (e.name.as_str(), e.revision.as_str()))
+        .collect();
+    let upstream = "f".repeat(40);
+    assert_eq!(
+        entries,
+        vec![
+            ("app_all_target_arch", upstream.as_str()),
+            ("app_target_arch_x86_64_only", upstream.as_str()),
+            ("other", "abc"),
+        ]
+    );
+
+    // 查询失败
+    let report =
+        OutdatedReport::check_with(cache_root, &tasks, |_, _| Err("network down".to_string()));
+    assert!(report
+        .tasks()
+        .iter()
+        .all(|t| t.status == OutdatedStatus::Unknown && t.upstream.is_none()));
+    assert!(report.table().contains("network down"));
+}
+
+#[test]
+fn parse_ls_remote_output() {
+    let output = "1111111111\trefs/heads/main-next\n2222222222\trefs/heads/main\n";
+    assert_eq!(
+        parse_ls_remote(output, "main").as_deref(),
+        Some("2222222222")
+    );
+    assert_eq!(parse_ls_remote(output, "dev"), None);
+    assert_eq!(short_revision(Some("0123456789abcdef")), "0123456789ab");
+    assert_eq!(short_revision(None), "-");
+}
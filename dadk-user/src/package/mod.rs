@@ -0,0 +1,270 @@
+//! # 二进制包
+//!
+//! `dadk user package`把任务的构建缓存目录打包为`<name>-<version>-<arch>.dpk`文件，
+//! 以便发布预编译的DragonOS用户程序。`.dpk`文件是gzip压缩的tar包，包含：
+//!
+//! - `dadk-package.toml`：包的元数据（名称、版本、架构、依赖）
+//! - `files/`：构建缓存目录中的文件
+//!
+//! `task-source.type = "package"`的任务会直接把`.dpk`文件中的`files/`解压到构建缓存目录，然后照常安装。
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use dadk_config::common::{target_arch::TargetArch, task::Dependency};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executor::cache::{CacheDir, CacheDirType, TaskDataDir},
+    parser::{task::DADKTask, task_log::BuildStatus},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// 包的元数据文件名
+pub const MANIFEST_FILE_NAME: &str = "dadk-package.toml";
+/// 包中存放构建结果的目录
+const FILES_DIR: &str = "files";
+/// 包文件的扩展名
+pub const PACKAGE_EXTENSION: &str = "dpk";
+/// 当前的包格式版本
+const FORMAT_VERSION: u32 = 1;
+
+/// # 包的元数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageManifest {
+    pub format_version: u32,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub target_arch: TargetArch,
+    #[serde(default)]
+    pub depends: Vec<Dependency>,
+    /// 打包时间
+    pub created: DateTime<Utc>,
+}
+
+impl PackageManifest {
+    pub fn new(task: &DADKTask, arch: TargetArch) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            name: task.name.clone(),
+            version: task.version.clone(),
+            description: task.description.clone(),
+            target_arch: arch,
+            depends: task.depends.clone(),
+            created: Utc::now(),
+        }
+    }
+
+    /// 包文件名：`<name>-<version>-<arch>.dpk`
+    pub fn file_name(&self) -> String {
+        let arch: &str = self.target_arch.into();
+        format!(
+            "{}-{}-{}.{}",
+            self.name, self.version, arch, PACKAGE_EXTENSION
+        )
+    }
+}
+
+/// 把目录`files`打包到`output_dir`中，返回包文件的路径
+pub fn create(
+    manifest: &PackageManifest,
+    files: &Path,
+    output_dir: &Path,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    let path = output_dir.join(manifest.file_name());
+    let tmp = path.with_extension(format!("{}.tmp", PACKAGE_EXTENSION));
+    let file =
+        File::create(&tmp).map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+    let r = write_package(manifest, files, file)
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to write package {}: {}", path.display(), e));
+    if r.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    r.map(|_| path)
+}
+
+fn write_package(manifest: &PackageManifest, files: &Path, file: File) -> std::io::Result<()> {
+    let content =
+        toml::to_string(manifest).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    // 保留符号链接
+    builder.follow_symlinks(false);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_FILE_NAME, content.as_bytes())?;
+    builder.append_dir_all(FILES_DIR, files)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn open(package: &Path) -> Result<tar::Archive<GzDecoder<File>>, String> {
+    let file = File::open(package)
+        .map_err(|e| format!("Failed to open package {}: {}", package.display(), e))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_overwrite(true);
+    Ok(archive)
+}
+
+/// 读取包的元数据
+pub fn read_manifest(package: &Path) -> Result<PackageManifest, String> {
+    let err = |e: &dyn std::fmt::Display| format!("Invalid package {}: {}", package.display(), e);
+    let mut archive = open(package)?;
+    for entry in archive.entries().map_err(|e| err(&e))? {
+        let mut entry = entry.map_err(|e| err(&e))?;
+        if entry.path().map_err(|e| err(&e))?.as_ref() == Path::new(MANIFEST_FILE_NAME) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content).map_err(|e| err(&e))?;
+            let manifest: PackageManifest = toml::from_str(&content).map_err(|e| err(&e))?;
+            if manifest.format_version > FORMAT_VERSION {
+                return Err(err(&format!(
+                    "unsupported format version {}",
+                    manifest.format_version
+                )));
+            }
+            return Ok(manifest);
+        }
+    }
+    Err(err(&format!("{} not found", MANIFEST_FILE_NAME)))
+}
+
+/// 把包中的文件解压到`dest`目录（`dest`中原有的内容会被删除），返回包的元数据
+///
+/// 包的目标架构必须与`arch`相同。包中的条目不能位于已经解压的符号链接之下，符号链接也不能指向`dest`之外
+pub fn unpack(package: &Path, dest: &Path, arch: TargetArch) -> Result<PackageManifest, String> {
+    let manifest = read_manifest(package)?;
+    if manifest.target_arch != arch {
+        let (expected, actual): (&str, &str) = (arch.into(), manifest.target_arch.into());
+        return Err(format!(
+            "Package {} is built for {}, but the target arch is {}",
+            package.display(),
+            actual,
+            expected
+        ));
+    }
+
+    let err = |e: &dyn std::fmt::Display| {
+        format!("Failed to unpack package {}: {}", package.display(), e)
+    };
+    if dest.exists() {
+        std::fs::remove_dir_all(dest).map_err(|e| err(&e))?;
+    }
+    std::fs::create_dir_all(dest).map_err(|e| err(&e))?;
+
+    let mut archive = open(package)?;
+    for entry in archive.entries().map_err(|e| err(&e))? {
+        let mut entry = entry.map_err(|e| err(&e))?;
+        let path = entry.path().map_err(|e| err(&e))?.to_path_buf();
+        let Ok(rel) = path.strip_prefix(FILES_DIR) else {
+            continue;
+        };
+        // 拒绝包含`..`、绝对路径的条目
+        if rel.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(err(&format!("invalid path {}", path.display())));
+        }
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+        check_entry(&entry, rel, dest).map_err(|e| err(&e))?;
+        let target = dest.join(rel);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| err(&e))?;
+        }
+        // 同一个路径出现多次时，不通过之前解压的符号链接写入
+        if is_symlink(&target) {
+            std::fs::remove_file(&target).map_err(|e| err(&e))?;
+        }
+        entry.unpack(&target).map_err(|e| err(&e))?;
+    }
+    Ok(manifest)
+}
+
+/// 检查条目不会写入`dest`之外：
+///
+/// - 不能位于已经解压的符号链接之下（例如先解压`lib -> /etc`，再解压`lib/passwd`）
+/// - 符号链接不能指向`dest`之外（绝对路径，或者`..`超出了`dest`）
+/// - 不支持硬链接
+fn check_entry<R: Read>(entry: &tar::Entry<R>, rel: &Path, dest: &Path) -> Result<(), String> {
+    let mut dir = dest.to_path_buf();
+    for component in rel.parent().into_iter().flat_map(Path::components) {
+        dir.push(component);
+        if is_symlink(&dir) {
+            return Err(format!(
+                "{} is inside the symlink {}",
+                rel.display(),
+                dir.display()
+            ));
+        }
+    }
+
+    let entry_type = entry.header().entry_type();
+    if entry_type.is_hard_link() {
+        return Err(format!("hard link {} is not supported", rel.display()));
+    }
+    if !entry_type.is_symlink() {
+        return Ok(());
+    }
+    let link = entry
+        .link_name()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("symlink {} has no target", rel.display()))?;
+    // 链接所在的目录相对于dest的深度
+    let mut depth = rel.components().count() - 1;
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(format!(
+                    "symlink {} -> {} points outside the package",
+                    rel.display(),
+                    link.display()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// 把已经构建成功的任务打包到`output_dir`中，返回包文件的路径
+pub fn export_task(
+    cache_root: &Path,
+    task: &DADKTask,
+    arch: TargetArch,
+    output_dir: &Path,
+) -> Result<PathBuf, String> {
+    let built = TaskDataDir::load_task_log(cache_root, task)
+        .and_then(|log| log.build_status().cloned())
+        == Some(BuildStatus::Success);
+    let build_dir = CacheDir::get_path(cache_root, task, CacheDirType::Build);
+    if !built || !build_dir.is_dir() {
+        return Err(format!(
+            "Task {} has not been built successfully",
+            task.name_version()
+        ));
+    }
+    create(&PackageManifest::new(task, arch), &build_dir, output_dir)
+}
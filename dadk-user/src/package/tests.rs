@@ -0,0 +1,199 @@
+use std::os::unix::fs::PermissionsExt;
+
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::parser::{task_log::TaskLog, Parser};
+
+fn parse_task(ctx: &BaseGlobalTestContext, config: &str) -> DADKTask {
+    Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join(config))
+        .unwrap()
+}
+
+/// 测试打包后解压，得到相同的文件、权限以及符号链接
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn create_and_unpack(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let files = dir.join("files");
+    std::fs::create_dir_all(files.join("bin")).unwrap();
+    std::fs::write(files.join("bin/hello"), "#!/bin/sh\necho hello\n").unwrap();
+    std::fs::set_permissions(files.join("bin/hello"), PermissionsExt::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink("hello", files.join("bin/hi")).unwrap();
+
+    let manifest = PackageManifest::new(&task, TargetArch::X86_64);
+    let package = create(&manifest, &files, &dir.join("out")).unwrap();
+    assert_eq!(
+        package.file_name().unwrap().to_str().unwrap(),
+        format!("{}-{}-x86_64.dpk", task.name, task.version)
+    );
+    assert_eq!(read_manifest(&package).unwrap().name, task.name);
+
+    let dest = dir.join("dest");
+    std::fs::create_dir_all(&dest).unwrap();
+    std::fs::write(dest.join("stale"), "").unwrap();
+    let unpacked = unpack(&package, &dest, TargetArch::X86_64).unwrap();
+    assert_eq!(unpacked.depends, task.depends);
+    assert_eq!(unpacked.created.timestamp(), manifest.created.timestamp());
+    assert!(!dest.join("stale").exists());
+    assert_eq!(
+        std::fs::read_to_string(dest.join("bin/hello")).unwrap(),
+        "#!/bin/sh\necho hello\n"
+    );
+    let mode = std::fs::metadata(dest.join("bin/hello"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(
+        std::fs::read_link(dest.join("bin/hi")).unwrap(),
+        PathBuf::from("hello")
+    );
+    assert!(!dest.join(MANIFEST_FILE_NAME).exists());
+
+    // 目标架构不同时拒绝安装
+    assert!(unpack(&package, &dest, TargetArch::RiscV64).is_err());
+    // 不是二进制包
+    std::fs::write(dir.join("bad.dpk"), "not a package").unwrap();
+    assert!(read_manifest(&dir.join("bad.dpk")).is_err());
+}
+
+/// 测试只有构建成功的任务才能打包
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn export_built_task(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let output = cache_root.join("packages");
+    assert!(export_task(cache_root, &task, TargetArch::X86_64, &output).is_err());
+
+    let build_dir = CacheDir::get_path(cache_root, &task, CacheDirType::Build);
+    std::fs::create_dir_all(&build_dir).unwrap();
+    std::fs::write(build_dir.join("app"), "app").unwrap();
+    let data_dir = CacheDir::get_path(cache_root, &task, CacheDirType::TaskData);
+    std::fs::create_dir_all(&data_dir).unwrap();
+    let mut log = TaskLog::new();
+    log.set_build_status(BuildStatus::Failed);
+    log.set_build_time_now();
+    let write_log = |log: &TaskLog| {
+        std::fs::write(
+            data_dir.join("task_log.toml"),
+            toml::to_string(log).unwrap(),
+        )
+        .unwrap()
+    };
+    write_log(&log);
+    assert!(export_task(cache_root, &task, TargetArch::X86_64, &output).is_err());
+
+    log.set_build_status(BuildStatus::Success);
+    write_log(&log);
+    let package = export_task(cache_root, &task, TargetArch::X86_64, &output).unwrap();
+    assert!(package.starts_with(&output));
+    let dest = cache_root.join("dest");
+    unpack(&package, &dest, TargetArch::X86_64).unwrap();
+    assert_eq!(std::fs::read_to_string(dest.join("app")).unwrap(), "app");
+}
+
+/// 构造包含任意条目的包：`(路径, 符号链接的目标或者文件内容, 是否为符号链接)`
+fn craft_package(path: &Path, task: &DADKTask, entries: &[(&str, &str, bool)]) {
+    let file = File::create(path).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let manifest = toml::to_string(&PackageManifest::new(task, TargetArch::X86_64)).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, MANIFEST_FILE_NAME, manifest.as_bytes())
+        .unwrap();
+    for (name, content, symlink) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        if *symlink {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, name, content).unwrap();
+        } else {
+            header.set_size(content.len() as u64);
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+/// 测试拒绝通过符号链接写入解压目录之外的包
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn unpack_rejects_symlink_escape(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let outside = dir.join("outside");
+    std::fs::create_dir_all(&outside).unwrap();
+    let package = dir.join("evil.dpk");
+    let dest = dir.join("dest");
+
+    // 先解压指向外部目录的符号链接，再通过它写入文件
+    let link = outside.to_string_lossy().to_string();
+    craft_package(
+        &package,
+        &task,
+        &[
+            ("files/lib", &link, true),
+            ("files/lib/passwd", "root::0:0::/:/bin/sh\n", false),
+        ],
+    );
+    assert!(unpack(&package, &dest, TargetArch::X86_64).is_err());
+    assert!(!outside.join("passwd").exists());
+
+    // 即使符号链接本身是相对路径，也不能超出解压目录
+    craft_package(&package, &task, &[("files/bin/up", "../../outside", true)]);
+    assert!(unpack(&package, &dest, TargetArch::X86_64).is_err());
+    assert!(!dest.join("bin/up").exists());
+
+    // 不通过已经解压的符号链接创建文件，即使链接指向解压目录之内
+    craft_package(
+        &package,
+        &task,
+        &[
+            ("files/usr/lib/README", "", false),
+            ("files/lib", "usr/lib", true),
+            ("files/lib/libc.so.6", "libc", false),
+        ],
+    );
+    assert!(unpack(&package, &dest, TargetArch::X86_64).is_err());
+    assert!(!dest.join("usr/lib/libc.so.6").exists());
+
+    // 解压目录之内的符号链接；同一路径先为符号链接、后为文件时，不通过符号链接写入
+    craft_package(
+        &package,
+        &task,
+        &[
+            ("files/lib/libc.so.6", "libc", false),
+            ("files/lib/libc.so", "./libc.so.6", true),
+            ("files/bin/sh", "../lib/libc.so", true),
+            ("files/bin/sh", "#!/bin/sh\n", false),
+        ],
+    );
+    unpack(&package, &dest, TargetArch::X86_64).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dest.join("lib/libc.so")).unwrap(),
+        "libc"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dest.join("bin/sh")).unwrap(),
+        "#!/bin/sh\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dest.join("lib/libc.so.6")).unwrap(),
+        "libc"
+    );
+}
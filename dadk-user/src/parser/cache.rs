@@ -0,0 +1,110 @@
+//! # 配置解析缓存
+//!
+//! 配置文件很多时，每次执行都重新解析所有配置文件需要较长时间。
+//! 解析成功的任务缓存在`<cache_root>/parse_cache.json`中，配置文件的修改时间和大小都没有变化时，
+//! 直接使用缓存中的任务。配置文件中可以引用的变量或者DADK的版本变化时，整个缓存失效。
+//!
+//! 缓存中的任务不会再次校验，因此配置文件没有变化、但是其中引用的本地路径被删除时，
+//! 要到执行任务时才会报错。
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::task::DADKTask;
+
+/// 缓存文件的文件名
+pub const PARSE_CACHE_FILE: &str = "parse_cache.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct ParseCache {
+    /// 生成缓存的DADK版本
+    version: String,
+    /// 解析时使用的变量
+    variables: BTreeMap<String, String>,
+    /// 配置文件路径 -> 解析结果
+    entries: BTreeMap<PathBuf, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// 配置文件的修改时间（纳秒）
+    modified: u128,
+    size: u64,
+    task: DADKTask,
+}
+
+impl ParseCache {
+    /// 读取缓存文件。缓存文件不存在、无法解析，或者版本、变量不匹配时返回空的缓存
+    pub fn load(path: &Path, variables: &BTreeMap<String, String>) -> Self {
+        let empty = Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            variables: variables.clone(),
+            entries: BTreeMap::new(),
+        };
+        let cache: Self = match std::fs::read(path) {
+            Ok(content) => match serde_json::from_slice(&content) {
+                Ok(cache) => cache,
+                Err(e) => {
+                    warn!("Ignoring invalid parse cache {}: {}", path.display(), e);
+                    return empty;
+                }
+            },
+            Err(_) => return empty,
+        };
+        if cache.version != empty.version || cache.variables != empty.variables {
+            debug!("Parse cache {} is outdated", path.display());
+            return empty;
+        }
+        cache
+    }
+
+    /// 写入缓存文件，只保留本次解析的配置文件
+    pub fn save(&mut self, path: &Path, config_files: &[PathBuf]) -> Result<(), String> {
+        self.entries.retain(|file, _| config_files.contains(file));
+        let content = serde_json::to_vec(self)
+            .map_err(|e| format!("Failed to serialize parse cache: {}", e))?;
+        crate::repository::write_atomically(path, &content)
+    }
+
+    /// 配置文件没有变化时，返回缓存中的任务
+    pub fn get(&self, config_file: &Path) -> Option<DADKTask> {
+        let entry = self.entries.get(config_file)?;
+        let (modified, size) = file_stamp(config_file)?;
+        if entry.modified == modified && entry.size == size {
+            Some(entry.task.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, config_file: &Path, task: &DADKTask) {
+        if let Some((modified, size)) = file_stamp(config_file) {
+            self.entries.insert(
+                config_file.to_path_buf(),
+                CacheEntry {
+                    modified,
+                    size,
+                    task: task.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// 文件的修改时间和大小
+fn file_stamp(path: &Path) -> Option<(u128, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((modified, metadata.len()))
+}
@@ -44,19 +44,31 @@
 //! LD_LIBRARY_PATH = "/usr/lib"
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
-    fs::{DirEntry, ReadDir},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use self::task::DADKTask;
+use self::{cache::ParseCache, task::DADKTask};
 use anyhow::Result;
-use dadk_config::user::UserConfigFile;
-use log::{debug, error, info};
+use dadk_config::{
+    app_blocklist::AppBlocklistConfigFile,
+    common::target_arch::TargetArch,
+    manifest::KernelConfig,
+    user::{config_files, UserConfigFile, KERNEL_MODULES_DIR},
+};
+use log::{debug, error, info, warn};
 
+pub mod cache;
 pub mod task;
 pub mod task_log;
 
+#[cfg(test)]
+mod tests;
+
+/// 内核版本对应的变量名称
+const KERNEL_VERSION_VAR: &str = "KERNEL_VERSION";
+
 /// # 配置解析器
 ///
 /// 用于解析配置文件，生成任务列表
@@ -64,8 +76,20 @@ pub mod task_log;
 pub struct Parser {
     /// 配置文件目录
     config_dir: PathBuf,
-    /// 扫描到的配置文件列表
-    config_files: Vec<PathBuf>,
+    /// 额外的配置文件目录，在`config_dir`之后扫描
+    overlay_dirs: Vec<PathBuf>,
+    /// 扫描到的配置文件列表(所在配置目录的序号, 配置文件路径)
+    config_files: Vec<(usize, PathBuf)>,
+    /// 跳过无法解析的配置文件，而不是返回错误
+    skip_invalid: bool,
+    /// 被跳过的配置文件，以及对应的错误信息
+    invalid_configs: Vec<(PathBuf, String)>,
+    /// 配置文件中可以引用的变量
+    variables: BTreeMap<String, String>,
+    /// 同一个程序有多个版本时，每个程序的默认版本
+    default_versions: BTreeMap<String, String>,
+    /// 解析结果的缓存文件，为None时不使用缓存
+    cache_file: Option<PathBuf>,
 }
 
 pub struct ParserError {
@@ -126,8 +150,76 @@ impl Parser {
     pub fn new(config_dir: PathBuf) -> Self {
         Self {
             config_dir,
+            overlay_dirs: Vec::new(),
             config_files: Vec::new(),
+            skip_invalid: false,
+            invalid_configs: Vec::new(),
+            variables: BTreeMap::new(),
+            default_versions: BTreeMap::new(),
+            cache_file: None,
+        }
+    }
+
+    /// 把解析结果缓存在`cache_root`中，配置文件没有变化时不再重新解析
+    pub fn cache_dir(mut self, cache_root: &Path) -> Self {
+        self.cache_file = Some(cache_root.join(cache::PARSE_CACHE_FILE));
+        self
+    }
+
+    /// 设置额外的配置文件目录
+    ///
+    /// 这些目录按顺序在`config_dir`之后扫描，后面的目录中的任务覆盖前面的目录中同名同版本的任务
+    pub fn overlay_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.overlay_dirs = dirs;
+        self
+    }
+
+    /// 设置配置文件中可以通过`${NAME}`引用的变量
+    pub fn variables(mut self, variables: BTreeMap<String, String>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    /// 设置每个程序的默认版本（manifest中的`default-versions`）
+    pub fn default_versions(mut self, default_versions: BTreeMap<String, String>) -> Self {
+        self.default_versions = default_versions;
+        self
+    }
+
+    /// 配置文件中可以引用的变量：manifest中定义的变量，以及`ARCH`、`DADK_CACHE_ROOT`，
+    /// manifest中设置了`[kernel]`时还有`KERNEL_VERSION`
+    ///
+    /// 内置变量的优先级高于manifest中定义的同名变量
+    pub fn config_variables(
+        variables: &BTreeMap<String, String>,
+        arch: TargetArch,
+        cache_root: &Path,
+        kernel: Option<&KernelConfig>,
+    ) -> BTreeMap<String, String> {
+        let mut variables = variables.clone();
+        let arch: &str = arch.into();
+        variables.insert("ARCH".to_string(), arch.to_string());
+        variables.insert(
+            "DADK_CACHE_ROOT".to_string(),
+            cache_root.to_string_lossy().to_string(),
+        );
+        if let Some(kernel) = kernel {
+            variables.insert(KERNEL_VERSION_VAR.to_string(), kernel.version.clone());
         }
+        variables
+    }
+
+    /// 设置是否跳过无法解析的配置文件
+    ///
+    /// 跳过时，每个无法解析的配置文件都会输出一条警告，解析结束后再输出汇总信息
+    pub fn skip_invalid_configs(mut self, skip: bool) -> Self {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// 上一次解析时被跳过的配置文件，以及对应的错误信息
+    pub fn invalid_configs(&self) -> &[(PathBuf, String)] {
+        &self.invalid_configs
     }
 
     /// # 解析所有配置文件，生成任务列表
@@ -150,60 +242,272 @@ impl Parser {
         return r;
     }
 
+    /// # 过滤掉被黑名单跳过的任务
+    ///
+    /// 黑名单条目可以只对部分架构生效，因此需要指定当前的目标架构
+    pub fn filter_blocked_apps(
+        tasks: Vec<(PathBuf, DADKTask)>,
+        blocklist: &AppBlocklistConfigFile,
+        arch: TargetArch,
+    ) -> Vec<(PathBuf, DADKTask)> {
+        tasks
+            .into_iter()
+            .filter(|(_, task)| {
+                if !blocklist.is_blocked(&task.name, arch) {
+                    return true;
+                }
+                let reason = match blocklist.entry(&task.name, arch) {
+                    Some(app) => app.reason.as_deref().unwrap_or("in blocklist"),
+                    None => "not in allowlist",
+                };
+                info!("Skip blocked app {}: {}", task.name_version(), reason);
+                false
+            })
+            .collect()
+    }
+
     /// # 扫描配置文件目录，找到所有配置文件
     fn scan_config_files(&mut self) -> Result<()> {
-        info!("Scanning config files in {}", self.config_dir.display());
-
-        let mut dir_queue: Vec<PathBuf> = Vec::new();
-        // 将config目录加入队列
-        dir_queue.push(self.config_dir.clone());
-
-        while !dir_queue.is_empty() {
-            // 扫描目录，找到所有*.dadk文件
-            let dir = dir_queue.pop().unwrap();
-            let entries: ReadDir = std::fs::read_dir(&dir)?;
-
-            for entry in entries {
-                let entry: DirEntry = entry?;
-
-                let path: PathBuf = entry.path();
-                if path.is_dir() {
-                    dir_queue.push(path);
-                } else if path.is_file() {
-                    let extension: Option<&std::ffi::OsStr> = path.extension();
-                    if extension.is_none() {
-                        continue;
-                    }
-                    let extension: &std::ffi::OsStr = extension.unwrap();
-                    if extension.to_ascii_lowercase() != "toml" {
-                        continue;
-                    }
-                    // 找到一个配置文件, 加入列表
-                    self.config_files.push(path);
-                }
-            }
+        let dirs: Vec<PathBuf> = std::iter::once(self.config_dir.clone())
+            .chain(self.overlay_dirs.iter().cloned())
+            .collect();
+        for (layer, dir) in dirs.into_iter().enumerate() {
+            self.scan_config_dir(layer, dir)?;
         }
+        // 目录的遍历顺序与文件系统有关，排序后使任务列表在不同机器上保持一致
+        self.config_files.sort();
+        return Ok(());
+    }
 
+    /// # 扫描一个配置文件目录（包括子目录）
+    fn scan_config_dir(&mut self, layer: usize, config_dir: PathBuf) -> Result<()> {
+        info!("Scanning config files in {}", config_dir.display());
+        for path in config_files(&config_dir)? {
+            self.config_files.push((layer, path));
+        }
         return Ok(());
     }
 
     /// # 解析所有配置文件，生成任务列表
     ///
-    /// 一旦发生错误，立即返回
+    /// 一旦发生错误，立即返回。设置了`skip_invalid_configs`时，跳过无法解析的配置文件
     ///
     /// ## 返回值
     ///
     /// * `Ok(Vec<DADKTask>)` - 任务列表
     /// * `Err(ParserError)` - 解析错误
-    fn gen_tasks(&self) -> Result<Vec<(PathBuf, DADKTask)>> {
+    fn gen_tasks(&mut self) -> Result<Vec<(PathBuf, DADKTask)>> {
         let mut result_vec = Vec::new();
-        for config_file in &self.config_files {
-            let task: DADKTask = self.parse_config_file(config_file)?;
+        self.invalid_configs.clear();
+        let mut cache = self
+            .cache_file
+            .as_ref()
+            .map(|path| ParseCache::load(path, &self.variables));
+        let mut cached = 0;
+        for (layer, config_file) in &self.config_files {
+            if let Some(task) = cache.as_ref().and_then(|c| c.get(config_file)) {
+                cached += 1;
+                result_vec.push((*layer, config_file.clone(), task));
+                continue;
+            }
+            let task = match self.parse_config_file(config_file) {
+                Ok(task) => {
+                    if let Some(cache) = &mut cache {
+                        cache.insert(config_file, &task);
+                    }
+                    task
+                }
+                Err(e) if self.skip_invalid => {
+                    warn!(
+                        "Skipping invalid config file {}: {:?}",
+                        config_file.display(),
+                        e
+                    );
+                    self.invalid_configs
+                        .push((config_file.clone(), format!("{:?}", e)));
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e.context(format!(
+                        "Failed to parse config file {}",
+                        config_file.display()
+                    )))
+                }
+            };
             debug!("Parsed config file {}: {:?}", config_file.display(), task);
-            result_vec.push((config_file.clone(), task));
+            result_vec.push((*layer, config_file.clone(), task));
         }
 
-        return Ok(result_vec);
+        if let (Some(cache), Some(path)) = (&mut cache, &self.cache_file) {
+            debug!("{} config file(s) loaded from the parse cache", cached);
+            let files: Vec<PathBuf> = self.config_files.iter().map(|(_, p)| p.clone()).collect();
+            if let Err(e) = cache.save(path, &files) {
+                warn!("Failed to save parse cache: {}", e);
+            }
+        }
+
+        if !self.invalid_configs.is_empty() {
+            warn!(
+                "Skipped {} invalid config file(s), {} task(s) parsed: {}",
+                self.invalid_configs.len(),
+                result_vec.len(),
+                self.invalid_configs
+                    .iter()
+                    .map(|(path, _)| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let mut tasks = Self::resolve_overrides(result_vec)?;
+        Self::resolve_versions(&mut tasks, &self.default_versions)?;
+        Self::check_shared_sources(&tasks)?;
+        Ok(tasks)
+    }
+
+    /// # 检查共用源码缓存目录的任务
+    ///
+    /// 设置了相同`source-cache-key`的任务共用一个源码缓存目录，它们的源码配置必须相同
+    fn check_shared_sources(tasks: &[(PathBuf, DADKTask)]) -> Result<()> {
+        let mut first: BTreeMap<&str, &DADKTask> = BTreeMap::new();
+        for (_, task) in tasks {
+            let Some(key) = &task.source_cache_key else {
+                continue;
+            };
+            match first.get(key.as_str()) {
+                Some(other) if other.task_type != task.task_type => {
+                    return Err(anyhow::anyhow!(
+                        "Tasks {} and {} share source-cache-key '{}' but have different sources",
+                        other.name_version(),
+                        task.name_version(),
+                        key
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    first.insert(key, task);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// # 合并多个配置目录中同名同版本的任务
+    ///
+    /// - 设置了`override = true`的任务会替换其他配置文件中同名同版本的任务，同一个任务只能被一个配置文件覆盖
+    /// - 否则后面的配置目录中的任务替换前面的配置目录中的同名同版本任务
+    ///
+    /// 同一个配置目录中的重复任务保持不变，由调度器报告冲突
+    fn resolve_overrides(
+        tasks: Vec<(usize, PathBuf, DADKTask)>,
+    ) -> Result<Vec<(PathBuf, DADKTask)>> {
+        let mut overrides: BTreeMap<(String, String), &PathBuf> = BTreeMap::new();
+        for (_, path, task) in tasks.iter().filter(|(_, _, task)| task.overrides) {
+            let key = (task.name.clone(), task.version.clone());
+            if let Some(other) = overrides.insert(key, path) {
+                return Err(anyhow::anyhow!(
+                    "Task {} is overridden by more than one config file: {} and {}",
+                    task.name_version(),
+                    other.display(),
+                    path.display()
+                ));
+            }
+        }
+
+        // 每个任务保留优先级最高的配置：(是否设置了override, 配置目录的序号)
+        let mut winners: BTreeMap<(String, String), ((bool, usize), PathBuf)> = BTreeMap::new();
+        for (layer, path, task) in &tasks {
+            let rank = (task.overrides, *layer);
+            let key = (task.name.clone(), task.version.clone());
+            match winners.get(&key) {
+                Some((best, _)) if *best >= rank => {}
+                _ => {
+                    winners.insert(key, (rank, path.clone()));
+                }
+            }
+        }
+
+        Ok(tasks
+            .into_iter()
+            .filter(|(layer, path, task)| {
+                let key = (task.name.clone(), task.version.clone());
+                let (best, by) = &winners[&key];
+                if *best == (task.overrides, *layer) {
+                    return true;
+                }
+                info!(
+                    "Task {} in {} is overridden by {}",
+                    task.name_version(),
+                    path.display(),
+                    by.display()
+                );
+                false
+            })
+            .map(|(_, path, task)| (path, task))
+            .collect())
+    }
+
+    /// # 选择同名任务的默认版本
+    ///
+    /// 同一个程序的多个版本可以同时构建、安装。每个程序的默认版本为`default-versions`中指定的版本，
+    /// 没有指定时为唯一的版本。
+    ///
+    /// - 省略了版本号的依赖项依赖默认版本，有多个版本但没有默认版本时报错
+    /// - 只有默认版本创建`[[install.files]]`中不带版本号的符号链接。
+    ///   没有默认版本时所有版本都创建，由安装路径检查报告冲突
+    fn resolve_versions(
+        tasks: &mut [(PathBuf, DADKTask)],
+        default_versions: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (_, task) in tasks.iter() {
+            versions
+                .entry(task.name.clone())
+                .or_default()
+                .insert(task.version.clone());
+        }
+        for (name, version) in default_versions {
+            // 没有这个程序的任务时（例如配置目录中没有它）忽略
+            match versions.get(name) {
+                Some(available) if !available.contains(version) => {
+                    return Err(anyhow::anyhow!(
+                        "Default version {} of {} not found, available versions: {}",
+                        version,
+                        name,
+                        available.iter().cloned().collect::<Vec<_>>().join(", ")
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let selected = |name: &str| -> Option<String> {
+            default_versions.get(name).cloned().or_else(|| {
+                let available = versions.get(name)?;
+                (available.len() == 1).then(|| available.iter().next().unwrap().clone())
+            })
+        };
+
+        for (path, task) in tasks.iter_mut() {
+            task.default_version = selected(&task.name).map_or(true, |v| v == task.version);
+            let name_version = task.name_version();
+            for dep in task.depends.iter_mut().filter(|d| d.version.is_empty()) {
+                // 不存在的依赖项由调度器报告
+                let Some(available) = versions.get(&dep.name) else {
+                    continue;
+                };
+                dep.version = selected(&dep.name).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Task {} ({}) depends on {} without a version, but several versions are available ({}). \
+                        Specify the version of the dependency or set it in [metadata.default-versions]",
+                        name_version,
+                        path.display(),
+                        dep.name,
+                        available.iter().cloned().collect::<Vec<_>>().join(", ")
+                    )
+                })?;
+            }
+        }
+        Ok(())
     }
 
     /// # 解析单个配置文件，生成任务
@@ -218,8 +522,22 @@ impl Parser {
     /// * `Err(ParserError)` - 解析错误
     pub(super) fn parse_config_file(&self, config_file: &PathBuf) -> Result<DADKTask> {
         log::trace!("Parsing config file {}", config_file.display());
-        // 从toml文件中解析出DADKTask
-        let mut task: DADKTask = Self::parse_toml_file(config_file)?;
+        // 从toml文件中解析出DADKTask，并替换其中的变量
+        let dadk_user_config = UserConfigFile::load_with_vars(config_file, &self.variables)?;
+        let mut task = DADKTask::try_from(dadk_user_config)?;
+        if let Some(dir) = config_file.parent() {
+            task.resolve_patches(dir);
+        }
+        // 内核模块默认安装到`/lib/modules/<内核版本>/extra`
+        if task.kernel_module && task.install.in_dragonos_path.is_none() {
+            let version = self.variables.get(KERNEL_VERSION_VAR).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "kernel-module tasks need the kernel version, set [kernel] in the manifest"
+                )
+            })?;
+            task.install.in_dragonos_path =
+                Some(Path::new(KERNEL_MODULES_DIR).join(version).join("extra"));
+        }
 
         // 去除字符串中的空白字符
         task.trim();
@@ -1,15 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::executor::source::{ArchiveSource, GitSource, LocalSource};
+use crate::executor::source::{ArchiveSource, GitSource, LocalSource, RepositorySource};
 use dadk_config::{
     common::{
         target_arch::TargetArch,
         task::{
-            BuildConfig, CleanConfig, Dependency, InstallConfig, Source, TaskEnv, TaskSource,
-            TaskSourceType,
+            AutotoolsConfig, BuildConfig, CargoConfig, CleanConfig, CmakeConfig, Dependency,
+            HostPackage, InstallConfig, InstallFileConfig, Source, TaskEnv, TaskPriority,
+            TaskSource, TaskSourceType, TestConfig,
         },
     },
-    user::UserConfigFile,
+    user::{UserConfigFile, KERNEL_MODULES_DIR},
 };
 use serde::{Deserialize, Serialize};
 
@@ -37,12 +38,18 @@ pub struct DADKTask {
     pub task_type: TaskType,
     /// 依赖的包
     pub depends: Vec<Dependency>,
+    /// 构建时需要的主机软件包
+    #[serde(default)]
+    pub host_packages: Vec<HostPackage>,
     /// 构建配置
     pub build: BuildConfig,
     /// 安装配置
     pub install: InstallConfig,
     /// 清理配置
     pub clean: CleanConfig,
+    /// (可选) 测试配置
+    #[serde(default)]
+    pub test: Option<TestConfig>,
     /// 环境变量
     pub envs: Option<Vec<TaskEnv>>,
 
@@ -56,6 +63,60 @@ pub struct DADKTask {
 
     #[serde(default = "DADKTask::default_target_arch_vec")]
     pub target_arch: Vec<TargetArch>,
+
+    /// (可选) 拉取源文件失败时的重试次数，为None时使用全局默认值
+    #[serde(default)]
+    pub retries: Option<u32>,
+
+    /// (可选) 调度优先级
+    #[serde(default)]
+    pub priority: TaskPriority,
+
+    /// cargo任务的构建配置。为Some时，由DADK执行`cargo build`，而不是执行构建命令
+    #[serde(default)]
+    pub cargo: Option<CargoConfig>,
+
+    /// cmake任务的构建配置。为Some时，由DADK生成构建命令
+    #[serde(default)]
+    pub cmake: Option<CmakeConfig>,
+
+    /// autotools任务的构建配置。为Some时，由DADK生成构建命令
+    #[serde(default)]
+    pub autotools: Option<AutotoolsConfig>,
+
+    /// 是否从二进制包（`.dpk`文件）安装。为true时，预编译包源指向的是`dadk user package`生成的包
+    ///
+    /// 从软件仓库安装的任务总是从二进制包安装
+    #[serde(default)]
+    pub from_package: bool,
+
+    /// 是否为内核模块。为true时，构建结果安装到`/lib/modules/<内核版本>/`下，
+    /// 安装之后重新生成模块依赖索引
+    #[serde(default)]
+    pub kernel_module: bool,
+
+    /// 是否覆盖其他配置文件中同名同版本的任务
+    #[serde(default)]
+    pub overrides: bool,
+
+    /// 拉取源文件之后、构建之前应用到源码目录的补丁文件
+    #[serde(default)]
+    pub patches: Vec<PathBuf>,
+
+    /// 源码缓存的共享键。为Some时，键相同的任务共用同一个源码缓存目录
+    #[serde(default)]
+    pub source_cache_key: Option<String>,
+
+    /// 不使用manifest中配置的镜像，总是从原始URL拉取源文件
+    #[serde(default)]
+    pub no_mirror: bool,
+
+    /// 是否为同名任务的默认版本。同一个程序的多个版本同时存在时，
+    /// 只有默认版本创建`[[install.files]]`中不带版本号的符号链接
+    ///
+    /// 由[`Parser`](crate::parser::Parser)根据所有任务和`default-versions`设置
+    #[serde(default = "DADKTask::default_default_version")]
+    pub default_version: bool,
 }
 
 impl DADKTask {
@@ -80,16 +141,44 @@ impl DADKTask {
             description,
             task_type,
             depends,
+            host_packages: Vec::new(),
             build,
             install,
             clean,
+            test: None,
             envs,
             build_once,
             install_once,
             target_arch: target_arch.unwrap_or_else(Self::default_target_arch_vec),
+            retries: None,
+            priority: TaskPriority::default(),
+            cargo: None,
+            cmake: None,
+            autotools: None,
+            from_package: false,
+            kernel_module: false,
+            overrides: false,
+            patches: Vec::new(),
+            source_cache_key: None,
+            no_mirror: false,
+            default_version: true,
         }
     }
 
+    fn default_default_version() -> bool {
+        true
+    }
+
+    /// 安装时需要处理的`[[install.files]]`：不是默认版本的任务不创建符号链接
+    pub fn install_files(&self) -> Vec<InstallFileConfig> {
+        self.install
+            .files
+            .iter()
+            .filter(|f| self.default_version || f.symlink.is_none())
+            .cloned()
+            .collect()
+    }
+
     /// 默认的目标处理器架构
     ///
     /// 从环境变量`ARCH`中获取，如果没有设置，则默认为`x86_64`
@@ -110,25 +199,90 @@ impl DADKTask {
             return Err(anyhow::Error::msg("version is empty"));
         }
         self.task_type.validate()?;
+        if let Some(cargo) = &self.cargo {
+            cargo.validate()?;
+        }
+        if let Some(cmake) = &self.cmake {
+            cmake.validate()?;
+        }
         self.build.validate()?;
         self.validate_build_type()?;
         self.install.validate()?;
         self.clean.validate()?;
+        if let Some(test) = &self.test {
+            test.validate()?;
+        }
         self.validate_depends()?;
         self.validate_envs()?;
         self.validate_target_arch()?;
+        self.validate_patches()?;
+        self.validate_kernel_module()?;
 
         return Ok(());
     }
 
+    fn validate_kernel_module(&self) -> Result<()> {
+        if !self.kernel_module {
+            return Ok(());
+        }
+        match &self.install.in_dragonos_path {
+            Some(path) if path.starts_with(KERNEL_MODULES_DIR) => Ok(()),
+            _ => Err(anyhow::Error::msg(format!(
+                "in-dragonos-path of kernel-module tasks should be under {}",
+                KERNEL_MODULES_DIR
+            ))),
+        }
+    }
+
+    fn validate_patches(&self) -> Result<()> {
+        if self.patches.is_empty() {
+            return Ok(());
+        }
+        if !matches!(
+            self.task_type,
+            TaskType::BuildFromSource(CodeSource::Git(_) | CodeSource::Archive(_))
+        ) {
+            return Err(anyhow::Error::msg(
+                "patches are only available for git and archive sources built from source",
+            ));
+        }
+        for patch in &self.patches {
+            if !patch.is_file() {
+                return Err(anyhow::Error::msg(format!(
+                    "patch file not found: {}",
+                    patch.display()
+                )));
+            }
+        }
+        return Ok(());
+    }
+
+    /// 补丁文件的路径相对于配置文件所在的目录
+    pub fn resolve_patches(&mut self, config_dir: &Path) {
+        for patch in &mut self.patches {
+            if patch.is_relative() {
+                *patch = config_dir.join(&*patch);
+            }
+        }
+    }
+
     pub fn trim(&mut self) {
         self.name = self.name.trim().to_string();
         self.version = self.version.trim().to_string();
         self.description = self.description.trim().to_string();
         self.task_type.trim();
+        if let Some(cargo) = &mut self.cargo {
+            cargo.trim();
+        }
+        if let Some(cmake) = &mut self.cmake {
+            cmake.trim();
+        }
         self.build.trim();
         self.install.trim();
         self.clean.trim();
+        if let Some(test) = &mut self.test {
+            test.trim();
+        }
         self.trim_depends();
         self.trim_envs();
     }
@@ -137,6 +291,9 @@ impl DADKTask {
         for depend in &self.depends {
             depend.validate()?;
         }
+        for package in &self.host_packages {
+            package.validate()?;
+        }
         return Ok(());
     }
 
@@ -144,6 +301,9 @@ impl DADKTask {
         for depend in &mut self.depends {
             depend.trim();
         }
+        for package in &mut self.host_packages {
+            package.trim();
+        }
     }
 
     fn validate_envs(&self) -> Result<()> {
@@ -174,39 +334,93 @@ impl DADKTask {
     fn validate_build_type(&self) -> Result<()> {
         match &self.task_type {
             TaskType::BuildFromSource(_) => {
-                if self.build.build_command.is_none() {
+                if self.generates_build_command() {
+                    if self.build.build_command.is_some() {
+                        return Err(anyhow::Error::msg(
+                            "build command should be empty when DADK generates the build commands",
+                        ));
+                    }
+                } else if self.build.build_command.is_none() {
                     return Err(anyhow::Error::msg("build command is empty"));
                 }
             }
-            TaskType::InstallFromPrebuilt(_) => {
+            TaskType::InstallFromPrebuilt(source) => {
                 if self.build.build_command.is_some() {
                     return Err(anyhow::Error::msg(
                         "build command should be empty when install from prebuilt",
                     ));
                 }
+                if let (true, PrebuiltSource::Local(local)) = (self.from_package, source) {
+                    if !local.path().is_file() {
+                        return Err(anyhow::Error::msg(format!(
+                            "package {:?} is not a file",
+                            local.path()
+                        )));
+                    }
+                }
             }
         }
         return Ok(());
     }
 
+    /// 是否由DADK生成构建命令（cargo、cmake、autotools任务）
+    pub fn generates_build_command(&self) -> bool {
+        self.cargo.is_some() || self.cmake.is_some() || self.autotools.is_some()
+    }
+
+    /// 任务类型在配置文件中的名称
+    pub fn task_type_name(&self) -> &'static str {
+        let source_type = match &self.task_type {
+            TaskType::InstallFromPrebuilt(_) if self.from_package => TaskSourceType::Package,
+            TaskType::InstallFromPrebuilt(_) => TaskSourceType::InstallFromPrebuilt,
+            TaskType::BuildFromSource(_) if self.cargo.is_some() => TaskSourceType::Cargo,
+            TaskType::BuildFromSource(_) if self.cmake.is_some() => TaskSourceType::Cmake,
+            TaskType::BuildFromSource(_) if self.autotools.is_some() => TaskSourceType::Autotools,
+            TaskType::BuildFromSource(_) if self.kernel_module => TaskSourceType::KernelModule,
+            TaskType::BuildFromSource(_) => TaskSourceType::BuildFromSource,
+        };
+        source_type.name()
+    }
+
     pub fn name_version(&self) -> String {
-        let mut name_version = format!("{}-{}", self.name, self.version);
+        Self::name_version_of(&self.name, &self.version)
+    }
+
+    /// 名称为`name`、版本为`version`的任务的`name_version`（例如依赖的任务）
+    pub fn name_version_of(name: &str, version: &str) -> String {
+        let mut name_version = format!("{}-{}", name, version);
         for (src, dst) in &NAME_VERSION_REPLACE_TABLE {
             name_version = name_version.replace(src, dst);
         }
         return name_version;
     }
 
+    /// # 源码缓存目录名
+    ///
+    /// 设置了`source-cache-key`的任务共用`shared-<key>`目录，否则为任务的`name_version`。
+    /// `name_version`中的`-`已被替换，因此两者不会重名
+    pub fn source_cache_name(&self) -> String {
+        match &self.source_cache_key {
+            Some(key) => format!("shared-{}", key),
+            None => self.name_version(),
+        }
+    }
+
     pub fn name_version_env(&self) -> String {
         return Self::name_version_uppercase(&self.name, &self.version);
     }
 
     pub fn name_version_uppercase(name: &str, version: &str) -> String {
-        let mut name_version = format!("{}-{}", name, version).to_ascii_uppercase();
+        Self::env_name(&format!("{}-{}", name, version))
+    }
+
+    /// 把任务名称等字符串转换为可以用在环境变量名称中的形式
+    pub fn env_name(s: &str) -> String {
+        let mut name = s.to_ascii_uppercase();
         for (src, dst) in &NAME_VERSION_REPLACE_TABLE {
-            name_version = name_version.replace(src, dst);
+            name = name.replace(src, dst);
         }
-        return name_version;
+        name
     }
 
     /// # 获取源码目录
@@ -238,19 +452,51 @@ impl TryFrom<UserConfigFile> for DADKTask {
     type Error = anyhow::Error;
 
     fn try_from(user_config: UserConfigFile) -> Result<Self> {
+        let source_type = &user_config.task_source.source_type.clone();
+        let cargo =
+            (*source_type == TaskSourceType::Cargo).then(|| user_config.cargo.unwrap_or_default());
+        let cmake =
+            (*source_type == TaskSourceType::Cmake).then(|| user_config.cmake.unwrap_or_default());
+        let autotools = (*source_type == TaskSourceType::Autotools)
+            .then(|| user_config.autotools.unwrap_or_default());
+        // 软件仓库中的包都是二进制包
+        let from_package = *source_type == TaskSourceType::Package
+            || user_config.task_source.source == Source::Repository;
+        let kernel_module = *source_type == TaskSourceType::KernelModule;
+        let patches = user_config.task_source.patches.clone();
+        let source_cache_key = user_config.task_source.source_cache_key.clone();
+        let no_mirror = user_config.task_source.no_mirror;
+        let mut task_type = TaskType::try_from(user_config.task_source)?;
+        if let TaskType::InstallFromPrebuilt(PrebuiltSource::Repository(repo)) = &mut task_type {
+            repo.set_default_name(&user_config.name);
+        }
         Ok(DADKTask {
             name: user_config.name,
             version: user_config.version,
             description: user_config.description,
-            task_type: TaskType::try_from(user_config.task_source)?,
+            task_type,
             depends: user_config.depends,
+            host_packages: user_config.host_packages,
             build: user_config.build,
             install: user_config.install,
             clean: user_config.clean,
+            test: user_config.test,
             envs: Some(user_config.envs),
             build_once: user_config.build_once,
             install_once: user_config.install_once,
             target_arch: user_config.target_arch,
+            retries: user_config.retries,
+            priority: user_config.priority,
+            cargo,
+            cmake,
+            autotools,
+            from_package,
+            kernel_module,
+            overrides: user_config.overrides,
+            patches,
+            source_cache_key,
+            no_mirror,
+            default_version: true,
         })
     }
 }
@@ -284,7 +530,12 @@ impl TryFrom<TaskSource> for TaskType {
     type Error = anyhow::Error;
     fn try_from(task_source: TaskSource) -> Result<Self> {
         match task_source.source_type {
-            TaskSourceType::BuildFromSource => match task_source.source {
+            // cargo、cmake、autotools任务以及内核模块与从源码构建的任务使用相同的源文件，只是构建、安装的方式不同
+            TaskSourceType::BuildFromSource
+            | TaskSourceType::Cargo
+            | TaskSourceType::Cmake
+            | TaskSourceType::Autotools
+            | TaskSourceType::KernelModule => match task_source.source {
                 Source::Git => Ok(TaskType::BuildFromSource(CodeSource::Git(GitSource::new(
                     task_source.source_path,
                     task_source.branch,
@@ -296,18 +547,28 @@ impl TryFrom<TaskSource> for TaskType {
                 Source::Archive => Ok(TaskType::BuildFromSource(CodeSource::Archive(
                     ArchiveSource::new(task_source.source_path),
                 ))),
-            },
-            TaskSourceType::InstallFromPrebuilt => match task_source.source {
-                Source::Git => Err(anyhow::Error::msg(
-                    "InstallFromPrebuild doesn't support Git",
-                )),
-                Source::Local => Ok(TaskType::InstallFromPrebuilt(PrebuiltSource::Local(
-                    LocalSource::new(PathBuf::from(task_source.source_path)),
-                ))),
-                Source::Archive => Ok(TaskType::InstallFromPrebuilt(PrebuiltSource::Archive(
-                    ArchiveSource::new(task_source.source_path),
+                Source::Repository => Err(anyhow::Error::msg(format!(
+                    "{} doesn't support repository source",
+                    task_source.source_type.name()
                 ))),
             },
+            // 二进制包与预编译包一样，来自本地文件或者在线文件
+            TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package => {
+                match task_source.source {
+                    Source::Git => Err(anyhow::Error::msg(
+                        "InstallFromPrebuild doesn't support Git",
+                    )),
+                    Source::Local => Ok(TaskType::InstallFromPrebuilt(PrebuiltSource::Local(
+                        LocalSource::new(PathBuf::from(task_source.source_path)),
+                    ))),
+                    Source::Archive => Ok(TaskType::InstallFromPrebuilt(PrebuiltSource::Archive(
+                        ArchiveSource::new(task_source.source_path),
+                    ))),
+                    Source::Repository => Ok(TaskType::InstallFromPrebuilt(
+                        PrebuiltSource::Repository(RepositorySource::new(task_source.source_path)),
+                    )),
+                }
+            }
         }
     }
 }
@@ -347,6 +608,8 @@ pub enum PrebuiltSource {
     Archive(ArchiveSource),
     /// 从本地目录/文件获取
     Local(LocalSource),
+    /// 从软件仓库获取二进制包
+    Repository(RepositorySource),
 }
 
 impl PrebuiltSource {
@@ -354,6 +617,8 @@ impl PrebuiltSource {
         match self {
             PrebuiltSource::Archive(source) => source.validate(),
             PrebuiltSource::Local(source) => source.validate(None),
+            // 软件仓库的URL在dadk-manifest.toml中配置，执行任务时才会检查
+            PrebuiltSource::Repository(_) => Ok(()),
         }
     }
 
@@ -361,6 +626,7 @@ impl PrebuiltSource {
         match self {
             PrebuiltSource::Archive(source) => source.trim(),
             PrebuiltSource::Local(source) => source.trim(),
+            PrebuiltSource::Repository(source) => source.trim(),
         }
     }
 }
@@ -2,6 +2,8 @@
 //!
 //! DADK在执行任务时，会把一些日志记录到任务的文件夹下。
 
+use std::{path::PathBuf, time::Duration};
+
 use chrono::{DateTime, Utc};
 use log::warn;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -11,6 +13,7 @@ use serde::{Deserialize, Deserializer, Serialize};
 pub struct TaskLog {
     /// 任务执行完成时间
     #[serde(
+        default,
         deserialize_with = "ok_or_default",
         skip_serializing_if = "Option::is_none"
     )]
@@ -20,6 +23,38 @@ pub struct TaskLog {
     build_status: Option<BuildStatus>,
     /// 任务安装状态
     install_status: Option<InstallStatus>,
+    /// 最近一次实际执行构建所花费的时间（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build_duration_ms: Option<u64>,
+    /// 最近一次实际执行安装所花费的时间（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    install_duration_ms: Option<u64>,
+    /// 最近一次实际执行构建的那一批构建的开始时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build_session: Option<DateTime<Utc>>,
+    /// 任务名称。缓存目录名中的特殊字符已被替换，无法从目录名还原任务名称和版本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    task_name: Option<String>,
+    /// 任务版本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    task_version: Option<String>,
+    /// 最近一次实际执行构建的原因
+    #[serde(
+        default,
+        deserialize_with = "ok_or_default",
+        skip_serializing_if = "Option::is_none"
+    )]
+    build_reason: Option<RebuildReason>,
+    /// 构建原因的详细说明，例如被修改的文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build_reason_detail: Option<String>,
+    /// 最近一次成功构建的来源信息
+    #[serde(
+        default,
+        deserialize_with = "ok_or_default",
+        skip_serializing_if = "Option::is_none"
+    )]
+    provenance: Option<Provenance>,
 }
 
 fn ok_or_default<'a, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -39,6 +74,14 @@ impl TaskLog {
             build_status: None,
             install_timestamp: None,
             install_status: None,
+            build_duration_ms: None,
+            install_duration_ms: None,
+            build_session: None,
+            task_name: None,
+            task_version: None,
+            build_reason: None,
+            build_reason_detail: None,
+            provenance: None,
         }
     }
 
@@ -55,6 +98,11 @@ impl TaskLog {
         self.build_timestamp = Some(Utc::now());
     }
 
+    #[allow(dead_code)]
+    pub fn set_install_time(&mut self, time: DateTime<Utc>) {
+        self.install_timestamp = Some(time);
+    }
+
     pub fn install_time(&self) -> Option<&DateTime<Utc>> {
         self.install_timestamp.as_ref()
     }
@@ -86,6 +134,217 @@ impl TaskLog {
     pub fn clean_install_status(&mut self) {
         self.install_status = None;
     }
+
+    pub fn build_duration(&self) -> Option<Duration> {
+        self.build_duration_ms.map(Duration::from_millis)
+    }
+
+    /// 记录一次实际执行的构建所花费的时间，以及它所属的那一批构建
+    pub fn set_build_duration(&mut self, duration: Duration, session: DateTime<Utc>) {
+        self.build_duration_ms = Some(duration.as_millis() as u64);
+        self.build_session = Some(session);
+    }
+
+    pub fn build_session(&self) -> Option<&DateTime<Utc>> {
+        self.build_session.as_ref()
+    }
+
+    pub fn install_duration(&self) -> Option<Duration> {
+        self.install_duration_ms.map(Duration::from_millis)
+    }
+
+    pub fn set_install_duration(&mut self, duration: Duration) {
+        self.install_duration_ms = Some(duration.as_millis() as u64);
+    }
+
+    pub fn set_task(&mut self, name: &str, version: &str) {
+        self.task_name = Some(name.to_string());
+        self.task_version = Some(version.to_string());
+    }
+
+    pub fn task_name(&self) -> Option<&str> {
+        self.task_name.as_deref()
+    }
+
+    pub fn task_version(&self) -> Option<&str> {
+        self.task_version.as_deref()
+    }
+
+    pub fn clean_durations(&mut self) {
+        self.build_duration_ms = None;
+        self.install_duration_ms = None;
+        self.build_session = None;
+        self.build_reason = None;
+        self.build_reason_detail = None;
+        self.provenance = None;
+    }
+
+    /// 记录最近一次实际执行构建的原因
+    pub fn set_build_reason(&mut self, reason: RebuildReason, detail: &str) {
+        self.build_reason = Some(reason);
+        self.build_reason_detail = Some(detail.to_string());
+    }
+
+    pub fn build_reason(&self) -> Option<RebuildReason> {
+        self.build_reason
+    }
+
+    pub fn build_reason_detail(&self) -> Option<&str> {
+        self.build_reason_detail.as_deref()
+    }
+
+    /// 记录实际执行的构建的来源信息（构建失败时为None）
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) {
+        self.provenance = provenance;
+    }
+
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+}
+
+/// # 构建结果的来源信息
+///
+/// 记录构建使用的源文件的确切版本、执行构建的主机以及DADK的版本，
+/// 用于把发布的DragonOS镜像追溯到确切的输入
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// 源文件的版本
+    pub source: SourceRevision,
+    /// 构建前应用的补丁文件及其sha256
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<PatchDigest>,
+    /// 执行构建的主机
+    pub builder: BuilderInfo,
+    /// 执行构建的DADK的版本
+    pub dadk_version: String,
+}
+
+/// # 源文件的版本
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SourceRevision {
+    /// git仓库中检出的提交
+    Git { url: String, commit: String },
+    /// 在线压缩包（二进制包下载后不会保留，没有sha256）
+    Archive {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// 本地目录或文件。位于git仓库中时，记录仓库检出的提交，以及目录中是否有未提交的修改
+    Local {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        commit: Option<String>,
+        #[serde(default)]
+        dirty: bool,
+    },
+    /// 软件仓库中的二进制包
+    Repository {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+}
+
+impl std::fmt::Display for SourceRevision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceRevision::Git { url, commit } => write!(f, "git {} @ {}", url, commit),
+            SourceRevision::Archive { url, sha256 } => {
+                write!(f, "archive {}", url)?;
+                match sha256 {
+                    Some(sha256) => write!(f, " (sha256 {})", sha256),
+                    None => Ok(()),
+                }
+            }
+            SourceRevision::Local {
+                path,
+                commit,
+                dirty,
+            } => {
+                write!(f, "local {}", path.display())?;
+                if let Some(commit) = commit {
+                    write!(f, " @ {}{}", commit, if *dirty { " (dirty)" } else { "" })?;
+                }
+                Ok(())
+            }
+            SourceRevision::Repository { name, sha256 } => {
+                write!(f, "repository {}", name)?;
+                match sha256 {
+                    Some(sha256) => write!(f, " (sha256 {})", sha256),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// 补丁文件及其sha256
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchDigest {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// # 执行构建的主机
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuilderInfo {
+    pub hostname: String,
+    /// 操作系统，例如`linux`
+    pub os: String,
+    /// 主机的CPU架构，例如`x86_64`
+    pub arch: String,
+    /// 内核版本（`uname -r`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    /// 在容器中执行构建命令时，使用的镜像
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_image: Option<String>,
+}
+
+/// # 实际执行构建（而不是跳过构建）的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RebuildReason {
+    /// 任务从未构建过
+    FirstBuild,
+    /// 上一次构建失败
+    LastBuildFailed,
+    /// 上一次构建被中断
+    Interrupted,
+    /// 禁用了构建缓存
+    CacheDisabled,
+    /// 通过`--force`或`--rebuild`要求重新构建
+    Forced,
+    /// 任务的配置文件被修改
+    ConfigChanged,
+    /// 源码或补丁文件被修改
+    SourceChanged,
+    /// 依赖的任务在上一次构建之后被重新构建
+    DependencyRebuilt,
+}
+
+impl RebuildReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RebuildReason::FirstBuild => "first-build",
+            RebuildReason::LastBuildFailed => "last-build-failed",
+            RebuildReason::Interrupted => "interrupted",
+            RebuildReason::CacheDisabled => "cache-disabled",
+            RebuildReason::Forced => "forced",
+            RebuildReason::ConfigChanged => "config-changed",
+            RebuildReason::SourceChanged => "source-changed",
+            RebuildReason::DependencyRebuilt => "dependency-rebuilt",
+        }
+    }
+}
+
+impl std::fmt::Display for RebuildReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 /// 任务构建状态
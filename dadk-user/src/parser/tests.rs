@@ -0,0 +1,560 @@
+use dadk_config::app_blocklist::AppListMode;
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::executor::cache::{CacheDir, CacheDirType};
+
+fn parse_tasks(ctx: &BaseGlobalTestContext) -> Vec<(PathBuf, DADKTask)> {
+    let parser = Parser::new(ctx.config_v2_dir());
+    [
+        "app_normal_with_env_0_2_0.toml",
+        "app_all_target_arch_0_2_0.toml",
+    ]
+    .iter()
+    .map(|name| {
+        let path = ctx.config_v2_dir().join(name);
+        let task = parser.parse_config_file(&path).unwrap();
+        (path, task)
+    })
+    .collect()
+}
+
+fn names(tasks: &[(PathBuf, DADKTask)]) -> Vec<&str> {
+    tasks.iter().map(|(_, t)| t.name.as_str()).collect()
+}
+
+/// 测试黑名单条目只在指定的架构上生效
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn filter_blocked_apps_per_arch(ctx: &BaseGlobalTestContext) {
+    let blocklist = AppBlocklistConfigFile::load_from_str(
+        r#"
+        [[blocked_apps]]
+        name = "app_all_target_arch"
+        target-arch = ["riscv64"]
+        "#,
+    )
+    .unwrap();
+
+    let tasks = Parser::filter_blocked_apps(parse_tasks(ctx), &blocklist, TargetArch::X86_64);
+    assert_eq!(
+        names(&tasks),
+        vec!["app_normal_with_env", "app_all_target_arch"]
+    );
+    let tasks = Parser::filter_blocked_apps(parse_tasks(ctx), &blocklist, TargetArch::RiscV64);
+    assert_eq!(names(&tasks), vec!["app_normal_with_env"]);
+}
+
+/// 测试白名单模式只保留列出的任务
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn filter_blocked_apps_allowlist(ctx: &BaseGlobalTestContext) {
+    let mut allowlist = AppBlocklistConfigFile::load_from_str(
+        r#"
+        mode = "allowlist"
+
+        [[blocked_apps]]
+        name = "app_all_target_arch"
+        "#,
+    )
+    .unwrap();
+    let tasks = Parser::filter_blocked_apps(parse_tasks(ctx), &allowlist, TargetArch::X86_64);
+    assert_eq!(names(&tasks), vec!["app_all_target_arch"]);
+
+    allowlist.mode = AppListMode::Blocklist;
+    let tasks = Parser::filter_blocked_apps(parse_tasks(ctx), &allowlist, TargetArch::X86_64);
+    assert_eq!(names(&tasks), vec!["app_normal_with_env"]);
+}
+
+/// 测试跳过无法解析的配置文件
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn skip_invalid_configs(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_dir = tmp.path().to_path_buf();
+    std::fs::copy(
+        ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"),
+        config_dir.join("app_normal_with_env_0_2_0.toml"),
+    )
+    .unwrap();
+    let broken = config_dir.join("broken.toml");
+    std::fs::write(&broken, "name = \"broken\"\nversion = ").unwrap();
+
+    let err = Parser::new(config_dir.clone()).parse().unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("broken.toml"),
+        "unexpected error: {:?}",
+        err
+    );
+
+    let mut parser = Parser::new(config_dir.clone()).skip_invalid_configs(true);
+    let tasks = parser.parse().unwrap();
+    assert_eq!(names(&tasks), vec!["app_normal_with_env"]);
+    assert_eq!(parser.invalid_configs().len(), 1);
+    assert_eq!(parser.invalid_configs()[0].0, broken);
+}
+
+/// 测试设置了相同`source-cache-key`的任务共用源码缓存目录
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn shared_source_cache_key(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap()
+            .replace(
+                "source = \"local\"\nsource-path = \"tests/data/apps/app_normal_with_env\"",
+                "source = \"archive\"\nsource-path = \"https://example.com/busybox.tar.gz\"\n\
+                source-cache-key = \"busybox\"",
+            );
+    std::fs::write(dir.join("a.toml"), &content).unwrap();
+    std::fs::write(
+        dir.join("b.toml"),
+        content.replace(
+            "name = \"app_normal_with_env\"",
+            "name = \"app_normal_with_env_static\"",
+        ),
+    )
+    .unwrap();
+
+    let tasks = Parser::new(dir.clone()).parse().unwrap();
+    assert_eq!(tasks.len(), 2);
+    let cache_root = PathBuf::from("/tmp/dadk_cache");
+    let source_dirs: Vec<PathBuf> = tasks
+        .iter()
+        .map(|(_, t)| CacheDir::get_path(&cache_root, t, CacheDirType::Source))
+        .collect();
+    assert_eq!(source_dirs[0], cache_root.join("source/shared-busybox"));
+    assert_eq!(source_dirs[0], source_dirs[1]);
+    // 其他缓存目录不共用
+    assert_ne!(
+        CacheDir::get_path(&cache_root, &tasks[0].1, CacheDirType::Build),
+        CacheDir::get_path(&cache_root, &tasks[1].1, CacheDirType::Build)
+    );
+
+    // 共用源码缓存目录的任务的源码配置必须相同
+    std::fs::write(
+        dir.join("b.toml"),
+        content
+            .replace(
+                "name = \"app_normal_with_env\"",
+                "name = \"app_normal_with_env_static\"",
+            )
+            .replace("busybox.tar.gz", "busybox-1.37.tar.gz"),
+    )
+    .unwrap();
+    let err = Parser::new(dir.clone()).parse().unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("share source-cache-key 'busybox'"),
+        "{:?}",
+        err
+    );
+}
+
+/// 测试同一个程序的多个版本：选择默认版本，以及省略版本号的依赖项
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn default_versions(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap();
+    std::fs::write(dir.join("app-0.2.0.toml"), &content).unwrap();
+    let consumer = format!(
+        "{}\n[[depends]]\nname = \"app_normal_with_env\"\n",
+        content.replace("name = \"app_normal_with_env\"", "name = \"app_consumer\"")
+    );
+    std::fs::write(dir.join("consumer.toml"), consumer).unwrap();
+    let dep_version = |tasks: &[(PathBuf, DADKTask)]| {
+        let (_, consumer) = tasks
+            .iter()
+            .find(|(_, t)| t.name == "app_consumer")
+            .unwrap();
+        consumer.depends[0].version.clone()
+    };
+
+    // 只有一个版本时依赖这个版本
+    let tasks = Parser::new(dir.clone()).parse().unwrap();
+    assert_eq!(dep_version(&tasks), "0.2.0");
+    assert!(tasks.iter().all(|(_, t)| t.default_version));
+
+    // 有多个版本但没有默认版本时，省略版本号的依赖项报错
+    std::fs::write(
+        dir.join("app-0.3.0.toml"),
+        content.replace("version = \"0.2.0\"", "version = \"0.3.0\""),
+    )
+    .unwrap();
+    let err = Parser::new(dir.clone()).parse().unwrap_err();
+    let msg = format!("{:?}", err);
+    assert!(
+        msg.contains("several versions are available (0.2.0, 0.3.0)"),
+        "{}",
+        msg
+    );
+
+    let tasks = Parser::new(dir.clone())
+        .default_versions(BTreeMap::from([(
+            "app_normal_with_env".to_string(),
+            "0.3.0".to_string(),
+        )]))
+        .parse()
+        .unwrap();
+    assert_eq!(dep_version(&tasks), "0.3.0");
+    let defaults: BTreeMap<String, bool> = tasks
+        .iter()
+        .map(|(_, t)| (t.name_version(), t.default_version))
+        .collect();
+    assert!(!defaults["app_normal_with_env_0_2_0"]);
+    assert!(defaults["app_normal_with_env_0_3_0"]);
+    assert!(defaults["app_consumer_0_2_0"]);
+
+    // 默认版本必须存在
+    let err = Parser::new(dir.clone())
+        .default_versions(BTreeMap::from([(
+            "app_normal_with_env".to_string(),
+            "0.4.0".to_string(),
+        )]))
+        .parse()
+        .unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("Default version 0.4.0 of app_normal_with_env not found"),
+        "{:?}",
+        err
+    );
+}
+
+/// 测试内核模块默认安装到`/lib/modules/<内核版本>/extra`
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn kernel_module_install_path(ctx: &BaseGlobalTestContext) {
+    use dadk_config::manifest::KernelConfig;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap()
+            .replace("type = \"build-from-source\"", "type = \"kernel-module\"");
+    let path = dir.join("kmod.toml");
+    std::fs::write(&path, content.replace("in-dragonos-path = \"/\"\n", "")).unwrap();
+
+    let kernel = KernelConfig {
+        source_dir: PathBuf::from("kernel"),
+        version: "0.1.10".to_string(),
+        depmod: String::new(),
+    };
+    let vars = Parser::config_variables(
+        &BTreeMap::new(),
+        TargetArch::X86_64,
+        Path::new("/tmp/dadk"),
+        Some(&kernel),
+    );
+    let task = Parser::new(dir.clone())
+        .variables(vars.clone())
+        .parse_config_file(&path)
+        .unwrap();
+    assert!(task.kernel_module);
+    assert_eq!(task.task_type_name(), "kernel-module");
+    assert_eq!(
+        task.install.in_dragonos_path,
+        Some(PathBuf::from("/lib/modules/0.1.10/extra"))
+    );
+
+    // 没有设置[kernel]时无法确定安装目录
+    let err = Parser::new(dir.clone())
+        .parse_config_file(&path)
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("set [kernel]"), "{:?}", err);
+
+    // 安装目录必须在/lib/modules下
+    std::fs::write(&path, &content).unwrap();
+    assert!(Parser::new(dir.clone())
+        .variables(vars)
+        .parse_config_file(&path)
+        .is_err());
+}
+
+/// 测试额外的配置目录中设置了`override`的任务覆盖同名同版本的任务
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn overlay_dirs_override(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let (upstream, overlay, overlay2) = (root.join("upstream"), root.join("a"), root.join("b"));
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap();
+    let overridden = format!("override = true\n{}", content)
+        .replace("\"bash build.sh\"", "\"make install-local\"");
+    for (dir, content) in [
+        (&upstream, &content),
+        (&overlay, &overridden),
+        (&overlay2, &overridden),
+    ] {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("app.toml"), content).unwrap();
+    }
+
+    // 同一个配置目录中的重复任务由调度器报告冲突
+    let dup = root.join("dup");
+    std::fs::create_dir_all(&dup).unwrap();
+    std::fs::write(dup.join("app.toml"), &content).unwrap();
+    std::fs::write(dup.join("app2.toml"), &content).unwrap();
+    let tasks = Parser::new(dup.clone()).parse().unwrap();
+    assert_eq!(tasks.len(), 2);
+
+    // override的优先级高于配置目录的顺序
+    let tasks = Parser::new(overlay.clone())
+        .overlay_dirs(vec![upstream.clone()])
+        .parse()
+        .unwrap();
+    assert_eq!(names(&tasks), vec!["app_normal_with_env"]);
+    assert_eq!(tasks[0].0, overlay.join("app.toml"));
+
+    let tasks = Parser::new(upstream.clone())
+        .overlay_dirs(vec![overlay.clone()])
+        .parse()
+        .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].0, overlay.join("app.toml"));
+    assert!(tasks[0].1.overrides);
+    assert_eq!(
+        tasks[0].1.build.build_command.as_deref(),
+        Some("make install-local")
+    );
+
+    // 同一个任务只能被覆盖一次
+    let err = Parser::new(upstream.clone())
+        .overlay_dirs(vec![overlay.clone(), overlay2.clone()])
+        .parse()
+        .unwrap_err();
+    let msg = format!("{:?}", err);
+    assert!(
+        msg.contains(&overlay.join("app.toml").display().to_string()),
+        "{}",
+        msg
+    );
+    assert!(
+        msg.contains(&overlay2.join("app.toml").display().to_string()),
+        "{}",
+        msg
+    );
+}
+
+/// 测试后面的配置目录中的任务覆盖前面的配置目录中同名同版本的任务
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn overlay_dirs_layering(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let (base, board) = (root.join("base"), root.join("board"));
+    std::fs::create_dir_all(&base).unwrap();
+    std::fs::create_dir_all(&board).unwrap();
+    for name in [
+        "app_normal_with_env_0_2_0.toml",
+        "app_all_target_arch_0_2_0.toml",
+    ] {
+        std::fs::copy(ctx.config_v2_dir().join(name), base.join(name)).unwrap();
+    }
+    let board_config =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap()
+            .replace("\"bash build.sh\"", "\"bash build-board.sh\"");
+    std::fs::write(board.join("app.toml"), board_config).unwrap();
+
+    let mut tasks = Parser::new(base.clone())
+        .overlay_dirs(vec![board.clone()])
+        .parse()
+        .unwrap();
+    tasks.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    assert_eq!(
+        names(&tasks),
+        vec!["app_all_target_arch", "app_normal_with_env"]
+    );
+    assert_eq!(tasks[1].0, board.join("app.toml"));
+    assert_eq!(
+        tasks[1].1.build.build_command.as_deref(),
+        Some("bash build-board.sh")
+    );
+}
+
+/// 测试解析结果的缓存：配置文件或者变量变化后重新解析
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn parse_cache(ctx: &BaseGlobalTestContext) {
+    use super::cache::{ParseCache, PARSE_CACHE_FILE};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let cache_root = root.join("cache");
+    let parse = |dir: PathBuf, cache: bool| {
+        let mut parser = Parser::new(dir).skip_invalid_configs(true);
+        if cache {
+            parser = parser.cache_dir(&cache_root);
+        }
+        let mut tasks = parser.parse().unwrap();
+        tasks.sort_by(|a, b| a.0.cmp(&b.0));
+        format!("{:?}", tasks)
+    };
+    // 缓存中的任务与重新解析得到的任务相同
+    let expected = parse(ctx.config_v2_dir(), false);
+    assert_eq!(parse(ctx.config_v2_dir(), true), expected);
+    assert!(cache_root.join(PARSE_CACHE_FILE).exists());
+    assert_eq!(parse(ctx.config_v2_dir(), true), expected);
+
+    let config_dir = root.join("config");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let config = config_dir.join("app.toml");
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap();
+    std::fs::write(&config, &content).unwrap();
+    let tasks = Parser::new(config_dir.clone())
+        .cache_dir(&cache_root)
+        .parse()
+        .unwrap();
+    assert_eq!(tasks.len(), 1);
+    let cache_file = cache_root.join(PARSE_CACHE_FILE);
+    assert!(ParseCache::load(&cache_file, &BTreeMap::new())
+        .get(&config)
+        .is_some());
+    // 只保留本次解析的配置文件
+    assert!(ParseCache::load(&cache_file, &BTreeMap::new())
+        .get(&ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+        .is_none());
+    // 变量变化后缓存失效
+    let variables = BTreeMap::from([("A".to_string(), "1".to_string())]);
+    assert!(ParseCache::load(&cache_file, &variables)
+        .get(&config)
+        .is_none());
+
+    // 配置文件被修改后重新解析
+    std::fs::write(
+        &config,
+        content.replace("\"bash build.sh\"", "\"bash build-new.sh\""),
+    )
+    .unwrap();
+    assert!(ParseCache::load(&cache_file, &BTreeMap::new())
+        .get(&config)
+        .is_none());
+    let tasks = Parser::new(config_dir)
+        .cache_dir(&cache_root)
+        .parse()
+        .unwrap();
+    assert_eq!(
+        tasks[0].1.build.build_command.as_deref(),
+        Some("bash build-new.sh")
+    );
+}
+
+/// 测试补丁文件的路径相对于配置文件所在的目录
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn patches_relative_to_config_file(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("patches")).unwrap();
+    let content =
+        std::fs::read_to_string(ctx.config_v2_dir().join("app_normal_with_env_0_2_0.toml"))
+            .unwrap()
+            .replace(
+                "source = \"local\"\nsource-path = \"tests/data/apps/app_normal_with_env\"",
+                "source = \"archive\"\nsource-path = \"https://example.com/app.tar.gz\"\n\
+                patches = [\"patches/0001-fix.patch\"]",
+            );
+    let config_file = dir.join("app.toml");
+    std::fs::write(&config_file, content).unwrap();
+
+    // 补丁文件不存在
+    let parser = Parser::new(dir.clone());
+    assert!(parser.parse_config_file(&config_file).is_err());
+
+    std::fs::write(dir.join("patches/0001-fix.patch"), "").unwrap();
+    let task = parser.parse_config_file(&config_file).unwrap();
+    assert_eq!(task.patches, vec![dir.join("patches/0001-fix.patch")]);
+
+    // 本地源码目录不支持补丁
+    let local = std::fs::read_to_string(&config_file)
+        .unwrap()
+        .replace("source = \"archive\"", "source = \"local\"")
+        .replace(
+            "https://example.com/app.tar.gz",
+            &ctx.abs_path("tests/data/apps/app_normal_with_env")
+                .display()
+                .to_string(),
+        );
+    std::fs::write(&config_file, local).unwrap();
+    let err = parser.parse_config_file(&config_file).unwrap_err();
+    assert!(format!("{:?}", err).contains("patches"), "{:?}", err);
+}
+
+/// 测试内置变量覆盖manifest中的同名变量
+#[test]
+fn config_variables_builtin() {
+    let user = BTreeMap::from([
+        ("ARCH".to_string(), "mips".to_string()),
+        (
+            "MIRROR".to_string(),
+            "https://mirrors.example.com".to_string(),
+        ),
+    ]);
+    let vars = Parser::config_variables(&user, TargetArch::RiscV64, Path::new("/tmp/dadk"), None);
+    assert_eq!(vars["ARCH"], "riscv64");
+    assert_eq!(vars["DADK_CACHE_ROOT"], "/tmp/dadk");
+    assert_eq!(vars["MIRROR"], "https://mirrors.example.com");
+}
+
+/// 测试从软件仓库安装的任务：包名默认与任务名相同，并且总是从二进制包安装
+#[test]
+fn repository_task_source() {
+    let config = |source_path: &str| {
+        UserConfigFile::load_from_str(&format!(
+            r#"
+            name = "app_repo"
+            version = "0.1.0"
+            description = ""
+            target-arch = ["x86_64"]
+
+            [task-source]
+            type = "install-from-prebuilt"
+            source = "repository"
+            {}
+
+            [build]
+            [install]
+            in-dragonos-path = "/bin"
+            [clean]
+            "#,
+            source_path
+        ))
+        .unwrap()
+    };
+    let package_name = |task: &DADKTask| match &task.task_type {
+        task::TaskType::InstallFromPrebuilt(task::PrebuiltSource::Repository(repo)) => {
+            repo.name().to_string()
+        }
+        other => panic!("unexpected task type {:?}", other),
+    };
+
+    let task = DADKTask::try_from(config("")).unwrap();
+    assert!(task.from_package);
+    assert_eq!(package_name(&task), "app_repo");
+    assert_eq!(task.task_type_name(), "package");
+
+    let task = DADKTask::try_from(config("source-path = \"app\"")).unwrap();
+    assert_eq!(package_name(&task), "app");
+}
+
+/// 清理后保存的任务日志中只有任务名称和版本，可以再次读取
+#[test]
+fn task_log_without_build_time() {
+    let log: task_log::TaskLog =
+        toml::from_str("task_name = \"app\"\ntask_version = \"0.1.0\"\n").unwrap();
+    assert_eq!(log.task_name(), Some("app"));
+    assert!(log.build_time().is_none());
+}
@@ -0,0 +1,335 @@
+//! # sysroot中的软件包数据库
+//!
+//! 每次安装任务后，DADK会在sysroot的`var/lib/dadk/installed.toml`中记录
+//! 任务的名称、版本以及安装的文件，用于查询sysroot中安装了哪些任务、某个文件属于哪个任务，
+//! 以及只删除sysroot中某个任务安装的文件（`dadk rootfs delete-sysroot --installed-by`）。
+
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+/// 数据库文件相对于sysroot的路径
+pub const PKGDB_PATH: &str = "var/lib/dadk/installed.toml";
+
+/// 多个任务同时安装时，串行地更新数据库
+static PKGDB_LOCK: Mutex<()> = Mutex::new(());
+
+/// # 已安装的任务
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    /// 安装时间
+    pub install_time: DateTime<Utc>,
+    /// 安装的文件（包括符号链接），为sysroot中以`/`开头的绝对路径
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+impl InstalledPackage {
+    pub fn new(name: String, version: String, files: Vec<String>) -> Self {
+        Self {
+            name,
+            version,
+            install_time: Utc::now(),
+            files,
+        }
+    }
+
+    pub fn name_version(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+/// # 软件包数据库
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageDatabase {
+    /// 记录被删除（文件被`dadk rootfs delete-sysroot --only/--installed-by`删除）、
+    /// 下次安装时即使构建结果没有变化也需要重新安装的任务名称
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<String>,
+    /// 按名称排序的已安装任务。同一个任务只记录最近安装的版本
+    #[serde(default, rename = "package")]
+    packages: Vec<InstalledPackage>,
+}
+
+impl PackageDatabase {
+    /// 数据库文件的路径
+    pub fn path(sysroot: &Path) -> PathBuf {
+        sysroot.join(PKGDB_PATH)
+    }
+
+    /// 读取sysroot中的数据库，数据库不存在时返回空的数据库
+    pub fn load(sysroot: &Path) -> Result<Self, String> {
+        let path = Self::path(sysroot);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// 写入数据库。先写入临时文件再重命名，避免中断时留下不完整的数据库
+    pub fn save(&self, sysroot: &Path) -> Result<(), String> {
+        let path = Self::path(sysroot);
+        let dir = path.parent().unwrap();
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let content = toml::to_string(self)
+            .map_err(|e| format!("Failed to serialize package database: {}", e))?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, content)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// 读取数据库，执行`f`之后写回。多个线程同时调用时串行执行
+    pub fn update(sysroot: &Path, f: impl FnOnce(&mut Self)) -> Result<(), String> {
+        let _guard = PKGDB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut db = Self::load(sysroot)?;
+        f(&mut db);
+        db.save(sysroot)
+    }
+
+    /// 记录安装的任务，替换同名任务之前的记录
+    pub fn record(&mut self, package: InstalledPackage) {
+        self.removed.remove(&package.name);
+        self.packages.retain(|p| p.name != package.name);
+        let pos = self.packages.partition_point(|p| p.name < package.name);
+        self.packages.insert(pos, package);
+    }
+
+    pub fn packages(&self) -> &[InstalledPackage] {
+        &self.packages
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// 任务的记录是否被删除，且之后还没有重新安装
+    pub fn is_removed(&self, name: &str) -> bool {
+        self.removed.contains(name)
+    }
+
+    /// 删除任务的记录，下次安装时重新安装该任务
+    pub fn remove(&mut self, name: &str) -> Option<InstalledPackage> {
+        let pos = self.packages.iter().position(|p| p.name == name)?;
+        self.removed.insert(name.to_string());
+        Some(self.packages.remove(pos))
+    }
+
+    /// 删除安装了`path`或者其下文件的任务的记录（这些任务已经不完整，下次安装时需要重新安装），
+    /// 返回被删除记录的任务
+    pub fn forget(&mut self, path: &str) -> Vec<InstalledPackage> {
+        let path = Path::new(path);
+        let (removed, kept) = std::mem::take(&mut self.packages)
+            .into_iter()
+            .partition(|p| p.files.iter().any(|f| Path::new(f).starts_with(path)));
+        self.packages = kept;
+        self.removed.extend(removed.iter().map(|p| p.name.clone()));
+        removed
+    }
+
+    /// 安装了给定文件的任务。`path`为sysroot中的路径，可以不以`/`开头
+    pub fn owners(&self, path: &str) -> Vec<&InstalledPackage> {
+        let path = normalize(Path::new(path));
+        self.packages
+            .iter()
+            .filter(|p| p.files.iter().any(|f| *f == path))
+            .collect()
+    }
+
+    /// 生成文本格式的已安装任务列表
+    pub fn table(&self) -> String {
+        if self.packages.is_empty() {
+            return "No task installed.\n".to_string();
+        }
+        let rows: Vec<[String; 4]> = self
+            .packages
+            .iter()
+            .map(|p| {
+                [
+                    p.name.clone(),
+                    p.version.clone(),
+                    p.files.len().to_string(),
+                    p.install_time
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                ]
+            })
+            .collect();
+        let header = ["NAME", "VERSION", "FILES", "INSTALLED AT"];
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let mut out = String::new();
+        for row in std::iter::once(header.map(String::from)).chain(rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// 删除任务安装到sysroot中的文件，以及因此变为空的目录，并删除数据库中任务的记录。
+/// 同时被其他任务安装的文件不删除。
+///
+/// `task`为任务名称或者`name@version`，返回删除的文件
+pub fn uninstall(sysroot: &Path, task: &str) -> Result<Vec<String>, String> {
+    let _guard = PKGDB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut db = PackageDatabase::load(sysroot)?;
+    let (name, version) = match task.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (task, None),
+    };
+    let package = match db.get(name) {
+        Some(p) if version.map_or(true, |v| v == p.version) => db.remove(name).unwrap(),
+        Some(p) => {
+            return Err(format!(
+                "{} is not installed in {}, the installed version is {}",
+                task,
+                sysroot.display(),
+                p.version
+            ))
+        }
+        None => {
+            return Err(format!(
+                "{} is not installed in {}",
+                task,
+                sysroot.display()
+            ))
+        }
+    };
+
+    let mut removed = Vec::new();
+    for file in &package.files {
+        if !db.owners(file).is_empty() {
+            continue;
+        }
+        let path = sysroot_entry(sysroot, file)?;
+        match std::fs::remove_file(&path) {
+            Ok(()) => removed.push(file.clone()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to remove {}: {}", path.display(), e)),
+        }
+        remove_empty_parents(sysroot, &path);
+    }
+    db.save(sysroot)?;
+    Ok(removed)
+}
+
+/// 删除sysroot中的`path`（文件或者目录），并删除数据库中安装了其中文件的任务的记录。
+///
+/// `path`为sysroot中的路径，可以不以`/`开头，不能包含`..`，也不能是sysroot本身。返回被删除记录的任务
+pub fn remove_path(sysroot: &Path, path: &Path) -> Result<Vec<InstalledPackage>, String> {
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("{} should not contain '..'", path.display()));
+    }
+    let normalized = normalize(path);
+    if normalized == "/" {
+        return Err("Refusing to remove the whole sysroot as a sub path".to_string());
+    }
+    let _guard = PKGDB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let target = sysroot_entry(sysroot, &normalized)?;
+    let metadata = target
+        .symlink_metadata()
+        .map_err(|e| format!("Failed to stat {}: {}", target.display(), e))?;
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(&target)
+    } else {
+        std::fs::remove_file(&target)
+    }
+    .map_err(|e| format!("Failed to remove {}: {}", target.display(), e))?;
+
+    let mut db = PackageDatabase::load(sysroot)?;
+    let removed = db.forget(&normalized);
+    db.save(sysroot)?;
+    Ok(removed)
+}
+
+/// sysroot中的路径对应的主机路径。父目录中的符号链接指向sysroot之外时返回错误，避免删除主机上的文件
+fn sysroot_entry(sysroot: &Path, path: &str) -> Result<PathBuf, String> {
+    let entry = sysroot.join(path.trim_start_matches('/'));
+    let root = sysroot
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve {}: {}", sysroot.display(), e))?;
+    let Some(parent) = entry.parent() else {
+        return Ok(entry);
+    };
+    match parent.canonicalize() {
+        Ok(parent) if !parent.starts_with(root.as_path()) => Err(format!(
+            "{} resolves to {}, which is outside of the sysroot",
+            entry.display(),
+            parent.display()
+        )),
+        _ => Ok(entry),
+    }
+}
+
+/// 删除`path`的父目录中变为空的目录，直到sysroot为止
+fn remove_empty_parents(sysroot: &Path, path: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == sysroot || !dir.starts_with(sysroot) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// 列出安装目录下的所有文件（包括符号链接，不包括目录），返回sysroot中以`/`开头的路径
+///
+/// `staging`为安装前的暂存目录，`in_dragonos_path`为安装到DragonOS中的路径
+pub fn installed_files(staging: &Path, in_dragonos_path: &Path) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel) = dirs.pop() {
+        let dir = staging.join(&rel);
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+            let file_type = entry
+                .file_type()
+                .map_err(|e| format!("Failed to stat {}: {}", entry.path().display(), e))?;
+            let rel = rel.join(entry.file_name());
+            if file_type.is_dir() {
+                dirs.push(rel);
+            } else {
+                files.push(normalize(&in_dragonos_path.join(rel)));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// 把路径转换为以`/`开头、没有`.`和`..`的形式
+pub(crate) fn normalize(path: &Path) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(s) => parts.push(s.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
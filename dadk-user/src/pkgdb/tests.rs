@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use super::*;
+
+fn package(name: &str, version: &str, files: &[&str]) -> InstalledPackage {
+    InstalledPackage::new(
+        name.to_string(),
+        version.to_string(),
+        files.iter().map(|f| f.to_string()).collect(),
+    )
+}
+
+/// 测试记录安装的任务时按名称排序，并替换同名任务之前的记录
+#[test]
+fn record_replaces_previous_version() {
+    let mut db = PackageDatabase::default();
+    db.record(package("b", "0.1.0", &["/bin/b"]));
+    db.record(package("a", "0.1.0", &["/bin/a"]));
+    db.record(package("b", "0.2.0", &["/bin/b", "/lib/libb.so"]));
+
+    let names: Vec<String> = db.packages().iter().map(|p| p.name_version()).collect();
+    assert_eq!(names, vec!["a@0.1.0", "b@0.2.0"]);
+    assert_eq!(db.get("b").unwrap().files.len(), 2);
+    assert!(db.get("c").is_none());
+}
+
+/// 测试查询文件属于哪个任务
+#[test]
+fn owners_of_path() {
+    let mut db = PackageDatabase::default();
+    db.record(package("a", "0.1.0", &["/bin/a", "/etc/shared.conf"]));
+    db.record(package("b", "0.1.0", &["/bin/b", "/etc/shared.conf"]));
+
+    let owners =
+        |path: &str| -> Vec<String> { db.owners(path).iter().map(|p| p.name_version()).collect() };
+    assert_eq!(owners("/bin/a"), vec!["a@0.1.0"]);
+    assert_eq!(owners("bin/./b"), vec!["b@0.1.0"]);
+    assert_eq!(owners("/usr/../bin/b"), vec!["b@0.1.0"]);
+    assert_eq!(owners("/etc/shared.conf"), vec!["a@0.1.0", "b@0.1.0"]);
+    assert!(owners("/bin").is_empty());
+}
+
+/// 测试数据库的读写，以及数据库不存在时返回空的数据库
+#[test]
+fn save_and_load() {
+    let tmp = tempfile::tempdir().unwrap();
+    let sysroot = tmp.path();
+    assert_eq!(
+        PackageDatabase::load(sysroot).unwrap(),
+        PackageDatabase::default()
+    );
+
+    PackageDatabase::update(sysroot, |db| db.record(package("a", "0.1.0", &["/bin/a"]))).unwrap();
+    PackageDatabase::update(sysroot, |db| db.record(package("b", "0.1.0", &["/bin/b"]))).unwrap();
+    assert!(sysroot.join(PKGDB_PATH).is_file());
+    let db = PackageDatabase::load(sysroot).unwrap();
+    assert_eq!(db.packages().len(), 2);
+    assert_eq!(db.get("a").unwrap().files, vec!["/bin/a".to_string()]);
+    assert!(db.table().contains("a     0.1.0    1"));
+
+    std::fs::write(sysroot.join(PKGDB_PATH), "package = 1").unwrap();
+    assert!(PackageDatabase::load(sysroot).is_err());
+}
+
+/// 测试列出安装的文件：包括子目录中的文件和符号链接，不包括目录
+#[test]
+fn list_installed_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    let staging = tmp.path();
+    std::fs::create_dir_all(staging.join("lib/pkgconfig")).unwrap();
+    std::fs::create_dir_all(staging.join("empty")).unwrap();
+    std::fs::write(staging.join("hello"), "").unwrap();
+    std::fs::write(staging.join("lib/pkgconfig/hello.pc"), "").unwrap();
+    std::os::unix::fs::symlink("hello", staging.join("hi")).unwrap();
+
+    assert_eq!(
+        installed_files(staging, Path::new("usr")).unwrap(),
+        vec!["/usr/hello", "/usr/hi", "/usr/lib/pkgconfig/hello.pc"]
+    );
+    assert_eq!(
+        installed_files(staging, Path::new("")).unwrap(),
+        vec!["/hello", "/hi", "/lib/pkgconfig/hello.pc"]
+    );
+}
+
+/// 测试删除任务安装的文件：保留其他任务也安装了的文件，删除变为空的目录
+#[test]
+fn uninstall_package() {
+    let tmp = tempfile::tempdir().unwrap();
+    let sysroot = tmp.path();
+    for file in ["bin/a", "bin/b", "etc/shared.conf", "usr/share/a/doc"] {
+        let path = sysroot.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    PackageDatabase::update(sysroot, |db| {
+        db.record(package(
+            "a",
+            "0.1.0",
+            &["/bin/a", "/etc/shared.conf", "/usr/share/a/doc"],
+        ));
+        db.record(package("b", "0.1.0", &["/bin/b", "/etc/shared.conf"]));
+    })
+    .unwrap();
+
+    assert!(uninstall(sysroot, "a@0.2.0").is_err());
+    assert!(uninstall(sysroot, "c").is_err());
+    let removed = uninstall(sysroot, "a@0.1.0").unwrap();
+    assert_eq!(removed, vec!["/bin/a", "/usr/share/a/doc"]);
+    assert!(!sysroot.join("bin/a").exists());
+    assert!(sysroot.join("bin/b").exists());
+    assert!(sysroot.join("etc/shared.conf").exists());
+    assert!(!sysroot.join("usr").exists());
+
+    let db = PackageDatabase::load(sysroot).unwrap();
+    let names: Vec<String> = db.packages().iter().map(|p| p.name_version()).collect();
+    assert_eq!(names, vec!["b@0.1.0"]);
+    assert!(db.is_removed("a"));
+    assert!(!db.is_removed("b"));
+
+    // 重新安装之后不再需要重新安装
+    PackageDatabase::update(sysroot, |db| db.record(package("a", "0.1.0", &["/bin/a"]))).unwrap();
+    assert!(!PackageDatabase::load(sysroot).unwrap().is_removed("a"));
+}
+
+/// 测试删除sysroot中的路径，并删除安装了其中文件的任务的记录
+#[test]
+fn remove_sub_path() {
+    let tmp = tempfile::tempdir().unwrap();
+    let sysroot = tmp.path();
+    for file in ["bin/a", "usr/share/doc/a", "usr/share/doc/b"] {
+        let path = sysroot.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    PackageDatabase::update(sysroot, |db| {
+        db.record(package("a", "0.1.0", &["/bin/a", "/usr/share/doc/a"]));
+        db.record(package("b", "0.1.0", &["/usr/share/doc/b"]));
+        db.record(package("c", "0.1.0", &["/usr/share/docs"]));
+    })
+    .unwrap();
+
+    assert!(remove_path(sysroot, Path::new("/")).is_err());
+    assert!(remove_path(sysroot, Path::new("bin/../..")).is_err());
+    assert!(remove_path(sysroot, Path::new("/no/such/file")).is_err());
+
+    let forgotten = remove_path(sysroot, Path::new("/usr/share/doc")).unwrap();
+    let names: Vec<String> = forgotten.iter().map(|p| p.name_version()).collect();
+    assert_eq!(names, vec!["a@0.1.0", "b@0.1.0"]);
+    assert!(!sysroot.join("usr/share/doc").exists());
+    assert!(sysroot.join("bin/a").exists());
+    let db = PackageDatabase::load(sysroot).unwrap();
+    let names: Vec<String> = db.packages().iter().map(|p| p.name_version()).collect();
+    assert_eq!(names, vec!["c@0.1.0"]);
+    assert!(db.is_removed("a") && db.is_removed("b"));
+    assert!(!db.is_removed("c"));
+
+    // 父目录是指向sysroot之外的符号链接时拒绝删除
+    let tmp_outside = tempfile::tempdir().unwrap();
+    let outside = tmp_outside.path();
+    std::fs::write(outside.join("keep"), "").unwrap();
+    std::os::unix::fs::symlink(outside, sysroot.join("usr/lib")).unwrap();
+    assert!(remove_path(sysroot, Path::new("usr/lib/keep")).is_err());
+    assert!(outside.join("keep").exists());
+}
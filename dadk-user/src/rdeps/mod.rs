@@ -0,0 +1,113 @@
+//! # 反向依赖
+//!
+//! 列出直接或间接依赖于某个任务的任务，用于在升级基础库（例如relibc）之前评估影响范围。
+//! 依赖关系来自调度器拓扑排序时建立的子节点（依赖于该任务的任务）。
+
+use std::{collections::BTreeSet, fmt::Write, sync::Arc};
+
+use serde::Serialize;
+
+use crate::scheduler::SchedEntity;
+
+#[cfg(test)]
+mod tests;
+
+/// # 反向依赖树
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RdepTree {
+    /// 任务（`name@version`）
+    pub task: String,
+    /// 任务已经在树中的其他位置列出过，不再重复列出依赖于它的任务
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+    /// 直接依赖于该任务的任务（按名称排序）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<RdepTree>,
+}
+
+impl RdepTree {
+    /// 从调度实体出发，沿着子节点建立反向依赖树
+    pub(crate) fn from_entity(entity: &Arc<SchedEntity>) -> Self {
+        Self::build(entity, &mut BTreeSet::new())
+    }
+
+    fn build(entity: &Arc<SchedEntity>, seen: &mut BTreeSet<String>) -> Self {
+        let task = entity.task();
+        let task = format!("{}@{}", task.name, task.version);
+        if !seen.insert(task.clone()) {
+            return Self {
+                task,
+                repeated: true,
+                dependents: Vec::new(),
+            };
+        }
+        let mut children = entity.children();
+        children.sort_by_key(|c| {
+            let t = c.task();
+            (t.name, t.version)
+        });
+        // 同一个任务可能通过多条依赖加入子节点
+        children.dedup_by_key(|c| c.id());
+        Self {
+            task,
+            repeated: false,
+            dependents: children.iter().map(|c| Self::build(c, seen)).collect(),
+        }
+    }
+
+    /// 所有直接或间接依赖于该任务的任务（按名称排序，不包括该任务自身）
+    pub fn flat(&self) -> Vec<String> {
+        let mut all = BTreeSet::new();
+        self.collect(&mut all);
+        all.remove(&self.task);
+        all.into_iter().collect()
+    }
+
+    fn collect(&self, all: &mut BTreeSet<String>) {
+        all.insert(self.task.clone());
+        for dep in &self.dependents {
+            dep.collect(all);
+        }
+    }
+
+    /// 树形输出，重复出现的任务标记为`(*)`
+    pub fn tree_text(&self) -> String {
+        let mut out = format!("{}\n", self.task);
+        self.write_dependents("", &mut out);
+        out
+    }
+
+    fn write_dependents(&self, prefix: &str, out: &mut String) {
+        for (i, dep) in self.dependents.iter().enumerate() {
+            let last = i + 1 == self.dependents.len();
+            out.push_str(&format!(
+                "{}{}{}{}\n",
+                prefix,
+                if last { "└── " } else { "├── " },
+                dep.task,
+                if dep.repeated { " (*)" } else { "" }
+            ));
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            dep.write_dependents(&prefix, out);
+        }
+    }
+
+    /// 每行一个任务
+    pub fn flat_text(&self) -> String {
+        let mut out = String::new();
+        for task in self.flat() {
+            writeln!(out, "{}", task).unwrap();
+        }
+        out
+    }
+
+    /// JSON格式的输出：`flat`为true时输出任务列表，否则输出整棵树
+    pub fn to_json(&self, flat: bool) -> String {
+        if flat {
+            serde_json::to_string_pretty(&self.flat())
+        } else {
+            serde_json::to_string_pretty(self)
+        }
+        .expect("Failed to serialize reverse dependencies")
+    }
+}
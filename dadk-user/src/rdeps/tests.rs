@@ -0,0 +1,54 @@
+use super::*;
+
+fn node(task: &str, dependents: Vec<RdepTree>) -> RdepTree {
+    RdepTree {
+        task: task.to_string(),
+        repeated: false,
+        dependents,
+    }
+}
+
+fn repeated(task: &str) -> RdepTree {
+    RdepTree {
+        task: task.to_string(),
+        repeated: true,
+        dependents: Vec::new(),
+    }
+}
+
+fn tree() -> RdepTree {
+    node(
+        "libc@0.1.0",
+        vec![
+            node("libbar@0.1.0", vec![node("app@0.1.0", vec![])]),
+            node("libfoo@0.1.0", vec![repeated("app@0.1.0")]),
+        ],
+    )
+}
+
+#[test]
+fn tree_text_marks_repeated_tasks() {
+    assert_eq!(
+        tree().tree_text(),
+        "libc@0.1.0\n\
+         ├── libbar@0.1.0\n\
+         │   └── app@0.1.0\n\
+         └── libfoo@0.1.0\n\
+         \x20   └── app@0.1.0 (*)\n"
+    );
+}
+
+#[test]
+fn flat_lists_each_dependent_once() {
+    let tree = tree();
+    assert_eq!(
+        tree.flat(),
+        vec!["app@0.1.0", "libbar@0.1.0", "libfoo@0.1.0"]
+    );
+    assert_eq!(tree.flat_text(), "app@0.1.0\nlibbar@0.1.0\nlibfoo@0.1.0\n");
+    assert_eq!(
+        tree.to_json(true),
+        serde_json::to_string_pretty(&tree.flat()).unwrap()
+    );
+    assert!(!tree.to_json(false).contains("\"repeated\": false"));
+}
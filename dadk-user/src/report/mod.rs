@@ -0,0 +1,123 @@
+//! # 构建报告
+//!
+//! `dadk user build --report`在构建结束时，把每个任务的构建结果以及构建结果的来源信息
+//! （源文件的确切版本、执行构建的主机、DADK的版本）以JSON格式写入文件。
+//! 发布的DragonOS镜像可以据此追溯到每个用户程序的确切输入。
+//!
+//! 因为没有变化而跳过构建的任务，记录的是上一次实际执行构建时的来源信息。
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    executor::{cache::TaskDataDir, provenance},
+    metrics::RunMetrics,
+    parser::{
+        task::DADKTask,
+        task_log::{BuilderInfo, Provenance},
+    },
+};
+
+#[cfg(test)]
+mod tests;
+
+/// # 一次构建的报告
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildReport {
+    pub dadk_version: String,
+    pub arch: String,
+    /// 构建结束的时间
+    pub finished: DateTime<Utc>,
+    pub success: bool,
+    /// 执行本次构建的主机
+    pub builder: BuilderInfo,
+    pub tasks: Vec<TaskReport>,
+}
+
+/// # 单个任务的构建结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskReport {
+    pub name: String,
+    pub version: String,
+    pub result: TaskResult,
+    /// 实际执行构建所花费的时间（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// 构建结果的来源信息。构建失败或者没有执行时为None
+    pub provenance: Option<Provenance>,
+}
+
+/// 任务在本次构建中的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskResult {
+    /// 实际执行了构建
+    Built,
+    /// 没有变化，跳过构建
+    Skipped,
+    Failed,
+    /// 构建被中断，或者依赖的任务构建失败，没有执行
+    NotRun,
+}
+
+impl BuildReport {
+    /// 根据本次构建的指标以及任务日志生成报告
+    pub fn new(
+        tasks: &[DADKTask],
+        metrics: &RunMetrics,
+        cache_root: &Path,
+        arch: &str,
+        success: bool,
+        builder: BuilderInfo,
+    ) -> Self {
+        let mut tasks: Vec<TaskReport> = tasks
+            .iter()
+            .map(|task| {
+                let name_version = task.name_version();
+                let executed = metrics.tasks().iter().find(|t| t.task == name_version);
+                let result = match executed {
+                    None => TaskResult::NotRun,
+                    Some(t) if t.failed => TaskResult::Failed,
+                    Some(t) if t.elapsed.is_some() => TaskResult::Built,
+                    Some(_) => TaskResult::Skipped,
+                };
+                let provenance = match result {
+                    TaskResult::Built | TaskResult::Skipped => {
+                        TaskDataDir::load_task_log(cache_root, task)
+                            .and_then(|log| log.provenance().cloned())
+                    }
+                    _ => None,
+                };
+                TaskReport {
+                    name: task.name.clone(),
+                    version: task.version.clone(),
+                    result,
+                    duration_ms: executed
+                        .and_then(|t| t.elapsed)
+                        .map(|d| d.as_millis() as u64),
+                    provenance,
+                }
+            })
+            .collect();
+        tasks.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Self {
+            dadk_version: provenance::DADK_VERSION.to_string(),
+            arch: arch.to_string(),
+            finished: Utc::now(),
+            success,
+            builder,
+            tasks,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize build report")
+    }
+}
+
+/// 写入构建报告
+pub fn write(path: &Path, report: &BuildReport) -> Result<(), String> {
+    crate::repository::write_atomically(path, report.to_json().as_bytes())
+}
@@ -0,0 +1,124 @@
+use std::{path::PathBuf, time::Duration};
+
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::{
+    executor::cache::{CacheDir, CacheDirType},
+    metrics::TaskMetrics,
+    parser::{
+        task_log::{SourceRevision, TaskLog},
+        Parser,
+    },
+};
+
+fn parse_task(ctx: &BaseGlobalTestContext, name: &str) -> DADKTask {
+    Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join(name))
+        .unwrap()
+}
+
+fn provenance(commit: &str) -> Provenance {
+    Provenance {
+        source: SourceRevision::Git {
+            url: "https://example.com/app.git".to_string(),
+            commit: commit.to_string(),
+        },
+        patches: Vec::new(),
+        builder: provenance::builder_info(None),
+        dadk_version: provenance::DADK_VERSION.to_string(),
+    }
+}
+
+fn save_log(cache_root: &Path, task: &DADKTask, provenance: Option<Provenance>) {
+    let dir = CacheDir::get_path(cache_root, task, CacheDirType::TaskData);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut log = TaskLog::new();
+    log.set_build_time_now();
+    log.set_provenance(provenance);
+    std::fs::write(
+        dir.join(TaskDataDir::TASK_LOG_FILE_NAME),
+        toml::to_string(&log).unwrap(),
+    )
+    .unwrap();
+}
+
+/// 根据本次构建的结果和任务日志生成报告
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn build_report_results(ctx: &BaseGlobalTestContext) {
+    let built = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    let mut skipped = built.clone();
+    skipped.name = "skipped".to_string();
+    let mut failed = built.clone();
+    failed.name = "failed".to_string();
+    let mut not_run = built.clone();
+    not_run.name = "not-run".to_string();
+
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    save_log(cache_root, &built, Some(provenance("1111111")));
+    save_log(cache_root, &skipped, Some(provenance("2222222")));
+    save_log(cache_root, &failed, None);
+    save_log(cache_root, &not_run, Some(provenance("3333333")));
+
+    let mut metrics = RunMetrics::default();
+    for (task, elapsed, is_failed) in [
+        (&built, Some(Duration::from_millis(1500)), false),
+        (&skipped, None, false),
+        (&failed, Some(Duration::from_secs(1)), true),
+    ] {
+        metrics.record(TaskMetrics {
+            task: task.name_version(),
+            elapsed,
+            failed: is_failed,
+            downloaded_bytes: 0,
+        });
+    }
+
+    let tasks = [
+        built.clone(),
+        skipped.clone(),
+        failed.clone(),
+        not_run.clone(),
+    ];
+    let builder = provenance::builder_info(None);
+    let report = BuildReport::new(&tasks, &metrics, cache_root, "x86_64", false, builder);
+    let results: Vec<_> = report
+        .tasks
+        .iter()
+        .map(|t| (t.name.as_str(), t.result))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            (built.name.as_str(), TaskResult::Built),
+            ("failed", TaskResult::Failed),
+            ("not-run", TaskResult::NotRun),
+            ("skipped", TaskResult::Skipped),
+        ]
+    );
+    assert_eq!(report.tasks[0].duration_ms, Some(1500));
+    assert_eq!(report.tasks[0].provenance, Some(provenance("1111111")));
+    // 跳过构建的任务记录上一次构建的来源
+    assert_eq!(report.tasks[3].provenance, Some(provenance("2222222")));
+    assert_eq!(report.tasks[3].duration_ms, None);
+    // 没有执行的任务不记录来源，即使任务日志中有之前的记录
+    assert_eq!(report.tasks[2].provenance, None);
+    assert!(!report.success);
+
+    let path: PathBuf = cache_root.join("report").join("build-report.json");
+    write(&path, &report).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["arch"], "x86_64");
+    assert_eq!(json["dadk_version"], provenance::DADK_VERSION);
+    assert_eq!(json["tasks"][1]["result"], "failed");
+    assert_eq!(
+        json["tasks"][0]["provenance"]["source"]["commit"],
+        "1111111"
+    );
+}
@@ -0,0 +1,329 @@
+//! # 软件仓库
+//!
+//! 软件仓库是一个可以通过HTTP访问的目录，其中包含`dadk user package`生成的二进制包，
+//! 以及记录了这些二进制包的索引文件`index.toml`：
+//!
+//! ```toml
+//! [[package]]
+//! name = "app1"
+//! version = "0.1.0"
+//! target-arch = "x86_64"
+//! # 相对于索引文件的路径，或者完整的URL
+//! url = "app1-0.1.0-x86_64.dpk"
+//! sha256 = "..."
+//! ```
+//!
+//! `source = "repository"`的任务只需要指定名称和版本，DADK从索引中查找二进制包的下载地址和sha256，
+//! 下载的二进制包缓存在`<cache_root>/repository/`目录中，校验通过的缓存不会重复下载。
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use dadk_config::common::target_arch::TargetArch;
+use log::{info, warn};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::file::FileUtils;
+
+#[cfg(test)]
+mod tests;
+
+/// 索引文件的文件名
+pub const INDEX_FILE_NAME: &str = "index.toml";
+
+/// 本次运行中已经获取的索引（索引URL -> 索引），同一个仓库只获取一次
+static FETCHED_INDEXES: Mutex<BTreeMap<String, RepositoryIndex>> = Mutex::new(BTreeMap::new());
+
+/// # 索引中的二进制包
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    pub target_arch: TargetArch,
+    /// 相对于索引文件的路径，或者完整的URL
+    pub url: String,
+    pub sha256: String,
+}
+
+/// # 软件仓库的索引
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryIndex {
+    #[serde(default, rename = "package")]
+    packages: Vec<IndexEntry>,
+}
+
+impl RepositoryIndex {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| format!("Invalid repository index: {}", e))
+    }
+
+    /// 读取本地的索引文件，文件不存在时返回空的索引
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&content)
+    }
+
+    /// 写入索引文件。先写入临时文件再重命名，避免中断时留下不完整的索引
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string(self)
+            .map_err(|e| format!("Failed to serialize repository index: {}", e))?;
+        write_atomically(path, content.as_bytes())
+    }
+
+    pub fn packages(&self) -> &[IndexEntry] {
+        &self.packages
+    }
+
+    pub fn find(&self, name: &str, version: &str, arch: TargetArch) -> Option<&IndexEntry> {
+        self.packages
+            .iter()
+            .find(|p| p.name == name && p.version == version && p.target_arch == arch)
+    }
+
+    /// 添加二进制包，替换名称、版本、架构都相同的记录
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.packages.retain(|p| {
+            !(p.name == entry.name
+                && p.version == entry.version
+                && p.target_arch == entry.target_arch)
+        });
+        let key = |p: &IndexEntry| (p.name.clone(), p.version.clone());
+        let pos = self.packages.partition_point(|p| key(p) <= key(&entry));
+        self.packages.insert(pos, entry);
+    }
+}
+
+/// # 软件仓库
+pub struct PackageRepository {
+    /// 索引文件的URL
+    index_url: Url,
+    /// 本地缓存目录
+    cache_dir: PathBuf,
+    /// 离线模式，只使用本地缓存中的索引和二进制包
+    offline: bool,
+}
+
+impl PackageRepository {
+    pub fn new(index_url: &str, cache_root: &Path) -> Result<Self, String> {
+        let index_url = Url::parse(index_url)
+            .map_err(|e| format!("Invalid package repository url {:?}: {}", index_url, e))?;
+        if index_url.scheme() != "http" && index_url.scheme() != "https" {
+            return Err(format!(
+                "Package repository url {:?} is not a http/https url",
+                index_url.as_str()
+            ));
+        }
+        Ok(Self {
+            index_url,
+            cache_dir: cache_root.join("repository"),
+            offline: false,
+        })
+    }
+
+    /// 设置离线模式
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// 获取仓库的索引
+    ///
+    /// 每次运行只获取一次。获取失败时，使用上次获取的索引的本地缓存
+    pub fn index(&self) -> Result<RepositoryIndex, String> {
+        let mut fetched = FETCHED_INDEXES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = fetched.get(self.index_url.as_str()) {
+            return Ok(index.clone());
+        }
+        let cached = self.cache_dir.join(INDEX_FILE_NAME);
+        if self.offline {
+            if !cached.exists() {
+                return Err(format!(
+                    "Repository index {} is not cached, can't fetch it in offline mode",
+                    self.index_url
+                ));
+            }
+            let index = RepositoryIndex::load(&cached)?;
+            fetched.insert(self.index_url.to_string(), index.clone());
+            return Ok(index);
+        }
+        let index = match self.fetch_index() {
+            Ok(index) => {
+                if let Err(e) = index.save(&cached) {
+                    warn!("Failed to cache repository index: {}", e);
+                }
+                index
+            }
+            Err(e) if cached.exists() => {
+                warn!(
+                    "Failed to fetch repository index {}: {}, using the cached index",
+                    self.index_url, e
+                );
+                RepositoryIndex::load(&cached)?
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Failed to fetch repository index {}: {}",
+                    self.index_url, e
+                ))
+            }
+        };
+        fetched.insert(self.index_url.to_string(), index.clone());
+        Ok(index)
+    }
+
+    fn fetch_index(&self) -> Result<RepositoryIndex, String> {
+        let response = reqwest::blocking::get(self.index_url.clone())
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?;
+        RepositoryIndex::parse(&response.text().map_err(|e| e.to_string())?)
+    }
+
+    /// 二进制包的下载地址
+    pub fn package_url(&self, entry: &IndexEntry) -> Result<String, String> {
+        self.index_url
+            .join(&entry.url)
+            .map(String::from)
+            .map_err(|e| {
+                format!(
+                    "Invalid url {:?} of package {}: {}",
+                    entry.url, entry.name, e
+                )
+            })
+    }
+
+    /// 获取二进制包，返回本地缓存中的文件路径
+    ///
+    /// 缓存中已经有sha256相同的文件时不会重复下载
+    pub fn fetch_package(
+        &self,
+        entry: &IndexEntry,
+        on_progress: impl FnMut(u64, u64),
+    ) -> Result<PathBuf, String> {
+        let arch: &str = entry.target_arch.into();
+        let packages_dir = self.cache_dir.join("packages");
+        let path = packages_dir.join(format!(
+            "{}-{}-{}.{}",
+            entry.name,
+            entry.version,
+            arch,
+            crate::package::PACKAGE_EXTENSION
+        ));
+        if path.is_file() && sha256_file(&path)? == entry.sha256.to_lowercase() {
+            info!("Using cached package {}", path.display());
+            return Ok(path);
+        }
+        if self.offline {
+            return Err(format!(
+                "Package {}-{} ({}) is not cached, can't download it in offline mode",
+                entry.name, entry.version, arch
+            ));
+        }
+
+        let url = self.package_url(entry)?;
+        // 下载到单独的临时目录，避免多个任务同时下载时互相覆盖
+        let tmp_dir = packages_dir.join(format!(
+            ".download-{}-{}-{}",
+            std::process::id(),
+            entry.name,
+            entry.version
+        ));
+        std::fs::remove_dir_all(&tmp_dir).ok();
+        std::fs::create_dir_all(&tmp_dir)
+            .map_err(|e| format!("Failed to create {}: {}", tmp_dir.display(), e))?;
+        let r = self.download(&url, &tmp_dir, entry, &path, on_progress);
+        std::fs::remove_dir_all(&tmp_dir).ok();
+        r.map(|_| path)
+    }
+
+    fn download(
+        &self,
+        url: &str,
+        tmp_dir: &Path,
+        entry: &IndexEntry,
+        path: &Path,
+        on_progress: impl FnMut(u64, u64),
+    ) -> Result<(), String> {
+        info!("downloading {}", url);
+        FileUtils::download_file(url, tmp_dir, on_progress).map_err(|e| e.to_string())?;
+        let file_name = Url::parse(url)
+            .ok()
+            .and_then(|u| u.path_segments()?.last().map(String::from))
+            .unwrap_or_default();
+        let downloaded = tmp_dir.join(file_name);
+        let sha256 = sha256_file(&downloaded)?;
+        if sha256 != entry.sha256.to_lowercase() {
+            return Err(format!(
+                "sha256 mismatch for {}: expected {}, got {}",
+                url, entry.sha256, sha256
+            ));
+        }
+        std::fs::rename(&downloaded, path)
+            .map_err(|e| format!("Failed to move {} to {}: {}", url, path.display(), e))
+    }
+}
+
+/// 把二进制包添加到与它位于同一目录的索引文件中，返回添加的记录
+pub fn add_to_index(package: &Path) -> Result<IndexEntry, String> {
+    let manifest = crate::package::read_manifest(package)?;
+    let file_name = package
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| format!("Invalid package path {}", package.display()))?;
+    let entry = IndexEntry {
+        name: manifest.name,
+        version: manifest.version,
+        target_arch: manifest.target_arch,
+        url: file_name,
+        sha256: sha256_file(package)?,
+    };
+    let index_path = package.with_file_name(INDEX_FILE_NAME);
+    let _guard = FETCHED_INDEXES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut index = RepositoryIndex::load(&index_path)?;
+    index.insert(entry.clone());
+    index.save(&index_path)?;
+    Ok(entry)
+}
+
+/// 计算文件的sha256，返回小写的十六进制字符串
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let err = |e: std::io::Error| format!("Failed to read {}: {}", path.display(), e);
+    let mut file = File::open(path).map_err(err)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(err)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let hex: Vec<String> = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(hex.concat())
+}
+
+pub(crate) fn write_atomically(path: &Path, content: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    std::fs::write(&tmp, content)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
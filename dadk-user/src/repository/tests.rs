@@ -0,0 +1,140 @@
+use super::*;
+use crate::package::{self, PackageManifest};
+
+fn entry(name: &str, version: &str, arch: TargetArch, sha256: &str) -> IndexEntry {
+    let arch_name: &str = arch.into();
+    IndexEntry {
+        name: name.to_string(),
+        version: version.to_string(),
+        target_arch: arch,
+        url: format!("{}-{}-{}.dpk", name, version, arch_name),
+        sha256: sha256.to_string(),
+    }
+}
+
+/// 测试按名称、版本、架构查找二进制包，以及替换相同的记录
+#[test]
+fn index_find_and_insert() {
+    let mut index = RepositoryIndex::parse(
+        r#"
+[[package]]
+name = "b"
+version = "0.1.0"
+target-arch = "x86_64"
+url = "b-0.1.0-x86_64.dpk"
+sha256 = "00"
+"#,
+    )
+    .unwrap();
+    index.insert(entry("a", "0.1.0", TargetArch::X86_64, "01"));
+    index.insert(entry("a", "0.1.0", TargetArch::RiscV64, "02"));
+    index.insert(entry("b", "0.1.0", TargetArch::X86_64, "03"));
+
+    assert_eq!(index.packages().len(), 3);
+    assert_eq!(index.packages()[0].name, "a");
+    assert_eq!(
+        index.find("b", "0.1.0", TargetArch::X86_64).unwrap().sha256,
+        "03"
+    );
+    assert_eq!(
+        index
+            .find("a", "0.1.0", TargetArch::RiscV64)
+            .unwrap()
+            .sha256,
+        "02"
+    );
+    assert!(index.find("a", "0.2.0", TargetArch::X86_64).is_none());
+    assert!(RepositoryIndex::parse("package = 1").is_err());
+}
+
+/// 测试二进制包的URL相对于索引文件
+#[test]
+fn package_url_relative_to_index() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache = tmp.path();
+    let repo = PackageRepository::new("https://example.com/dadk/index.toml", cache).unwrap();
+    let mut e = entry("a", "0.1.0", TargetArch::X86_64, "00");
+    assert_eq!(
+        repo.package_url(&e).unwrap(),
+        "https://example.com/dadk/a-0.1.0-x86_64.dpk"
+    );
+    e.url = "https://mirror.example.com/a.dpk".to_string();
+    assert_eq!(
+        repo.package_url(&e).unwrap(),
+        "https://mirror.example.com/a.dpk"
+    );
+    assert!(PackageRepository::new("ftp://example.com/index.toml", cache).is_err());
+    assert!(PackageRepository::new("index.toml", cache).is_err());
+}
+
+/// 测试生成索引，以及使用本地缓存中sha256相同的二进制包
+#[test]
+fn add_to_index_and_cached_package() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let files = dir.join("files");
+    std::fs::create_dir_all(&files).unwrap();
+    std::fs::write(files.join("app"), "app").unwrap();
+    let manifest = PackageManifest {
+        format_version: 1,
+        name: "app".to_string(),
+        version: "0.1.0".to_string(),
+        description: String::new(),
+        target_arch: TargetArch::X86_64,
+        depends: Vec::new(),
+        created: chrono::Utc::now(),
+    };
+    let output = dir.join("out");
+    let pkg = package::create(&manifest, &files, &output).unwrap();
+
+    let added = add_to_index(&pkg).unwrap();
+    assert_eq!(added.url, "app-0.1.0-x86_64.dpk");
+    assert_eq!(added.sha256, sha256_file(&pkg).unwrap());
+    assert_eq!(added.sha256.len(), 64);
+    add_to_index(&pkg).unwrap();
+    let index = RepositoryIndex::load(&output.join(INDEX_FILE_NAME)).unwrap();
+    assert_eq!(index.packages(), &[added.clone()]);
+
+    // 缓存中的文件与索引中的sha256相同时，不需要下载
+    let cache_root = dir.join("cache");
+    let repo = PackageRepository::new("http://127.0.0.1:1/index.toml", &cache_root).unwrap();
+    let cached = cache_root.join("repository/packages/app-0.1.0-x86_64.dpk");
+    std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+    std::fs::copy(&pkg, &cached).unwrap();
+    assert_eq!(repo.fetch_package(&added, |_, _| {}).unwrap(), cached);
+
+    // 无法连接到仓库时，使用缓存的索引
+    assert!(repo.index().is_err());
+    let repo = PackageRepository::new("http://127.0.0.1:1/cached/index.toml", &cache_root).unwrap();
+    index
+        .save(&cache_root.join("repository").join(INDEX_FILE_NAME))
+        .unwrap();
+    assert_eq!(repo.index().unwrap(), index);
+
+    // 离线模式下只使用缓存，没有缓存时报错
+    let offline = PackageRepository::new("http://127.0.0.1:1/offline/index.toml", &cache_root)
+        .unwrap()
+        .offline(true);
+    assert_eq!(offline.index().unwrap(), index);
+    assert_eq!(offline.fetch_package(&added, |_, _| {}).unwrap(), cached);
+    std::fs::remove_file(&cached).unwrap();
+    let err = offline.fetch_package(&added, |_, _| {}).unwrap_err();
+    assert!(err.contains("offline"), "{}", err);
+    std::fs::remove_file(cache_root.join("repository").join(INDEX_FILE_NAME)).unwrap();
+    let offline = PackageRepository::new("http://127.0.0.1:1/offline2/index.toml", &cache_root)
+        .unwrap()
+        .offline(true);
+    assert!(offline.index().unwrap_err().contains("offline"));
+}
+
+#[test]
+fn sha256_of_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::write(dir.join("abc"), "abc").unwrap();
+    assert_eq!(
+        sha256_file(&dir.join("abc")).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert!(sha256_file(&dir.join("missing")).is_err());
+}
@@ -0,0 +1,133 @@
+//! # 主机软件包检查
+//!
+//! 构建之前检查所有任务在`depends-on-host-packages`中声明的命令是否在`PATH`中。
+//! 有缺少的命令时不构建任何任务，一次性报告所有缺少的软件包以及需要它们的任务；
+//! 能够根据`/etc/os-release`识别主机的发行版时，同时给出apt/dnf/pacman的安装命令。
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use crate::parser::task::DADKTask;
+
+/// 检查任务需要的主机软件包，返回所有缺少的软件包
+pub(super) fn check_host_packages(tasks: &[DADKTask]) -> Result<(), String> {
+    if tasks.iter().all(|t| t.host_packages.is_empty()) {
+        return Ok(());
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let manager = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|s| PackageManager::detect(&s));
+    missing_host_packages(tasks, &path, manager)
+}
+
+/// 在`path`中查找每个任务需要的命令
+pub(super) fn missing_host_packages(
+    tasks: &[DADKTask],
+    path: &OsStr,
+    manager: Option<PackageManager>,
+) -> Result<(), String> {
+    // (软件包, 命令) -> 需要它的任务
+    let mut missing: BTreeMap<(&str, &str), Vec<String>> = BTreeMap::new();
+    for task in tasks {
+        for package in &task.host_packages {
+            if find_in_path(package.command(), path).is_none() {
+                missing
+                    .entry((&package.package, package.command()))
+                    .or_default()
+                    .push(task.name_version());
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "{} host package(s) required by the build are missing, nothing was built:\n",
+        missing.len()
+    );
+    for ((package, command), tasks) in &missing {
+        let name = if package == command {
+            package.to_string()
+        } else {
+            format!("{} (command {})", package, command)
+        };
+        message.push_str(&format!("  {}: needed by {}\n", name, tasks.join(", ")));
+    }
+    if let Some(manager) = manager {
+        let mut packages: Vec<&str> = missing.keys().map(|(package, _)| *package).collect();
+        packages.dedup();
+        message.push_str(&format!(
+            "Install them with:\n  {}\n",
+            manager.install_command(&packages)
+        ));
+    }
+    Err(message.trim_end().to_string())
+}
+
+/// 主机上的包管理器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+}
+
+impl PackageManager {
+    /// 根据`/etc/os-release`中的`ID`以及`ID_LIKE`识别包管理器
+    pub(super) fn detect(os_release: &str) -> Option<Self> {
+        os_release
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                matches!(key.trim(), "ID" | "ID_LIKE")
+                    .then(|| value.trim().trim_matches(|c| c == '"' || c == '\''))
+            })
+            .flat_map(|ids| ids.split_whitespace())
+            .find_map(|id| match id {
+                "debian" | "ubuntu" => Some(Self::Apt),
+                "fedora" | "rhel" | "centos" => Some(Self::Dnf),
+                "arch" | "archlinux" | "manjaro" => Some(Self::Pacman),
+                _ => None,
+            })
+    }
+
+    fn install_command(&self, packages: &[&str]) -> String {
+        let prefix = match self {
+            Self::Apt => "sudo apt-get install -y",
+            Self::Dnf => "sudo dnf install -y",
+            Self::Pacman => "sudo pacman -S --needed",
+        };
+        format!("{} {}", prefix, packages.join(" "))
+    }
+}
+
+/// 在`path`中查找可执行文件。名称中包含`/`时直接检查该路径
+fn find_in_path(name: &str, path: &OsStr) -> Option<PathBuf> {
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return is_executable(&path).then_some(path);
+    }
+    // 普通用户的PATH中通常没有sbin目录，但是mkfs.fat等工具在其中
+    let sbin = ["/sbin", "/usr/sbin", "/usr/local/sbin"].map(PathBuf::from);
+    std::env::split_paths(path)
+        .chain(sbin)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,178 @@
+//! # 安装路径检查
+//!
+//! 安装之前检查所有任务将要安装的文件，发现问题时不安装任何文件，一次性报告所有问题：
+//!
+//! - 两个任务安装同一个文件
+//! - 文件不在`rootfs.toml`的`install.allowed_paths`中的任何一个目录下（列表为空时不检查）
+//!
+//! 安装路径中的`..`在解析配置文件时已经被拒绝，这里只检查构建结果中的文件。
+//!
+//! 安装时任务按照依赖顺序执行，互不依赖的任务会被同时调度。每个任务在把文件同步到sysroot之前，
+//! 还会在[`InstallClaims`]中登记本次要安装的文件，与其他任务冲突时不安装任何文件。
+//! 任务可以覆盖它所依赖的任务安装的文件，因为依赖总是先安装。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    executor::{
+        cache::{CacheDir, CacheDirType},
+        install::is_excluded,
+    },
+    parser::task::DADKTask,
+    pkgdb,
+};
+
+/// 检查任务的安装路径，返回所有问题
+pub(super) fn check_install_paths(
+    tasks: &[DADKTask],
+    cache_root: &Path,
+    allowed_paths: &[PathBuf],
+) -> Result<(), String> {
+    // 文件在sysroot中的路径 -> 安装它的任务
+    let mut owners: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut problems = Vec::new();
+    for task in tasks {
+        let Some(in_dragonos_path) = &task.install.in_dragonos_path else {
+            continue;
+        };
+        let build_dir = CacheDir::get_path(cache_root, task, CacheDirType::Build);
+        // 还没有构建的任务在安装时会报错，这里不检查
+        if !build_dir.is_dir() {
+            continue;
+        }
+        let mut files = pkgdb::installed_files(&build_dir, in_dragonos_path)?;
+        // `install.exclude`排除的文件不会被安装
+        let root = pkgdb::normalize(in_dragonos_path);
+        files.retain(|file| {
+            let rel = Path::new(file)
+                .strip_prefix(&root)
+                .unwrap_or(Path::new(file));
+            !is_excluded(&task.install.exclude, &rel.to_string_lossy(), false)
+        });
+        files.extend(
+            task.install_files()
+                .iter()
+                .filter(|f| f.symlink.is_some())
+                .map(|f| pkgdb::normalize(&in_dragonos_path.join(&f.path))),
+        );
+        files.sort();
+        files.dedup();
+        for file in files {
+            if !allowed_paths.is_empty()
+                && !allowed_paths
+                    .iter()
+                    .any(|p| Path::new(&file).starts_with(p))
+            {
+                problems.push(format!(
+                    "{}: {} is not under any of install.allowed_paths",
+                    task.name_version(),
+                    file
+                ));
+            }
+            owners.entry(file).or_default().push(task.name_version());
+        }
+    }
+    for (file, tasks) in owners.iter().filter(|(_, tasks)| tasks.len() > 1) {
+        problems.push(format!("{} is installed by {}", file, tasks.join(", ")));
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "{} install path problem(s) found, nothing was installed:\n{}",
+        problems.len(),
+        problems.join("\n")
+    ))
+}
+
+/// # 本次安装中各个任务登记的文件
+///
+/// 文件已经被其他任务登记，或者与其他任务登记的文件互为父目录
+/// （例如一个任务安装符号链接`/usr/lib`，另一个任务安装`/usr/lib/libc.so`）时，登记失败。
+/// 登记文件的任务是当前任务的依赖时不算冲突，当前任务会覆盖依赖安装的文件
+#[derive(Debug, Default)]
+pub(crate) struct InstallClaims {
+    inner: Mutex<InstallClaimsInner>,
+}
+
+#[derive(Debug, Default)]
+struct InstallClaimsInner {
+    /// 文件在sysroot中的路径 -> 登记它的任务
+    owners: BTreeMap<String, String>,
+    /// 登记过文件的任务 -> 直接或间接依赖它的任务
+    dependents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl InstallClaimsInner {
+    /// `owner`登记的文件是否与`task`冲突
+    fn conflicts(&self, owner: &str, task: &str) -> bool {
+        owner != task
+            && !self
+                .dependents
+                .get(owner)
+                .is_some_and(|dependents| dependents.contains(task))
+    }
+}
+
+impl InstallClaims {
+    /// 登记任务将要安装的文件。有冲突时不登记任何文件，返回所有冲突
+    ///
+    /// ## 参数
+    ///
+    /// - `task` : 任务的名称和版本
+    /// - `dependents` : 直接或间接依赖该任务的任务，它们可以覆盖该任务登记的文件
+    /// - `files` : 将要安装的文件
+    pub(crate) fn claim(
+        &self,
+        task: &str,
+        dependents: BTreeSet<String>,
+        files: &[String],
+    ) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut conflicts = Vec::new();
+        for file in files {
+            // 同一个文件，或者它的父目录被其他任务登记为文件
+            for ancestor in Path::new(file).ancestors() {
+                let ancestor = ancestor.to_string_lossy();
+                match inner.owners.get(ancestor.as_ref()) {
+                    Some(owner) if inner.conflicts(owner, task) => conflicts.push(format!(
+                        "{} conflicts with {} installed by {}",
+                        file, ancestor, owner
+                    )),
+                    _ => {}
+                }
+            }
+            // 其他任务在它下面安装了文件
+            let prefix = format!("{}/", file);
+            if let Some((other, owner)) = inner
+                .owners
+                .range(prefix.clone()..)
+                .take_while(|(other, _)| other.starts_with(&prefix))
+                .find(|(_, owner)| inner.conflicts(owner, task))
+            {
+                conflicts.push(format!(
+                    "{} conflicts with {} installed by {}",
+                    file, other, owner
+                ));
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(format!(
+                "{} conflicts with the files of other tasks installed in this run, nothing was installed:\n{}",
+                task,
+                conflicts.join("\n")
+            ));
+        }
+        for file in files {
+            inner.owners.insert(file.clone(), task.to_string());
+        }
+        inner.dependents.insert(task.to_string(), dependents);
+        Ok(())
+    }
+}
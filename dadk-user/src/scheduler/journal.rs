@@ -0,0 +1,146 @@
+//! # 运行日志
+//!
+//! 记录一次构建/安装中每个任务的执行状态，保存在缓存根目录下的`run_journal/<action>.toml`。
+//! 每个任务开始、结束时都会更新运行日志。
+//!
+//! 如果上一次执行没有正常结束（被中断、断电等），下一次执行时：
+//!
+//! - 上次正在执行（`running`）或被中断（`dirty`）的任务会被强制重新执行，避免使用不完整的缓存
+//! - 指定`--resume`时，跳过上次已经完成（`completed`）的任务
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::context::Action;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalTaskState {
+    /// 正在执行。进程异常退出后，运行日志中会残留这个状态
+    Running,
+    /// 已完成
+    Completed,
+    /// 执行失败或被中断
+    Dirty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunJournal {
+    /// 本次执行的开始时间
+    started_at: DateTime<Utc>,
+    /// 是否所有任务都已成功完成
+    #[serde(default)]
+    finished: bool,
+    #[serde(default)]
+    tasks: BTreeMap<String, JournalTaskState>,
+}
+
+impl RunJournal {
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            finished: false,
+            tasks: BTreeMap::new(),
+        }
+    }
+
+    /// 运行日志的路径。清理操作不记录运行日志
+    pub fn path(cache_root: &Path, action: &Action) -> Option<PathBuf> {
+        let name = match action {
+            Action::Build => "build",
+            Action::Install => "install",
+            Action::Clean(_) => return None,
+        };
+        Some(
+            cache_root
+                .join("run_journal")
+                .join(format!("{}.toml", name)),
+        )
+    }
+
+    /// 读取运行日志，文件不存在或无法解析时返回None
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    /// 保存运行日志。先写入临时文件再重命名，保证不会留下写了一半的文件
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let content = toml::to_string(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, content).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn set_finished(&mut self) {
+        self.finished = true;
+    }
+
+    pub fn set_state(&mut self, name_version: String, state: JournalTaskState) {
+        self.tasks.insert(name_version, state);
+    }
+
+    /// 已完成的任务
+    pub fn completed(&self) -> BTreeSet<String> {
+        self.tasks_in(|s| s == JournalTaskState::Completed)
+    }
+
+    /// 需要强制重新执行的任务：被中断的任务，以及异常退出时仍在执行的任务
+    pub fn dirty(&self) -> BTreeSet<String> {
+        self.tasks_in(|s| s != JournalTaskState::Completed)
+    }
+
+    fn tasks_in(&self, f: impl Fn(JournalTaskState) -> bool) -> BTreeSet<String> {
+        self.tasks
+            .iter()
+            .filter(|(_, s)| f(**s))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// # 运行日志记录器
+///
+/// 每次更新任务状态后立即写入磁盘。写入失败只会打印警告，不影响任务的执行
+pub(crate) struct JournalRecorder {
+    path: Option<PathBuf>,
+    journal: RunJournal,
+}
+
+impl JournalRecorder {
+    pub fn new(path: Option<PathBuf>, journal: RunJournal) -> Self {
+        let recorder = Self { path, journal };
+        recorder.save();
+        recorder
+    }
+
+    pub fn set_state(&mut self, name_version: String, state: JournalTaskState) {
+        self.journal.set_state(name_version, state);
+        self.save();
+    }
+
+    pub fn finish(&mut self) {
+        self.journal.set_finished();
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = self.journal.save(path) {
+                log::warn!("Failed to save run journal {}: {}", path.display(), e);
+            }
+        }
+    }
+}
@@ -1,34 +1,38 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     path::PathBuf,
-    process::exit,
-    sync::{
-        atomic::{AtomicI32, Ordering},
-        Arc, Mutex, RwLock,
-    },
-    thread::ThreadId,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
-use log::{error, info};
+use dadk_config::common::task::TaskPriority;
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
 
 use crate::{
     context::{Action, DadkUserExecuteContext},
-    executor::Executor,
+    error::{DadkUserError, ErrorCode},
+    event,
+    executor::{compiler_cache, explain::CommandPlan, kernel_module, Executor, ExecutorError},
+    interrupt,
     parser::task::DADKTask,
+    rdeps::RdepTree,
 };
 
-use self::task_deque::TASK_DEQUE;
+use self::{
+    journal::{JournalRecorder, JournalTaskState, RunJournal},
+    task_deque::{TaskDeque, DEFAULT_THREAD_NUM},
+};
 
+mod host_packages;
+pub(crate) mod install_paths;
+pub mod journal;
 pub mod task_deque;
 #[cfg(test)]
 mod tests;
 
-lazy_static! {
-    // 线程id与任务实体id映射表
-    pub static ref TID_EID: Mutex<HashMap<ThreadId,i32>> = Mutex::new(HashMap::new());
-}
-
 /// # 调度实体内部结构
 #[derive(Debug, Clone)]
 pub struct InnerEntity {
@@ -41,6 +45,8 @@ pub struct InnerEntity {
     indegree: usize,
     /// 子节点
     children: Vec<Arc<SchedEntity>>,
+    /// 执行任务时下载的字节数
+    downloaded_bytes: u64,
 }
 
 /// # 调度实体
@@ -51,7 +57,8 @@ pub struct SchedEntity {
 
 impl PartialEq for SchedEntity {
     fn eq(&self, other: &Self) -> bool {
-        self.inner.lock().unwrap().id == other.inner.lock().unwrap().id
+        // 分别加锁读取id，避免与自身比较时重复加锁导致死锁
+        self.id() == other.id()
     }
 }
 
@@ -71,6 +78,11 @@ impl SchedEntity {
         self.inner.lock().unwrap().task.clone()
     }
 
+    /// 任务的调度优先级
+    pub fn priority(&self) -> TaskPriority {
+        self.inner.lock().unwrap().task.priority
+    }
+
     /// 入度加1
     pub fn add_indegree(&self) {
         self.inner.lock().unwrap().indegree += 1;
@@ -87,6 +99,21 @@ impl SchedEntity {
         self.inner.lock().unwrap().children.push(entity);
     }
 
+    /// 子节点（直接依赖于当前任务的任务）
+    pub fn children(&self) -> Vec<Arc<SchedEntity>> {
+        self.inner.lock().unwrap().children.clone()
+    }
+
+    /// 记录执行任务时下载的字节数
+    pub fn add_downloaded_bytes(&self, bytes: u64) {
+        self.inner.lock().unwrap().downloaded_bytes += bytes;
+    }
+
+    /// 执行任务时下载的字节数
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().downloaded_bytes
+    }
+
     /// 获取入度
     pub fn indegree(&self) -> usize {
         self.inner.lock().unwrap().indegree
@@ -101,6 +128,18 @@ impl SchedEntity {
     /// ## 返回值
     ///
     /// 所有入度为0的子节点集合
+    /// 直接或间接依赖当前任务的所有任务的名称和版本
+    pub fn dependents(&self) -> BTreeSet<String> {
+        let mut dependents = BTreeSet::new();
+        let mut stack = self.children();
+        while let Some(child) = stack.pop() {
+            if dependents.insert(child.task().name_version()) {
+                stack.extend(child.children());
+            }
+        }
+        dependents
+    }
+
     pub fn sub_children_indegree(&self) -> Vec<Arc<SchedEntity>> {
         let mut zero_child = Vec::new();
         let children = &self.inner.lock().unwrap().children;
@@ -120,16 +159,31 @@ impl SchedEntity {
 pub struct SchedEntities {
     /// 任务ID到调度实体的映射
     id2entity: RwLock<BTreeMap<i32, Arc<SchedEntity>>>,
+    /// 任务的`name@version`到任务ID的映射，用于按名称和版本查找依赖
+    ///
+    /// 使用原始的名称和版本，`libc-0.1.0`与`libc.0.1.0`是两个不同的任务
+    /// （它们导出的环境变量名称冲突，在准备全局环境变量时检查）
+    name_version2id: RwLock<BTreeMap<String, i32>>,
 }
 
 impl SchedEntities {
     pub fn new() -> Self {
         Self {
             id2entity: RwLock::new(BTreeMap::new()),
+            name_version2id: RwLock::new(BTreeMap::new()),
         }
     }
 
     pub fn add(&mut self, entity: Arc<SchedEntity>) {
+        // 有重复的任务时，保留先添加的任务
+        self.name_version2id
+            .write()
+            .unwrap()
+            .entry(Self::name_version_key(
+                &entity.task().name,
+                &entity.task().version,
+            ))
+            .or_insert(entity.id());
         self.id2entity
             .write()
             .unwrap()
@@ -142,12 +196,16 @@ impl SchedEntities {
     }
 
     pub fn get_by_name_version(&self, name: &str, version: &str) -> Option<Arc<SchedEntity>> {
-        for e in self.id2entity.read().unwrap().iter() {
-            if e.1.task().name_version_env() == DADKTask::name_version_uppercase(name, version) {
-                return Some(e.1.clone());
-            }
-        }
-        return None;
+        let id = *self
+            .name_version2id
+            .read()
+            .unwrap()
+            .get(&Self::name_version_key(name, version))?;
+        self.get(id)
+    }
+
+    fn name_version_key(name: &str, version: &str) -> String {
+        format!("{}@{}", name, version)
     }
 
     pub fn entities(&self) -> Vec<Arc<SchedEntity>> {
@@ -175,31 +233,46 @@ impl SchedEntities {
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.id2entity.write().unwrap().clear();
+        self.name_version2id.write().unwrap().clear();
     }
 
-    pub fn topo_sort(&self) -> Vec<Arc<SchedEntity>> {
+    /// # 对调度实体进行拓扑排序
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(Vec<Arc<SchedEntity>>)` : 拓扑排序后的调度实体列表
+    /// - `Err(Vec<SchedulerError>)` : 检查到的所有环形依赖、不存在的依赖
+    pub fn topo_sort(&self) -> Result<Vec<Arc<SchedEntity>>, Vec<SchedulerError>> {
         let mut result = Vec::new();
         let mut visited = BTreeMap::new();
+        let mut errors = Vec::new();
         let btree = self.id2entity.write().unwrap().clone();
         for entity in btree.iter() {
             if !visited.contains_key(entity.0) {
-                let r = self.dfs(entity.1, &mut visited, &mut result);
-                if r.is_err() {
-                    let err = r.unwrap_err();
-                    error!("{}", err.display());
-                    println!("Please fix the errors above and try again.");
-                    std::process::exit(1);
+                let r = self.dfs(entity.1, &mut visited, &mut result, &mut errors);
+                if let Err(err) = r {
+                    errors.push(SchedulerError::DependencyCycle(err));
                 }
             }
         }
-        return result;
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(result)
     }
 
+    /// 深度优先遍历依赖关系
+    ///
+    /// 不存在的依赖会被记录到`errors`中，并继续遍历。
+    /// 检测到环形依赖时，会沿着调用栈回溯，记录环形依赖的完整路径，
+    /// 直到回溯到环的起点为止，然后继续遍历其余的依赖。
     fn dfs(
         &self,
         entity: &Arc<SchedEntity>,
         visited: &mut BTreeMap<i32, bool>,
         result: &mut Vec<Arc<SchedEntity>>,
+        errors: &mut Vec<SchedulerError>,
     ) -> Result<(), DependencyCycleError> {
         visited.insert(entity.id(), false);
         for dep in entity.task().depends.iter() {
@@ -214,32 +287,34 @@ impl SchedEntities {
                     let mut err = DependencyCycleError::new(dep_entity.clone());
 
                     err.add(entity.clone(), dep_entity);
+                    // 已经出错的实体不会被再次遍历，避免重复报告同一个环
+                    visited.insert(entity.id(), true);
                     return Err(err);
                 }
                 if !visited.contains_key(&dep_entity.id()) {
                     drop(guard);
-                    let r = self.dfs(&dep_entity, visited, result);
+                    let r = self.dfs(&dep_entity, visited, result, errors);
                     if r.is_err() {
                         let mut err: DependencyCycleError = r.unwrap_err();
-                        // 如果错误已经停止传播，则直接返回
+                        // 如果错误已经停止传播，则记录下来，继续检查其余的依赖
                         if err.stop_propagation {
-                            return Err(err);
+                            errors.push(SchedulerError::DependencyCycle(err));
+                            continue;
                         }
                         // 如果当前实体是错误的起始实体，则停止传播
                         if entity == &err.head_entity {
                             err.stop_propagation();
                         }
                         err.add(entity.clone(), dep_entity);
+                        visited.insert(entity.id(), true);
                         return Err(err);
                     }
                 }
             } else {
-                error!(
-                    "Dependency not found: {} -> {}",
-                    entity.task().name_version(),
-                    dep.name_version()
-                );
-                std::process::exit(1);
+                errors.push(SchedulerError::DependencyNotFound(
+                    entity.clone(),
+                    format!("name:{}, version:{}", dep.name, dep.version),
+                ));
             }
         }
         visited.insert(entity.id(), true);
@@ -266,7 +341,19 @@ pub enum SchedulerError {
     /// 不是当前正在编译的目标架构
     InvalidTargetArch(String),
     DependencyNotFound(Arc<SchedEntity>, String),
+    /// 环形依赖
+    DependencyCycle(DependencyCycleError),
+    /// 拓扑排序时检查到的所有依赖错误
+    DependencyErrors(Vec<SchedulerError>),
     RunError(String),
+    /// 任务执行失败（任务的name_version，错误）
+    TaskFailed(String, ExecutorError),
+    /// 多个任务执行失败
+    TaskErrors(Vec<SchedulerError>),
+    /// 收到中断信号
+    Interrupted(String),
+    /// 构建需要的主机软件包不存在
+    HostPackagesMissing(String),
 }
 
 impl Debug for SchedulerError {
@@ -287,9 +374,62 @@ impl Debug for SchedulerError {
             SchedulerError::RunError(msg) => {
                 write!(f, "RunError: {}", msg)
             }
+            SchedulerError::TaskFailed(task, e) => {
+                write!(f, "Error while executing task {}: {}", task, e)
+            }
+            SchedulerError::TaskErrors(errors) => {
+                let msg = errors
+                    .iter()
+                    .map(|e| format!("{:?}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                write!(f, "{}", msg)
+            }
+            SchedulerError::Interrupted(msg) | SchedulerError::HostPackagesMissing(msg) => {
+                write!(f, "{}", msg)
+            }
             SchedulerError::InvalidTargetArch(msg) => {
                 write!(f, "InvalidTargetArch: {}", msg)
             }
+            SchedulerError::DependencyCycle(err) => {
+                write!(f, "{}", err.display())
+            }
+            SchedulerError::DependencyErrors(errors) => {
+                writeln!(f, "{} dependency error(s) found:", errors.len())?;
+                for e in errors.iter() {
+                    writeln!(f, "{:?}", e)?;
+                }
+                write!(f, "Please fix the errors above and try again.")
+            }
+        }
+    }
+}
+
+impl From<SchedulerError> for DadkUserError {
+    fn from(e: SchedulerError) -> Self {
+        let message = format!("{:?}", e);
+        match e {
+            SchedulerError::TaskError(_) | SchedulerError::InvalidTargetArch(_) => {
+                DadkUserError::new(ErrorCode::InvalidConfig, message)
+            }
+            SchedulerError::DependencyNotFound(entity, _) => {
+                DadkUserError::new(ErrorCode::DependencyMissing, message)
+                    .with_task(entity.task().name_version())
+            }
+            SchedulerError::DependencyCycle(_) => {
+                DadkUserError::new(ErrorCode::DependencyCycle, message)
+            }
+            SchedulerError::RunError(_) => DadkUserError::new(ErrorCode::Other, message),
+            SchedulerError::TaskFailed(task, e) => {
+                DadkUserError::new(e.code(), message).with_task(task)
+            }
+            SchedulerError::DependencyErrors(errors) | SchedulerError::TaskErrors(errors) => {
+                DadkUserError::multiple(message, errors.into_iter().map(Into::into).collect())
+            }
+            SchedulerError::Interrupted(_) => DadkUserError::new(ErrorCode::Interrupted, message),
+            SchedulerError::HostPackagesMissing(_) => {
+                DadkUserError::new(ErrorCode::PrepareFailed, message)
+            }
         }
     }
 }
@@ -357,7 +497,7 @@ impl Scheduler {
             )));
         }
 
-        let id: i32 = self.generate_task_id();
+        let id: i32 = self.generate_task_id(&task);
         let indegree: usize = 0;
         let children = Vec::new();
         let entity = Arc::new(SchedEntity {
@@ -367,19 +507,21 @@ impl Scheduler {
                 file_path: path.clone(),
                 indegree,
                 children,
+                downloaded_bytes: 0,
             }),
         });
         let name_version = (entity.task().name.clone(), entity.task().version.clone());
 
-        if self
+        if let Some(existing) = self
             .target
             .get_by_name_version(&name_version.0, &name_version.1)
-            .is_some()
         {
             return Err(SchedulerError::TaskError(format!(
-                "Task with name [{}] and version [{}] already exists. Config file: {}",
+                "Task with name [{}] and version [{}] is defined in more than one config file: {} and {}. \
+                Set `override = true` in one of them to shadow the other",
                 name_version.0,
                 name_version.1,
+                existing.file_path().display(),
                 path.display()
             )));
         }
@@ -390,9 +532,22 @@ impl Scheduler {
         return Ok(entity);
     }
 
-    fn generate_task_id(&self) -> i32 {
-        static TASK_ID: AtomicI32 = AtomicI32::new(0);
-        return TASK_ID.fetch_add(1, Ordering::SeqCst);
+    /// 根据任务的名称和版本生成任务ID
+    ///
+    /// ID与配置文件的扫描顺序无关，同一个任务在不同机器上的ID相同。
+    /// 与已有任务的ID冲突时，使用下一个未被占用的ID
+    fn generate_task_id(&self, task: &DADKTask) -> i32 {
+        let mut id = Self::stable_task_id(&task.name, &task.version);
+        while self.target.get(id).is_some() {
+            id = id.wrapping_add(1) & i32::MAX;
+        }
+        id
+    }
+
+    /// `name@version`的sha256的前4个字节（非负数）
+    pub(crate) fn stable_task_id(name: &str, version: &str) -> i32 {
+        let digest = Sha256::digest(format!("{}@{}", name, version).as_bytes());
+        i32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) & i32::MAX
     }
 
     /// # 执行调度器中的所有任务
@@ -403,7 +558,26 @@ impl Scheduler {
 
         match self.action {
             Action::Build | Action::Install => {
-                self.run_with_topo_sort()?;
+                if self.action == Action::Install {
+                    self.check_install_paths()?;
+                } else {
+                    self.check_host_packages()?;
+                }
+                // 构建/安装时，收到中断信号后等待正在执行的任务结束，以便记录运行日志
+                let _graceful = interrupt::GracefulScope::new();
+                let r = self.run_with_topo_sort();
+                if self.action == Action::Build {
+                    let tasks: Vec<DADKTask> =
+                        self.target.entities().iter().map(|e| e.task()).collect();
+                    compiler_cache::report(&self.context, &tasks);
+                }
+                r?;
+                if self.action == Action::Install {
+                    let tasks: Vec<DADKTask> =
+                        self.target.entities().iter().map(|e| e.task()).collect();
+                    kernel_module::update_module_index(&self.context, &self.sysroot_dir, &tasks)
+                        .map_err(|e| SchedulerError::RunError(format!("{:?}", e)))?;
+                }
             }
             Action::Clean(_) => self.run_without_topo_sort()?,
         }
@@ -411,95 +585,266 @@ impl Scheduler {
         return Ok(());
     }
 
+    /// # 说明任务构建时将要执行的命令
+    ///
+    /// 与执行任务时一样准备全局环境变量和任务的环境变量，但不执行任何命令
+    pub fn explain_env(&self, name: &str, version: &str) -> Result<CommandPlan, SchedulerError> {
+        crate::executor::prepare_env(&self.target, &self.context)
+            .map_err(|e| SchedulerError::RunError(format!("{:?}", e)))?;
+        let entity = self
+            .target
+            .get_by_name_version(name, version)
+            .ok_or_else(|| {
+                SchedulerError::InvalidTargetArch(format!(
+                    "Task {}-{} is not for target arch: {:?}",
+                    name,
+                    version,
+                    self.context.target_arch()
+                ))
+            })?;
+        let name_version = entity.task().name_version();
+        Executor::new(
+            self.context.clone(),
+            entity,
+            Action::Build,
+            self.sysroot_dir.clone(),
+        )
+        .and_then(|mut executor| executor.explain())
+        .map_err(|e| SchedulerError::TaskFailed(name_version, e))
+    }
+
+    /// # 任务的反向依赖树
+    ///
+    /// 拓扑排序时建立子节点，同时检查不存在的依赖以及环形依赖
+    pub fn rdeps(&self, name: &str, version: &str) -> Result<RdepTree, SchedulerError> {
+        self.target
+            .topo_sort()
+            .map_err(SchedulerError::DependencyErrors)?;
+        let entity = self
+            .target
+            .get_by_name_version(name, version)
+            .ok_or_else(|| {
+                SchedulerError::InvalidTargetArch(format!(
+                    "Task {}-{} is not for target arch: {:?}",
+                    name,
+                    version,
+                    self.context.target_arch()
+                ))
+            })?;
+        Ok(RdepTree::from_entity(&entity))
+    }
+
+    /// 安装之前检查所有任务的安装路径，有冲突时不安装任何文件
+    fn check_install_paths(&self) -> Result<(), SchedulerError> {
+        let tasks: Vec<DADKTask> = self.target.entities().iter().map(|e| e.task()).collect();
+        install_paths::check_install_paths(
+            &tasks,
+            self.context.cache_root(),
+            self.context.install_allowed_paths(),
+        )
+        .map_err(SchedulerError::TaskError)
+    }
+
+    /// 构建之前检查所有任务需要的主机软件包，缺少时不构建任何任务
+    fn check_host_packages(&self) -> Result<(), SchedulerError> {
+        let tasks: Vec<DADKTask> = self.target.entities().iter().map(|e| e.task()).collect();
+        host_packages::check_host_packages(&tasks).map_err(SchedulerError::HostPackagesMissing)
+    }
+
     /// Action需要按照拓扑序执行
     ///
     /// Action::Build | Action::Install
     fn run_with_topo_sort(&self) -> Result<(), SchedulerError> {
-        // 检查是否有不存在的依赖
-        let r = self.check_not_exists_dependency();
-        if r.is_err() {
-            error!("Error while checking tasks: {:?}", r);
-            return r;
-        }
+        // 对调度实体进行拓扑排序，同时检查是否有不存在的依赖、环形依赖
+        let r: Vec<Arc<SchedEntity>> = self
+            .target
+            .topo_sort()
+            .map_err(SchedulerError::DependencyErrors)?;
 
-        // 对调度实体进行拓扑排序
-        let r: Vec<Arc<SchedEntity>> = self.target.topo_sort();
+        let (journal, skip) = self.prepare_journal();
+        for entity in r
+            .iter()
+            .filter(|e| !skip.contains(&e.task().name_version()))
+        {
+            event::task_queued(entity);
+        }
 
         let action = self.action.clone();
         let dragonos_dir = self.sysroot_dir.clone();
-        let id2entity = self.target.id2entity();
         let count = r.len();
+        let context = self.context.clone();
 
         // 启动守护线程
         let handler = std::thread::spawn(move || {
-            Self::build_install_daemon(action, dragonos_dir, id2entity, count, &r)
+            let mut journal = journal;
+            Self::build_install_daemon(
+                context,
+                action,
+                dragonos_dir,
+                count,
+                &r,
+                &mut journal,
+                &skip,
+            )
         });
 
-        handler.join().expect("Could not join deamon");
+        handler.join().expect("Could not join deamon")
+    }
 
-        return Ok(());
+    /// # 准备运行日志
+    ///
+    /// 如果上一次执行没有正常结束，把其中未完成的任务标记为dirty；
+    /// 指定了`resume`时，返回上一次已经完成、本次需要跳过的任务
+    fn prepare_journal(&self) -> (JournalRecorder, BTreeSet<String>) {
+        let path = RunJournal::path(self.context.cache_root(), &self.action);
+        let previous = path
+            .as_deref()
+            .and_then(RunJournal::load)
+            .filter(|j| !j.finished());
+
+        let mut journal = RunJournal::new(*self.context.session_start());
+        let mut skip = BTreeSet::new();
+        match previous {
+            Some(previous) => {
+                let dirty = previous.dirty();
+                if !dirty.is_empty() {
+                    warn!(
+                        "Last run did not finish, tasks will be re-executed: {}",
+                        dirty.iter().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                }
+                for name_version in dirty.iter() {
+                    journal.set_state(name_version.clone(), JournalTaskState::Dirty);
+                }
+                self.context.set_dirty_tasks(dirty);
+
+                if self.context.resume() {
+                    skip = previous.completed();
+                    info!(
+                        "Resuming last run, {} task(s) already completed",
+                        skip.len()
+                    );
+                    for name_version in skip.iter() {
+                        journal.set_state(name_version.clone(), JournalTaskState::Completed);
+                    }
+                }
+            }
+            None => {
+                if self.context.resume() {
+                    info!("No unfinished run to resume, run all tasks");
+                }
+            }
+        }
+        (JournalRecorder::new(path, journal), skip)
     }
 
     /// Action不需要按照拓扑序执行
+    ///
+    /// Action::Clean
     fn run_without_topo_sort(&self) -> Result<(), SchedulerError> {
         // 启动守护线程
         let action = self.action.clone();
         let dragonos_dir = self.sysroot_dir.clone();
-        let mut r = self.target.entities();
-        let handler = std::thread::spawn(move || {
-            Self::clean_daemon(action, dragonos_dir, &mut r);
-        });
+        let r = self.target.entities();
+        let context = self.context.clone();
+        let handler =
+            std::thread::spawn(move || Self::clean_daemon(context, action, dragonos_dir, &r));
 
-        handler.join().expect("Could not join deamon");
-        return Ok(());
+        handler.join().expect("Could not join deamon")
+    }
+
+    pub fn execute(
+        context: Arc<DadkUserExecuteContext>,
+        action: Action,
+        dragonos_dir: PathBuf,
+        entity: Arc<SchedEntity>,
+    ) -> Result<(), SchedulerError> {
+        let start = Instant::now();
+        event::task_started(&entity, &action);
+        let r = Self::do_execute(context, action, dragonos_dir, entity.clone());
+        let error = r.as_ref().err().map(|e| format!("{:?}", e));
+        event::task_finished(&entity, start.elapsed(), error.as_deref());
+        r
     }
 
-    pub fn execute(action: Action, dragonos_dir: PathBuf, entity: Arc<SchedEntity>) {
-        let mut executor = Executor::new(entity.clone(), action.clone(), dragonos_dir.clone())
-            .map_err(|e| {
-                error!(
-                    "Error while creating executor for task {} : {:?}",
-                    entity.task().name_version(),
-                    e
-                );
-                exit(-1);
-            })
-            .unwrap();
-
-        executor
-            .execute()
-            .map_err(|e| {
-                error!(
-                    "Error while executing task {} : {:?}",
-                    entity.task().name_version(),
-                    e
-                );
-                exit(-1);
-            })
-            .unwrap();
+    fn do_execute(
+        context: Arc<DadkUserExecuteContext>,
+        action: Action,
+        dragonos_dir: PathBuf,
+        entity: Arc<SchedEntity>,
+    ) -> Result<(), SchedulerError> {
+        if context.interrupt_state().is_task_cancelled(entity.id()) {
+            let msg = format!("Task {} cancelled", entity.task().name_version());
+            error!("{}", msg);
+            return Err(SchedulerError::Interrupted(msg));
+        }
+
+        let mut executor = Executor::new(
+            context,
+            entity.clone(),
+            action.clone(),
+            dragonos_dir.clone(),
+        )
+        .map_err(|e| {
+            error!(
+                "Error while creating executor for task {}: {}",
+                entity.task().name_version(),
+                e
+            );
+            SchedulerError::TaskFailed(entity.task().name_version(), e)
+        })?;
+
+        executor.execute().map_err(|e| {
+            let e = SchedulerError::TaskFailed(entity.task().name_version(), e);
+            error!("{:?}", e);
+            e
+        })?;
+
+        return Ok(());
     }
 
     /// 构建和安装DADK任务的守护线程
     ///
+    /// 构建和安装时，任务的依赖都完成后才会开始执行；依赖都已完成的任务会被同时调度。
+    ///
+    /// 当某个任务执行失败后，不再调度新的任务，等待正在执行的任务结束后返回错误。
+    ///
     /// ## 参数
     ///
+    /// - `context` : dadk执行的上下文
     /// - `action` : 要执行的操作
     /// - `dragonos_dir` : DragonOS sysroot在主机上的路径
-    /// - `id2entity` : DADK任务id与实体映射表
     /// - `count` : 当前剩余任务数
     /// - `r` : 总任务实体表
+    /// - `journal` : 运行日志
+    /// - `skip` : 上次执行中已经完成、本次跳过的任务
     ///
     /// ## 返回值
     ///
-    /// 无
-    pub fn build_install_daemon(
+    /// 所有任务执行成功时返回Ok，否则返回失败任务的错误信息
+    /// 从可以开始执行的任务中，选出下一个要执行的任务
+    ///
+    /// 优先级最高的任务先执行，优先级相同时按照名称和版本的顺序执行，
+    /// 使执行顺序与任务完成的先后无关
+    pub(crate) fn next_ready(ready: &[Arc<SchedEntity>]) -> Option<usize> {
+        ready
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, e)| (e.priority(), Reverse(e.task().name_version())))
+            .map(|(i, _)| i)
+    }
+
+    pub(crate) fn build_install_daemon(
+        context: Arc<DadkUserExecuteContext>,
         action: Action,
         dragonos_dir: PathBuf,
-        id2entity: BTreeMap<i32, Arc<SchedEntity>>,
         mut count: usize,
         r: &Vec<Arc<SchedEntity>>,
-    ) {
-        let mut guard = TASK_DEQUE.lock().unwrap();
+        journal: &mut JournalRecorder,
+        skip: &BTreeSet<String>,
+    ) -> Result<(), SchedulerError> {
+        let mut task_deque = TaskDeque::new(context.thread_num().unwrap_or(DEFAULT_THREAD_NUM));
+        let mut failed: Vec<SchedulerError> = Vec::new();
         // 初始化0入度的任务实体
         let mut zero_entity: Vec<Arc<SchedEntity>> = Vec::new();
         for e in r.iter() {
@@ -510,73 +855,138 @@ impl Scheduler {
 
         while count > 0 {
             // 将入度为0的任务实体加入任务队列中，直至没有入度为0的任务实体 或 任务队列满了
-            while !zero_entity.is_empty()
-                && guard.build_install_task(
+            while failed.is_empty() && !context.interrupt_state().is_interrupted() {
+                let Some(index) = Self::next_ready(&zero_entity) else {
+                    break;
+                };
+                let entity = zero_entity[index].clone();
+                let name_version = entity.task().name_version();
+                if skip.contains(&name_version) {
+                    info!("Task {} completed in last run, skip.", name_version);
+                    zero_entity.remove(index);
+                    count -= 1;
+                    zero_entity.extend(entity.sub_children_indegree());
+                    continue;
+                }
+                if !task_deque.spawn_task(
+                    context.clone(),
                     action.clone(),
                     dragonos_dir.clone(),
-                    zero_entity.last().unwrap().clone(),
-                )
-            {
-                zero_entity.pop();
+                    entity,
+                ) {
+                    break;
+                }
+                journal.set_state(name_version, JournalTaskState::Running);
+                zero_entity.remove(index);
             }
 
-            let queue = guard.queue_mut();
             // 如果任务线程已完成，将其从任务队列中删除，并把它的子节点入度减1，如果有0入度子节点，则加入zero_entity，后续可以加入任务队列中
-            queue.retain(|x| {
-                if x.is_finished() {
-                    count -= 1;
-                    let tid = x.thread().id();
-                    let eid = *TID_EID.lock().unwrap().get(&tid).unwrap();
-                    let entity = id2entity.get(&eid).unwrap();
-                    let zero = entity.sub_children_indegree();
-                    for e in zero.iter() {
-                        zero_entity.push(e.clone());
+            for (entity, result) in task_deque.take_finished() {
+                count -= 1;
+                match result {
+                    Ok(()) => {
+                        journal
+                            .set_state(entity.task().name_version(), JournalTaskState::Completed);
+                        zero_entity.extend(entity.sub_children_indegree());
+                    }
+                    Err(e) => {
+                        journal.set_state(entity.task().name_version(), JournalTaskState::Dirty);
+                        failed.push(e);
                     }
-                    return false;
                 }
-                return true;
-            })
+            }
+
+            // 有任务失败或收到中断信号时，等待正在执行的任务结束后退出
+            if (!failed.is_empty() || context.interrupt_state().is_interrupted())
+                && task_deque.queue().is_empty()
+            {
+                break;
+            }
+        }
+
+        if context.interrupt_state().is_interrupted() {
+            return Err(SchedulerError::Interrupted(format!(
+                "Interrupted, {} task(s) not finished. Run again with `--resume` to continue",
+                count
+            )));
         }
+
+        if failed.len() == 1 {
+            return Err(failed.pop().unwrap());
+        } else if !failed.is_empty() {
+            return Err(SchedulerError::TaskErrors(failed));
+        }
+        journal.finish();
+        return Ok(());
     }
 
     /// 清理DADK任务的守护线程
     ///
+    /// 各个任务的清理互不依赖，使用与构建相同的任务队列，最多同时清理`thread_num`个任务。
+    /// 某个任务清理失败时，继续清理其他任务。
+    ///
     /// ## 参数
     ///
+    /// - `context` : dadk执行的上下文
     /// - `action` : 要执行的操作
     /// - `dragonos_dir` : DragonOS sysroot在主机上的路径
     /// - `r` : 总任务实体表
     ///
     /// ## 返回值
     ///
-    /// 无
-    pub fn clean_daemon(action: Action, dragonos_dir: PathBuf, r: &mut Vec<Arc<SchedEntity>>) {
-        let mut guard = TASK_DEQUE.lock().unwrap();
-        while !guard.queue().is_empty() && !r.is_empty() {
-            guard.clean_task(action, dragonos_dir.clone(), r.pop().unwrap().clone());
-        }
-    }
+    /// 所有任务清理成功时返回Ok，否则返回失败任务的错误信息
+    pub fn clean_daemon(
+        context: Arc<DadkUserExecuteContext>,
+        action: Action,
+        dragonos_dir: PathBuf,
+        r: &[Arc<SchedEntity>],
+    ) -> Result<(), SchedulerError> {
+        let mut task_deque = TaskDeque::new(context.thread_num().unwrap_or(DEFAULT_THREAD_NUM));
+        let mut pending: Vec<Arc<SchedEntity>> = r.to_vec();
+        let mut failed: Vec<SchedulerError> = Vec::new();
+
+        loop {
+            while !context.interrupt_state().is_interrupted() {
+                let Some(index) = Self::next_ready(&pending) else {
+                    break;
+                };
+                if !task_deque.spawn_task(
+                    context.clone(),
+                    action,
+                    dragonos_dir.clone(),
+                    pending[index].clone(),
+                ) {
+                    break;
+                }
+                pending.remove(index);
+            }
 
-    /// # 检查是否有不存在的依赖
-    ///
-    /// 如果某个任务的dependency中的任务不存在，则返回错误
-    fn check_not_exists_dependency(&self) -> Result<(), SchedulerError> {
-        for entity in self.target.entities().iter() {
-            for dependency in entity.task().depends.iter() {
-                let name_version = (dependency.name.clone(), dependency.version.clone());
-                if !self
-                    .target
-                    .get_by_name_version(&name_version.0, &name_version.1)
-                    .is_some()
-                {
-                    return Err(SchedulerError::DependencyNotFound(
-                        entity.clone(),
-                        format!("name:{}, version:{}", name_version.0, name_version.1,),
-                    ));
+            for (_, result) in task_deque.take_finished() {
+                if let Err(e) = result {
+                    failed.push(e);
                 }
             }
+
+            if task_deque.queue().is_empty()
+                && (pending.is_empty() || context.interrupt_state().is_interrupted())
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
         }
 
+        if context.interrupt_state().is_interrupted() {
+            return Err(SchedulerError::Interrupted(format!(
+                "Interrupted, {} task(s) not cleaned",
+                pending.len()
+            )));
+        }
+
+        if failed.len() == 1 {
+            return Err(failed.pop().unwrap());
+        } else if !failed.is_empty() {
+            return Err(SchedulerError::TaskErrors(failed));
+        }
         return Ok(());
     }
 }
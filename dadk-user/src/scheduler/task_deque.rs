@@ -1,37 +1,39 @@
-use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    thread::JoinHandle,
-};
+use std::{path::PathBuf, sync::Arc, thread::JoinHandle};
 
-use crate::{context::Action, scheduler::TID_EID};
+use crate::context::{Action, DadkUserExecuteContext};
 
-use super::{SchedEntity, Scheduler};
+use super::{SchedEntity, Scheduler, SchedulerError};
 
 // 最大线程数
 pub const MAX_THREAD_NUM: usize = 32;
 // 默认线程数
 pub const DEFAULT_THREAD_NUM: usize = 2;
 
-lazy_static! {
-    // 全局任务队列
-    pub static ref TASK_DEQUE: Mutex<TaskDeque> = Mutex::new(TaskDeque {
-        max_num: DEFAULT_THREAD_NUM,
-        queue: Vec::new(),
-    });
-}
+/// 正在执行的任务：任务实体及其工作线程
+pub type TaskHandle = (Arc<SchedEntity>, JoinHandle<Result<(), SchedulerError>>);
 
 /// # 任务队列
 pub struct TaskDeque {
-    max_num: usize,
-    queue: Vec<JoinHandle<()>>,
+    /// 最多同时执行的任务数量
+    pub(super) max_num: usize,
+    queue: Vec<TaskHandle>,
 }
 
 impl TaskDeque {
-    /// 将构建或安装DADK任务添加到任务队列中
+    pub fn new(thread: usize) -> Self {
+        let mut deque = Self {
+            max_num: DEFAULT_THREAD_NUM,
+            queue: Vec::new(),
+        };
+        deque.set_thread(thread);
+        deque
+    }
+
+    /// 将DADK任务添加到任务队列中，在新的工作线程中执行
     ///
     /// ## 参数
     ///
+    /// - `context` : dadk执行的上下文
     /// - `action` : 要执行的操作
     /// - `dragonos_dir` : DragonOS sysroot在主机上的路径
     /// - `entity` : 任务实体
@@ -39,58 +41,51 @@ impl TaskDeque {
     /// ## 返回值
     ///
     /// true 任务添加成功
-    /// false 任务添加失败
-    pub fn build_install_task(
+    /// false 任务队列已满，任务添加失败
+    pub fn spawn_task(
         &mut self,
+        context: Arc<DadkUserExecuteContext>,
         action: Action,
         dragonos_dir: PathBuf,
         entity: Arc<SchedEntity>,
     ) -> bool {
-        // log::warn!("push stack: task:{} {entity:?}", entity.id());
         if self.queue.len() < self.max_num {
-            let id = entity.id();
+            let e = entity.clone();
             let handler = std::thread::spawn(move || {
-                Scheduler::execute(action, dragonos_dir.clone(), entity)
+                Scheduler::execute(context, action, dragonos_dir.clone(), e)
             });
-            TID_EID.lock().unwrap().insert(handler.thread().id(), id);
-            self.queue.push(handler);
+            self.queue.push((entity, handler));
             return true;
         }
         return false;
     }
 
-    /// 将清理DADK任务添加到任务队列中
-    ///
-    /// ## 参数
-    ///
-    /// - `action` : 要执行的操作
-    /// - `dragonos_dir` : DragonOS sysroot在主机上的路径
-    /// - `entity` : 任务实体
-    ///
-    /// ## 返回值
-    ///
-    /// 无
-    pub fn clean_task(&mut self, action: Action, dragonos_dir: PathBuf, entity: Arc<SchedEntity>) {
-        while self.queue.len() >= self.max_num {
-            self.queue.retain(|x| !x.is_finished());
+    /// 从任务队列中取出所有已经执行完毕的任务，及其执行结果
+    pub fn take_finished(&mut self) -> Vec<(Arc<SchedEntity>, Result<(), SchedulerError>)> {
+        let mut finished = Vec::new();
+        let mut i = 0;
+        while i < self.queue.len() {
+            if self.queue[i].1.is_finished() {
+                let (entity, handler) = self.queue.swap_remove(i);
+                let result = handler.join().unwrap_or_else(|_| {
+                    Err(SchedulerError::RunError(format!(
+                        "Worker thread of task {} panicked",
+                        entity.task().name_version()
+                    )))
+                });
+                finished.push((entity, result));
+            } else {
+                i += 1;
+            }
         }
-        let handler =
-            std::thread::spawn(move || Scheduler::execute(action, dragonos_dir.clone(), entity));
-        self.queue.push(handler);
+        finished
     }
 
-    pub fn queue(&self) -> &Vec<JoinHandle<()>> {
+    pub fn queue(&self) -> &Vec<TaskHandle> {
         return &self.queue;
     }
 
-    pub fn queue_mut(&mut self) -> &mut Vec<JoinHandle<()>> {
-        return &mut self.queue;
-    }
-
-    pub fn set_thread(&mut self, mut thread: usize) {
-        if thread > MAX_THREAD_NUM {
-            thread = MAX_THREAD_NUM;
-        }
-        self.max_num = thread;
+    pub fn set_thread(&mut self, thread: usize) {
+        self.max_num = thread.clamp(1, MAX_THREAD_NUM);
     }
 }
@@ -180,3 +180,596 @@ fn ensure_all_target_arch_testcase_v1(ctx: &BaseGlobalTestContext) {
         );
     }
 }
+
+/// 以`app_all_target_arch_0_2_0.toml`为基础，创建具有指定名称和依赖的任务
+fn task_with_depends<T: TestContextExt>(
+    ctx: &T,
+    name: &str,
+    depends: &[&str],
+) -> (PathBuf, DADKTask) {
+    let config_file = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_all_target_arch_0_2_0.toml");
+    let mut task = Parser::new(ctx.base_context().config_v2_dir())
+        .parse_config_file(&config_file)
+        .unwrap();
+    task.name = name.to_string();
+    task.depends = depends
+        .iter()
+        .map(|d| dadk_config::common::task::Dependency::new(d.to_string(), task.version.clone()))
+        .collect();
+    (config_file, task)
+}
+
+fn setup_scheduler<T: TestContextExt>(ctx: &T, tasks: Vec<(PathBuf, DADKTask)>) -> Scheduler {
+    let scheduler = Scheduler::new(
+        ctx.execute_context().self_ref().unwrap(),
+        ctx.base_context().fake_dragonos_sysroot(),
+        *ctx.execute_context().action(),
+        tasks,
+    );
+    assert!(scheduler.is_ok(), "Create scheduler error: {:?}", scheduler);
+    scheduler.unwrap()
+}
+
+/// 拓扑排序应保证依赖在被依赖者之前
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn topo_sort_orders_dependencies_first(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let scheduler = setup_scheduler(
+        ctx,
+        vec![
+            task_with_depends(ctx, "app", &["libfoo"]),
+            task_with_depends(ctx, "libfoo", &["libc"]),
+            task_with_depends(ctx, "libc", &[]),
+        ],
+    );
+    let r = scheduler.target.topo_sort();
+    assert!(r.is_ok(), "topo sort error: {:?}", r.err());
+    let names: Vec<String> = r.unwrap().iter().map(|e| e.task().name).collect();
+    assert_eq!(names, vec!["libc", "libfoo", "app"]);
+}
+
+/// 反向依赖树沿着子节点展开，通过多条路径依赖的任务只展开一次
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn rdeps_lists_transitive_dependents(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let scheduler = setup_scheduler(
+        ctx,
+        vec![
+            task_with_depends(ctx, "app", &["libfoo", "libbar"]),
+            task_with_depends(ctx, "libfoo", &["libc"]),
+            task_with_depends(ctx, "libbar", &["libc"]),
+            task_with_depends(ctx, "libc", &[]),
+            task_with_depends(ctx, "other", &[]),
+        ],
+    );
+    let version = scheduler.target.entities()[0].task().version;
+    let tree = scheduler.rdeps("libc", &version).unwrap();
+    let names: Vec<String> = tree
+        .flat()
+        .iter()
+        .map(|t| t.split('@').next().unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["app", "libbar", "libfoo"]);
+    let repeated: Vec<bool> = tree
+        .dependents
+        .iter()
+        .map(|d| d.dependents[0].repeated)
+        .collect();
+    assert_eq!(repeated, vec![false, true]);
+
+    let leaf = scheduler.rdeps("other", &version).unwrap();
+    assert!(leaf.flat().is_empty());
+}
+
+/// 多个任务可以同时开始执行时，优先级高的任务先执行；优先级相同时，最后加入的任务先执行
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn next_ready_prefers_higher_priority(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use dadk_config::common::task::TaskPriority;
+
+    let mut tasks = vec![
+        task_with_depends(ctx, "relibc", &[]),
+        task_with_depends(ctx, "llvm", &[]),
+        task_with_depends(ctx, "app", &[]),
+        task_with_depends(ctx, "tool", &[]),
+    ];
+    tasks[0].1.priority = TaskPriority(5);
+    tasks[1].1.priority = TaskPriority::HIGH;
+    tasks[3].1.priority = TaskPriority::LOW;
+    let scheduler = setup_scheduler(ctx, tasks);
+    let mut ready = scheduler.target.entities();
+
+    let mut order = Vec::new();
+    while let Some(index) = Scheduler::next_ready(&ready) {
+        order.push(ready.remove(index).task().name);
+    }
+    assert_eq!(order, vec!["llvm", "relibc", "app", "tool"]);
+}
+
+/// 优先级相同的任务按照名称和版本的顺序执行，与加入的先后无关
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn next_ready_is_deterministic(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let scheduler = setup_scheduler(
+        ctx,
+        vec![
+            task_with_depends(ctx, "c", &[]),
+            task_with_depends(ctx, "a", &[]),
+            task_with_depends(ctx, "b", &[]),
+        ],
+    );
+    for reversed in [false, true] {
+        let mut ready = scheduler.target.entities();
+        if reversed {
+            ready.reverse();
+        }
+        let mut order = Vec::new();
+        while let Some(index) = Scheduler::next_ready(&ready) {
+            order.push(ready.remove(index).task().name);
+        }
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+}
+
+/// 任务ID由名称和版本决定，与任务加入的顺序无关
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn stable_task_ids(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let tasks = vec![
+        task_with_depends(ctx, "a", &[]),
+        task_with_depends(ctx, "b", &["a"]),
+    ];
+    let ids = |tasks: Vec<(PathBuf, DADKTask)>| {
+        setup_scheduler(ctx, tasks)
+            .target
+            .entities()
+            .iter()
+            .map(|e| (e.task().name, e.id()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let forward = ids(tasks.clone());
+    let backward = ids(tasks.into_iter().rev().collect());
+    assert_eq!(forward, backward);
+    let version = task_with_depends(ctx, "a", &[]).1.version;
+    assert_eq!(forward["a"], Scheduler::stable_task_id("a", &version));
+    assert!(forward.values().all(|id| *id >= 0));
+    assert_ne!(forward["a"], forward["b"]);
+}
+
+/// 拓扑排序应一次性报告所有不存在的依赖和环形依赖，而不是退出进程
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn topo_sort_reports_all_dependency_errors(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let scheduler = setup_scheduler(
+        ctx,
+        vec![
+            task_with_depends(ctx, "a", &["b"]),
+            task_with_depends(ctx, "b", &["a"]),
+            task_with_depends(ctx, "c", &["missing1"]),
+            task_with_depends(ctx, "d", &["c", "missing2"]),
+            task_with_depends(ctx, "e", &["e"]),
+        ],
+    );
+    let errors = scheduler
+        .target
+        .topo_sort()
+        .expect_err("topo sort should fail");
+
+    let not_found = errors
+        .iter()
+        .filter(|e| matches!(e, SchedulerError::DependencyNotFound(_, _)))
+        .count();
+    let cycles = errors
+        .iter()
+        .filter(|e| matches!(e, SchedulerError::DependencyCycle(_)))
+        .count();
+    assert_eq!(not_found, 2, "errors: {:?}", errors);
+    assert_eq!(cycles, 2, "errors: {:?}", errors);
+}
+
+/// 调度器的错误转换为带有错误码的错误
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn scheduler_error_codes(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let scheduler = setup_scheduler(
+        ctx,
+        vec![
+            task_with_depends(ctx, "c", &["missing1"]),
+            task_with_depends(ctx, "d", &["missing2"]),
+        ],
+    );
+    let errors = scheduler.target.topo_sort().unwrap_err();
+    let e: DadkUserError = SchedulerError::DependencyErrors(errors).into();
+    assert_eq!(e.code(), ErrorCode::DependencyMissing);
+    assert_eq!(e.errors().len(), 2);
+    assert!(e.errors().iter().all(|e| e.task().is_some()));
+    assert!(e.to_string().starts_with("2 dependency error(s) found"));
+
+    let e: DadkUserError = SchedulerError::TaskErrors(vec![
+        SchedulerError::TaskFailed(
+            "a-0.1.0".to_string(),
+            ExecutorError::FetchFailed("timed out".to_string()),
+        ),
+        SchedulerError::TaskFailed(
+            "b-0.1.0".to_string(),
+            ExecutorError::TaskFailed("make exited with 2".to_string()),
+        ),
+    ])
+    .into();
+    assert_eq!(e.code(), ErrorCode::Multiple);
+    let json: serde_json::Value = serde_json::from_str(&e.to_json()).unwrap();
+    assert_eq!(json["code"], "multiple");
+    assert_eq!(json["errors"][0]["code"], "fetch-failed");
+    assert_eq!(json["errors"][0]["task"], "a-0.1.0");
+    assert_eq!(json["errors"][1]["code"], "build-failed");
+    assert_eq!(
+        json["errors"][1]["message"],
+        "Error while executing task b-0.1.0: make exited with 2"
+    );
+    assert!(json.get("task").is_none());
+}
+
+/// 重复的任务应报告两个配置文件的路径
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn duplicate_task_reports_both_config_files(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    let (path, task) = task_with_depends(ctx, "app", &[]);
+    let overlay = PathBuf::from("overlay/app.toml");
+    let r = Scheduler::new(
+        ctx.execute_context().self_ref().unwrap(),
+        ctx.base_context().fake_dragonos_sysroot(),
+        *ctx.execute_context().action(),
+        vec![(path.clone(), task.clone()), (overlay.clone(), task)],
+    );
+    let Err(SchedulerError::TaskError(msg)) = r else {
+        panic!("expected duplicate task error: {:?}", r.err());
+    };
+    assert!(msg.contains(&path.display().to_string()), "{}", msg);
+    assert!(msg.contains("overlay/app.toml"), "{}", msg);
+    assert!(msg.contains("override = true"), "{}", msg);
+}
+
+/// 运行日志：保存后能被重新读取，并正确区分已完成与需要重新执行的任务
+#[test]
+fn run_journal_roundtrip() {
+    use chrono::Utc;
+    use dadk_config::user::UserCleanLevel;
+
+    use super::journal::{JournalTaskState, RunJournal};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let path = RunJournal::path(cache_root, &Action::Build).unwrap();
+    assert_eq!(path, cache_root.join("run_journal/build.toml"));
+    assert_eq!(
+        RunJournal::path(cache_root, &Action::Install).unwrap(),
+        cache_root.join("run_journal/install.toml")
+    );
+    assert!(RunJournal::path(cache_root, &Action::Clean(UserCleanLevel::All)).is_none());
+    assert!(RunJournal::load(&path).is_none());
+
+    let mut journal = RunJournal::new(Utc::now());
+    journal.set_state("a-0.1.0".to_string(), JournalTaskState::Completed);
+    journal.set_state("b-0.1.0".to_string(), JournalTaskState::Running);
+    journal.set_state("c-0.1.0".to_string(), JournalTaskState::Dirty);
+    journal.save(&path).unwrap();
+
+    let loaded = RunJournal::load(&path).unwrap();
+    assert_eq!(loaded, journal);
+    assert!(!loaded.finished());
+    assert_eq!(
+        loaded.completed().into_iter().collect::<Vec<_>>(),
+        vec!["a-0.1.0"]
+    );
+    assert_eq!(
+        loaded.dirty().into_iter().collect::<Vec<_>>(),
+        vec!["b-0.1.0", "c-0.1.0"]
+    );
+
+    journal.set_finished();
+    journal.save(&path).unwrap();
+    assert!(RunJournal::load(&path).unwrap().finished());
+    assert!(!path.with_extension("toml.tmp").exists());
+}
+
+/// 安装前报告所有冲突的文件以及不在允许目录中的文件
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn install_path_conflicts(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use crate::executor::cache::{CacheDir, CacheDirType};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let mut tasks = Vec::new();
+    for (name, in_path, file) in [
+        ("a", "/bin", "app"),
+        ("b", "//bin/", "app"),
+        ("c", "/opt/c", "c"),
+    ] {
+        let (_, mut task) = task_with_depends(ctx, name, &[]);
+        task.install.in_dragonos_path = Some(PathBuf::from(in_path));
+        let build_dir = CacheDir::get_path(cache_root, &task, CacheDirType::Build);
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(build_dir.join(file), "").unwrap();
+        tasks.push(task);
+    }
+    // 没有构建结果的任务不检查
+    let (_, mut unbuilt) = task_with_depends(ctx, "d", &[]);
+    unbuilt.install.in_dragonos_path = Some(PathBuf::from("/bin"));
+    tasks.push(unbuilt);
+
+    let err = install_paths::check_install_paths(&tasks, cache_root, &[]).unwrap_err();
+    assert!(err.starts_with("1 install path problem(s)"), "{}", err);
+    assert!(
+        err.contains("/bin/app is installed by a_0_2_0, b_0_2_0"),
+        "{}",
+        err
+    );
+
+    let allowed = [PathBuf::from("/bin"), PathBuf::from("/usr")];
+    let err = install_paths::check_install_paths(&tasks, cache_root, &allowed).unwrap_err();
+    assert!(err.starts_with("2 install path problem(s)"), "{}", err);
+    assert!(err.contains("/opt/c/c is not under any of install.allowed_paths"));
+
+    assert!(install_paths::check_install_paths(&tasks[2..], cache_root, &[]).is_ok());
+
+    // `install.exclude`排除的文件不会被安装，不会冲突
+    tasks[1].install.exclude = vec!["app".to_string()];
+    assert!(install_paths::check_install_paths(&tasks, cache_root, &[]).is_ok());
+}
+
+/// 任务登记相同的文件，或者互为父目录的文件时失败，失败时不登记任何文件；
+/// 依赖当前任务的任务可以覆盖它的文件
+#[test]
+fn install_claims_conflicts() {
+    use std::collections::BTreeSet;
+
+    use super::install_paths::InstallClaims;
+
+    let files = |files: &[&str]| -> Vec<String> { files.iter().map(|f| f.to_string()).collect() };
+    let claims = InstallClaims::default();
+    assert!(claims
+        .claim("a", BTreeSet::new(), &files(&["/bin/a", "/usr/lib"]))
+        .is_ok());
+    assert!(claims
+        .claim("b", BTreeSet::new(), &files(&["/bin/b"]))
+        .is_ok());
+
+    let err = claims
+        .claim(
+            "c",
+            BTreeSet::new(),
+            &files(&["/bin/c", "/bin/a", "/usr/lib/libc.so"]),
+        )
+        .unwrap_err();
+    assert!(
+        err.contains("/bin/a conflicts with /bin/a installed by a"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("/usr/lib/libc.so conflicts with /usr/lib installed by a"),
+        "{}",
+        err
+    );
+    let err = claims
+        .claim("d", BTreeSet::new(), &files(&["/bin"]))
+        .unwrap_err();
+    assert!(
+        err.contains("/bin conflicts with /bin/a installed by a"),
+        "{}",
+        err
+    );
+
+    // 失败的任务没有登记任何文件；同一个任务可以再次登记自己的文件
+    assert!(claims
+        .claim("e", BTreeSet::new(), &files(&["/bin/c"]))
+        .is_ok());
+    assert!(claims
+        .claim("a", BTreeSet::new(), &files(&["/bin/a"]))
+        .is_ok());
+
+    // 依赖f的任务g可以覆盖f的文件，f不能覆盖g的文件
+    let dependents = BTreeSet::from(["g".to_string()]);
+    assert!(claims
+        .claim("f", dependents, &files(&["/etc/f.conf"]))
+        .is_ok());
+    assert!(claims
+        .claim("g", BTreeSet::new(), &files(&["/etc/f.conf"]))
+        .is_ok());
+    assert!(claims
+        .claim("f", BTreeSet::new(), &files(&["/etc/f.conf"]))
+        .is_err());
+}
+
+/// 清理时所有任务都会被执行，不受入度和任务队列容量的影响
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn clean_daemon_cleans_all_tasks(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use dadk_config::user::UserCleanLevel;
+
+    use crate::{
+        context::DadkUserExecuteContextBuilder,
+        executor::cache::{CacheDir, CacheDirType},
+    };
+
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path().to_path_buf();
+    let action = Action::Clean(UserCleanLevel::Output);
+    let context = DadkUserExecuteContextBuilder::default()
+        .sysroot_dir(Some(ctx.base_context().fake_dragonos_sysroot()))
+        .config_dir(Some(ctx.base_context().config_v2_dir()))
+        .action(action)
+        .thread_num(None)
+        .cache_dir(Some(cache_root.clone()))
+        .base_test_context(Some(ctx.base_context().clone()))
+        .build()
+        .unwrap();
+    let context = Arc::new(context);
+    context.init(context.clone()).unwrap();
+
+    let tasks: Vec<_> = (0..5)
+        .map(|i| task_with_depends(ctx, &format!("clean_daemon_{}", i), &[]))
+        .collect();
+    let scheduler = Scheduler::new(
+        context.clone(),
+        ctx.base_context().fake_dragonos_sysroot(),
+        action,
+        tasks,
+    )
+    .unwrap();
+    let build_dirs: Vec<PathBuf> = scheduler
+        .target
+        .entities()
+        .iter()
+        .map(|e| CacheDir::get_path(&cache_root, &e.task(), CacheDirType::Build))
+        .collect();
+    for dir in &build_dirs {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("output"), "").unwrap();
+    }
+
+    // 第二次清理时会读取第一次清理写入的任务日志，其中没有构建时间
+    for _ in 0..2 {
+        let r = Scheduler::clean_daemon(
+            context.clone(),
+            action,
+            scheduler.sysroot_dir.clone(),
+            &scheduler.target.entities(),
+        );
+        assert!(r.is_ok(), "clean error: {:?}", r);
+        for dir in &build_dirs {
+            assert!(
+                !dir.join("output").exists(),
+                "{} not cleaned",
+                dir.display()
+            );
+        }
+    }
+}
+
+/// 任务队列的容量限制在1到MAX_THREAD_NUM之间
+#[test]
+fn task_deque_thread_limit() {
+    use super::task_deque::{TaskDeque, MAX_THREAD_NUM};
+
+    let mut deque = TaskDeque::new(0);
+    assert_eq!(deque.max_num, 1);
+    deque.set_thread(MAX_THREAD_NUM + 1);
+    assert_eq!(deque.max_num, MAX_THREAD_NUM);
+    deque.set_thread(8);
+    assert_eq!(deque.max_num, 8);
+}
+
+/// 只有安装需要sysroot目录：构建时可以不指定，安装时目录不存在则报错或者按要求创建
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn sysroot_only_required_for_install(ctx: &BaseGlobalTestContext) {
+    use crate::context::{Action, DadkUserExecuteContextBuilder};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("sysroot");
+    let init = |action: Action, sysroot: Option<PathBuf>, create: bool| {
+        let context = DadkUserExecuteContextBuilder::default()
+            .sysroot_dir(sysroot)
+            .config_dir(Some(ctx.config_v2_dir()))
+            .action(action)
+            .thread_num(None)
+            .cache_dir(Some(ctx.fake_dadk_cache_root()))
+            .create_sysroot(create)
+            .base_test_context(Some(ctx.clone()))
+            .build()
+            .unwrap();
+        let context = Arc::new(context);
+        context.init(context.clone())
+    };
+
+    assert!(init(Action::Build, None, false).is_ok());
+    assert!(init(Action::Build, Some(missing.clone()), false).is_ok());
+    assert!(init(Action::Install, None, false).is_err());
+    let e = init(Action::Install, Some(missing.clone()), false).unwrap_err();
+    assert!(e.to_string().contains("--create-sysroot"), "{}", e);
+    assert!(!missing.exists());
+
+    assert!(init(Action::Install, Some(missing.clone()), true).is_ok());
+    assert!(missing.is_dir());
+}
+
+/// 缺少的主机软件包汇总在一条错误中
+#[test_context(DadkExecuteContextTestBuildX86_64V1)]
+#[test]
+fn host_packages_missing(ctx: &DadkExecuteContextTestBuildX86_64V1) {
+    use dadk_config::common::task::HostPackage;
+    use host_packages::{missing_host_packages, PackageManager};
+
+    let config_file = ctx
+        .base_context()
+        .config_v2_dir()
+        .join("app_normal_with_env_0_2_0.toml");
+    let mut a = Parser::new(ctx.base_context().config_v2_dir())
+        .parse_config_file(&config_file)
+        .unwrap();
+    let mut b = a.clone();
+    b.name = "other".to_string();
+    a.host_packages = vec![
+        HostPackage::new("sh"),
+        HostPackage::new("dadk-missing-tool"),
+    ];
+    b.host_packages = vec![
+        HostPackage::new("dadk-missing-tool"),
+        HostPackage {
+            package: "dadk-missing-pkg".to_string(),
+            command: Some("dadk-missing-cmd".to_string()),
+        },
+    ];
+
+    let path = std::ffi::OsString::from("/bin:/usr/bin");
+    let err = missing_host_packages(&[a.clone(), b.clone()], &path, Some(PackageManager::Apt))
+        .unwrap_err();
+    assert!(err.starts_with("2 host package(s)"), "{}", err);
+    assert!(
+        err.contains(&format!(
+            "dadk-missing-tool: needed by {}, {}",
+            a.name_version(),
+            b.name_version()
+        )),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("dadk-missing-pkg (command dadk-missing-cmd)"),
+        "{}",
+        err
+    );
+    assert!(
+        err.ends_with("sudo apt-get install -y dadk-missing-pkg dadk-missing-tool"),
+        "{}",
+        err
+    );
+    assert!(!err.contains("  sh:"), "{}", err);
+
+    a.host_packages.truncate(1);
+    assert!(missing_host_packages(&[a], &path, None).is_ok());
+}
+
+#[test]
+fn package_manager_detect() {
+    use host_packages::PackageManager;
+
+    assert_eq!(
+        PackageManager::detect("NAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\n"),
+        Some(PackageManager::Apt)
+    );
+    assert_eq!(
+        PackageManager::detect("ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n"),
+        Some(PackageManager::Dnf)
+    );
+    assert_eq!(
+        PackageManager::detect("ID=arch\n"),
+        Some(PackageManager::Pacman)
+    );
+    assert_eq!(PackageManager::detect("ID=alpine\n"), None);
+}
@@ -0,0 +1,241 @@
+//! # 构建会话
+//!
+//! 以库的形式使用dadk-user时的入口。
+//!
+//! 每个会话持有独立的执行上下文（缓存目录、全局环境变量、任务队列等都不是全局状态），
+//! 因此同一进程内可以先后运行多个会话。所有错误都以带有错误码的[`DadkUserError`]返回，不会退出进程。
+//!
+//! ```no_run
+//! use dadk_user::{context::{Action, DadkUserExecuteContextBuilder}, BuildSession};
+//!
+//! let context = DadkUserExecuteContextBuilder::default()
+//!     .sysroot_dir(Some("bin/sysroot".into()))
+//!     .config_dir(Some("user/dadk/config".into()))
+//!     .action(Action::Build)
+//!     .thread_num(Some(4))
+//!     .cache_dir(Some("bin/dadk_cache".into()))
+//!     .build()
+//!     .unwrap();
+//! let session = BuildSession::new(context).unwrap();
+//! session.run().unwrap();
+//! ```
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{info, warn};
+
+use crate::{
+    affected,
+    context::{Action, DadkUserExecuteContext},
+    error::{DadkUserError, ErrorCode},
+    executor::explain::CommandPlan,
+    executor::provenance,
+    lock::{self, LockMode},
+    metrics::{self, RunInfo},
+    parser::{task::DADKTask, Parser},
+    rdeps::RdepTree,
+    report::{self, BuildReport},
+    scheduler::Scheduler,
+    status,
+};
+
+/// # 构建会话
+pub struct BuildSession {
+    context: Arc<DadkUserExecuteContext>,
+}
+
+impl BuildSession {
+    /// 创建构建会话，并初始化执行上下文
+    pub fn new(context: DadkUserExecuteContext) -> Result<Self, DadkUserError> {
+        let context = Arc::new(context);
+        context.init(context.clone()).map_err(|e| {
+            DadkUserError::new(ErrorCode::InvalidContext, format!("Context error: {}", e))
+        })?;
+        Ok(Self { context })
+    }
+
+    pub fn context(&self) -> &Arc<DadkUserExecuteContext> {
+        &self.context
+    }
+
+    /// 解析配置目录下的所有任务
+    pub fn parse(&self) -> Result<Vec<(PathBuf, DADKTask)>, DadkUserError> {
+        let config_dir = self.context.config_dir().unwrap().clone();
+        let variables = Parser::config_variables(
+            self.context.variables(),
+            *self.context.target_arch(),
+            self.context.cache_root(),
+            self.context.kernel(),
+        );
+        let mut parser = Parser::new(config_dir)
+            .overlay_dirs(self.context.overlay_config_dirs().to_vec())
+            .skip_invalid_configs(self.context.skip_invalid_configs())
+            .variables(variables)
+            .default_versions(self.context.default_versions().clone())
+            .cache_dir(self.context.cache_root());
+        let tasks = parser.parse().map_err(|e| {
+            DadkUserError::new(ErrorCode::InvalidConfig, format!("Parse error: {:?}", e))
+        })?;
+        Ok(Parser::filter_blocked_apps(
+            tasks,
+            self.context.app_blocklist(),
+            *self.context.target_arch(),
+        ))
+    }
+
+    /// 解析配置文件，并执行上下文中指定的操作
+    pub fn run(&self) -> Result<(), DadkUserError> {
+        let mut tasks = self.parse()?;
+        if !self.context.affected_by().is_empty() {
+            let affected = affected::affected_tasks(&tasks, self.context.affected_by());
+            if affected.is_empty() {
+                info!("No task is affected by the changed paths, nothing to do");
+                return Ok(());
+            }
+            info!(
+                "Tasks affected by the changed paths: {}",
+                affected
+                    .iter()
+                    .map(|(name, version)| DADKTask::name_version_of(name, version))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            tasks = affected::with_dependencies(tasks, &affected);
+        }
+        if !self.context.selected_tasks().is_empty() {
+            tasks = affected::select(tasks, self.context.selected_tasks())
+                .map_err(|e| DadkUserError::new(ErrorCode::InvalidConfig, e))?;
+        }
+        self.run_tasks(tasks)
+    }
+
+    /// 任务构建时将要执行的命令、工作目录以及环境变量，不执行任何任务
+    ///
+    /// 任务可以通过名称（只有一个版本时）、`name@version`或者`name-version`指定
+    pub fn explain_env(&self, query: &str) -> Result<CommandPlan, DadkUserError> {
+        let tasks = self.parse()?;
+        let (name, version) = status::find_task(&tasks, query)
+            .map(|(_, task)| (task.name.clone(), task.version.clone()))
+            .map_err(|e| DadkUserError::new(ErrorCode::InvalidConfig, e))?;
+        let scheduler = Scheduler::new(
+            self.context.clone(),
+            self.context.sysroot_dir().cloned().unwrap_or_default(),
+            Action::Build,
+            tasks,
+        )?;
+        scheduler.explain_env(&name, &version).map_err(Into::into)
+    }
+
+    /// 直接或间接依赖于给定任务的任务
+    ///
+    /// 任务可以通过名称（只有一个版本时）、`name@version`或者`name-version`指定
+    pub fn rdeps(&self, query: &str) -> Result<RdepTree, DadkUserError> {
+        let tasks = self.parse()?;
+        let (name, version) = status::find_task(&tasks, query)
+            .map(|(_, task)| (task.name.clone(), task.version.clone()))
+            .map_err(|e| DadkUserError::new(ErrorCode::InvalidConfig, e))?;
+        let scheduler = Scheduler::new(
+            self.context.clone(),
+            self.context.sysroot_dir().cloned().unwrap_or_default(),
+            Action::Build,
+            tasks,
+        )?;
+        scheduler.rdeps(&name, &version).map_err(Into::into)
+    }
+
+    /// 执行给定的任务列表
+    ///
+    /// 执行期间持有缓存根目录的排他锁，同一个缓存根目录上的其他dadk进程需要等待
+    pub fn run_tasks(&self, tasks: Vec<(PathBuf, DADKTask)>) -> Result<(), DadkUserError> {
+        let _lock = lock::lock_cache_root(
+            self.context.cache_root(),
+            LockMode::Exclusive,
+            self.context.lock_timeout(),
+        )
+        .map_err(|e| DadkUserError::new(ErrorCode::Locked, e))?;
+        let start = Instant::now();
+        let report_tasks: Vec<DADKTask> = match self.context.build_report() {
+            Some(_) => tasks.iter().map(|(_, task)| task.clone()).collect(),
+            None => Vec::new(),
+        };
+        let r = self.schedule(tasks);
+        let written = self
+            .write_metrics(start.elapsed(), r.is_ok())
+            .and(self.write_report(&report_tasks, r.is_ok()));
+        match (r, written) {
+            (Err(e), Err(we)) => {
+                warn!("{}", we.message());
+                Err(e)
+            }
+            (r, written) => r.and(written),
+        }
+    }
+
+    fn schedule(&self, tasks: Vec<(PathBuf, DADKTask)>) -> Result<(), DadkUserError> {
+        // 构建、清理时可以不指定sysroot目录，安装时已经在初始化上下文时检查过
+        let scheduler = Scheduler::new(
+            self.context.clone(),
+            self.context.sysroot_dir().cloned().unwrap_or_default(),
+            *self.context.action(),
+            tasks,
+        )?;
+
+        scheduler.run().map_err(Into::into)
+    }
+
+    /// 指定了指标文件时，写入本次执行的构建指标（执行失败时也写入）
+    fn write_metrics(&self, duration: Duration, success: bool) -> Result<(), DadkUserError> {
+        let Some(path) = self.context.metrics_file() else {
+            return Ok(());
+        };
+        let action = match self.context.action() {
+            Action::Build => "build",
+            Action::Install => "install",
+            Action::Clean(_) => "clean",
+        };
+        let run = RunInfo {
+            action,
+            arch: (*self.context.target_arch()).into(),
+            duration,
+            finished: SystemTime::now(),
+            success,
+        };
+        let content = self
+            .context
+            .metrics()
+            .render(self.context.metrics_format(), &run);
+        metrics::write(path, &content).map_err(|e| {
+            DadkUserError::new(ErrorCode::Io, format!("Failed to write metrics: {}", e))
+        })
+    }
+
+    /// 构建时指定了构建报告文件时，写入每个任务的构建结果和来源信息（构建失败时也写入）
+    fn write_report(&self, tasks: &[DADKTask], success: bool) -> Result<(), DadkUserError> {
+        let Some(path) = self.context.build_report() else {
+            return Ok(());
+        };
+        if *self.context.action() != Action::Build {
+            return Ok(());
+        }
+        let report = BuildReport::new(
+            tasks,
+            &self.context.metrics(),
+            self.context.cache_root(),
+            (*self.context.target_arch()).into(),
+            success,
+            provenance::builder_info(self.context.container()),
+        );
+        report::write(path, &report).map_err(|e| {
+            DadkUserError::new(
+                ErrorCode::Io,
+                format!("Failed to write build report: {}", e),
+            )
+        })?;
+        info!("Build report written to {}", path.display());
+        Ok(())
+    }
+}
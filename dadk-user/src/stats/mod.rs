@@ -0,0 +1,357 @@
+//! # 构建耗时统计
+//!
+//! 根据各个任务的任务日志中记录的构建耗时，统计：
+//!
+//! - 耗时最长的任务
+//! - 所有任务的构建耗时之和
+//! - 最近一批构建的墙钟时间、平均并行度以及并行效率
+//! - 依赖图上的关键路径（耗时之和最大的依赖链）
+//! - 每个任务最近一次被重新构建的原因，以及最近一批构建中各种原因的任务数
+//!
+//! 只有实际执行过构建的任务才有耗时记录，命中缓存而跳过构建的任务会保留上一次的记录。
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    executor::cache::TaskDataDir,
+    parser::{task::DADKTask, task_log::RebuildReason},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// # 单个任务的耗时记录
+#[derive(Debug, Clone)]
+pub struct TaskTiming {
+    pub name: String,
+    pub version: String,
+    pub name_version: String,
+    /// 任务依赖的(name, version)
+    pub depends: Vec<(String, String)>,
+    /// 构建耗时，任务从未被实际构建过时为None
+    pub duration: Option<Duration>,
+    /// 构建结束的时间
+    pub end: Option<DateTime<Utc>>,
+    /// 构建所属的那一批构建的开始时间
+    pub session: Option<DateTime<Utc>>,
+    /// 最近一次实际执行构建的原因
+    pub reason: Option<RebuildReason>,
+}
+
+impl TaskTiming {
+    /// 从任务日志中读取任务的耗时记录
+    pub fn load(cache_root: &Path, task: &DADKTask) -> Self {
+        let task_log = TaskDataDir::load_task_log(cache_root, task);
+        let task_log = task_log.as_ref();
+        Self {
+            name: task.name.clone(),
+            version: task.version.clone(),
+            name_version: task.name_version(),
+            depends: task
+                .depends
+                .iter()
+                .map(|d| (d.name.clone(), d.version.clone()))
+                .collect(),
+            duration: task_log.and_then(|l| l.build_duration()),
+            end: task_log.and_then(|l| l.build_time().cloned()),
+            session: task_log.and_then(|l| l.build_session().cloned()),
+            reason: task_log.and_then(|l| l.build_reason()),
+        }
+    }
+
+    fn start(&self) -> Option<DateTime<Utc>> {
+        let duration = chrono::Duration::from_std(self.duration?).ok()?;
+        Some(self.end? - duration)
+    }
+}
+
+/// # 最近一批构建的统计
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionStats {
+    pub session: DateTime<Utc>,
+    /// 这一批构建中实际执行构建的任务数
+    pub tasks: usize,
+    /// 从第一个任务开始构建到最后一个任务构建结束的时间
+    pub wall_clock: Duration,
+    /// 这一批任务的构建耗时之和
+    pub busy: Duration,
+    /// 同时在构建的任务数的最大值
+    pub peak_concurrency: usize,
+    /// 这一批构建中，各种重新构建原因的任务数
+    pub reasons: BTreeMap<RebuildReason, usize>,
+}
+
+impl SessionStats {
+    /// 平均并行度：构建耗时之和 / 墙钟时间
+    pub fn average_parallelism(&self) -> f64 {
+        if self.wall_clock.is_zero() {
+            return 0.0;
+        }
+        self.busy.as_secs_f64() / self.wall_clock.as_secs_f64()
+    }
+
+    /// 并行效率：平均并行度 / 最大并发数
+    pub fn efficiency(&self) -> f64 {
+        if self.peak_concurrency == 0 {
+            return 0.0;
+        }
+        self.average_parallelism() / self.peak_concurrency as f64
+    }
+}
+
+/// # 构建耗时统计
+#[derive(Debug)]
+pub struct BuildStats {
+    timings: Vec<TaskTiming>,
+}
+
+impl BuildStats {
+    pub fn new(timings: Vec<TaskTiming>) -> Self {
+        Self { timings }
+    }
+
+    /// 读取给定任务的耗时记录
+    pub fn collect(cache_root: &Path, tasks: &[(PathBuf, DADKTask)]) -> Self {
+        Self::new(
+            tasks
+                .iter()
+                .map(|(_, task)| TaskTiming::load(cache_root, task))
+                .collect(),
+        )
+    }
+
+    /// 按构建耗时从大到小排序的任务列表（不包括没有耗时记录的任务）
+    pub fn slowest(&self) -> Vec<&TaskTiming> {
+        let mut timed: Vec<&TaskTiming> = self
+            .timings
+            .iter()
+            .filter(|t| t.duration.is_some())
+            .collect();
+        timed.sort_by(|a, b| {
+            b.duration
+                .cmp(&a.duration)
+                .then(a.name_version.cmp(&b.name_version))
+        });
+        timed
+    }
+
+    /// 所有任务的构建耗时之和
+    pub fn total_build_time(&self) -> Duration {
+        self.timings.iter().filter_map(|t| t.duration).sum()
+    }
+
+    /// 没有耗时记录的任务数
+    pub fn untimed(&self) -> usize {
+        self.timings.iter().filter(|t| t.duration.is_none()).count()
+    }
+
+    /// 最近一批构建的统计
+    pub fn last_session(&self) -> Option<SessionStats> {
+        let session = self.timings.iter().filter_map(|t| t.session).max()?;
+        let spans: Vec<(DateTime<Utc>, DateTime<Utc>)> = self
+            .timings
+            .iter()
+            .filter(|t| t.session == Some(session))
+            .filter_map(|t| Some((t.start()?, t.end?)))
+            .collect();
+        let mut reasons = BTreeMap::new();
+        for reason in self
+            .timings
+            .iter()
+            .filter(|t| t.session == Some(session))
+            .filter_map(|t| t.reason)
+        {
+            *reasons.entry(reason).or_insert(0) += 1;
+        }
+
+        let first = spans.iter().map(|(start, _)| *start).min()?;
+        let last = spans.iter().map(|(_, end)| *end).max()?;
+        let busy = spans
+            .iter()
+            .map(|(start, end)| (*end - *start).to_std().unwrap_or_default())
+            .sum();
+
+        // 扫描线求最大并发数。同一时刻有任务结束也有任务开始时，先处理结束
+        let mut points: Vec<(DateTime<Utc>, i32)> = spans
+            .iter()
+            .flat_map(|(start, end)| [(*start, 1), (*end, -1)])
+            .collect();
+        points.sort();
+        let mut current = 0;
+        let mut peak = 0;
+        for (_, delta) in points {
+            current += delta;
+            peak = peak.max(current);
+        }
+
+        Some(SessionStats {
+            session,
+            tasks: spans.len(),
+            wall_clock: (last - first).to_std().unwrap_or_default(),
+            busy,
+            peak_concurrency: peak as usize,
+            reasons,
+        })
+    }
+
+    /// # 关键路径
+    ///
+    /// 依赖图上构建耗时之和最大的依赖链，按构建顺序排列（被依赖的任务在前）。
+    /// 没有耗时记录的任务按0计算。
+    pub fn critical_path(&self) -> (Duration, Vec<&TaskTiming>) {
+        let index: HashMap<(&str, &str), usize> = self
+            .timings
+            .iter()
+            .enumerate()
+            .map(|(i, t)| ((t.name.as_str(), t.version.as_str()), i))
+            .collect();
+
+        let mut memo: BTreeMap<usize, (Duration, Option<usize>)> = BTreeMap::new();
+        let mut visiting = vec![false; self.timings.len()];
+        for i in 0..self.timings.len() {
+            self.longest_from(i, &index, &mut memo, &mut visiting);
+        }
+
+        let Some((mut cur, (total, _))) = memo
+            .iter()
+            .max_by(|a, b| a.1 .0.cmp(&b.1 .0).then(b.0.cmp(a.0)))
+            .map(|(i, v)| (*i, *v))
+        else {
+            return (Duration::ZERO, Vec::new());
+        };
+
+        let mut path = vec![&self.timings[cur]];
+        while let Some(next) = memo[&cur].1 {
+            path.push(&self.timings[next]);
+            cur = next;
+        }
+        path.reverse();
+        (total, path)
+    }
+
+    /// 以`i`为终点的最长依赖链，返回（耗时之和，链上的前一个任务）
+    fn longest_from(
+        &self,
+        i: usize,
+        index: &HashMap<(&str, &str), usize>,
+        memo: &mut BTreeMap<usize, (Duration, Option<usize>)>,
+        visiting: &mut [bool],
+    ) -> Option<Duration> {
+        if let Some((d, _)) = memo.get(&i) {
+            return Some(*d);
+        }
+        // 循环依赖会在调度时报错，这里只需忽略成环的那条边，保证不会无限递归
+        if visiting[i] {
+            return None;
+        }
+        visiting[i] = true;
+
+        let mut best: (Duration, Option<usize>) = (Duration::ZERO, None);
+        for (name, version) in &self.timings[i].depends {
+            let Some(&dep) = index.get(&(name.as_str(), version.as_str())) else {
+                continue;
+            };
+            if let Some(d) = self.longest_from(dep, index, memo, visiting) {
+                if best.1.is_none() || d > best.0 {
+                    best = (d, Some(dep));
+                }
+            }
+        }
+
+        visiting[i] = false;
+        let total = best.0 + self.timings[i].duration.unwrap_or_default();
+        memo.insert(i, (total, best.1));
+        Some(total)
+    }
+
+    /// 生成文本格式的统计报告，最多列出`top`个耗时最长的任务
+    pub fn report(&self, top: usize) -> String {
+        let mut out = String::new();
+        let slowest = self.slowest();
+        if slowest.is_empty() {
+            out.push_str("No build timing recorded yet, run `dadk user build` first.\n");
+            return out;
+        }
+
+        out.push_str("Slowest tasks:\n");
+        let width = slowest
+            .iter()
+            .take(top)
+            .map(|t| t.name_version.len())
+            .max()
+            .unwrap_or_default();
+        for (i, t) in slowest.iter().take(top).enumerate() {
+            out.push_str(&format!(
+                "  {:>2}. {:<width$}  {}",
+                i + 1,
+                t.name_version,
+                format_duration(t.duration.unwrap_or_default()),
+                width = width
+            ));
+            match t.reason {
+                Some(reason) => out.push_str(&format!("  ({})\n", reason)),
+                None => out.push('\n'),
+            }
+        }
+        out.push('\n');
+
+        out.push_str(&format!(
+            "Total build time: {} ({} tasks",
+            format_duration(self.total_build_time()),
+            slowest.len()
+        ));
+        match self.untimed() {
+            0 => out.push_str(")\n"),
+            n => out.push_str(&format!(", {} without timing data)\n", n)),
+        }
+
+        if let Some(session) = self.last_session() {
+            out.push_str(&format!(
+                "Last build session: started at {}, {} tasks built\n",
+                session
+                    .session
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                session.tasks
+            ));
+            out.push_str(&format!(
+                "  Wall-clock time: {}\n  Average parallelism: {:.2} (peak {})\n  Parallelism efficiency: {:.1}%\n",
+                format_duration(session.wall_clock),
+                session.average_parallelism(),
+                session.peak_concurrency,
+                session.efficiency() * 100.0
+            ));
+            if !session.reasons.is_empty() {
+                out.push_str(&format!(
+                    "  Rebuilt because: {}\n",
+                    session
+                        .reasons
+                        .iter()
+                        .map(|(reason, n)| format!("{} {}", reason, n))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+
+        let (total, path) = self.critical_path();
+        out.push_str(&format!(
+            "Critical path: {}\n  {}\n",
+            format_duration(total),
+            path.iter()
+                .map(|t| t.name_version.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ));
+        out
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.2}s", d.as_secs_f64())
+}
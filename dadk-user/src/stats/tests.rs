@@ -0,0 +1,146 @@
+use chrono::TimeZone;
+
+use super::*;
+
+fn at(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap()
+}
+
+/// 构造一个在`[start, end]`内构建的任务
+fn timing(name: &str, depends: &[&str], span: Option<(i64, i64)>, session: i64) -> TaskTiming {
+    TaskTiming {
+        name: name.to_string(),
+        version: "0.1.0".to_string(),
+        name_version: format!("{}-0.1.0", name),
+        depends: depends
+            .iter()
+            .map(|d| (d.to_string(), "0.1.0".to_string()))
+            .collect(),
+        duration: span.map(|(start, end)| Duration::from_secs((end - start) as u64)),
+        end: span.map(|(_, end)| at(end)),
+        session: span.map(|_| at(session)),
+        reason: None,
+    }
+}
+
+/// libc(0-10) -> app(10-30)，与之并行的tool(0-5)，以及从未构建过的docs
+fn stats() -> BuildStats {
+    BuildStats::new(vec![
+        timing("libc", &[], Some((0, 10)), 0),
+        timing("app", &["libc", "tool"], Some((10, 30)), 0),
+        timing("tool", &[], Some((0, 5)), 0),
+        timing("docs", &["app"], None, 0),
+    ])
+}
+
+#[test]
+fn test_slowest_and_total() {
+    let stats = stats();
+    let slowest: Vec<&str> = stats
+        .slowest()
+        .iter()
+        .map(|t| t.name_version.as_str())
+        .collect();
+    assert_eq!(slowest, vec!["app-0.1.0", "libc-0.1.0", "tool-0.1.0"]);
+    assert_eq!(stats.total_build_time(), Duration::from_secs(35));
+    assert_eq!(stats.untimed(), 1);
+}
+
+#[test]
+fn test_last_session() {
+    let session = stats().last_session().unwrap();
+    assert_eq!(session.session, at(0));
+    assert_eq!(session.tasks, 3);
+    assert_eq!(session.wall_clock, Duration::from_secs(30));
+    assert_eq!(session.busy, Duration::from_secs(35));
+    assert_eq!(session.peak_concurrency, 2);
+    assert!((session.average_parallelism() - 35.0 / 30.0).abs() < 1e-9);
+    assert!((session.efficiency() - 35.0 / 60.0).abs() < 1e-9);
+}
+
+/// 只统计最近一批构建，之前的构建（本次命中缓存的任务）不计入墙钟时间
+#[test]
+fn test_last_session_ignores_older_builds() {
+    let stats = BuildStats::new(vec![
+        timing("old", &[], Some((-100, -50)), -100),
+        timing("new", &[], Some((0, 10)), 0),
+    ]);
+    let session = stats.last_session().unwrap();
+    assert_eq!(session.tasks, 1);
+    assert_eq!(session.wall_clock, Duration::from_secs(10));
+    assert_eq!(session.peak_concurrency, 1);
+}
+
+/// 首尾相接的任务不算同时构建
+#[test]
+fn test_peak_concurrency_back_to_back() {
+    let stats = BuildStats::new(vec![
+        timing("a", &[], Some((0, 10)), 0),
+        timing("b", &["a"], Some((10, 20)), 0),
+    ]);
+    assert_eq!(stats.last_session().unwrap().peak_concurrency, 1);
+}
+
+#[test]
+fn test_critical_path() {
+    let stats = stats();
+    let (total, path) = stats.critical_path();
+    assert_eq!(total, Duration::from_secs(30));
+    let path: Vec<&str> = path.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(path, vec!["libc", "app"]);
+}
+
+#[test]
+fn test_critical_path_with_cycle() {
+    let stats = BuildStats::new(vec![
+        timing("a", &["b"], Some((0, 10)), 0),
+        timing("b", &["a"], Some((10, 15)), 0),
+    ]);
+    let (total, path) = stats.critical_path();
+    assert_eq!(total, Duration::from_secs(15));
+    assert_eq!(path.len(), 2);
+}
+
+#[test]
+fn test_report() {
+    let report = stats().report(2);
+    assert!(report.contains("Slowest tasks:\n   1. app-0.1.0   20.00s\n   2. libc-0.1.0  10.00s\n"));
+    assert!(!report.contains("3. tool-0.1.0"));
+    assert!(report.contains("Total build time: 35.00s (3 tasks, 1 without timing data)"));
+    assert!(report.contains("Wall-clock time: 30.00s"));
+    assert!(report.contains("Average parallelism: 1.17 (peak 2)"));
+    assert!(report.contains("Parallelism efficiency: 58.3%"));
+    assert!(report.contains("Critical path: 30.00s\n  libc-0.1.0 -> app-0.1.0\n"));
+
+    let empty = BuildStats::new(vec![timing("docs", &[], None, 0)]).report(10);
+    assert!(empty.starts_with("No build timing recorded yet"));
+}
+
+#[test]
+fn test_report_rebuild_reasons() {
+    let mut timings = vec![
+        timing("libc", &[], Some((0, 10)), 0),
+        timing("app", &["libc"], Some((10, 30)), 0),
+        timing("tool", &[], Some((0, 5)), 0),
+        timing("old", &[], Some((-100, -50)), -100),
+    ];
+    timings[0].reason = Some(RebuildReason::SourceChanged);
+    timings[1].reason = Some(RebuildReason::DependencyRebuilt);
+    timings[2].reason = Some(RebuildReason::SourceChanged);
+    timings[3].reason = Some(RebuildReason::FirstBuild);
+    let stats = BuildStats::new(timings);
+
+    let session = stats.last_session().unwrap();
+    assert_eq!(
+        session.reasons.into_iter().collect::<Vec<_>>(),
+        vec![
+            (RebuildReason::SourceChanged, 2),
+            (RebuildReason::DependencyRebuilt, 1)
+        ]
+    );
+
+    let report = stats.report(10);
+    assert!(report.contains("   1. old-0.1.0   50.00s  (first-build)\n"));
+    assert!(report.contains("   2. app-0.1.0   20.00s  (dependency-rebuilt)\n"));
+    assert!(report.contains("  Rebuilt because: source-changed 2, dependency-rebuilt 1\n"));
+}
@@ -0,0 +1,208 @@
+//! # 单个任务的状态
+//!
+//! 读取任务日志中记录的最近一次构建、安装的结果和耗时，并使用与执行器相同的判断逻辑，
+//! 解释下一次构建、安装会不会被跳过，以及参与比较的输入文件的修改时间。
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::{
+    context::Action,
+    executor::{
+        cache::{CacheDir, CacheDirType, TaskDataDir},
+        freshness::{self, SkipDecision},
+    },
+    list::TaskSummary,
+    parser::{
+        task::DADKTask,
+        task_log::{Provenance, TaskLog},
+    },
+    scheduler::journal::RunJournal,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// # 任务的状态
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskStatus {
+    #[serde(flatten)]
+    pub summary: TaskSummary,
+    pub config_file: PathBuf,
+    /// 最近一次实际执行构建所花费的时间（毫秒）
+    pub build_duration_ms: Option<u64>,
+    /// 最近一次实际执行安装所花费的时间（毫秒）
+    pub install_duration_ms: Option<u64>,
+    /// 最近一次成功构建的来源信息
+    pub provenance: Option<Provenance>,
+    pub next_build: SkipDecision,
+    pub next_install: SkipDecision,
+}
+
+impl TaskStatus {
+    /// 读取任务日志，判断下一次构建、安装是否会被跳过
+    pub fn load(cache_root: &Path, config_file: &Path, task: &DADKTask) -> Self {
+        let task_log = TaskDataDir::load_task_log(cache_root, task);
+        let log = task_log.clone().unwrap_or_else(TaskLog::new);
+        let src_dir = task
+            .source_path()
+            .unwrap_or_else(|| CacheDir::get_path(cache_root, task, CacheDirType::Source));
+        let build_dir = CacheDir::get_path(cache_root, task, CacheDirType::Build);
+
+        let interrupted = |action: Action| {
+            RunJournal::path(cache_root, &action)
+                .as_deref()
+                .and_then(RunJournal::load)
+                .is_some_and(|j| !j.finished() && j.dirty().contains(&task.name_version()))
+        };
+        let next_build = if interrupted(Action::Build) {
+            SkipDecision::interrupted("build")
+        } else {
+            freshness::build_decision(task, &log, cache_root, config_file, &src_dir)
+                .unwrap_or_else(|e| SkipDecision::failed(&e.to_string()))
+        };
+        let next_install = if interrupted(Action::Install) {
+            SkipDecision::interrupted("install")
+        } else {
+            freshness::install_decision(task, &log, config_file, &build_dir)
+                .unwrap_or_else(|e| SkipDecision::failed(&e.to_string()))
+        };
+
+        Self {
+            summary: TaskSummary::new(task, task_log.as_ref()),
+            config_file: config_file.to_path_buf(),
+            build_duration_ms: log.build_duration().map(|d| d.as_millis() as u64),
+            install_duration_ms: log.install_duration().map(|d| d.as_millis() as u64),
+            provenance: log.provenance().cloned(),
+            next_build,
+            next_install,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize task status")
+    }
+
+    /// 文本格式的状态
+    pub fn text(&self) -> String {
+        let s = &self.summary;
+        let mut out = format!(
+            "task:          {} {}\nconfig:        {}\n",
+            s.name,
+            s.version,
+            self.config_file.display()
+        );
+        out.push_str(&format!(
+            "last build:    {}\n",
+            format_result(
+                s.build_status.as_deref(),
+                s.build_time,
+                self.build_duration_ms
+            )
+        ));
+        out.push_str(&format!(
+            "last install:  {}\n",
+            format_result(
+                s.install_status.as_deref(),
+                s.install_time,
+                self.install_duration_ms
+            )
+        ));
+        if let Some(provenance) = &self.provenance {
+            out.push_str(&format!("source:        {}\n", provenance.source));
+            for patch in &provenance.patches {
+                out.push_str(&format!(
+                    "  patch {}  {}\n",
+                    patch.sha256,
+                    patch.path.display()
+                ));
+            }
+            let builder = &provenance.builder;
+            out.push_str(&format!(
+                "built by:      dadk {} on {} ({} {}{})\n",
+                provenance.dadk_version,
+                builder.hostname,
+                builder.os,
+                builder.arch,
+                builder
+                    .container_image
+                    .as_ref()
+                    .map(|image| format!(", container {}", image))
+                    .unwrap_or_default()
+            ));
+        }
+        for (title, decision) in [
+            ("next build:    ", &self.next_build),
+            ("next install:  ", &self.next_install),
+        ] {
+            out.push_str(&format!(
+                "{}{} ({})\n",
+                title,
+                if decision.skip { "skip" } else { "run" },
+                decision.reason
+            ));
+            for input in &decision.inputs {
+                out.push_str(&format!(
+                    "  {}  {}{}\n",
+                    format_time(&input.modified),
+                    input.path.display(),
+                    if input.newer { "  (newer)" } else { "" }
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// 按照名称查找任务。`query`可以是任务名称（只有一个版本时）、`name@version`或者`name-version`
+pub fn find_task<'a>(
+    tasks: &'a [(PathBuf, DADKTask)],
+    query: &str,
+) -> Result<&'a (PathBuf, DADKTask), String> {
+    let exact = tasks.iter().find(|(_, t)| {
+        format!("{}@{}", t.name, t.version) == query
+            || format!("{}-{}", t.name, t.version) == query
+            || t.name_version() == query
+    });
+    if let Some(found) = exact {
+        return Ok(found);
+    }
+    let by_name: Vec<_> = tasks.iter().filter(|(_, t)| t.name == query).collect();
+    match by_name.as_slice() {
+        [] => Err(format!("Task {} not found", query)),
+        [found] => Ok(found),
+        _ => Err(format!(
+            "Task {} has multiple versions, specify one of: {}",
+            query,
+            by_name
+                .iter()
+                .map(|(_, t)| format!("{}@{}", t.name, t.version))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn format_result(
+    status: Option<&str>,
+    time: Option<DateTime<Utc>>,
+    duration_ms: Option<u64>,
+) -> String {
+    let (Some(status), Some(time)) = (status, time) else {
+        return "-".to_string();
+    };
+    let mut out = format!("{} at {}", status, format_time(&time));
+    if let Some(ms) = duration_ms {
+        out.push_str(&format!(", took {:.1?}", Duration::from_millis(ms)));
+    }
+    out
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -0,0 +1,233 @@
+use chrono::TimeZone;
+use dadk_config::common::task::Dependency;
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::parser::{
+    task_log::{
+        BuildStatus, BuilderInfo, InstallStatus, PatchDigest, Provenance, RebuildReason,
+        SourceRevision,
+    },
+    Parser,
+};
+
+const CONFIG: &str = "app_target_arch_riscv64_only_0_2_0.toml";
+
+fn parse_task(ctx: &BaseGlobalTestContext) -> DADKTask {
+    Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join(CONFIG))
+        .unwrap()
+}
+
+/// 在临时的缓存目录中写入任务日志，并创建源码目录和构建目录
+fn setup(cache_root: &Path, task: &DADKTask, log: &TaskLog) {
+    for dir_type in [CacheDirType::Source, CacheDirType::Build] {
+        let dir = CacheDir::get_path(cache_root, task, dir_type);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("file"), "").unwrap();
+    }
+    let task_data = CacheDir::get_path(cache_root, task, CacheDirType::TaskData);
+    std::fs::create_dir_all(&task_data).unwrap();
+    std::fs::write(
+        task_data.join(TaskDataDir::TASK_LOG_FILE_NAME),
+        toml::to_string(log).unwrap(),
+    )
+    .unwrap();
+}
+
+/// 从未构建过的任务
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn status_never_built(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx);
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path().join("cache");
+    let status = TaskStatus::load(&cache_root, &ctx.config_v2_dir().join(CONFIG), &task);
+    assert!(!status.next_build.skip);
+    assert_eq!(status.next_build.reason, "never built");
+    assert_eq!(status.next_build.rebuild, Some(RebuildReason::FirstBuild));
+    assert_eq!(status.next_install.reason, "never installed");
+    assert!(status.text().contains("last build:    -"));
+}
+
+/// 输入文件都早于上次构建时跳过，否则报告较新的文件
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn status_compares_modified_time(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx);
+    let config_file = ctx.config_v2_dir().join(CONFIG);
+
+    let future = Utc::now() + chrono::TimeDelta::try_hours(1).unwrap();
+    let mut log = TaskLog::new();
+    log.set_build_status(BuildStatus::Success);
+    log.set_build_time(future);
+    log.set_build_duration(Duration::from_millis(1500), future);
+    log.set_install_status(InstallStatus::Failed);
+    log.set_install_time(future);
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path().join("cache");
+    setup(&cache_root, &task, &log);
+    let status = TaskStatus::load(&cache_root, &config_file, &task);
+    assert!(status.next_build.skip, "{:?}", status.next_build);
+    assert_eq!(status.next_build.rebuild, None);
+    assert!(status
+        .next_build
+        .reason
+        .starts_with("no input was modified"));
+    assert_eq!(status.next_build.inputs.len(), 2);
+    assert_eq!(status.next_build.inputs[0].path, config_file);
+    assert!(!status.next_install.skip);
+    assert_eq!(status.next_install.reason, "the last install failed");
+    assert_eq!(status.build_duration_ms, Some(1500));
+    assert!(status.text().contains(", took 1.5s"), "{}", status.text());
+
+    let past = Utc.timestamp_opt(1_000_000_000, 0).unwrap();
+    log.set_build_time(past);
+    setup(&cache_root, &task, &log);
+    let status = TaskStatus::load(&cache_root, &config_file, &task);
+    assert!(!status.next_build.skip);
+    assert!(
+        status
+            .next_build
+            .reason
+            .starts_with(&format!("{} was modified at", config_file.display())),
+        "{}",
+        status.next_build.reason
+    );
+    assert!(status.next_build.inputs[0].newer);
+    assert_eq!(
+        status.next_build.rebuild,
+        Some(RebuildReason::ConfigChanged)
+    );
+    assert!(status.text().contains("  (newer)"));
+}
+
+/// 依赖的任务在上次构建之后被重新构建过时，需要重新构建
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn status_dependency_rebuilt(ctx: &BaseGlobalTestContext) {
+    let mut task = parse_task(ctx);
+    let mut dep = task.clone();
+    dep.name = "dep".to_string();
+    task.depends = vec![Dependency::new(dep.name.clone(), dep.version.clone())];
+    let config_file = ctx.config_v2_dir().join(CONFIG);
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path().join("cache");
+
+    let future = Utc::now() + chrono::TimeDelta::try_hours(1).unwrap();
+    let mut log = TaskLog::new();
+    log.set_build_status(BuildStatus::Success);
+    log.set_build_time(future);
+    setup(&cache_root, &task, &log);
+
+    // 依赖在本任务之前构建：跳过
+    let mut dep_log = TaskLog::new();
+    dep_log.set_build_status(BuildStatus::Success);
+    dep_log.set_build_time(future);
+    dep_log.set_build_duration(
+        Duration::from_secs(1),
+        future - chrono::TimeDelta::try_minutes(1).unwrap(),
+    );
+    setup(&cache_root, &dep, &dep_log);
+    let status = TaskStatus::load(&cache_root, &config_file, &task);
+    assert!(status.next_build.skip, "{:?}", status.next_build);
+
+    // 依赖在本任务之后又被重新构建
+    dep_log.set_build_duration(
+        Duration::from_secs(1),
+        future + chrono::TimeDelta::try_minutes(1).unwrap(),
+    );
+    setup(&cache_root, &dep, &dep_log);
+    let status = TaskStatus::load(&cache_root, &config_file, &task);
+    assert!(!status.next_build.skip);
+    assert_eq!(
+        status.next_build.rebuild,
+        Some(RebuildReason::DependencyRebuilt)
+    );
+    assert!(
+        status
+            .next_build
+            .reason
+            .starts_with(&format!("dependency {} was rebuilt", dep.name_version())),
+        "{}",
+        status.next_build.reason
+    );
+}
+
+/// 输出最近一次成功构建的来源信息
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn status_provenance(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx);
+    let config_file = ctx.config_v2_dir().join(CONFIG);
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path().join("cache");
+    let provenance = Provenance {
+        source: SourceRevision::Git {
+            url: "https://example.com/app.git".to_string(),
+            commit: "01cdc56863".to_string(),
+        },
+        patches: vec![PatchDigest {
+            path: PathBuf::from("/patches/0001-fix.patch"),
+            sha256: "abcdef".to_string(),
+        }],
+        builder: BuilderInfo {
+            hostname: "builder".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            kernel: Some("6.6.0".to_string()),
+            container_image: Some("dragonos/build:v1".to_string()),
+        },
+        dadk_version: "0.2.0".to_string(),
+    };
+    let mut log = TaskLog::new();
+    log.set_build_status(BuildStatus::Success);
+    log.set_build_time(Utc::now());
+    log.set_provenance(Some(provenance.clone()));
+    setup(&cache_root, &task, &log);
+
+    let status = TaskStatus::load(&cache_root, &config_file, &task);
+    assert_eq!(status.provenance.as_ref(), Some(&provenance));
+    let text = status.text();
+    assert!(
+        text.contains("source:        git https://example.com/app.git @ 01cdc56863"),
+        "{}",
+        text
+    );
+    assert!(text.contains("  patch abcdef  /patches/0001-fix.patch"));
+    assert!(text.contains(
+        "built by:      dadk 0.2.0 on builder (linux x86_64, container dragonos/build:v1)"
+    ));
+    let json: serde_json::Value = serde_json::from_str(&status.to_json()).unwrap();
+    assert_eq!(json["provenance"]["source"]["type"], "git");
+    assert_eq!(json["provenance"]["source"]["commit"], "01cdc56863");
+
+    // 清理之后不再记录来源
+    log.clean_durations();
+    assert_eq!(log.provenance(), None);
+}
+
+/// 按照名称、`name@version`或者`name-version`查找任务
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn find_task_by_name(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx);
+    let mut other = task.clone();
+    other.version = "0.3.0".to_string();
+    let tasks = vec![
+        (PathBuf::from("a.toml"), task.clone()),
+        (PathBuf::from("b.toml"), other),
+    ];
+
+    let found = find_task(&tasks, &format!("{}@0.3.0", task.name)).unwrap();
+    assert_eq!(found.0, PathBuf::from("b.toml"));
+    let found = find_task(&tasks, &format!("{}-{}", task.name, task.version)).unwrap();
+    assert_eq!(found.0, PathBuf::from("a.toml"));
+    let err = find_task(&tasks, &task.name).unwrap_err();
+    assert!(err.contains("multiple versions"), "{}", err);
+    assert!(find_task(&tasks[..1], &task.name).is_ok());
+    assert!(find_task(&tasks, "missing").is_err());
+}
@@ -0,0 +1,400 @@
+//! # 用户程序的测试
+//!
+//! `dadk user test`在任务构建成功后，执行任务配置中`[test]`指定的测试：
+//!
+//! - `test-command`：在主机上、任务的源码目录中执行（见[`run_host_test`]）
+//! - `guest-script`：复制到磁盘镜像的[`GUEST_TEST_DIR`]目录中，由[`guest_runner`]生成的脚本在QEMU中依次执行。
+//!   每个测试脚本执行结束后，向串口输出一行`DADK-TEST-RESULT <name_version> <退出码>`，
+//!   全部执行结束后输出`DADK-TEST-DONE`，由[`GuestOutput`]解析
+//!
+//! 没有构建成功的任务不会被测试，在报告中记为跳过。所有测试的结果汇总到[`TestReport`]中。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use dadk_config::common::target_arch::TargetArch;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    executor::cache::{CacheDir, CacheDirType, TaskDataDir},
+    interrupt,
+    parser::{task::DADKTask, task_log::BuildStatus},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// 磁盘镜像中存放测试脚本的目录
+pub const GUEST_TEST_DIR: &str = "/dadk-tests";
+/// 依次执行所有测试脚本的脚本，位于[`GUEST_TEST_DIR`]中
+pub const GUEST_RUNNER: &str = "run.sh";
+/// 测试脚本执行结束后输出的标记
+const RESULT_MARKER: &str = "DADK-TEST-RESULT";
+/// 所有测试脚本执行结束后输出的标记
+const DONE_MARKER: &str = "DADK-TEST-DONE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    /// 任务没有构建成功，没有执行测试
+    Skipped,
+}
+
+impl TestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestStatus::Passed => "passed",
+            TestStatus::Failed => "failed",
+            TestStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// 测试执行的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestLocation {
+    Host,
+    Qemu,
+}
+
+impl TestLocation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestLocation::Host => "host",
+            TestLocation::Qemu => "qemu",
+        }
+    }
+}
+
+/// # 单个任务的测试结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub version: String,
+    pub location: TestLocation,
+    pub status: TestStatus,
+    /// 测试耗时（毫秒）。在QEMU中执行的测试没有单独的耗时，为整个QEMU测试的耗时
+    pub elapsed_ms: u64,
+    /// 测试失败或者跳过的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl TestResult {
+    pub fn new(
+        task: &DADKTask,
+        location: TestLocation,
+        status: TestStatus,
+        elapsed: Duration,
+        message: Option<String>,
+    ) -> Self {
+        Self {
+            name: task.name.clone(),
+            version: task.version.clone(),
+            location,
+            status,
+            elapsed_ms: elapsed.as_millis() as u64,
+            message,
+        }
+    }
+
+    fn skipped(task: &DADKTask, location: TestLocation) -> Self {
+        Self::new(
+            task,
+            location,
+            TestStatus::Skipped,
+            Duration::ZERO,
+            Some("not built successfully".to_string()),
+        )
+    }
+}
+
+/// # 测试报告
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn new(results: Vec<TestResult>) -> Self {
+        let count = |status| results.iter().filter(|r| r.status == status).count();
+        Self {
+            passed: count(TestStatus::Passed),
+            failed: count(TestStatus::Failed),
+            skipped: count(TestStatus::Skipped),
+            results,
+        }
+    }
+
+    /// 没有失败的测试
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize test report")
+    }
+
+    /// 生成文本格式的表格
+    pub fn table(&self) -> String {
+        if self.results.is_empty() {
+            return "No test found.\n".to_string();
+        }
+        let header = ["NAME", "VERSION", "WHERE", "STATUS", "TIME", "MESSAGE"];
+        let rows: Vec<[String; 6]> = self
+            .results
+            .iter()
+            .map(|r| {
+                [
+                    r.name.clone(),
+                    r.version.clone(),
+                    r.location.as_str().to_string(),
+                    r.status.as_str().to_string(),
+                    format!("{:.1}s", r.elapsed_ms as f64 / 1000.0),
+                    r.message.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut out = String::new();
+        let mut push_row = |cells: Vec<&str>| {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        };
+        push_row(header.to_vec());
+        for row in &rows {
+            push_row(row.iter().map(String::as_str).collect());
+        }
+        out.push_str(&format!(
+            "\n{} passed, {} failed, {} skipped\n",
+            self.passed, self.failed, self.skipped
+        ));
+        out
+    }
+}
+
+/// 任务是否已经构建成功
+fn is_built(cache_root: &Path, task: &DADKTask) -> bool {
+    TaskDataDir::load_task_log(cache_root, task).and_then(|log| log.build_status().cloned())
+        == Some(BuildStatus::Success)
+        && CacheDir::get_path(cache_root, task, CacheDirType::Build).is_dir()
+}
+
+/// 在主机上执行任务的测试命令，任务没有配置`test-command`时返回None
+///
+/// 测试命令在任务的源码目录中执行，没有源码目录时在构建缓存目录中执行
+pub fn run_host_test(cache_root: &Path, task: &DADKTask, arch: TargetArch) -> Option<TestResult> {
+    let test = task.test.as_ref()?;
+    let test_command = test.test_command.as_ref()?;
+    if !is_built(cache_root, task) {
+        return Some(TestResult::skipped(task, TestLocation::Host));
+    }
+
+    let build_dir = CacheDir::get_path(cache_root, task, CacheDirType::Build);
+    let source_dir = task.source_path().or_else(|| {
+        Some(CacheDir::get_path(cache_root, task, CacheDirType::Source)).filter(|p| p.is_dir())
+    });
+    let arch: &str = arch.into();
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(test_command)
+        .current_dir(source_dir.as_ref().unwrap_or(&build_dir))
+        .env("DADK_CACHE_ROOT", cache_root)
+        .env("DADK_CURRENT_BUILD_DIR", &build_dir)
+        .env("ARCH", arch);
+    if let Some(source_dir) = &source_dir {
+        command.env("DADK_CURRENT_SOURCE_DIR", source_dir);
+    }
+    for env in task.envs.iter().flatten() {
+        command.env(env.key(), env.value());
+    }
+
+    info!("Testing {} on host: {}", task.name_version(), test_command);
+    let start = Instant::now();
+    let (status, message) = match test.timeout_duration() {
+        Ok(timeout) => match run_command(command, timeout) {
+            Ok(()) => (TestStatus::Passed, None),
+            Err(e) => (TestStatus::Failed, Some(e)),
+        },
+        Err(e) => (TestStatus::Failed, Some(e.to_string())),
+    };
+    Some(TestResult::new(
+        task,
+        TestLocation::Host,
+        status,
+        start.elapsed(),
+        message,
+    ))
+}
+
+/// 执行命令，超时后终止整个进程组
+fn run_command(mut command: Command, timeout: Option<Duration>) -> Result<(), String> {
+    let (mut child, tracked) = interrupt::spawn_tracked(command.stdin(Stdio::null()))
+        .map_err(|e| format!("Failed to run test command: {}", e))?;
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("test command failed, {}", status)),
+            Ok(None) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        if let Some(timeout) = timeout.filter(|t| start.elapsed() >= *t) {
+            tracked.kill_group();
+            child.wait().ok();
+            return Err(format!("timed out after {:?}", timeout));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// 磁盘镜像中，任务的测试脚本的文件名
+fn guest_script_name(task: &DADKTask) -> String {
+    format!("{}.sh", task.name_version())
+}
+
+/// 找出需要在QEMU中测试的任务（配置了`guest-script`的任务）
+///
+/// 返回已经构建成功的任务，以及没有构建成功而被跳过的测试结果
+pub fn guest_tests<'a>(
+    cache_root: &Path,
+    tasks: &'a [DADKTask],
+) -> (Vec<&'a DADKTask>, Vec<TestResult>) {
+    let mut runnable = Vec::new();
+    let mut skipped = Vec::new();
+    for task in tasks {
+        if task
+            .test
+            .as_ref()
+            .and_then(|t| t.guest_script.as_ref())
+            .is_none()
+        {
+            continue;
+        }
+        if is_built(cache_root, task) {
+            runnable.push(task);
+        } else {
+            skipped.push(TestResult::skipped(task, TestLocation::Qemu));
+        }
+    }
+    (runnable, skipped)
+}
+
+/// 依次执行所有测试脚本的脚本的内容
+pub fn guest_runner(tasks: &[&DADKTask]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# Generated by dadk user test\ncd {}\n",
+        GUEST_TEST_DIR
+    );
+    for task in tasks {
+        script.push_str(&format!(
+            "sh ./{}\necho \"{} {} $?\"\n",
+            guest_script_name(task),
+            RESULT_MARKER,
+            task.name_version()
+        ));
+    }
+    script.push_str(&format!("echo \"{}\"\n", DONE_MARKER));
+    script
+}
+
+/// 把任务的测试脚本（相对于`workdir`）以及[`guest_runner`]复制到`dest`目录中
+///
+/// `dest`为磁盘镜像挂载后，[`GUEST_TEST_DIR`]在主机上的路径。`dest`中原有的内容会被删除
+pub fn install_guest_tests(workdir: &Path, tasks: &[&DADKTask], dest: &Path) -> Result<(), String> {
+    let err = |path: &Path, e: std::io::Error| format!("{}: {}", path.display(), e);
+    if dest.exists() {
+        std::fs::remove_dir_all(dest).map_err(|e| err(dest, e))?;
+    }
+    std::fs::create_dir_all(dest).map_err(|e| err(dest, e))?;
+    for task in tasks {
+        let Some(script) = task.test.as_ref().and_then(|t| t.guest_script.as_ref()) else {
+            continue;
+        };
+        let script: PathBuf = workdir.join(script);
+        std::fs::copy(&script, dest.join(guest_script_name(task))).map_err(|e| {
+            format!(
+                "Failed to copy test script of {}, {}",
+                task.name_version(),
+                err(&script, e)
+            )
+        })?;
+    }
+    let runner = dest.join(GUEST_RUNNER);
+    std::fs::write(&runner, guest_runner(tasks)).map_err(|e| err(&runner, e))?;
+    Ok(())
+}
+
+/// # QEMU的串口输出中的测试结果
+#[derive(Debug, Default)]
+pub struct GuestOutput {
+    /// 任务的name_version -> 测试脚本的退出码
+    exit_codes: HashMap<String, i32>,
+    done: bool,
+}
+
+impl GuestOutput {
+    /// 解析一行输出。串口输出中可能夹杂着内核日志，因此标记不要求位于行首
+    pub fn feed(&mut self, line: &str) {
+        if let Some(pos) = line.find(RESULT_MARKER) {
+            let mut fields = line[pos + RESULT_MARKER.len()..].split_whitespace();
+            if let (Some(name_version), Some(Ok(code))) =
+                (fields.next(), fields.next().map(str::parse))
+            {
+                self.exit_codes.insert(name_version.to_string(), code);
+            }
+        } else if line.contains(DONE_MARKER) {
+            self.done = true;
+        }
+    }
+
+    /// 所有测试脚本都已经执行结束
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// 生成测试结果。没有输出结果的任务（例如QEMU超时、客户机崩溃）记为失败
+    pub fn results(&self, tasks: &[&DADKTask], elapsed: Duration) -> Vec<TestResult> {
+        tasks
+            .iter()
+            .map(|task| {
+                let (status, message) = match self.exit_codes.get(&task.name_version()) {
+                    Some(0) => (TestStatus::Passed, None),
+                    Some(code) => (
+                        TestStatus::Failed,
+                        Some(format!("test script exited with {}", code)),
+                    ),
+                    None => {
+                        warn!("No test result of {} from QEMU", task.name_version());
+                        (TestStatus::Failed, Some("no result from QEMU".to_string()))
+                    }
+                };
+                TestResult::new(task, TestLocation::Qemu, status, elapsed, message)
+            })
+            .collect()
+    }
+}
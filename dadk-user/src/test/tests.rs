@@ -0,0 +1,182 @@
+use dadk_config::common::task::TestConfig;
+use test_base::{
+    global::BaseGlobalTestContext,
+    test_context::{self as test_context, test_context},
+};
+
+use super::*;
+use crate::parser::{task_log::TaskLog, Parser};
+
+fn parse_task(ctx: &BaseGlobalTestContext, config: &str) -> DADKTask {
+    Parser::new(ctx.config_v2_dir())
+        .parse_config_file(&ctx.config_v2_dir().join(config))
+        .unwrap()
+}
+
+/// 在缓存目录中把任务标记为构建成功
+fn mark_built(cache_root: &Path, task: &DADKTask) {
+    let build_dir = CacheDir::get_path(cache_root, task, CacheDirType::Build);
+    std::fs::create_dir_all(&build_dir).unwrap();
+    std::fs::write(build_dir.join("app"), "app").unwrap();
+    let data_dir = CacheDir::get_path(cache_root, task, CacheDirType::TaskData);
+    std::fs::create_dir_all(&data_dir).unwrap();
+    let mut log = TaskLog::new();
+    log.set_build_status(BuildStatus::Success);
+    log.set_build_time_now();
+    std::fs::write(
+        data_dir.join("task_log.toml"),
+        toml::to_string(&log).unwrap(),
+    )
+    .unwrap();
+}
+
+fn with_test(mut task: DADKTask, test: TestConfig) -> DADKTask {
+    task.test = Some(test);
+    task
+}
+
+/// 测试在主机上执行测试命令
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn host_test(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let task = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    assert!(run_host_test(cache_root, &task, TargetArch::X86_64).is_none());
+
+    let command = |cmd: &str, timeout: Option<&str>| TestConfig {
+        test_command: Some(cmd.to_string()),
+        timeout: timeout.map(|t| t.to_string()),
+        ..Default::default()
+    };
+    let run = |test: TestConfig| {
+        run_host_test(
+            cache_root,
+            &with_test(task.clone(), test),
+            TargetArch::X86_64,
+        )
+        .unwrap()
+    };
+    // 测试命令可以读取任务的环境变量以及构建结果
+    let check = command(
+        "test \"$CC\" = abc-gcc && test \"$ARCH\" = x86_64 && test -f \"$DADK_CURRENT_BUILD_DIR/app\"",
+        None,
+    );
+    let result = run(check.clone());
+    assert_eq!(result.status, TestStatus::Skipped);
+    assert_eq!(result.location, TestLocation::Host);
+
+    mark_built(cache_root, &task);
+    let result = run(check);
+    assert_eq!(result.status, TestStatus::Passed, "{:?}", result.message);
+    assert_eq!(result.message, None);
+
+    let result = run(command("exit 3", None));
+    assert_eq!(result.status, TestStatus::Failed);
+    assert!(result.message.unwrap().contains('3'));
+
+    let start = Instant::now();
+    let result = run(command("sleep 10", Some("1s")));
+    assert_eq!(result.status, TestStatus::Failed);
+    assert!(result.message.unwrap().contains("timed out"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+/// 测试生成QEMU中执行的测试脚本，并解析串口输出中的测试结果
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn guest_test(ctx: &BaseGlobalTestContext) {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache_root = tmp.path();
+    let guest = |script: &str| TestConfig {
+        guest_script: Some(PathBuf::from(script)),
+        ..Default::default()
+    };
+    let tasks = vec![
+        with_test(
+            parse_task(ctx, "app_normal_with_env_0_2_0.toml"),
+            guest("a.sh"),
+        ),
+        with_test(
+            parse_task(ctx, "app_target_arch_riscv64_only_0_2_0.toml"),
+            guest("b.sh"),
+        ),
+        parse_task(ctx, "app_all_target_arch_0_2_0.toml"),
+    ];
+    mark_built(cache_root, &tasks[0]);
+    let (runnable, skipped) = guest_tests(cache_root, &tasks);
+    assert_eq!(runnable.len(), 1);
+    assert_eq!(runnable[0].name, tasks[0].name);
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].name, tasks[1].name);
+    assert_eq!(skipped[0].status, TestStatus::Skipped);
+
+    let workdir = cache_root.join("workdir");
+    std::fs::create_dir_all(&workdir).unwrap();
+    std::fs::write(workdir.join("a.sh"), "exit 0").unwrap();
+    let dest = cache_root.join("mnt/dadk-tests");
+    install_guest_tests(&workdir, &runnable, &dest).unwrap();
+    let nv = tasks[0].name_version();
+    assert_eq!(
+        std::fs::read_to_string(dest.join(format!("{}.sh", nv))).unwrap(),
+        "exit 0"
+    );
+    let runner = std::fs::read_to_string(dest.join(GUEST_RUNNER)).unwrap();
+    assert_eq!(runner, guest_runner(&runnable));
+    assert!(runner.contains(&format!("sh ./{}.sh\n", nv)));
+    assert!(runner.ends_with("echo \"DADK-TEST-DONE\"\n"));
+    // 测试脚本不存在时报错
+    assert!(install_guest_tests(&workdir, &[&tasks[1]], &dest).is_err());
+
+    let both = [&tasks[0], &tasks[1]];
+    let mut output = GuestOutput::default();
+    output.feed("[ INFO ] (src/init/mod.rs:42) booting");
+    output.feed(&format!("DADK-TEST-RESULT {} 0", nv));
+    output.feed(&format!(
+        "\x1b[0mDADK-TEST-RESULT {} 1",
+        tasks[1].name_version()
+    ));
+    assert!(!output.done());
+    output.feed("DADK-TEST-DONE");
+    assert!(output.done());
+    let results = output.results(&both, Duration::from_secs(2));
+    assert_eq!(results[0].status, TestStatus::Passed);
+    assert_eq!(results[0].elapsed_ms, 2000);
+    assert_eq!(results[1].status, TestStatus::Failed);
+    assert_eq!(results[1].location, TestLocation::Qemu);
+
+    // 没有输出结果的任务记为失败
+    let results = GuestOutput::default().results(&both, Duration::ZERO);
+    assert!(results.iter().all(|r| r.status == TestStatus::Failed));
+}
+
+/// 测试报告的统计以及输出格式
+#[test_context(BaseGlobalTestContext)]
+#[test]
+fn test_report(ctx: &BaseGlobalTestContext) {
+    let task = parse_task(ctx, "app_normal_with_env_0_2_0.toml");
+    let result = |location, status| TestResult::new(&task, location, status, Duration::ZERO, None);
+    let report = TestReport::new(vec![
+        result(TestLocation::Host, TestStatus::Passed),
+        result(TestLocation::Qemu, TestStatus::Failed),
+        TestResult::skipped(&task, TestLocation::Qemu),
+    ]);
+    assert_eq!((report.passed, report.failed, report.skipped), (1, 1, 1));
+    assert!(!report.success());
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["failed"], 1);
+    assert_eq!(json["results"][1]["location"], "qemu");
+    assert_eq!(json["results"][2]["status"], "skipped");
+    assert!(json["results"][0].get("message").is_none());
+
+    let table = report.table();
+    assert!(table.starts_with("NAME"), "{}", table);
+    assert!(table.contains("not built successfully"), "{}", table);
+    assert!(
+        table.ends_with("1 passed, 1 failed, 1 skipped\n"),
+        "{}",
+        table
+    );
+    assert!(TestReport::new(vec![]).success());
+}
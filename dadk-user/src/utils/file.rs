@@ -1,5 +1,8 @@
 use std::{
+    ffi::CString,
     fs::File,
+    io::{Read, Write},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::Path,
     process::{Command, Stdio},
 };
@@ -10,9 +13,39 @@ use super::stdio::StdioUtils;
 
 pub struct FileUtils;
 
+/// 复制文件的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyMode {
+    /// 优先使用reflink（btrfs、xfs等支持写时复制的文件系统），不支持时使用copy_file_range复制
+    #[default]
+    Auto,
+    /// 创建硬链接，无法创建时（例如跨文件系统）与Auto相同。
+    ///
+    /// 目标文件与源文件共享同一个inode，只适用于之后不会被原地修改的文件
+    Hardlink,
+}
+
+/// 复制目录的选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CopyOptions {
+    /// 复制文件的方式
+    pub mode: CopyMode,
+    /// 保留文件、目录以及符号链接自身的访问时间和修改时间
+    pub preserve_times: bool,
+}
+
 impl FileUtils {
     ///从指定url下载文件到指定路径
-    pub fn download_file(url: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    ///
+    /// `on_progress`的参数为已下载的字节数以及文件总大小（未知时为0）。
+    /// 每下载1%（总大小未知时为每1MiB）以及下载完成时，会调用一次`on_progress`
+    ///
+    /// 返回下载的字节数
+    pub fn download_file(
+        url: &str,
+        path: &Path,
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Result<u64, Box<dyn std::error::Error>> {
         let tempurl = Url::parse(url).expect("failed to parse the url");
         let file_name = tempurl
             .path_segments()
@@ -24,8 +57,50 @@ impl FileUtils {
             .build()?;
         let mut response = client.get(url).send()?;
         let mut file = File::create(path.join(file_name))?;
-        response.copy_to(&mut file)?;
-        Ok(())
+
+        let total = response.content_length().unwrap_or(0);
+        let step = if total > 0 {
+            (total / 100).max(1)
+        } else {
+            1024 * 1024
+        };
+        let mut downloaded: u64 = 0;
+        let mut reported: u64 = 0;
+        let mut buf = vec![0u8; 64 * 1024];
+        on_progress(0, total);
+        loop {
+            let n = response.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            downloaded += n as u64;
+            if downloaded - reported >= step {
+                on_progress(downloaded, total);
+                reported = downloaded;
+            }
+        }
+        if reported != downloaded {
+            on_progress(downloaded, total);
+        }
+        Ok(downloaded)
+    }
+
+    /// 目录中所有文件的大小之和（字节）。不跟随符号链接，路径不存在时返回0
+    pub fn dir_size(path: &Path) -> std::io::Result<u64> {
+        let meta = match std::fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if !meta.is_dir() {
+            return Ok(meta.len());
+        }
+        let mut size = 0;
+        for entry in std::fs::read_dir(path)? {
+            size += Self::dir_size(&entry?.path())?;
+        }
+        Ok(size)
     }
 
     /// 把指定路径下所有文件和文件夹递归地移动到另一个文件中
@@ -44,11 +119,171 @@ impl FileUtils {
         Ok(())
     }
 
-    /// 递归地复制给定目录下所有文件到另一个文件夹中
+    /// 递归地复制给定目录下所有文件到另一个文件夹中（保留权限以及符号链接）
+    #[allow(dead_code)]
     pub fn copy_dir_all(src: &Path, dst: &Path) -> Result<(), String> {
-        log::trace!("FileUtils::copy_dir_all: src: {:?}, dst: {:?}", src, dst);
+        Self::copy_dir_with(src, dst, CopyMode::Auto)
+    }
+
+    /// 按照指定的方式，递归地复制给定目录下所有文件到另一个文件夹中
+    pub fn copy_dir_with(src: &Path, dst: &Path, mode: CopyMode) -> Result<(), String> {
+        Self::copy_dir_with_options(
+            src,
+            dst,
+            CopyOptions {
+                mode,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// # 按照指定的选项，递归地复制给定目录下所有文件到另一个文件夹中
+    ///
+    /// 符号链接（包括指向目录的、悬空的符号链接）按原样复制，不跟随。
+    /// 目标目录中已有的同名符号链接会被替换，不会把文件写到符号链接指向的位置
+    pub fn copy_dir_with_options(
+        src: &Path,
+        dst: &Path,
+        options: CopyOptions,
+    ) -> Result<(), String> {
+        log::trace!(
+            "FileUtils::copy_dir_with_options: src: {:?}, dst: {:?}, options: {:?}",
+            src,
+            dst,
+            options
+        );
+        Self::copy_tree(src, dst, options).map_err(|e| {
+            format!(
+                "Failed to copy {} to {}: {}",
+                src.display(),
+                dst.display(),
+                e
+            )
+        })
+    }
+
+    fn copy_tree(src: &Path, dst: &Path, options: CopyOptions) -> std::io::Result<()> {
+        // 目标是指向目录的符号链接时，create_dir_all会跟随它，需要先删除
+        if std::fs::symlink_metadata(dst).is_ok_and(|m| m.file_type().is_symlink()) {
+            std::fs::remove_file(dst)?;
+        }
+        std::fs::create_dir_all(dst)?;
+        for entry in src.read_dir()? {
+            let entry = entry?;
+            let from = entry.path();
+            let to = dst.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                Self::copy_tree(&from, &to, options)?;
+                continue;
+            }
+            if file_type.is_symlink() {
+                Self::remove_existing(&to)?;
+                std::os::unix::fs::symlink(std::fs::read_link(&from)?, &to)?;
+            } else {
+                Self::copy_file(&from, &to, options.mode)?;
+            }
+            if options.preserve_times {
+                Self::copy_times(&from, &to)?;
+            }
+        }
+        std::fs::set_permissions(dst, src.metadata()?.permissions())?;
+        // 目录的修改时间在复制完其中的文件之后才能设置
+        if options.preserve_times {
+            Self::copy_times(src, dst)?;
+        }
+        Ok(())
+    }
+
+    /// 把`src`的访问时间和修改时间设置到`dst`上（不跟随符号链接）
+    fn copy_times(src: &Path, dst: &Path) -> std::io::Result<()> {
+        let meta = std::fs::symlink_metadata(src)?;
+        let times = [
+            libc::timespec {
+                tv_sec: meta.atime() as libc::time_t,
+                tv_nsec: meta.atime_nsec() as _,
+            },
+            libc::timespec {
+                tv_sec: meta.mtime() as libc::time_t,
+                tv_nsec: meta.mtime_nsec() as _,
+            },
+        ];
+        let path = CString::new(dst.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let ret = unsafe {
+            libc::utimensat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// 复制单个文件，目标文件已存在时覆盖
+    pub fn copy_file(src: &Path, dst: &Path, mode: CopyMode) -> std::io::Result<()> {
+        // 先删除目标文件：目标文件可能是源文件的硬链接，直接写入会同时修改源文件
+        Self::remove_existing(dst)?;
+        if mode == CopyMode::Hardlink && std::fs::hard_link(src, dst).is_ok() {
+            return Ok(());
+        }
+        if Self::reflink(src, dst)? {
+            return Ok(());
+        }
+        // 在Linux上，std::fs::copy会使用copy_file_range，在内核中完成复制
+        std::fs::copy(src, dst)?;
+        Ok(())
+    }
+
+    /// 尝试通过FICLONE创建reflink，文件系统不支持时返回false
+    #[cfg(target_os = "linux")]
+    fn reflink(src: &Path, dst: &Path) -> std::io::Result<bool> {
+        use std::{fs::OpenOptions, os::fd::AsRawFd};
+
+        let from = File::open(src)?;
+        let to = OpenOptions::new().write(true).create_new(true).open(dst)?;
+        let ret = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) };
+        if ret == 0 {
+            to.set_permissions(from.metadata()?.permissions())?;
+            return Ok(true);
+        }
+        drop(to);
+        std::fs::remove_file(dst)?;
+        Ok(false)
+    }
+
+    /// FICLONE只在Linux上可用，其他系统上总是直接复制
+    #[cfg(not(target_os = "linux"))]
+    fn reflink(_src: &Path, _dst: &Path) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    fn remove_existing(path: &Path) -> std::io::Result<()> {
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 递归地复制给定目录下所有文件到另一个文件夹中，并保留权限、属主以及符号链接
+    pub fn sync_dir_all(src: &Path, dst: &Path) -> Result<(), String> {
+        log::trace!("FileUtils::sync_dir_all: src: {:?}, dst: {:?}", src, dst);
+        Self::cp_dir(src, dst, "-a")
+    }
+
+    fn cp_dir(src: &Path, dst: &Path, mode_arg: &str) -> Result<(), String> {
         let mut cmd = Command::new("cp");
-        cmd.arg("-r").arg("-f").arg("./").arg(dst);
+        cmd.arg(mode_arg).arg("-f").arg("./").arg(dst);
 
         cmd.current_dir(src);
 
@@ -61,7 +296,8 @@ impl FileUtils {
 
         if !output.status.success() {
             return Err(format!(
-                "copy_dir_all failed, status: {:?},  stderr: {:?}",
+                "cp {} failed, status: {:?},  stderr: {:?}",
+                mode_arg,
                 output.status,
                 StdioUtils::tail_n_str(StdioUtils::stderr_to_lines(&output.stderr), 5)
             ));
@@ -2,3 +2,5 @@ pub mod file;
 pub mod lazy_init;
 pub mod path;
 pub mod stdio;
+#[cfg(test)]
+mod tests;
@@ -1,3 +1,10 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
 pub struct StdioUtils;
 
 impl StdioUtils {
@@ -18,4 +25,75 @@ impl StdioUtils {
         }
         return result;
     }
+
+    /// # 转发子进程的标准错误输出
+    ///
+    /// 在新线程中把`stderr`的内容原样写入当前进程的标准错误输出，同时保留最后`n`行。
+    /// 子进程退出（管道关闭）后，线程返回保留的行
+    pub fn tee_stderr(stderr: impl Read + Send + 'static, n: usize) -> JoinHandle<Vec<String>> {
+        std::thread::spawn(move || Self::tee_lines(stderr, std::io::stderr(), n))
+    }
+
+    /// 把`reader`的内容逐行写入`writer`，返回最后`n`行（不含换行符）
+    pub fn tee_lines(reader: impl Read, mut writer: impl Write, n: usize) -> Vec<String> {
+        let tail = Self::read_lines(reader, n, |line| {
+            writer.write_all(line).ok();
+        });
+        writer.flush().ok();
+        tail
+    }
+
+    /// # 捕获子进程的输出
+    ///
+    /// 在新线程中逐行读取`reader`，对每一行（不含换行符）调用`on_line`，而不是输出到终端。
+    /// 管道关闭后，线程返回最后`n`行
+    pub fn capture_lines(
+        reader: impl Read + Send + 'static,
+        n: usize,
+        mut on_line: impl FnMut(&str) + Send + 'static,
+    ) -> JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            Self::read_lines(reader, n, |line| {
+                on_line(String::from_utf8_lossy(line).trim_end_matches(['\n', '\r']))
+            })
+        })
+    }
+
+    /// # 在截止时间之前等待读取线程结束
+    ///
+    /// 子进程退出后，它在后台启动的进程（例如sccache服务器）可能继承了输出管道并一直保持打开，
+    /// 读取线程因此不会结束。超过`deadline`后不再等待，返回`None`，线程留在后台直到管道关闭
+    pub fn join_until<T>(handle: JoinHandle<T>, deadline: Instant) -> Option<T> {
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.join().ok()
+    }
+
+    /// 逐行读取`reader`（包含换行符）并调用`on_line`，返回最后`n`行（不含换行符）
+    fn read_lines(reader: impl Read, n: usize, mut on_line: impl FnMut(&[u8])) -> Vec<String> {
+        let mut reader = BufReader::new(reader);
+        let mut tail: VecDeque<String> = VecDeque::with_capacity(n);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            on_line(&line);
+            if n == 0 {
+                continue;
+            }
+            if tail.len() == n {
+                tail.pop_front();
+            }
+            let s = String::from_utf8_lossy(&line);
+            tail.push_back(s.trim_end_matches(['\n', '\r']).to_string());
+        }
+        tail.into()
+    }
 }
@@ -0,0 +1,228 @@
+use std::{
+    fs::Permissions,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use super::{
+    file::{CopyMode, CopyOptions, FileUtils},
+    stdio::StdioUtils,
+};
+
+#[test]
+fn tee_lines_keeps_last_n_lines() {
+    let input = b"line 1\nline 2\r\nline 3\nline 4".to_vec();
+    let mut output = Vec::new();
+    let tail = StdioUtils::tee_lines(input.as_slice(), &mut output, 2);
+    // 原样转发所有内容
+    assert_eq!(output, input);
+    assert_eq!(tail, vec!["line 3", "line 4"]);
+
+    let mut output = Vec::new();
+    let tail = StdioUtils::tee_lines(input.as_slice(), &mut output, 10);
+    assert_eq!(tail, vec!["line 1", "line 2", "line 3", "line 4"]);
+
+    let tail = StdioUtils::tee_lines(input.as_slice(), std::io::sink(), 0);
+    assert!(tail.is_empty());
+}
+
+#[test]
+fn tee_lines_invalid_utf8() {
+    let input = b"ok\n\xff\xfe\n".to_vec();
+    let tail = StdioUtils::tee_lines(input.as_slice(), std::io::sink(), 10);
+    assert_eq!(tail.len(), 2);
+    assert_eq!(tail[0], "ok");
+    assert_eq!(tail[1], "\u{fffd}\u{fffd}");
+}
+
+#[test]
+fn capture_lines_calls_back_for_each_line() {
+    let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let captured = lines.clone();
+    let tail = StdioUtils::capture_lines(&b"a\nb\r\nc"[..], 2, move |line| {
+        captured.lock().unwrap().push(line.to_string())
+    })
+    .join()
+    .unwrap();
+    assert_eq!(*lines.lock().unwrap(), vec!["a", "b", "c"]);
+    assert_eq!(tail, vec!["b", "c"]);
+}
+
+#[test]
+fn join_until_gives_up_after_deadline() {
+    let handle = std::thread::spawn(|| 1);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    assert_eq!(StdioUtils::join_until(handle, deadline), Some(1));
+
+    // 模拟被后台进程保持打开的管道：线程一直阻塞，直到发送端被丢弃
+    let (tx, rx) = std::sync::mpsc::channel::<()>();
+    let handle = std::thread::spawn(move || rx.recv().is_err());
+    let started = Instant::now();
+    let deadline = started + Duration::from_millis(100);
+    assert_eq!(StdioUtils::join_until(handle, deadline), None);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    drop(tx);
+}
+
+/// 在临时目录中创建复制的源目录，返回临时目录、源目录以及（尚未创建的）目标目录
+fn copy_test_dirs() -> (tempfile::TempDir, PathBuf, PathBuf) {
+    let base = tempfile::tempdir().unwrap();
+    let src = base.path().join("src");
+    std::fs::create_dir_all(src.join("bin")).unwrap();
+    std::fs::write(src.join("bin/app"), "app").unwrap();
+    std::fs::set_permissions(src.join("bin/app"), Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(src.join("README"), "readme").unwrap();
+    std::os::unix::fs::symlink("bin/app", src.join("app-link")).unwrap();
+    let dst = base.path().join("dst");
+    (base, src, dst)
+}
+
+/// 测试复制目录时保留权限、符号链接，并覆盖已存在的文件
+#[test]
+fn copy_dir_all_keeps_modes_and_symlinks() {
+    let (_tmp, src, dst) = copy_test_dirs();
+    std::fs::create_dir_all(&dst).unwrap();
+    // 目标文件是源文件的硬链接时，覆盖它不能修改源文件
+    std::fs::hard_link(src.join("README"), dst.join("README")).unwrap();
+    std::fs::write(src.join("README"), "new readme").unwrap();
+
+    FileUtils::copy_dir_all(&src, &dst).unwrap();
+    assert_eq!(std::fs::read_to_string(dst.join("bin/app")).unwrap(), "app");
+    let mode = std::fs::metadata(dst.join("bin/app"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(
+        std::fs::read_link(dst.join("app-link")).unwrap(),
+        PathBuf::from("bin/app")
+    );
+    assert_eq!(
+        std::fs::read_to_string(dst.join("README")).unwrap(),
+        "new readme"
+    );
+    assert_ne!(
+        std::fs::metadata(dst.join("README")).unwrap().ino(),
+        std::fs::metadata(src.join("README")).unwrap().ino()
+    );
+
+    // 再次复制会覆盖已存在的文件和符号链接
+    FileUtils::copy_dir_all(&src, &dst).unwrap();
+}
+
+/// 测试使用硬链接复制目录
+#[test]
+fn copy_dir_with_hardlinks() {
+    let (_tmp, src, dst) = copy_test_dirs();
+    FileUtils::copy_dir_with(&src, &dst, CopyMode::Hardlink).unwrap();
+    assert_eq!(
+        std::fs::metadata(dst.join("bin/app")).unwrap().ino(),
+        std::fs::metadata(src.join("bin/app")).unwrap().ino()
+    );
+    assert!(std::fs::symlink_metadata(dst.join("app-link"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+
+    assert!(FileUtils::copy_dir_with(&src.join("missing"), &dst, CopyMode::Auto).is_err());
+}
+
+/// 测试复制嵌套的符号链接、悬空的符号链接，以及替换目标目录中的符号链接
+#[test]
+fn copy_dir_nested_and_dangling_symlinks() {
+    let (_tmp, src, dst) = copy_test_dirs();
+    std::fs::create_dir_all(src.join("usr/lib")).unwrap();
+    std::fs::write(src.join("usr/lib/libfoo.so.1.2"), "lib").unwrap();
+    std::os::unix::fs::symlink("libfoo.so.1.2", src.join("usr/lib/libfoo.so.1")).unwrap();
+    std::os::unix::fs::symlink("libfoo.so.1", src.join("usr/lib/libfoo.so")).unwrap();
+    std::os::unix::fs::symlink("usr/lib", src.join("lib")).unwrap();
+    std::os::unix::fs::symlink("missing", src.join("usr/lib/dangling")).unwrap();
+
+    // 目标目录中的usr是指向其他目录的符号链接，复制时不能写到它指向的目录中
+    let elsewhere = src.parent().unwrap().join("elsewhere");
+    std::fs::create_dir_all(&elsewhere).unwrap();
+    std::fs::create_dir_all(&dst).unwrap();
+    std::os::unix::fs::symlink(&elsewhere, dst.join("usr")).unwrap();
+
+    FileUtils::copy_dir_all(&src, &dst).unwrap();
+    for (link, target) in [
+        ("usr/lib/libfoo.so.1", "libfoo.so.1.2"),
+        ("usr/lib/libfoo.so", "libfoo.so.1"),
+        ("lib", "usr/lib"),
+        ("usr/lib/dangling", "missing"),
+    ] {
+        assert_eq!(
+            std::fs::read_link(dst.join(link)).unwrap(),
+            PathBuf::from(target),
+            "{}",
+            link
+        );
+    }
+    assert_eq!(
+        std::fs::read_to_string(dst.join("lib/libfoo.so")).unwrap(),
+        "lib"
+    );
+    assert!(!std::fs::symlink_metadata(dst.join("usr"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(std::fs::read_dir(&elsewhere).unwrap().count(), 0);
+
+    // 不能用文件覆盖目录
+    std::fs::remove_file(src.join("lib")).unwrap();
+    std::fs::write(src.join("lib"), "").unwrap();
+    std::fs::remove_file(dst.join("lib")).unwrap();
+    std::fs::create_dir_all(dst.join("lib")).unwrap();
+    let err = FileUtils::copy_dir_all(&src, &dst).unwrap_err();
+    assert!(err.contains("is a directory"), "{}", err);
+}
+
+/// 测试复制时保留文件、目录以及符号链接的修改时间
+#[test]
+fn copy_dir_preserves_times() {
+    let (_tmp, src, dst) = copy_test_dirs();
+    let old = libc::timespec {
+        tv_sec: 1_000_000_000,
+        tv_nsec: 0,
+    };
+    for path in ["bin/app", "app-link", "bin", ""] {
+        let path = std::ffi::CString::new(src.join(path).to_str().unwrap()).unwrap();
+        let ret = unsafe {
+            libc::utimensat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                [old, old].as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        assert_eq!(ret, 0);
+    }
+
+    FileUtils::copy_dir_with_options(
+        &src,
+        &dst,
+        CopyOptions {
+            preserve_times: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for path in ["bin/app", "app-link", "bin", ""] {
+        let meta = std::fs::symlink_metadata(dst.join(path)).unwrap();
+        assert_eq!(meta.mtime(), 1_000_000_000, "{}", path);
+    }
+    // README没有修改过时间，与源文件相同
+    assert_eq!(
+        std::fs::metadata(dst.join("README")).unwrap().mtime(),
+        std::fs::metadata(src.join("README")).unwrap().mtime()
+    );
+
+    // 默认不保留修改时间
+    let plain = src.parent().unwrap().join("plain");
+    FileUtils::copy_dir_all(&src, &plain).unwrap();
+    assert_ne!(
+        std::fs::metadata(plain.join("bin/app")).unwrap().mtime(),
+        1_000_000_000
+    );
+}
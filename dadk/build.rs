@@ -0,0 +1,7 @@
+fn main() {
+    // `dadk self-update`根据主机的target triple选择要下载的二进制文件
+    println!(
+        "cargo:rustc-env=DADK_HOST_TRIPLE={}",
+        std::env::var("TARGET").unwrap()
+    );
+}
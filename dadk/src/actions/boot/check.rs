@@ -0,0 +1,126 @@
+//! # `dadk boot check`
+//!
+//! 在花时间制作磁盘镜像之前检查启动所需的配置和环境：
+//!
+//! - boot配置能否解析，启动协议、hypervisor所需的配置段是否存在，`[grub]`与`[dragonstub]`是否同时存在
+//! - 启动协议用到的文件（grub-file、grub-install、DragonStub源码目录）是否存在
+//! - 目标架构的QEMU是否存在，启用KVM加速时`/dev/kvm`是否可以读写
+//!
+//! 最后输出将要执行的QEMU命令行，但不启动。有检查失败时返回错误。
+
+use anyhow::{anyhow, Result};
+use dadk_config::boot::{hypervisor::qemu::QemuAccel, BootConfigFile};
+use serde::Serialize;
+
+use super::Qemu;
+use crate::{
+    actions::doctor::{check_kvm, check_qemu, format_table, CheckResult, CheckStatus},
+    console::boot::BootCheckCommand,
+    context::DADKExecContext,
+};
+
+#[derive(Debug, Serialize)]
+struct BootCheckReport {
+    checks: Vec<CheckResult>,
+    /// 将要执行的QEMU命令行，无法生成时为None
+    qemu_command: Option<String>,
+}
+
+pub(super) fn run(ctx: &DADKExecContext, args: &BootCheckCommand) -> Result<()> {
+    let report = check(ctx);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", format_table(&report.checks));
+        if let Some(command) = &report.qemu_command {
+            println!("\nQEMU command line:\n  {}", command);
+        }
+    }
+    let failed = report
+        .checks
+        .iter()
+        .filter(|r| r.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(anyhow!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+fn check(ctx: &DADKExecContext) -> BootCheckReport {
+    let mut checks = Vec::new();
+    let boot_config = &ctx.manifest().metadata.boot_config;
+    let boot = match BootConfigFile::load(boot_config) {
+        Ok(boot) => boot,
+        Err(e) => {
+            checks.push(CheckResult::problem(
+                "boot config",
+                CheckStatus::Fail,
+                format!("failed to load {}: {}", boot_config.display(), e),
+                "fix the boot config, see dadk-config/templates/config/boot.toml",
+            ));
+            return BootCheckReport {
+                checks,
+                qemu_command: None,
+            };
+        }
+    };
+
+    let arch = ctx.target_arch();
+    let problems = boot.check(arch);
+    if problems.is_empty() {
+        checks.push(CheckResult::pass(
+            "boot config",
+            format!(
+                "{} (boot-protocol: {})",
+                boot_config.display(),
+                boot.metadata.boot_protocol.name()
+            ),
+        ));
+    }
+    for problem in problems {
+        checks.push(CheckResult::problem(
+            "boot config",
+            CheckStatus::Fail,
+            problem,
+            "fix the boot config, see dadk-config/templates/config/boot.toml",
+        ));
+    }
+
+    for (name, path) in boot.referenced_paths() {
+        let full_path = ctx.workdir().join(&path);
+        checks.push(if full_path.exists() {
+            CheckResult::pass(name, path.display().to_string())
+        } else {
+            CheckResult::problem(
+                name,
+                CheckStatus::Fail,
+                format!("{} does not exist", full_path.display()),
+                format!("install it, or fix the path of {} in the boot config", name),
+            )
+        });
+    }
+
+    let qemu = boot.qemu.as_ref();
+    checks.push(check_qemu(qemu, arch));
+    checks.push(check_kvm(
+        qemu.is_some_and(|q| q.accelerate() == QemuAccel::Kvm),
+    ));
+
+    let qemu_command = match Qemu::new(ctx, false) {
+        Ok(qemu) => Some(qemu.command_line()),
+        Err(e) => {
+            checks.push(CheckResult::problem(
+                "qemu command",
+                CheckStatus::Fail,
+                e.to_string(),
+                "fix the [qemu] section of the boot config",
+            ));
+            None
+        }
+    };
+    BootCheckReport {
+        checks,
+        qemu_command,
+    }
+}
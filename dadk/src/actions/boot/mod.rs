@@ -0,0 +1,445 @@
+//! # `dadk boot`
+//!
+//! 按照boot配置中的`[qemu]`，使用QEMU从磁盘镜像启动DragonOS：
+//!
+//! - `[[qemu.port-forwards]]`：通过QEMU的user模式网络，把主机的端口转发到DragonOS中，便于ssh/scp
+//! - `[qemu.share]`：通过9p或者virtio-fs，把主机上的目录（默认为sysroot目录）共享给DragonOS。
+//!   新构建的用户程序安装到sysroot后，在DragonOS中挂载共享目录即可使用，不需要重新制作磁盘镜像
+//!
+//! `dadk boot check`在制作磁盘镜像之前检查boot配置以及QEMU、KVM是否可用
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    boot::{
+        hypervisor::qemu::{QemuAccel, QemuConfig, ShareType},
+        metadata::{BootMode, BootProtocol},
+        BootConfigFile,
+    },
+    common::target_arch::TargetArch,
+};
+
+use crate::{
+    console::boot::{BootCommand, BootRunCommand},
+    context::DADKExecContext,
+};
+
+use super::rootfs::{compress, disk_img};
+
+mod check;
+
+/// 用户模式网络的netdev id
+const NETDEV_ID: &str = "dadk-net";
+/// virtio-fs的chardev id
+const VIRTIOFS_CHARDEV_ID: &str = "dadk-virtiofs";
+/// virtio-fs使用的共享内存的id
+const VIRTIOFS_MEMORY_ID: &str = "dadk-mem";
+/// 等待virtiofsd创建socket的时间
+const VIRTIOFSD_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) fn run(ctx: &DADKExecContext, cmd: &BootCommand) -> Result<()> {
+    match cmd {
+        BootCommand::Run(args) => run_qemu(ctx, args),
+        BootCommand::Check(args) => check::run(ctx, args),
+    }
+}
+
+/// 准备从磁盘镜像启动DragonOS：检查boot配置，从压缩的镜像恢复raw镜像（如果需要），
+/// 返回QEMU的命令行，但不启动
+pub(super) fn prepare(ctx: &DADKExecContext) -> Result<String> {
+    let qemu = Qemu::new(ctx, false)?;
+    if let Some(source) = disk_img::mounted_source(ctx) {
+        return Err(anyhow!(
+            "Disk image is mounted at {} ({}), run `dadk rootfs umount` first",
+            ctx.disk_mount_path().display(),
+            source
+        ));
+    }
+    compress::ensure_raw_image(ctx)?;
+    Ok(qemu.command_line())
+}
+
+fn run_qemu(ctx: &DADKExecContext, args: &BootRunCommand) -> Result<()> {
+    let qemu = Qemu::new(ctx, args.nographic)?;
+    if args.dry_run {
+        println!("{}", qemu.command_line());
+        return Ok(());
+    }
+    if let Some(source) = disk_img::mounted_source(ctx) {
+        return Err(anyhow!(
+            "Disk image is mounted at {} ({}), run `dadk rootfs umount` first",
+            ctx.disk_mount_path().display(),
+            source
+        ));
+    }
+
+    compress::ensure_raw_image(ctx)?;
+
+    let _virtiofsd = qemu.start_virtiofsd()?;
+    log::info!("Booting QEMU: {}", qemu.command_line());
+    let status = qemu
+        .command()
+        .status()
+        .map_err(|e| anyhow!("Failed to start QEMU: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!("QEMU exited with {}", status));
+    }
+    Ok(())
+}
+
+/// # QEMU的命令行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Qemu {
+    program: String,
+    args: Vec<String>,
+    /// 使用virtio-fs共享目录时，需要先启动的virtiofsd
+    virtiofsd: Option<VirtiofsdCommand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VirtiofsdCommand {
+    program: String,
+    socket: PathBuf,
+    shared_dir: PathBuf,
+}
+
+impl Qemu {
+    /// 按照manifest中的boot配置生成命令行
+    pub(super) fn new(ctx: &DADKExecContext, nographic: bool) -> Result<Self> {
+        let boot_config = &ctx.manifest().metadata.boot_config;
+        let boot = BootConfigFile::load(boot_config).map_err(|e| {
+            anyhow!(
+                "Failed to load boot config {}: {}",
+                boot_config.display(),
+                e
+            )
+        })?;
+        if matches!(
+            boot.metadata.boot_protocol,
+            BootProtocol::Direct | BootProtocol::DragonStub
+        ) {
+            return Err(anyhow!(
+                "Only grub-legacy and grub-efi boot protocols can boot from the disk image"
+            ));
+        }
+        let qemu = boot
+            .qemu
+            .as_ref()
+            .ok_or_else(|| anyhow!("`qemu` is not set in boot config {}", boot_config.display()))?;
+        let share_dir = match qemu.share() {
+            Some(share) => Some(match &share.path {
+                Some(path) => ctx.workdir().join(path),
+                None => ctx.sysroot_dir()?,
+            }),
+            None => None,
+        };
+        let virtiofsd_socket =
+            std::env::temp_dir().join(format!("dadk-virtiofsd-{}.sock", std::process::id()));
+        Self::from_config(
+            qemu,
+            ctx.target_arch(),
+            &ctx.disk_image_path(),
+            nographic || boot.metadata.boot_mode == BootMode::NoGraphic,
+            share_dir.as_deref(),
+            &virtiofsd_socket,
+        )
+    }
+
+    fn from_config(
+        qemu: &QemuConfig,
+        arch: TargetArch,
+        disk_image: &Path,
+        nographic: bool,
+        share_dir: Option<&Path>,
+        virtiofsd_socket: &Path,
+    ) -> Result<Self> {
+        let config_args = qemu.args();
+        let mut args: Vec<String> = config_args.split_whitespace().map(String::from).collect();
+        if nographic {
+            args.extend(qemu.no_graphic_args.split_whitespace().map(String::from));
+        }
+        let accel = match qemu.accelerate() {
+            QemuAccel::None => None,
+            QemuAccel::Kvm => Some("kvm"),
+            QemuAccel::Hvf => Some("hvf"),
+            QemuAccel::Tcg => Some("tcg"),
+        };
+        if let Some(accel) = accel {
+            args.extend(["-accel".to_string(), accel.to_string()]);
+        }
+        args.push("-drive".to_string());
+        args.push(format!(
+            "file={},format=raw,index=0,media=disk",
+            disk_image.display()
+        ));
+
+        if !qemu.port_forwards().is_empty() {
+            let mut netdev = format!("user,id={}", NETDEV_ID);
+            for forward in qemu.port_forwards() {
+                netdev.push_str(&format!(
+                    ",hostfwd={}::{}-:{}",
+                    forward.protocol.name(),
+                    forward.host,
+                    forward.guest
+                ));
+            }
+            args.extend([
+                "-netdev".to_string(),
+                netdev,
+                "-device".to_string(),
+                format!("virtio-net-pci,netdev={}", NETDEV_ID),
+            ]);
+        }
+
+        let mut virtiofsd = None;
+        if let (Some(share), Some(dir)) = (qemu.share(), share_dir) {
+            match share.share_type {
+                ShareType::NineP => {
+                    let mut virtfs = format!(
+                        "local,path={},mount_tag={},security_model=none,id={}",
+                        dir.display(),
+                        share.tag,
+                        share.tag
+                    );
+                    if share.readonly {
+                        virtfs.push_str(",readonly=on");
+                    }
+                    args.extend(["-virtfs".to_string(), virtfs]);
+                }
+                ShareType::Virtiofs => {
+                    // vhost-user设备要求客户机的内存是共享内存，大小与`-m`相同
+                    let memory = memory_size(&args).ok_or_else(|| {
+                        anyhow!("qemu.share: virtiofs requires `-m` to be set in qemu.args")
+                    })?;
+                    args.extend([
+                        "-chardev".to_string(),
+                        format!(
+                            "socket,id={},path={}",
+                            VIRTIOFS_CHARDEV_ID,
+                            virtiofsd_socket.display()
+                        ),
+                        "-device".to_string(),
+                        format!(
+                            "vhost-user-fs-pci,chardev={},tag={}",
+                            VIRTIOFS_CHARDEV_ID, share.tag
+                        ),
+                        "-object".to_string(),
+                        format!(
+                            "memory-backend-memfd,id={},size={},share=on",
+                            VIRTIOFS_MEMORY_ID, memory
+                        ),
+                        "-numa".to_string(),
+                        format!("node,memdev={}", VIRTIOFS_MEMORY_ID),
+                    ]);
+                    virtiofsd = Some(VirtiofsdCommand {
+                        program: share
+                            .virtiofsd
+                            .clone()
+                            .unwrap_or_else(|| "virtiofsd".to_string()),
+                        socket: virtiofsd_socket.to_path_buf(),
+                        shared_dir: dir.to_path_buf(),
+                    });
+                }
+            }
+        }
+
+        Ok(Self {
+            program: qemu.path(arch),
+            args,
+            virtiofsd,
+        })
+    }
+
+    /// 追加参数
+    pub(super) fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub(super) fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    /// 用于输出的命令行
+    pub(super) fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// 需要时启动virtiofsd，返回的进程drop时被终止
+    pub(super) fn start_virtiofsd(&self) -> Result<Option<Virtiofsd>> {
+        let Some(cmd) = &self.virtiofsd else {
+            return Ok(None);
+        };
+        std::fs::remove_file(&cmd.socket).ok();
+        let child = Command::new(&cmd.program)
+            .arg(format!("--socket-path={}", cmd.socket.display()))
+            .arg(format!("--shared-dir={}", cmd.shared_dir.display()))
+            .arg("--cache=auto")
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start {}: {}", cmd.program, e))?;
+        let mut virtiofsd = Virtiofsd {
+            child,
+            socket: cmd.socket.clone(),
+        };
+        let start = Instant::now();
+        while !cmd.socket.exists() {
+            if let Ok(Some(status)) = virtiofsd.child.try_wait() {
+                return Err(anyhow!("{} exited with {}", cmd.program, status));
+            }
+            if start.elapsed() >= VIRTIOFSD_STARTUP_TIMEOUT {
+                return Err(anyhow!(
+                    "{} did not create {} in {:?}",
+                    cmd.program,
+                    cmd.socket.display(),
+                    VIRTIOFSD_STARTUP_TIMEOUT
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Ok(Some(virtiofsd))
+    }
+}
+
+/// 运行中的virtiofsd
+pub(super) struct Virtiofsd {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Drop for Virtiofsd {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+        std::fs::remove_file(&self.socket).ok();
+    }
+}
+
+/// QEMU参数中`-m`指定的内存大小（例如`512M`、`size=1G`）
+fn memory_size(args: &[String]) -> Option<String> {
+    let pos = args.iter().rposition(|a| a == "-m")?;
+    let value = args.get(pos + 1)?;
+    let size = value
+        .split(',')
+        .find_map(|part| match part.split_once('=') {
+            Some(("size", size)) => Some(size),
+            Some(_) => None,
+            None => Some(part),
+        })?;
+    Some(size.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qemu_config(extra: &str) -> QemuConfig {
+        let content = format!(
+            "[metadata]\nboot-protocol = \"grub-legacy\"\nboot-mode = \"graphic\"\nhypervisor = \"qemu\"\n\
+             [qemu]\nargs = \"-m 512M -smp 2\"\nno-graphic-args = \"-nographic\"\naccelerate = \"kvm\"\n{}",
+            extra
+        );
+        BootConfigFile::load_from_str(&content)
+            .unwrap()
+            .qemu
+            .unwrap()
+    }
+
+    fn build(qemu: &QemuConfig, nographic: bool, share_dir: Option<&Path>) -> Result<Qemu> {
+        Qemu::from_config(
+            qemu,
+            TargetArch::X86_64,
+            Path::new("/tmp/disk.img"),
+            nographic,
+            share_dir,
+            Path::new("/tmp/virtiofsd.sock"),
+        )
+    }
+
+    #[test]
+    fn test_qemu_command_line() {
+        let qemu = build(&qemu_config(""), false, None).unwrap();
+        assert_eq!(
+            qemu.command_line(),
+            "qemu-system-x86_64 -m 512M -smp 2 -accel kvm -drive file=/tmp/disk.img,format=raw,index=0,media=disk"
+        );
+        assert_eq!(qemu.virtiofsd, None);
+        let qemu = build(&qemu_config(""), true, None)
+            .unwrap()
+            .arg("-no-reboot");
+        assert!(qemu.command_line().ends_with("-nographic -accel kvm -drive file=/tmp/disk.img,format=raw,index=0,media=disk -no-reboot"));
+    }
+
+    #[test]
+    fn test_qemu_port_forwards() {
+        let config = qemu_config(
+            "[[qemu.port-forwards]]\nhost = 2222\nguest = 22\n\
+             [[qemu.port-forwards]]\nhost = 5353\nguest = 53\nprotocol = \"udp\"\n",
+        );
+        let qemu = build(&config, false, None).unwrap();
+        assert!(qemu.command_line().ends_with(
+            "-netdev user,id=dadk-net,hostfwd=tcp::2222-:22,hostfwd=udp::5353-:53 -device virtio-net-pci,netdev=dadk-net"
+        ));
+    }
+
+    #[test]
+    fn test_qemu_share_9p() {
+        let config = qemu_config("[qemu.share]\ntag = \"sysroot\"\nreadonly = true\n");
+        let qemu = build(&config, false, Some(Path::new("/work/bin/sysroot"))).unwrap();
+        assert!(qemu.command_line().ends_with(
+            "-virtfs local,path=/work/bin/sysroot,mount_tag=sysroot,security_model=none,id=sysroot,readonly=on"
+        ));
+        assert_eq!(qemu.virtiofsd, None);
+    }
+
+    #[test]
+    fn test_qemu_share_virtiofs() {
+        let config = qemu_config("[qemu.share]\ntype = \"virtiofs\"\n");
+        let qemu = build(&config, false, Some(Path::new("/work/bin/sysroot"))).unwrap();
+        assert!(qemu.command_line().ends_with(
+            "-chardev socket,id=dadk-virtiofs,path=/tmp/virtiofsd.sock \
+             -device vhost-user-fs-pci,chardev=dadk-virtiofs,tag=dadk \
+             -object memory-backend-memfd,id=dadk-mem,size=512M,share=on \
+             -numa node,memdev=dadk-mem"
+        ));
+        assert_eq!(
+            qemu.virtiofsd,
+            Some(VirtiofsdCommand {
+                program: "virtiofsd".to_string(),
+                socket: PathBuf::from("/tmp/virtiofsd.sock"),
+                shared_dir: PathBuf::from("/work/bin/sysroot"),
+            })
+        );
+
+        // 没有指定内存大小时无法使用virtio-fs
+        let content = "[metadata]\nboot-protocol = \"grub-legacy\"\nboot-mode = \"graphic\"\nhypervisor = \"qemu\"\n\
+             [qemu]\nargs = \"-smp 2\"\nno-graphic-args = \"\"\n[qemu.share]\ntype = \"virtiofs\"\n";
+        let config = BootConfigFile::load_from_str(content)
+            .unwrap()
+            .qemu
+            .unwrap();
+        assert!(build(&config, false, Some(Path::new("/work"))).is_err());
+    }
+
+    #[test]
+    fn test_memory_size() {
+        let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(memory_size(&args("-m 1G")), Some("1G".to_string()));
+        assert_eq!(
+            memory_size(&args("-m size=2G,slots=2,maxmem=4G")),
+            Some("2G".to_string())
+        );
+        assert_eq!(memory_size(&args("-smp 2")), None);
+    }
+}
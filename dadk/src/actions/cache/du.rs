@@ -0,0 +1,22 @@
+//! # `dadk cache du`
+//!
+//! 统计每个任务版本的源码缓存、构建缓存以及安装到sysroot中的文件所占用的空间，按总大小从大到小输出。
+
+use anyhow::{anyhow, Result};
+use dadk_user::{cache, pkgdb::PackageDatabase};
+
+use crate::{console::cache::CacheDuCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &CacheDuCommand) -> Result<()> {
+    let (cache_root_dir, tasks) = super::cache_root_and_tasks(ctx)?;
+    let sysroot = ctx.sysroot_dir()?;
+    let cached = cache::scan(&cache_root_dir, &tasks).map_err(|e| anyhow!(e))?;
+    let db = PackageDatabase::load(&sysroot).map_err(|e| anyhow!(e))?;
+    let usages = cache::disk_usage(&cached, &db, &sysroot);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&usages)?);
+    } else {
+        print!("{}", cache::disk_usage_table(&usages));
+    }
+    Ok(())
+}
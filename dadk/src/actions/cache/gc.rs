@@ -0,0 +1,192 @@
+//! # `dadk cache gc`
+//!
+//! 按照清理策略删除缓存根目录中不再需要的任务版本（包括它的构建缓存、源码缓存以及任务数据），
+//! 当前配置文件中的任务版本不会被删除。
+//!
+//! 删除前会列出将要删除的内容并请求确认，指定`--yes`时直接删除，指定`--dry-run`时只列出不删除。
+
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_user::{
+    cache::{self, format_size, CachedTask, GcPolicy},
+    lock::{self, LockMode},
+};
+
+use crate::{console::cache::CacheGcCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &CacheGcCommand) -> Result<()> {
+    let policy = GcPolicy {
+        keep_latest: args.keep_latest,
+        older_than: args.older_than,
+        max_size: args.max_size,
+    };
+    if policy.is_empty() {
+        return Err(anyhow!(
+            "No gc policy given, use --keep-latest, --max-size or --older-than"
+        ));
+    }
+
+    let (cache_root_dir, tasks) = super::cache_root_and_tasks(ctx)?;
+    // 扫描之前加锁，避免删除其他dadk进程正在使用的目录
+    let _lock = lock::lock_cache_root(&cache_root_dir, LockMode::Exclusive, ctx.lock_timeout())
+        .map_err(|e| anyhow!(e))?;
+    let in_use: BTreeSet<String> = tasks
+        .iter()
+        .flat_map(|t| [t.name_version(), t.source_cache_name()])
+        .collect();
+    let cached = cache::scan(&cache_root_dir, &tasks).map_err(|e| anyhow!(e))?;
+    let now = SystemTime::now();
+    let plan = cache::plan_gc(cached, &policy, &in_use, now);
+
+    if plan.remove.is_empty() {
+        println!(
+            "Nothing to remove ({} in {})",
+            format_size(plan.remaining()),
+            cache_root_dir.display()
+        );
+        return Ok(());
+    }
+
+    let mut out = std::io::stdout().lock();
+    print_plan(&mut out, &plan.remove, now)?;
+    writeln!(
+        out,
+        "{} task version(s), {} will be freed, {} will remain",
+        plan.remove.len(),
+        format_size(plan.freed()),
+        format_size(plan.remaining())
+    )?;
+    if args.dry_run {
+        return Ok(());
+    }
+    if !args.yes && !confirm(&mut std::io::stdin().lock(), &mut out)? {
+        writeln!(out, "Aborted")?;
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for task in &plan.remove {
+        if let Err(e) = task.remove() {
+            log::error!("{}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("Failed to remove {} task version(s)", failed));
+    }
+    writeln!(out, "Removed {}", format_size(plan.freed()))?;
+    Ok(())
+}
+
+fn print_plan(out: &mut impl Write, tasks: &[CachedTask], now: SystemTime) -> Result<()> {
+    let name_width = tasks.iter().map(|t| label(t).len()).max().unwrap_or(0);
+    for task in tasks {
+        let dirs: Vec<&str> = task.dirs.iter().map(|(t, _, _)| t.dir_name()).collect();
+        writeln!(
+            out,
+            "{:<width$}  {:>10}  last used {:<8}  {}",
+            label(task),
+            format_size(task.size()),
+            format_age(now, task.last_used),
+            dirs.join(","),
+            width = name_width
+        )?;
+    }
+    Ok(())
+}
+
+/// 任务名称和版本，无法确定时使用缓存目录名
+fn label(task: &CachedTask) -> String {
+    match (&task.name, &task.version) {
+        (Some(name), Some(version)) => format!("{}-{}", name, version),
+        _ => task.name_version.clone(),
+    }
+}
+
+fn format_age(now: SystemTime, time: SystemTime) -> String {
+    let secs = now.duration_since(time).unwrap_or_default().as_secs();
+    match secs {
+        0..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn confirm(input: &mut impl BufRead, out: &mut impl Write) -> Result<bool> {
+    write!(out, "Remove them? [y/N] ")?;
+    out.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(matches!(
+        line.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use dadk_user::executor::cache::CacheDirType;
+
+    use super::*;
+
+    #[test]
+    fn test_print_plan() {
+        let now = SystemTime::now();
+        let tasks = vec![
+            CachedTask {
+                name_version: "libc_0_1_0".to_string(),
+                name: Some("libc".to_string()),
+                version: Some("0.1.0".to_string()),
+                dirs: vec![
+                    (CacheDirType::Build, PathBuf::from("build/libc_0_1_0"), 1536),
+                    (
+                        CacheDirType::TaskData,
+                        PathBuf::from("task_data/libc_0_1_0"),
+                        0,
+                    ),
+                ],
+                last_used: now - Duration::from_secs(3 * 86400),
+            },
+            CachedTask {
+                name_version: "old_app_0_1_0".to_string(),
+                name: None,
+                version: None,
+                dirs: vec![(CacheDirType::Source, PathBuf::from("source/old"), 10)],
+                last_used: now - Duration::from_secs(7200),
+            },
+        ];
+        let mut out = Vec::new();
+        print_plan(&mut out, &tasks, now).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "libc-0.1.0        1.5 KiB  last used 3d ago    build,task_data"
+        );
+        assert_eq!(
+            lines[1],
+            "old_app_0_1_0        10 B  last used 2h ago    source"
+        );
+    }
+
+    #[test]
+    fn test_confirm() {
+        for (input, expected) in [
+            ("y\n", true),
+            ("YES\n", true),
+            ("n\n", false),
+            ("\n", false),
+        ] {
+            let mut out = Vec::new();
+            assert_eq!(confirm(&mut input.as_bytes(), &mut out).unwrap(), expected);
+            assert_eq!(String::from_utf8(out).unwrap(), "Remove them? [y/N] ");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use dadk_user::parser::{task::DADKTask, Parser};
+
+use crate::{console::cache::CacheCommand, context::DADKExecContext};
+
+mod du;
+mod gc;
+
+pub(super) fn run(ctx: &DADKExecContext, cmd: &CacheCommand) -> Result<()> {
+    match cmd {
+        CacheCommand::Gc(args) => gc::run(ctx, args),
+        CacheCommand::Du(args) => du::run(ctx, args),
+    }
+}
+
+/// 缓存根目录，以及当前配置文件中的所有任务
+fn cache_root_and_tasks(ctx: &DADKExecContext) -> Result<(PathBuf, Vec<DADKTask>)> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let tasks = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?
+        .into_iter()
+        .map(|(_, task)| task)
+        .collect();
+    Ok((cache_root_dir, tasks))
+}
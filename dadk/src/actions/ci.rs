@@ -0,0 +1,188 @@
+//! # `dadk ci`
+//!
+//! 按顺序执行从配置到可启动镜像的完整流程，取代DragonOS仓库中Makefile对多条dadk命令的拼接：
+//!
+//! 1. `check-config`：检查rootfs、boot配置以及所有用户程序配置能否解析
+//! 2. `build`：构建用户程序（与`dadk user build`相同）
+//! 3. `install`：把用户程序安装到sysroot（sysroot目录不存在时直接创建）
+//! 4. `rootfs`：创建磁盘镜像（已存在时跳过），挂载后把sysroot复制到镜像中，然后卸载
+//! 5. `boot`：准备启动用的raw镜像，输出QEMU的命令行
+//!
+//! `--stop-at`指定最后执行的阶段。某个阶段失败时不再执行后面的阶段。
+//! 结束时（包括失败时）输出每个阶段的状态和耗时。
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{boot::BootConfigFile, rootfs::RootFSConfigFile};
+use dadk_user::parser::Parser;
+
+use super::{boot, rootfs, user};
+use crate::{
+    console::{
+        ci::{CiCommand, CiStage},
+        user::{UserBuildCommand, UserCommand, UserInstallCommand},
+    },
+    context::DADKExecContext,
+};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &CiCommand) -> Result<()> {
+    let stop_at = args.stop_at.unwrap_or(CiStage::Boot);
+    let stages: Vec<CiStage> = CiStage::ALL.into_iter().filter(|s| *s <= stop_at).collect();
+
+    let mut summary = CiSummary::default();
+    let mut result = Ok(());
+    for stage in stages {
+        if result.is_err() {
+            summary.push(stage, StageStatus::Skipped, Duration::ZERO);
+            continue;
+        }
+        log::info!("CI stage: {}", stage.as_str());
+        let start = Instant::now();
+        let r = run_stage(ctx, stage);
+        let status = if r.is_ok() {
+            StageStatus::Ok
+        } else {
+            StageStatus::Failed
+        };
+        summary.push(stage, status, start.elapsed());
+        result = r.map_err(|e| e.context(format!("CI stage {} failed", stage.as_str())));
+    }
+    eprint!("{}", summary.table());
+    result
+}
+
+fn run_stage(ctx: &DADKExecContext, stage: CiStage) -> Result<()> {
+    match stage {
+        CiStage::CheckConfig => check_config(ctx),
+        CiStage::Build => user::run_stage(ctx, &UserCommand::Build(UserBuildCommand::default())),
+        CiStage::Install => user::run_stage(
+            ctx,
+            &UserCommand::Install(UserInstallCommand {
+                force: false,
+                create_sysroot: true,
+                into_image: false,
+                task: Vec::new(),
+            }),
+        ),
+        CiStage::Rootfs => rootfs::update_image(ctx),
+        CiStage::Boot => {
+            let command_line = boot::prepare(ctx)?;
+            println!("{}", command_line);
+            Ok(())
+        }
+    }
+}
+
+/// 检查manifest引用的配置文件。manifest本身在dadk启动时已经解析过
+fn check_config(ctx: &DADKExecContext) -> Result<()> {
+    let metadata = &ctx.manifest().metadata;
+    RootFSConfigFile::load(&metadata.rootfs_config).map_err(|e| {
+        anyhow!(
+            "Failed to load rootfs config {}: {}",
+            metadata.rootfs_config.display(),
+            e
+        )
+    })?;
+    BootConfigFile::load(&metadata.boot_config).map_err(|e| {
+        anyhow!(
+            "Failed to load boot config {}: {}",
+            metadata.boot_config.display(),
+            e
+        )
+    })?;
+
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let tasks = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .parse()?;
+    log::info!("{} user task config(s) parsed", tasks.len());
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StageStatus {
+    Ok,
+    Failed,
+    /// 前面的阶段失败，没有执行
+    Skipped,
+}
+
+impl StageStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StageStatus::Ok => "ok",
+            StageStatus::Failed => "failed",
+            StageStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// 各阶段的执行结果
+#[derive(Debug, Default)]
+struct CiSummary {
+    stages: Vec<(CiStage, StageStatus, Duration)>,
+}
+
+impl CiSummary {
+    fn push(&mut self, stage: CiStage, status: StageStatus, elapsed: Duration) {
+        self.stages.push((stage, status, elapsed));
+    }
+
+    fn table(&self) -> String {
+        let mut out = format!("{:<14}{:<10}{}\n", "STAGE", "STATUS", "TIME");
+        for (stage, status, elapsed) in &self.stages {
+            let time = match status {
+                StageStatus::Skipped => "-".to_string(),
+                _ => format_duration(*elapsed),
+            };
+            out.push_str(&format!(
+                "{:<14}{:<10}{}\n",
+                stage.as_str(),
+                status.as_str(),
+                time
+            ));
+        }
+        let total: Duration = self.stages.iter().map(|(_, _, d)| *d).sum();
+        out.push_str(&format!("{:<24}{}\n", "total", format_duration(total)));
+        out
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", d.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_table() {
+        let mut summary = CiSummary::default();
+        summary.push(
+            CiStage::CheckConfig,
+            StageStatus::Ok,
+            Duration::from_millis(200),
+        );
+        summary.push(CiStage::Build, StageStatus::Failed, Duration::from_secs(75));
+        summary.push(CiStage::Install, StageStatus::Skipped, Duration::ZERO);
+        assert_eq!(
+            summary.table(),
+            "STAGE         STATUS    TIME\n\
+             check-config  ok        0.2s\n\
+             build         failed    1m15s\n\
+             install       skipped   -\n\
+             total                   1m15s\n"
+        );
+    }
+}
@@ -0,0 +1,589 @@
+//! # `dadk daemon`
+//!
+//! 以守护进程的方式运行：在内存中保留解析好的任务列表，通过Unix socket接受构建、安装、查询请求。
+//! IDE插件以及反复执行的命令不需要每次都重新解析、扫描所有的用户程序配置文件。
+//!
+//! 协议为JSON-RPC 2.0，每行一个请求或者响应（没有`id`的请求不返回响应）。支持的方法：
+//!
+//! - `build`：构建用户程序。参数（都可以省略）：`tasks`（只构建这些任务以及它们依赖的任务）、
+//!   `force`、`rebuild`（与`dadk user build`的同名参数相同）
+//! - `install`：安装用户程序。参数（都可以省略）：`tasks`、`force`、`create_sysroot`
+//! - `status`：单个任务的状态（与`dadk user status --json`相同）。参数：`task`
+//! - `list`：所有任务的状态（与`dadk user list --json`相同）
+//! - `reload`：丢弃保留的任务列表，重新解析配置文件
+//! - `shutdown`：停止守护进程
+//!
+//! 每次请求之前比较配置目录中所有文件的路径、大小和修改时间，发生变化时才重新解析配置文件。
+//! 构建、安装在主线程中依次执行，其他构建、安装请求排队等待；查询请求在各个连接的线程中处理，不需要等待。
+//! manifest只在启动时读取，修改后需要重启守护进程。
+//!
+//! 执行失败时，错误码为`-32000`，`data`中是带有错误码的dadk-user错误（与`--error-format json`相同）。
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::manifest::HookPoint;
+use dadk_user::{
+    affected, interrupt,
+    list::TaskList,
+    parser::task::DADKTask,
+    status::{find_task, TaskStatus},
+    BuildSession, DadkUserError, ErrorCode,
+};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use super::{hooks, user};
+use crate::{
+    console::{
+        daemon::DaemonCommand,
+        user::{UserBuildCommand, UserCommand, UserInstallCommand},
+    },
+    context::DADKExecContext,
+};
+
+/// 默认的socket文件名（位于缓存根目录下）
+const SOCKET_NAME: &str = "dadk.sock";
+/// 检查是否需要停止的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// JSON-RPC 2.0的错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// 执行失败，`data`中是dadk-user的错误
+const EXECUTION_FAILED: i64 = -32000;
+
+pub(super) fn run(ctx: &DADKExecContext, args: &DaemonCommand) -> Result<()> {
+    let socket = match &args.socket {
+        Some(path) => path.clone(),
+        None => ctx.cache_root_dir()?.join(SOCKET_NAME),
+    };
+    match &args.call {
+        Some(method) => call(&socket, method, args.params.as_deref()),
+        None => serve(ctx, &socket),
+    }
+}
+
+/// 向正在运行的守护进程发送一个请求，输出结果
+fn call(socket: &Path, method: &str, params: Option<&str>) -> Result<()> {
+    let params: Value = match params {
+        Some(params) => serde_json::from_str(params)
+            .map_err(|e| anyhow!("Invalid params {:?}: {}", params, e))?,
+        None => Value::Null,
+    };
+    let mut stream = UnixStream::connect(socket).map_err(|e| {
+        anyhow!(
+            "Failed to connect to dadk daemon at {}: {}",
+            socket.display(),
+            e
+        )
+    })?;
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    writeln!(stream, "{}", request)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response: Value = serde_json::from_str(&line)
+        .map_err(|e| anyhow!("Invalid response from dadk daemon {:?}: {}", line, e))?;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("dadk daemon returned an error: {}", error));
+    }
+    println!("{}", serde_json::to_string_pretty(&response["result"])?);
+    Ok(())
+}
+
+/// 启动守护进程，直到收到`shutdown`请求或者中断信号
+fn serve(ctx: &DADKExecContext, socket: &Path) -> Result<()> {
+    interrupt::install_handler();
+    let session = session(ctx)?;
+    let listener = bind(socket)?;
+    listener.set_nonblocking(true)?;
+    let path = socket.to_path_buf();
+    let _cleanup = interrupt::register_cleanup(move || {
+        let _ = std::fs::remove_file(path);
+    });
+
+    let (tx, rx) = channel();
+    let daemon = Daemon::new(session, tx);
+    info!("dadk daemon listening on {}", socket.display());
+    std::thread::scope(|s| {
+        s.spawn(|| daemon.accept(s, &listener));
+        run_jobs(ctx, &daemon, rx);
+    });
+
+    let _ = std::fs::remove_file(socket);
+    info!("dadk daemon stopped");
+    Ok(())
+}
+
+/// 在主线程中依次执行构建、安装请求。返回时丢弃队列，还在排队的请求会收到错误
+fn run_jobs(ctx: &DADKExecContext, daemon: &Daemon, jobs: Receiver<Job>) {
+    while !daemon.stopped() {
+        match jobs.recv_timeout(POLL_INTERVAL) {
+            Ok(job) => {
+                let r = run_action(ctx, daemon, &job.cmd, &job.tasks);
+                let _ = job.reply.send(r);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// 使用保留的任务列表执行构建或者安装（包括对应的钩子）
+fn run_action(
+    ctx: &DADKExecContext,
+    daemon: &Daemon,
+    cmd: &UserCommand,
+    selected: &[String],
+) -> Result<Value, RpcError> {
+    let (pre, post) = match cmd {
+        UserCommand::Install(_) => (HookPoint::PreInstall, HookPoint::PostInstall),
+        _ => (HookPoint::PreBuild, HookPoint::PostBuild),
+    };
+    let start = Instant::now();
+    let (count, reparsed) = hooks::with_hooks(ctx, pre, post, || {
+        let (mut tasks, reparsed) = daemon.tasks()?;
+        if !selected.is_empty() {
+            tasks = affected::select(tasks, selected)
+                .map_err(|e| DadkUserError::new(ErrorCode::InvalidConfig, e))?;
+        }
+        let count = tasks.len();
+        user::build_session(ctx, cmd)?.run_tasks(tasks)?;
+        Ok((count, reparsed))
+    })?;
+    Ok(json!({
+        "tasks": count,
+        "reparsed": reparsed,
+        "duration_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+/// 用于解析配置文件、读取任务状态的会话
+fn session(ctx: &DADKExecContext) -> Result<BuildSession> {
+    user::build_session(ctx, &UserCommand::Build(UserBuildCommand::default()))
+}
+
+/// 绑定socket。socket文件已经存在时，如果有守护进程在监听则报错，否则删除残留的文件
+fn bind(socket: &Path) -> Result<UnixListener> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(anyhow!(
+                "Another dadk daemon is already listening on {}",
+                socket.display()
+            ));
+        }
+        std::fs::remove_file(socket)
+            .map_err(|e| anyhow!("Failed to remove stale socket {}: {}", socket.display(), e))?;
+    }
+    UnixListener::bind(socket)
+        .map_err(|e| anyhow!("Failed to bind socket {}: {}", socket.display(), e))
+}
+
+/// 配置目录中所有文件的路径 -> (大小, 修改时间)
+type Fingerprint = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+/// 解析好的任务列表，以及解析时配置目录的状态
+struct TaskGraph {
+    fingerprint: Fingerprint,
+    tasks: Vec<(PathBuf, DADKTask)>,
+}
+
+/// 交给主线程执行的构建、安装请求
+struct Job {
+    cmd: UserCommand,
+    tasks: Vec<String>,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+struct Daemon {
+    session: BuildSession,
+    graph: Mutex<Option<TaskGraph>>,
+    jobs: Sender<Job>,
+    shutdown: AtomicBool,
+}
+
+impl Daemon {
+    fn new(session: BuildSession, jobs: Sender<Job>) -> Self {
+        Self {
+            session,
+            graph: Mutex::new(None),
+            jobs,
+            shutdown: AtomicBool::new(false),
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst) || interrupt::signal_received()
+    }
+
+    /// 接受连接，每个连接在单独的线程中处理
+    fn accept<'scope>(
+        &'scope self,
+        s: &'scope std::thread::Scope<'scope, '_>,
+        listener: &UnixListener,
+    ) {
+        while !self.stopped() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    s.spawn(move || self.serve_connection(stream));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => warn!("Failed to accept connection: {}", e),
+            }
+        }
+    }
+
+    fn serve_connection(&self, stream: UnixStream) {
+        if let Err(e) = self.try_serve_connection(stream) {
+            warn!("dadk daemon connection error: {}", e);
+        }
+    }
+
+    /// 逐行读取请求并返回响应。设置读取超时，以便在守护进程停止时关闭空闲的连接
+    fn try_serve_connection(&self, stream: UnixStream) -> std::io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        // 读取超时时，已经读到的部分保留在line中
+        let mut line = Vec::new();
+        while !self.stopped() {
+            let eof = match reader.read_until(b'\n', &mut line) {
+                Ok(n) => n == 0 || !line.ends_with(b"\n"),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            let request = String::from_utf8_lossy(&line).trim().to_string();
+            line.clear();
+            if !request.is_empty() {
+                if let Some(response) = self.handle(&request) {
+                    writeln!(writer, "{}", response)?;
+                }
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理一个请求，返回响应（没有`id`的请求不返回响应）
+    fn handle(&self, line: &str) -> Option<Value> {
+        let request = match parse_request(line) {
+            Ok(request) => request,
+            Err(e) => return Some(response(Value::Null, Err(e))),
+        };
+        let result = self.dispatch(&request.method, request.params);
+        request.id.map(|id| response(id, result))
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "build" => {
+                let params: BuildParams = parse_params(params)?;
+                let cmd = UserCommand::Build(UserBuildCommand {
+                    force: params.force,
+                    rebuild: params.rebuild,
+                    ..Default::default()
+                });
+                self.submit(cmd, params.tasks)
+            }
+            "install" => {
+                let params: InstallParams = parse_params(params)?;
+                let cmd = UserCommand::Install(UserInstallCommand {
+                    force: params.force,
+                    create_sysroot: params.create_sysroot,
+                    ..Default::default()
+                });
+                self.submit(cmd, params.tasks)
+            }
+            "status" => {
+                let params: StatusParams = parse_params(params)?;
+                let (tasks, _) = self.tasks()?;
+                let (config_file, task) = find_task(&tasks, &params.task)
+                    .map_err(|e| DadkUserError::new(ErrorCode::InvalidConfig, e))?;
+                let status = TaskStatus::load(self.cache_root(), config_file, task);
+                Ok(serde_json::to_value(status).expect("Failed to serialize task status"))
+            }
+            "list" => {
+                let (tasks, _) = self.tasks()?;
+                let list = TaskList::collect(self.cache_root(), &tasks);
+                Ok(serde_json::to_value(list.tasks()).expect("Failed to serialize task list"))
+            }
+            "reload" => {
+                *self.graph() = None;
+                let (tasks, _) = self.tasks()?;
+                Ok(json!({"tasks": tasks.len()}))
+            }
+            "shutdown" => {
+                info!("dadk daemon is shutting down");
+                self.shutdown.store(true, Ordering::SeqCst);
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", method),
+            )),
+        }
+    }
+
+    /// 把构建、安装请求交给主线程，等待执行结果
+    fn submit(&self, cmd: UserCommand, tasks: Vec<String>) -> Result<Value, RpcError> {
+        let (reply, result) = channel();
+        let stopping =
+            || DadkUserError::new(ErrorCode::Interrupted, "dadk daemon is shutting down");
+        self.jobs
+            .send(Job { cmd, tasks, reply })
+            .map_err(|_| stopping())?;
+        result.recv().map_err(|_| stopping())?
+    }
+
+    fn cache_root(&self) -> &Path {
+        self.session.context().cache_root()
+    }
+
+    fn graph(&self) -> MutexGuard<'_, Option<TaskGraph>> {
+        self.graph.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 保留的任务列表。配置目录发生变化时重新解析，此时第二个返回值为true
+    fn tasks(&self) -> Result<(Vec<(PathBuf, DADKTask)>, bool), DadkUserError> {
+        let context = self.session.context();
+        let dirs: Vec<PathBuf> = context
+            .config_dir()
+            .into_iter()
+            .chain(context.overlay_config_dirs())
+            .cloned()
+            .collect();
+        let fingerprint = fingerprint(&dirs);
+
+        let mut graph = self.graph();
+        if let Some(graph) = graph.as_ref().filter(|g| g.fingerprint == fingerprint) {
+            return Ok((graph.tasks.clone(), false));
+        }
+        let tasks = self.session.parse()?;
+        info!("Parsed {} task(s)", tasks.len());
+        *graph = Some(TaskGraph {
+            fingerprint,
+            tasks: tasks.clone(),
+        });
+        Ok((tasks, true))
+    }
+}
+
+/// 递归地记录目录中所有文件的大小和修改时间。无法读取的目录和文件被忽略
+fn fingerprint(dirs: &[PathBuf]) -> Fingerprint {
+    let mut fingerprint = Fingerprint::new();
+    let mut pending: Vec<PathBuf> = dirs.to_vec();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+            } else {
+                fingerprint.insert(path, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    fingerprint
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BuildParams {
+    tasks: Vec<String>,
+    force: bool,
+    rebuild: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct InstallParams {
+    tasks: Vec<String>,
+    force: bool,
+    create_sysroot: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatusParams {
+    task: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<DadkUserError> for RpcError {
+    fn from(e: DadkUserError) -> Self {
+        Self {
+            code: EXECUTION_FAILED,
+            message: e.message().to_string(),
+            data: Some(serde_json::to_value(&e).expect("Failed to serialize error")),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        e.downcast::<DadkUserError>()
+            .unwrap_or_else(|e| DadkUserError::new(ErrorCode::Other, format!("{:#}", e)))
+            .into()
+    }
+}
+
+fn parse_request(line: &str) -> Result<Request, RpcError> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| RpcError::new(PARSE_ERROR, format!("Parse error: {}", e)))?;
+    let request: Request = serde_json::from_value(value)
+        .map_err(|e| RpcError::new(INVALID_REQUEST, format!("Invalid request: {}", e)))?;
+    if request.jsonrpc != "2.0" {
+        return Err(RpcError::new(
+            INVALID_REQUEST,
+            format!("Unsupported jsonrpc version: {}", request.jsonrpc),
+        ));
+    }
+    Ok(request)
+}
+
+/// 省略参数时按照空对象处理
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => {
+            let mut error = json!({"code": e.code, "message": e.message});
+            if let Some(data) = e.data {
+                error["data"] = data;
+            }
+            json!({"jsonrpc": "2.0", "id": id, "error": error})
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            r#"{"jsonrpc": "2.0", "id": 7, "method": "build", "params": {"tasks": ["hello"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(request.method, "build");
+        let params: BuildParams = parse_params(request.params).unwrap();
+        assert_eq!(params.tasks, vec!["hello".to_string()]);
+        assert!(!params.force);
+
+        let request = parse_request(r#"{"jsonrpc": "2.0", "method": "shutdown"}"#).unwrap();
+        assert_eq!(request.id, None);
+        let params: InstallParams = parse_params(request.params).unwrap();
+        assert!(params.tasks.is_empty());
+
+        assert_eq!(parse_request("{").unwrap_err().code, PARSE_ERROR);
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "1.0", "id": 1, "method": "list"}"#)
+                .unwrap_err()
+                .code,
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 1}"#)
+                .unwrap_err()
+                .code,
+            INVALID_REQUEST
+        );
+        let e = parse_params::<BuildParams>(json!({"jobs": 4})).unwrap_err();
+        assert_eq!(e.code, INVALID_PARAMS);
+        let e = parse_params::<StatusParams>(Value::Null).unwrap_err();
+        assert_eq!(e.code, INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_response() {
+        assert_eq!(
+            response(json!(1), Ok(json!({"tasks": 3}))),
+            json!({"jsonrpc": "2.0", "id": 1, "result": {"tasks": 3}})
+        );
+        let e = RpcError::new(METHOD_NOT_FOUND, "Method not found: foo");
+        assert_eq!(
+            response(json!(2), Err(e)),
+            json!({"jsonrpc": "2.0", "id": 2, "error": {"code": -32601, "message": "Method not found: foo"}})
+        );
+
+        let e: RpcError = DadkUserError::new(ErrorCode::BuildFailed, "hello failed").into();
+        let r = response(json!(3), Err(e));
+        assert_eq!(r["error"]["code"], json!(EXECUTION_FAILED));
+        assert_eq!(r["error"]["message"], json!("hello failed"));
+        assert_eq!(r["error"]["data"]["code"], json!("build-failed"));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = vec![dir.path().to_path_buf()];
+        std::fs::create_dir(dir.path().join("apps")).unwrap();
+        std::fs::write(dir.path().join("apps/hello.toml"), "name = \"hello\"").unwrap();
+        let before = fingerprint(&dirs);
+        assert_eq!(before.len(), 1);
+        assert_eq!(fingerprint(&dirs), before);
+
+        std::fs::write(dir.path().join("apps/hello.toml"), "name = \"hello2\"").unwrap();
+        assert_ne!(fingerprint(&dirs), before);
+        std::fs::write(dir.path().join("libc.toml"), "").unwrap();
+        assert_eq!(fingerprint(&dirs).len(), 2);
+    }
+}
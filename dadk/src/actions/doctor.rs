@@ -0,0 +1,521 @@
+//! # `dadk doctor`
+//!
+//! 检查主机上构建、运行DragonOS所需的工具和环境，输出检查结果以及修复建议：
+//!
+//! - 制作磁盘镜像需要的`losetup`、`mount`、`fdisk`、`mkfs.fat`等工具
+//! - 拉取源码需要的`git`
+//! - 启动DragonOS需要的QEMU（按照boot配置中的`qemu.path-prefix`查找），以及KVM是否可用
+//! - `dadk profile`需要的`gdb`、`nm`
+//! - 构建cargo任务需要的rustup以及对应架构的编译目标
+//! - 缓存根目录所在磁盘的剩余空间
+//!
+//! 当前目录下有manifest时按照manifest中的架构、目录进行检查，否则使用主机的架构。
+//! 有检查失败时返回错误。
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    boot::{
+        hypervisor::qemu::{QemuAccel, QemuConfig},
+        BootConfigFile,
+    },
+    common::{target_arch::TargetArch, task::CargoConfig},
+    manifest::DadkManifestFile,
+};
+use dadk_user::cache::format_size;
+use serde::Serialize;
+
+use crate::{
+    console::doctor::DoctorCommand,
+    context::{check_requirements, DADKExecContext},
+};
+
+/// 剩余空间少于该值时检查失败
+const MIN_FREE_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+/// 剩余空间少于该值时给出警告
+const LOW_FREE_SPACE: u64 = 10 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum CheckStatus {
+    Pass,
+    /// 只影响部分功能
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct CheckResult {
+    pub(super) name: String,
+    pub(super) status: CheckStatus,
+    pub(super) detail: String,
+    /// 检查没有通过时的修复建议
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+impl CheckResult {
+    pub(super) fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub(super) fn problem(
+        name: &str,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// 只用于制作磁盘镜像的工具。在其他系统上只能构建、打包用户程序，不检查这些工具
+const LINUX_ONLY_TOOLS: [&str; 4] = ["losetup", "mount", "fdisk", "mkfs.fat"];
+
+/// 需要检查的命令行工具：名称、用途、缺少时的状态、修复建议
+const HOST_TOOLS: [(&str, &str, CheckStatus, &str); 9] = [
+    ("git", "fetch git sources", CheckStatus::Fail, "install git"),
+    (
+        "losetup",
+        "mount disk images",
+        CheckStatus::Fail,
+        "install util-linux",
+    ),
+    (
+        "mount",
+        "mount disk images",
+        CheckStatus::Fail,
+        "install util-linux",
+    ),
+    (
+        "fdisk",
+        "partition disk images",
+        CheckStatus::Fail,
+        "install fdisk (util-linux)",
+    ),
+    (
+        "mkfs.fat",
+        "format FAT32 disk images",
+        CheckStatus::Fail,
+        "install dosfstools",
+    ),
+    (
+        "gdb",
+        "sample the kernel with `dadk profile`",
+        CheckStatus::Warn,
+        "install gdb",
+    ),
+    (
+        "nm",
+        "resolve kernel symbols with `dadk profile`",
+        CheckStatus::Warn,
+        "install binutils",
+    ),
+    (
+        "qemu-img",
+        "produce qcow2 disk images",
+        CheckStatus::Warn,
+        "install qemu-utils",
+    ),
+    (
+        "zstd",
+        "produce zstd-compressed disk images",
+        CheckStatus::Warn,
+        "install zstd",
+    ),
+];
+
+pub(super) fn run(ctx: &DADKExecContext, args: &DoctorCommand) -> Result<()> {
+    let results = run_checks(ctx);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        print!("{}", format_table(&results));
+    }
+    let failed = results
+        .iter()
+        .filter(|r| r.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(anyhow!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+fn run_checks(ctx: &DADKExecContext) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+    let manifest = check_manifest(ctx, &mut results);
+    let arch = manifest
+        .as_ref()
+        .map(|m| m.metadata.arch)
+        .unwrap_or_else(host_arch);
+
+    for (tool, usage, status, hint) in HOST_TOOLS {
+        if !cfg!(target_os = "linux") && LINUX_ONLY_TOOLS.contains(&tool) {
+            results.push(CheckResult::problem(
+                tool,
+                CheckStatus::Warn,
+                "not needed: disk images are only supported on Linux hosts",
+                "use a Linux host to create and mount disk images",
+            ));
+            continue;
+        }
+        results.push(match find_in_path(tool) {
+            Some(path) => CheckResult::pass(tool, path.display().to_string()),
+            None => CheckResult::problem(
+                tool,
+                status,
+                format!("not found in PATH (needed to {})", usage),
+                hint,
+            ),
+        });
+    }
+
+    let boot = manifest
+        .as_ref()
+        .and_then(|m| BootConfigFile::load(&m.metadata.boot_config).ok());
+    let qemu = boot.as_ref().and_then(|b| b.qemu.as_ref());
+    results.push(check_qemu(qemu, arch));
+    let kvm_required = qemu.is_some_and(|q| q.accelerate() == QemuAccel::Kvm);
+    results.push(check_kvm(kvm_required));
+
+    results.push(check_rust_target(arch));
+
+    if let Some(manifest) = &manifest {
+        results.push(check_free_space(&manifest.metadata.cache_root_dir));
+    }
+    results
+}
+
+/// 读取manifest，没有manifest时使用主机的架构继续检查
+fn check_manifest(
+    ctx: &DADKExecContext,
+    results: &mut Vec<CheckResult>,
+) -> Option<DadkManifestFile> {
+    let path = &ctx.command.manifest_path;
+    if !Path::new(path).is_file() {
+        results.push(CheckResult::problem(
+            "manifest",
+            CheckStatus::Warn,
+            format!("{} not found, checking for the host architecture", path),
+            "run `dadk doctor` in the DragonOS source directory, or pass `-f <manifest>`",
+        ));
+        return None;
+    }
+    match ctx.load_manifest() {
+        Ok(manifest) => {
+            if let Err(e) = check_requirements(&manifest) {
+                results.push(CheckResult::problem(
+                    "manifest",
+                    CheckStatus::Fail,
+                    e.to_string(),
+                    "install a dadk version matching dadk-version, or fix experimental in the manifest",
+                ));
+                return Some(manifest);
+            }
+            let arch: &str = manifest.metadata.arch.into();
+            results.push(CheckResult::pass(
+                "manifest",
+                format!("{} (arch: {})", path, arch),
+            ));
+            Some(manifest)
+        }
+        Err(e) => {
+            results.push(CheckResult::problem(
+                "manifest",
+                CheckStatus::Fail,
+                format!("failed to load {}: {}", path, e),
+                "fix the manifest, see docs/user-manual/manifest.md",
+            ));
+            None
+        }
+    }
+}
+
+fn host_arch() -> TargetArch {
+    TargetArch::try_from(std::env::consts::ARCH).unwrap_or(TargetArch::X86_64)
+}
+
+/// 在`PATH`中查找可执行文件。名称中包含`/`时直接检查该路径
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return is_executable(&path).then_some(path);
+    }
+    let paths = std::env::var_os("PATH")?;
+    // 普通用户的PATH中通常没有sbin目录，但是losetup、mkfs.fat等工具在其中
+    let sbin = ["/sbin", "/usr/sbin", "/usr/local/sbin"].map(PathBuf::from);
+    std::env::split_paths(&paths)
+        .chain(sbin)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// 目标架构的QEMU是否存在（按照boot配置中的`qemu.path-prefix`查找）
+pub(super) fn check_qemu(qemu: Option<&QemuConfig>, arch: TargetArch) -> CheckResult {
+    let qemu_bin = match qemu {
+        Some(qemu) => qemu.path(arch),
+        None => format!("qemu-system-{}", <&str>::from(arch)),
+    };
+    match find_in_path(&qemu_bin) {
+        Some(path) => CheckResult::pass("qemu", path.display().to_string()),
+        None => CheckResult::problem(
+            "qemu",
+            CheckStatus::Fail,
+            format!("{} not found in PATH (needed to boot DragonOS)", qemu_bin),
+            format!("install QEMU with {} support", <&str>::from(arch)),
+        ),
+    }
+}
+
+/// `/dev/kvm`是否可以读写。boot配置中启用了KVM加速时，KVM不可用是错误
+pub(super) fn check_kvm(required: bool) -> CheckResult {
+    let status = if required {
+        CheckStatus::Fail
+    } else {
+        CheckStatus::Warn
+    };
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/kvm")
+    {
+        Ok(_) => CheckResult::pass("kvm", "/dev/kvm is accessible"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => CheckResult::problem(
+            "kvm",
+            status,
+            "/dev/kvm not found, QEMU will fall back to slow emulation",
+            "enable virtualization in the BIOS and load the kvm module",
+        ),
+        Err(e) => CheckResult::problem(
+            "kvm",
+            status,
+            format!("cannot open /dev/kvm: {}", e),
+            "add the current user to the `kvm` group and log in again",
+        ),
+    }
+}
+
+/// cargo任务默认的编译目标是否已经通过rustup安装
+fn check_rust_target(arch: TargetArch) -> CheckResult {
+    let target = CargoConfig::default_rust_target(arch);
+    let output = Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            let installed = String::from_utf8_lossy(&output.stdout);
+            if installed.lines().any(|line| line.trim() == target) {
+                CheckResult::pass("rust-target", target)
+            } else {
+                CheckResult::problem(
+                    "rust-target",
+                    CheckStatus::Warn,
+                    format!("{} is not installed (needed by cargo tasks)", target),
+                    format!("rustup target add {}", target),
+                )
+            }
+        }
+        Ok(output) => CheckResult::problem(
+            "rust-target",
+            CheckStatus::Warn,
+            format!("rustup target list failed with {}", output.status),
+            "check the rustup installation with `rustup show`",
+        ),
+        Err(_) => CheckResult::problem(
+            "rust-target",
+            CheckStatus::Warn,
+            "rustup not found (needed by cargo tasks)",
+            "install rustup from https://rustup.rs",
+        ),
+    }
+}
+
+/// 缓存根目录所在磁盘的剩余空间。缓存根目录还不存在时检查它的上级目录
+fn check_free_space(cache_root: &Path) -> CheckResult {
+    let Some(dir) = cache_root.ancestors().find(|p| p.is_dir()) else {
+        return CheckResult::problem(
+            "disk-space",
+            CheckStatus::Warn,
+            format!("cannot find {}", cache_root.display()),
+            "check `cache-root-dir` in the manifest",
+        );
+    };
+    let available = Command::new("df")
+        .arg("-Pk")
+        .arg(dir)
+        .stdin(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| parse_df_available(&String::from_utf8_lossy(&output.stdout)));
+    let Some(available) = available else {
+        return CheckResult::problem(
+            "disk-space",
+            CheckStatus::Warn,
+            format!("failed to get free space of {}", dir.display()),
+            "make sure `df` is installed",
+        );
+    };
+    let detail = format!(
+        "{} free in {}",
+        format_size(available),
+        cache_root.display()
+    );
+    match free_space_status(available) {
+        CheckStatus::Pass => CheckResult::pass("disk-space", detail),
+        status => CheckResult::problem(
+            "disk-space",
+            status,
+            detail,
+            format!(
+                "free up space or move `cache-root-dir` to a larger disk (at least {} recommended)",
+                format_size(LOW_FREE_SPACE)
+            ),
+        ),
+    }
+}
+
+fn free_space_status(available: u64) -> CheckStatus {
+    if available < MIN_FREE_SPACE {
+        CheckStatus::Fail
+    } else if available < LOW_FREE_SPACE {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    }
+}
+
+/// 从`df -Pk`的输出中解析出可用空间（字节）
+fn parse_df_available(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let kb: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+pub(super) fn format_table(results: &[CheckResult]) -> String {
+    let header = ["CHECK", "STATUS", "DETAIL"];
+    let rows: Vec<[&str; 3]> = results
+        .iter()
+        .map(|r| [r.name.as_str(), r.status.as_str(), r.detail.as_str()])
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+
+    let hints: Vec<_> = results
+        .iter()
+        .filter_map(|r| r.hint.as_ref().map(|hint| (r, hint)))
+        .collect();
+    if !hints.is_empty() {
+        out.push_str("\nHints:\n");
+        for (result, hint) in hints {
+            out.push_str(&format!("  {}: {}\n", result.name, hint));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_df_available() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                      /dev/nvme0n1p2   479596200 312345678 142837204      69% /\n";
+        assert_eq!(parse_df_available(output), Some(142837204 * 1024));
+        assert_eq!(parse_df_available("Filesystem\n"), None);
+        assert_eq!(parse_df_available(""), None);
+    }
+
+    #[test]
+    fn test_free_space_status() {
+        assert_eq!(free_space_status(MIN_FREE_SPACE - 1), CheckStatus::Fail);
+        assert_eq!(free_space_status(MIN_FREE_SPACE), CheckStatus::Warn);
+        assert_eq!(free_space_status(LOW_FREE_SPACE), CheckStatus::Pass);
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        assert!(find_in_path("sh").is_some());
+        assert!(find_in_path("dadk-doctor-no-such-tool").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("tool");
+        std::fs::write(&file, "").unwrap();
+        assert!(find_in_path(file.to_str().unwrap()).is_none());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!(find_in_path(file.to_str().unwrap()), Some(file));
+        }
+    }
+
+    #[test]
+    fn test_format_table() {
+        let results = vec![
+            CheckResult::pass("git", "/usr/bin/git"),
+            CheckResult::problem("gdb", CheckStatus::Warn, "not found in PATH", "install gdb"),
+        ];
+        assert_eq!(
+            format_table(&results),
+            "CHECK  STATUS  DETAIL\n\
+             git    PASS    /usr/bin/git\n\
+             gdb    WARN    not found in PATH\n\
+             \n\
+             Hints:\n  gdb: install gdb\n"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! # `dadk completions` / `dadk man`
+//!
+//! 根据命令行参数的定义生成shell补全脚本和man page，覆盖所有嵌套的子命令。
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::{
+    console::{
+        generate::{CompletionsCommand, ManCommand},
+        CommandLineArgs,
+    },
+    context::DADKExecContext,
+};
+
+const BIN_NAME: &str = "dadk";
+
+pub(super) fn run_completions(_ctx: &DADKExecContext, args: &CompletionsCommand) -> Result<()> {
+    completions(args.shell, &mut std::io::stdout());
+    Ok(())
+}
+
+pub(super) fn run_man(_ctx: &DADKExecContext, args: &ManCommand) -> Result<()> {
+    let cmd = CommandLineArgs::command().name(BIN_NAME);
+    match &args.output {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+            clap_mangen::generate_to(cmd, dir)
+                .map_err(|e| anyhow!("Failed to write man pages to {}: {}", dir.display(), e))
+        }
+        None => clap_mangen::Man::new(cmd)
+            .render(&mut std::io::stdout())
+            .map_err(|e| anyhow!("Failed to render man page: {}", e)),
+    }
+}
+
+fn completions(shell: Shell, buf: &mut dyn Write) {
+    clap_complete::generate(shell, &mut CommandLineArgs::command(), BIN_NAME, buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions_cover_nested_subcommands() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut buf = Vec::new();
+            completions(shell, &mut buf);
+            let script = String::from_utf8(buf).unwrap();
+            for sub in [
+                "rootfs",
+                "user",
+                "profile",
+                "self-update",
+                "show-mountpoint",
+            ] {
+                assert!(script.contains(sub), "{:?}: missing {}", shell, sub);
+            }
+        }
+    }
+
+    #[test]
+    fn test_man_pages_for_subcommands() {
+        let dir = tempfile::tempdir().unwrap();
+        clap_mangen::generate_to(CommandLineArgs::command().name(BIN_NAME), dir.path()).unwrap();
+        for page in [
+            "dadk.1",
+            "dadk-rootfs-create.1",
+            "dadk-user-build.1",
+            "dadk-profile.1",
+        ] {
+            assert!(dir.path().join(page).is_file(), "missing {}", page);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! # manifest中的钩子
+//!
+//! `[hooks]`中配置的脚本会在对应的操作之前/之后，在DADK的工作目录中通过`sh -c`执行，
+//! 并导出以下环境变量：
+//!
+//! - `DADK_HOOK`：钩子的名称，例如`post-build`
+//! - `DADK_ARCH`：目标架构
+//! - `DADK_PROFILE`：当前使用的profile（未使用profile时不导出）
+//! - `DADK_WORKDIR`、`DADK_CACHE_ROOT`、`DADK_SYSROOT_DIR`、`DADK_DISK_IMAGE`：对应的绝对路径
+//!
+//! `pre-*`钩子执行失败时，不会执行对应的操作；`post-*`钩子只在操作成功后执行。
+
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+use dadk_config::manifest::HookPoint;
+
+use crate::{context::DADKExecContext, utils::abs_path};
+
+/// 依次执行`pre`钩子、`f`以及`post`钩子
+pub(crate) fn with_hooks<T>(
+    ctx: &DADKExecContext,
+    pre: HookPoint,
+    post: HookPoint,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    run(ctx, pre)?;
+    let r = f()?;
+    run(ctx, post)?;
+    Ok(r)
+}
+
+/// 执行manifest中配置的钩子，没有配置时什么也不做
+pub(crate) fn run(ctx: &DADKExecContext, point: HookPoint) -> Result<()> {
+    let Some(script) = ctx.manifest().hooks.get(point) else {
+        return Ok(());
+    };
+    execute(point, script, &ctx.workdir(), &envs(ctx))
+}
+
+fn envs(ctx: &DADKExecContext) -> Vec<(&'static str, String)> {
+    let metadata = &ctx.manifest().metadata;
+    let arch: String = ctx.target_arch().into();
+    let mut envs = vec![
+        ("DADK_ARCH", arch),
+        ("DADK_WORKDIR", ctx.workdir().display().to_string()),
+        (
+            "DADK_CACHE_ROOT",
+            abs_path(&metadata.cache_root_dir).display().to_string(),
+        ),
+        (
+            "DADK_SYSROOT_DIR",
+            abs_path(&metadata.sysroot_dir).display().to_string(),
+        ),
+        (
+            "DADK_DISK_IMAGE",
+            ctx.disk_image_path().display().to_string(),
+        ),
+    ];
+    if let Some(profile) = &ctx.manifest().profile {
+        envs.push(("DADK_PROFILE", profile.clone()));
+    }
+    envs
+}
+
+fn execute(
+    point: HookPoint,
+    script: &str,
+    workdir: &Path,
+    envs: &[(&'static str, String)],
+) -> Result<()> {
+    log::info!("Running {} hook: {}", point.name(), script);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .current_dir(workdir)
+        .env("DADK_HOOK", point.name())
+        .envs(envs.iter().map(|(k, v)| (*k, v)))
+        .status()
+        .map_err(|e| anyhow!("Failed to run {} hook: {}", point.name(), e))?;
+    if !status.success() {
+        return Err(anyhow!("{} hook failed: {}", point.name(), status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let dir = std::env::temp_dir().join(format!("dadk-hooks-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let envs = vec![("DADK_ARCH", "riscv64".to_string())];
+
+        execute(
+            HookPoint::PostBuild,
+            "echo \"$DADK_HOOK $DADK_ARCH\" > hook.out",
+            &dir,
+            &envs,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("hook.out")).unwrap(),
+            "post-build riscv64\n"
+        );
+
+        let e = execute(HookPoint::PreBuild, "exit 3", &dir, &envs).unwrap_err();
+        assert!(e.to_string().starts_with("pre-build hook failed"), "{}", e);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,451 @@
+//! # `dadk manifest init`
+//!
+//! 为新项目生成相互关联的配置文件，新的DragonOS应用开发者不需要再从主仓库手动复制：
+//!
+//! - `dadk-manifest.toml`（`-f/--manifest`指定的路径），其中的路径指向下面生成的文件
+//! - `rootfs.toml`、`boot.toml`（默认在`config/`目录中），启动协议、QEMU参数按照目标架构选择
+//! - 用户程序配置目录，以及一个示例用户程序`hello`（源码在`user/apps/hello`）
+//!
+//! 默认以交互式向导的方式询问目标架构等选项；指定`-y/--yes`时不询问，使用命令行参数以及默认值。
+//! 写入文件之前，会使用dadk-config解析生成的每个文件，确保dadk能够读取。
+//! 已经存在的文件不会被覆盖，除非指定`--force`。
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    boot::BootConfigFile, common::target_arch::TargetArch, manifest::DadkManifestFile,
+    rootfs::RootFSConfigFile, user::UserConfigFile,
+};
+use log::info;
+
+use crate::{
+    actions::prompt::Prompter,
+    console::manifest::{ManifestCommand, ManifestInitCommand},
+    context::DADKExecContext,
+};
+
+const DEFAULT_CONFIG_DIR: &str = "config";
+const DEFAULT_USER_CONFIG_DIR: &str = "user/dadk/config";
+const DEFAULT_ROOTFS_SIZE: &str = "1G";
+const SYSROOT_DIR: &str = "bin/sysroot";
+const CACHE_ROOT_DIR: &str = "bin/dadk_cache";
+/// 示例用户程序的源码目录
+const EXAMPLE_SOURCE_DIR: &str = "user/apps/hello";
+/// 示例用户程序的源码
+const EXAMPLE_MAIN_C: &str = r#"#include <stdio.h>
+
+int main(void)
+{
+    printf("Hello, DragonOS!\n");
+    return 0;
+}
+"#;
+
+pub(super) fn run(ctx: &DADKExecContext, cmd: &ManifestCommand) -> Result<()> {
+    match cmd {
+        ManifestCommand::Init(args) => init(ctx, args),
+    }
+}
+
+/// 生成项目时使用的选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InitOptions {
+    arch: TargetArch,
+    config_dir: PathBuf,
+    user_config_dir: PathBuf,
+    rootfs_size: String,
+    example: bool,
+}
+
+fn init(ctx: &DADKExecContext, args: &ManifestInitCommand) -> Result<()> {
+    let options = if args.yes {
+        options_from_args(args)?
+    } else {
+        let stdin = std::io::stdin();
+        let mut prompter = Prompter::new(stdin.lock(), std::io::stdout());
+        wizard(&mut prompter, args)?
+    };
+
+    let files = generate(Path::new(&ctx.command.manifest_path), &options)?;
+    if !args.force {
+        let existing: Vec<String> = files
+            .iter()
+            .filter(|(path, _)| path.exists())
+            .map(|(path, _)| path.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            return Err(anyhow!(
+                "{} already exist(s), use --force to overwrite",
+                existing.join(", ")
+            ));
+        }
+    }
+    for (path, content) in &files {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, content)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        info!("Created {}", path.display());
+    }
+    // dadk要求sysroot目录和缓存根目录已经存在
+    for dir in [SYSROOT_DIR, CACHE_ROOT_DIR] {
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("Failed to create {}: {}", dir, e))?;
+    }
+    info!("Run `dadk doctor` to check the host, then `dadk user build` to build the user programs");
+    Ok(())
+}
+
+/// 不询问时的选项：命令行参数以及默认值
+fn options_from_args(args: &ManifestInitCommand) -> Result<InitOptions> {
+    let arch = match &args.arch {
+        Some(arch) => parse_arch(arch)?,
+        None => TargetArch::default(),
+    };
+    Ok(InitOptions {
+        arch,
+        config_dir: args
+            .config_dir
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CONFIG_DIR.into()),
+        user_config_dir: args
+            .user_config_dir
+            .clone()
+            .unwrap_or_else(|| DEFAULT_USER_CONFIG_DIR.into()),
+        rootfs_size: args
+            .rootfs_size
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ROOTFS_SIZE.to_string()),
+        example: !args.no_example,
+    })
+}
+
+/// 交互式向导，命令行参数作为各个问题的默认值
+fn wizard<R: BufRead, W: Write>(
+    p: &mut Prompter<R, W>,
+    args: &ManifestInitCommand,
+) -> Result<InitOptions> {
+    let defaults = options_from_args(args)?;
+    let arch = p.ask_choice("Target arch", &TargetArch::EXPECTED, defaults.arch.into())?;
+    let config_dir = p.ask_required(
+        "Directory of rootfs.toml and boot.toml",
+        Some(&defaults.config_dir.to_string_lossy()),
+    )?;
+    let user_config_dir = p.ask_required(
+        "User program config directory",
+        Some(&defaults.user_config_dir.to_string_lossy()),
+    )?;
+    let rootfs_size = p.ask_required("Disk image size", Some(&defaults.rootfs_size))?;
+    let example = p.ask_bool("Create an example user program", defaults.example)?;
+    Ok(InitOptions {
+        arch: parse_arch(&arch)?,
+        config_dir: config_dir.into(),
+        user_config_dir: user_config_dir.into(),
+        rootfs_size,
+        example,
+    })
+}
+
+fn parse_arch(arch: &str) -> Result<TargetArch> {
+    TargetArch::try_from(arch).map_err(|e| anyhow!("{}", e))
+}
+
+/// 生成所有文件：(路径, 内容)。每个配置文件都会被解析一次
+fn generate(manifest_path: &Path, options: &InitOptions) -> Result<Vec<(PathBuf, String)>> {
+    let rootfs_path = options.config_dir.join("rootfs.toml");
+    let boot_path = options.config_dir.join("boot.toml");
+
+    let manifest = manifest_toml(options, &rootfs_path, &boot_path);
+    let manifest_file = DadkManifestFile::load_from_str(&manifest)
+        .map_err(|e| anyhow!("Generated manifest can not be parsed: {}", e))?;
+    if manifest_file.metadata.arch != options.arch {
+        return Err(anyhow!("Generated manifest does not match the input"));
+    }
+
+    let rootfs = rootfs_toml(options);
+    RootFSConfigFile::load_from_str(&rootfs)
+        .map_err(|e| anyhow!("Generated rootfs config can not be parsed: {}", e))?;
+
+    let boot = boot_toml(options.arch);
+    let problems = BootConfigFile::load_from_str(&boot)
+        .map_err(|e| anyhow!("Generated boot config can not be parsed: {}", e))?
+        .check(options.arch);
+    if !problems.is_empty() {
+        return Err(anyhow!(
+            "Generated boot config is invalid: {}",
+            problems.join("; ")
+        ));
+    }
+
+    let mut files = vec![
+        (manifest_path.to_path_buf(), manifest),
+        (rootfs_path, rootfs),
+        (boot_path, boot),
+    ];
+    if options.example {
+        let example = example_config(options.arch);
+        UserConfigFile::load_from_str(&example)
+            .map_err(|e| anyhow!("Generated example config can not be parsed: {}", e))?;
+        files.push((options.user_config_dir.join("hello_0_1_0.toml"), example));
+        files.push((
+            Path::new(EXAMPLE_SOURCE_DIR).join("main.c"),
+            EXAMPLE_MAIN_C.to_string(),
+        ));
+    }
+    Ok(files)
+}
+
+fn manifest_toml(options: &InitOptions, rootfs_path: &Path, boot_path: &Path) -> String {
+    let arch: &str = options.arch.into();
+    format!(
+        r#"# DADK manifest, generated by `dadk manifest init`.
+# See dadk-config/templates/dadk-manifest.toml in the DADK repository for all options.
+
+[metadata]
+# Target architecture. Options: {arches}
+arch = "{arch}"
+
+# RootFS config path
+rootfs-config = "{rootfs}"
+
+# Boot config path
+boot-config = "{boot}"
+
+# System root directory (DADK will copy the files in this directory to the root directory of the disk image)
+sysroot-dir = "{sysroot}"
+
+# DADK root cache directory path
+cache-root-dir = "{cache_root}"
+
+# User program config directory
+user-config-dir = "{user_config_dir}"
+
+# Number of retries when fetching the source of a user program fails
+retries = 0
+"#,
+        arches = TargetArch::EXPECTED.join(", "),
+        arch = arch,
+        rootfs = toml_path(rootfs_path),
+        boot = toml_path(boot_path),
+        user_config_dir = toml_path(&options.user_config_dir),
+        sysroot = SYSROOT_DIR,
+        cache_root = CACHE_ROOT_DIR,
+    )
+}
+
+fn rootfs_toml(options: &InitOptions) -> String {
+    format!(
+        r#"[metadata]
+# Filesystem type (options: `fat32`)
+fs_type = "fat32"
+# Size of the rootfs disk image (eg, `1G`, `1024M`)
+size = "{size}"
+
+[partition]
+# Partition type (options: "none", "mbr", "gpt")
+# "none" is incompatible with GRUB boot.
+type = "{partition}"
+
+[install]
+# (Optional) Directories that user programs are allowed to install files into.
+# An empty list disables the check.
+allowed_paths = []
+"#,
+        size = options.rootfs_size,
+        // GRUB需要分区表
+        partition = match options.arch {
+            TargetArch::X86_64 => "mbr",
+            _ => "none",
+        },
+    )
+}
+
+fn boot_toml(arch: TargetArch) -> String {
+    // 启动协议相关的配置
+    let (protocol, loader, machine, accelerate) = match arch {
+        TargetArch::X86_64 => (
+            "grub-legacy",
+            r#"[grub]
+# Time in seconds before the default entry is booted
+timeout = 10
+
+[grub.i386-legacy]
+grub-file = "/opt/dragonos-grub/arch/i386/legacy/grub/bin/grub-file"
+grub-install = "/opt/dragonos-grub/arch/i386/legacy/grub/sbin/grub-install"
+"#,
+            "-machine q35",
+            "kvm",
+        ),
+        TargetArch::RiscV64 => (
+            "dragon-stub",
+            r#"[dragonstub]
+# The path to the source code of the DragonStub project
+src-path = "kernel/submodules/DragonStub"
+
+[uboot]
+# The final download URL is `{download-url}/u-boot-{version}-{arch}.tar.xz`
+download-url = "https://mirrors.dragonos.org.cn/pub/third_party/u-boot"
+version = "v2023.10"
+path-prefix = "bin/uboot"
+"#,
+            "-machine virt",
+            "tcg",
+        ),
+        TargetArch::AArch64 => ("direct", "", "-machine virt -cpu cortex-a72", "tcg"),
+    };
+    format!(
+        r#"[metadata]
+# Options: "grub-legacy", "grub-efi", "direct", "dragon-stub"
+boot-protocol = "{protocol}"
+# Options: "graphic", "graphic-vnc", "no-graphic"
+boot-mode = "graphic"
+hypervisor = "qemu"
+# Kernel command line arguments
+kcmd-args = ["console=ttyS0", "root=/dev/vda"]
+# Arguments passed to the init process
+init-args = []
+
+{loader}
+[qemu]
+# Arguments to pass to qemu
+args = "{machine} -m 512M -smp 2"
+# Parameters to apply when no-graphic is enabled
+no-graphic-args = "-nographic"
+# Options: "kvm", "tcg", "hvf", "none"
+accelerate = "{accelerate}"
+"#,
+    )
+}
+
+/// 示例用户程序：使用musl交叉编译器静态链接`main.c`，安装到`/bin/hello`
+fn example_config(arch: TargetArch) -> String {
+    let arch: &str = arch.into();
+    format!(
+        r#"# Example user program, generated by `dadk manifest init`.
+# See dadk-config/templates/config/userapp_config.toml in the DADK repository for all options.
+name = "hello"
+version = "0.1.0"
+description = "Prints a greeting"
+target-arch = ["{arch}"]
+
+[task-source]
+type = "build-from-source"
+source = "local"
+source-path = "{source}"
+
+[build]
+build-command = '${{ARCH}}-linux-musl-gcc -static -o "$DADK_CURRENT_BUILD_DIR/hello" main.c'
+
+[install]
+in-dragonos-path = "/bin"
+
+[clean]
+clean-command = ""
+"#,
+        arch = arch,
+        source = EXAMPLE_SOURCE_DIR,
+    )
+}
+
+/// 配置文件中的路径（统一使用`/`分隔）
+fn toml_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_options(arch: TargetArch) -> InitOptions {
+        InitOptions {
+            arch,
+            config_dir: DEFAULT_CONFIG_DIR.into(),
+            user_config_dir: DEFAULT_USER_CONFIG_DIR.into(),
+            rootfs_size: DEFAULT_ROOTFS_SIZE.to_string(),
+            example: true,
+        }
+    }
+
+    /// 每个架构生成的配置文件都能被解析，并且相互关联
+    #[test]
+    #[allow(deprecated)]
+    fn test_generate_all_arches() -> Result<()> {
+        for arch in TargetArch::EXPECTED {
+            let arch = TargetArch::try_from(arch).unwrap();
+            let files = generate(Path::new("dadk-manifest.toml"), &default_options(arch))?;
+            let paths: Vec<&Path> = files.iter().map(|(p, _)| p.as_path()).collect();
+            assert_eq!(
+                paths,
+                [
+                    Path::new("dadk-manifest.toml"),
+                    Path::new("config/rootfs.toml"),
+                    Path::new("config/boot.toml"),
+                    Path::new("user/dadk/config/hello_0_1_0.toml"),
+                    Path::new("user/apps/hello/main.c"),
+                ]
+            );
+            let manifest = DadkManifestFile::load_from_str(&files[0].1)?;
+            assert_eq!(manifest.metadata.arch, arch);
+            assert_eq!(manifest.metadata.rootfs_config, paths[1]);
+            assert_eq!(manifest.metadata.boot_config, paths[2]);
+            assert_eq!(
+                manifest.metadata.user_config_dirs,
+                [PathBuf::from("user/dadk/config")]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_rejects_invalid_size() {
+        let mut options = default_options(TargetArch::X86_64);
+        options.rootfs_size = "big".to_string();
+        assert!(generate(Path::new("dadk-manifest.toml"), &options).is_err());
+    }
+
+    #[test]
+    fn test_wizard() -> Result<()> {
+        let input = "riscv64\n\nuser/config\n512M\nn\n";
+        let mut prompter = Prompter::new(input.as_bytes(), Vec::new());
+        let options = wizard(&mut prompter, &ManifestInitCommand::default())?;
+        assert_eq!(
+            options,
+            InitOptions {
+                arch: TargetArch::RiscV64,
+                config_dir: "config".into(),
+                user_config_dir: "user/config".into(),
+                rootfs_size: "512M".to_string(),
+                example: false,
+            }
+        );
+        let files = generate(Path::new("dadk-manifest.toml"), &options)?;
+        assert_eq!(files.len(), 3);
+        assert!(files[2].1.contains("boot-protocol = \"dragon-stub\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_from_args() -> Result<()> {
+        let args = ManifestInitCommand {
+            arch: Some("aarch64".to_string()),
+            no_example: true,
+            yes: true,
+            ..Default::default()
+        };
+        let options = options_from_args(&args)?;
+        assert_eq!(options.arch, TargetArch::AArch64);
+        assert!(!options.example);
+        assert_eq!(options.config_dir, PathBuf::from("config"));
+
+        let args = ManifestInitCommand {
+            arch: Some("mips".to_string()),
+            ..Default::default()
+        };
+        assert!(options_from_args(&args).is_err());
+        Ok(())
+    }
+}
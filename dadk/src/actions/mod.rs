@@ -1,22 +1,76 @@
-use crate::context::DADKExecContext;
+use dadk_user::{DadkUserError, ErrorCode};
 
+use crate::{console::ErrorFormat, context::DADKExecContext};
+
+pub mod boot;
+pub mod cache;
+pub mod ci;
+pub mod daemon;
+pub mod doctor;
+pub mod generate;
+mod hooks;
+pub mod manifest;
 pub mod profile;
+mod prompt;
+pub mod release;
 pub mod rootfs;
+pub mod self_update;
 pub mod user;
 
 pub fn run(ctx: DADKExecContext) {
-    match &ctx.command.action {
+    let (action, r) = match &ctx.command.action {
         crate::console::Action::Kernel => {
             unimplemented!("kernel command has not implemented for run yet.")
         }
         crate::console::Action::Rootfs(rootfs_command) => {
-            rootfs::run(&ctx, rootfs_command).expect("Run rootfs action error.")
-        }
-        crate::console::Action::User(user_command) => {
-            user::run(&ctx, user_command).expect("Run user action error.")
+            ("rootfs", rootfs::run(&ctx, rootfs_command))
         }
+        crate::console::Action::User(user_command) => ("user", user::run(&ctx, user_command)),
         crate::console::Action::Profile(profile_command) => {
-            profile::run(&ctx, profile_command).expect("Run profile action error.")
+            ("profile", profile::run(&ctx, profile_command))
+        }
+        crate::console::Action::Boot(boot_command) => ("boot", boot::run(&ctx, boot_command)),
+        crate::console::Action::Cache(cache_command) => ("cache", cache::run(&ctx, cache_command)),
+        crate::console::Action::Ci(ci_command) => ("ci", ci::run(&ctx, ci_command)),
+        crate::console::Action::Daemon(daemon_command) => {
+            ("daemon", daemon::run(&ctx, daemon_command))
+        }
+        crate::console::Action::Doctor(doctor_command) => {
+            ("doctor", doctor::run(&ctx, doctor_command))
+        }
+        crate::console::Action::Manifest(manifest_command) => {
+            ("manifest", manifest::run(&ctx, manifest_command))
+        }
+        crate::console::Action::Release(release_command) => {
+            ("release", release::run(&ctx, release_command))
         }
+        crate::console::Action::SelfUpdate(self_update_command) => {
+            ("self-update", self_update::run(&ctx, self_update_command))
+        }
+        crate::console::Action::Completions(completions_command) => (
+            "completions",
+            generate::run_completions(&ctx, completions_command),
+        ),
+        crate::console::Action::Man(man_command) => ("man", generate::run_man(&ctx, man_command)),
+    };
+    if let Err(e) = r {
+        match ctx.error_format() {
+            ErrorFormat::Human => panic!("Run {} action error.: {:?}", action, e),
+            ErrorFormat::Json => {
+                let e = e
+                    .downcast::<DadkUserError>()
+                    .unwrap_or_else(|e| DadkUserError::new(ErrorCode::Other, format!("{:#}", e)));
+                report_error(&ctx, &e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// 按照`--error-format`输出执行失败的错误
+pub(crate) fn report_error(ctx: &DADKExecContext, e: &DadkUserError) {
+    match ctx.error_format() {
+        ErrorFormat::Human => log::error!("{}", e),
+        ErrorFormat::Json => eprintln!("{}", e.to_json()),
     }
 }
@@ -10,16 +10,25 @@ use std::{
 };
 
 use crate::{
-    console::profile::{ProfileCommand, ProfileFileType, ProfileParseArgs, ProfileSampleArgs},
+    console::profile::{
+        ProfileBackend, ProfileCommand, ProfileFileType, ProfileFrameFilterArgs,
+        ProfileFrameNameArgs, ProfileParseArgs, ProfileSampleArgs, ProfileTargetArgs,
+    },
     context::DADKExecContext,
 };
 
 use anyhow::{anyhow, Result};
+use dadk_config::{boot::BootConfigFile, manifest::DadkManifestFile};
 use indicatif::{ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
 use rayon::ThreadPoolBuilder;
 use serde::{Deserialize, Serialize};
 
+use self::qmp::QmpSampler;
+
+mod qmp;
+mod top;
+
 lazy_static! {
     static ref GUEST_ADDRESS_HEX_PATTERN: regex::Regex =
         regex::Regex::new(r"0x[0-9a-fA-F]+ in").unwrap();
@@ -30,23 +39,154 @@ pub(super) fn run(ctx: &DADKExecContext, cmd: &ProfileCommand) -> Result<()> {
     match cmd {
         ProfileCommand::Sample(profile_sample_args) => sample(ctx, profile_sample_args),
         ProfileCommand::Parse(profile_parse_args) => parse_input_data(ctx, profile_parse_args),
+        ProfileCommand::Top(profile_top_args) => top::run(ctx, profile_top_args),
     }
 }
 
-fn sample(_ctx: &DADKExecContext, args: &ProfileSampleArgs) -> Result<()> {
-    let profiler = Profiler::new(args.clone());
+fn sample(ctx: &DADKExecContext, args: &ProfileSampleArgs) -> Result<()> {
+    let sampler = Sampler::new(ctx, &args.target)?;
+    let profiler = Profiler::new(args.clone(), sampler);
     profiler.run()?;
     profiler.save()
 }
 
+/// 从boot配置文件中读取qmp socket地址
+///
+/// profile命令不会在启动时读取manifest，因此这里按需加载
+fn qmp_socket_from_boot_config(ctx: &DADKExecContext) -> Result<String> {
+    let manifest_path = PathBuf::from(&ctx.command.manifest_path);
+    let manifest = DadkManifestFile::load(&manifest_path).map_err(|e| {
+        anyhow!(
+            "No `--qmp` specified, and failed to load manifest {}: {}",
+            manifest_path.display(),
+            e
+        )
+    })?;
+    let boot = BootConfigFile::load(&manifest.metadata.boot_config).map_err(|e| {
+        anyhow!(
+            "Failed to load boot config {}: {}",
+            manifest.metadata.boot_config.display(),
+            e
+        )
+    })?;
+    boot.qemu
+        .as_ref()
+        .and_then(|q| q.qmp_socket())
+        .map(|s| s.to_string())
+        .ok_or_else(|| {
+            anyhow!("No `--qmp` specified, and `qemu.qmp-socket` is not set in boot config")
+        })
+}
+
+/// 从之前保存的采样数据（json或folded格式）重新导出，无需重新采样
 fn parse_input_data(_ctx: &DADKExecContext, args: &ProfileParseArgs) -> Result<()> {
-    let sample_buf =
-        SampleBuffer::from_saved_file(&args.input).expect("Failed to load sample buffer");
-    sample_buf.export_data(args.format, &args.output, args.cpu_mask);
+    let sample_buf = SampleBuffer::from_saved_file(&args.input).map_err(|e| {
+        anyhow!(
+            "Failed to load sample buffer from {}: {}",
+            args.input.display(),
+            e
+        )
+    })?;
+    let filter = SampleFilter::new(
+        args.cpu_mask,
+        args.include.as_deref(),
+        args.exclude.as_deref(),
+    )?
+    .frame_filter(&args.frames)?;
+    sample_buf.export_data(args.format, &args.output, &filter)?;
     log::info!("Profile data saved to {}", args.output.display());
     Ok(())
 }
 
+/// 导出采样数据时使用的过滤条件
+#[derive(Debug, Default)]
+struct SampleFilter {
+    /// 只保留这些cpu的采样数据
+    cpu_mask: Option<u128>,
+    /// 只保留包含匹配该正则表达式的栈帧的调用栈
+    include: Option<regex::Regex>,
+    /// 丢弃包含匹配该正则表达式的栈帧的调用栈
+    exclude: Option<regex::Regex>,
+    /// 只保留调用栈最内层的这么多个栈帧
+    max_depth: Option<usize>,
+    /// 去掉没有符号的栈帧（`??`）
+    drop_unknown_frames: bool,
+    /// 从调用栈中去掉匹配这些正则表达式的栈帧
+    exclude_frames: Vec<regex::Regex>,
+}
+
+impl SampleFilter {
+    fn new(cpu_mask: Option<u128>, include: Option<&str>, exclude: Option<&str>) -> Result<Self> {
+        let compile = |pattern: Option<&str>| {
+            pattern
+                .map(|p| regex::Regex::new(p).map_err(|e| anyhow!("Invalid regex '{}': {}", p, e)))
+                .transpose()
+        };
+        Ok(Self {
+            cpu_mask,
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            ..Default::default()
+        })
+    }
+
+    /// 设置对调用栈中的栈帧的过滤
+    fn frame_filter(mut self, args: &ProfileFrameFilterArgs) -> Result<Self> {
+        self.max_depth = args.max_depth;
+        self.drop_unknown_frames = args.drop_unknown_frames;
+        self.exclude_frames = args
+            .exclude_frames
+            .iter()
+            .map(|p| regex::Regex::new(p).map_err(|e| anyhow!("Invalid regex '{}': {}", p, e)))
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    fn with_cpu_mask(cpu_mask: Option<u128>) -> Self {
+        Self {
+            cpu_mask,
+            ..Default::default()
+        }
+    }
+
+    fn accept(&self, cpu: usize, stack: &[String]) -> bool {
+        let cpumask = self.cpu_mask.unwrap_or(u128::MAX);
+        if cpu >= 128 || (cpumask & (1 << cpu)) == 0 {
+            return false;
+        }
+        if let Some(include) = &self.include {
+            if !stack.iter().any(|frame| include.is_match(frame)) {
+                return false;
+            }
+        }
+        if let Some(exclude) = &self.exclude {
+            if stack.iter().any(|frame| exclude.is_match(frame)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 过滤调用栈中的栈帧。`include`、`exclude`按照过滤之前的调用栈判断
+    fn frames(&self, stack: &[String]) -> Vec<String> {
+        let mut frames: Vec<String> = stack
+            .iter()
+            .filter(|frame| !(self.drop_unknown_frames && is_unknown_frame(frame)))
+            .filter(|frame| !self.exclude_frames.iter().any(|re| re.is_match(frame)))
+            .cloned()
+            .collect();
+        if let Some(max_depth) = self.max_depth {
+            frames.truncate(max_depth);
+        }
+        frames
+    }
+}
+
+/// 没有符号的栈帧（保留地址时为`??@0x...`）
+fn is_unknown_frame(frame: &str) -> bool {
+    frame == "??" || frame.starts_with("??@")
+}
+
 /// 一个时刻的采样数据
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Sample {
@@ -70,30 +210,20 @@ impl Sample {
         }
     }
 
-    fn push_new_line(&mut self, line: &str) {
+    fn push_new_line(&mut self, line: &str, names: &ProfileFrameNameArgs) {
         if line.starts_with("#") {
-            self.parse_frame_line(line);
+            self.parse_frame_line(line, names);
         } else {
             self.parse_thread_line(line);
         }
     }
 
-    fn parse_frame_line(&mut self, line: &str) {
-        let line = line.trim();
-        // todo: 支持调整删除的`<>`的层级，以便打印更详细的信息
-        let line = remove_angle_bracket_content(&line);
-        let line = remove_guest_address(&line);
-        let mut line = remove_rust_impl_pattern(&line);
-        line = line.replace("(...)", "");
-        line = line.replace("()", "");
-
-        let parts = line.split_whitespace().collect::<Vec<_>>();
-        if parts.len() >= 2 {
-            let fn_name = parts[1];
-            self.data
-                .get_mut(&self.current_cpu.unwrap())
-                .unwrap()
-                .push(fn_name.to_string());
+    fn parse_frame_line(&mut self, line: &str, names: &ProfileFrameNameArgs) {
+        let Some(cpu) = self.current_cpu else {
+            return;
+        };
+        if let Some(name) = frame_name(line, names) {
+            self.data.get_mut(&cpu).unwrap().push(name);
         }
     }
 
@@ -142,19 +272,25 @@ impl SampleBuffer {
         self.samples.push(sample);
     }
 
-    fn export_data(&self, t: ProfileFileType, outpath: &PathBuf, cpumask: Option<u128>) {
-        let mut writer = std::fs::File::create(outpath).unwrap();
+    fn export_data(
+        &self,
+        t: ProfileFileType,
+        outpath: &PathBuf,
+        filter: &SampleFilter,
+    ) -> Result<()> {
+        let mut writer = std::fs::File::create(outpath)
+            .map_err(|e| anyhow!("Failed to create {}: {}", outpath.display(), e))?;
         match t {
             ProfileFileType::Json => {
-                let filtered = self.filter_cpu(cpumask);
-                serde_json::to_writer(&mut writer, &filtered).unwrap();
+                let filtered = self.filter(filter);
+                serde_json::to_writer(&mut writer, &filtered)?;
             }
             ProfileFileType::Folded => {
-                let folded = self.fold(cpumask);
-                writer.write(folded.to_string().as_bytes()).unwrap();
+                let folded = self.fold(filter);
+                writer.write_all(folded.to_string().as_bytes())?;
             }
             ProfileFileType::Flamegraph => {
-                let folded = self.fold(cpumask);
+                let folded = self.fold(filter);
                 let lines: Vec<String> = folded
                     .data
                     .iter()
@@ -163,19 +299,22 @@ impl SampleBuffer {
 
                 let mut opt = inferno::flamegraph::Options::default();
                 inferno::flamegraph::from_lines(&mut opt, lines.iter().map(|s| s.as_str()), writer)
-                    .unwrap();
+                    .map_err(|e| anyhow!("Failed to generate flamegraph: {}", e))?;
             }
         }
+        Ok(())
     }
 
-    fn filter_cpu(&self, cpumask: Option<u128>) -> SampleBuffer {
-        let cpumask = cpumask.unwrap_or(u128::MAX);
+    fn filter(&self, filter: &SampleFilter) -> SampleBuffer {
         let mut result = SampleBuffer::new();
         self.samples.iter().for_each(|s| {
             let mut sample = Sample::new(s.id, s.timestamp);
             s.data.iter().for_each(|(cpu, stack)| {
-                if *cpu < 128 && (cpumask & (1 << cpu) != 0) {
-                    sample.data.insert(*cpu, stack.clone());
+                if filter.accept(*cpu, stack) {
+                    let frames = filter.frames(stack);
+                    if !frames.is_empty() {
+                        sample.data.insert(*cpu, frames);
+                    }
                 }
             });
             result.push(sample);
@@ -184,21 +323,11 @@ impl SampleBuffer {
         result
     }
 
-    fn fold(&self, cpumask: Option<u128>) -> FoldedSampleBuffer {
+    fn fold(&self, filter: &SampleFilter) -> FoldedSampleBuffer {
         let mut folded_buffer = FoldedSampleBuffer::default();
-        let cpumask = cpumask.unwrap_or(u128::MAX);
 
         for sample in &self.samples {
-            for (cpu, stack) in &sample.data {
-                if *cpu < 128 && (cpumask & (1 << *cpu)) != 0 {
-                    let folded_stack = stack.iter().rev().cloned().collect::<Vec<_>>().join(";");
-                    if let Some(cnt) = folded_buffer.data.get_mut(&folded_stack) {
-                        *cnt += 1;
-                    } else {
-                        folded_buffer.data.insert(folded_stack, 1);
-                    }
-                }
-            }
+            folded_buffer.add_sample(sample, filter);
         }
 
         folded_buffer
@@ -219,19 +348,117 @@ impl SampleBuffer {
     }
 }
 
+/// 采样器，负责采样一次所有vCPU的调用栈
+struct Sampler {
+    target: ProfileTargetArgs,
+    /// 使用qmp后端时的采样器
+    qmp: Option<QmpSampler>,
+}
+
+impl Sampler {
+    fn new(ctx: &DADKExecContext, target: &ProfileTargetArgs) -> Result<Self> {
+        let qmp = match target.backend {
+            ProfileBackend::Gdb => None,
+            ProfileBackend::Qmp => {
+                let addr = match &target.qmp {
+                    Some(addr) => addr.clone(),
+                    None => qmp_socket_from_boot_config(ctx)?,
+                };
+                log::info!("Using qmp backend, connecting to {}", addr);
+                Some(QmpSampler::new(&addr, &target.kernel, &target.frame_names)?)
+            }
+        };
+        Ok(Self {
+            target: target.clone(),
+            qmp,
+        })
+    }
+
+    fn kernel_path(&self) -> &PathBuf {
+        &self.target.kernel
+    }
+
+    fn remote(&self) -> &str {
+        &self.target.remote
+    }
+
+    /// 生成gdb的命令行参数
+    ///
+    /// 除内核外，额外的符号文件（例如用户程序）也会被加载，以便解析用户态的栈帧
+    fn gdb_args(&self) -> Vec<String> {
+        let mut cmds = vec![
+            "set pagination off".to_string(),
+            "set logging file /dev/null".to_string(),
+        ];
+        if let Some(sysroot) = &self.target.sysroot {
+            cmds.push(format!("set sysroot {}", sysroot.display()));
+        }
+        cmds.push(format!("file {}", &self.kernel_path().display()));
+        for sym in &self.target.symbol_files {
+            let path = match &self.target.sysroot {
+                Some(sysroot) if sym.path.is_relative() => sysroot.join(&sym.path),
+                _ => sym.path.clone(),
+            };
+            match sym.load_addr {
+                Some(addr) => {
+                    cmds.push(format!("add-symbol-file {} -o {:#x}", path.display(), addr))
+                }
+                None => cmds.push(format!("add-symbol-file {}", path.display())),
+            }
+        }
+        cmds.push(format!("target remote {}", &self.remote()));
+        cmds.push(
+            "thread apply all bt -frame-arguments presence -frame-info short-location".to_string(),
+        );
+
+        let mut args = vec!["-batch".to_string()];
+        for cmd in cmds {
+            args.push("-ex".to_string());
+            args.push(cmd);
+        }
+        args
+    }
+
+    fn sample_one(&self, id: usize) -> Result<Sample> {
+        if let Some(qmp) = &self.qmp {
+            let data = qmp
+                .sample()
+                .map_err(|e| anyhow!("[sample {}]: {}", id, e))?;
+            let mut sample = Sample::new(id, current_timestamp());
+            sample.data = data;
+            return Ok(sample);
+        }
+
+        let output = Command::new("gdb")
+            .args(self.gdb_args())
+            .output()
+            .map_err(|e| anyhow::anyhow!("[sample {}]: failed to execute gdb: {}", id, e))?;
+
+        let mut sample = Sample::new(id, current_timestamp());
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            sample.push_new_line(line, &self.target.frame_names);
+        }
+
+        Ok(sample)
+    }
+}
+
 struct Profiler {
     samples: Mutex<SampleBuffer>,
     self_ref: Weak<Profiler>,
 
     args: ProfileSampleArgs,
+    sampler: Sampler,
 }
 
 impl Profiler {
-    fn new(args: ProfileSampleArgs) -> Arc<Profiler> {
+    fn new(args: ProfileSampleArgs, sampler: Sampler) -> Arc<Profiler> {
         Arc::new_cyclic(|self_ref| Self {
             samples: Mutex::new(SampleBuffer::new()),
             args,
             self_ref: self_ref.clone(),
+            sampler,
         })
     }
 
@@ -285,7 +512,7 @@ impl Profiler {
                     let sd = sender.clone();
                     let pp = p.clone();
                     thread_pool.spawn_fifo(move || {
-                        if let Ok(sample) = pp.do_sample_one(id) {
+                        if let Ok(sample) = pp.sampler.sample_one(id) {
                             sd.send(Some(sample)).unwrap();
                         } else {
                             sd.send(None).unwrap();
@@ -308,49 +535,16 @@ impl Profiler {
         self.samples.lock().unwrap().export_data(
             self.args.format,
             &self.args.output,
-            self.args.cpu_mask,
-        );
-        Ok(())
+            &SampleFilter::with_cpu_mask(self.args.cpu_mask).frame_filter(&self.args.frames)?,
+        )
     }
+}
 
-    fn kernel_path(&self) -> &PathBuf {
-        &self.args.kernel
-    }
-
-    fn remote(&self) -> &str {
-        &self.args.remote
-    }
-
-    fn do_sample_one(&self, id: usize) -> Result<Sample> {
-        let output = Command::new("gdb")
-            .args([
-                "-batch",
-                "-ex",
-                "set pagination off",
-                "-ex",
-                "set logging file /dev/null",
-                "-ex",
-                &format!("file {}", &self.kernel_path().display()),
-                "-ex",
-                &format!("target remote {}", &self.remote()),
-                "-ex",
-                "thread apply all bt -frame-arguments presence -frame-info short-location",
-            ])
-            .output()
-            .map_err(|e| anyhow::anyhow!("[sample {}]: failed to execute gdb: {}", id, e))?;
-
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as usize;
-        let mut sample = Sample::new(id, timestamp);
-
-        for line in String::from_utf8_lossy(&output.stdout).lines() {
-            sample.push_new_line(line);
-        }
-
-        Ok(sample)
-    }
+fn current_timestamp() -> usize {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as usize
 }
 
 #[derive(Debug, Default)]
@@ -361,23 +555,41 @@ struct FoldedSampleBuffer {
     data: HashMap<String, usize>,
 }
 impl FoldedSampleBuffer {
+    /// 把一个采样增量地折叠进来
+    fn add_sample(&mut self, sample: &Sample, filter: &SampleFilter) {
+        for (cpu, stack) in &sample.data {
+            if !filter.accept(*cpu, stack) {
+                continue;
+            }
+            let frames = filter.frames(stack);
+            if frames.is_empty() {
+                continue;
+            }
+            let folded_stack = frames.into_iter().rev().collect::<Vec<_>>().join(";");
+            *self.data.entry(folded_stack).or_insert(0) += 1;
+        }
+    }
+
     pub fn try_from<T: AsRef<str>>(s: T) -> Result<Self> {
         let s = s.as_ref();
         let mut data = HashMap::new();
 
         for line in s.lines() {
-            let parts: Vec<&str> = line.split(' ').collect();
-            if parts.len() != 2 {
-                return Err(anyhow!("Invalid format"));
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
             }
+            let (key, value) = line
+                .rsplit_once(' ')
+                .ok_or_else(|| anyhow!("Invalid format"))?;
 
-            let key = parts[0].trim().to_string();
-            let value = parts[1]
+            let key = key.trim().to_string();
+            let value = value
                 .trim()
                 .parse::<usize>()
                 .map_err(|_| anyhow!("Invalid number"))?;
 
-            data.insert(key, value);
+            *data.entry(key).or_insert(0) += value;
         }
 
         Ok(FoldedSampleBuffer { data })
@@ -399,10 +611,11 @@ impl Into<SampleBuffer> for FoldedSampleBuffer {
     fn into(self) -> SampleBuffer {
         let mut samples = SampleBuffer::new();
         for (stack, count) in self.data {
+            // folded格式不包含cpu信息，统一记为cpu 0
             let mut sample = Sample::new(0, 0);
-            for frame in stack.split(';').rev() {
-                sample.push_new_line(frame);
-            }
+            sample
+                .data
+                .insert(0, stack.split(';').rev().map(|f| f.to_string()).collect());
             for _ in 0..count {
                 samples.push(sample.clone());
             }
@@ -411,37 +624,70 @@ impl Into<SampleBuffer> for FoldedSampleBuffer {
     }
 }
 
+/// 从gdb输出的一行栈帧（例如`#7  0xffff800001080320 in foo<T> (...)`）中取出函数名
+fn frame_name(line: &str, names: &ProfileFrameNameArgs) -> Option<String> {
+    let line = line
+        .trim()
+        .trim_start_matches('#')
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim();
+    let addr = GUEST_ADDRESS_HEX_PATTERN
+        .find(line)
+        .filter(|m| m.start() == 0)
+        .map(|m| m.as_str().trim_end_matches(" in").to_string());
+    let line = remove_guest_address(line);
+    let line = remove_angle_bracket_content(&line, names.keep_templates);
+    let line = remove_rust_impl_pattern(&line);
+    let name = line
+        .trim()
+        .trim_end_matches("(...)")
+        .trim_end_matches("()")
+        .trim();
+    if name.is_empty() {
+        return None;
+    }
+    match addr {
+        Some(addr) if names.keep_addresses => Some(format!("{}@{}", name, addr)),
+        _ => Some(name.to_string()),
+    }
+}
+
 /// Removes content within angle brackets from the input string.
 ///
 /// This function iterates through each character in the input string and
-/// removes any characters that are inside angle brackets (`<` and `>`).
-/// Nested brackets are handled correctly by maintaining a count of open
-/// brackets. Characters outside of any brackets are added to the result.
+/// removes any characters that are inside angle brackets (`<` and `>`)
+/// nested deeper than `keep_depth` levels. Nested brackets are handled
+/// correctly by maintaining a count of open brackets, and the `>` of `->`
+/// is not treated as a closing bracket.
 ///
 /// # Arguments
 ///
 /// * `input` - A string slice that holds the input string to be processed.
+/// * `keep_depth` - How many levels of brackets to keep, 0 removes them all.
 ///
 /// # Returns
 ///
 /// A new `String` with the content inside angle brackets removed.
-fn remove_angle_bracket_content(input: &str) -> String {
+fn remove_angle_bracket_content(input: &str, keep_depth: usize) -> String {
     let mut result = String::new();
-    let mut inside_brackets = 0;
+    let mut depth: usize = 0;
+    let mut prev = None;
 
     for c in input.chars() {
         if c == '<' {
-            inside_brackets += 1;
-            continue;
-        } else if c == '>' {
-            inside_brackets -= 1;
-            continue; // Skip the closing bracket
-        }
-
-        // TODO: 支持调整层级数，以便打印更精细的信息？
-        if inside_brackets == 0 {
+            depth += 1;
+            if depth <= keep_depth {
+                result.push(c);
+            }
+        } else if c == '>' && prev != Some('-') {
+            if depth <= keep_depth {
+                result.push(c);
+            }
+            depth = depth.saturating_sub(1);
+        } else if depth <= keep_depth {
             result.push(c);
         }
+        prev = Some(c);
     }
 
     result
@@ -463,34 +709,71 @@ mod tests {
     fn test_remove_angle_bracket_content_no_brackets() {
         let input = "Hello, World!";
         let expected = "Hello, World!";
-        assert_eq!(remove_angle_bracket_content(input), expected);
+        assert_eq!(remove_angle_bracket_content(input, 0), expected);
     }
 
     #[test]
     fn test_remove_angle_bracket_content_single_pair() {
         let input = "Hello <World>!";
         let expected = "Hello !";
-        assert_eq!(remove_angle_bracket_content(input), expected);
+        assert_eq!(remove_angle_bracket_content(input, 0), expected);
     }
 
     #[test]
     fn test_remove_angle_bracket_content_multiple_pairs() {
         let input = "Hello <World> <Again>!";
         let expected = "Hello  !";
-        assert_eq!(remove_angle_bracket_content(input), expected);
+        assert_eq!(remove_angle_bracket_content(input, 0), expected);
     }
 
     #[test]
     fn test_remove_angle_bracket_content_nested_brackets() {
         let input = "Hello <W<or>ld>!";
         let expected = "Hello !";
-        assert_eq!(remove_angle_bracket_content(input), expected);
+        assert_eq!(remove_angle_bracket_content(input, 0), expected);
     }
     #[test]
     fn test_remove_angle_bracket_content_unmatched_brackets() {
         let input = "Hello <World!";
         let expected = "Hello ";
-        assert_eq!(remove_angle_bracket_content(input), expected);
+        assert_eq!(remove_angle_bracket_content(input, 0), expected);
+    }
+
+    #[test]
+    fn test_remove_angle_bracket_content_keep_depth() {
+        let input = "Arc<ProcessControlBlock<Global>, Vec<u8>>::inner<fn(usize) -> Option<usize>>";
+        assert_eq!(remove_angle_bracket_content(input, 0), "Arc::inner");
+        assert_eq!(
+            remove_angle_bracket_content(input, 1),
+            "Arc<ProcessControlBlock, Vec>::inner<fn(usize) -> Option>"
+        );
+        assert_eq!(remove_angle_bracket_content(input, 2), input);
+    }
+
+    #[test]
+    fn test_frame_name_options() {
+        let line = "#7  0xffff800001080320 in alloc::sync::Arc<Pcb<u8>>::drop (...)";
+        let default = ProfileFrameNameArgs::default();
+        assert_eq!(
+            frame_name(line, &default).as_deref(),
+            Some("alloc::sync::Arc::drop")
+        );
+        let names = ProfileFrameNameArgs {
+            keep_templates: 1,
+            keep_addresses: true,
+        };
+        assert_eq!(
+            frame_name(line, &names).as_deref(),
+            Some("alloc::sync::Arc<Pcb>::drop@0xffff800001080320")
+        );
+        assert_eq!(
+            frame_name("#5  0xffff80001ff94800 in ?? ()", &names).as_deref(),
+            Some("??@0xffff80001ff94800")
+        );
+        assert_eq!(
+            frame_name("#4  dragonos_kernel::idle ()", &names).as_deref(),
+            Some("dragonos_kernel::idle")
+        );
     }
 
     #[test]
@@ -551,7 +834,7 @@ Thread 1 (Thread 1.1 (CPU#0 [running])):
         "#;
         let mut sample = Sample::new(0, 0);
         for line in stack.lines() {
-            sample.push_new_line(line);
+            sample.push_new_line(line, &ProfileFrameNameArgs::default());
         }
         assert_eq!(sample.vcpu_count(), 2);
         assert_eq!(sample.data.get(&0).unwrap().len(), 8);
@@ -567,4 +850,166 @@ Thread 1 (Thread 1.1 (CPU#0 [running])):
         );
         println!("{:?}", sample);
     }
+
+    #[test]
+    fn test_gdb_args_with_symbol_files() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Args {
+            #[clap(flatten)]
+            target: ProfileTargetArgs,
+        }
+        let args = Args::parse_from([
+            "dadk",
+            "--kernel",
+            "kernel.elf",
+            "--sysroot",
+            "/sysroot",
+            "--symbol-file",
+            "bin/shell",
+            "--symbol-file",
+            "/tmp/app@0x10000",
+        ]);
+        let sampler = Sampler {
+            target: args.target,
+            qmp: None,
+        };
+        let gdb_args = sampler.gdb_args();
+        assert_eq!(gdb_args[0], "-batch");
+        assert!(gdb_args.iter().skip(1).step_by(2).all(|s| s == "-ex"));
+        let cmds: Vec<&str> = gdb_args
+            .iter()
+            .skip(2)
+            .step_by(2)
+            .map(|s| s.as_str())
+            .collect();
+        assert_eq!(
+            cmds,
+            vec![
+                "set pagination off",
+                "set logging file /dev/null",
+                "set sysroot /sysroot",
+                "file kernel.elf",
+                "add-symbol-file /sysroot/bin/shell",
+                "add-symbol-file /tmp/app -o 0x10000",
+                "target remote localhost:1234",
+                "thread apply all bt -frame-arguments presence -frame-info short-location",
+            ]
+        );
+    }
+
+    fn stack(frames: &[&str]) -> Vec<String> {
+        frames.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn sample_buffer() -> SampleBuffer {
+        let mut sample = Sample::new(0, 0);
+        sample.data.insert(0, stack(&["idle", "main"]));
+        sample
+            .data
+            .insert(1, stack(&["do_syscall", "syscall_entry"]));
+        sample
+            .data
+            .insert(2, stack(&["page_fault", "do_syscall", "syscall_entry"]));
+        let mut buf = SampleBuffer::new();
+        buf.push(sample);
+        buf
+    }
+
+    #[test]
+    fn test_fold_with_filter() {
+        let buf = sample_buffer();
+
+        let folded = buf.fold(&SampleFilter::default());
+        assert_eq!(folded.data.len(), 3);
+
+        let folded = buf.fold(&SampleFilter::with_cpu_mask(Some(0b01)));
+        assert_eq!(folded.data.len(), 1);
+        assert_eq!(folded.data.get("main;idle"), Some(&1));
+
+        let filter = SampleFilter::new(None, Some("syscall"), Some("^page_fault$")).unwrap();
+        let folded = buf.fold(&filter);
+        assert_eq!(folded.data.len(), 1);
+        assert_eq!(folded.data.get("syscall_entry;do_syscall"), Some(&1));
+
+        assert!(SampleFilter::new(None, Some("("), None).is_err());
+    }
+
+    #[test]
+    fn test_fold_with_frame_filter() {
+        let mut sample = Sample::new(0, 0);
+        sample.data.insert(
+            0,
+            stack(&["??@0x10", "spin_lock", "do_syscall", "??", "entry"]),
+        );
+        sample.data.insert(1, stack(&["??"]));
+        let mut buf = SampleBuffer::new();
+        buf.push(sample);
+
+        let args = ProfileFrameFilterArgs {
+            max_depth: Some(2),
+            drop_unknown_frames: true,
+            exclude_frames: vec!["^spin_".to_string()],
+        };
+        let filter = SampleFilter::default().frame_filter(&args).unwrap();
+        let folded = buf.fold(&filter);
+        assert_eq!(folded.data.len(), 1);
+        assert_eq!(folded.data.get("entry;do_syscall"), Some(&1));
+        assert_eq!(buf.filter(&filter).samples[0].data.len(), 1);
+
+        // 按照过滤之前的调用栈判断include
+        let filter = SampleFilter::new(None, Some("spin_lock"), None)
+            .unwrap()
+            .frame_filter(&args)
+            .unwrap();
+        assert_eq!(buf.fold(&filter).data.len(), 1);
+
+        let args = ProfileFrameFilterArgs {
+            exclude_frames: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(SampleFilter::default().frame_filter(&args).is_err());
+    }
+
+    #[test]
+    fn test_folded_roundtrip() {
+        let folded =
+            FoldedSampleBuffer::try_from("main;idle 3\nsyscall_entry;do_syscall 2\n\n").unwrap();
+        let buf: SampleBuffer = folded.into();
+        assert_eq!(buf.samples.len(), 5);
+
+        let refolded = buf.fold(&SampleFilter::default());
+        assert_eq!(refolded.data.get("main;idle"), Some(&3));
+        assert_eq!(refolded.data.get("syscall_entry;do_syscall"), Some(&2));
+
+        assert!(FoldedSampleBuffer::try_from("main;idle").is_err());
+        assert!(FoldedSampleBuffer::try_from("main;idle x").is_err());
+    }
+
+    #[test]
+    fn test_export_from_saved_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("samples.json");
+        sample_buffer()
+            .export_data(ProfileFileType::Json, &input, &SampleFilter::default())
+            .unwrap();
+
+        let buf = SampleBuffer::from_saved_file(&input).unwrap();
+        assert_eq!(buf.samples.len(), 1);
+        assert_eq!(buf.samples[0].data.len(), 3);
+
+        let output = dir.path().join("samples.folded");
+        buf.export_data(
+            ProfileFileType::Folded,
+            &output,
+            &SampleFilter::with_cpu_mask(Some(0b110)),
+        )
+        .unwrap();
+        let folded = SampleBuffer::from_saved_file(&output)
+            .unwrap()
+            .fold(&SampleFilter::default());
+        assert_eq!(folded.data.len(), 2);
+        assert!(!folded.data.contains_key("main;idle"));
+    }
 }
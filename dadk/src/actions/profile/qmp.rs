@@ -0,0 +1,413 @@
+//! # 基于QEMU QMP的采样后端
+//!
+//! 通过QMP的`human-monitor-command`读取每个vCPU的寄存器，并沿栈帧指针回溯调用栈，
+//! 再使用内核ELF的符号表（`nm`）把地址解析为函数名。
+//!
+//! 与gdb后端相比，不需要每次采样都启动gdb并加载内核ELF，虚拟机被暂停的时间更短。
+//!
+//! 目前仅支持x86_64（要求内核保留栈帧指针）。
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::Path,
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use super::{remove_angle_bracket_content, remove_rust_impl_pattern};
+use crate::console::profile::ProfileFrameNameArgs;
+
+/// 回溯调用栈的最大深度
+const MAX_STACK_DEPTH: usize = 64;
+
+/// QMP客户端
+struct QmpClient {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl QmpClient {
+    /// 连接到QMP socket
+    ///
+    /// 地址格式：`unix:<path>`、`tcp:<host>:<port>`或`<host>:<port>`
+    fn connect(addr: &str) -> Result<Self> {
+        let map_err =
+            |e: std::io::Error| anyhow!("Failed to connect to qmp socket {}: {}", addr, e);
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) =
+            if let Some(path) = addr.strip_prefix("unix:") {
+                let stream = UnixStream::connect(path).map_err(map_err)?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            } else {
+                let stream = TcpStream::connect(addr.strip_prefix("tcp:").unwrap_or(addr))
+                    .map_err(map_err)?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            };
+        let mut client = Self {
+            reader: Box::new(BufReader::new(reader)),
+            writer,
+        };
+        client.handshake()?;
+        Ok(client)
+    }
+
+    /// 读取服务端的问候消息，并进入命令模式
+    fn handshake(&mut self) -> Result<()> {
+        let greeting = self.read_message()?;
+        if greeting.get("QMP").is_none() {
+            return Err(anyhow!("Unexpected qmp greeting: {}", greeting));
+        }
+        self.execute("qmp_capabilities", None)?;
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("qmp connection closed"));
+        }
+        serde_json::from_str(&line).map_err(|e| anyhow!("Invalid qmp message '{}': {}", line, e))
+    }
+
+    /// 执行一条QMP命令，返回`return`字段的内容
+    fn execute(&mut self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        let mut request = json!({ "execute": command });
+        if let Some(arguments) = arguments {
+            request["arguments"] = arguments;
+        }
+        writeln!(self.writer, "{}", request)?;
+        self.writer.flush()?;
+
+        loop {
+            let mut msg = self.read_message()?;
+            if let Some(ret) = msg.get_mut("return") {
+                return Ok(ret.take());
+            }
+            if let Some(err) = msg.get("error") {
+                return Err(anyhow!("qmp command `{}` failed: {}", command, err));
+            }
+            // 其他消息（异步事件）直接忽略
+        }
+    }
+
+    /// 执行一条HMP命令，返回其输出
+    fn hmp(&mut self, command_line: &str) -> Result<String> {
+        let ret = self.execute(
+            "human-monitor-command",
+            Some(json!({ "command-line": command_line })),
+        )?;
+        ret.as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| anyhow!("Unexpected hmp output: {}", ret))
+    }
+}
+
+/// 内核符号表
+struct KernelSymbols {
+    /// 按地址排序的（地址，函数名）
+    symbols: Vec<(u64, String)>,
+}
+
+impl KernelSymbols {
+    fn load(kernel: &Path, keep_templates: usize) -> Result<Self> {
+        let output = Command::new("nm")
+            .args(["-n", "-C", "--defined-only"])
+            .arg(kernel)
+            .output()
+            .map_err(|e| anyhow!("Failed to execute nm: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to read symbols from {}: {}",
+                kernel.display(),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        Ok(Self::parse(
+            &String::from_utf8_lossy(&output.stdout),
+            keep_templates,
+        ))
+    }
+
+    /// 解析`nm`的输出，只保留代码段的符号
+    fn parse(nm_output: &str, keep_templates: usize) -> Self {
+        let mut symbols: Vec<(u64, String)> = nm_output
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ' ');
+                let addr = u64::from_str_radix(parts.next()?, 16).ok()?;
+                let ty = parts.next()?;
+                let name = parts.next()?;
+                if !matches!(ty, "t" | "T" | "w" | "W") {
+                    return None;
+                }
+                Some((addr, normalize_symbol(name, keep_templates)))
+            })
+            .collect();
+        symbols.sort_by_key(|(addr, _)| *addr);
+        Self { symbols }
+    }
+
+    /// 查找地址所在的函数
+    fn lookup(&self, addr: u64) -> Option<&str> {
+        let idx = self.symbols.partition_point(|(a, _)| *a <= addr);
+        if idx == 0 {
+            return None;
+        }
+        Some(self.symbols[idx - 1].1.as_str())
+    }
+}
+
+/// 把符号名处理成与gdb后端相同的形式
+fn normalize_symbol(name: &str, keep_templates: usize) -> String {
+    // 去掉rust符号末尾的哈希值（例如`::h1234567890abcdef`）
+    let name = match name.rfind("::h") {
+        Some(idx)
+            if name.len() - idx == 19 && name[idx + 3..].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            &name[..idx]
+        }
+        _ => name,
+    };
+    let name = remove_angle_bracket_content(name, keep_templates);
+    remove_rust_impl_pattern(&name)
+}
+
+/// 解析`info registers -a`的输出，返回每个cpu的（cpu id，rip，rbp）
+fn parse_registers(output: &str) -> Result<Vec<(usize, u64, u64)>> {
+    let mut cpus: Vec<(usize, Option<u64>, Option<u64>)> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("CPU#") {
+            let cpu = rest
+                .split(|c: char| !c.is_ascii_digit())
+                .next()
+                .and_then(|s| s.parse::<usize>().ok())
+                .ok_or_else(|| anyhow!("Invalid cpu line: {}", line))?;
+            cpus.push((cpu, None, None));
+            continue;
+        }
+        if let Some((_, rip, rbp)) = cpus.last_mut() {
+            for token in line.split_whitespace() {
+                if let Some(v) = token.strip_prefix("RIP=") {
+                    *rip = u64::from_str_radix(v, 16).ok();
+                } else if let Some(v) = token.strip_prefix("RBP=") {
+                    *rbp = u64::from_str_radix(v, 16).ok();
+                }
+            }
+        }
+    }
+
+    if cpus.is_empty() {
+        return Err(anyhow!("No cpu found in `info registers -a` output"));
+    }
+    cpus.into_iter()
+        .map(|(cpu, rip, rbp)| match (rip, rbp) {
+            (Some(rip), Some(rbp)) => Ok((cpu, rip, rbp)),
+            _ => Err(anyhow!(
+                "RIP/RBP of CPU#{} not found, only x86_64 is supported",
+                cpu
+            )),
+        })
+        .collect()
+}
+
+/// 解析`x /2gx <addr>`的输出
+fn parse_memory(output: &str) -> Option<(u64, u64)> {
+    let (_, values) = output.lines().next()?.split_once(':')?;
+    let mut values = values
+        .split_whitespace()
+        .map(|v| u64::from_str_radix(v.trim_start_matches("0x"), 16));
+    Some((values.next()?.ok()?, values.next()?.ok()?))
+}
+
+/// 沿栈帧指针回溯调用栈，返回从栈顶开始的返回地址
+///
+/// `read`读取栈帧中保存的（上一个栈帧指针，返回地址）
+fn walk_stack(rip: u64, rbp: u64, mut read: impl FnMut(u64) -> Option<(u64, u64)>) -> Vec<u64> {
+    let mut pcs = vec![rip];
+    let mut fp = rbp;
+    while pcs.len() < MAX_STACK_DEPTH && fp != 0 && fp % 8 == 0 {
+        let Some((next_fp, ret)) = read(fp) else {
+            break;
+        };
+        if ret == 0 {
+            break;
+        }
+        pcs.push(ret);
+        // 栈向低地址增长，上一个栈帧的地址一定更高
+        if next_fp <= fp {
+            break;
+        }
+        fp = next_fp;
+    }
+    pcs
+}
+
+/// QMP采样器
+pub(super) struct QmpSampler {
+    client: Mutex<QmpClient>,
+    symbols: KernelSymbols,
+    /// 栈帧名称中保留地址
+    keep_addresses: bool,
+}
+
+impl QmpSampler {
+    pub(super) fn new(addr: &str, kernel: &Path, names: &ProfileFrameNameArgs) -> Result<Self> {
+        let symbols = KernelSymbols::load(kernel, names.keep_templates)?;
+        let client = QmpClient::connect(addr)?;
+        Ok(Self {
+            client: Mutex::new(client),
+            symbols,
+            keep_addresses: names.keep_addresses,
+        })
+    }
+
+    /// 采样一次所有vCPU的调用栈
+    ///
+    /// 返回值的key为cpu id，value为从栈顶开始的函数名
+    pub(super) fn sample(&self) -> Result<BTreeMap<usize, Vec<String>>> {
+        let mut client = self.client.lock().unwrap();
+        client.execute("stop", None)?;
+        let r = self.do_sample(&mut client);
+        // 无论采样是否成功，都要让虚拟机继续运行
+        client.execute("cont", None)?;
+        r
+    }
+
+    fn do_sample(&self, client: &mut QmpClient) -> Result<BTreeMap<usize, Vec<String>>> {
+        let regs = parse_registers(&client.hmp("info registers -a")?)?;
+        let mut data = BTreeMap::new();
+        for (cpu, rip, rbp) in regs {
+            client.hmp(&format!("cpu {}", cpu))?;
+            let pcs = walk_stack(rip, rbp, |fp| {
+                client
+                    .hmp(&format!("x /2gx {:#x}", fp))
+                    .ok()
+                    .and_then(|out| parse_memory(&out))
+            });
+            let frames = pcs
+                .into_iter()
+                .map(|pc| {
+                    let name = self.symbols.lookup(pc).unwrap_or("??");
+                    if self.keep_addresses {
+                        format!("{}@{:#x}", name, pc)
+                    } else {
+                        name.to_string()
+                    }
+                })
+                .collect();
+            data.insert(cpu, frames);
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_qmp_client_execute() {
+        let input = concat!(
+            r#"{"QMP": {"version": {}, "capabilities": []}}"#,
+            "\n",
+            r#"{"return": {}}"#,
+            "\n",
+            r#"{"event": "STOP", "timestamp": {}}"#,
+            "\n",
+            r#"{"return": "CPU#0\r\nRIP=ffff800000100000\r\n"}"#,
+            "\n",
+            r#"{"error": {"class": "GenericError", "desc": "oops"}}"#,
+            "\n",
+        );
+        let mut client = QmpClient {
+            reader: Box::new(Cursor::new(input.as_bytes().to_vec())),
+            writer: Box::new(Vec::new()),
+        };
+        client.handshake().unwrap();
+        assert_eq!(
+            client.hmp("info registers -a").unwrap(),
+            "CPU#0\r\nRIP=ffff800000100000\r\n"
+        );
+        assert!(client.execute("stop", None).is_err());
+        // 连接已关闭
+        assert!(client.execute("cont", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_registers() {
+        let output = "CPU#0\r\n\
+RAX=0000000000000000 RBX=0000000000000001 RCX=0000000000000002 RDX=0000000000000003\r\n\
+RSI=0000000000000004 RDI=0000000000000005 RBP=ffff80001ff94c28 RSP=ffff80001ff94c00\r\n\
+RIP=ffff8000001e196a RFL=00000246 [---Z-P-] CPL=0 II=0 A20=1 SMM=0 HLT=1\r\n\
+CPU#1 (halted)\r\n\
+RSI=0000000000000004 RDI=0000000000000005 RBP=0000000000000000 RSP=ffff80001ff94c00\r\n\
+RIP=ffff800000182638 RFL=00000246 [---Z-P-] CPL=0 II=0 A20=1 SMM=0 HLT=1\r\n";
+        assert_eq!(
+            parse_registers(output).unwrap(),
+            vec![
+                (0, 0xffff8000001e196a, 0xffff80001ff94c28),
+                (1, 0xffff800000182638, 0),
+            ]
+        );
+
+        // riscv64等架构没有RIP/RBP
+        assert!(parse_registers("CPU#0\r\n pc       0000000080200000\r\n").is_err());
+        assert!(parse_registers("").is_err());
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(
+            parse_memory("ffff80001ff94c28: 0xffff80001ff94c58 0xffff8000001e1234\r\n"),
+            Some((0xffff80001ff94c58, 0xffff8000001e1234))
+        );
+        assert_eq!(parse_memory("Cannot access memory\r\n"), None);
+    }
+
+    #[test]
+    fn test_walk_stack() {
+        let frames = BTreeMap::from([
+            (0x1000, (0x1010, 0xa)),
+            (0x1010, (0x1020, 0xb)),
+            (0x1020, (0, 0xc)),
+        ]);
+        let pcs = walk_stack(0x9, 0x1000, |fp| frames.get(&fp).copied());
+        assert_eq!(pcs, vec![0x9, 0xa, 0xb, 0xc]);
+
+        // 栈帧指针没有增长时停止回溯
+        let pcs = walk_stack(0x9, 0x1000, |_| Some((0x1000, 0xa)));
+        assert_eq!(pcs, vec![0x9, 0xa]);
+
+        assert_eq!(walk_stack(0x9, 0, |_| unreachable!()), vec![0x9]);
+    }
+
+    #[test]
+    fn test_kernel_symbols() {
+        let nm = "\
+ffff800000100000 T _start
+ffff800000100100 t dragonos_kernel::process::ProcessManager::current_pcb::h0123456789abcdef
+ffff800000100200 T <alloc::sync::Arc<T> as core::ops::drop::Drop>::drop
+ffff800000200000 D SOME_DATA
+ffff800000300000 W weak_func
+";
+        let symbols = KernelSymbols::parse(nm, 0);
+        assert_eq!(symbols.lookup(0xffff8000000fffff), None);
+        assert_eq!(symbols.lookup(0xffff800000100010), Some("_start"));
+        assert_eq!(
+            symbols.lookup(0xffff800000100150),
+            Some("dragonos_kernel::process::ProcessManager::current_pcb")
+        );
+        assert_eq!(symbols.lookup(0xffff800000100200), Some("::drop"));
+        // 数据段的符号会被忽略
+        assert_eq!(symbols.lookup(0xffff800000200010), Some("::drop"));
+        assert_eq!(symbols.lookup(0xffff800000300010), Some("weak_func"));
+    }
+}
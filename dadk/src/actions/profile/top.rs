@@ -0,0 +1,247 @@
+//! # `dadk profile top`
+//!
+//! 持续采样，并在终端中实时刷新占用采样最多的函数（类似`perf top`）。
+//!
+//! - Self：函数位于栈顶的次数
+//! - Total：函数出现在调用栈中的次数（同一个调用栈中多次出现只计一次）
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use crossbeam::channel::RecvTimeoutError;
+
+use super::{FoldedSampleBuffer, Sample, SampleFilter, Sampler};
+use crate::{console::profile::ProfileTopArgs, context::DADKExecContext};
+
+/// 函数名的最大显示宽度
+const MAX_NAME_WIDTH: usize = 100;
+
+pub(super) fn run(ctx: &DADKExecContext, args: &ProfileTopArgs) -> Result<()> {
+    let sampler = Arc::new(Sampler::new(ctx, &args.target)?);
+    let filter = SampleFilter::with_cpu_mask(args.cpu_mask);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let (sender, receiver) = crossbeam::channel::unbounded::<Result<Sample>>();
+    let sample_handle = {
+        let sampler = sampler.clone();
+        let stop = stop.clone();
+        let interval = args.interval;
+        std::thread::spawn(move || {
+            let mut id = 0;
+            while !stop.load(Ordering::SeqCst) {
+                let start = Instant::now();
+                if sender.send(sampler.sample_one(id)).is_err() {
+                    break;
+                }
+                id += 1;
+                std::thread::sleep(interval.saturating_sub(start.elapsed()));
+            }
+        })
+    };
+
+    let mut state = TopState::default();
+    let start = Instant::now();
+    let mut last_render: Option<Instant> = None;
+    loop {
+        if args.duration.is_some_and(|d| start.elapsed() >= d) {
+            break;
+        }
+        match receiver.recv_timeout(args.refresh.min(Duration::from_millis(100))) {
+            Ok(Ok(sample)) => state.add_sample(&sample, &filter),
+            Ok(Err(e)) => {
+                state.errors += 1;
+                state.last_error = Some(e.to_string());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if last_render.map_or(true, |t| t.elapsed() >= args.refresh) {
+            render(&state, args.lines, start.elapsed())?;
+            last_render = Some(Instant::now());
+        }
+    }
+
+    stop.store(true, Ordering::SeqCst);
+    drop(receiver);
+    render(&state, args.lines, start.elapsed())?;
+    sample_handle.join().unwrap();
+    Ok(())
+}
+
+/// 实时模式下累积的采样数据
+#[derive(Debug, Default)]
+struct TopState {
+    folded: FoldedSampleBuffer,
+    samples: usize,
+    errors: usize,
+    last_error: Option<String>,
+}
+
+impl TopState {
+    fn add_sample(&mut self, sample: &Sample, filter: &SampleFilter) {
+        self.folded.add_sample(sample, filter);
+        self.samples += 1;
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct TopEntry {
+    name: String,
+    self_count: usize,
+    total_count: usize,
+}
+
+/// 统计占用采样最多的`n`个函数，返回（调用栈总数，函数列表）
+fn top_functions(folded: &FoldedSampleBuffer, n: usize) -> (usize, Vec<TopEntry>) {
+    let mut stacks = 0;
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (stack, cnt) in &folded.data {
+        stacks += cnt;
+        let frames: Vec<&str> = stack.split(';').collect();
+        if let Some(leaf) = frames.last() {
+            counts.entry(leaf).or_default().0 += cnt;
+        }
+        for frame in frames.iter().collect::<BTreeSet<_>>() {
+            counts.entry(frame).or_default().1 += cnt;
+        }
+    }
+
+    let mut entries: Vec<TopEntry> = counts
+        .into_iter()
+        .map(|(name, (self_count, total_count))| TopEntry {
+            name: name.to_string(),
+            self_count,
+            total_count,
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.self_count
+            .cmp(&a.self_count)
+            .then(b.total_count.cmp(&a.total_count))
+            .then(a.name.cmp(&b.name))
+    });
+    entries.truncate(n);
+    (stacks, entries)
+}
+
+fn format_table(state: &TopState, lines: usize, elapsed: Duration) -> String {
+    let (stacks, entries) = top_functions(&state.folded, lines);
+    let percent = |cnt: usize| {
+        if stacks == 0 {
+            0.0
+        } else {
+            cnt as f64 * 100.0 / stacks as f64
+        }
+    };
+
+    let mut out = format!(
+        "Samples: {}  Stacks: {}  Errors: {}  Elapsed: {}s\n\n",
+        state.samples,
+        stacks,
+        state.errors,
+        elapsed.as_secs()
+    );
+    out.push_str(&format!("{:>7}  {:>7}  Function\n", "Self", "Total"));
+    for entry in entries {
+        let mut name = entry.name;
+        if name.chars().count() > MAX_NAME_WIDTH {
+            name = name.chars().take(MAX_NAME_WIDTH - 3).collect::<String>() + "...";
+        }
+        out.push_str(&format!(
+            "{:>6.2}%  {:>6.2}%  {}\n",
+            percent(entry.self_count),
+            percent(entry.total_count),
+            name
+        ));
+    }
+    if let Some(e) = &state.last_error {
+        out.push_str(&format!("\nLast error: {}\n", e));
+    }
+    out
+}
+
+fn render(state: &TopState, lines: usize, elapsed: Duration) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    // 清屏，并把光标移动到左上角
+    write!(
+        stdout,
+        "\x1b[2J\x1b[H{}",
+        format_table(state, lines, elapsed)
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(stacks: &[&[&str]]) -> Sample {
+        let mut sample = Sample::new(0, 0);
+        for (cpu, stack) in stacks.iter().enumerate() {
+            sample
+                .data
+                .insert(cpu, stack.iter().map(|f| f.to_string()).collect());
+        }
+        sample
+    }
+
+    fn state() -> TopState {
+        let mut state = TopState::default();
+        let filter = SampleFilter::default();
+        state.add_sample(
+            &sample(&[&["idle", "main"], &["memcpy", "read", "main"]]),
+            &filter,
+        );
+        state.add_sample(&sample(&[&["idle", "main"], &["idle", "main"]]), &filter);
+        state
+    }
+
+    #[test]
+    fn test_top_functions() {
+        let state = state();
+        assert_eq!(state.samples, 2);
+
+        let (stacks, entries) = top_functions(&state.folded, 10);
+        assert_eq!(stacks, 4);
+        let get = |name: &str| entries.iter().find(|e| e.name == name).unwrap();
+        assert_eq!(entries[0].name, "idle");
+        assert_eq!((get("idle").self_count, get("idle").total_count), (3, 3));
+        assert_eq!(
+            (get("memcpy").self_count, get("memcpy").total_count),
+            (1, 1)
+        );
+        assert_eq!((get("main").self_count, get("main").total_count), (0, 4));
+
+        let (_, entries) = top_functions(&state.folded, 2);
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn test_top_recursive_frames_counted_once() {
+        let mut state = TopState::default();
+        state.add_sample(&sample(&[&["f", "f", "main"]]), &SampleFilter::default());
+        let (_, entries) = top_functions(&state.folded, 10);
+        let f = entries.iter().find(|e| e.name == "f").unwrap();
+        assert_eq!((f.self_count, f.total_count), (1, 1));
+    }
+
+    #[test]
+    fn test_format_table() {
+        let table = format_table(&state(), 2, Duration::from_secs(3));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "Samples: 2  Stacks: 4  Errors: 0  Elapsed: 3s");
+        assert_eq!(lines[2], "   Self    Total  Function");
+        assert_eq!(lines[3], " 75.00%   75.00%  idle");
+        assert_eq!(lines[4], " 25.00%   25.00%  memcpy");
+        assert_eq!(lines.len(), 5);
+    }
+}
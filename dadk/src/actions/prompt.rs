@@ -0,0 +1,82 @@
+//! # 交互式问答
+//!
+//! `dadk user new`、`dadk manifest init`的向导从输入流逐行读取用户的回答，
+//! 测试时可以用字符串代替标准输入。
+
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, Result};
+
+/// 从输入流读取用户的回答
+pub(crate) struct Prompter<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub(crate) fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    pub(crate) fn say(&mut self, msg: &str) -> Result<()> {
+        writeln!(self.output, "{}", msg)?;
+        Ok(())
+    }
+
+    /// 询问一个问题，输入为空时返回默认值（没有默认值时返回空字符串）
+    pub(crate) fn ask(&mut self, question: &str, default: Option<&str>) -> Result<String> {
+        match default {
+            Some(d) if !d.is_empty() => write!(self.output, "{} [{}]: ", question, d)?,
+            _ => write!(self.output, "{}: ", question)?,
+        }
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Unexpected end of input"));
+        }
+        let answer = line.trim();
+        if answer.is_empty() {
+            return Ok(default.unwrap_or_default().to_string());
+        }
+        Ok(answer.to_string())
+    }
+
+    pub(crate) fn ask_required(&mut self, question: &str, default: Option<&str>) -> Result<String> {
+        loop {
+            let answer = self.ask(question, default)?;
+            if !answer.is_empty() {
+                return Ok(answer);
+            }
+            self.say("This field is required.")?;
+        }
+    }
+
+    pub(crate) fn ask_choice(
+        &mut self,
+        question: &str,
+        choices: &[&str],
+        default: &str,
+    ) -> Result<String> {
+        let question = format!("{} ({})", question, choices.join("/"));
+        loop {
+            let answer = self.ask(&question, Some(default))?;
+            if choices.contains(&answer.as_str()) {
+                return Ok(answer);
+            }
+            self.say(&format!("Please choose one of: {}", choices.join(", ")))?;
+        }
+    }
+
+    pub(crate) fn ask_bool(&mut self, question: &str, default: bool) -> Result<bool> {
+        let default_str = if default { "y" } else { "n" };
+        loop {
+            let answer = self.ask(&format!("{} (y/n)", question), Some(default_str))?;
+            match answer.to_ascii_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.say("Please answer y or n.")?,
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! # `dadk release`
+//!
+//! 计算内核、磁盘镜像以及二进制包的sha256，写入`sha256sum`格式的校验和清单（默认为`bin/SHA256SUMS`），
+//! 并按照manifest中的`[release.sign]`使用minisign或者gpg对清单签名，便于下游用户校验DADK产出的镜像：
+//!
+//! ```shell
+//! cd bin && sha256sum -c SHA256SUMS
+//! minisign -V -p minisign.pub -m SHA256SUMS
+//! ```
+//!
+//! 清单中的路径相对于清单所在的目录，因此所有文件都需要位于清单所在的目录下。
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    manifest::{SignConfig, SignTool},
+    rootfs::image_format::ImageFormat,
+};
+use dadk_user::repository::sha256_file;
+
+use crate::{console::release::ReleaseCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &ReleaseCommand) -> Result<()> {
+    let release = &ctx.manifest().release;
+    let workdir = ctx.workdir();
+    let manifest_path = workdir.join(args.output.as_ref().unwrap_or(&release.manifest));
+    let packages_dir = args
+        .packages_dir
+        .clone()
+        .unwrap_or_else(|| workdir.join("bin/packages"));
+
+    let mut artifacts = Vec::new();
+    if let Some(kernel) = &release.kernel {
+        artifacts.push(workdir.join(kernel));
+    }
+    let disk_images: Vec<PathBuf> = ImageFormat::ALL
+        .iter()
+        .map(|f| f.image_path(&ctx.disk_image_path()))
+        .filter(|p| p.exists())
+        .collect();
+    if disk_images.is_empty() {
+        return Err(anyhow!(
+            "Disk image does not exist: {}, run `dadk rootfs create` first",
+            ctx.disk_image_path().display()
+        ));
+    }
+    artifacts.extend(disk_images);
+    artifacts.extend(packages(&packages_dir)?);
+    artifacts.extend(release.artifacts.iter().map(|p| workdir.join(p)));
+
+    artifacts.sort();
+    artifacts.dedup();
+
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new("/"));
+    let mut lines = Vec::new();
+    for artifact in &artifacts {
+        if !artifact.is_file() {
+            return Err(anyhow!(
+                "Release artifact not found: {}",
+                artifact.display()
+            ));
+        }
+        log::info!("Hashing {}", artifact.display());
+        let sha256 = sha256_file(artifact).map_err(|e| anyhow!(e))?;
+        lines.push(checksum_line(&sha256, artifact, manifest_dir)?);
+    }
+
+    std::fs::create_dir_all(manifest_dir)
+        .map_err(|e| anyhow!("Failed to create {}: {}", manifest_dir.display(), e))?;
+    std::fs::write(&manifest_path, lines.join(""))
+        .map_err(|e| anyhow!("Failed to write {}: {}", manifest_path.display(), e))?;
+    println!("{}", manifest_path.display());
+
+    if let Some(sign) = release.sign.as_ref().filter(|_| !args.no_sign) {
+        let signature = sign_manifest(sign, &manifest_path)?;
+        println!("{}", signature.display());
+    }
+    Ok(())
+}
+
+/// 二进制包目录中的`.dpk`以及`index.toml`，目录不存在时返回空列表
+fn packages(packages_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !packages_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let entries = std::fs::read_dir(packages_dir)
+        .map_err(|e| anyhow!("Failed to read {}: {}", packages_dir.display(), e))?;
+    Ok(entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.extension().is_some_and(|ext| ext == "dpk")
+                || p.file_name().is_some_and(|name| name == "index.toml")
+        })
+        .collect())
+}
+
+/// `sha256sum`格式的一行，路径相对于清单所在的目录
+fn checksum_line(sha256: &str, artifact: &Path, manifest_dir: &Path) -> Result<String> {
+    let relative = artifact.strip_prefix(manifest_dir).map_err(|_| {
+        anyhow!(
+            "Release artifact {} is not under the directory of the checksum manifest ({})",
+            artifact.display(),
+            manifest_dir.display()
+        )
+    })?;
+    Ok(format!("{}  {}\n", sha256, relative.display()))
+}
+
+/// 对清单签名，返回签名文件的路径
+fn sign_manifest(sign: &SignConfig, manifest_path: &Path) -> Result<PathBuf> {
+    let (mut cmd, signature) = sign_command(sign, manifest_path);
+    log::info!("Signing {} with {:?}", manifest_path.display(), sign.tool);
+    let status = cmd
+        .status()
+        .map_err(|e| anyhow!("Failed to execute {:?}: {}", cmd.get_program(), e))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Failed to sign {}: {:?} exited with {}",
+            manifest_path.display(),
+            cmd.get_program(),
+            status
+        ));
+    }
+    Ok(signature)
+}
+
+/// 签名命令以及签名文件的路径
+fn sign_command(sign: &SignConfig, manifest_path: &Path) -> (Command, PathBuf) {
+    let mut signature = manifest_path.as_os_str().to_owned();
+    match sign.tool {
+        SignTool::Minisign => {
+            signature.push(".minisig");
+            let mut cmd = Command::new("minisign");
+            cmd.arg("-S");
+            if let Some(key) = &sign.key {
+                cmd.arg("-s").arg(key);
+            }
+            cmd.arg("-m").arg(manifest_path);
+            (cmd, signature.into())
+        }
+        SignTool::Gpg => {
+            signature.push(".asc");
+            let mut cmd = Command::new("gpg");
+            cmd.args(["--batch", "--yes", "--armor", "--detach-sign"]);
+            if let Some(key) = &sign.key {
+                cmd.arg("--local-user").arg(key);
+            }
+            cmd.arg("--output").arg(&signature).arg(manifest_path);
+            (cmd, signature.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|s| s.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_checksum_line() {
+        let sha256 = "a".repeat(64);
+        let dir = Path::new("/work/bin");
+        assert_eq!(
+            checksum_line(&sha256, Path::new("/work/bin/packages/a.dpk"), dir).unwrap(),
+            format!("{}  packages/a.dpk\n", sha256)
+        );
+        assert!(checksum_line(&sha256, Path::new("/work/kernel.elf"), dir).is_err());
+    }
+
+    #[test]
+    fn test_sign_command() {
+        let manifest = Path::new("bin/SHA256SUMS");
+        let sign = SignConfig {
+            tool: SignTool::Minisign,
+            key: Some("minisign.key".to_string()),
+        };
+        let (cmd, signature) = sign_command(&sign, manifest);
+        assert_eq!(
+            args(&cmd),
+            vec![
+                "minisign",
+                "-S",
+                "-s",
+                "minisign.key",
+                "-m",
+                "bin/SHA256SUMS"
+            ]
+        );
+        assert_eq!(signature, Path::new("bin/SHA256SUMS.minisig"));
+
+        let sign = SignConfig {
+            tool: SignTool::Gpg,
+            key: None,
+        };
+        let (cmd, signature) = sign_command(&sign, manifest);
+        assert_eq!(
+            args(&cmd),
+            vec![
+                "gpg",
+                "--batch",
+                "--yes",
+                "--armor",
+                "--detach-sign",
+                "--output",
+                "bin/SHA256SUMS.asc",
+                "bin/SHA256SUMS"
+            ]
+        );
+        assert_eq!(signature, Path::new("bin/SHA256SUMS.asc"));
+    }
+
+    #[test]
+    fn test_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(packages(&dir.path().join("missing")).unwrap().is_empty());
+        for name in ["a-0.1.0-x86_64.dpk", "index.toml", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let mut found = packages(dir.path()).unwrap();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                dir.path().join("a-0.1.0-x86_64.dpk"),
+                dir.path().join("index.toml")
+            ]
+        );
+    }
+}
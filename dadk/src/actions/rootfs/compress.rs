@@ -0,0 +1,215 @@
+//! # 压缩的磁盘镜像
+//!
+//! 挂载、启动始终使用raw镜像。`rootfs.toml`中`metadata.format`为`qcow2`或`zstd`时，
+//! 创建磁盘镜像以及卸载磁盘镜像之后，把raw镜像转换为压缩的镜像，放在raw镜像旁边，便于归档：
+//!
+//! - `qcow2`：`qemu-img convert -c -O qcow2`，输出`disk-image-<arch>.qcow2`
+//! - `zstd`：`zstd`，输出`disk-image-<arch>.img.zst`
+//!
+//! raw镜像不存在（例如只从CI下载了压缩的镜像）时，挂载、启动之前会先从压缩的镜像恢复raw镜像。
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::rootfs::image_format::ImageFormat;
+
+use crate::context::DADKExecContext;
+
+/// 把raw镜像转换为`format`格式，`format`为raw时不做任何事情
+pub(super) fn export(raw_image: &Path, format: ImageFormat) -> Result<()> {
+    if format == ImageFormat::Raw {
+        return Ok(());
+    }
+    let output = format.image_path(raw_image);
+    // 先写入临时文件，避免转换失败时留下不完整的镜像
+    let tmp = tmp_path(&output);
+    log::info!(
+        "Converting disk image to {}: {}",
+        format.as_str(),
+        output.display()
+    );
+    let r = run(convert_command(format, raw_image, &tmp, false))
+        .and_then(|_| std::fs::rename(&tmp, &output).map_err(|e| anyhow!(e)));
+    if r.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    r.map_err(|e| anyhow!("Failed to convert disk image to {}: {}", format.as_str(), e))
+}
+
+/// raw镜像不存在时，从压缩的镜像恢复。优先使用`rootfs.toml`中配置的格式
+pub fn ensure_raw_image(ctx: &DADKExecContext) -> Result<()> {
+    let raw_image = ctx.disk_image_path();
+    if raw_image.exists() {
+        return Ok(());
+    }
+    let Some(format) = compressed_image_format(&raw_image, ctx.rootfs().metadata.format) else {
+        return Err(anyhow!(
+            "Disk image does not exist: {}",
+            raw_image.display()
+        ));
+    };
+    let input = format.image_path(&raw_image);
+    log::info!(
+        "Restoring disk image {} from {}",
+        raw_image.display(),
+        input.display()
+    );
+    let tmp = tmp_path(&raw_image);
+    let r = run(convert_command(format, &input, &tmp, true))
+        .and_then(|_| std::fs::rename(&tmp, &raw_image).map_err(|e| anyhow!(e)));
+    if r.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    r.map_err(|e| {
+        anyhow!(
+            "Failed to restore disk image from {}: {}",
+            input.display(),
+            e
+        )
+    })
+}
+
+/// 已经存在的压缩镜像
+pub(super) fn compressed_images(raw_image: &Path) -> Vec<PathBuf> {
+    ImageFormat::ALL
+        .iter()
+        .filter(|f| **f != ImageFormat::Raw)
+        .map(|f| f.image_path(raw_image))
+        .filter(|p| p.exists())
+        .collect()
+}
+
+/// 可以用于恢复raw镜像的压缩镜像的格式，`preferred`对应的镜像存在时优先使用
+fn compressed_image_format(raw_image: &Path, preferred: ImageFormat) -> Option<ImageFormat> {
+    std::iter::once(preferred)
+        .chain(ImageFormat::ALL)
+        .filter(|f| *f != ImageFormat::Raw)
+        .find(|f| f.image_path(raw_image).exists())
+}
+
+/// 转换命令。`decompress`为true时，从`format`格式转换为raw镜像
+fn convert_command(format: ImageFormat, input: &Path, output: &Path, decompress: bool) -> Command {
+    match format {
+        ImageFormat::Qcow2 => {
+            let mut cmd = Command::new("qemu-img");
+            cmd.arg("convert");
+            if decompress {
+                cmd.args(["-f", "qcow2", "-O", "raw"]);
+            } else {
+                cmd.args(["-c", "-f", "raw", "-O", "qcow2"]);
+            }
+            cmd.arg(input).arg(output);
+            cmd
+        }
+        ImageFormat::Zstd => {
+            let mut cmd = Command::new("zstd");
+            cmd.args(["-q", "-f"]);
+            cmd.arg(if decompress { "-d" } else { "-T0" });
+            cmd.arg(input).arg("-o").arg(output);
+            cmd
+        }
+        ImageFormat::Raw => unreachable!("raw images need no conversion"),
+    }
+}
+
+fn run(mut cmd: Command) -> Result<()> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd
+        .output()
+        .map_err(|e| anyhow!("Failed to execute {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|s| s.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_convert_command() {
+        let raw = Path::new("disk.img");
+        let qcow2 = Path::new("disk.qcow2");
+        assert_eq!(
+            args(&convert_command(ImageFormat::Qcow2, raw, qcow2, false)),
+            vec![
+                "qemu-img",
+                "convert",
+                "-c",
+                "-f",
+                "raw",
+                "-O",
+                "qcow2",
+                "disk.img",
+                "disk.qcow2"
+            ]
+        );
+        assert_eq!(
+            args(&convert_command(ImageFormat::Qcow2, qcow2, raw, true)),
+            vec![
+                "qemu-img",
+                "convert",
+                "-f",
+                "qcow2",
+                "-O",
+                "raw",
+                "disk.qcow2",
+                "disk.img"
+            ]
+        );
+        let zst = Path::new("disk.img.zst");
+        assert_eq!(
+            args(&convert_command(ImageFormat::Zstd, raw, zst, false)),
+            vec!["zstd", "-q", "-f", "-T0", "disk.img", "-o", "disk.img.zst"]
+        );
+        assert_eq!(
+            args(&convert_command(ImageFormat::Zstd, zst, raw, true)),
+            vec!["zstd", "-q", "-f", "-d", "disk.img.zst", "-o", "disk.img"]
+        );
+    }
+
+    #[test]
+    fn test_compressed_image_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = dir.path().join("disk-image-x86_64.img");
+        assert_eq!(compressed_image_format(&raw, ImageFormat::Raw), None);
+
+        std::fs::write(dir.path().join("disk-image-x86_64.img.zst"), "").unwrap();
+        assert_eq!(
+            compressed_image_format(&raw, ImageFormat::Qcow2),
+            Some(ImageFormat::Zstd)
+        );
+        std::fs::write(dir.path().join("disk-image-x86_64.qcow2"), "").unwrap();
+        assert_eq!(
+            compressed_image_format(&raw, ImageFormat::Zstd),
+            Some(ImageFormat::Zstd)
+        );
+        assert_eq!(
+            compressed_image_format(&raw, ImageFormat::Raw),
+            Some(ImageFormat::Qcow2)
+        );
+        assert_eq!(compressed_images(&raw).len(), 2);
+    }
+}
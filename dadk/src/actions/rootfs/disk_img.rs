@@ -1,20 +1,38 @@
-use std::{fs::File, io::Write, mem::ManuallyDrop, path::PathBuf, process::Command};
+use std::{
+    fs::File,
+    io::Write,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use crate::context::DADKExecContext;
 use anyhow::{anyhow, Result};
-use dadk_config::rootfs::{fstype::FsType, partition::PartitionType};
+use dadk_config::rootfs::{
+    fstype::FsType, image_format::ImageFormat, partition::PartitionType, population::Population,
+};
+use dadk_user::cache::format_size;
+use serde::Serialize;
 
-use super::loopdev::LoopDeviceBuilder;
-pub(super) fn create(ctx: &DADKExecContext, skip_if_exists: bool) -> Result<()> {
+use super::{compress, fat, loopdev::LoopDeviceBuilder};
+
+/// 创建磁盘镜像。`format`为None时使用rootfs.toml中的`metadata.format`
+pub(super) fn create(
+    ctx: &DADKExecContext,
+    skip_if_exists: bool,
+    format: Option<ImageFormat>,
+) -> Result<()> {
     let disk_image_path = ctx.disk_image_path();
-    if disk_image_path.exists() {
+    let format = format.unwrap_or(ctx.rootfs().metadata.format);
+    // 只有压缩的镜像时，挂载、启动之前会从压缩的镜像恢复raw镜像
+    let existing = std::iter::once(disk_image_path.clone())
+        .chain(compress::compressed_images(&disk_image_path))
+        .find(|p| p.exists());
+    if let Some(existing) = existing {
         if skip_if_exists {
             return Ok(());
         }
-        return Err(anyhow!(
-            "Disk image already exists: {}",
-            disk_image_path.display()
-        ));
+        return Err(anyhow!("Disk image already exists: {}", existing.display()));
     }
 
     disk_path_safety_check(&disk_image_path)?;
@@ -25,7 +43,9 @@ pub(super) fn create(ctx: &DADKExecContext, skip_if_exists: bool) -> Result<()>
 
     // 判断是否需要分区？
 
-    let r = if ctx.rootfs().partition.image_should_be_partitioned() {
+    let r = if ctx.rootfs().metadata.population == Population::Userspace {
+        fat::create_image(&disk_image_path, ctx.rootfs().partition.partition_type)
+    } else if ctx.rootfs().partition.image_should_be_partitioned() {
         create_partitioned_image(ctx, &disk_image_path)
     } else {
         create_unpartitioned_image(ctx, &disk_image_path)
@@ -34,12 +54,14 @@ pub(super) fn create(ctx: &DADKExecContext, skip_if_exists: bool) -> Result<()>
     if r.is_err() {
         std::fs::remove_file(&disk_image_path).expect("Failed to remove disk image");
     }
-    r
+    r?;
+    compress::export(&disk_image_path, format)
 }
 
 pub(super) fn delete(ctx: &DADKExecContext, skip_if_not_exists: bool) -> Result<()> {
     let disk_image_path = ctx.disk_image_path();
-    if !disk_image_path.exists() {
+    let compressed = compress::compressed_images(&disk_image_path);
+    if !disk_image_path.exists() && compressed.is_empty() {
         if skip_if_not_exists {
             return Ok(());
         }
@@ -50,20 +72,37 @@ pub(super) fn delete(ctx: &DADKExecContext, skip_if_not_exists: bool) -> Result<
     }
     disk_path_safety_check(&disk_image_path)?;
 
-    std::fs::remove_file(&disk_image_path)
-        .map_err(|e| anyhow!("Failed to remove disk image: {}", e))?;
+    if disk_image_path.exists() {
+        std::fs::remove_file(&disk_image_path)
+            .map_err(|e| anyhow!("Failed to remove disk image: {}", e))?;
+    }
+    for path in compressed {
+        std::fs::remove_file(&path)
+            .map_err(|e| anyhow!("Failed to remove {}: {}", path.display(), e))?;
+    }
     Ok(())
 }
 
-pub fn mount(ctx: &DADKExecContext) -> Result<()> {
+pub fn mount(ctx: &DADKExecContext, idempotent: bool) -> Result<()> {
     let disk_image_path = ctx.disk_image_path();
-    if !disk_image_path.exists() {
+    let disk_mount_path = ctx.disk_mount_path();
+    if let Some(source) = mount_source(&disk_mount_path) {
+        if idempotent {
+            log::info!(
+                "Disk image already mounted at {} ({})",
+                disk_mount_path.display(),
+                source
+            );
+            return Ok(());
+        }
         return Err(anyhow!(
-            "Disk image does not exist: {}",
-            disk_image_path.display()
+            "{} is already mounted ({})",
+            disk_mount_path.display(),
+            source
         ));
     }
-    let disk_mount_path = ctx.disk_mount_path();
+
+    compress::ensure_raw_image(ctx)?;
 
     // 尝试创建挂载点
     std::fs::create_dir_all(&disk_mount_path)
@@ -85,12 +124,10 @@ fn mount_partitioned_image(
     disk_image_path: &PathBuf,
     disk_mount_path: &PathBuf,
 ) -> Result<()> {
-    let mut loop_device = ManuallyDrop::new(
-        LoopDeviceBuilder::new()
-            .img_path(disk_image_path.clone())
-            .build()
-            .map_err(|e| anyhow!("Failed to create loop device: {}", e))?,
-    );
+    let mut loop_device = LoopDeviceBuilder::new()
+        .img_path(disk_image_path.clone())
+        .build()
+        .map_err(|e| anyhow!("Failed to create loop device: {}", e))?;
 
     loop_device
         .attach()
@@ -98,6 +135,8 @@ fn mount_partitioned_image(
 
     let dev_path = loop_device.partition_path(1)?;
     mount_unpartitioned_image(ctx, &dev_path, disk_mount_path)?;
+    // 挂载成功后loop设备需要一直保持attach状态，直到umount。挂载失败时会在drop时detach
+    loop_device.keep_attached();
 
     Ok(())
 }
@@ -121,7 +160,7 @@ fn mount_unpartitioned_image(
     Ok(())
 }
 
-pub fn umount(ctx: &DADKExecContext) -> Result<()> {
+pub fn umount(ctx: &DADKExecContext, idempotent: bool) -> Result<()> {
     let disk_img_path = ctx.disk_image_path();
     let disk_mount_path = ctx.disk_mount_path();
     let mut loop_device = LoopDeviceBuilder::new().img_path(disk_img_path).build();
@@ -137,7 +176,10 @@ pub fn umount(ctx: &DADKExecContext) -> Result<()> {
         should_detach_loop_device = false;
     }
 
-    if disk_mount_path.exists() {
+    // 没有挂载时不执行umount，只detach残留的loop设备
+    let mounted = mount_source(&disk_mount_path).is_some();
+    let skip_umount = idempotent && !mounted;
+    if disk_mount_path.exists() && !skip_umount {
         let cmd = Command::new("umount")
             .arg(disk_mount_path)
             .output()
@@ -167,15 +209,300 @@ pub fn umount(ctx: &DADKExecContext) -> Result<()> {
     }
 
     if let Ok(mut loop_device) = loop_device {
-        let loop_dev_path = loop_device.dev_path().cloned();
-        loop_device.detach().ok();
+        if loop_device.attached() {
+            let loop_dev_path = loop_device.dev_path().cloned();
+            loop_device.detach().ok();
+
+            log::info!("Loop device detached: {:?}", loop_dev_path);
+        }
+    }
+
+    // 镜像的内容可能已经被修改，重新生成压缩的镜像
+    if mounted {
+        compress::export(&ctx.disk_image_path(), ctx.rootfs().metadata.format)?;
+    }
+    Ok(())
+}
+
+/// 把sysroot中的文件复制到已经挂载的磁盘镜像中，保留权限、符号链接，覆盖同名文件
+pub(super) fn install_sysroot(ctx: &DADKExecContext, sysroot_dir: &Path) -> Result<()> {
+    let disk_mount_path = ctx.disk_mount_path();
+    if mount_source(&disk_mount_path).is_none() {
+        return Err(anyhow!(
+            "Disk image is not mounted at {}",
+            disk_mount_path.display()
+        ));
+    }
+    log::info!(
+        "Installing {} into {}",
+        sysroot_dir.display(),
+        disk_mount_path.display()
+    );
+    let output = Command::new("cp")
+        .arg("-a")
+        .arg(sysroot_dir.join("."))
+        .arg(&disk_mount_path)
+        .output()
+        .map_err(|e| anyhow!("Failed to run cp: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to install sysroot into the disk image: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// 不挂载磁盘镜像，在用户态把sysroot中的文件写入磁盘镜像（`population = "userspace"`），
+/// 然后重新生成压缩的镜像
+pub(super) fn populate(ctx: &DADKExecContext, sysroot_dir: &Path) -> Result<()> {
+    let disk_image_path = ctx.disk_image_path();
+    let disk_mount_path = ctx.disk_mount_path();
+    // 内核中挂载的文件系统会缓存元数据，同时写入会损坏文件系统
+    if let Some(source) = mount_source(&disk_mount_path) {
+        return Err(anyhow!(
+            "Disk image is mounted at {} ({}), umount it before populating it in userspace",
+            disk_mount_path.display(),
+            source
+        ));
+    }
+    compress::ensure_raw_image(ctx)?;
+    fat::install_sysroot(
+        &disk_image_path,
+        ctx.rootfs().partition.image_should_be_partitioned(),
+        sysroot_dir,
+    )?;
+    compress::export(&disk_image_path, ctx.rootfs().metadata.format)
+}
+
+/// 压缩磁盘镜像：挂载后用0填充文件系统中的空闲空间，卸载时重新生成压缩的镜像。
+/// 已删除的文件留下的数据被清零之后，压缩的镜像（qcow2、zstd）会变小。
+///
+/// `sparse`为true时，再把raw镜像中全0的块转换为空洞，raw镜像成为稀疏文件
+pub(super) fn shrink(ctx: &DADKExecContext, sparse: bool) -> Result<()> {
+    let disk_image_path = ctx.disk_image_path();
+    let disk_mount_path = ctx.disk_mount_path();
+    if let Some(source) = mount_source(&disk_mount_path) {
+        return Err(anyhow!(
+            "Disk image is mounted at {} ({}), umount it before shrinking",
+            disk_mount_path.display(),
+            source
+        ));
+    }
+    disk_path_safety_check(&disk_image_path)?;
+    let images = |raw: &Path| {
+        std::iter::once(raw.to_path_buf())
+            .chain(compress::compressed_images(raw))
+            .filter_map(|p| allocated_size(&p).map(|size| (p, size)))
+            .collect::<Vec<_>>()
+    };
+    // raw镜像不存在时，挂载会从压缩的镜像恢复
+    let before = images(&disk_image_path);
+
+    mount(ctx, false)?;
+    let filled = zero_fill_free_space(&disk_mount_path);
+    let umounted = umount(ctx, false);
+    let filled = filled?;
+    umounted?;
+    log::info!("Zero-filled {} of free space", format_size(filled));
+
+    if sparse {
+        dig_holes(&disk_image_path)?;
+    }
+    for (path, after) in images(&disk_image_path) {
+        match before.iter().find(|(p, _)| *p == path) {
+            Some((_, before)) => println!(
+                "{}: {} -> {}",
+                path.display(),
+                format_size(*before),
+                format_size(after)
+            ),
+            None => println!("{}: {}", path.display(), format_size(after)),
+        }
+    }
+    Ok(())
+}
+
+/// 文件实际占用的磁盘空间（稀疏文件中的空洞不占用空间），文件不存在时返回None
+fn allocated_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|m| m.blocks() * 512)
+}
 
-        log::info!("Loop device detached: {:?}", loop_dev_path);
+/// 填充空闲空间时每个文件的最大大小，FAT32中单个文件不能超过4GiB
+const ZERO_FILL_FILE_LIMIT: u64 = 4 * 1024 * 1024 * 1024 - 1024 * 1024;
+
+/// 在`dir`中写入全0的文件直到文件系统没有空闲空间，然后删除这些文件。返回写入的字节数
+fn zero_fill_free_space(dir: &Path) -> Result<u64> {
+    const ENOSPC: i32 = 28;
+    let zeros = vec![0u8; 1024 * 1024];
+    let mut files = Vec::new();
+    let mut total = 0;
+    let r = (|| -> Result<()> {
+        loop {
+            let path = dir.join(format!(".dadk_zero_fill.{}", files.len()));
+            let mut file = File::create(&path)
+                .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+            files.push(path);
+            let mut written = 0;
+            let mut full = false;
+            while written < ZERO_FILL_FILE_LIMIT && !full {
+                match file.write(&zeros) {
+                    Ok(0) => full = true,
+                    Ok(n) => written += n as u64,
+                    Err(e) if e.raw_os_error() == Some(ENOSPC) => full = true,
+                    Err(e) => return Err(anyhow!("Failed to fill free space: {}", e)),
+                }
+            }
+            total += written;
+            // 确保0被写入磁盘镜像，而不是只留在页缓存中
+            file.sync_all().ok();
+            if full {
+                return Ok(());
+            }
+        }
+    })();
+    for path in &files {
+        std::fs::remove_file(path)
+            .map_err(|e| anyhow!("Failed to remove {}: {}", path.display(), e))?;
     }
+    r.map(|_| total)
+}
 
+/// 把文件中全0的块转换为空洞（`fallocate --dig-holes`）
+fn dig_holes(path: &Path) -> Result<()> {
+    log::info!("Converting {} to a sparse file", path.display());
+    let output = Command::new("fallocate")
+        .arg("--dig-holes")
+        .arg(path)
+        .output()
+        .map_err(|e| anyhow!("Failed to run fallocate: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to convert {} to a sparse file: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// 磁盘镜像的状态
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct RootFSStatus {
+    disk_image: PathBuf,
+    image_exists: bool,
+    partitioned: bool,
+    /// 磁盘镜像attach到的loop设备
+    loop_device: Option<String>,
+    /// loop设备的分区设备
+    partitions: Vec<PathBuf>,
+    mount_point: PathBuf,
+    /// 挂载到挂载点的设备，没有挂载时为None
+    mounted: Option<String>,
+}
+
+impl RootFSStatus {
+    fn text(&self) -> String {
+        let or_none = |s: Option<&String>| s.cloned().unwrap_or_else(|| "none".to_string());
+        let partitions: Vec<String> = self
+            .partitions
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        format!(
+            "disk image:   {} ({})\npartitioned:  {}\nloop device:  {}\npartitions:   {}\nmount point:  {}\nmounted:      {}\n",
+            self.disk_image.display(),
+            if self.image_exists { "exists" } else { "not exists" },
+            self.partitioned,
+            or_none(self.loop_device.as_ref()),
+            if partitions.is_empty() {
+                "none".to_string()
+            } else {
+                partitions.join(", ")
+            },
+            self.mount_point.display(),
+            or_none(self.mounted.as_ref()),
+        )
+    }
+}
+
+pub fn status(ctx: &DADKExecContext, json: bool) -> Result<()> {
+    let disk_image_path = ctx.disk_image_path();
+    let mount_point = ctx.disk_mount_path();
+    // 只查询状态，不能在drop时detach已经存在的loop设备
+    let mut loop_device = LoopDeviceBuilder::new()
+        .img_path(disk_image_path.clone())
+        .try_detach_when_drop(false)
+        .build()?;
+    if disk_image_path.exists() {
+        if let Err(e) = loop_device.attach_by_exists() {
+            log::trace!("status: {}", e);
+        }
+    }
+    let status = RootFSStatus {
+        image_exists: disk_image_path.exists(),
+        disk_image: disk_image_path,
+        partitioned: ctx.rootfs().partition.image_should_be_partitioned(),
+        loop_device: loop_device.dev_path().cloned(),
+        partitions: loop_device.partition_devices(),
+        mounted: mount_source(&mount_point),
+        mount_point,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        print!("{}", status.text());
+    }
     Ok(())
 }
 
+/// 磁盘镜像已经挂载到挂载点时，返回挂载的设备
+pub fn mounted_source(ctx: &DADKExecContext) -> Option<String> {
+    mount_source(&ctx.disk_mount_path())
+}
+
+/// 挂载到`mount_point`的设备，没有挂载时返回None
+fn mount_source(mount_point: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string("/proc/mounts").ok()?;
+    let mount_point = mount_point.canonicalize().ok()?;
+    parse_mount_source(&mounts, &mount_point)
+}
+
+/// 从`/proc/mounts`的内容中查找挂载到`mount_point`的设备。同一个挂载点被多次挂载时，返回最后一次挂载的设备
+fn parse_mount_source(mounts: &str, mount_point: &Path) -> Option<String> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?, fields.next()?))
+        })
+        .filter(|(_, target)| Path::new(&unescape_mount_field(target)) == mount_point)
+        .last()
+        .map(|(source, _)| unescape_mount_field(source))
+}
+
+/// `/proc/mounts`中的空格、制表符、换行以及反斜杠以八进制转义（例如`\040`）
+fn unescape_mount_field(field: &str) -> String {
+    let mut out = String::new();
+    let mut rest = field;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest.get(i + 1..i + 4);
+        match code.and_then(|c| u8::from_str_radix(c, 8).ok()) {
+            Some(c) => {
+                out.push(c as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
 /// Ensures the provided disk image path is not a device node.
 fn disk_path_safety_check(disk_image_path: &PathBuf) -> Result<()> {
     const DONT_ALLOWED_PREFIX: [&str; 5] =
@@ -459,4 +786,72 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_dig_holes() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_path_buf();
+        create_raw_img(&path, 4 * 1024 * 1024)?;
+        let mut file = fs::OpenOptions::new().write(true).open(&path)?;
+        file.write_all(b"dadk")?;
+        file.sync_all()?;
+        let before = allocated_size(&path).unwrap();
+
+        dig_holes(&path)?;
+        let after = allocated_size(&path).unwrap();
+        assert!(after < before, "{} >= {}", after, before);
+        // 文件的大小和内容不变
+        let content = fs::read(&path)?;
+        assert_eq!(content.len(), 4 * 1024 * 1024);
+        assert_eq!(&content[..4], b"dadk");
+        assert!(content[4..].iter().all(|b| *b == 0));
+        assert!(allocated_size(&path.with_extension("missing")).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mount_source() {
+        let mounts = r"sysfs /sys sysfs rw,nosuid 0 0
+/dev/loop1p1 /data/bin/mnt\040disk vfat rw,relatime 0 0
+/dev/loop2 /data/bin/mnt/x86_64 ext4 rw 0 0
+/dev/loop3 /data/bin/mnt/x86_64 ext4 rw 0 0
+";
+        assert_eq!(
+            parse_mount_source(mounts, Path::new("/data/bin/mnt disk")).as_deref(),
+            Some("/dev/loop1p1")
+        );
+        assert_eq!(
+            parse_mount_source(mounts, Path::new("/data/bin/mnt/x86_64")).as_deref(),
+            Some("/dev/loop3")
+        );
+        assert!(parse_mount_source(mounts, Path::new("/data/bin/mnt")).is_none());
+        assert_eq!(unescape_mount_field(r"a\134b\011c\x"), "a\\b\tc\\x");
+    }
+
+    #[test]
+    fn test_rootfs_status_output() {
+        let status = RootFSStatus {
+            disk_image: PathBuf::from("/data/bin/disk-image-x86_64.img"),
+            image_exists: true,
+            partitioned: true,
+            loop_device: Some("/dev/loop1".to_string()),
+            partitions: vec![PathBuf::from("/dev/loop1p1")],
+            mount_point: PathBuf::from("/data/bin/mnt/x86_64"),
+            mounted: None,
+        };
+        assert_eq!(
+            status.text(),
+            "disk image:   /data/bin/disk-image-x86_64.img (exists)
+partitioned:  true
+loop device:  /dev/loop1
+partitions:   /dev/loop1p1
+mount point:  /data/bin/mnt/x86_64
+mounted:      none
+"
+        );
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["loop_device"], "/dev/loop1");
+        assert_eq!(json["partitions"][0], "/dev/loop1p1");
+        assert!(json["mounted"].is_null());
+    }
 }
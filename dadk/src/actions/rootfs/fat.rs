@@ -0,0 +1,313 @@
+//! # 在用户态创建、写入FAT32磁盘镜像
+//!
+//! `rootfs.toml`中指定`population = "userspace"`时使用：直接读写磁盘镜像文件中的FAT32文件系统，
+//! 不需要loop设备、`mount`、`fdisk`、`mkfs.fat`，也不需要root权限，可以在普通用户的CI中制作磁盘镜像。
+//!
+//! FAT32不支持符号链接、权限和属主：sysroot中的符号链接被跳过（输出警告），文件的权限和属主不会保留。
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::rootfs::partition::PartitionType;
+use fatfs::{FatType, FileSystem, FormatVolumeOptions, FsOptions};
+
+use super::loopdev::mbr_partition_range;
+
+/// 卷标，不足11字节时用空格补齐
+const VOLUME_LABEL: [u8; 11] = *b"DRAGONOS   ";
+/// 分区的起始扇区，与fdisk的默认值相同（1MiB对齐）
+const PARTITION_START_LBA: u32 = 2048;
+const SECTOR_SIZE: u32 = 512;
+/// MBR分区类型：FAT32（LBA）
+const MBR_FAT32_LBA_TYPE: u8 = 0x0c;
+
+/// 在全0的raw镜像中创建分区表（需要时）以及FAT32文件系统
+pub(super) fn create_image(disk_image_path: &Path, part_type: PartitionType) -> Result<()> {
+    let mut file = open(disk_image_path)?;
+    let (offset, len) = match part_type {
+        PartitionType::None => (0, file.metadata()?.len()),
+        PartitionType::Mbr => write_mbr(&mut file, disk_image_path)?,
+        PartitionType::Gpt => {
+            return Err(anyhow!(
+                "GPT partition tables are not supported when populating in userspace"
+            ))
+        }
+    };
+    let options = FormatVolumeOptions::new()
+        .fat_type(FatType::Fat32)
+        .volume_label(VOLUME_LABEL);
+    fatfs::format_volume(Slice::new(&mut file, offset, len)?, options)
+        .map_err(|e| anyhow!("Failed to format disk image as FAT32: {}", e))?;
+    Ok(())
+}
+
+/// 把sysroot中的文件写入磁盘镜像中的FAT32文件系统，覆盖同名文件
+pub(super) fn install_sysroot(
+    disk_image_path: &Path,
+    partitioned: bool,
+    sysroot_dir: &Path,
+) -> Result<()> {
+    let (offset, len) = if partitioned {
+        mbr_partition_range(disk_image_path, 1)?
+    } else {
+        (0, std::fs::metadata(disk_image_path)?.len())
+    };
+    let mut file = open(disk_image_path)?;
+    let fs = FileSystem::new(Slice::new(&mut file, offset, len)?, FsOptions::new())
+        .map_err(|e| anyhow!("Failed to open the FAT filesystem in the disk image: {}", e))?;
+    log::info!(
+        "Installing {} into {}",
+        sysroot_dir.display(),
+        disk_image_path.display()
+    );
+    copy_dir(sysroot_dir, &fs.root_dir(), "")?;
+    fs.unmount()
+        .map_err(|e| anyhow!("Failed to flush the FAT filesystem: {}", e))?;
+    Ok(())
+}
+
+fn open(disk_image_path: &Path) -> Result<File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(disk_image_path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", disk_image_path.display(), e))
+}
+
+/// 写入只有一个可启动分区的MBR分区表（与`create_mbr_partitioned_image`中fdisk的操作相同），返回分区的偏移量和大小
+fn write_mbr(file: &mut File, disk_image_path: &Path) -> Result<(u64, u64)> {
+    let sectors = u32::try_from(file.metadata()?.len() / u64::from(SECTOR_SIZE))
+        .map_err(|_| anyhow!("Disk image is too large for an MBR partition table"))?;
+    if sectors <= PARTITION_START_LBA {
+        return Err(anyhow!("Disk image is too small to be partitioned"));
+    }
+    let mut mbr = mbrman::MBR::new_from(file, SECTOR_SIZE, rand_disk_signature())
+        .map_err(|e| anyhow!("Failed to create MBR: {}", e))?;
+    mbr[1] = mbrman::MBRPartitionEntry {
+        boot: mbrman::BOOT_ACTIVE,
+        first_chs: mbrman::CHS::empty(),
+        sys: MBR_FAT32_LBA_TYPE,
+        last_chs: mbrman::CHS::empty(),
+        starting_lba: PARTITION_START_LBA,
+        sectors: sectors - PARTITION_START_LBA,
+    };
+    mbr.write_into(file).map_err(|e| {
+        anyhow!(
+            "Failed to write MBR into {}: {}",
+            disk_image_path.display(),
+            e
+        )
+    })?;
+    Ok((
+        u64::from(PARTITION_START_LBA) * u64::from(SECTOR_SIZE),
+        u64::from(sectors - PARTITION_START_LBA) * u64::from(SECTOR_SIZE),
+    ))
+}
+
+/// 磁盘签名只需要在不同的镜像之间不同
+fn rand_disk_signature() -> [u8; 4] {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    (nanos ^ std::process::id()).to_le_bytes()
+}
+
+/// 递归地复制目录。`rel`为目录在sysroot中的相对路径，用于错误信息
+fn copy_dir<T: fatfs::ReadWriteSeek>(src: &Path, dst: &fatfs::Dir<T>, rel: &str) -> Result<()> {
+    let mut entries = std::fs::read_dir(src)
+        .map_err(|e| anyhow!("Failed to read {}: {}", src.display(), e))?
+        .collect::<io::Result<Vec<_>>>()?;
+    // 按照名称排序，文件在目录中的顺序与read_dir返回的顺序无关
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .ok_or_else(|| anyhow!("File name is not valid UTF-8: {}", entry.path().display()))?;
+        let rel = format!("{}/{}", rel, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let dir = dst
+                .create_dir(name)
+                .map_err(|e| anyhow!("Failed to create {} in the disk image: {}", rel, e))?;
+            copy_dir(&entry.path(), &dir, &rel)?;
+        } else if file_type.is_file() {
+            let mut src_file = File::open(entry.path())
+                .map_err(|e| anyhow!("Failed to open {}: {}", entry.path().display(), e))?;
+            let mut dst_file = dst
+                .create_file(name)
+                .map_err(|e| anyhow!("Failed to create {} in the disk image: {}", rel, e))?;
+            dst_file.truncate()?;
+            io::copy(&mut src_file, &mut dst_file)
+                .map_err(|e| anyhow!("Failed to write {} into the disk image: {}", rel, e))?;
+        } else if file_type.is_symlink() {
+            log::warn!("Skipping symlink {}: FAT32 does not support symlinks", rel);
+        } else {
+            log::warn!("Skipping {}: not a regular file or directory", rel);
+        }
+    }
+    Ok(())
+}
+
+/// 磁盘镜像文件中的一段（分区），对文件系统来说从偏移量0开始
+struct Slice<'a> {
+    file: &'a mut File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<'a> Slice<'a> {
+    fn new(file: &'a mut File, start: u64, len: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            file,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// 从当前位置开始，最多可以读写的字节数
+    fn remaining(&self, want: usize) -> usize {
+        want.min(self.len.saturating_sub(self.pos) as usize)
+    }
+}
+
+impl Read for Slice<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining(buf.len());
+        let n = self.file.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for Slice<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.remaining(buf.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "write past the end of the partition",
+            ));
+        }
+        let n = self.file.write(&buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Slice<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        let pos = pos.filter(|p| *p <= self.len).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek out of the partition")
+        })?;
+        self.file.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 创建raw镜像，在其中创建FAT32文件系统，然后写入sysroot
+    fn populate(part_type: PartitionType) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let img = dir.path().join("disk.img");
+        File::create(&img)
+            .unwrap()
+            .set_len(64 * 1024 * 1024)
+            .unwrap();
+        create_image(&img, part_type).unwrap();
+
+        let sysroot = dir.path().join("sysroot");
+        std::fs::create_dir_all(sysroot.join("bin")).unwrap();
+        std::fs::create_dir_all(sysroot.join("etc/init.d")).unwrap();
+        std::fs::write(sysroot.join("bin/hello"), b"hello").unwrap();
+        std::fs::write(sysroot.join("etc/init.d/rcS"), b"#!/bin/sh\n").unwrap();
+        std::os::unix::fs::symlink("hello", sysroot.join("bin/hi")).unwrap();
+        install_sysroot(&img, part_type == PartitionType::Mbr, &sysroot).unwrap();
+        (dir, img)
+    }
+
+    fn read_file(img: &Path, partitioned: bool, path: &str) -> Option<Vec<u8>> {
+        let (offset, len) = if partitioned {
+            mbr_partition_range(img, 1).unwrap()
+        } else {
+            (0, std::fs::metadata(img).unwrap().len())
+        };
+        let mut file = open(img).unwrap();
+        let fs = FileSystem::new(
+            Slice::new(&mut file, offset, len).unwrap(),
+            FsOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat32);
+        let mut f = fs.root_dir().open_file(path).ok()?;
+        let mut content = Vec::new();
+        f.read_to_end(&mut content).unwrap();
+        Some(content)
+    }
+
+    #[test]
+    fn test_populate_unpartitioned() {
+        let (_dir, img) = populate(PartitionType::None);
+        assert_eq!(read_file(&img, false, "bin/hello").unwrap(), b"hello");
+        assert_eq!(
+            read_file(&img, false, "etc/init.d/rcS").unwrap(),
+            b"#!/bin/sh\n"
+        );
+        assert!(read_file(&img, false, "bin/hi").is_none());
+    }
+
+    #[test]
+    fn test_populate_mbr() {
+        let (dir, img) = populate(PartitionType::Mbr);
+        assert_eq!(
+            mbr_partition_range(&img, 1).unwrap(),
+            (2048 * 512, (64 * 2048 - 2048) * 512)
+        );
+        assert_eq!(read_file(&img, true, "bin/hello").unwrap(), b"hello");
+
+        // 再次写入时覆盖同名文件
+        let sysroot = dir.path().join("sysroot");
+        std::fs::write(sysroot.join("bin/hello"), b"hi").unwrap();
+        install_sysroot(&img, true, &sysroot).unwrap();
+        assert_eq!(read_file(&img, true, "bin/hello").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn test_slice() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.img");
+        std::fs::write(&path, b"0123456789").unwrap();
+        let mut file = open(&path).unwrap();
+        let mut slice = Slice::new(&mut file, 2, 5).unwrap();
+        let mut buf = Vec::new();
+        slice.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"23456");
+        assert_eq!(slice.seek(SeekFrom::End(-1)).unwrap(), 4);
+        slice.write_all(b"x").unwrap();
+        assert!(slice.write_all(b"y").is_err());
+        assert!(slice.seek(SeekFrom::Start(6)).is_err());
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), b"012345x789");
+    }
+}
@@ -1,18 +1,33 @@
 use core::str;
-use std::{path::PathBuf, process::Command, thread::sleep, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    thread::sleep,
+    time::Duration,
+};
 
 use anyhow::{anyhow, Result};
+use dadk_user::interrupt::{self, CleanupGuard};
 use regex::Regex;
 
 use crate::utils::abs_path;
 
 const LOOP_DEVICE_LOSETUP_A_REGEX: &str = r"^/dev/loop(\d+)";
+/// MBR分区表的扇区大小
+const MBR_SECTOR_SIZE: u32 = 512;
+/// GPT保护分区的分区类型
+const MBR_GPT_PROTECTIVE_TYPE: u8 = 0xee;
 
 pub struct LoopDevice {
     img_path: Option<PathBuf>,
     loop_device_path: Option<String>,
     /// 尝试在drop时自动detach
     try_detach_when_drop: bool,
+    /// 进程因信号退出时detach loop设备
+    cleanup: Option<CleanupGuard>,
+    /// 没有分区设备节点时，按照分区的偏移量单独attach的loop设备
+    partition_loop: Option<String>,
+    partition_cleanup: Option<CleanupGuard>,
 }
 impl LoopDevice {
     pub fn attached(&self) -> bool {
@@ -40,6 +55,12 @@ impl LoopDevice {
 
         if output.status.success() {
             let loop_device = String::from_utf8(output.stdout)?.trim().to_string();
+            if self.try_detach_when_drop {
+                let dev = loop_device.clone();
+                self.cleanup = Some(interrupt::register_cleanup(move || {
+                    Command::new("losetup").arg("-d").arg(dev).output().ok();
+                }));
+            }
             self.loop_device_path = Some(loop_device);
             sleep(Duration::from_millis(100));
             log::trace!(
@@ -73,12 +94,11 @@ impl LoopDevice {
             .output()
             .map_err(|e| anyhow!("Failed to run losetup -a: {}", e))?;
         let output = String::from_utf8(cmd.stdout)?;
-        let s = __loop_device_path_by_disk_image_path(
-            self.img_path.as_ref().unwrap().to_str().unwrap(),
-            &output,
-        )
-        .map_err(|e| anyhow!("Failed to find loop device: {}", e))?;
+        let img_path = self.img_path.as_ref().unwrap().to_str().unwrap();
+        let s = __loop_device_path_by_disk_image_path(img_path, &output)
+            .map_err(|e| anyhow!("Failed to find loop device: {}", e))?;
         self.loop_device_path = Some(s);
+        self.partition_loop = __partition_loop_device_path_by_disk_image_path(img_path, &output);
         Ok(())
     }
 
@@ -92,26 +112,109 @@ impl LoopDevice {
     ///
     /// 返回一个 `Result<String>`，包含分区路径的字符串。如果循环设备未附加，则返回错误。
     ///
+    /// `losetup -P`没有创建分区设备节点时（例如在没有udev的容器中），从镜像的MBR分区表中读取分区的偏移量和大小，
+    /// 为该分区单独attach一个loop设备（`losetup -o <偏移量> --sizelimit <大小>`），它会随着本loop设备一起detach。
+    ///
     /// # 错误
     ///
     /// 如果循环设备未附加，则返回 `anyhow!("Loop device not attached")` 错误。
-    pub fn partition_path(&self, nth: u8) -> Result<PathBuf> {
+    pub fn partition_path(&mut self, nth: u8) -> Result<PathBuf> {
         if !self.attached() {
             return Err(anyhow!("Loop device not attached"));
         }
         let s = format!("{}p{}", self.loop_device_path.as_ref().unwrap(), nth);
         let s = PathBuf::from(s);
         // 判断路径是否存在
-        if !s.exists() {
-            return Err(anyhow!("Partition not exist"));
+        if s.exists() {
+            return Ok(s);
+        }
+        if let Some(dev) = &self.partition_loop {
+            return Ok(PathBuf::from(dev));
+        }
+        let img_path = self
+            .img_path
+            .as_ref()
+            .ok_or(anyhow!("Image path not set"))?;
+        let (offset, size) = mbr_partition_range(img_path, nth)
+            .map_err(|e| anyhow!("Partition not exist: {}", e))?;
+        log::trace!(
+            "Partition device {} not exist, attach partition {} at offset {} (size {})",
+            s.display(),
+            nth,
+            offset,
+            size
+        );
+        let output = Command::new("losetup")
+            .arg("-f")
+            .arg("--show")
+            .arg("-o")
+            .arg(offset.to_string())
+            .arg("--sizelimit")
+            .arg(size.to_string())
+            .arg(img_path)
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to attach partition {}: losetup command exited with status {}",
+                nth,
+                output.status
+            ));
         }
-        Ok(s)
+        let dev = String::from_utf8(output.stdout)?.trim().to_string();
+        if self.try_detach_when_drop {
+            let d = dev.clone();
+            self.partition_cleanup = Some(interrupt::register_cleanup(move || {
+                Command::new("losetup").arg("-d").arg(d).output().ok();
+            }));
+        }
+        self.partition_loop = Some(dev.clone());
+        Ok(PathBuf::from(dev))
+    }
+
+    /// 已经存在的分区设备：`losetup -P`创建的分区设备节点，以及按照分区的偏移量attach的loop设备
+    pub fn partition_devices(&self) -> Vec<PathBuf> {
+        let Some(dev) = &self.loop_device_path else {
+            return Vec::new();
+        };
+        let dev = PathBuf::from(dev);
+        let prefix = format!("{}p", dev.file_name().unwrap_or_default().to_string_lossy());
+        let mut partitions: Vec<PathBuf> = dev
+            .parent()
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .strip_prefix(&prefix)
+                    .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            })
+            .map(|entry| entry.path())
+            .collect();
+        partitions.sort();
+        partitions.extend(self.partition_loop.iter().map(PathBuf::from));
+        partitions
     }
 
     pub fn detach(&mut self) -> Result<()> {
         if self.loop_device_path.is_none() {
             return Ok(());
         }
+        self.cleanup = None;
+        self.partition_cleanup = None;
+        if let Some(dev) = self.partition_loop.take() {
+            log::trace!("Detach partition loop device: {}", dev);
+            let output = Command::new("losetup").arg("-d").arg(&dev).output()?;
+            if !output.status.success() {
+                log::warn!(
+                    "Failed to detach partition loop device {}: {}",
+                    dev,
+                    str::from_utf8(output.stderr.as_slice()).unwrap_or("<Unknown>")
+                );
+            }
+        }
         let loop_device = self.loop_device_path.take().unwrap();
         let p = PathBuf::from(&loop_device);
         log::trace!(
@@ -143,6 +246,15 @@ impl LoopDevice {
     #[allow(dead_code)]
     pub fn set_try_detach_when_drop(&mut self, try_detach_when_drop: bool) {
         self.try_detach_when_drop = try_detach_when_drop;
+        if !try_detach_when_drop {
+            self.cleanup = None;
+            self.partition_cleanup = None;
+        }
+    }
+
+    /// 保持loop设备处于attach状态（例如挂载成功之后），drop以及进程被中断时都不再detach
+    pub fn keep_attached(&mut self) {
+        self.set_try_detach_when_drop(false);
     }
 }
 
@@ -176,7 +288,6 @@ impl LoopDeviceBuilder {
         self
     }
 
-    #[allow(dead_code)]
     pub fn try_detach_when_drop(mut self, try_detach_when_drop: bool) -> Self {
         self.try_detach_when_drop = try_detach_when_drop;
         self
@@ -187,6 +298,9 @@ impl LoopDeviceBuilder {
             img_path: self.img_path,
             loop_device_path: self.loop_device_path,
             try_detach_when_drop: self.try_detach_when_drop,
+            cleanup: None,
+            partition_loop: None,
+            partition_cleanup: None,
         };
 
         Ok(loop_dev)
@@ -199,7 +313,8 @@ fn __loop_device_path_by_disk_image_path(
 ) -> Result<String> {
     let re = Regex::new(LOOP_DEVICE_LOSETUP_A_REGEX)?;
     for line in losetup_a_output.lines() {
-        if !line.contains(disk_img_path) {
+        // 带有偏移量的是单独attach的分区
+        if !line.contains(disk_img_path) || line.contains(", offset") {
             continue;
         }
         let caps = re.captures(line);
@@ -214,6 +329,39 @@ fn __loop_device_path_by_disk_image_path(
     Err(anyhow!("Loop device not found"))
 }
 
+/// 查找按照分区的偏移量attach的loop设备
+fn __partition_loop_device_path_by_disk_image_path(
+    disk_img_path: &str,
+    losetup_a_output: &str,
+) -> Option<String> {
+    let re = Regex::new(LOOP_DEVICE_LOSETUP_A_REGEX).ok()?;
+    losetup_a_output
+        .lines()
+        .filter(|line| line.contains(disk_img_path) && line.contains(", offset"))
+        .find_map(|line| re.captures(line))
+        .map(|caps| format!("/dev/loop{}", caps.get(1).unwrap().as_str()))
+}
+
+/// 从磁盘镜像的MBR分区表中读取第`nth`个分区的偏移量和大小（字节）
+pub(super) fn mbr_partition_range(img_path: &Path, nth: u8) -> Result<(u64, u64)> {
+    let mut file = std::fs::File::open(img_path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", img_path.display(), e))?;
+    let mbr = mbrman::MBR::read_from(&mut file, MBR_SECTOR_SIZE)
+        .map_err(|e| anyhow!("Failed to read MBR of {}: {}", img_path.display(), e))?;
+    if mbr.iter().any(|(_, p)| p.sys == MBR_GPT_PROTECTIVE_TYPE) {
+        return Err(anyhow!("GPT partition table is not supported"));
+    }
+    let partition = mbr
+        .get(nth as usize)
+        .filter(|p| p.is_used())
+        .ok_or(anyhow!("Partition {} not found in MBR", nth))?;
+    let sector_size = u64::from(mbr.sector_size);
+    Ok((
+        u64::from(partition.starting_lba) * sector_size,
+        u64::from(partition.sectors) * sector_size,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,4 +410,53 @@ mod tests {
             "should not match any loop device"
         );
     }
+
+    #[test]
+    fn test_parse_losetup_a_output_with_partition() {
+        let losetup_a_output = r#"/dev/loop3: []: (/data/bin/disk-image-x86_64.img), offset 1048576, sizelimit 10485760
+/dev/loop1: []: (/data/bin/disk-image-x86_64.img)"#;
+        let disk_img_path = "/data/bin/disk-image-x86_64.img";
+        assert_eq!(
+            __loop_device_path_by_disk_image_path(disk_img_path, losetup_a_output).unwrap(),
+            "/dev/loop1"
+        );
+        assert_eq!(
+            __partition_loop_device_path_by_disk_image_path(disk_img_path, losetup_a_output)
+                .as_deref(),
+            Some("/dev/loop3")
+        );
+        assert_eq!(
+            __partition_loop_device_path_by_disk_image_path(
+                "/data/bin/disk-image-riscv64.img",
+                losetup_a_output
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mbr_partition_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = dir.path().join("disk.img");
+        let mut file = std::fs::File::create(&img).unwrap();
+        file.set_len(16 * 1024 * 1024).unwrap();
+        let mut mbr = mbrman::MBR::new_from(&mut file, MBR_SECTOR_SIZE, [1, 2, 3, 4]).unwrap();
+        mbr[1] = mbrman::MBRPartitionEntry {
+            boot: mbrman::BOOT_INACTIVE,
+            first_chs: mbrman::CHS::empty(),
+            sys: 0x83,
+            last_chs: mbrman::CHS::empty(),
+            starting_lba: 2048,
+            sectors: 4096,
+        };
+        mbr.write_into(&mut file).unwrap();
+        drop(file);
+
+        assert_eq!(
+            mbr_partition_range(&img, 1).unwrap(),
+            (2048 * 512, 4096 * 512)
+        );
+        assert!(mbr_partition_range(&img, 2).is_err());
+        assert!(mbr_partition_range(&dir.path().join("missing.img"), 1).is_err());
+    }
 }
@@ -1,19 +1,83 @@
+use std::path::Path;
+
 use crate::{console::rootfs::RootFSCommand, context::DADKExecContext};
 use anyhow::Result;
+use dadk_config::{manifest::HookPoint, rootfs::population::Population};
+
+use super::hooks;
 
-mod disk_img;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(super) mod compress;
+#[cfg(target_os = "linux")]
+pub(super) mod disk_img;
+#[cfg(not(target_os = "linux"))]
+#[path = "unsupported.rs"]
+pub(super) mod disk_img;
+#[cfg(target_os = "linux")]
+mod fat;
+#[cfg(target_os = "linux")]
 mod loopdev;
 mod sysroot;
 
 pub(super) fn run(ctx: &DADKExecContext, rootfs_cmd: &RootFSCommand) -> Result<()> {
     match rootfs_cmd {
-        RootFSCommand::Create(param) => disk_img::create(ctx, param.skip_if_exists),
+        RootFSCommand::Create(param) => hooks::with_hooks(
+            ctx,
+            HookPoint::PreRootfsCreate,
+            HookPoint::PostRootfsCreate,
+            || disk_img::create(ctx, param.skip_if_exists, param.format),
+        ),
         RootFSCommand::Delete => disk_img::delete(ctx, false),
-        RootFSCommand::DeleteSysroot => sysroot::delete(ctx),
-        RootFSCommand::Mount => disk_img::mount(ctx),
-        RootFSCommand::Umount => disk_img::umount(ctx),
+        RootFSCommand::DeleteSysroot(param) => sysroot::delete(ctx, param),
+        RootFSCommand::Mount(param) => disk_img::mount(ctx, param.idempotent),
+        RootFSCommand::Umount(param) => disk_img::umount(ctx, param.idempotent),
+        RootFSCommand::Shrink(param) => disk_img::shrink(ctx, param.sparse),
+        RootFSCommand::Status(param) => disk_img::status(ctx, param.json),
         RootFSCommand::CheckDiskImageExists => disk_img::check_disk_image_exists(ctx),
         RootFSCommand::ShowMountPoint => disk_img::show_mount_point(ctx),
         RootFSCommand::ShowLoopDevice => disk_img::show_loop_device(ctx),
     }
 }
+
+/// 创建磁盘镜像（已存在时跳过），挂载后把sysroot中的文件复制到磁盘镜像中，然后卸载。
+/// 复制失败时同样会卸载磁盘镜像。
+///
+/// `population = "userspace"`时不挂载，直接在用户态写入磁盘镜像中的文件系统
+pub(super) fn update_image(ctx: &DADKExecContext) -> Result<()> {
+    let sysroot_dir = ctx.sysroot_dir()?;
+    if ctx.rootfs().metadata.population == Population::Userspace {
+        create_image(ctx)?;
+        return disk_img::populate(ctx, &sysroot_dir);
+    }
+    with_mounted_image(ctx, |_| disk_img::install_sysroot(ctx, &sysroot_dir))
+}
+
+/// 创建磁盘镜像（已存在时跳过），包括对应的钩子
+fn create_image(ctx: &DADKExecContext) -> Result<()> {
+    hooks::with_hooks(
+        ctx,
+        HookPoint::PreRootfsCreate,
+        HookPoint::PostRootfsCreate,
+        || disk_img::create(ctx, true, None),
+    )
+}
+
+/// 创建磁盘镜像（已存在时跳过）并挂载，以挂载点为参数执行`f`，然后卸载。
+///
+/// `f`失败时同样会卸载磁盘镜像。磁盘镜像原本已经挂载时不卸载
+pub(super) fn with_mounted_image<T>(
+    ctx: &DADKExecContext,
+    f: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    create_image(ctx)?;
+    let was_mounted = disk_img::mounted_source(ctx).is_some();
+    disk_img::mount(ctx, true)?;
+    let r = f(&ctx.disk_mount_path());
+    if was_mounted {
+        return r;
+    }
+    let umounted = disk_img::umount(ctx, true);
+    let r = r?;
+    umounted?;
+    Ok(r)
+}
@@ -1,8 +1,12 @@
+use std::path::Path;
+
 use anyhow::{anyhow, Result};
+use dadk_user::pkgdb;
+use log::info;
 
-use crate::context::DADKExecContext;
+use crate::{console::rootfs::DeleteSysrootCommandParam, context::DADKExecContext};
 
-pub(super) fn delete(ctx: &DADKExecContext) -> Result<()> {
+pub(super) fn delete(ctx: &DADKExecContext, param: &DeleteSysrootCommandParam) -> Result<()> {
     let sysroot_dir = ctx.sysroot_dir()?;
     // 检查 sysroot_dir 是否存在
     if !sysroot_dir.exists() {
@@ -14,13 +18,71 @@ pub(super) fn delete(ctx: &DADKExecContext) -> Result<()> {
         return Err(anyhow!("Sysroot path is not a directory"));
     }
 
+    check_sysroot_dir(&sysroot_dir, &ctx.workdir())?;
+
+    if param.only.is_empty() && param.installed_by.is_empty() {
+        std::fs::remove_dir_all(sysroot_dir)?;
+        return Ok(());
+    }
+
+    // 只删除部分文件：按照软件包数据库删除任务安装的文件，或者删除指定的路径
+    for task in &param.installed_by {
+        let removed = pkgdb::uninstall(&sysroot_dir, task).map_err(|e| anyhow!(e))?;
+        info!("Removed {} file(s) installed by {}", removed.len(), task);
+    }
+    for path in &param.only {
+        let forgotten = pkgdb::remove_path(&sysroot_dir, path).map_err(|e| anyhow!(e))?;
+        info!("Removed {} from the sysroot", path.display());
+        for package in forgotten {
+            info!(
+                "{} is no longer complete and will be reinstalled by the next `dadk user install`",
+                package.name_version()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 检查sysroot目录可以被删除：必须是当前工作目录下的子目录（解析符号链接之后），不能是`/`或者工作目录本身
+fn check_sysroot_dir(sysroot_dir: &Path, workdir: &Path) -> Result<()> {
+    let sysroot_dir = sysroot_dir
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", sysroot_dir.display(), e))?;
+    let workdir = workdir
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", workdir.display(), e))?;
+    if sysroot_dir.parent().is_none() {
+        return Err(anyhow!("Refusing to delete the root directory"));
+    }
     // 检查 sysroot_dir 是否是当前工作目录的子目录
-    if !sysroot_dir.starts_with(&ctx.workdir()) {
+    if sysroot_dir == workdir || !sysroot_dir.starts_with(&workdir) {
         return Err(anyhow!(
-            "Sysroot directory must be a subdirectory of the current working directory"
+            "Sysroot directory {} must be a subdirectory of the current working directory {}",
+            sysroot_dir.display(),
+            workdir.display()
         ));
     }
-
-    std::fs::remove_dir_all(sysroot_dir)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_sysroot_dir() {
+        let workdir = tempfile::tempdir().unwrap();
+        let sysroot = workdir.path().join("bin/sysroot");
+        std::fs::create_dir_all(&sysroot).unwrap();
+        assert!(check_sysroot_dir(&sysroot, workdir.path()).is_ok());
+        assert!(check_sysroot_dir(workdir.path(), workdir.path()).is_err());
+        assert!(check_sysroot_dir(Path::new("/"), workdir.path()).is_err());
+        assert!(check_sysroot_dir(&sysroot.join("../../.."), workdir.path()).is_err());
+
+        // 指向工作目录之外的符号链接
+        let outside = tempfile::tempdir().unwrap();
+        let link = workdir.path().join("link");
+        std::os::unix::fs::symlink(outside.path(), &link).unwrap();
+        assert!(check_sysroot_dir(&link, workdir.path()).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! # 非Linux主机上的磁盘镜像操作
+//!
+//! 创建、挂载磁盘镜像依赖loop设备、`mount`以及`mkfs`等Linux工具，在其他系统上只能构建、
+//! 打包用户程序。这里的函数与Linux上的`disk_img`模块同名，需要loop设备的操作直接返回错误。
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use dadk_config::rootfs::image_format::ImageFormat;
+
+use crate::context::DADKExecContext;
+
+fn unsupported(what: &str) -> anyhow::Error {
+    anyhow!(
+        "Cannot {} on {}: disk images are only supported on Linux hosts",
+        what,
+        std::env::consts::OS
+    )
+}
+
+pub(super) fn create(
+    _ctx: &DADKExecContext,
+    _skip_if_exists: bool,
+    _format: Option<ImageFormat>,
+) -> Result<()> {
+    Err(unsupported("create the disk image"))
+}
+
+pub(super) fn delete(_ctx: &DADKExecContext, _skip_if_not_exists: bool) -> Result<()> {
+    Err(unsupported("delete the disk image"))
+}
+
+pub fn mount(_ctx: &DADKExecContext, _idempotent: bool) -> Result<()> {
+    Err(unsupported("mount the disk image"))
+}
+
+pub fn umount(_ctx: &DADKExecContext, _idempotent: bool) -> Result<()> {
+    Err(unsupported("umount the disk image"))
+}
+
+pub(super) fn install_sysroot(_ctx: &DADKExecContext, _sysroot_dir: &Path) -> Result<()> {
+    Err(unsupported("install the sysroot into the disk image"))
+}
+
+pub(super) fn populate(_ctx: &DADKExecContext, _sysroot_dir: &Path) -> Result<()> {
+    Err(unsupported("populate the disk image"))
+}
+
+pub(super) fn shrink(_ctx: &DADKExecContext, _sparse: bool) -> Result<()> {
+    Err(unsupported("shrink the disk image"))
+}
+
+pub fn status(_ctx: &DADKExecContext, _json: bool) -> Result<()> {
+    Err(unsupported("show the disk image status"))
+}
+
+/// 没有loop设备，磁盘镜像不会被挂载
+pub fn mounted_source(_ctx: &DADKExecContext) -> Option<String> {
+    None
+}
+
+pub fn check_disk_image_exists(ctx: &DADKExecContext) -> Result<()> {
+    println!("{}", ctx.disk_image_path().exists() as u8);
+    Ok(())
+}
+
+pub fn show_mount_point(ctx: &DADKExecContext) -> Result<()> {
+    println!("{}", ctx.disk_mount_path().display());
+    Ok(())
+}
+
+pub fn show_loop_device(_ctx: &DADKExecContext) -> Result<()> {
+    Err(unsupported("show the loop device"))
+}
@@ -0,0 +1,332 @@
+//! # `dadk self-update`
+//!
+//! 从GitHub Release获取DADK的最新版本，下载与当前主机的target triple对应的二进制文件，
+//! 校验sha256之后替换正在运行的可执行文件。
+//!
+//! Release中的每个二进制文件都需要有一个同名的`.sha256`文件，例如：
+//!
+//! - `dadk-x86_64-unknown-linux-gnu`
+//! - `dadk-x86_64-unknown-linux-gnu.sha256`
+//!
+//! 替换前的可执行文件会备份为`<可执行文件>.bak`，可以通过`dadk self-update --rollback`恢复。
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_user::repository::sha256_file;
+use log::info;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use crate::{console::self_update::SelfUpdateCommand, context::DADKExecContext};
+
+/// GitHub Release API
+const RELEASES_API: &str = "https://api.github.com/repos/DragonOS-Community/DADK/releases";
+/// 当前主机的target triple，由build.rs设置
+const HOST_TRIPLE: &str = env!("DADK_HOST_TRIPLE");
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// 去掉tag开头的`v`之后的版本号
+    fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    /// 查找指定target triple的二进制文件以及它的sha256文件
+    fn host_assets(&self, triple: &str) -> Result<(&ReleaseAsset, &ReleaseAsset)> {
+        let name = format!("dadk-{}", triple);
+        let find = |name: &str| self.assets.iter().find(|a| a.name == name);
+        let binary = find(&name).ok_or_else(|| {
+            anyhow!(
+                "Release {} has no binary for {} (expected asset {})",
+                self.tag_name,
+                triple,
+                name
+            )
+        })?;
+        let checksum = find(&format!("{}.sha256", name)).ok_or_else(|| {
+            anyhow!(
+                "Release {} has no checksum for {} (expected asset {}.sha256)",
+                self.tag_name,
+                name,
+                name
+            )
+        })?;
+        Ok((binary, checksum))
+    }
+}
+
+pub(super) fn run(ctx: &DADKExecContext, args: &SelfUpdateCommand) -> Result<()> {
+    let exe = std::env::current_exe()
+        .and_then(|p| p.canonicalize())
+        .map_err(|e| anyhow!("Failed to locate the dadk executable: {}", e))?;
+    if args.rollback {
+        rollback(&exe)?;
+        println!(
+            "Restored {} from {}",
+            exe.display(),
+            backup_path(&exe).display()
+        );
+        return Ok(());
+    }
+    if ctx.offline() {
+        return Err(anyhow!("Can't check for updates in offline mode"));
+    }
+
+    let client = Client::builder()
+        .user_agent(concat!("dadk/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let release = fetch_release(&client, args.tag.as_deref())?;
+    let version = release.version();
+    // 指定了tag时允许降级
+    let up_to_date = match args.tag {
+        Some(_) => version == CURRENT_VERSION,
+        None => !is_newer(version, CURRENT_VERSION),
+    };
+    if up_to_date && !args.force {
+        println!("dadk {} is up to date", CURRENT_VERSION);
+        return Ok(());
+    }
+    if args.check {
+        println!(
+            "dadk {} is available (current: {})",
+            version, CURRENT_VERSION
+        );
+        return Ok(());
+    }
+
+    let (binary, checksum) = release.host_assets(HOST_TRIPLE)?;
+    let expected = parse_checksum(&download_text(&client, &checksum.browser_download_url)?)
+        .ok_or_else(|| anyhow!("Invalid checksum file {}", checksum.name))?;
+
+    // 下载到可执行文件所在的目录，保证之后可以通过重命名替换
+    let new_exe = exe.with_extension("new");
+    info!("downloading {}", binary.browser_download_url);
+    let r = download_file(&client, &binary.browser_download_url, &new_exe)
+        .and_then(|_| verify(&new_exe, &expected))
+        .and_then(|_| replace_executable(&exe, &new_exe));
+    if r.is_err() {
+        std::fs::remove_file(&new_exe).ok();
+    }
+    r?;
+    println!(
+        "Updated dadk {} -> {} (backup: {})",
+        CURRENT_VERSION,
+        version,
+        backup_path(&exe).display()
+    );
+    Ok(())
+}
+
+fn fetch_release(client: &Client, tag: Option<&str>) -> Result<Release> {
+    let url = match tag {
+        Some(tag) => format!("{}/tags/{}", RELEASES_API, tag),
+        None => format!("{}/latest", RELEASES_API),
+    };
+    client
+        .get(&url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| anyhow!("Failed to fetch release info from {}: {}", url, e))
+}
+
+fn download_text(client: &Client, url: &str) -> Result<String> {
+    client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .map_err(|e| anyhow!("Failed to download {}: {}", url, e))
+}
+
+fn download_file(client: &Client, url: &str, path: &Path) -> Result<()> {
+    let mut response = client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow!("Failed to download {}: {}", url, e))?;
+    let mut file = std::fs::File::create(path)
+        .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+    response
+        .copy_to(&mut file)
+        .map_err(|e| anyhow!("Failed to download {}: {}", url, e))?;
+    Ok(())
+}
+
+/// 解析sha256文件，兼容`sha256sum`的输出格式（`<sha256>  <文件名>`）
+fn parse_checksum(content: &str) -> Option<String> {
+    let hex = content.split_whitespace().next()?;
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then(|| hex.to_lowercase())
+}
+
+/// 校验下载的可执行文件的sha256，并确认它可以运行
+fn verify(new_exe: &Path, expected: &str) -> Result<()> {
+    let actual = sha256_file(new_exe).map_err(|e| anyhow!(e))?;
+    if actual != expected {
+        return Err(anyhow!(
+            "sha256 mismatch for the downloaded dadk: expected {}, got {}",
+            expected,
+            actual
+        ));
+    }
+    set_executable(new_exe)?;
+    let status = Command::new(new_exe)
+        .arg("--version")
+        .output()
+        .map_err(|e| anyhow!("Failed to run the downloaded dadk: {}", e))?
+        .status;
+    if !status.success() {
+        return Err(anyhow!(
+            "The downloaded dadk exited with {} when checking its version",
+            status
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .map_err(|e| anyhow!("Failed to set permissions of {}: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// 更新前的可执行文件的备份路径
+fn backup_path(exe: &Path) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    exe.with_file_name(name)
+}
+
+/// 把当前的可执行文件备份后，用新的可执行文件替换它。替换失败时恢复备份
+fn replace_executable(exe: &Path, new_exe: &Path) -> Result<()> {
+    let backup = backup_path(exe);
+    std::fs::rename(exe, &backup)
+        .map_err(|e| anyhow!("Failed to back up {}: {}", exe.display(), e))?;
+    if let Err(e) = std::fs::rename(new_exe, exe) {
+        std::fs::rename(&backup, exe).ok();
+        return Err(anyhow!("Failed to replace {}: {}", exe.display(), e));
+    }
+    Ok(())
+}
+
+/// 用备份恢复可执行文件
+fn rollback(exe: &Path) -> Result<()> {
+    let backup = backup_path(exe);
+    if !backup.is_file() {
+        return Err(anyhow!("No backup found at {}", backup.display()));
+    }
+    std::fs::rename(&backup, exe).map_err(|e| anyhow!("Failed to restore {}: {}", exe.display(), e))
+}
+
+/// 比较两个版本号（忽略预发布后缀），`version`比`current`新时返回true
+fn is_newer(version: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(version) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> ReleaseAsset {
+        ReleaseAsset {
+            name: name.to_string(),
+            browser_download_url: format!("https://example.com/{}", name),
+        }
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.1", "0.2.0"));
+        assert!(is_newer("v0.10.0", "0.9.3"));
+        assert!(is_newer("1.0.0", "0.99.99"));
+        assert!(!is_newer("0.2.0", "0.2.0"));
+        assert!(!is_newer("0.1.9", "0.2.0"));
+        assert!(!is_newer("0.2.0-rc.1", "0.2.0"));
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let hex = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+        assert_eq!(
+            parse_checksum(&format!("{}  dadk-x86_64-unknown-linux-gnu\n", hex)).unwrap(),
+            hex.to_lowercase()
+        );
+        assert_eq!(parse_checksum(hex).unwrap(), hex.to_lowercase());
+        assert!(parse_checksum("").is_none());
+        assert!(parse_checksum("not-a-checksum").is_none());
+    }
+
+    #[test]
+    fn test_release_host_assets() {
+        let release = Release {
+            tag_name: "v0.3.0".to_string(),
+            assets: vec![
+                asset("dadk-x86_64-unknown-linux-gnu"),
+                asset("dadk-x86_64-unknown-linux-gnu.sha256"),
+                asset("dadk-aarch64-unknown-linux-gnu"),
+            ],
+        };
+        assert_eq!(release.version(), "0.3.0");
+        let (binary, checksum) = release.host_assets("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(binary.name, "dadk-x86_64-unknown-linux-gnu");
+        assert_eq!(checksum.name, "dadk-x86_64-unknown-linux-gnu.sha256");
+        // 缺少sha256文件
+        assert!(release.host_assets("aarch64-unknown-linux-gnu").is_err());
+        assert!(release.host_assets("riscv64gc-unknown-linux-gnu").is_err());
+    }
+
+    #[test]
+    fn test_replace_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("dadk");
+        let new_exe = dir.path().join("dadk.new");
+        std::fs::write(&exe, "old").unwrap();
+        assert!(rollback(&exe).is_err());
+
+        // 新的可执行文件不存在时，恢复原来的可执行文件
+        assert!(replace_executable(&exe, &new_exe).is_err());
+        assert_eq!(std::fs::read_to_string(&exe).unwrap(), "old");
+
+        std::fs::write(&new_exe, "new").unwrap();
+        replace_executable(&exe, &new_exe).unwrap();
+        assert_eq!(std::fs::read_to_string(&exe).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(backup_path(&exe)).unwrap(), "old");
+        assert!(!new_exe.exists());
+
+        rollback(&exe).unwrap();
+        assert_eq!(std::fs::read_to_string(&exe).unwrap(), "old");
+        assert!(!backup_path(&exe).exists());
+    }
+}
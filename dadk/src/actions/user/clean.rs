@@ -0,0 +1,39 @@
+//! # `dadk user clean --dry-run`
+//!
+//! 不执行清理，只列出每个用户程序在指定的清理级别下将要执行的清理命令、删除的目录，
+//! 以及各个清理级别可以释放的空间，以免误删需要很长时间才能重新构建的缓存。
+
+use anyhow::{anyhow, Result};
+use dadk_user::{cache::CleanPlan, parser::Parser, status::find_task};
+
+use crate::{console::user::UserCleanCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserCleanCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    // 与实际清理一样，只包括当前目标架构的任务
+    let arch = ctx.target_arch();
+    let tasks: Vec<_> = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?
+        .into_iter()
+        .filter(|(_, task)| task.target_arch.contains(&arch))
+        .collect();
+    // 指定了`--task`时，只列出这个task
+    let tasks: Vec<_> = match &args.task {
+        Some(name) => {
+            let (_, task) = find_task(&tasks, name).map_err(|e| anyhow!(e))?;
+            vec![task.clone()]
+        }
+        None => tasks.into_iter().map(|(_, task)| task).collect(),
+    };
+
+    let plan = CleanPlan::new(&cache_root_dir, &tasks).map_err(|e| anyhow!(e))?;
+    print!("{}", plan.text(args.level.into()));
+    Ok(())
+}
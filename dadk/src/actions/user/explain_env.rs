@@ -0,0 +1,25 @@
+//! # `dadk user explain-env`
+//!
+//! 与`dadk user build`一样准备全局环境变量和任务的环境变量，输出任务构建时将要执行的命令、
+//! 工作目录，以及与dadk进程不同的环境变量，但不执行构建。
+
+use anyhow::Result;
+use dadk_user::BuildSession;
+
+use super::ArchTarget;
+use crate::{
+    console::user::{UserBuildCommand, UserCommand, UserExplainEnvCommand},
+    context::DADKExecContext,
+};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserExplainEnvCommand) -> Result<()> {
+    let target = ArchTarget::from_ctx(ctx)?;
+    let context = target.execute_context(&UserCommand::Build(UserBuildCommand::default()))?;
+    let plan = BuildSession::new(context)?.explain_env(&args.task)?;
+    if args.json {
+        println!("{}", plan.to_json());
+    } else {
+        print!("{}", plan.text());
+    }
+    Ok(())
+}
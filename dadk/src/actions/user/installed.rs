@@ -0,0 +1,91 @@
+//! # `dadk user installed`、`dadk user owns`
+//!
+//! 查询sysroot中的软件包数据库（`var/lib/dadk/installed.toml`）：
+//! 列出已安装的用户程序，或者查询某个文件是由哪个用户程序安装的。
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use dadk_user::pkgdb::PackageDatabase;
+
+use crate::{
+    console::user::{UserInstalledCommand, UserOwnsCommand},
+    context::DADKExecContext,
+};
+
+pub(super) fn run_installed(ctx: &DADKExecContext, args: &UserInstalledCommand) -> Result<()> {
+    let db = PackageDatabase::load(&ctx.sysroot_dir()?).map_err(|e| anyhow!(e))?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(db.packages())?);
+    } else if args.files {
+        for package in db.packages() {
+            println!("{}:", package.name_version());
+            for file in &package.files {
+                println!("  {}", file);
+            }
+        }
+    } else {
+        print!("{}", db.table());
+    }
+    Ok(())
+}
+
+pub(super) fn run_owns(ctx: &DADKExecContext, args: &UserOwnsCommand) -> Result<()> {
+    let sysroot = ctx.sysroot_dir()?;
+    let db = PackageDatabase::load(&sysroot).map_err(|e| anyhow!(e))?;
+    let path = sysroot_path(&sysroot, &args.path);
+    let owners = db.owners(&path.to_string_lossy());
+    if owners.is_empty() {
+        return Err(anyhow!(
+            "{} is not owned by any installed task",
+            path.display()
+        ));
+    }
+    for owner in owners {
+        println!("{} is owned by {}", path.display(), owner.name_version());
+    }
+    Ok(())
+}
+
+/// 把sysroot目录下的文件路径转换为DragonOS中的路径，其他路径保持不变
+fn sysroot_path(sysroot: &Path, path: &Path) -> PathBuf {
+    // 只解析父目录中的符号链接，文件本身可能就是符号链接
+    let abs = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if path.symlink_metadata().is_ok() => {
+            parent.canonicalize().map(|p| p.join(name))
+        }
+        _ => return path.to_path_buf(),
+    };
+    if let (Ok(sysroot), Ok(abs)) = (sysroot.canonicalize(), abs) {
+        if let Ok(rel) = abs.strip_prefix(&sysroot) {
+            return Path::new("/").join(rel);
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sysroot_path() {
+        let sysroot = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(sysroot.path().join("bin")).unwrap();
+        std::fs::write(sysroot.path().join("bin/hello"), "").unwrap();
+        std::os::unix::fs::symlink("hello", sysroot.path().join("bin/hi")).unwrap();
+
+        assert_eq!(
+            sysroot_path(sysroot.path(), &sysroot.path().join("bin/hello")),
+            PathBuf::from("/bin/hello")
+        );
+        assert_eq!(
+            sysroot_path(sysroot.path(), &sysroot.path().join("bin/hi")),
+            PathBuf::from("/bin/hi")
+        );
+        assert_eq!(
+            sysroot_path(sysroot.path(), Path::new("/bin/hello")),
+            PathBuf::from("/bin/hello")
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! # `dadk user list`
+//!
+//! 列出所有用户程序：名称、版本、目标架构、源文件类型，以及任务日志中记录的
+//! 最近一次构建、安装的状态和时间。构建之后还没有安装的任务会被标记出来。
+
+use anyhow::Result;
+use dadk_user::{list::TaskList, parser::Parser};
+
+use crate::{console::user::UserListCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserListCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let tasks = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?;
+
+    let list = TaskList::collect(&cache_root_dir, &tasks);
+    if args.json {
+        println!("{}", list.to_json());
+    } else {
+        print!("{}", list.table());
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::{anyhow, Result};
+use dadk_config::manifest::HookPoint;
+use dadk_user::{dadk_user_main, interrupt, BuildSession, DadkUserError, ErrorCode};
+
+use super::{hooks, report_error, rootfs};
+use crate::{
+    console::user::UserCommand,
+    context::{DADKExecContext, IMAGE_INSTALL},
+};
+use multi_arch::ArchTarget;
+
+mod clean;
+mod explain_env;
+mod installed;
+mod list;
+mod multi_arch;
+mod new_config;
+mod outdated;
+mod package;
+mod rdeps;
+mod stats;
+mod status;
+mod test;
+mod tui;
+mod watch;
+
+pub(super) fn run(ctx: &DADKExecContext, cmd: &UserCommand) -> Result<()> {
+    match cmd {
+        UserCommand::Watch(args) => return watch::run(ctx, args),
+        UserCommand::New(args) => return new_config::run(ctx, args),
+        UserCommand::Stats(args) => return stats::run(ctx, args),
+        UserCommand::List(args) => return list::run(ctx, args),
+        UserCommand::Status(args) => return status::run(ctx, args),
+        UserCommand::ExplainEnv(args) => return explain_env::run(ctx, args),
+        UserCommand::Rdeps(args) => return rdeps::run(ctx, args),
+        UserCommand::Installed(args) => return installed::run_installed(ctx, args),
+        UserCommand::Owns(args) => return installed::run_owns(ctx, args),
+        UserCommand::Package(args) => return package::run(ctx, args),
+        UserCommand::Test(args) => return test::run(ctx, args),
+        UserCommand::Outdated(args) => return outdated::run(ctx, args),
+        UserCommand::Clean(args) if args.dry_run => return clean::run(ctx, args),
+        _ => {}
+    }
+
+    let (pre, post) = match cmd {
+        UserCommand::Build(_) => (HookPoint::PreBuild, HookPoint::PostBuild),
+        UserCommand::Install(_) => (HookPoint::PreInstall, HookPoint::PostInstall),
+        UserCommand::Clean(_) => (HookPoint::PreClean, HookPoint::PostClean),
+        _ => return run_build_session(ctx, cmd),
+    };
+    hooks::with_hooks(ctx, pre, post, || run_build_session(ctx, cmd))
+}
+
+/// 执行构建或者安装（包括对应的钩子）。与`dadk user build/install`不同，失败时返回错误而不是退出进程，
+/// 供`dadk ci`继续输出各阶段的耗时
+pub(super) fn run_stage(ctx: &DADKExecContext, cmd: &UserCommand) -> Result<()> {
+    let (pre, post) = match cmd {
+        UserCommand::Install(_) => (HookPoint::PreInstall, HookPoint::PostInstall),
+        _ => (HookPoint::PreBuild, HookPoint::PostBuild),
+    };
+    hooks::with_hooks(ctx, pre, post, || {
+        let target = ArchTarget::from_ctx(ctx)?;
+        dadk_user_main(target.execute_context(cmd)?)?;
+        Ok(())
+    })
+}
+
+/// 创建执行构建或者安装的会话（不执行钩子），供`dadk daemon`使用已经解析好的任务列表执行
+pub(super) fn build_session(ctx: &DADKExecContext, cmd: &UserCommand) -> Result<BuildSession> {
+    let target = ArchTarget::from_ctx(ctx)?;
+    Ok(BuildSession::new(target.execute_context(cmd)?)?)
+}
+
+/// 执行构建、安装或者清理
+fn run_build_session(ctx: &DADKExecContext, cmd: &UserCommand) -> Result<()> {
+    if let UserCommand::Build(args) = cmd {
+        if args.multi_arch() {
+            return multi_arch::run(ctx, args);
+        }
+        if args.tui {
+            return tui::run(ctx, args);
+        }
+    }
+
+    if matches!(cmd, UserCommand::Install(args) if args.into_image) {
+        return install_into_image(ctx, cmd);
+    }
+
+    let target = ArchTarget::from_ctx(ctx)?;
+    let cmd = &confirm_create_sysroot(&target, cmd)?;
+    let context = target.execute_context(cmd)?;
+    if let Err(e) = dadk_user_main(context) {
+        report_error(ctx, &e);
+        std::process::exit(exit_code(&e));
+    }
+    Ok(())
+}
+
+/// 挂载磁盘镜像，直接安装到磁盘镜像中（不经过sysroot目录），然后卸载。
+/// 安装失败时同样会卸载磁盘镜像
+fn install_into_image(ctx: &DADKExecContext, cmd: &UserCommand) -> Result<()> {
+    if !ctx.manifest().experimental_enabled(IMAGE_INSTALL) {
+        return Err(anyhow!(
+            "--into-image is experimental, add \"{}\" to `experimental` in the manifest to enable it",
+            IMAGE_INSTALL
+        ));
+    }
+    let target = ArchTarget::from_ctx(ctx)?;
+    let r = rootfs::with_mounted_image(ctx, |mount_path| {
+        let target = target.with_sysroot_dir(mount_path.to_path_buf());
+        Ok(dadk_user_main(target.execute_context(cmd)?))
+    })?;
+    if let Err(e) = r {
+        report_error(ctx, &e);
+        std::process::exit(exit_code(&e));
+    }
+    Ok(())
+}
+
+/// 安装时sysroot目录不存在，并且没有指定`--create-sysroot`：在终端中询问是否创建。
+/// 不在终端中执行或者用户拒绝时不创建，由dadk-user报告错误
+fn confirm_create_sysroot(target: &ArchTarget, cmd: &UserCommand) -> Result<UserCommand> {
+    let mut cmd = cmd.clone();
+    if let UserCommand::Install(args) = &mut cmd {
+        let sysroot_dir = target.sysroot_dir();
+        if !args.create_sysroot && !sysroot_dir.exists() && std::io::stdin().is_terminal() {
+            let prompt = format!(
+                "DragonOS sysroot dir {} does not exist, create it?",
+                sysroot_dir.display()
+            );
+            args.create_sysroot = confirm(
+                &prompt,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stderr(),
+            )?;
+        }
+    }
+    Ok(cmd)
+}
+
+fn confirm(prompt: &str, input: &mut impl BufRead, out: &mut impl Write) -> Result<bool> {
+    write!(out, "{} [y/N] ", prompt)?;
+    out.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(matches!(
+        line.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// 执行失败时的退出码
+fn exit_code(e: &DadkUserError) -> i32 {
+    match e.code() {
+        ErrorCode::Interrupted => interrupt::exit_code(),
+        _ => 1,
+    }
+}
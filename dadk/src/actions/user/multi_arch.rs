@@ -0,0 +1,551 @@
+//! # 多架构构建
+//!
+//! `dadk user build --arch x86_64,riscv64`（或`--all-arches`）为每个架构分别执行一次构建，
+//! 每个架构使用独立的缓存根目录和sysroot：
+//!
+//! - 与manifest中`[metadata]`相同的架构：使用manifest中的目录
+//! - 存在与架构同名的profile，或者arch为该架构的profile：使用profile中的目录
+//! - 其他架构：缓存根目录为`<cache-root-dir>/<arch>`，sysroot为`<sysroot-dir>-<arch>`（不存在时自动创建）
+//!
+//! 指定`--parallel`时，各个架构同时构建。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    app_blocklist::AppBlocklistConfigFile,
+    common::{target_arch::TargetArch, task::BuildShell},
+    manifest::{
+        CompilerCacheConfig, ContainerConfig, DadkManifestFile, EnvConfig, KernelConfig,
+        MirrorConfig,
+    },
+    rootfs::RootFSConfigFile,
+};
+use dadk_user::{
+    context::DadkUserExecuteContext, dadk_user_main, executor::source::GitTimeouts, interrupt,
+    lock::LockTimeout, metrics::MetricsFormat, DadkUserError, ErrorCode,
+};
+use log::{error, info};
+
+use crate::{
+    console::{
+        user::{UserBuildCommand, UserCommand},
+        ErrorFormat,
+    },
+    context::DADKExecContext,
+    utils::check_dir_exists,
+};
+
+/// 一个架构的构建目标
+#[derive(Debug, Clone)]
+pub(super) struct ArchTarget {
+    arch: TargetArch,
+    sysroot_dir: PathBuf,
+    cache_root_dir: PathBuf,
+    config_dir: PathBuf,
+    overlay_config_dirs: Vec<PathBuf>,
+    container: Option<ContainerConfig>,
+    retries: u32,
+    hardlink_prebuilt: bool,
+    app_blocklist: AppBlocklistConfigFile,
+    skip_invalid_configs: bool,
+    offline: bool,
+    variables: BTreeMap<String, String>,
+    default_versions: BTreeMap<String, String>,
+    package_repository: Option<String>,
+    rust_toolchain: Option<String>,
+    compiler_cache: CompilerCacheConfig,
+    env_config: EnvConfig,
+    kernel: Option<KernelConfig>,
+    git_timeouts: GitTimeouts,
+    mirrors: MirrorConfig,
+    shell: BuildShell,
+    shell_strict: bool,
+    install_allowed_paths: Vec<PathBuf>,
+    lock_timeout: LockTimeout,
+    thread_num: usize,
+    verbose: bool,
+    allow_env_collisions: bool,
+    metrics_file: Option<PathBuf>,
+    metrics_format: MetricsFormat,
+}
+
+impl ArchTarget {
+    /// 当前manifest（已应用`--profile`）对应的构建目标
+    pub fn from_ctx(ctx: &DADKExecContext) -> Result<Self> {
+        let mut target = Self::from_manifest(ctx.manifest())?;
+        target.apply_cli_overrides(ctx)?;
+        Ok(target)
+    }
+
+    /// 应用命令行参数中对所有架构都相同的设置
+    fn apply_cli_overrides(&mut self, ctx: &DADKExecContext) -> Result<()> {
+        self.skip_invalid_configs |= ctx.command.skip_invalid_configs;
+        self.lock_timeout = ctx.lock_timeout();
+        self.thread_num = ctx.thread_num();
+        self.verbose = ctx.verbose();
+        self.allow_env_collisions = ctx.allow_env_collisions();
+        self.metrics_file = ctx.metrics_file();
+        self.metrics_format = ctx.metrics_format();
+        self.offline = ctx.offline();
+        for dir in &ctx.command.config_dirs {
+            let dir = check_dir_exists(dir)
+                .map_err(|e| anyhow!("Failed to get user config dir: {}", e))?;
+            self.overlay_config_dirs.push(dir.clone());
+        }
+        Ok(())
+    }
+
+    fn from_manifest(manifest: &DadkManifestFile) -> Result<Self> {
+        let metadata = &manifest.metadata;
+        // 只有安装需要sysroot目录，由dadk-user按照要执行的操作检查
+        let sysroot_dir = &metadata.sysroot_dir;
+        let cache_root_dir = check_dir_exists(&metadata.cache_root_dir)
+            .map_err(|e| anyhow!("Failed to get cache root dir: {}", e))?;
+        #[allow(deprecated)]
+        let config_dirs = metadata
+            .user_config_dirs
+            .iter()
+            .map(|dir| {
+                check_dir_exists(dir)
+                    .cloned()
+                    .map_err(|e| anyhow!("Failed to get user config dir: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let app_blocklist = match &metadata.app_blocklist_config {
+            Some(path) => AppBlocklistConfigFile::load(path)
+                .map_err(|e| anyhow!("Failed to load app blocklist {}: {}", path.display(), e))?,
+            None => AppBlocklistConfigFile::default(),
+        };
+        // 只构建用户程序时可以没有rootfs配置
+        let install_allowed_paths = if metadata.rootfs_config.is_file() {
+            RootFSConfigFile::load(&metadata.rootfs_config)
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to load rootfs config {}: {}",
+                        metadata.rootfs_config.display(),
+                        e
+                    )
+                })?
+                .install
+                .allowed_paths
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            arch: metadata.arch,
+            sysroot_dir: sysroot_dir.clone(),
+            cache_root_dir: cache_root_dir.clone(),
+            config_dir: config_dirs[0].clone(),
+            overlay_config_dirs: config_dirs[1..].to_vec(),
+            container: manifest.container.clone(),
+            retries: metadata.retries,
+            hardlink_prebuilt: metadata.hardlink_prebuilt,
+            app_blocklist,
+            skip_invalid_configs: metadata.skip_invalid_configs,
+            offline: false,
+            variables: metadata.variables.clone(),
+            default_versions: metadata.default_versions.clone(),
+            package_repository: metadata.package_repository.clone(),
+            rust_toolchain: metadata.rust_toolchain.clone(),
+            compiler_cache: metadata.compiler_cache.clone(),
+            env_config: manifest.env.clone(),
+            kernel: manifest.kernel.clone(),
+            git_timeouts: GitTimeouts {
+                timeout: manifest.git.timeout_duration()?,
+                stall: manifest.git.stall_timeout_duration()?,
+            },
+            mirrors: manifest.mirrors.clone(),
+            shell: metadata.shell,
+            shell_strict: metadata.shell_strict,
+            install_allowed_paths,
+            lock_timeout: LockTimeout::default(),
+            thread_num: 1,
+            verbose: false,
+            allow_env_collisions: false,
+            metrics_file: None,
+            metrics_format: MetricsFormat::default(),
+        })
+    }
+
+    /// 没有profile的架构：在基础目标的目录旁边创建该架构专用的目录
+    fn derived(base: &ArchTarget, arch: TargetArch) -> Result<Self> {
+        let arch_name: &str = arch.into();
+        let cache_root_dir = base.cache_root_dir.join(arch_name);
+        let sysroot_dir = PathBuf::from(format!("{}-{}", base.sysroot_dir.display(), arch_name));
+        for dir in [&cache_root_dir, &sysroot_dir] {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        // 除了目录以外（包括命令行参数中的设置）都与基础目标相同
+        Ok(Self {
+            arch,
+            sysroot_dir,
+            cache_root_dir,
+            ..base.clone()
+        })
+    }
+
+    pub fn sysroot_dir(&self) -> &PathBuf {
+        &self.sysroot_dir
+    }
+
+    /// 安装到其他目录（例如挂载的磁盘镜像）而不是manifest中的sysroot目录
+    pub fn with_sysroot_dir(mut self, sysroot_dir: PathBuf) -> Self {
+        self.sysroot_dir = sysroot_dir;
+        self
+    }
+
+    pub fn execute_context(&self, cmd: &UserCommand) -> Result<DadkUserExecuteContext> {
+        let dadk_user_action = dadk_user::context::Action::try_from(cmd.clone())?;
+        let (rebuild_tasks, affected_by, resume, capture_output) = match cmd {
+            UserCommand::Build(args) => (
+                args.rebuild.clone(),
+                args.affected_by.clone(),
+                args.resume,
+                args.tui,
+            ),
+            _ => (Vec::new(), Vec::new(), false, false),
+        };
+        let selected_tasks = match cmd {
+            UserCommand::Install(args) => args.task.clone(),
+            _ => Vec::new(),
+        };
+        let build_report = match cmd {
+            UserCommand::Build(args) => args.report.clone(),
+            _ => None,
+        };
+        let (force, no_build_cache, refetch) = match cmd {
+            UserCommand::Build(args) => (args.force, args.no_build_cache, args.refetch),
+            UserCommand::Install(args) => (args.force, false, false),
+            _ => (false, false, false),
+        };
+        let create_sysroot = matches!(cmd, UserCommand::Install(args) if args.create_sysroot);
+
+        Ok(dadk_user::context::DadkUserExecuteContextBuilder::default()
+            .sysroot_dir(self.sysroot_dir.clone())
+            .config_dir(self.config_dir.clone())
+            .overlay_config_dirs(self.overlay_config_dirs.clone())
+            .action(dadk_user_action)
+            .thread_num(self.thread_num)
+            .cache_dir(self.cache_root_dir.clone())
+            .target_arch(self.arch)
+            .rebuild_tasks(rebuild_tasks)
+            .selected_tasks(selected_tasks)
+            .affected_by(affected_by)
+            .force(force)
+            .no_build_cache(no_build_cache)
+            .refetch(refetch)
+            .container(self.container.clone())
+            .resume(resume)
+            .retries(self.retries)
+            .hardlink_prebuilt(self.hardlink_prebuilt)
+            .app_blocklist(self.app_blocklist.clone())
+            .skip_invalid_configs(self.skip_invalid_configs)
+            .offline(self.offline)
+            .variables(self.variables.clone())
+            .default_versions(self.default_versions.clone())
+            .package_repository(self.package_repository.clone())
+            .rust_toolchain(self.rust_toolchain.clone())
+            .compiler_cache(self.compiler_cache.clone())
+            .env_config(self.env_config.clone())
+            .kernel(self.kernel.clone())
+            .git_timeouts(self.git_timeouts)
+            .mirrors(self.mirrors.clone())
+            .shell(self.shell)
+            .shell_strict(self.shell_strict)
+            .install_allowed_paths(self.install_allowed_paths.clone())
+            .capture_output(capture_output)
+            .verbose(self.verbose)
+            .allow_env_collisions(self.allow_env_collisions)
+            .metrics_file(self.metrics_file.clone())
+            .metrics_format(self.metrics_format)
+            .build_report(build_report)
+            .create_sysroot(create_sysroot)
+            .lock_timeout(self.lock_timeout)
+            .build()
+            .expect("Failed to build execute context"))
+    }
+}
+
+/// 确定要构建的架构以及各个架构的目录
+fn resolve_targets(ctx: &DADKExecContext, args: &UserBuildCommand) -> Result<Vec<ArchTarget>> {
+    let base = ArchTarget::from_ctx(ctx)?;
+    let mut profiles = Vec::new();
+    for name in &ctx.manifest().profiles {
+        profiles.push((name.as_str(), ctx.load_manifest_with_profile(name)?));
+    }
+
+    let mut arches = Vec::new();
+    if args.all_arches {
+        arches.push(base.arch);
+        arches.extend(profiles.iter().map(|(_, m)| m.metadata.arch));
+    } else {
+        arches.extend(args.arch.iter().copied());
+    }
+    let mut seen = BTreeSet::new();
+    arches.retain(|arch| seen.insert(*arch));
+
+    let mut targets = Vec::new();
+    for arch in arches {
+        let arch_name: &str = arch.into();
+        let profile = profiles
+            .iter()
+            .filter(|(_, m)| m.metadata.arch == arch)
+            .min_by_key(|(name, _)| *name != arch_name);
+        let target = if arch == base.arch {
+            base.clone()
+        } else if let Some((_, manifest)) = profile {
+            let mut target = ArchTarget::from_manifest(manifest)?;
+            target.apply_cli_overrides(ctx)?;
+            target
+        } else {
+            ArchTarget::derived(&base, arch)?
+        };
+        targets.push(target);
+    }
+
+    check_distinct_dirs(&targets)?;
+    // 同时构建多个架构时，每个架构写入各自的指标文件（`dadk.prom` -> `dadk-x86_64.prom`）
+    if targets.len() > 1 {
+        for target in &mut targets {
+            target.metrics_file = target
+                .metrics_file
+                .as_ref()
+                .map(|path| arch_file(path, target.arch));
+        }
+    }
+    Ok(targets)
+}
+
+/// 在文件名中加上架构，例如`dadk.prom` -> `dadk-x86_64.prom`
+fn arch_file(path: &Path, arch: TargetArch) -> PathBuf {
+    let arch: &str = arch.into();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, arch, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, arch),
+    };
+    path.with_file_name(name)
+}
+
+/// 不同架构不能共用缓存根目录或者sysroot
+fn check_distinct_dirs(targets: &[ArchTarget]) -> Result<()> {
+    for (i, a) in targets.iter().enumerate() {
+        for b in &targets[i + 1..] {
+            let (a_arch, b_arch): (&str, &str) = (a.arch.into(), b.arch.into());
+            if a.cache_root_dir == b.cache_root_dir {
+                return Err(anyhow!(
+                    "{} and {} share the same cache root dir {}",
+                    a_arch,
+                    b_arch,
+                    a.cache_root_dir.display()
+                ));
+            }
+            if a.sysroot_dir == b.sysroot_dir {
+                return Err(anyhow!(
+                    "{} and {} share the same sysroot dir {}",
+                    a_arch,
+                    b_arch,
+                    a.sysroot_dir.display()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserBuildCommand) -> Result<()> {
+    let targets = resolve_targets(ctx, args)?;
+    let names: Vec<&str> = targets.iter().map(|t| t.arch.into()).collect();
+    info!("Building user programs for: {}", names.join(", "));
+
+    let build = |target: &ArchTarget| {
+        let arch: &str = target.arch.into();
+        // 同时构建多个架构时，每个架构写入各自的构建报告
+        let mut args = args.clone();
+        if targets.len() > 1 {
+            args.report = args.report.map(|path| arch_file(&path, target.arch));
+        }
+        let cmd = UserCommand::Build(args);
+        info!(
+            "[{}] sysroot: {}, cache root: {}",
+            arch,
+            target.sysroot_dir.display(),
+            target.cache_root_dir.display()
+        );
+        let context = target
+            .execute_context(&cmd)
+            .map_err(|e| DadkUserError::new(ErrorCode::InvalidContext, e.to_string()))?;
+        dadk_user_main(context)
+    };
+
+    let results: Vec<Result<(), DadkUserError>> = if args.parallel {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = targets.iter().map(|t| s.spawn(|| build(t))).collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Build thread panicked"))
+                .collect()
+        })
+    } else {
+        let mut results = Vec::new();
+        for target in &targets {
+            results.push(build(target));
+            if interrupt::signal_received() {
+                break;
+            }
+        }
+        results
+    };
+
+    let mut exit_code = None;
+    let mut failed = Vec::new();
+    for (target, result) in targets.iter().zip(&results) {
+        let arch: &str = target.arch.into();
+        match result {
+            Ok(()) => info!("[{}] build succeeded", arch),
+            Err(e) => {
+                error!("[{}] build failed: {}", arch, e);
+                exit_code.get_or_insert(super::exit_code(e));
+                failed.push((arch, e.clone()));
+            }
+        }
+    }
+    for target in &targets[results.len()..] {
+        let arch: &str = target.arch.into();
+        error!("[{}] build skipped", arch);
+    }
+    if let Some(code) = exit_code {
+        // 每个架构的错误已经输出到日志中，JSON格式时再汇总输出一次
+        if ctx.error_format() == ErrorFormat::Json {
+            let archs: Vec<&str> = failed.iter().map(|(arch, _)| *arch).collect();
+            let errors = failed.into_iter().map(|(_, e)| e).collect();
+            let e =
+                DadkUserError::multiple(format!("Build failed for: {}", archs.join(", ")), errors);
+            crate::actions::report_error(ctx, &e);
+        }
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(arch: TargetArch, sysroot: &str, cache: &str) -> ArchTarget {
+        ArchTarget {
+            arch,
+            sysroot_dir: PathBuf::from(sysroot),
+            cache_root_dir: PathBuf::from(cache),
+            config_dir: PathBuf::from("user/apps/dadk/config"),
+            overlay_config_dirs: Vec::new(),
+            container: None,
+            retries: 0,
+            hardlink_prebuilt: false,
+            app_blocklist: AppBlocklistConfigFile::default(),
+            skip_invalid_configs: false,
+            offline: false,
+            variables: BTreeMap::new(),
+            default_versions: BTreeMap::new(),
+            package_repository: None,
+            rust_toolchain: None,
+            compiler_cache: CompilerCacheConfig::default(),
+            env_config: EnvConfig::default(),
+            kernel: None,
+            git_timeouts: GitTimeouts::default(),
+            mirrors: MirrorConfig::default(),
+            shell: BuildShell::default(),
+            shell_strict: false,
+            install_allowed_paths: Vec::new(),
+            lock_timeout: LockTimeout::default(),
+            thread_num: 1,
+            verbose: false,
+            allow_env_collisions: false,
+            metrics_file: None,
+            metrics_format: MetricsFormat::default(),
+        }
+    }
+
+    #[test]
+    fn test_derived_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = target(
+            TargetArch::X86_64,
+            dir.path().join("sysroot").to_str().unwrap(),
+            dir.path().join("dadk_cache").to_str().unwrap(),
+        );
+        let riscv = ArchTarget::derived(&base, TargetArch::RiscV64).unwrap();
+        assert_eq!(riscv.arch, TargetArch::RiscV64);
+        assert_eq!(riscv.sysroot_dir, dir.path().join("sysroot-riscv64"));
+        assert_eq!(riscv.cache_root_dir, dir.path().join("dadk_cache/riscv64"));
+        assert!(riscv.sysroot_dir.is_dir());
+        assert!(riscv.cache_root_dir.is_dir());
+        assert_eq!(riscv.config_dir, base.config_dir);
+        check_distinct_dirs(&[base, riscv]).unwrap();
+    }
+
+    /// 从profile得到的目标同样应用命令行参数中的设置
+    #[test]
+    fn test_apply_cli_overrides() {
+        use clap::Parser;
+
+        use crate::{console::CommandLineArgs, context::DADKExecContextBuilder};
+
+        let dir = tempfile::tempdir().unwrap();
+        let command = CommandLineArgs::parse_from([
+            "dadk",
+            "--skip-invalid-configs",
+            "--offline",
+            "--thread",
+            "4",
+            "-c",
+            dir.path().to_str().unwrap(),
+            "user",
+            "build",
+        ]);
+        let ctx = DADKExecContextBuilder::default()
+            .command(command)
+            .manifest(None)
+            .rootfs(Default::default())
+            .build()
+            .unwrap();
+        let mut target = target(TargetArch::RiscV64, "bin/sysroot", "bin/dadk_cache");
+        target.apply_cli_overrides(&ctx).unwrap();
+        assert!(target.skip_invalid_configs);
+        assert!(target.offline);
+        assert_eq!(target.thread_num, 4);
+        assert_eq!(target.overlay_config_dirs, [dir.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn test_check_distinct_dirs() {
+        let x86 = target(TargetArch::X86_64, "bin/sysroot", "bin/dadk_cache");
+        let riscv = target(TargetArch::RiscV64, "bin/sysroot", "bin/dadk_cache_riscv64");
+        let err = check_distinct_dirs(&[x86.clone(), riscv]).unwrap_err();
+        assert!(err.to_string().contains("same sysroot dir"));
+
+        let riscv = target(TargetArch::RiscV64, "bin/sysroot-riscv64", "bin/dadk_cache");
+        let err = check_distinct_dirs(&[x86, riscv]).unwrap_err();
+        assert!(err.to_string().contains("same cache root dir"));
+    }
+
+    #[test]
+    fn test_arch_file() {
+        assert_eq!(
+            arch_file(
+                Path::new("/var/lib/node_exporter/dadk.prom"),
+                TargetArch::RiscV64
+            ),
+            PathBuf::from("/var/lib/node_exporter/dadk-riscv64.prom")
+        );
+        assert_eq!(
+            arch_file(Path::new("metrics"), TargetArch::X86_64),
+            PathBuf::from("metrics-x86_64")
+        );
+    }
+}
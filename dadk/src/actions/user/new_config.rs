@@ -0,0 +1,411 @@
+//! # `dadk user new`
+//!
+//! 生成新的用户程序配置文件（TOML格式）。
+//!
+//! - 默认以交互式向导的方式，逐项询问配置内容
+//! - 指定`--from-template`时，以已有的配置文件为模板，非交互式地生成
+//!
+//! 写入文件之前，会使用dadk-config对生成的配置进行校验。
+
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use dadk_config::{
+    common::{
+        target_arch::TargetArch,
+        task::{
+            BuildConfig, CleanConfig, Dependency, InstallConfig, Source, TaskPriority, TaskSource,
+            TaskSourceType,
+        },
+    },
+    user::UserConfigFile,
+};
+use log::info;
+
+use crate::{actions::prompt::Prompter, console::user::UserNewCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserNewCommand) -> Result<()> {
+    let config = if let Some(template) = &args.from_template {
+        from_template(template, args)?
+    } else {
+        let stdin = std::io::stdin();
+        let mut prompter = Prompter::new(stdin.lock(), std::io::stdout());
+        wizard(&mut prompter, args, ctx.target_arch())?
+    };
+
+    let content = validated_toml(&config)?;
+
+    let output = match &args.output {
+        Some(p) => p.clone(),
+        None => {
+            #[allow(deprecated)]
+            let config_dir = ctx.user_config_dir()?;
+            config_dir.join(format!("{}.toml", config.name))
+        }
+    };
+    if output.exists() {
+        return Err(anyhow!("File '{}' already exists", output.display()));
+    }
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&output, content)?;
+    info!("User config file created: {}", output.display());
+    Ok(())
+}
+
+/// 校验配置，并返回序列化后的TOML内容
+///
+/// 序列化结果会被重新解析一次，以确保dadk能够读取生成的文件
+fn validated_toml(config: &UserConfigFile) -> Result<String> {
+    config
+        .validate()
+        .map_err(|e| anyhow!("Invalid user config: {}", e))?;
+    let content = config.to_toml_string()?;
+    let reparsed = UserConfigFile::load_from_str(&content)
+        .map_err(|e| anyhow!("Generated config can not be parsed: {}", e))?;
+    if &reparsed != config {
+        return Err(anyhow!("Generated config does not match the input"));
+    }
+    Ok(content)
+}
+
+/// 以已有的配置文件为模板生成配置，命令行中指定的字段会覆盖模板中的值
+fn from_template(template: &PathBuf, args: &UserNewCommand) -> Result<UserConfigFile> {
+    let mut config = UserConfigFile::load(template)
+        .map_err(|e| anyhow!("Failed to load template '{}': {}", template.display(), e))?;
+    if let Some(name) = &args.name {
+        config.name.clone_from(name);
+    }
+    if let Some(version) = &args.version {
+        config.version.clone_from(version);
+    }
+    Ok(config)
+}
+
+/// 交互式向导
+fn wizard<R: BufRead, W: Write>(
+    p: &mut Prompter<R, W>,
+    args: &UserNewCommand,
+    default_arch: TargetArch,
+) -> Result<UserConfigFile> {
+    let name = p.ask_required("Name", args.name.as_deref())?;
+    let version = p.ask_required("Version", Some(args.version.as_deref().unwrap_or("0.1.0")))?;
+    let description = p.ask("Description", None)?;
+
+    let source_type = match p
+        .ask_choice(
+            "Task type",
+            &[
+                "build-from-source",
+                "install-from-prebuilt",
+                "cargo",
+                "cmake",
+                "autotools",
+                "package",
+                "kernel-module",
+            ],
+            "build-from-source",
+        )?
+        .as_str()
+    {
+        "build-from-source" => TaskSourceType::BuildFromSource,
+        "cargo" => TaskSourceType::Cargo,
+        "cmake" => TaskSourceType::Cmake,
+        "autotools" => TaskSourceType::Autotools,
+        "package" => TaskSourceType::Package,
+        "kernel-module" => TaskSourceType::KernelModule,
+        _ => TaskSourceType::InstallFromPrebuilt,
+    };
+
+    let source = match source_type {
+        TaskSourceType::InstallFromPrebuilt | TaskSourceType::Package => {
+            p.ask_choice("Source", &["local", "archive", "repository"], "archive")?
+        }
+        _ => p.ask_choice("Source", &["git", "local", "archive"], "git")?,
+    };
+    let source = match source.as_str() {
+        "git" => Source::Git,
+        "local" => Source::Local,
+        "repository" => Source::Repository,
+        _ => Source::Archive,
+    };
+    let source_path = match source {
+        // 包名默认与任务名相同
+        Source::Repository => p.ask(
+            "Package name in the repository (leave empty to use the task name)",
+            None,
+        )?,
+        _ => p.ask_required("Source path or URL", None)?,
+    };
+
+    let (mut branch, mut revision) = (None, None);
+    if source == Source::Git {
+        branch = non_empty(p.ask("Git branch (leave empty to use revision)", None)?);
+        if branch.is_none() {
+            revision = non_empty(p.ask("Git revision (leave empty for default branch)", None)?);
+        }
+    }
+
+    let build_command = match source_type {
+        TaskSourceType::BuildFromSource | TaskSourceType::KernelModule => {
+            Some(p.ask_required("Build command", Some("make install"))?)
+        }
+        // 从预编译包安装的任务不需要构建命令，cargo、cmake、autotools任务由DADK生成构建命令
+        _ => None,
+    };
+    let in_dragonos_path = match source_type {
+        // 内核模块默认安装到`/lib/modules/<内核版本>/extra`
+        TaskSourceType::KernelModule => p.ask(
+            "Install path in DragonOS (leave empty for /lib/modules/<kernel version>/extra)",
+            None,
+        )?,
+        _ => p.ask("Install path in DragonOS", Some("/bin"))?,
+    };
+    let in_dragonos_path = non_empty(in_dragonos_path);
+    let clean_command = non_empty(p.ask("Clean command", None)?);
+
+    let default_arch: String = default_arch.into();
+    let target_arch = loop {
+        let input = p.ask("Target arch (comma separated)", Some(&default_arch))?;
+        match parse_target_arch(&input) {
+            Ok(v) => break v,
+            Err(e) => p.say(&format!("{}", e))?,
+        }
+    };
+
+    let depends = loop {
+        let input = p.ask(
+            "Dependencies (comma separated, e.g. `libc@0.1.0`, or `libc` for the default version)",
+            None,
+        )?;
+        match parse_depends(&input) {
+            Ok(v) => break v,
+            Err(e) => p.say(&format!("{}", e))?,
+        }
+    };
+
+    let build_once = p.ask_bool("Build only once", false)?;
+    let install_once = p.ask_bool("Install only once", false)?;
+
+    Ok(UserConfigFile {
+        name,
+        version,
+        description,
+        task_source: TaskSource {
+            source_type,
+            source,
+            source_path,
+            branch,
+            revision,
+            patches: Vec::new(),
+            source_cache_key: None,
+            no_mirror: false,
+        },
+        depends,
+        host_packages: Vec::new(),
+        build: BuildConfig::new(build_command, None, None),
+        install: InstallConfig::new(in_dragonos_path.map(PathBuf::from)),
+        clean: CleanConfig::new(clean_command),
+        test: None,
+        envs: vec![],
+        build_once,
+        install_once,
+        target_arch,
+        retries: None,
+        priority: TaskPriority::default(),
+        cargo: None,
+        cmake: None,
+        autotools: None,
+        overrides: false,
+    })
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+fn parse_target_arch(input: &str) -> Result<Vec<TargetArch>> {
+    let mut result = Vec::new();
+    for arch in input.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let arch = TargetArch::try_from(arch).map_err(|e| anyhow!("{}", e))?;
+        if !result.contains(&arch) {
+            result.push(arch);
+        }
+    }
+    if result.is_empty() {
+        return Err(anyhow!("At least one target arch is required"));
+    }
+    Ok(result)
+}
+
+/// 解析`name@version`形式的依赖项，省略版本号时依赖默认版本
+fn parse_depends(input: &str) -> Result<Vec<Dependency>> {
+    let mut result = Vec::new();
+    for dep in input.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let (name, version) = match dep.split_once('@') {
+            Some((_, version)) if version.trim().is_empty() => {
+                return Err(anyhow!(
+                    "Invalid dependency '{}', expected `name@version` or `name`",
+                    dep
+                ))
+            }
+            Some((name, version)) => (name, version),
+            None => (dep, ""),
+        };
+        let dep = Dependency::new(name.trim().to_string(), version.trim().to_string());
+        dep.validate()?;
+        result.push(dep);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_args() -> UserNewCommand {
+        UserNewCommand {
+            from_template: None,
+            name: None,
+            version: None,
+            output: None,
+        }
+    }
+
+    fn run_wizard(input: &str) -> Result<UserConfigFile> {
+        let mut prompter = Prompter::new(input.as_bytes(), Vec::new());
+        wizard(&mut prompter, &new_args(), TargetArch::X86_64)
+    }
+
+    #[test]
+    fn test_wizard_git_source() {
+        let input = "\
+test_app
+0.2.0
+a test app
+
+git
+https://example.com/test_app.git
+main
+
+/bin
+make clean
+x86_64, riscv64
+libc@0.1.0
+n
+y
+";
+        let config = run_wizard(input).unwrap();
+        assert_eq!(config.name, "test_app");
+        assert_eq!(config.version, "0.2.0");
+        assert_eq!(
+            config.task_source.source_type,
+            TaskSourceType::BuildFromSource
+        );
+        assert_eq!(config.task_source.source, Source::Git);
+        assert_eq!(config.task_source.branch, Some("main".to_string()));
+        assert_eq!(config.task_source.revision, None);
+        assert_eq!(config.build.build_command, Some("make install".to_string()));
+        assert_eq!(config.install.in_dragonos_path, Some(PathBuf::from("/bin")));
+        assert_eq!(config.clean.clean_command, Some("make clean".to_string()));
+        assert_eq!(
+            config.target_arch,
+            vec![TargetArch::X86_64, TargetArch::RiscV64]
+        );
+        assert_eq!(
+            config.depends,
+            vec![Dependency::new("libc".to_string(), "0.1.0".to_string())]
+        );
+        assert!(!config.build_once);
+        assert!(config.install_once);
+        assert!(validated_toml(&config).is_ok());
+    }
+
+    #[test]
+    fn test_wizard_prebuilt_with_retries() {
+        // 非法的类型、架构和依赖会被重新询问
+        let input = "\
+prebuilt_app
+
+
+bogus
+install-from-prebuilt
+git
+local
+/opt/prebuilt
+
+
+unknown_arch
+
+bad_dep@
+
+
+
+";
+        let config = run_wizard(input).unwrap();
+        assert_eq!(config.version, "0.1.0");
+        assert_eq!(
+            config.task_source.source_type,
+            TaskSourceType::InstallFromPrebuilt
+        );
+        assert_eq!(config.task_source.source, Source::Local);
+        assert_eq!(config.build.build_command, None);
+        assert_eq!(config.target_arch, vec![TargetArch::X86_64]);
+        assert!(config.depends.is_empty());
+        assert!(validated_toml(&config).is_ok());
+    }
+
+    #[test]
+    fn test_wizard_cargo_source() {
+        let input = "cargo_app
+
+
+cargo
+local
+user/apps/cargo_app
+
+
+
+
+
+
+";
+        let config = run_wizard(input).unwrap();
+        assert_eq!(config.task_source.source_type, TaskSourceType::Cargo);
+        assert_eq!(config.task_source.source, Source::Local);
+        assert_eq!(config.build.build_command, None);
+        assert!(validated_toml(&config).is_ok());
+    }
+
+    #[test]
+    fn test_wizard_unexpected_eof() {
+        assert!(run_wizard("only_name\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_depends() {
+        let deps = parse_depends("a@0.1.0, b @ 1.0").unwrap();
+        assert_eq!(
+            deps,
+            vec![
+                Dependency::new("a".to_string(), "0.1.0".to_string()),
+                Dependency::new("b".to_string(), "1.0".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_depends("a").unwrap(),
+            vec![Dependency::new("a".to_string(), String::new())]
+        );
+        assert!(parse_depends("a@").is_err());
+        assert!(parse_depends("@0.1.0").is_err());
+        assert!(parse_depends("").unwrap().is_empty());
+    }
+}
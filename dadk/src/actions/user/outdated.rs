@@ -0,0 +1,38 @@
+//! # `dadk user outdated`
+//!
+//! 对于从git分支构建的用户程序，通过`git ls-remote`查询上游分支的最新提交，
+//! 与源码缓存中检出的提交比较，列出落后于上游的任务。指定`--lockfile`时把上游的最新提交写入锁文件。
+
+use anyhow::{anyhow, Result};
+use dadk_user::{outdated::OutdatedReport, parser::Parser};
+
+use crate::{console::user::UserOutdatedCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserOutdatedCommand) -> Result<()> {
+    if ctx.offline() {
+        return Err(anyhow!("Can't query upstream repositories in offline mode"));
+    }
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let tasks = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?;
+
+    let report = OutdatedReport::check(&cache_root_dir, &tasks);
+    if let Some(lockfile) = &args.lockfile {
+        report
+            .update_lockfile(&ctx.workdir().join(lockfile))
+            .map_err(|e| anyhow!(e))?;
+    }
+    if args.json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report.table());
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! # `dadk user package`
+//!
+//! 把已经构建成功的任务的构建缓存目录打包为二进制包（`<name>-<version>-<arch>.dpk`），
+//! 其他项目可以通过`task-source.type = "package"`直接安装这些二进制包，而不必从源码构建。
+//!
+//! 输出目录中的`index.toml`会同时更新，把输出目录发布到HTTP服务器上即可作为软件仓库使用。
+
+use anyhow::{anyhow, Result};
+use dadk_user::{
+    lock::{self, LockMode},
+    package,
+    parser::Parser,
+    repository,
+};
+
+use crate::{console::user::UserPackageCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserPackageCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let arch = ctx.target_arch();
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| ctx.workdir().join("bin/packages"));
+    let tasks: Vec<_> = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?
+        .into_iter()
+        .map(|(_, task)| task)
+        .filter(|task| task.target_arch.contains(&arch))
+        .collect();
+
+    if let Some(missing) = args
+        .task
+        .iter()
+        .find(|name| !tasks.iter().any(|t| t.name == **name))
+    {
+        return Err(anyhow!("Task {} not found", missing));
+    }
+
+    // 只读取构建结果，允许与其他只读的dadk进程同时运行
+    let _root_lock = lock::lock_cache_root(&cache_root_dir, LockMode::Shared, ctx.lock_timeout())
+        .map_err(|e| anyhow!(e))?;
+    let mut failed = 0;
+    for task in tasks
+        .iter()
+        .filter(|t| args.task.is_empty() || args.task.contains(&t.name))
+    {
+        let exported = lock::lock_task(
+            &cache_root_dir,
+            &task.name_version(),
+            LockMode::Shared,
+            ctx.lock_timeout(),
+        )
+        .and_then(|_task_lock| package::export_task(&cache_root_dir, task, arch, &output));
+        match exported {
+            Ok(path) => {
+                repository::add_to_index(&path).map_err(|e| anyhow!(e))?;
+                println!("{}", path.display());
+            }
+            // 未指定task时跳过没有构建的任务
+            Err(e) if args.task.is_empty() => log::warn!("{}, skipped", e),
+            Err(e) => {
+                log::error!("{}", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("Failed to package {} task(s)", failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! # `dadk user rdeps`
+//!
+//! 列出直接或间接依赖于给定任务的任务，用于在升级基础库之前评估影响范围。
+//! 默认以树的形式输出，`--flat`时每行输出一个任务。
+
+use anyhow::Result;
+use dadk_user::BuildSession;
+
+use super::ArchTarget;
+use crate::{
+    console::user::{UserBuildCommand, UserCommand, UserRdepsCommand},
+    context::DADKExecContext,
+};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserRdepsCommand) -> Result<()> {
+    let target = ArchTarget::from_ctx(ctx)?;
+    let context = target.execute_context(&UserCommand::Build(UserBuildCommand::default()))?;
+    let tree = BuildSession::new(context)?.rdeps(&args.task)?;
+    if args.json {
+        println!("{}", tree.to_json(args.flat));
+    } else if args.flat {
+        print!("{}", tree.flat_text());
+    } else {
+        print!("{}", tree.tree_text());
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! # `dadk user stats`
+//!
+//! 根据各个任务的任务日志中记录的构建耗时，输出耗时最长的任务、构建总耗时、
+//! 最近一次构建的并行效率以及依赖图上的关键路径，用于判断哪些任务值得缓存或拆分。
+
+use anyhow::Result;
+use dadk_user::{parser::Parser, stats::BuildStats};
+
+use crate::{console::user::UserStatsCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserStatsCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let arch = ctx.target_arch();
+    let tasks: Vec<_> = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?
+        .into_iter()
+        .filter(|(_, task)| task.target_arch.contains(&arch))
+        .collect();
+
+    let stats = BuildStats::collect(&cache_root_dir, &tasks);
+    print!("{}", stats.report(args.top));
+    Ok(())
+}
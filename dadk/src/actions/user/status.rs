@@ -0,0 +1,34 @@
+//! # `dadk user status`
+//!
+//! 输出单个用户程序最近一次构建、安装的结果和耗时，以及下一次构建、安装是否会被跳过：
+//! 跳过或者重新执行的原因，以及参与比较的输入文件的修改时间。
+
+use anyhow::{anyhow, Result};
+use dadk_user::{
+    parser::Parser,
+    status::{find_task, TaskStatus},
+};
+
+use crate::{console::user::UserStatusCommand, context::DADKExecContext};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserStatusCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let tasks = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?;
+
+    let (config_file, task) = find_task(&tasks, &args.task).map_err(|e| anyhow!(e))?;
+    let status = TaskStatus::load(&cache_root_dir, config_file, task);
+    if args.json {
+        println!("{}", status.to_json());
+    } else {
+        print!("{}", status.text());
+    }
+    Ok(())
+}
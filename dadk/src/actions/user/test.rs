@@ -0,0 +1,166 @@
+//! # `dadk user test`
+//!
+//! 构建用户程序，然后执行任务配置中`[test]`指定的测试：
+//!
+//! - `test-command`在主机上执行
+//! - 指定`--qemu`时，把`guest-script`复制到磁盘镜像的`/dadk-tests`目录中，按照boot配置启动QEMU
+//!   （与`dadk boot run`相同），由客户机的init执行`/dadk-tests/run.sh`，从串口输出中收集每个任务的测试结果
+//!
+//! 所有测试的结果汇总为测试报告，有测试失败时返回错误。
+
+use std::{
+    process::Stdio,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use dadk_user::{
+    interrupt,
+    lock::{self, LockMode},
+    parser::{task::DADKTask, Parser},
+    test::{self, GuestOutput, TestReport, TestResult, GUEST_TEST_DIR},
+};
+
+use crate::{
+    actions::{boot::Qemu, rootfs::disk_img},
+    console::user::{UserBuildCommand, UserCommand, UserTestCommand},
+    context::DADKExecContext,
+};
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserTestCommand) -> Result<()> {
+    if !args.no_build {
+        super::run(ctx, &UserCommand::Build(UserBuildCommand::default()))?;
+    }
+
+    #[allow(deprecated)]
+    let config_dir = ctx.user_config_dir()?;
+    let cache_root_dir = ctx.cache_root_dir()?;
+    let arch = ctx.target_arch();
+    let tasks: Vec<_> = Parser::new(config_dir)
+        .overlay_dirs(ctx.overlay_config_dirs()?)
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&cache_root_dir)
+        .parse()?
+        .into_iter()
+        .map(|(_, task)| task)
+        .filter(|task| task.target_arch.contains(&arch))
+        .collect();
+    if let Some(missing) = args
+        .task
+        .iter()
+        .find(|name| !tasks.iter().any(|t| t.name == **name))
+    {
+        return Err(anyhow!("Task {} not found", missing));
+    }
+    let tasks: Vec<_> = tasks
+        .into_iter()
+        .filter(|t| t.test.is_some())
+        .filter(|t| args.task.is_empty() || args.task.contains(&t.name))
+        .collect();
+
+    // 只读取构建结果，允许与其他只读的dadk进程同时运行
+    let _root_lock = lock::lock_cache_root(&cache_root_dir, LockMode::Shared, ctx.lock_timeout())
+        .map_err(|e| anyhow!(e))?;
+    let mut results = Vec::new();
+    for task in &tasks {
+        let _task_lock = lock::lock_task(
+            &cache_root_dir,
+            &task.name_version(),
+            LockMode::Shared,
+            ctx.lock_timeout(),
+        )
+        .map_err(|e| anyhow!(e))?;
+        results.extend(test::run_host_test(&cache_root_dir, task, arch));
+    }
+    if args.qemu {
+        let (runnable, skipped) = test::guest_tests(&cache_root_dir, &tasks);
+        results.extend(skipped);
+        if !runnable.is_empty() {
+            results.extend(run_guest_tests(ctx, &runnable, args.qemu_timeout)?);
+        }
+    }
+
+    let report = TestReport::new(results);
+    if let Some(path) = &args.report {
+        std::fs::write(path, report.to_json())
+            .map_err(|e| anyhow!("Failed to write test report {}: {}", path.display(), e))?;
+    }
+    if args.json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report.table());
+    }
+    if !report.success() {
+        return Err(anyhow!("{} test(s) failed", report.failed));
+    }
+    Ok(())
+}
+
+/// 把测试脚本安装到磁盘镜像中，然后启动QEMU执行
+fn run_guest_tests(
+    ctx: &DADKExecContext,
+    tasks: &[&DADKTask],
+    timeout: Duration,
+) -> Result<Vec<TestResult>> {
+    let qemu = Qemu::new(ctx, true)?.arg("-no-reboot");
+
+    // QEMU启动前必须卸载磁盘镜像，避免主机和客户机同时写入文件系统
+    disk_img::mount(ctx, true)?;
+    let dest = ctx
+        .disk_mount_path()
+        .join(GUEST_TEST_DIR.trim_start_matches('/'));
+    let installed = test::install_guest_tests(&ctx.workdir(), tasks, &dest);
+    disk_img::umount(ctx, false)?;
+    installed.map_err(|e| anyhow!("Failed to install test scripts: {}", e))?;
+
+    let start = Instant::now();
+    let output = boot_qemu(&qemu, timeout)?;
+    Ok(output.results(tasks, start.elapsed()))
+}
+
+/// 启动QEMU，转发串口输出，直到测试结束或者超时
+fn boot_qemu(qemu: &Qemu, timeout: Duration) -> Result<GuestOutput> {
+    let _virtiofsd = qemu.start_virtiofsd()?;
+    log::info!("Booting QEMU: {}", qemu.command_line());
+    let mut command = qemu.command();
+    command.stdin(Stdio::null()).stdout(Stdio::piped());
+    let (mut child, tracked) = interrupt::spawn_tracked(&mut command)
+        .map_err(|e| anyhow!("Failed to start QEMU: {}", e))?;
+
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        use std::io::BufRead;
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + timeout;
+    let mut output = GuestOutput::default();
+    while !output.done() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(line) => {
+                // 输出到标准错误，避免与JSON格式的测试报告混在一起
+                eprintln!("{}", line);
+                output.feed(&line);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                log::error!("Tests in QEMU did not finish in {:?}", timeout);
+                break;
+            }
+            // QEMU已经退出
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    tracked.kill_group();
+    child.wait().ok();
+    Ok(output)
+}
@@ -0,0 +1,363 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::widgets::ListState;
+use serde_json::Value;
+
+/// 每个任务保留的输出行数
+const TASK_LOG_LINES: usize = 500;
+/// 保留的全局日志行数
+const MESSAGE_LINES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TaskStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug)]
+pub(super) struct TaskState {
+    pub id: i32,
+    pub name: String,
+    pub depends: Vec<String>,
+    pub status: TaskStatus,
+    pub started_at: Option<Instant>,
+    pub elapsed: Option<Duration>,
+    /// 是否已经请求取消
+    pub cancelling: bool,
+    /// 下载/解压的进度（描述，已完成，总量）
+    pub progress: Option<(String, u64, u64)>,
+    pub log: VecDeque<String>,
+}
+
+impl TaskState {
+    fn new(id: i32, name: String) -> Self {
+        Self {
+            id,
+            name,
+            depends: Vec::new(),
+            status: TaskStatus::Queued,
+            started_at: None,
+            elapsed: None,
+            cancelling: false,
+            progress: None,
+            log: VecDeque::new(),
+        }
+    }
+
+    /// 任务已经执行的时间
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+            .or_else(|| self.started_at.map(|t| t.elapsed()))
+    }
+
+    fn push_log(&mut self, line: String) {
+        if self.log.len() == TASK_LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+}
+
+/// 按键对应的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Command {
+    /// 取消指定ID的任务
+    CancelTask(i32),
+    /// 中断整个构建
+    CancelRun,
+    /// 退出界面
+    Quit,
+}
+
+/// # 界面的状态
+///
+/// 由dadk-user发出的结构化事件（见[`dadk_user::event`]）驱动
+pub(super) struct App {
+    /// 按照进入队列的顺序排列的任务
+    pub tasks: Vec<TaskState>,
+    pub list_state: ListState,
+    /// 不属于任何任务的日志
+    pub messages: VecDeque<String>,
+    pub started_at: Instant,
+    /// 构建结束时的结果（None表示仍在构建）
+    pub result: Option<Result<(), String>>,
+    /// 是否已经请求中断整个构建
+    pub interrupting: bool,
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            list_state: ListState::default(),
+            messages: VecDeque::new(),
+            started_at: Instant::now(),
+            result: None,
+            interrupting: false,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&TaskState> {
+        self.list_state.selected().and_then(|i| self.tasks.get(i))
+    }
+
+    /// 已结束的任务数与总任务数
+    pub fn progress(&self) -> (usize, usize) {
+        let done = self
+            .tasks
+            .iter()
+            .filter(|t| matches!(t.status, TaskStatus::Succeeded | TaskStatus::Failed))
+            .count();
+        (done, self.tasks.len())
+    }
+
+    pub fn count(&self, status: TaskStatus) -> usize {
+        self.tasks.iter().filter(|t| t.status == status).count()
+    }
+
+    pub fn finish(&mut self, result: Result<(), String>) {
+        if let Err(e) = &result {
+            self.push_message(format!("[ERROR] {}", e));
+        }
+        self.result = Some(result);
+    }
+
+    /// 处理一条日志（JSON格式，见[`crate::logger`]）
+    pub fn handle_event(&mut self, event: &Value) {
+        let str_field = |name: &str| event[name].as_str().unwrap_or_default().to_string();
+        let id = event["task_id"].as_i64().map(|id| id as i32);
+        match (event["event"].as_str().unwrap_or_default(), id) {
+            ("task_queued", Some(id)) => {
+                let task = self.task_mut(id, str_field("task"));
+                task.depends = str_field("depends")
+                    .split(',')
+                    .filter(|d| !d.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            ("task_started", Some(id)) => {
+                let task = self.task_mut(id, str_field("task"));
+                task.status = TaskStatus::Running;
+                task.started_at = Some(Instant::now());
+            }
+            ("task_finished", Some(id)) => {
+                let task = self.task_mut(id, str_field("task"));
+                task.elapsed = event["elapsed_ms"].as_u64().map(Duration::from_millis);
+                task.progress = None;
+                if event["status"] == "ok" {
+                    task.status = TaskStatus::Succeeded;
+                } else {
+                    task.status = TaskStatus::Failed;
+                    task.push_log(format!("[FAILED] {}", str_field("error")));
+                }
+            }
+            ("task_output", Some(id)) => {
+                let task = self.task_mut(id, str_field("task"));
+                task.push_log(str_field("line"));
+            }
+            ("download_progress", Some(id)) => {
+                let progress = (
+                    format!("downloading {}", str_field("url")),
+                    event["downloaded"].as_u64().unwrap_or(0),
+                    event["total"].as_u64().unwrap_or(0),
+                );
+                self.task_mut(id, str_field("task")).progress = Some(progress);
+            }
+            ("extract_progress", Some(id)) => {
+                let progress = (
+                    format!("extracting {}", str_field("archive")),
+                    event["extracted"].as_u64().unwrap_or(0),
+                    event["total"].as_u64().unwrap_or(0),
+                );
+                self.task_mut(id, str_field("task")).progress = Some(progress);
+            }
+            ("install_done", Some(id)) => {
+                let line = str_field("message");
+                self.task_mut(id, str_field("task")).push_log(line);
+            }
+            ("log" | "error", _) => {
+                let level = str_field("level").to_uppercase();
+                self.push_message(format!("[{}] {}", level, str_field("message")));
+            }
+            _ => {}
+        }
+    }
+
+    /// 处理按键，返回需要执行的操作
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Command> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        let ctrl_c =
+            key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Home | KeyCode::Char('g') => self.select(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.select(isize::MAX),
+            // 构建结束后，按q、Esc或Ctrl+C退出
+            KeyCode::Char('q') | KeyCode::Esc if self.result.is_some() => {
+                return Some(Command::Quit)
+            }
+            _ if ctrl_c && self.result.is_some() => return Some(Command::Quit),
+            // 构建过程中，按q、x或Ctrl+C中断整个构建。再次按下时强制退出
+            KeyCode::Char('q') | KeyCode::Char('x') | KeyCode::Esc if self.result.is_none() => {
+                return self.cancel_run()
+            }
+            _ if ctrl_c => return self.cancel_run(),
+            KeyCode::Char('c') if self.result.is_none() => {
+                let task = self
+                    .list_state
+                    .selected()
+                    .and_then(|i| self.tasks.get_mut(i))?;
+                if matches!(task.status, TaskStatus::Queued | TaskStatus::Running) {
+                    task.cancelling = true;
+                    return Some(Command::CancelTask(task.id));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn cancel_run(&mut self) -> Option<Command> {
+        if !self.interrupting {
+            self.interrupting = true;
+            self.push_message(
+                "Interrupting, waiting for running tasks to stop (press again to force exit)..."
+                    .to_string(),
+            );
+        }
+        Some(Command::CancelRun)
+    }
+
+    fn select(&mut self, delta: isize) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let max = self.tasks.len() as isize - 1;
+        let next = current.saturating_add(delta).clamp(0, max);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn task_mut(&mut self, id: i32, name: String) -> &mut TaskState {
+        let index = match self.tasks.iter().position(|t| t.id == id) {
+            Some(index) => index,
+            None => {
+                self.tasks.push(TaskState::new(id, name));
+                if self.list_state.selected().is_none() {
+                    self.list_state.select(Some(0));
+                }
+                self.tasks.len() - 1
+            }
+        };
+        &mut self.tasks[index]
+    }
+
+    fn push_message(&mut self, message: String) {
+        if self.messages.len() == MESSAGE_LINES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn app_with_tasks() -> App {
+        let mut app = App::new();
+        app.handle_event(
+            &json!({"event": "task_queued", "task_id": 0, "task": "libc-0.1.0", "depends": ""}),
+        );
+        app.handle_event(&json!({"event": "task_queued", "task_id": 1, "task": "app-0.1.0", "depends": "libc-0.1.0"}));
+        app
+    }
+
+    #[test]
+    fn test_events_update_tasks() {
+        let mut app = app_with_tasks();
+        assert_eq!(app.tasks.len(), 2);
+        assert_eq!(app.tasks[1].depends, vec!["libc-0.1.0"]);
+        assert_eq!(app.selected().unwrap().name, "libc-0.1.0");
+
+        app.handle_event(&json!({"event": "task_started", "task_id": 0, "task": "libc-0.1.0"}));
+        assert_eq!(app.tasks[0].status, TaskStatus::Running);
+        app.handle_event(&json!({"event": "task_output", "task_id": 0, "task": "libc-0.1.0", "stream": "stdout", "line": "make all"}));
+        app.handle_event(&json!({"event": "download_progress", "task_id": 0, "task": "libc-0.1.0", "url": "https://example.com/a.tar.gz", "downloaded": 10, "total": 100}));
+        assert_eq!(app.tasks[0].log, vec!["make all"]);
+        assert_eq!(app.tasks[0].progress.as_ref().unwrap().1, 10);
+
+        app.handle_event(&json!({"event": "task_finished", "task_id": 0, "task": "libc-0.1.0", "status": "ok", "elapsed_ms": 1500}));
+        assert_eq!(app.tasks[0].status, TaskStatus::Succeeded);
+        assert_eq!(app.tasks[0].elapsed(), Some(Duration::from_millis(1500)));
+        assert!(app.tasks[0].progress.is_none());
+
+        app.handle_event(&json!({"event": "task_started", "task_id": 1, "task": "app-0.1.0"}));
+        app.handle_event(&json!({"event": "task_finished", "task_id": 1, "task": "app-0.1.0", "status": "failed", "error": "exit status: 2"}));
+        assert_eq!(app.tasks[1].status, TaskStatus::Failed);
+        assert_eq!(app.tasks[1].log.back().unwrap(), "[FAILED] exit status: 2");
+        assert_eq!(app.progress(), (2, 2));
+
+        app.handle_event(&json!({"event": "log", "level": "warn", "message": "hello"}));
+        assert_eq!(app.messages.back().unwrap(), "[WARN] hello");
+    }
+
+    #[test]
+    fn test_task_log_is_bounded() {
+        let mut app = app_with_tasks();
+        for i in 0..TASK_LOG_LINES + 10 {
+            app.handle_event(&json!({"event": "task_output", "task_id": 0, "task": "libc-0.1.0", "line": i.to_string()}));
+        }
+        assert_eq!(app.tasks[0].log.len(), TASK_LOG_LINES);
+        assert_eq!(app.tasks[0].log.front().unwrap(), "10");
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let mut app = app_with_tasks();
+        assert_eq!(app.handle_key(key(KeyCode::Down)), None);
+        assert_eq!(app.selected().unwrap().id, 1);
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.selected().unwrap().id, 1);
+        app.handle_key(key(KeyCode::Char('k')));
+        assert_eq!(app.selected().unwrap().id, 0);
+
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('c'))),
+            Some(Command::CancelTask(0))
+        );
+        assert!(app.tasks[0].cancelling);
+        // 已经结束的任务不能取消
+        app.handle_event(&json!({"event": "task_finished", "task_id": 0, "task": "libc-0.1.0", "status": "failed"}));
+        assert_eq!(app.handle_key(key(KeyCode::Char('c'))), None);
+
+        assert_eq!(
+            app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Command::CancelRun)
+        );
+        assert!(app.interrupting);
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('q'))),
+            Some(Command::CancelRun)
+        );
+
+        app.finish(Err("Interrupted".to_string()));
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), Some(Command::Quit));
+        assert_eq!(app.handle_key(key(KeyCode::Char('c'))), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! # `dadk user build --tui`
+//!
+//! 以交互式界面显示构建过程：任务队列及其依赖、各个任务的构建命令输出以及整体进度。
+//!
+//! 构建在后台线程中执行，构建命令的输出以及dadk-user发出的结构化事件通过[`crate::logger::redirect`]
+//! 发送给界面，不会直接输出到终端。终端处于raw模式，Ctrl+C不会产生信号，由界面读取按键后中断构建。
+
+use std::{io::Stdout, sync::mpsc, time::Duration};
+
+use anyhow::Result;
+use crossterm::{
+    event::{self, Event},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use dadk_user::{dadk_user_main, interrupt};
+use ratatui::{backend::CrosstermBackend, Terminal};
+
+use crate::{
+    actions::report_error,
+    console::user::{UserBuildCommand, UserCommand},
+    context::DADKExecContext,
+    logger,
+};
+
+use self::app::{App, Command};
+
+use super::{exit_code, multi_arch::ArchTarget};
+
+mod app;
+mod ui;
+
+/// 界面刷新的间隔
+const TICK: Duration = Duration::from_millis(100);
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserBuildCommand) -> Result<()> {
+    let target = ArchTarget::from_ctx(ctx)?;
+    let context = target.execute_context(&UserCommand::Build(args.clone()))?;
+
+    let (sender, receiver) = mpsc::channel();
+    logger::redirect(Some(sender));
+    let mut terminal = match setup_terminal() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            logger::redirect(None);
+            return Err(e);
+        }
+    };
+    // 再次中断时进程会立即退出，退出前需要恢复终端
+    let _restore = interrupt::register_cleanup(restore_terminal);
+
+    let interrupts = context.interrupt_state().clone();
+    let mut build = Some(std::thread::spawn(move || dadk_user_main(context)));
+    let mut result = None;
+    let mut app = App::new();
+    let r: Result<()> = loop {
+        while let Ok(event) = receiver.try_recv() {
+            app.handle_event(&event);
+        }
+        if build.as_ref().is_some_and(|b| b.is_finished()) {
+            let r = build.take().unwrap().join().expect("Build thread panicked");
+            app.finish(r.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+            result = Some(r);
+        }
+        if let Err(e) = terminal.draw(|f| ui::draw(f, &mut app)) {
+            break Err(e.into());
+        }
+
+        match event::poll(TICK) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(e) => break Err(e.into()),
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        match app.handle_key(key) {
+            Some(Command::CancelTask(id)) => interrupts.cancel_task(id),
+            Some(Command::CancelRun) => interrupt::interrupt_as_signal(),
+            Some(Command::Quit) => break Ok(()),
+            None => {}
+        }
+    };
+
+    restore_terminal();
+    logger::redirect(None);
+    r?;
+
+    // 构建结束后，照常输出结果
+    if let Some(Err(e)) = result {
+        report_error(ctx, &e);
+        std::process::exit(exit_code(&e));
+    }
+    Ok(())
+}
+
+fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    if let Err(e) = execute!(std::io::stdout(), EnterAlternateScreen) {
+        disable_raw_mode().ok();
+        return Err(e.into());
+    }
+    Ok(Terminal::new(CrosstermBackend::new(std::io::stdout()))?)
+}
+
+fn restore_terminal() {
+    disable_raw_mode().ok();
+    execute!(std::io::stdout(), LeaveAlternateScreen).ok();
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
+    Frame,
+};
+
+use super::app::{App, TaskState, TaskStatus};
+
+/// 同时显示输出的任务数
+const MAX_LOG_PANES: usize = 3;
+/// 全局日志区域的高度（含边框）
+const MESSAGES_HEIGHT: u16 = 7;
+
+pub(super) fn draw(frame: &mut Frame, app: &mut App) {
+    let [header, body, messages, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(5),
+        Constraint::Length(MESSAGES_HEIGHT),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [tasks, logs] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+
+    draw_progress(frame, app, header);
+    draw_tasks(frame, app, tasks);
+    draw_logs(frame, app, logs);
+    draw_messages(frame, app, messages);
+    draw_help(frame, app, footer);
+}
+
+fn draw_progress(frame: &mut Frame, app: &App, area: Rect) {
+    let (done, total) = app.progress();
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        done as f64 / total as f64
+    };
+    let state = match &app.result {
+        None if app.interrupting => "interrupting",
+        None => "building",
+        Some(Ok(())) => "finished",
+        Some(Err(_)) => "failed",
+    };
+    let label = format!(
+        "{}/{} tasks, {} running, {} failed, {} ({})",
+        done,
+        total,
+        app.count(TaskStatus::Running),
+        app.count(TaskStatus::Failed),
+        format_duration(app.started_at.elapsed()),
+        state
+    );
+    let color = match &app.result {
+        Some(Err(_)) => Color::Red,
+        Some(Ok(())) => Color::Green,
+        None => Color::Cyan,
+    };
+    let gauge = Gauge::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" dadk user build "),
+        )
+        .gauge_style(Style::default().fg(color))
+        .ratio(ratio)
+        .label(label);
+    frame.render_widget(gauge, area);
+}
+
+fn draw_tasks(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app.tasks.iter().map(task_item).collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Tasks "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+}
+
+fn task_item(task: &TaskState) -> ListItem<'static> {
+    let (symbol, color) = match task.status {
+        TaskStatus::Queued => ("·", Color::DarkGray),
+        TaskStatus::Running => ("▶", Color::Yellow),
+        TaskStatus::Succeeded => ("✔", Color::Green),
+        TaskStatus::Failed => ("✘", Color::Red),
+    };
+    let mut spans = vec![
+        Span::styled(format!("{} ", symbol), Style::default().fg(color)),
+        Span::raw(task.name.clone()),
+    ];
+    if let Some(elapsed) = task.elapsed() {
+        spans.push(format!(" {}", format_duration(elapsed)).dark_gray());
+    }
+    if task.cancelling && matches!(task.status, TaskStatus::Queued | TaskStatus::Running) {
+        spans.push(" (cancelling)".red());
+    }
+    if !task.depends.is_empty() {
+        spans.push(format!(" ← {}", task.depends.join(", ")).dark_gray());
+    }
+    ListItem::new(Line::from(spans))
+}
+
+/// 显示选中的任务以及其他正在执行的任务的最后若干行输出
+fn draw_logs(frame: &mut Frame, app: &App, area: Rect) {
+    let selected = app.selected();
+    let mut panes: Vec<&TaskState> = selected.into_iter().collect();
+    panes.extend(
+        app.tasks
+            .iter()
+            .filter(|t| t.status == TaskStatus::Running && Some(t.id) != selected.map(|s| s.id))
+            .take(MAX_LOG_PANES - panes.len()),
+    );
+    if panes.is_empty() {
+        frame.render_widget(
+            Block::default().borders(Borders::ALL).title(" Output "),
+            area,
+        );
+        return;
+    }
+
+    let areas = Layout::vertical(vec![Constraint::Fill(1); panes.len()]).split(area);
+    for (task, area) in panes.into_iter().zip(areas.iter()) {
+        let mut title = format!(" {} ", task.name);
+        if let Some((what, done, total)) = &task.progress {
+            title.push_str(&match total {
+                0 => format!("- {} {} bytes ", what, done),
+                _ => format!("- {} {}% ", what, done * 100 / total),
+            });
+        }
+        let height = area.height.saturating_sub(2) as usize;
+        let skip = task.log.len().saturating_sub(height);
+        let lines: Vec<Line> = task
+            .log
+            .iter()
+            .skip(skip)
+            .map(|l| Line::raw(l.clone()))
+            .collect();
+        let border = if task.status == TaskStatus::Failed {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        };
+        let paragraph = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border)
+                .title(title),
+        );
+        frame.render_widget(paragraph, *area);
+    }
+}
+
+fn draw_messages(frame: &mut Frame, app: &App, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let skip = app.messages.len().saturating_sub(height);
+    let lines: Vec<Line> = app
+        .messages
+        .iter()
+        .skip(skip)
+        .map(|m| {
+            if m.starts_with("[ERROR]") {
+                Line::raw(m.clone()).red()
+            } else if m.starts_with("[WARN]") {
+                Line::raw(m.clone()).yellow()
+            } else {
+                Line::raw(m.clone())
+            }
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Messages ")),
+        area,
+    );
+}
+
+fn draw_help(frame: &mut Frame, app: &App, area: Rect) {
+    let help = if app.result.is_some() {
+        "↑/↓ select task   q quit"
+    } else {
+        "↑/↓ select task   c cancel task   x/q/Ctrl+C cancel build (press again to force exit)"
+    };
+    frame.render_widget(Paragraph::new(help).dark_gray(), area);
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", d.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_draw_tasks_and_output() {
+        let mut app = App::new();
+        app.handle_event(
+            &json!({"event": "task_queued", "task_id": 0, "task": "libc-0.1.0", "depends": ""}),
+        );
+        app.handle_event(&json!({"event": "task_queued", "task_id": 1, "task": "app-0.1.0", "depends": "libc-0.1.0"}));
+        app.handle_event(&json!({"event": "task_started", "task_id": 0, "task": "libc-0.1.0"}));
+        app.handle_event(&json!({"event": "task_output", "task_id": 0, "task": "libc-0.1.0", "line": "compiling libc"}));
+        app.handle_event(&json!({"event": "log", "level": "warn", "message": "low disk space"}));
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .collect();
+        assert!(screen.contains("0/2 tasks, 1 running"));
+        assert!(screen.contains("app-0.1.0 ← libc-0.1.0"));
+        assert!(screen.contains("compiling libc"));
+        assert!(screen.contains("[WARN] low disk space"));
+        assert!(screen.contains("c cancel task"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+    }
+}
@@ -0,0 +1,261 @@
+//! # `dadk user watch`
+//!
+//! 监视每个任务的本地源码目录以及配置文件，当文件发生变更时，
+//! 自动重新构建、安装发生变更的任务，以及（直接或间接）依赖于它的任务。
+//!
+//! 每一轮构建、安装都在一个新的dadk子进程中进行，这样某个任务构建失败时，不会导致监视进程退出。
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use dadk_user::{
+    affected,
+    parser::{task::DADKTask, Parser},
+};
+use log::{debug, error, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{console::user::UserWatchCommand, context::DADKExecContext, utils::abs_path};
+
+type WatchEvent = notify::Result<Event>;
+
+pub(super) fn run(ctx: &DADKExecContext, args: &UserWatchCommand) -> Result<()> {
+    #[allow(deprecated)]
+    let config_dir = abs_path(&ctx.user_config_dir()?);
+    let overlay_dirs: Vec<PathBuf> = ctx.overlay_config_dirs()?.iter().map(abs_path).collect();
+    let config_dirs: Vec<PathBuf> = std::iter::once(config_dir.clone())
+        .chain(overlay_dirs.iter().cloned())
+        .collect();
+    let debounce = Duration::from_millis(args.debounce);
+
+    let (tx, rx) = channel::<WatchEvent>();
+    let mut watcher = notify::recommended_watcher(tx)
+        .map_err(|e| anyhow!("Failed to create file watcher: {}", e))?;
+
+    let mut tasks = load_tasks(ctx, &config_dir, &overlay_dirs)?;
+    let mut watched: BTreeSet<PathBuf> = BTreeSet::new();
+    update_watch_list(&mut watcher, &mut watched, &config_dirs, &tasks)?;
+
+    // 进入监视之前，先完整地构建、安装一遍
+    build_and_install(ctx, &BTreeSet::new());
+    drain_events(&rx);
+
+    info!("Watching {} path(s) for changes...", watched.len());
+    loop {
+        let changed = wait_for_changes(&rx, debounce)?;
+        if changed.is_empty() {
+            continue;
+        }
+        debug!("Changed paths: {:?}", changed);
+
+        // 配置文件发生变化时，重新解析任务列表，并更新监视列表
+        if changed
+            .iter()
+            .any(|p| config_dirs.iter().any(|dir| p.starts_with(dir)))
+        {
+            match load_tasks(ctx, &config_dir, &overlay_dirs) {
+                Ok(t) => {
+                    tasks = t;
+                    update_watch_list(&mut watcher, &mut watched, &config_dirs, &tasks)?;
+                }
+                Err(e) => {
+                    error!("Failed to reload task configs: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        let changed: Vec<PathBuf> = changed.into_iter().collect();
+        let affected: BTreeSet<String> = affected::affected_tasks(&tasks, &changed)
+            .into_iter()
+            .map(|(name, version)| format!("{}@{}", name, version))
+            .collect();
+        if affected.is_empty() {
+            continue;
+        }
+        info!(
+            "Detected changes, rebuilding: {}",
+            affected.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+        build_and_install(ctx, &affected);
+        // 构建过程中（例如在本地源码目录中构建）产生的文件变更事件，不应触发新一轮构建
+        drain_events(&rx);
+        info!("Watching for changes...");
+    }
+}
+
+/// 解析配置目录下的所有任务，只保留当前目标架构的任务
+fn load_tasks(
+    ctx: &DADKExecContext,
+    config_dir: &Path,
+    overlay_dirs: &[PathBuf],
+) -> Result<Vec<(PathBuf, DADKTask)>> {
+    let tasks = Parser::new(config_dir.to_path_buf())
+        .overlay_dirs(overlay_dirs.to_vec())
+        .skip_invalid_configs(ctx.skip_invalid_configs())
+        .variables(ctx.config_variables()?)
+        .default_versions(ctx.default_versions())
+        .cache_dir(&ctx.cache_root_dir()?)
+        .parse()?;
+    let arch = ctx.target_arch();
+    Ok(tasks
+        .into_iter()
+        .filter(|(_, task)| task.target_arch.contains(&arch))
+        .map(|(path, task)| (abs_path(&path), task))
+        .collect())
+}
+
+/// 根据任务列表，更新需要监视的路径（配置目录以及每个任务的本地源码目录）
+fn update_watch_list(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut BTreeSet<PathBuf>,
+    config_dirs: &[PathBuf],
+    tasks: &[(PathBuf, DADKTask)],
+) -> Result<()> {
+    let mut wanted: BTreeSet<PathBuf> = config_dirs.iter().cloned().collect();
+    for (_, task) in tasks {
+        if let Some(src) = task.source_path() {
+            wanted.insert(abs_path(&src));
+        }
+    }
+
+    for path in watched.difference(&wanted) {
+        if let Err(e) = watcher.unwatch(path) {
+            warn!("Failed to unwatch {}: {}", path.display(), e);
+        }
+    }
+    for path in wanted.difference(watched) {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(|e| anyhow!("Failed to watch {}: {}", path.display(), e))?;
+        debug!("Watching {}", path.display());
+    }
+    *watched = wanted;
+    Ok(())
+}
+
+/// 阻塞等待文件变更事件，并在防抖时间内合并后续的事件
+fn wait_for_changes(rx: &Receiver<WatchEvent>, debounce: Duration) -> Result<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    let first = rx
+        .recv()
+        .map_err(|_| anyhow!("File watcher stopped unexpectedly"))?;
+    collect_event(first, &mut changed);
+
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(event) => collect_event(event, &mut changed),
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("File watcher stopped unexpectedly"))
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn collect_event(event: WatchEvent, changed: &mut BTreeSet<PathBuf>) {
+    match event {
+        Ok(event) => {
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            changed.extend(event.paths);
+        }
+        Err(e) => warn!("File watcher error: {}", e),
+    }
+}
+
+/// 丢弃所有尚未处理的事件
+fn drain_events(rx: &Receiver<WatchEvent>) {
+    while rx.try_recv().is_ok() {}
+}
+
+/// 在子进程中依次执行`dadk user build`和`dadk user install`
+///
+/// `affected`（`name@version`）不为空时，重新构建这些任务，并且只安装这些任务
+fn build_and_install(ctx: &DADKExecContext, affected: &BTreeSet<String>) {
+    let mut build_args = vec!["build".to_string()];
+    let mut install_args = vec!["install".to_string()];
+    for task in affected {
+        build_args.extend(["--rebuild".to_string(), task.clone()]);
+        install_args.extend(["--task".to_string(), task.clone()]);
+    }
+
+    if let Err(e) = run_dadk_user(ctx, &build_args) {
+        error!("Build failed: {}", e);
+        return;
+    }
+    if let Err(e) = run_dadk_user(ctx, &install_args) {
+        error!("Install failed: {}", e);
+    }
+}
+
+fn run_dadk_user(ctx: &DADKExecContext, args: &[String]) -> Result<()> {
+    let exe = std::env::current_exe()
+        .map_err(|e| anyhow!("Failed to get path of current executable: {}", e))?;
+    let mut command = Command::new(exe);
+    if let Some(profile) = &ctx.command.profile {
+        command.arg("--profile").arg(profile);
+    }
+    if ctx.command.skip_invalid_configs {
+        command.arg("--skip-invalid-configs");
+    }
+    for _ in 0..ctx.command.verbose {
+        command.arg("--verbose");
+    }
+    for _ in 0..ctx.command.quiet {
+        command.arg("--quiet");
+    }
+    if let Some(filter) = &ctx.command.log_filter {
+        command.arg("--log-filter").arg(filter);
+    }
+    if ctx.allow_env_collisions() {
+        command.arg("--allow-env-collisions");
+    }
+    if let Some(metrics_file) = ctx.metrics_file() {
+        command
+            .arg("--metrics-file")
+            .arg(metrics_file)
+            .arg("--metrics-format")
+            .arg(
+                ctx.command
+                    .metrics_format
+                    .to_possible_value()
+                    .expect("metrics format has no value")
+                    .get_name(),
+            );
+    }
+    let status = command
+        .arg("--manifest")
+        .arg(&ctx.command.manifest_path)
+        .arg("--workdir")
+        .arg(ctx.workdir())
+        .arg("--log-format")
+        .arg(
+            ctx.command
+                .log_format
+                .to_possible_value()
+                .expect("log format has no value")
+                .get_name(),
+        )
+        .arg("user")
+        .args(args)
+        .status()
+        .map_err(|e| anyhow!("Failed to spawn dadk: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "dadk user {} exited with {}",
+            args.join(" "),
+            status
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
+pub enum BootCommand {
+    /// 使用QEMU从磁盘镜像启动DragonOS（按照boot配置转发端口、共享目录）
+    Run(BootRunCommand),
+    /// 检查boot配置、QEMU以及KVM，输出将要执行的QEMU命令行（不启动）
+    Check(BootCheckCommand),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct BootRunCommand {
+    /// 不使用图形界面，通过终端与DragonOS交互（boot-mode为no-graphic时总是如此）
+    #[clap(long)]
+    pub nographic: bool,
+    /// 只输出QEMU的命令行，不启动
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct BootCheckCommand {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
+pub enum CacheCommand {
+    /// 清理缓存根目录中不再需要的任务版本
+    Gc(CacheGcCommand),
+    /// 统计每个任务的源码缓存、构建缓存以及安装的文件占用的空间
+    Du(CacheDuCommand),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct CacheGcCommand {
+    /// 每个任务只保留最近使用过的N个版本（包括当前配置文件中的版本）
+    #[clap(long, value_name = "N")]
+    pub keep_latest: Option<usize>,
+    /// 按照最近最少使用的顺序删除，直到缓存的总大小不超过此值，例如`50G`、`512M`
+    #[clap(long, value_name = "SIZE", value_parser = dadk_user::cache::parse_size)]
+    pub max_size: Option<u64>,
+    /// 删除超过此时间没有使用过的版本，例如`30d`、`12h`
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub older_than: Option<Duration>,
+    /// 不询问，直接删除
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+    /// 只显示将要删除的内容，不实际删除
+    #[clap(long, conflicts_with = "yes")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct CacheDuCommand {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
+
+fn parse_duration(s: &str) -> Result<Duration> {
+    let d = s
+        .parse::<humantime::Duration>()
+        .map_err(|e| anyhow!("Failed to parse duration: {}, error: {}", s, e))?;
+    Ok(d.into())
+}
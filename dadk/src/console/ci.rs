@@ -0,0 +1,43 @@
+use clap::{Parser, ValueEnum};
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct CiCommand {
+    /// 执行完这个阶段之后停止，默认执行所有阶段
+    #[clap(long = "stop-at", value_enum, value_name = "STAGE")]
+    pub stop_at: Option<CiStage>,
+}
+
+/// `dadk ci`的阶段，按照执行顺序排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CiStage {
+    /// 检查rootfs、boot配置以及用户程序配置能否解析
+    CheckConfig,
+    /// 构建用户程序
+    Build,
+    /// 把用户程序安装到sysroot
+    Install,
+    /// 创建、挂载磁盘镜像，把sysroot复制到磁盘镜像中，然后卸载
+    Rootfs,
+    /// 准备启动用的raw镜像，并生成QEMU的命令行
+    Boot,
+}
+
+impl CiStage {
+    pub const ALL: [CiStage; 5] = [
+        CiStage::CheckConfig,
+        CiStage::Build,
+        CiStage::Install,
+        CiStage::Rootfs,
+        CiStage::Boot,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CiStage::CheckConfig => "check-config",
+            CiStage::Build => "build",
+            CiStage::Install => "install",
+            CiStage::Rootfs => "rootfs",
+            CiStage::Boot => "boot",
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct DaemonCommand {
+    /// Unix socket的路径（默认为缓存根目录下的`dadk.sock`）
+    #[clap(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    /// 不启动守护进程，而是向正在运行的守护进程发送一个请求（例如`build`、`status`），并输出结果
+    #[clap(long, value_name = "METHOD")]
+    pub call: Option<String>,
+    /// 与`--call`一起使用，请求的参数（JSON对象），例如`{"tasks": ["hello"]}`
+    #[clap(long, value_name = "JSON", requires = "call")]
+    pub params: Option<String>,
+}
@@ -0,0 +1,8 @@
+use clap::Parser;
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct DoctorCommand {
+    /// 以JSON格式输出检查结果
+    #[clap(long)]
+    pub json: bool,
+}
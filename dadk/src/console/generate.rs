@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use clap_complete::Shell;
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct CompletionsCommand {
+    /// 要生成补全脚本的shell
+    #[clap(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct ManCommand {
+    /// 把dadk及其所有子命令的man page写入此目录（`dadk.1`、`dadk-user-build.1`等），
+    /// 未指定时只把dadk的man page输出到标准输出
+    #[clap(long, short = 'o', value_name = "DIR")]
+    pub output: Option<PathBuf>,
+}
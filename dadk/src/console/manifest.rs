@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
+pub enum ManifestCommand {
+    /// 生成新项目的dadk-manifest.toml、rootfs.toml、boot.toml，以及一个示例用户程序
+    Init(ManifestInitCommand),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct ManifestInitCommand {
+    /// 目标架构（x86_64、riscv64、aarch64）
+    #[clap(long)]
+    pub arch: Option<String>,
+    /// rootfs.toml、boot.toml所在的目录
+    #[clap(long = "system-config-dir", value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+    /// 用户程序配置目录
+    #[clap(long = "user-config-dir", value_name = "DIR")]
+    pub user_config_dir: Option<PathBuf>,
+    /// 磁盘镜像的大小（例如`1G`、`512M`）
+    #[clap(long = "rootfs-size", value_name = "SIZE")]
+    pub rootfs_size: Option<String>,
+    /// 不生成示例用户程序
+    #[clap(long = "no-example")]
+    pub no_example: bool,
+    /// 不询问，未指定的选项使用默认值
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+    /// 覆盖已经存在的文件
+    #[clap(long)]
+    pub force: bool,
+}
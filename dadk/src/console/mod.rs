@@ -1,10 +1,30 @@
-use clap::{Parser, Subcommand};
+use std::{path::PathBuf, time::Duration};
+
+use boot::BootCommand;
+use cache::CacheCommand;
+use ci::CiCommand;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use daemon::DaemonCommand;
+use doctor::DoctorCommand;
+use generate::{CompletionsCommand, ManCommand};
+use manifest::ManifestCommand;
 use profile::ProfileCommand;
+use release::ReleaseCommand;
 use rootfs::RootFSCommand;
+use self_update::SelfUpdateCommand;
 use user::UserCommand;
 
+pub mod boot;
+pub mod cache;
+pub mod ci;
+pub mod daemon;
+pub mod doctor;
+pub mod generate;
+pub mod manifest;
 pub mod profile;
+pub mod release;
 pub mod rootfs;
+pub mod self_update;
 #[cfg(test)]
 mod tests;
 pub mod user;
@@ -25,9 +45,139 @@ pub struct CommandLineArgs {
     )]
     pub manifest_path: String,
 
+    /// 要使用的 manifest profile（覆盖 manifest 中的 default-profile）
+    #[arg(long = "profile", global = true)]
+    pub profile: Option<String>,
+
+    /// 额外的用户程序配置目录（可以多次指定），在manifest中的`user-config-dir`之后依次扫描。
+    /// 后面的目录中的任务覆盖前面的目录中同名同版本的任务
+    #[arg(short = 'c', long = "config-dir", value_name = "DIR", global = true)]
+    pub config_dirs: Vec<PathBuf>,
+
+    /// 跳过无法解析的用户程序配置文件（输出警告），而不是终止执行
+    #[arg(long = "skip-invalid-configs", global = true)]
+    pub skip_invalid_configs: bool,
+
+    /// 不同用户程序导出的环境变量名称冲突（例如`libc-0.1.0`与`libc.0.1.0`）时只输出警告，而不是终止执行
+    #[arg(long = "allow-env-collisions", global = true)]
+    pub allow_env_collisions: bool,
+
+    /// 离线模式，禁止访问网络：源码缓存中没有的git仓库、压缩包、二进制包直接报错，
+    /// cargo任务使用`cargo build --offline`构建
+    #[arg(long = "offline", global = true)]
+    pub offline: bool,
+
+    /// 缓存目录被其他dadk进程锁定时，最多等待的时间（例如`30s`、`5m`，`0s`表示不等待）。默认一直等待
+    #[arg(long = "lock-timeout", value_name = "DURATION", value_parser = parse_duration, global = true)]
+    pub lock_timeout: Option<Duration>,
+
+    /// 同时执行的用户程序任务（构建、安装、清理）的数量，默认为1，最多为32
+    #[arg(
+        short = 'j',
+        long = "thread",
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        global = true
+    )]
+    pub thread: Option<usize>,
+
+    /// 输出更多日志，可以重复指定：`-v`输出DADK的debug日志，并在执行用户程序的每条命令之前
+    /// 输出命令行、工作目录以及DADK设置的环境变量；`-vv`输出DADK的trace日志
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        action = ArgAction::Count,
+        conflicts_with = "quiet",
+        global = true
+    )]
+    pub verbose: u8,
+
+    /// 减少日志输出，可以重复指定：`-q`只输出警告和错误，`-qq`只输出错误
+    #[arg(short = 'q', long = "quiet", action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// 按模块设置日志级别，语法与`RUST_LOG`相同（例如`info,dadk_user::executor=debug`），
+    /// 优先于`RUST_LOG`以及`-q`、`-v`
+    #[arg(long = "log-filter", value_name = "FILTER", global = true)]
+    pub log_filter: Option<String>,
+
     /// DADK 的工作目录
     #[arg(short = 'w', long = "workdir", default_value = ".", global = true)]
     pub workdir: String,
+
+    /// 日志输出格式
+    #[arg(
+        long = "log-format",
+        value_enum,
+        default_value_t = LogFormat::Human,
+        global = true
+    )]
+    pub log_format: LogFormat,
+
+    /// 执行失败时错误的输出格式
+    #[arg(
+        long = "error-format",
+        value_enum,
+        default_value_t = ErrorFormat::Human,
+        global = true
+    )]
+    pub error_format: ErrorFormat,
+
+    /// 执行用户程序的构建、安装、清理之后，把本次执行的构建指标（任务耗时、缓存命中率、下载量、失败数）
+    /// 写入该文件（例如node_exporter textfile collector目录中的`dadk.prom`）
+    #[arg(long = "metrics-file", value_name = "PATH", global = true)]
+    pub metrics_file: Option<PathBuf>,
+
+    /// 构建指标文件的格式
+    #[arg(
+        long = "metrics-format",
+        value_enum,
+        default_value_t = MetricsFormat::Prometheus,
+        global = true
+    )]
+    pub metrics_format: MetricsFormat,
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    s.parse::<humantime::Duration>()
+        .map(Into::into)
+        .map_err(|e| format!("Failed to parse duration: {}, error: {}", s, e))
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// 便于人阅读的日志
+    Human,
+    /// 每行一个JSON事件，便于其他程序解析
+    Json,
+}
+
+/// 错误输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// 输出到错误日志
+    Human,
+    /// 在标准错误中输出一行带有错误码的JSON，便于其他程序区分错误类型
+    Json,
+}
+
+/// 构建指标文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MetricsFormat {
+    /// Prometheus的文本格式
+    Prometheus,
+    /// OpenMetrics格式
+    Openmetrics,
+}
+
+impl From<MetricsFormat> for dadk_user::metrics::MetricsFormat {
+    fn from(format: MetricsFormat) -> Self {
+        match format {
+            MetricsFormat::Prometheus => dadk_user::metrics::MetricsFormat::Prometheus,
+            MetricsFormat::Openmetrics => dadk_user::metrics::MetricsFormat::OpenMetrics,
+        }
+    }
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
@@ -43,12 +193,54 @@ pub enum Action {
 
     #[command(subcommand, name = "profile")]
     Profile(ProfileCommand),
+
+    /// 启动DragonOS
+    #[command(subcommand, name = "boot")]
+    Boot(BootCommand),
+
+    /// 缓存目录相关操作
+    #[command(subcommand, name = "cache")]
+    Cache(CacheCommand),
+
+    /// 依次执行检查配置、构建、安装用户程序、制作磁盘镜像以及准备启动，并输出各阶段的耗时
+    Ci(CiCommand),
+
+    /// 以守护进程的方式运行，在内存中保留解析好的任务列表，通过Unix socket（JSON-RPC）接受构建、安装、查询请求
+    Daemon(DaemonCommand),
+
+    /// 检查主机上构建、运行DragonOS所需的工具和环境
+    Doctor(DoctorCommand),
+
+    /// manifest相关操作
+    #[command(subcommand, name = "manifest")]
+    Manifest(ManifestCommand),
+
+    /// 生成内核、磁盘镜像和二进制包的校验和清单，并对其签名
+    Release(ReleaseCommand),
+
+    /// 更新DADK到最新版本
+    #[command(name = "self-update")]
+    SelfUpdate(SelfUpdateCommand),
+
+    /// 生成shell补全脚本
+    Completions(CompletionsCommand),
+
+    /// 生成man page
+    Man(ManCommand),
 }
 
 impl Action {
     /// 是否需要在dadk启动时读取 manifest 文件
     pub fn needs_manifest(&self) -> bool {
-        if matches!(self, Action::Profile(_)) {
+        if matches!(
+            self,
+            Action::Profile(_)
+                | Action::Doctor(_)
+                | Action::Manifest(_)
+                | Action::SelfUpdate(_)
+                | Action::Completions(_)
+                | Action::Man(_)
+        ) {
             return false;
         }
         return true;
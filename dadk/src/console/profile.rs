@@ -1,7 +1,7 @@
 use std::{path::PathBuf, time::Duration};
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
 pub enum ProfileCommand {
@@ -9,16 +9,107 @@ pub enum ProfileCommand {
     Sample(ProfileSampleArgs),
     #[clap(about = "Parse the collected sample data")]
     Parse(ProfileParseArgs),
+    #[clap(about = "Keep sampling and show the hottest functions in the terminal")]
+    Top(ProfileTopArgs),
 }
 
-#[derive(Debug, Parser, Clone, PartialEq, Eq)]
-pub struct ProfileSampleArgs {
+/// 被采样的内核以及采样后端相关的参数
+#[derive(Debug, Args, Clone, PartialEq, Eq)]
+pub struct ProfileTargetArgs {
     #[clap(
         long = "kernel",
         help = "Path to the kernel image to use",
         default_value = "./bin/kernel/kernel.elf"
     )]
     pub kernel: PathBuf,
+
+    #[clap(
+        long = "remote",
+        help = "Remote address to connect to",
+        default_value = "localhost:1234"
+    )]
+    pub remote: String,
+
+    #[clap(
+        long = "backend",
+        help = "Sampling backend (gdb, qmp)",
+        default_value = "gdb",
+        value_parser = parse_profile_backend
+    )]
+    pub backend: ProfileBackend,
+
+    #[clap(
+        long = "qmp",
+        value_name = "ADDR",
+        help = "QMP socket address for the qmp backend (e.g. unix:/tmp/qmp.sock, tcp:localhost:4444). Defaults to `qmp-socket` in boot config"
+    )]
+    pub qmp: Option<String>,
+
+    #[clap(
+        long = "symbol-file",
+        value_name = "FILE[@LOAD_ADDR]",
+        help = "Additional symbol file (e.g. a user program) to resolve frames with, optionally with its load address",
+        value_parser = parse_symbol_file
+    )]
+    pub symbol_files: Vec<SymbolFile>,
+
+    #[clap(
+        long = "sysroot",
+        help = "Sysroot of DragonOS on the host, relative symbol files are looked up in it"
+    )]
+    pub sysroot: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub frame_names: ProfileFrameNameArgs,
+}
+
+/// 采样时栈帧名称的处理方式
+#[derive(Debug, Args, Clone, PartialEq, Eq, Default)]
+pub struct ProfileFrameNameArgs {
+    #[clap(
+        long = "keep-templates",
+        value_name = "DEPTH",
+        help = "Keep generic parameters in frame names up to the given nesting depth (0 strips them all)",
+        default_value = "0"
+    )]
+    pub keep_templates: usize,
+
+    #[clap(
+        long = "keep-addresses",
+        help = "Keep the guest address of each frame (as `name@0xaddr`), so that different call sites are not merged"
+    )]
+    pub keep_addresses: bool,
+}
+
+/// 导出时对每个调用栈中的栈帧的过滤
+#[derive(Debug, Args, Clone, PartialEq, Eq, Default)]
+pub struct ProfileFrameFilterArgs {
+    #[clap(
+        long = "max-depth",
+        value_name = "N",
+        help = "Only keep the N innermost frames of each stack"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[clap(
+        long = "drop-unknown-frames",
+        help = "Drop frames without a symbol (`??`)"
+    )]
+    pub drop_unknown_frames: bool,
+
+    #[clap(
+        long = "exclude-frame",
+        value_name = "REGEX",
+        help = "Remove frames matching the regex from the stacks (can be given multiple times)"
+    )]
+    pub exclude_frames: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct ProfileSampleArgs {
+    #[clap(flatten)]
+    pub target: ProfileTargetArgs,
+
     #[clap(
         long = "interval",
         help = "Interval between samples (e.g., 200ms, 1s, 1m)",
@@ -45,13 +136,6 @@ pub struct ProfileSampleArgs {
     )]
     pub format: ProfileFileType,
 
-    #[clap(
-        long = "remote",
-        help = "Remote address to connect to",
-        default_value = "localhost:1234"
-    )]
-    pub remote: String,
-
     #[clap(
         long = "workers",
         help = "Number of worker threads to use",
@@ -64,6 +148,61 @@ pub struct ProfileSampleArgs {
         value_parser = parse_cpu_mask
     )]
     pub cpu_mask: Option<u128>,
+
+    #[clap(flatten)]
+    pub frames: ProfileFrameFilterArgs,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct ProfileTopArgs {
+    #[clap(flatten)]
+    pub target: ProfileTargetArgs,
+
+    #[clap(
+        long = "interval",
+        help = "Interval between samples (e.g., 200ms, 1s, 1m)",
+        default_value = "200ms",
+        value_parser = parse_time_interval
+    )]
+    pub interval: Duration,
+
+    #[clap(
+        long = "refresh",
+        help = "Interval between refreshes of the table",
+        default_value = "1s",
+        value_parser = parse_time_interval
+    )]
+    pub refresh: Duration,
+
+    #[clap(
+        long = "duration",
+        help = "Stop after the given duration (run until interrupted if not set)",
+        value_parser = parse_time_interval
+    )]
+    pub duration: Option<Duration>,
+
+    #[clap(
+        long = "lines",
+        short = 'n',
+        help = "Number of functions to show",
+        default_value = "20"
+    )]
+    pub lines: usize,
+
+    #[clap(
+        long = "cpu-mask",
+        help = "CPU mask to filter",
+        value_parser = parse_cpu_mask
+    )]
+    pub cpu_mask: Option<u128>,
+}
+
+/// 额外的符号文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolFile {
+    pub path: PathBuf,
+    /// 加载基址（用于位置无关的程序）
+    pub load_addr: Option<u64>,
 }
 
 impl ProfileSampleArgs {
@@ -92,6 +231,14 @@ fn parse_profile_file_type(format: &str) -> Result<ProfileFileType> {
     }
 }
 
+fn parse_profile_backend(backend: &str) -> Result<ProfileBackend> {
+    match backend.trim().to_ascii_lowercase().as_str() {
+        "gdb" => Ok(ProfileBackend::Gdb),
+        "qmp" => Ok(ProfileBackend::Qmp),
+        _ => Err(anyhow!("Unknown profile backend: {}", backend)),
+    }
+}
+
 fn parse_cpu_mask(s: &str) -> Result<u128> {
     let mask = if s.starts_with("0x") || s.starts_with("0X") {
         u128::from_str_radix(&s[2..], 16)
@@ -103,6 +250,33 @@ fn parse_cpu_mask(s: &str) -> Result<u128> {
     Ok(mask)
 }
 
+/// 解析`FILE[@LOAD_ADDR]`。只有`@`之后是合法的地址时才把它视为加载基址，
+/// 否则整个参数都是文件路径（路径中可能包含`@`）
+fn parse_symbol_file(s: &str) -> Result<SymbolFile> {
+    let (path, load_addr) = match s
+        .rsplit_once('@')
+        .and_then(|(path, addr)| Some((path, parse_load_addr(addr.trim())?)))
+    {
+        Some((path, load_addr)) => (path, Some(load_addr)),
+        None => (s, None),
+    };
+    if path.is_empty() {
+        return Err(anyhow!("Symbol file path is empty"));
+    }
+    Ok(SymbolFile {
+        path: PathBuf::from(path),
+        load_addr,
+    })
+}
+
+fn parse_load_addr(addr: &str) -> Option<u64> {
+    if addr.starts_with("0x") || addr.starts_with("0X") {
+        u64::from_str_radix(&addr[2..], 16).ok()
+    } else {
+        addr.parse::<u64>().ok()
+    }
+}
+
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
 pub struct ProfileParseArgs {
     #[clap(
@@ -132,6 +306,32 @@ pub struct ProfileParseArgs {
         value_parser = parse_cpu_mask
     )]
     pub cpu_mask: Option<u128>,
+
+    #[clap(
+        long = "include",
+        value_name = "REGEX",
+        help = "Only keep stacks that contain a frame matching the regex"
+    )]
+    pub include: Option<String>,
+
+    #[clap(
+        long = "exclude",
+        value_name = "REGEX",
+        help = "Drop stacks that contain a frame matching the regex"
+    )]
+    pub exclude: Option<String>,
+
+    #[clap(flatten)]
+    pub frames: ProfileFrameFilterArgs,
+}
+
+/// 采样后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileBackend {
+    /// 每次采样都通过gdb获取所有vCPU的调用栈
+    Gdb,
+    /// 通过QEMU的QMP接口读取寄存器和内存，回溯调用栈
+    Qmp,
 }
 
 /// 输出的文件类型
@@ -164,4 +364,53 @@ mod tests {
         assert_eq!(parse_cpu_mask("1").unwrap(), 1);
         assert_eq!(parse_cpu_mask("0x1").unwrap(), 1);
     }
+
+    #[test]
+    fn test_parse_profile_backend() {
+        assert_eq!(parse_profile_backend("gdb").unwrap(), ProfileBackend::Gdb);
+        assert_eq!(parse_profile_backend("QMP").unwrap(), ProfileBackend::Qmp);
+        assert!(parse_profile_backend("perf").is_err());
+    }
+
+    #[test]
+    fn test_parse_symbol_file() {
+        assert_eq!(
+            parse_symbol_file("bin/shell").unwrap(),
+            SymbolFile {
+                path: PathBuf::from("bin/shell"),
+                load_addr: None
+            }
+        );
+        assert_eq!(
+            parse_symbol_file("bin/shell@0x400000").unwrap(),
+            SymbolFile {
+                path: PathBuf::from("bin/shell"),
+                load_addr: Some(0x400000)
+            }
+        );
+        assert_eq!(
+            parse_symbol_file("bin/shell@4096").unwrap().load_addr,
+            Some(4096)
+        );
+        // `@`之后不是地址时，整个参数都是路径
+        assert_eq!(
+            parse_symbol_file("bin/shell@0xzz").unwrap(),
+            SymbolFile {
+                path: PathBuf::from("bin/shell@0xzz"),
+                load_addr: None
+            }
+        );
+        assert_eq!(
+            parse_symbol_file("/tmp/build@v2/app@0x10000").unwrap(),
+            SymbolFile {
+                path: PathBuf::from("/tmp/build@v2/app"),
+                load_addr: Some(0x10000)
+            }
+        );
+        assert_eq!(
+            parse_symbol_file("/tmp/build@v2/app").unwrap().path,
+            PathBuf::from("/tmp/build@v2/app")
+        );
+        assert!(parse_symbol_file("@0x1000").is_err());
+    }
 }
@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct ReleaseCommand {
+    /// 校验和清单的输出路径，覆盖manifest中的`release.manifest`
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+    /// 二进制包所在的目录，默认为`<workdir>/bin/packages`
+    #[clap(long)]
+    pub packages_dir: Option<PathBuf>,
+    /// 不对校验和清单签名
+    #[clap(long)]
+    pub no_sign: bool,
+}
@@ -1,4 +1,7 @@
+use std::path::PathBuf;
+
 use clap::Parser;
+use dadk_config::rootfs::image_format::ImageFormat;
 
 // 定义一个枚举类型 RootFSCommand，表示根文件系统操作命令
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
@@ -7,18 +10,22 @@ pub enum RootFSCommand {
     Create(CreateCommandParam),
     /// 删除根文件系统（磁盘镜像）
     Delete,
-    /// 删除系统根目录（sysroot文件夹）
-    DeleteSysroot,
+    /// 删除系统根目录（sysroot文件夹），或者只删除其中的部分文件
+    DeleteSysroot(DeleteSysrootCommandParam),
     /// 挂载根文件系统（磁盘镜像）
-    Mount,
+    Mount(MountCommandParam),
     /// 卸载根文件系统（磁盘镜像）
-    Umount,
-    /// 输出磁盘镜像的挂载点
+    Umount(UmountCommandParam),
+    /// 压缩磁盘镜像：用0填充文件系统的空闲空间，减小压缩的镜像，可以转换为稀疏文件
+    Shrink(ShrinkCommandParam),
+    /// 输出磁盘镜像的状态：镜像是否存在、loop设备、分区以及挂载点
+    Status(StatusCommandParam),
+    /// 输出磁盘镜像的挂载点（已被`status`取代）
     #[clap(name = "show-mountpoint")]
     ShowMountPoint,
-    /// 输出磁盘镜像挂载到的loop设备
+    /// 输出磁盘镜像挂载到的loop设备（已被`status`取代）
     ShowLoopDevice,
-    /// 检查磁盘镜像文件是否存在
+    /// 检查磁盘镜像文件是否存在（已被`status`取代）
     CheckDiskImageExists,
 }
 
@@ -27,4 +34,45 @@ pub struct CreateCommandParam {
     /// 当磁盘镜像文件存在时，跳过创建
     #[clap(long = "skip-if-exists", default_value = "false")]
     pub skip_if_exists: bool,
+    /// 磁盘镜像的输出格式（raw、qcow2、zstd），覆盖rootfs.toml中的`metadata.format`
+    #[clap(long)]
+    pub format: Option<ImageFormat>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct DeleteSysrootCommandParam {
+    /// 只删除sysroot中的这个路径（文件或目录），例如`/bin/hello`（可多次指定）
+    #[clap(long, value_name = "SUBPATH")]
+    pub only: Vec<PathBuf>,
+    /// 只删除这个用户程序安装的文件，任务名称或者`name@version`（可多次指定）
+    #[clap(long = "installed-by", value_name = "TASK")]
+    pub installed_by: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct MountCommandParam {
+    /// 磁盘镜像已经挂载到挂载点时，不报错
+    #[clap(long)]
+    pub idempotent: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct UmountCommandParam {
+    /// 磁盘镜像没有挂载时，不报错
+    #[clap(long)]
+    pub idempotent: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct ShrinkCommandParam {
+    /// 把raw镜像中全0的块转换为空洞，使raw镜像成为稀疏文件
+    #[clap(long)]
+    pub sparse: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct StatusCommandParam {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
 }
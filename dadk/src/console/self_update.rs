@@ -0,0 +1,17 @@
+use clap::Parser;
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct SelfUpdateCommand {
+    /// 只检查是否有新版本，不进行更新
+    #[clap(long, conflicts_with = "rollback")]
+    pub check: bool,
+    /// 安装指定的版本（GitHub Release的tag，例如`v0.2.0`），而不是最新版本
+    #[clap(long, value_name = "TAG", conflicts_with = "rollback")]
+    pub tag: Option<String>,
+    /// 即使当前已经是最新版本，也重新安装
+    #[clap(long, conflicts_with = "rollback")]
+    pub force: bool,
+    /// 恢复上次更新前备份的可执行文件
+    #[clap(long)]
+    pub rollback: bool,
+}
@@ -1,3 +1,5 @@
+use boot::BootCommand;
+use dadk_config::{common::target_arch::TargetArch, rootfs::image_format::ImageFormat};
 use rootfs::CreateCommandParam;
 use user::UserCleanLevel;
 
@@ -5,7 +7,7 @@ use super::*;
 
 #[test]
 fn test_command_line_args_default() {
-    let args = CommandLineArgs::parse_from(&["dadk", "kernel"]);
+    let args = CommandLineArgs::parse_from(["dadk", "kernel"]);
     assert_eq!(args.action, Action::Kernel);
     assert_eq!(args.manifest_path, "dadk-manifest.toml");
 }
@@ -13,38 +15,225 @@ fn test_command_line_args_default() {
 #[test]
 fn test_command_line_args_with_manifest() {
     // test short
-    let args = CommandLineArgs::parse_from(&["dadk", "-f", "custom-manifest.toml", "kernel"]);
+    let args = CommandLineArgs::parse_from(["dadk", "-f", "custom-manifest.toml", "kernel"]);
     assert_eq!(args.action, Action::Kernel);
     assert_eq!(args.manifest_path, "custom-manifest.toml");
     // test long
     let args =
-        CommandLineArgs::parse_from(&["dadk", "--manifest", "custom-manifest.toml", "kernel"]);
+        CommandLineArgs::parse_from(["dadk", "--manifest", "custom-manifest.toml", "kernel"]);
     assert_eq!(args.action, Action::Kernel);
     assert_eq!(args.manifest_path, "custom-manifest.toml");
 }
 
+#[test]
+fn test_command_line_args_with_profile() {
+    let args = CommandLineArgs::parse_from(["dadk", "kernel"]);
+    assert_eq!(args.profile, None);
+    // global option, can be placed after the subcommand
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--profile", "riscv64"]);
+    assert_eq!(args.profile.as_deref(), Some("riscv64"));
+}
+
+#[test]
+fn test_command_line_args_skip_invalid_configs() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert!(!args.skip_invalid_configs);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--skip-invalid-configs"]);
+    assert!(args.skip_invalid_configs);
+}
+
+#[test]
+fn test_command_line_args_config_dirs() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert!(args.config_dirs.is_empty());
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "--config-dir",
+        "overlay",
+        "-c",
+        "local",
+        "user",
+        "build",
+    ]);
+    assert_eq!(
+        args.config_dirs,
+        vec![PathBuf::from("overlay"), PathBuf::from("local")]
+    );
+}
+
+#[test]
+fn test_command_line_args_offline() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert!(!args.offline);
+    let args = CommandLineArgs::parse_from(["dadk", "--offline", "user", "build"]);
+    assert!(args.offline);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--offline"]);
+    assert!(args.offline);
+}
+
+#[test]
+fn test_command_line_args_lock_timeout() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert_eq!(args.lock_timeout, None);
+    // global option, can be placed after the subcommand
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--lock-timeout", "30s"]);
+    assert_eq!(args.lock_timeout, Some(std::time::Duration::from_secs(30)));
+    let args = CommandLineArgs::parse_from(["dadk", "--lock-timeout", "0s", "cache", "gc"]);
+    assert_eq!(args.lock_timeout, Some(std::time::Duration::ZERO));
+    assert!(CommandLineArgs::try_parse_from(["dadk", "--lock-timeout", "soon", "kernel"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_thread() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "clean"]);
+    assert_eq!(args.thread, None);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "clean", "-j", "8"]);
+    assert_eq!(args.thread, Some(8));
+    let args = CommandLineArgs::parse_from(["dadk", "--thread", "4", "user", "build"]);
+    assert_eq!(args.thread, Some(4));
+    assert!(CommandLineArgs::try_parse_from(["dadk", "user", "build", "-j", "0"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_user_build_multi_arch() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    let Action::User(UserCommand::Build(build)) = args.action else {
+        panic!("expected user build");
+    };
+    assert!(!build.multi_arch());
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "build",
+        "--arch",
+        "x86_64,riscv64",
+        "--parallel",
+    ]);
+    let Action::User(UserCommand::Build(build)) = args.action else {
+        panic!("expected user build");
+    };
+    assert!(build.multi_arch());
+    assert!(build.parallel);
+    assert_eq!(build.arch, vec![TargetArch::X86_64, TargetArch::RiscV64]);
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--all-arches"]);
+    let Action::User(UserCommand::Build(build)) = args.action else {
+        panic!("expected user build");
+    };
+    assert!(build.all_arches && build.multi_arch());
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "user", "build", "--arch", "mips"]).is_err());
+    assert!(CommandLineArgs::try_parse_from([
+        "dadk",
+        "user",
+        "build",
+        "--arch",
+        "x86_64",
+        "--all-arches"
+    ])
+    .is_err());
+}
+
 #[test]
 fn test_command_line_args_rootfs_subcommand() {
-    let args = CommandLineArgs::parse_from(&["dadk", "rootfs", "create"]);
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "create"]);
     assert!(matches!(
         args.action,
         Action::Rootfs(RootFSCommand::Create(CreateCommandParam {
-            skip_if_exists: false
+            skip_if_exists: false,
+            format: None,
         }))
     ));
 
-    let args = CommandLineArgs::parse_from(&["dadk", "rootfs", "create", "--skip-if-exists"]);
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "create", "--skip-if-exists"]);
     assert!(matches!(
         args.action,
         Action::Rootfs(RootFSCommand::Create(CreateCommandParam {
-            skip_if_exists: true
+            skip_if_exists: true,
+            format: None,
         }))
     ));
+
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "create", "--format", "qcow2"]);
+    assert!(matches!(
+        args.action,
+        Action::Rootfs(RootFSCommand::Create(CreateCommandParam {
+            skip_if_exists: false,
+            format: Some(ImageFormat::Qcow2),
+        }))
+    ));
+    assert!(
+        CommandLineArgs::try_parse_from(["dadk", "rootfs", "create", "--format", "vmdk"]).is_err()
+    );
+}
+
+#[test]
+fn test_command_line_args_rootfs_mount_and_status() {
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "mount", "--idempotent"]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::Mount(rootfs::MountCommandParam {
+            idempotent: true
+        }))
+    );
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "umount"]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::Umount(rootfs::UmountCommandParam {
+            idempotent: false
+        }))
+    );
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "status", "--json"]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::Status(rootfs::StatusCommandParam {
+            json: true
+        }))
+    );
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "shrink", "--sparse"]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::Shrink(rootfs::ShrinkCommandParam {
+            sparse: true
+        }))
+    );
+}
+
+#[test]
+fn test_command_line_args_rootfs_delete_sysroot() {
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "delete-sysroot"]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::DeleteSysroot(
+            rootfs::DeleteSysrootCommandParam::default()
+        ))
+    );
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "rootfs",
+        "delete-sysroot",
+        "--only",
+        "/usr/share/doc",
+        "--installed-by",
+        "hello",
+        "--installed-by",
+        "world@0.1.0",
+    ]);
+    assert_eq!(
+        args.action,
+        Action::Rootfs(RootFSCommand::DeleteSysroot(
+            rootfs::DeleteSysrootCommandParam {
+                only: vec![PathBuf::from("/usr/share/doc")],
+                installed_by: vec!["hello".to_string(), "world@0.1.0".to_string()],
+            }
+        ))
+    );
 }
 
 #[test]
 fn test_show_mountpoint() {
-    let args = CommandLineArgs::parse_from(&["dadk", "rootfs", "show-mountpoint"]);
+    let args = CommandLineArgs::parse_from(["dadk", "rootfs", "show-mountpoint"]);
     assert!(matches!(
         args.action,
         Action::Rootfs(RootFSCommand::ShowMountPoint)
@@ -53,15 +242,357 @@ fn test_show_mountpoint() {
 
 #[test]
 fn test_command_line_args_user() {
-    let args = CommandLineArgs::parse_from(&["dadk", "user", "build"]);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+
+    assert!(matches!(args.action, Action::User(UserCommand::Build(_))));
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert!(args.rebuild.is_empty());
+        assert!(!args.resume);
+    }
+
+    // 检查 `--resume` 参数
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--resume"]);
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert!(args.resume);
+    } else {
+        panic!("Expected UserCommand::Build");
+    }
+
+    // 检查 `--rebuild` 参数
+    let args =
+        CommandLineArgs::parse_from(["dadk", "user", "build", "--rebuild", "a", "--rebuild", "b"]);
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert_eq!(args.rebuild, vec!["a".to_string(), "b".to_string()]);
+        assert!(!args.force);
+        assert!(!args.no_build_cache);
+    } else {
+        panic!("Expected UserCommand::Build");
+    }
+
+    // 检查 `--force` 和 `--no-build-cache` 参数
+    let args =
+        CommandLineArgs::parse_from(["dadk", "user", "build", "--force", "--no-build-cache"]);
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert!(args.force);
+        assert!(args.no_build_cache);
+        assert!(!args.refetch);
+    } else {
+        panic!("Expected UserCommand::Build");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--refetch"]);
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert!(args.refetch);
+        assert_eq!(args.report, None);
+    } else {
+        panic!("Expected UserCommand::Build");
+    }
+
+    let args =
+        CommandLineArgs::parse_from(["dadk", "user", "build", "--report", "bin/build-report.json"]);
+    if let Action::User(UserCommand::Build(args)) = args.action {
+        assert_eq!(args.report, Some(PathBuf::from("bin/build-report.json")));
+    } else {
+        panic!("Expected UserCommand::Build");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "install"]);
+    if let Action::User(UserCommand::Install(args)) = args.action {
+        assert!(!args.force);
+    } else {
+        panic!("Expected UserCommand::Install");
+    }
+    let args = CommandLineArgs::parse_from(["dadk", "user", "install", "--force"]);
+    if let Action::User(UserCommand::Install(args)) = args.action {
+        assert!(args.force);
+        assert!(!args.create_sysroot);
+    } else {
+        panic!("Expected UserCommand::Install");
+    }
+    let args = CommandLineArgs::parse_from(["dadk", "user", "install", "--create-sysroot"]);
+    if let Action::User(UserCommand::Install(args)) = args.action {
+        assert!(args.create_sysroot);
+        assert!(!args.into_image);
+    } else {
+        panic!("Expected UserCommand::Install");
+    }
+    let args = CommandLineArgs::parse_from(["dadk", "user", "install", "--into-image"]);
+    if let Action::User(UserCommand::Install(args)) = args.action {
+        assert!(args.into_image);
+        assert!(args.task.is_empty());
+    } else {
+        panic!("Expected UserCommand::Install");
+    }
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "install",
+        "--task",
+        "libc@0.1.0",
+        "--task",
+        "app",
+    ]);
+    if let Action::User(UserCommand::Install(args)) = args.action {
+        assert_eq!(args.task, vec!["libc@0.1.0", "app"]);
+    } else {
+        panic!("Expected UserCommand::Install");
+    }
+    assert!(CommandLineArgs::try_parse_from([
+        "dadk",
+        "user",
+        "install",
+        "--into-image",
+        "--create-sysroot"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_command_line_args_user_watch() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "watch"]);
+    if let Action::User(UserCommand::Watch(args)) = args.action {
+        assert_eq!(args.debounce, 500);
+    } else {
+        panic!("Expected UserCommand::Watch");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "watch", "--debounce", "1000"]);
+    if let Action::User(UserCommand::Watch(args)) = args.action {
+        assert_eq!(args.debounce, 1000);
+    } else {
+        panic!("Expected UserCommand::Watch");
+    }
+}
+
+#[test]
+fn test_command_line_args_user_stats() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "stats"]);
+    if let Action::User(UserCommand::Stats(args)) = args.action {
+        assert_eq!(args.top, 10);
+    } else {
+        panic!("Expected UserCommand::Stats");
+    }
 
-    assert!(matches!(args.action, Action::User(UserCommand::Build)));
+    let args = CommandLineArgs::parse_from(["dadk", "user", "stats", "--top", "3"]);
+    if let Action::User(UserCommand::Stats(args)) = args.action {
+        assert_eq!(args.top, 3);
+    } else {
+        panic!("Expected UserCommand::Stats");
+    }
+}
+
+#[test]
+fn test_command_line_args_user_list() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "list"]);
+    if let Action::User(UserCommand::List(args)) = args.action {
+        assert!(!args.json);
+    } else {
+        panic!("Expected UserCommand::List");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "list", "--json"]);
+    if let Action::User(UserCommand::List(args)) = args.action {
+        assert!(args.json);
+    } else {
+        panic!("Expected UserCommand::List");
+    }
+}
+
+#[test]
+fn test_command_line_args_user_status() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "status", "hello@0.1.0", "--json"]);
+    if let Action::User(UserCommand::Status(args)) = args.action {
+        assert_eq!(args.task, "hello@0.1.0");
+        assert!(args.json);
+    } else {
+        panic!("Expected UserCommand::Status");
+    }
+    assert!(CommandLineArgs::try_parse_from(["dadk", "user", "status"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_user_explain_env() {
+    let args = CommandLineArgs::parse_from(["dadk", "-v", "user", "explain-env", "hello"]);
+    assert_eq!(args.verbose, 1);
+    if let Action::User(UserCommand::ExplainEnv(args)) = args.action {
+        assert_eq!(args.task, "hello");
+        assert!(!args.json);
+    } else {
+        panic!("Expected UserCommand::ExplainEnv");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--verbose"]);
+    assert_eq!(args.verbose, 1);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert_eq!(args.verbose, 0);
+}
+
+#[test]
+fn test_command_line_args_log_level() {
+    let args = CommandLineArgs::parse_from(["dadk", "-vv", "user", "build"]);
+    assert_eq!(args.verbose, 2);
+    assert_eq!(args.quiet, 0);
+    assert_eq!(args.log_filter, None);
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "build",
+        "-q",
+        "--log-filter",
+        "dadk_user::executor=debug",
+    ]);
+    assert_eq!(args.quiet, 1);
+    assert_eq!(
+        args.log_filter.as_deref(),
+        Some("dadk_user::executor=debug")
+    );
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "-q", "-v", "user", "build"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_user_rdeps() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "rdeps", "relibc", "--flat"]);
+    if let Action::User(UserCommand::Rdeps(args)) = args.action {
+        assert_eq!(args.task, "relibc");
+        assert!(args.flat);
+        assert!(!args.json);
+    } else {
+        panic!("Expected UserCommand::Rdeps");
+    }
+    assert!(CommandLineArgs::try_parse_from(["dadk", "user", "rdeps"]).is_err());
+}
+
+/// 该函数测试CommandLineArgs解析器是否正确解析`dadk user installed`、`dadk user owns`命令
+#[test]
+fn test_command_line_args_user_installed_owns() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "installed", "--files"]);
+    if let Action::User(UserCommand::Installed(args)) = args.action {
+        assert!(args.files);
+        assert!(!args.json);
+    } else {
+        panic!("Expected UserCommand::Installed");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "owns", "/bin/hello"]);
+    if let Action::User(UserCommand::Owns(args)) = args.action {
+        assert_eq!(args.path, std::path::PathBuf::from("/bin/hello"));
+    } else {
+        panic!("Expected UserCommand::Owns");
+    }
+    assert!(CommandLineArgs::try_parse_from(["dadk", "user", "owns"]).is_err());
+}
+
+/// 该函数测试CommandLineArgs解析器是否正确解析`dadk user package`命令
+#[test]
+fn test_command_line_args_user_package() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "package"]);
+    if let Action::User(UserCommand::Package(args)) = args.action {
+        assert_eq!(args.output, None);
+        assert!(args.task.is_empty());
+    } else {
+        panic!("Expected UserCommand::Package");
+    }
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "package",
+        "-o",
+        "/tmp/pkgs",
+        "--task",
+        "a",
+        "--task",
+        "b",
+    ]);
+    if let Action::User(UserCommand::Package(args)) = args.action {
+        assert_eq!(args.output, Some(std::path::PathBuf::from("/tmp/pkgs")));
+        assert_eq!(args.task, vec!["a".to_string(), "b".to_string()]);
+    } else {
+        panic!("Expected UserCommand::Package");
+    }
+}
+
+/// 该函数测试CommandLineArgs解析器是否正确解析`dadk boot run`命令
+#[test]
+fn test_command_line_args_boot_run() {
+    let args = CommandLineArgs::parse_from(["dadk", "boot", "run"]);
+    assert!(args.action.needs_manifest());
+    if let Action::Boot(BootCommand::Run(args)) = args.action {
+        assert!(!args.nographic);
+        assert!(!args.dry_run);
+    } else {
+        panic!("Expected BootCommand::Run");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "boot", "run", "--nographic", "--dry-run"]);
+    if let Action::Boot(BootCommand::Run(args)) = args.action {
+        assert!(args.nographic);
+        assert!(args.dry_run);
+    } else {
+        panic!("Expected BootCommand::Run");
+    }
+
+    let args = CommandLineArgs::parse_from(["dadk", "boot", "check", "--json"]);
+    assert!(args.action.needs_manifest());
+    if let Action::Boot(BootCommand::Check(args)) = args.action {
+        assert!(args.json);
+    } else {
+        panic!("Expected BootCommand::Check");
+    }
+}
+
+/// 该函数测试CommandLineArgs解析器是否正确解析`dadk user test`命令
+#[test]
+fn test_command_line_args_user_test() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "test"]);
+    if let Action::User(UserCommand::Test(args)) = args.action {
+        assert!(args.task.is_empty());
+        assert!(!args.no_build);
+        assert!(!args.qemu);
+        assert_eq!(args.qemu_timeout, std::time::Duration::from_secs(600));
+        assert!(!args.json);
+        assert_eq!(args.report, None);
+    } else {
+        panic!("Expected UserCommand::Test");
+    }
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "test",
+        "--task",
+        "a",
+        "--no-build",
+        "--qemu",
+        "--qemu-timeout",
+        "90s",
+        "--report",
+        "/tmp/report.json",
+    ]);
+    if let Action::User(UserCommand::Test(args)) = args.action {
+        assert_eq!(args.task, vec!["a".to_string()]);
+        assert!(args.no_build);
+        assert!(args.qemu);
+        assert_eq!(args.qemu_timeout, std::time::Duration::from_secs(90));
+        assert_eq!(
+            args.report,
+            Some(std::path::PathBuf::from("/tmp/report.json"))
+        );
+    } else {
+        panic!("Expected UserCommand::Test");
+    }
+    assert!(
+        CommandLineArgs::try_parse_from(["dadk", "user", "test", "--qemu-timeout", "soon"])
+            .is_err()
+    );
 }
 
 /// 该函数测试CommandLineArgs解析器是否正确解析`dadk user clean`命令
 #[test]
 fn test_command_line_args_user_clean() {
-    let args = CommandLineArgs::parse_from(&["dadk", "user", "clean"]);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "clean"]);
     assert!(matches!(args.action, Action::User(UserCommand::Clean(_))));
     if let Action::User(UserCommand::Clean(args)) = args.action {
         assert_eq!(args.level, UserCleanLevel::All);
@@ -70,7 +601,7 @@ fn test_command_line_args_user_clean() {
     }
 
     // 检查 `--level` 参数
-    let args = CommandLineArgs::parse_from(&["dadk", "user", "clean", "--level", "in-src"]);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "clean", "--level", "in-src"]);
     if let Action::User(UserCommand::Clean(args)) = args.action {
         assert_eq!(args.level, UserCleanLevel::InSrc);
     } else {
@@ -78,10 +609,406 @@ fn test_command_line_args_user_clean() {
     }
 
     // 检查 `--task` 参数
-    let args = CommandLineArgs::parse_from(&["dadk", "user", "clean", "--task", "a-0.1.0"]);
+    let args = CommandLineArgs::parse_from(["dadk", "user", "clean", "--task", "a-0.1.0"]);
     if let Action::User(UserCommand::Clean(args)) = args.action {
         assert_eq!(args.task, Some("a-0.1.0".to_string()));
+        assert!(!args.dry_run);
     } else {
         panic!("Expected UserCommand::Clean");
     }
+
+    // 检查 `--dry-run` 参数
+    let args =
+        CommandLineArgs::parse_from(["dadk", "user", "clean", "--level", "output", "--dry-run"]);
+    if let Action::User(UserCommand::Clean(args)) = args.action {
+        assert_eq!(args.level, UserCleanLevel::Output);
+        assert!(args.dry_run);
+    } else {
+        panic!("Expected UserCommand::Clean");
+    }
+}
+
+#[test]
+fn test_command_line_args_user_new() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "new"]);
+    if let Action::User(UserCommand::New(args)) = args.action {
+        assert_eq!(args.from_template, None);
+        assert_eq!(args.name, None);
+        assert_eq!(args.output, None);
+    } else {
+        panic!("Expected UserCommand::New");
+    }
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "new",
+        "--from-template",
+        "tpl.toml",
+        "--name",
+        "app",
+        "--version",
+        "0.2.0",
+        "-o",
+        "user/apps/app.toml",
+    ]);
+    if let Action::User(UserCommand::New(args)) = args.action {
+        assert_eq!(args.from_template, Some("tpl.toml".into()));
+        assert_eq!(args.name, Some("app".to_string()));
+        assert_eq!(args.version, Some("0.2.0".to_string()));
+        assert_eq!(args.output, Some("user/apps/app.toml".into()));
+    } else {
+        panic!("Expected UserCommand::New");
+    }
+}
+
+#[test]
+fn test_command_line_args_error_format() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert_eq!(args.error_format, ErrorFormat::Human);
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--error-format", "json"]);
+    assert_eq!(args.error_format, ErrorFormat::Json);
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "--error-format", "xml", "kernel"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_allow_env_collisions() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert!(!args.allow_env_collisions);
+    let args = CommandLineArgs::parse_from(["dadk", "--allow-env-collisions", "user", "install"]);
+    assert!(args.allow_env_collisions);
+}
+
+#[test]
+fn test_command_line_args_log_format() {
+    let args = CommandLineArgs::parse_from(["dadk", "kernel"]);
+    assert_eq!(args.log_format, LogFormat::Human);
+
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--log-format", "json"]);
+    assert_eq!(args.log_format, LogFormat::Json);
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "--log-format", "xml", "kernel"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_metrics() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build"]);
+    assert_eq!(args.metrics_file, None);
+    assert_eq!(args.metrics_format, MetricsFormat::Prometheus);
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "--metrics-file",
+        "metrics/dadk.prom",
+        "user",
+        "install",
+        "--metrics-format",
+        "openmetrics",
+    ]);
+    assert_eq!(args.metrics_file, Some(PathBuf::from("metrics/dadk.prom")));
+    assert_eq!(args.metrics_format, MetricsFormat::Openmetrics);
+}
+
+#[test]
+fn test_command_line_args_self_update() {
+    let args = CommandLineArgs::parse_from(["dadk", "self-update"]);
+    assert_eq!(
+        args.action,
+        Action::SelfUpdate(self_update::SelfUpdateCommand::default())
+    );
+    assert!(!args.action.needs_manifest());
+
+    let args = CommandLineArgs::parse_from(["dadk", "self-update", "--check", "--tag", "v0.2.0"]);
+    let Action::SelfUpdate(cmd) = args.action else {
+        panic!("expected self-update");
+    };
+    assert!(cmd.check);
+    assert_eq!(cmd.tag.as_deref(), Some("v0.2.0"));
+
+    assert!(
+        CommandLineArgs::try_parse_from(["dadk", "self-update", "--rollback", "--force"]).is_err()
+    );
+}
+
+#[test]
+fn test_command_line_args_doctor() {
+    let args = CommandLineArgs::parse_from(["dadk", "doctor"]);
+    assert_eq!(
+        args.action,
+        Action::Doctor(doctor::DoctorCommand::default())
+    );
+    assert!(!args.action.needs_manifest());
+
+    let args = CommandLineArgs::parse_from(["dadk", "doctor", "--json"]);
+    assert_eq!(
+        args.action,
+        Action::Doctor(doctor::DoctorCommand { json: true })
+    );
+}
+
+#[test]
+fn test_command_line_args_manifest_init() {
+    let args = CommandLineArgs::parse_from(["dadk", "manifest", "init"]);
+    assert_eq!(
+        args.action,
+        Action::Manifest(manifest::ManifestCommand::Init(
+            manifest::ManifestInitCommand::default()
+        ))
+    );
+    assert!(!args.action.needs_manifest());
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "-f",
+        "os/dadk-manifest.toml",
+        "manifest",
+        "init",
+        "--arch",
+        "riscv64",
+        "--system-config-dir",
+        "os/config",
+        "--rootfs-size",
+        "2G",
+        "--no-example",
+        "-y",
+    ]);
+    assert_eq!(args.manifest_path, "os/dadk-manifest.toml");
+    let Action::Manifest(manifest::ManifestCommand::Init(init)) = args.action else {
+        panic!("expected manifest init");
+    };
+    assert_eq!(init.arch.as_deref(), Some("riscv64"));
+    assert_eq!(init.config_dir, Some(PathBuf::from("os/config")));
+    assert_eq!(init.rootfs_size.as_deref(), Some("2G"));
+    assert!(init.no_example && init.yes && !init.force);
+}
+
+#[test]
+fn test_command_line_args_daemon() {
+    let args = CommandLineArgs::parse_from(["dadk", "daemon"]);
+    assert_eq!(
+        args.action,
+        Action::Daemon(daemon::DaemonCommand::default())
+    );
+    assert!(args.action.needs_manifest());
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "daemon",
+        "--socket",
+        "/tmp/dadk.sock",
+        "--call",
+        "build",
+        "--params",
+        r#"{"tasks": ["hello"]}"#,
+    ]);
+    let Action::Daemon(daemon) = args.action else {
+        panic!("expected daemon");
+    };
+    assert_eq!(daemon.socket, Some(PathBuf::from("/tmp/dadk.sock")));
+    assert_eq!(daemon.call.as_deref(), Some("build"));
+    assert_eq!(daemon.params.as_deref(), Some(r#"{"tasks": ["hello"]}"#));
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "daemon", "--params", "{}"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_release() {
+    let args = CommandLineArgs::parse_from(["dadk", "release"]);
+    assert_eq!(
+        args.action,
+        Action::Release(release::ReleaseCommand::default())
+    );
+    assert!(args.action.needs_manifest());
+
+    let args =
+        CommandLineArgs::parse_from(["dadk", "release", "--output", "out/SHA256SUMS", "--no-sign"]);
+    assert_eq!(
+        args.action,
+        Action::Release(release::ReleaseCommand {
+            output: Some(PathBuf::from("out/SHA256SUMS")),
+            packages_dir: None,
+            no_sign: true,
+        })
+    );
+}
+
+#[test]
+fn test_command_line_args_completions_and_man() {
+    let args = CommandLineArgs::parse_from(["dadk", "completions", "zsh"]);
+    assert_eq!(
+        args.action,
+        Action::Completions(generate::CompletionsCommand {
+            shell: clap_complete::Shell::Zsh
+        })
+    );
+    assert!(!args.action.needs_manifest());
+    assert!(CommandLineArgs::try_parse_from(["dadk", "completions", "tcsh"]).is_err());
+
+    let args = CommandLineArgs::parse_from(["dadk", "man", "-o", "target/man"]);
+    let Action::Man(cmd) = args.action else {
+        panic!("expected man");
+    };
+    assert_eq!(cmd.output, Some(std::path::PathBuf::from("target/man")));
+}
+
+#[test]
+fn test_command_line_args_user_build_tui() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "build", "--tui"]);
+    let Action::User(UserCommand::Build(build)) = args.action else {
+        panic!("expected user build");
+    };
+    assert!(build.tui);
+    assert!(
+        CommandLineArgs::try_parse_from(["dadk", "user", "build", "--tui", "--all-arches"])
+            .is_err()
+    );
+}
+
+#[test]
+fn test_command_line_args_cache_gc() {
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "cache",
+        "gc",
+        "--keep-latest",
+        "2",
+        "--max-size",
+        "50G",
+        "--older-than",
+        "30d",
+        "-y",
+    ]);
+    assert!(args.action.needs_manifest());
+    let Action::Cache(cache::CacheCommand::Gc(gc)) = args.action else {
+        panic!("expected cache gc");
+    };
+    assert_eq!(gc.keep_latest, Some(2));
+    assert_eq!(gc.max_size, Some(50 << 30));
+    assert_eq!(
+        gc.older_than,
+        Some(std::time::Duration::from_secs(30 * 24 * 60 * 60))
+    );
+    assert!(gc.yes);
+    assert!(!gc.dry_run);
+
+    assert!(CommandLineArgs::try_parse_from(["dadk", "cache", "gc", "--max-size", "10X"]).is_err());
+    assert!(
+        CommandLineArgs::try_parse_from(["dadk", "cache", "gc", "--dry-run", "--yes"]).is_err()
+    );
+}
+
+#[test]
+fn test_command_line_args_cache_du() {
+    let args = CommandLineArgs::parse_from(["dadk", "cache", "du", "--json"]);
+    assert!(args.action.needs_manifest());
+    assert_eq!(
+        args.action,
+        Action::Cache(cache::CacheCommand::Du(cache::CacheDuCommand {
+            json: true
+        }))
+    );
+}
+
+#[test]
+fn test_command_line_args_user_outdated() {
+    let args = CommandLineArgs::parse_from(["dadk", "user", "outdated"]);
+    if let Action::User(UserCommand::Outdated(args)) = args.action {
+        assert!(!args.json);
+        assert_eq!(args.lockfile, None);
+    } else {
+        panic!("Expected UserCommand::Outdated");
+    }
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "user",
+        "outdated",
+        "--json",
+        "--lockfile",
+        "dadk-user.lock",
+    ]);
+    if let Action::User(UserCommand::Outdated(args)) = args.action {
+        assert!(args.json);
+        assert_eq!(args.lockfile, Some(PathBuf::from("dadk-user.lock")));
+    } else {
+        panic!("Expected UserCommand::Outdated");
+    }
+}
+
+#[test]
+fn test_command_line_args_ci() {
+    let args = CommandLineArgs::parse_from(["dadk", "ci"]);
+    assert_eq!(args.action, Action::Ci(ci::CiCommand::default()));
+    assert!(args.action.needs_manifest());
+
+    let args = CommandLineArgs::parse_from(["dadk", "ci", "--stop-at", "check-config"]);
+    assert_eq!(
+        args.action,
+        Action::Ci(ci::CiCommand {
+            stop_at: Some(ci::CiStage::CheckConfig)
+        })
+    );
+    assert!(CommandLineArgs::try_parse_from(["dadk", "ci", "--stop-at", "deploy"]).is_err());
+}
+
+#[test]
+fn test_command_line_args_profile_frame_options() {
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "profile",
+        "sample",
+        "--output",
+        "flame.svg",
+        "--keep-templates",
+        "2",
+        "--keep-addresses",
+        "--max-depth",
+        "16",
+    ]);
+    let Action::Profile(profile::ProfileCommand::Sample(sample)) = args.action else {
+        panic!("expected profile sample");
+    };
+    assert_eq!(sample.target.frame_names.keep_templates, 2);
+    assert!(sample.target.frame_names.keep_addresses);
+    assert_eq!(sample.frames.max_depth, Some(16));
+
+    let args = CommandLineArgs::parse_from([
+        "dadk",
+        "profile",
+        "parse",
+        "--input",
+        "samples.json",
+        "--output",
+        "flame.svg",
+        "--drop-unknown-frames",
+        "--exclude-frame",
+        "^core::",
+        "--exclude-frame",
+        "spin",
+    ]);
+    let Action::Profile(profile::ProfileCommand::Parse(parse)) = args.action else {
+        panic!("expected profile parse");
+    };
+    assert_eq!(
+        parse.frames,
+        profile::ProfileFrameFilterArgs {
+            max_depth: None,
+            drop_unknown_frames: true,
+            exclude_frames: vec!["^core::".to_string(), "spin".to_string()],
+        }
+    );
+    // 栈帧名称在采样时处理，parse不接受这些选项
+    assert!(CommandLineArgs::try_parse_from([
+        "dadk",
+        "profile",
+        "parse",
+        "--input",
+        "a",
+        "--output",
+        "b",
+        "--keep-addresses",
+    ])
+    .is_err());
 }
@@ -1,10 +1,92 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::anyhow;
 use clap::{Parser, Subcommand, ValueEnum};
+use dadk_config::common::target_arch::TargetArch;
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
 pub enum UserCommand {
-    Build,
+    Build(UserBuildCommand),
     Clean(UserCleanCommand),
-    Install,
+    Install(UserInstallCommand),
+    /// 监视用户程序的源码和配置文件，在变更时自动重新构建并安装
+    Watch(UserWatchCommand),
+    /// 创建新的用户程序配置文件
+    New(UserNewCommand),
+    /// 统计用户程序的构建耗时，并分析依赖图上的关键路径
+    Stats(UserStatsCommand),
+    /// 列出所有用户程序，以及最近一次构建、安装的状态
+    List(UserListCommand),
+    /// 输出用户程序最近一次构建、安装的结果，以及下一次构建、安装是否会被跳过
+    Status(UserStatusCommand),
+    /// 输出用户程序构建时将要执行的命令、工作目录以及DADK设置的环境变量（不执行构建）
+    ExplainEnv(UserExplainEnvCommand),
+    /// 列出直接或间接依赖于某个用户程序的用户程序
+    Rdeps(UserRdepsCommand),
+    /// 列出sysroot中已安装的用户程序
+    Installed(UserInstalledCommand),
+    /// 查询sysroot中的文件是由哪个用户程序安装的
+    Owns(UserOwnsCommand),
+    /// 把已经构建成功的用户程序打包为二进制包（.dpk文件）
+    Package(UserPackageCommand),
+    /// 构建用户程序，然后在主机上或者QEMU中执行用户程序的测试
+    Test(UserTestCommand),
+    /// 检查从git分支构建的用户程序是否落后于上游
+    Outdated(UserOutdatedCommand),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct UserBuildCommand {
+    /// 忽略构建缓存，强制重新构建指定的task（名称或者`name@version`，可多次指定）
+    #[clap(long = "rebuild", value_name = "TASK")]
+    pub rebuild: Vec<String>,
+    /// 忽略`build_once`以及输入文件的修改时间，强制重新构建所有task
+    #[clap(long)]
+    pub force: bool,
+    /// 不使用上次构建的结果，构建前清空每个task的构建缓存目录
+    #[clap(long = "no-build-cache")]
+    pub no_build_cache: bool,
+    /// 重新下载在线压缩包（即使缓存中已有同一个URL的压缩包），并重新构建使用它们的task
+    #[clap(long)]
+    pub refetch: bool,
+    /// 只构建受这些文件变更影响的task（以及依赖于它们的task），例如`git diff --name-only`的输出。
+    /// 相对路径相对于DragonOS的根目录
+    #[clap(long = "affected-by", value_name = "PATH", num_args = 1..)]
+    pub affected_by: Vec<PathBuf>,
+    /// 继续上次被中断的构建，跳过其中已经完成的task
+    #[clap(long)]
+    pub resume: bool,
+    /// 为多个架构构建（逗号分隔），例如`--arch x86_64,riscv64`
+    #[clap(
+        long = "arch",
+        value_delimiter = ',',
+        value_parser = parse_target_arch,
+        conflicts_with = "all_arches"
+    )]
+    pub arch: Vec<TargetArch>,
+    /// 为manifest中配置的所有架构（`[metadata]`以及所有profile）构建
+    #[clap(long = "all-arches")]
+    pub all_arches: bool,
+    /// 为多个架构构建时，同时构建各个架构
+    #[clap(long)]
+    pub parallel: bool,
+    /// 以交互式界面显示任务队列、各个任务的输出以及整体进度
+    #[clap(long, conflicts_with_all = ["arch", "all_arches"])]
+    pub tui: bool,
+    /// 构建结束时，把每个task的构建结果以及来源信息（源文件的版本、构建主机、DADK版本）以JSON格式写入文件
+    #[clap(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+}
+
+impl UserBuildCommand {
+    /// 是否为多个架构构建
+    pub fn multi_arch(&self) -> bool {
+        self.all_arches || !self.arch.is_empty()
+    }
+}
+
+fn parse_target_arch(arch: &str) -> Result<TargetArch, String> {
+    TargetArch::try_from(arch)
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
@@ -15,6 +97,150 @@ pub struct UserCleanCommand {
     /// 要清理的task
     #[clap(long)]
     pub task: Option<String>,
+    /// 只列出每个task将要执行的清理命令、删除的目录以及可以释放的空间，不执行清理
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq, Default)]
+pub struct UserInstallCommand {
+    /// 忽略`install_once`以及构建结果的修改时间，强制重新安装所有task
+    #[clap(long)]
+    pub force: bool,
+    /// sysroot目录不存在时直接创建，不询问
+    #[clap(long = "create-sysroot")]
+    pub create_sysroot: bool,
+    /// 挂载磁盘镜像，直接安装到磁盘镜像中，而不是sysroot目录（实验性功能`image-install`）
+    #[clap(long = "into-image", conflicts_with = "create_sysroot")]
+    pub into_image: bool,
+    /// 只安装指定的task（名称或者`name@version`，可多次指定）以及它们依赖的task，未指定时安装所有task
+    #[clap(long, value_name = "TASK")]
+    pub task: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserWatchCommand {
+    /// 文件变更事件的防抖时间（毫秒）
+    #[clap(long, default_value = "500")]
+    pub debounce: u64,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserNewCommand {
+    /// 以指定的配置文件为模板，非交互式地生成配置文件
+    #[clap(long = "from-template", value_name = "FILE")]
+    pub from_template: Option<PathBuf>,
+    /// 用户程序名称
+    #[clap(long)]
+    pub name: Option<String>,
+    /// 用户程序版本号
+    #[clap(long)]
+    pub version: Option<String>,
+    /// 配置文件的输出路径（默认为用户程序配置目录下的`<name>.toml`）
+    #[clap(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserStatsCommand {
+    /// 列出耗时最长的任务的数量
+    #[clap(long, default_value = "10")]
+    pub top: usize,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserListCommand {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserStatusCommand {
+    /// 任务名称（只有一个版本时），或者`name@version`、`name-version`
+    pub task: String,
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserExplainEnvCommand {
+    /// 任务名称（只有一个版本时），或者`name@version`、`name-version`
+    pub task: String,
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserRdepsCommand {
+    /// 任务名称（只有一个版本时），或者`name@version`、`name-version`
+    pub task: String,
+    /// 每行输出一个任务，而不是依赖树
+    #[clap(long)]
+    pub flat: bool,
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserInstalledCommand {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+    /// 同时列出每个用户程序安装的文件
+    #[clap(long)]
+    pub files: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserOwnsCommand {
+    /// DragonOS中的路径（例如`/bin/hello`），或者sysroot目录下的文件路径
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserPackageCommand {
+    /// 二进制包的输出目录，默认为`<workdir>/bin/packages`
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+    /// 要打包的task（可多次指定），未指定时打包所有已经构建成功的task
+    #[clap(long)]
+    pub task: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserTestCommand {
+    /// 要测试的task（可多次指定），未指定时测试所有配置了`[test]`的task
+    #[clap(long)]
+    pub task: Vec<String>,
+    /// 不构建，直接测试已经构建成功的task
+    #[clap(long = "no-build")]
+    pub no_build: bool,
+    /// 把测试脚本复制到磁盘镜像中，启动QEMU执行
+    #[clap(long)]
+    pub qemu: bool,
+    /// 等待QEMU中的测试结束的时间
+    #[clap(long = "qemu-timeout", value_name = "DURATION", default_value = "10m", value_parser = super::parse_duration)]
+    pub qemu_timeout: Duration,
+    /// 以JSON格式输出测试报告
+    #[clap(long)]
+    pub json: bool,
+    /// 同时把JSON格式的测试报告写入文件
+    #[clap(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+pub struct UserOutdatedCommand {
+    /// 以JSON格式输出
+    #[clap(long)]
+    pub json: bool,
+    /// 把上游分支的最新提交写入锁文件（保留锁文件中其他任务的记录）
+    #[clap(long, value_name = "FILE")]
+    pub lockfile: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -37,16 +263,39 @@ impl Into<dadk_config::user::UserCleanLevel> for UserCleanLevel {
     }
 }
 
-impl Into<dadk_user::context::Action> for UserCommand {
-    fn into(self) -> dadk_user::context::Action {
-        match self {
-            UserCommand::Build => dadk_user::context::Action::Build,
-            UserCommand::Install => dadk_user::context::Action::Install,
-            UserCommand::Clean(args) => dadk_user::context::Action::Clean(args.level.into()),
+impl TryFrom<UserCommand> for dadk_user::context::Action {
+    type Error = anyhow::Error;
+
+    /// 只有构建、安装、清理（以及watch）命令由dadk-user执行，其他子命令不能转换
+    fn try_from(cmd: UserCommand) -> Result<Self, Self::Error> {
+        match cmd {
+            UserCommand::Build(_) => Ok(dadk_user::context::Action::Build),
+            UserCommand::Install(_) => Ok(dadk_user::context::Action::Install),
+            UserCommand::Clean(args) => Ok(dadk_user::context::Action::Clean(args.level.into())),
+            // watch 模式的每一轮都从构建开始
+            UserCommand::Watch(_) => Ok(dadk_user::context::Action::Build),
+            UserCommand::New(_) => Err(unsupported("new")),
+            UserCommand::Stats(_) => Err(unsupported("stats")),
+            UserCommand::List(_) => Err(unsupported("list")),
+            UserCommand::Status(_) => Err(unsupported("status")),
+            UserCommand::ExplainEnv(_) => Err(unsupported("explain-env")),
+            UserCommand::Rdeps(_) => Err(unsupported("rdeps")),
+            UserCommand::Installed(_) => Err(unsupported("installed")),
+            UserCommand::Owns(_) => Err(unsupported("owns")),
+            UserCommand::Package(_) => Err(unsupported("package")),
+            UserCommand::Test(_) => Err(unsupported("test")),
+            UserCommand::Outdated(_) => Err(unsupported("outdated")),
         }
     }
 }
 
+fn unsupported(subcommand: &str) -> anyhow::Error {
+    anyhow!(
+        "`dadk user {}` does not map to a dadk-user action",
+        subcommand
+    )
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -71,4 +320,16 @@ mod tests {
         // Test invalid case
         assert!(UserCleanLevel::from_str("invalid", true).is_err());
     }
+
+    #[test]
+    fn test_user_command_to_action() {
+        let build = UserCommand::Build(UserBuildCommand::default());
+        assert_eq!(
+            dadk_user::context::Action::try_from(build).unwrap(),
+            dadk_user::context::Action::Build
+        );
+        let list = UserCommand::List(UserListCommand::parse_from(["list"]));
+        let err = dadk_user::context::Action::try_from(list).unwrap_err();
+        assert!(err.to_string().contains("dadk user list"));
+    }
 }
@@ -1,16 +1,74 @@
 use std::{path::PathBuf, str::FromStr};
 
-use crate::utils::abs_path;
+use crate::{console::CommandLineArgs, utils::abs_path};
 
 use super::DADKExecContextBuilder;
 use anyhow::{anyhow, Result};
-use dadk_config::manifest::DadkManifestFile;
+use dadk_config::{common::version_req::Version, manifest::DadkManifestFile};
+
+/// 当前版本的dadk支持的实验性功能，需要在manifest的`experimental`中启用
+pub(crate) const EXPERIMENTAL_FEATURES: &[&str] = &[IMAGE_INSTALL];
+
+/// `dadk user install --into-image`：直接安装到挂载的磁盘镜像中
+pub(crate) const IMAGE_INSTALL: &str = "image-install";
 
 pub(super) fn parse_manifest(builder: &mut DADKExecContextBuilder) -> Result<()> {
-    let manifest_path = PathBuf::from_str(&builder.command.as_ref().unwrap().manifest_path)
+    let command = builder.command.as_ref().unwrap();
+    let manifest_path = manifest_path(command)?;
+    let dadk_manifest_file =
+        DadkManifestFile::load_with_profile(&manifest_path, command.profile.as_deref())?;
+    check_requirements(&dadk_manifest_file)?;
+    builder.manifest = Some(Some(dadk_manifest_file));
+    Ok(())
+}
+
+/// 检查manifest对dadk的要求：当前版本满足`dadk-version`，并且支持`experimental`中的所有功能
+pub(crate) fn check_requirements(manifest: &DadkManifestFile) -> Result<()> {
+    check_dadk_version(manifest, env!("CARGO_PKG_VERSION"))?;
+    let unknown: Vec<&str> = manifest
+        .experimental
+        .iter()
+        .map(|f| f.as_str())
+        .filter(|f| !EXPERIMENTAL_FEATURES.contains(f))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(anyhow!(
+            "Unknown experimental feature(s) in the manifest: {} (supported by dadk {}: {})",
+            unknown.join(", "),
+            env!("CARGO_PKG_VERSION"),
+            EXPERIMENTAL_FEATURES.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn check_dadk_version(manifest: &DadkManifestFile, version: &str) -> Result<()> {
+    let Some(req) = &manifest.dadk_version else {
+        return Ok(());
+    };
+    let version = Version::parse(version)?;
+    if req.matches(&version) {
+        return Ok(());
+    }
+    let hint = if req.is_too_old(&version) {
+        "upgrade dadk"
+    } else {
+        "install an older dadk"
+    };
+    Err(anyhow!(
+        "The manifest requires dadk-version {}, but this dadk is {}, please {}",
+        req,
+        version,
+        hint
+    ))
+}
+
+/// 获取manifest文件的绝对路径
+pub(super) fn manifest_path(command: &CommandLineArgs) -> Result<PathBuf> {
+    let manifest_path = PathBuf::from_str(&command.manifest_path)
         .map_err(|e| anyhow::anyhow!("Failed to get manifest path: {}", e))?;
 
-    let workdir = builder.command.as_ref().unwrap().workdir.clone();
+    let workdir = command.workdir.clone();
 
     // 将相对路径转换为基于workdir的绝对路径
     let manifest_path = abs_path(&PathBuf::from(workdir)).join(manifest_path);
@@ -18,7 +76,38 @@ pub(super) fn parse_manifest(builder: &mut DADKExecContextBuilder) -> Result<()>
     if !manifest_path.exists() || !manifest_path.is_file() {
         return Err(anyhow!("Manifest path does not exist or is not a file"));
     }
-    let dadk_manifest_file = DadkManifestFile::load(&manifest_path)?;
-    builder.manifest = Some(Some(dadk_manifest_file));
-    Ok(())
+    Ok(manifest_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(header: &str) -> DadkManifestFile {
+        DadkManifestFile::load_from_str(&format!("{}\n[metadata]\narch = \"x86_64\"", header))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_dadk_version() {
+        let m = manifest("dadk-version = \">=0.4, <0.5\"");
+        assert!(check_dadk_version(&m, "0.4.1").is_ok());
+        let err = check_dadk_version(&m, "0.2.0").unwrap_err().to_string();
+        assert!(err.contains("upgrade dadk"), "{}", err);
+        let err = check_dadk_version(&m, "0.5.0").unwrap_err().to_string();
+        assert!(err.contains("install an older dadk"), "{}", err);
+        assert!(check_dadk_version(&manifest(""), "0.2.0").is_ok());
+    }
+
+    #[test]
+    fn test_check_experimental() {
+        assert!(check_requirements(&manifest("experimental = []")).is_ok());
+        let err = check_requirements(&manifest("experimental = [\"no-such-feature\"]"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no-such-feature"), "{}", err);
+        let m = manifest("experimental = [\"image-install\"]");
+        assert!(check_requirements(&m).is_ok());
+        assert!(m.experimental_enabled(IMAGE_INSTALL));
+    }
 }
@@ -1,15 +1,16 @@
-use std::{cell::OnceCell, path::PathBuf};
+use std::{cell::OnceCell, collections::BTreeMap, path::PathBuf};
 
 use anyhow::Result;
-use clap::Parser;
 use dadk_config::{
     common::target_arch::TargetArch, manifest::DadkManifestFile, rootfs::RootFSConfigFile,
 };
+use dadk_user::{lock::LockTimeout, parser::Parser};
 use derive_builder::Builder;
 use manifest::parse_manifest;
+pub(crate) use manifest::{check_requirements, IMAGE_INSTALL};
 
 use crate::{
-    console::CommandLineArgs,
+    console::{CommandLineArgs, ErrorFormat},
     utils::{abs_path, check_dir_exists},
 };
 
@@ -26,9 +27,9 @@ pub struct DADKExecContext {
     rootfs: OnceCell<RootFSConfigFile>,
 }
 
-pub fn build_exec_context() -> Result<DADKExecContext> {
+pub fn build_exec_context(command: CommandLineArgs) -> Result<DADKExecContext> {
     let mut builder = DADKExecContextBuilder::create_empty();
-    builder.command(CommandLineArgs::parse());
+    builder.command(command);
     builder.rootfs(OnceCell::new());
     if builder.command.as_ref().unwrap().action.needs_manifest() {
         parse_manifest(&mut builder).expect("Failed to parse manifest");
@@ -63,6 +64,18 @@ impl DADKExecContext {
         self.manifest.as_ref().unwrap()
     }
 
+    /// 读取manifest（用于启动时不读取manifest的命令）
+    pub fn load_manifest(&self) -> Result<DadkManifestFile> {
+        let manifest_path = manifest::manifest_path(&self.command)?;
+        DadkManifestFile::load_with_profile(&manifest_path, self.command.profile.as_deref())
+    }
+
+    /// 使用指定的profile重新加载manifest（不影响当前上下文）
+    pub fn load_manifest_with_profile(&self, profile: &str) -> Result<DadkManifestFile> {
+        let manifest_path = manifest::manifest_path(&self.command)?;
+        DadkManifestFile::load_with_profile(&manifest_path, Some(profile))
+    }
+
     /// Get sysroot directory
     ///
     /// If the directory does not exist, or the path is not a folder, an error is returned
@@ -81,13 +94,94 @@ impl DADKExecContext {
             .map_err(|e| anyhow::anyhow!("Failed to get cache root dir: {}", e))
     }
 
+    /// manifest中`user-config-dir`的第一个目录
     #[deprecated]
     pub fn user_config_dir(&self) -> Result<PathBuf> {
-        check_dir_exists(&self.manifest().metadata.user_config_dir)
-            .map(|p| p.clone())
+        #[allow(deprecated)]
+        let dir = &self.manifest().metadata.user_config_dirs[0];
+        check_dir_exists(dir)
+            .cloned()
             .map_err(|e| anyhow::anyhow!("Failed to get user config dir: {}", e))
     }
 
+    /// 在`user_config_dir`之后扫描的用户程序配置目录：
+    /// manifest中`user-config-dir`的其余目录，以及通过`-c`/`--config-dir`指定的目录
+    pub fn overlay_config_dirs(&self) -> Result<Vec<PathBuf>> {
+        #[allow(deprecated)]
+        let dirs = self.manifest().metadata.user_config_dirs[1..].iter();
+        dirs.chain(self.command.config_dirs.iter())
+            .map(|dir| {
+                check_dir_exists(dir)
+                    .cloned()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user config dir: {}", e))
+            })
+            .collect()
+    }
+
+    /// 是否跳过无法解析的用户程序配置文件（命令行参数或者manifest中启用）
+    pub fn skip_invalid_configs(&self) -> bool {
+        self.command.skip_invalid_configs || self.manifest().metadata.skip_invalid_configs
+    }
+
+    pub fn error_format(&self) -> ErrorFormat {
+        self.command.error_format
+    }
+
+    /// 是否处于离线模式
+    pub fn offline(&self) -> bool {
+        self.command.offline
+    }
+
+    /// 是否允许不同用户程序导出的环境变量名称冲突
+    pub fn allow_env_collisions(&self) -> bool {
+        self.command.allow_env_collisions
+    }
+
+    /// 是否输出用户程序执行的每条命令
+    pub fn verbose(&self) -> bool {
+        self.command.verbose > 0
+    }
+
+    /// 构建指标文件的路径（相对路径相对于工作目录）
+    pub fn metrics_file(&self) -> Option<PathBuf> {
+        self.command
+            .metrics_file
+            .as_ref()
+            .map(|path| self.workdir().join(path))
+    }
+
+    pub fn metrics_format(&self) -> dadk_user::metrics::MetricsFormat {
+        self.command.metrics_format.into()
+    }
+
+    /// 同时执行的用户程序任务的数量
+    pub fn thread_num(&self) -> usize {
+        self.command.thread.unwrap_or(1)
+    }
+
+    /// 用户程序配置文件中可以引用的变量
+    pub fn config_variables(&self) -> Result<BTreeMap<String, String>> {
+        Ok(Parser::config_variables(
+            &self.manifest().metadata.variables,
+            self.target_arch(),
+            &self.cache_root_dir()?,
+            self.manifest().kernel.as_ref(),
+        ))
+    }
+
+    /// 同一个用户程序有多个版本时，每个程序的默认版本
+    pub fn default_versions(&self) -> BTreeMap<String, String> {
+        self.manifest().metadata.default_versions.clone()
+    }
+
+    /// 缓存目录被其他dadk进程锁定时，等待的时间
+    pub fn lock_timeout(&self) -> LockTimeout {
+        match self.command.lock_timeout {
+            Some(timeout) => LockTimeout::Timeout(timeout),
+            None => LockTimeout::Wait,
+        }
+    }
+
     pub fn target_arch(&self) -> TargetArch {
         self.manifest().metadata.arch
     }
@@ -110,6 +204,7 @@ impl DADKExecContext {
     }
 
     /// 获取磁盘镜像大小
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
     pub fn disk_image_size(&self) -> usize {
         self.rootfs().metadata.size
     }
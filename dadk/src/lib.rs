@@ -1,15 +1,22 @@
+use clap::Parser;
+use console::CommandLineArgs;
 use context::build_exec_context;
 
 mod actions;
 mod console;
 mod context;
+mod logger;
 mod utils;
 
 extern crate anyhow;
 
 pub fn dadk_main() {
     // dadk_user_main();
-    let exec_ctx = build_exec_context().expect("Failed to build execution context");
+    // 尽早安装信号处理函数，保证之后创建的线程不会收到信号
+    dadk_user::interrupt::install_handler();
+    let command = CommandLineArgs::parse();
+    logger::init(&command);
+    let exec_ctx = build_exec_context(command).expect("Failed to build execution context");
     log::debug!("Execution context: {:?}", exec_ctx);
     actions::run(exec_ctx);
 }
@@ -0,0 +1,246 @@
+//! # 日志初始化
+//!
+//! - `human`：env_logger的默认格式。dadk-user发出的结构化事件已有对应的普通日志，因此不会重复输出
+//! - `json`：每行一个JSON对象。结构化事件输出为对应的事件（字段见[`dadk_user::event`]），
+//!   其他日志输出为`log`事件，error级别的日志输出为`error`事件
+//!
+//! 每个JSON对象都包含`timestamp`（RFC3339格式）以及`event`字段
+//!
+//! 日志级别默认为info，可以通过`RUST_LOG`修改。全局参数`-q`/`-v`覆盖`RUST_LOG`中的默认级别，
+//! `--log-filter`（语法与`RUST_LOG`相同）最后生效。日志器是全局的，dadk-user以及构建时的工作线程都使用同一个日志器。
+//!
+//! 通过[`redirect`]设置接收者后（例如`dadk user build --tui`运行期间），日志不再输出到终端，
+//! 而是以JSON事件的形式发送给接收者。此时无论日志格式如何，都会发送结构化事件
+
+use std::{
+    io::Write,
+    sync::{mpsc::Sender, Mutex},
+    time::SystemTime,
+};
+
+use dadk_user::event::EVENT_TARGET;
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use serde_json::{Map, Number};
+
+use crate::console::{CommandLineArgs, LogFormat};
+
+pub(crate) fn init(command: &CommandLineArgs) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    // 同一个模块的规则以后解析的为准
+    if let Some(filters) = verbosity_filters(command.quiet, command.verbose) {
+        builder.parse_filters(filters);
+    }
+    if let Some(filters) = &command.log_filter {
+        builder.parse_filters(filters);
+    }
+    match command.log_format {
+        LogFormat::Human => {
+            builder.filter_module(EVENT_TARGET, LevelFilter::Off);
+        }
+        LogFormat::Json => {
+            // 无论日志级别如何设置，都输出事件，以便其他程序跟踪构建进度
+            builder.filter_module(EVENT_TARGET, LevelFilter::Trace);
+            builder.format(|buf, record| {
+                writeln!(buf, "{}", record_to_json(record, SystemTime::now()))
+            });
+        }
+    }
+    let inner = builder.build();
+    // 结构化事件为info级别，保证重定向时事件不会被全局的日志级别过滤掉
+    log::set_max_level(inner.filter().max(LevelFilter::Info));
+    log::set_boxed_logger(Box::new(DadkLogger { inner })).expect("Failed to init logger");
+}
+
+/// `-q`、`-v`对应的日志过滤规则。都没有指定时为None
+///
+/// `-v`只提高DADK自身（`dadk`、`dadk_user`、`dadk_config`）的日志级别，依赖库的日志仍为info级别
+fn verbosity_filters(quiet: u8, verbose: u8) -> Option<&'static str> {
+    match (quiet, verbose) {
+        (0, 0) => None,
+        (1, _) => Some("warn"),
+        (_, 0) => Some("error"),
+        (_, 1) => Some("info,dadk=debug"),
+        _ => Some("info,dadk=trace"),
+    }
+}
+
+/// 日志的接收者，为None时输出到终端
+static REDIRECT: Mutex<Option<Sender<serde_json::Value>>> = Mutex::new(None);
+
+/// 把日志重定向给`sender`，传入None时恢复输出到终端
+pub(crate) fn redirect(sender: Option<Sender<serde_json::Value>>) {
+    *REDIRECT.lock().unwrap() = sender;
+}
+
+struct DadkLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for DadkLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+            || (metadata.target() == EVENT_TARGET && REDIRECT.lock().unwrap().is_some())
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(sender) = REDIRECT.lock().unwrap().as_ref() {
+            if record.target() == EVENT_TARGET || self.inner.matches(record) {
+                sender.send(record_to_json(record, SystemTime::now())).ok();
+            }
+            return;
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 把一条日志记录转换为JSON事件
+pub(crate) fn record_to_json(record: &Record, timestamp: SystemTime) -> serde_json::Value {
+    let mut obj = Map::new();
+    obj.insert(
+        "timestamp".to_string(),
+        humantime::format_rfc3339_millis(timestamp)
+            .to_string()
+            .into(),
+    );
+
+    if record.target() == EVENT_TARGET {
+        let mut fields = JsonFields(Map::new());
+        // 事件的字段都是简单类型，不会出错
+        record.key_values().visit(&mut fields).ok();
+        obj.extend(fields.0);
+    } else {
+        let event = if record.level() == Level::Error {
+            "error"
+        } else {
+            "log"
+        };
+        obj.insert("event".to_string(), event.into());
+        obj.insert(
+            "level".to_string(),
+            record.level().as_str().to_lowercase().into(),
+        );
+        obj.insert("target".to_string(), record.target().into());
+    }
+    obj.insert("message".to_string(), record.args().to_string().into());
+    serde_json::Value::Object(obj)
+}
+
+struct JsonFields(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(v) = value.to_u64() {
+            v.into()
+        } else if let Some(v) = value.to_i64() {
+            v.into()
+        } else if let Some(v) = value.to_f64().and_then(Number::from_f64) {
+            v.into()
+        } else if let Some(v) = value.to_bool() {
+            v.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn timestamp() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)
+    }
+
+    #[test]
+    fn test_verbosity_filters() {
+        assert_eq!(verbosity_filters(0, 0), None);
+        assert_eq!(verbosity_filters(1, 0), Some("warn"));
+        assert_eq!(verbosity_filters(3, 0), Some("error"));
+        assert_eq!(verbosity_filters(0, 1), Some("info,dadk=debug"));
+        assert_eq!(verbosity_filters(0, 2), Some("info,dadk=trace"));
+
+        // `dadk`同时匹配`dadk_user`、`dadk_config`等模块，但结构化事件仍然由单独的规则控制
+        let logger = env_logger::Builder::new()
+            .parse_filters("info,dadk=debug")
+            .filter_module(EVENT_TARGET, LevelFilter::Off)
+            .build();
+        let enabled = |target: &str, level: Level| {
+            logger.enabled(&Metadata::builder().target(target).level(level).build())
+        };
+        assert!(enabled("dadk_user::executor", Level::Debug));
+        assert!(enabled("dadk_config::manifest", Level::Debug));
+        assert!(!enabled("reqwest::connect", Level::Debug));
+        assert!(!enabled(EVENT_TARGET, Level::Info));
+    }
+
+    #[test]
+    fn test_event_record_to_json() {
+        let kvs: &[(&str, Value)] = &[
+            ("event", Value::from("task_finished")),
+            ("task_id", Value::from(3i32)),
+            ("task", Value::from("libc-0.1.0")),
+            ("elapsed_ms", Value::from(1500u64)),
+        ];
+        let json = record_to_json(
+            &Record::builder()
+                .target(EVENT_TARGET)
+                .level(Level::Info)
+                .args(format_args!("Task libc-0.1.0 finished: ok"))
+                .key_values(&kvs)
+                .build(),
+            timestamp(),
+        );
+        assert_eq!(
+            json,
+            json!({
+                "timestamp": "2023-11-14T22:13:20.123Z",
+                "event": "task_finished",
+                "task_id": 3,
+                "task": "libc-0.1.0",
+                "elapsed_ms": 1500,
+                "message": "Task libc-0.1.0 finished: ok",
+            })
+        );
+    }
+
+    #[test]
+    fn test_plain_record_to_json() {
+        let json = record_to_json(
+            &Record::builder()
+                .target("dadk_user::executor")
+                .level(Level::Error)
+                .args(format_args!("Build failed"))
+                .build(),
+            timestamp(),
+        );
+        assert_eq!(json["event"], "error");
+        assert_eq!(json["level"], "error");
+        assert_eq!(json["target"], "dadk_user::executor");
+        assert_eq!(json["message"], "Build failed");
+
+        let json = record_to_json(
+            &Record::builder()
+                .target("dadk")
+                .level(Level::Info)
+                .args(format_args!("hello"))
+                .build(),
+            timestamp(),
+        );
+        assert_eq!(json["event"], "log");
+        assert_eq!(json["level"], "info");
+    }
+}
@@ -50,7 +50,9 @@ export default defineUserConfig({
                         children: [
                             '/user-manual/quickstart.md',
                             '/user-manual/profiling.md',
+                            '/user-manual/manifest.md',
                             '/user-manual/user-prog-build.md',
+                            '/user-manual/rootfs.md',
                             '/user-manual/envs.md',
                         ]
                     }